@@ -1,4 +1,6 @@
 #!/bin/bash
 echo "🧪 Running GNOS tests..."
 cargo test --verbose
+echo "🔀 Exploring concurrent interleavings..."
+cargo test --verbose --features shuttle --test concurrency
 echo "✅ Tests complete!"
@@ -0,0 +1,157 @@
+//! `#[gnos_driver]`, re-exported by `gnos-driver-sdk`
+//!
+//! Placed on a driver's `impl GnosDriver` block, above `#[async_trait]`:
+//!
+//! ```ignore
+//! #[gnos_driver(name = "kv", prefixes = ["/kv"], capabilities(write, delete))]
+//! #[async_trait]
+//! impl GnosDriver for KvDriver { ... }
+//! ```
+//!
+//! It implements `DriverInfo` for the driver from the arguments and fills
+//! in the trait methods they answer (`name`, `supports`, `prefixes`, and
+//! `supports_parts`, `supports_batches`, `conditional_writes` or `streams`
+//! for the matching capabilities) unless the block defines them itself.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Expr, ExprArray, ExprLit, Ident, ImplItem, ItemImpl, Lit, LitStr};
+
+/// Capabilities a driver can declare, with the trait method each one
+/// answers `true`; `None` for those the driver shows by implementing a method
+const CAPABILITIES: &[(&str, Option<&str>)] = &[
+    ("write", None),
+    ("delete", None),
+    ("create_dir", None),
+    ("parts", Some("supports_parts")),
+    ("batches", Some("supports_batches")),
+    ("conditional_writes", Some("conditional_writes")),
+    ("streams", Some("streams")),
+];
+
+#[proc_macro_attribute]
+pub fn gnos_driver(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name: Option<LitStr> = None;
+    let mut prefixes: Vec<LitStr> = Vec::new();
+    let mut capabilities: Vec<Ident> = Vec::new();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("prefixes") {
+            let array: ExprArray = meta.value()?.parse()?;
+            for element in array.elems {
+                match element {
+                    Expr::Lit(ExprLit { lit: Lit::Str(prefix), .. }) if prefix.value().starts_with('/') => {
+                        prefixes.push(prefix);
+                    }
+                    other => return Err(syn::Error::new_spanned(other, "prefixes are absolute paths like \"/kv\"")),
+                }
+            }
+            Ok(())
+        } else if meta.path.is_ident("capabilities") {
+            meta.parse_nested_meta(|capability| {
+                match capability.path.get_ident() {
+                    Some(ident) if CAPABILITIES.iter().any(|(known, _)| ident == known) => {
+                        capabilities.push(ident.clone());
+                        Ok(())
+                    }
+                    _ => Err(capability.error(format!(
+                        "unknown capability; expected one of {}",
+                        CAPABILITIES.iter().map(|(known, _)| *known).collect::<Vec<_>>().join(", "),
+                    ))),
+                }
+            })
+        } else {
+            Err(meta.error("expected `name`, `prefixes` or `capabilities`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+    let mut item = parse_macro_input!(item as ItemImpl);
+    
+    let Some(name) = name else {
+        return syn::Error::new(Span::call_site(), "a driver needs a `name`").to_compile_error().into();
+    };
+    if prefixes.is_empty() {
+        return syn::Error::new(Span::call_site(), "a driver needs at least one of `prefixes`").to_compile_error().into();
+    }
+    let implements_driver = item.trait_.as_ref()
+        .and_then(|(_, path, _)| path.segments.last())
+        .is_some_and(|segment| segment.ident == "GnosDriver");
+    if !implements_driver {
+        return syn::Error::new_spanned(&item.self_ty, "#[gnos_driver] goes on an `impl GnosDriver for ...` block")
+            .to_compile_error()
+            .into();
+    }
+    
+    let defined: Vec<String> = item.items.iter()
+        .filter_map(|item| match item {
+            ImplItem::Fn(function) => Some(function.sig.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+    let defines = |method: &str| defined.iter().any(|name| name == method);
+    
+    if !defines("name") {
+        item.items.push(parse_quote! {
+            fn name(&self) -> &'static str {
+                <Self as ::gnos_driver_sdk::DriverInfo>::NAME
+            }
+        });
+    }
+    if !defines("supports") {
+        item.items.push(parse_quote! {
+            fn supports(&self, path: &::std::path::Path) -> bool {
+                ::gnos_driver_sdk::covers(<Self as ::gnos_driver_sdk::DriverInfo>::PREFIXES, path)
+            }
+        });
+    }
+    if !defines("prefixes") {
+        item.items.push(parse_quote! {
+            fn prefixes(&self) -> ::std::vec::Vec<::std::path::PathBuf> {
+                <Self as ::gnos_driver_sdk::DriverInfo>::PREFIXES.iter().map(::std::path::PathBuf::from).collect()
+            }
+        });
+    }
+    for (capability, method) in CAPABILITIES {
+        let Some(method) = method else { continue };
+        if !capabilities.iter().any(|declared| declared == capability) || defines(method) {
+            continue;
+        }
+        let method = Ident::new(method, Span::call_site());
+        item.items.push(if method == "supports_batches" {
+            parse_quote! {
+                fn #method(&self) -> bool {
+                    true
+                }
+            }
+        } else {
+            parse_quote! {
+                fn #method(&self, _path: &::std::path::Path) -> bool {
+                    true
+                }
+            }
+        });
+    }
+    
+    let fields = CAPABILITIES.iter().map(|(capability, _)| {
+        let field = Ident::new(capability, Span::call_site());
+        let declared = capabilities.iter().any(|declared| declared == capability);
+        quote! { #field: #declared }
+    });
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::gnos_driver_sdk::DriverInfo for #self_ty #where_clause {
+            const NAME: &'static str = #name;
+            const PREFIXES: &'static [&'static str] = &[#(#prefixes),*];
+            const CAPABILITIES: ::gnos_driver_sdk::Capabilities = ::gnos_driver_sdk::Capabilities {
+                #(#fields),*
+            };
+        }
+        
+        #item
+    }
+    .into()
+}
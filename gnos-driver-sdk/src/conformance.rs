@@ -0,0 +1,51 @@
+//! Conformance suite for drivers
+//!
+//! [`check`] runs `gnos::drivers::conformance` against a driver written with
+//! this crate, checking its declared name and prefixes first and then only
+//! the capabilities it declares. From a driver's tests:
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn conforms() {
+//!     let driver = KvDriver::new();
+//!     gnos_driver_sdk::conformance::check(&driver, Path::new("/kv/conformance")).await.assert_passed();
+//! }
+//! ```
+
+use std::path::Path;
+
+pub use gnos::drivers::conformance::{Check, Report};
+
+use crate::{DriverInfo, GnosDriver};
+
+/// Run every check that applies to `driver`, writing below `scratch`
+pub async fn check<D>(driver: &D, scratch: &Path) -> Report
+where
+    D: GnosDriver + DriverInfo,
+{
+    let mut report = Report::new(D::NAME);
+    report.record("name", identity(driver));
+    report.record("prefixes", prefixes(driver));
+    gnos::drivers::conformance::run(driver, D::CAPABILITIES, scratch, &mut report).await;
+    report
+}
+
+fn identity<D: GnosDriver + DriverInfo>(driver: &D) -> Result<(), String> {
+    if driver.name() != D::NAME {
+        return Err(format!("name() is {:?} but the driver is registered as {:?}", driver.name(), D::NAME));
+    }
+    Ok(())
+}
+
+fn prefixes<D: GnosDriver + DriverInfo>(driver: &D) -> Result<(), String> {
+    for prefix in D::PREFIXES {
+        if !driver.supports(Path::new(prefix)) {
+            return Err(format!("doesn't support its own prefix {}", prefix));
+        }
+    }
+    let outside = Path::new("/.gnos-conformance-outside");
+    if driver.supports(outside) {
+        return Err(format!("claims {}, outside its prefixes", outside.display()));
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! Writing GNOS drivers outside this repository
+//!
+//! A driver is a type implementing [`GnosDriver`]. This crate re-exports the
+//! trait with every type its methods use, so a driver depends on nothing
+//! else, and `#[gnos_driver]` declares what the driver serves:
+//!
+//! ```ignore
+//! use gnos_driver_sdk::{async_trait, gnos_driver, Bytes, GnosDriver, ResourceMetadata, Result};
+//!
+//! #[gnos_driver(name = "kv", prefixes = ["/kv"], capabilities(write, delete))]
+//! #[async_trait]
+//! impl GnosDriver for KvDriver {
+//!     async fn read(&self, path: &Path) -> Result<Bytes> { ... }
+//!     async fn write(&self, path: &Path, data: &[u8]) -> Result<()> { ... }
+//!     async fn delete(&self, path: &Path) -> Result<()> { ... }
+//!     async fn list(&self, path: &Path) -> Result<Vec<String>> { ... }
+//!     async fn exists(&self, path: &Path) -> Result<bool> { ... }
+//!     async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> { ... }
+//! }
+//! ```
+//!
+//! The attribute supplies `name`, `supports` and `prefixes` from its
+//! arguments; prefixes reserve the namespace the driver serves. The
+//! driver is then added to a registry with [`register`], or built into a
+//! `cdylib` plugin with [`export_plugin!`], and its tests can run the
+//! [`conformance`] suite against a scratch prefix to check it behaves the
+//! way the VFS expects.
+
+pub mod conformance;
+
+use std::path::Path;
+use std::sync::Arc;
+
+pub use async_trait::async_trait;
+pub use bytes::Bytes;
+pub use gnos::checksum::{Checksum, ChecksumAlgorithm};
+pub use gnos::config::CacheMode;
+pub use gnos::drivers::conformance::Capabilities;
+pub use gnos::drivers::{
+    BatchOp, DriverRegistry, GnosDriver, Growth, PartPolicy, PathParams, Precondition, ResourceMetadata, WriteOptions,
+};
+pub use gnos::plugins::{PluginDriver, PLUGIN_ABI};
+pub use gnos::{GnosError, Result};
+pub use gnos_driver_sdk_macros::gnos_driver;
+
+/// What a driver registers as, written by `#[gnos_driver]`
+pub trait DriverInfo {
+    /// Label the driver is registered and configured under
+    const NAME: &'static str;
+    /// Namespace prefixes the driver serves
+    const PREFIXES: &'static [&'static str];
+    /// Declared through `#[gnos_driver(capabilities(...))]`
+    const CAPABILITIES: Capabilities;
+}
+
+/// Whether `path` is one of `prefixes` or lies below one
+pub fn covers(prefixes: &[&str], path: &Path) -> bool {
+    prefixes.iter().any(|prefix| path.starts_with(prefix))
+}
+
+/// Add `driver` to `registry` under its declared name
+///
+/// Register drivers before the registry is wrapped with faults, pools and
+/// the like, so those apply to them too.
+pub fn register<D>(registry: DriverRegistry, driver: D) -> DriverRegistry
+where
+    D: GnosDriver + DriverInfo + 'static,
+{
+    registry.with_driver(D::NAME, Arc::new(driver))
+}
+
+/// Export a driver from a `cdylib` crate as a plugin, for `[plugins] paths`
+///
+/// ```ignore
+/// gnos_driver_sdk::export_plugin!(KvDriver::new());
+/// ```
+///
+/// The driver must be declared with `#[gnos_driver]`, which names it. Only
+/// a GNOS of the version this crate was built against will load it.
+#[macro_export]
+macro_rules! export_plugin {
+    ($driver:expr) => {
+        #[no_mangle]
+        pub extern "C" fn gnos_plugin_abi() -> *const ::std::ffi::c_char {
+            $crate::PLUGIN_ABI.as_ptr().cast()
+        }
+        
+        #[no_mangle]
+        pub extern "C" fn gnos_plugin_create() -> *mut $crate::PluginDriver {
+            fn plugin<D: $crate::GnosDriver + $crate::DriverInfo + 'static>(driver: D) -> $crate::PluginDriver {
+                $crate::PluginDriver { name: D::NAME.to_string(), driver: ::std::sync::Arc::new(driver) }
+            }
+            ::std::boxed::Box::into_raw(::std::boxed::Box::new(plugin($driver)))
+        }
+    };
+}
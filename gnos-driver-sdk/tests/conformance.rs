@@ -0,0 +1,69 @@
+//! The conformance suite against a driver written with the SDK
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use gnos_driver_sdk::{
+    async_trait, conformance, gnos_driver, Bytes, Capabilities, DriverInfo, GnosDriver, GnosError, ResourceMetadata, Result,
+};
+
+/// Objects kept in memory, keyed by path
+#[derive(Default)]
+struct MemoryDriver {
+    objects: Mutex<BTreeMap<PathBuf, Bytes>>,
+}
+
+#[gnos_driver(name = "memory", prefixes = ["/memory"], capabilities(write, delete))]
+#[async_trait]
+impl GnosDriver for MemoryDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        self.objects.lock().unwrap().get(path).cloned()
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.objects.lock().unwrap().insert(path.to_path_buf(), Bytes::copy_from_slice(data));
+        Ok(())
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        self.objects.lock().unwrap().remove(path)
+            .map(drop)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.objects.lock().unwrap().keys()
+            .filter(|object| object.parent() == Some(path))
+            .filter_map(|object| object.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .collect())
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        Ok(self.objects.lock().unwrap().contains_key(path))
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let data = self.read(path).await?;
+        Ok(ResourceMetadata { size: data.len() as u64, ..ResourceMetadata::default() })
+    }
+}
+
+#[test]
+fn attribute_declares_registration() {
+    assert_eq!(MemoryDriver::NAME, "memory");
+    assert_eq!(MemoryDriver::PREFIXES, &["/memory"]);
+    assert_eq!(MemoryDriver::CAPABILITIES, Capabilities { write: true, delete: true, ..Capabilities::default() });
+    
+    let driver = MemoryDriver::default();
+    assert_eq!(driver.name(), "memory");
+    assert!(driver.supports(Path::new("/memory/a/b")));
+    assert!(!driver.supports(Path::new("/memoryless")));
+}
+
+#[tokio::test]
+async fn memory_driver_conforms() {
+    let driver = MemoryDriver::default();
+    conformance::check(&driver, Path::new("/memory/scratch")).await.assert_passed();
+}
@@ -0,0 +1,79 @@
+/*
+ * GNOS client API for C and C++
+ *
+ * Link against libgnos_ffi (cdylib or staticlib). Every function returning
+ * int answers GNOS_OK or a negative GNOS_ERR_* code; gnos_last_error()
+ * then describes the failure on the calling thread. Handles may be shared
+ * between threads. Capability tokens are taken from GNOS_TOKEN, as for the
+ * mount.
+ */
+
+#ifndef GNOS_H
+#define GNOS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define GNOS_OK 0
+#define GNOS_ERR_INVALID_ARGUMENT -1
+#define GNOS_ERR_PERMISSION_DENIED -2
+#define GNOS_ERR_NOT_FOUND -3
+#define GNOS_ERR_DRIVER -4
+#define GNOS_ERR_IO -5
+#define GNOS_ERR_CAPABILITY_EXPIRED -6
+#define GNOS_ERR_INVALID_PATH -7
+#define GNOS_ERR_BUSY -8
+#define GNOS_ERR_PANIC -9
+#define GNOS_ERR_UNREACHABLE -10
+#define GNOS_ERR_QUOTA_EXCEEDED -11
+#define GNOS_ERR_CHECKSUM_MISMATCH -12
+#define GNOS_ERR_IMMUTABLE -13
+#define GNOS_ERR_RATE_LIMITED -14
+#define GNOS_ERR_CONFIG -15
+
+typedef struct GnosHandle gnos_client_t;
+
+/* Owned by the caller once filled in; release with gnos_buffer_free */
+typedef struct {
+    uint8_t *data;
+    size_t len;
+} gnos_buffer_t;
+
+/* Owned by the caller once filled in; release with gnos_string_list_free */
+typedef struct {
+    char **items;
+    size_t len;
+} gnos_string_list_t;
+
+typedef struct {
+    uint64_t size;
+    int is_directory;
+    int64_t mtime_seconds;
+    uint32_t mtime_nanos;
+} gnos_metadata_t;
+
+/* Load drivers from a gnos.toml, or the defaults when config_path is NULL */
+int gnos_client_open(const char *config_path, gnos_client_t **out);
+void gnos_client_close(gnos_client_t *client);
+
+int gnos_read(const gnos_client_t *client, const char *path, gnos_buffer_t *out);
+int gnos_write(const gnos_client_t *client, const char *path, const uint8_t *data, size_t len);
+int gnos_list(const gnos_client_t *client, const char *path, gnos_string_list_t *out);
+int gnos_metadata(const gnos_client_t *client, const char *path, gnos_metadata_t *out);
+
+void gnos_buffer_free(gnos_buffer_t *buffer);
+void gnos_string_list_free(gnos_string_list_t *list);
+
+/* Valid until the next failing call on the same thread; NULL if none */
+const char *gnos_last_error(void);
+const char *gnos_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GNOS_H */
@@ -0,0 +1,295 @@
+//! C bindings for `GnosClient`
+//!
+//! The stable interface is `include/gnos.h`. Every call returns a status
+//! code; on failure `gnos_last_error()` describes what went wrong on the
+//! calling thread. Buffers and lists handed out by the library must be
+//! released with the matching `*_free` function.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::time::UNIX_EPOCH;
+
+use gnos::config::GnosConfig;
+use gnos::{GnosClient, GnosError};
+use tokio::runtime::Runtime;
+
+pub const GNOS_OK: c_int = 0;
+pub const GNOS_ERR_INVALID_ARGUMENT: c_int = -1;
+pub const GNOS_ERR_PERMISSION_DENIED: c_int = -2;
+pub const GNOS_ERR_NOT_FOUND: c_int = -3;
+pub const GNOS_ERR_DRIVER: c_int = -4;
+pub const GNOS_ERR_IO: c_int = -5;
+pub const GNOS_ERR_CAPABILITY_EXPIRED: c_int = -6;
+pub const GNOS_ERR_INVALID_PATH: c_int = -7;
+pub const GNOS_ERR_BUSY: c_int = -8;
+pub const GNOS_ERR_PANIC: c_int = -9;
+pub const GNOS_ERR_UNREACHABLE: c_int = -10;
+pub const GNOS_ERR_QUOTA_EXCEEDED: c_int = -11;
+pub const GNOS_ERR_CHECKSUM_MISMATCH: c_int = -12;
+pub const GNOS_ERR_IMMUTABLE: c_int = -13;
+pub const GNOS_ERR_RATE_LIMITED: c_int = -14;
+pub const GNOS_ERR_CONFIG: c_int = -15;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque client handle; the library owns the runtime its calls block on
+pub struct GnosHandle {
+    runtime: Runtime,
+    client: GnosClient,
+}
+
+#[repr(C)]
+pub struct GnosBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct GnosStringList {
+    pub items: *mut *mut c_char,
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct GnosMetadata {
+    pub size: u64,
+    pub is_directory: c_int,
+    pub mtime_seconds: i64,
+    pub mtime_nanos: u32,
+}
+
+/// Open a client from a config file, or the defaults when `config_path` is NULL
+///
+/// # Safety
+/// `config_path` must be NULL or a NUL-terminated string; `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn gnos_client_open(config_path: *const c_char, out: *mut *mut GnosHandle) -> c_int {
+    guard(|| {
+        if out.is_null() {
+            return Err(invalid_argument("out is NULL"));
+        }
+        let config_path = if config_path.is_null() { None } else { Some(c_str(config_path)?) };
+        
+        let runtime = Runtime::new().map_err(|e| fail(GNOS_ERR_IO, e.to_string()))?;
+        let client = runtime.block_on(async {
+            let config = match config_path {
+                Some(path) => GnosConfig::load(Path::new(path)).await?,
+                None => GnosConfig::default(),
+            };
+            GnosClient::new(&config).await
+        }).map_err(from_gnos)?;
+        
+        *out = Box::into_raw(Box::new(GnosHandle { runtime, client }));
+        Ok(())
+    })
+}
+
+/// Release a client; NULL is ignored
+///
+/// # Safety
+/// `handle` must come from `gnos_client_open` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn gnos_client_close(handle: *mut GnosHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Read a whole resource into `out`
+///
+/// # Safety
+/// `handle` must be open, `path` NUL-terminated and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn gnos_read(handle: *const GnosHandle, path: *const c_char, out: *mut GnosBuffer) -> c_int {
+    guard(|| {
+        let handle = handle_ref(handle)?;
+        let path = c_str(path)?;
+        if out.is_null() {
+            return Err(invalid_argument("out is NULL"));
+        }
+        
+        let data = handle.runtime.block_on(handle.client.read(Path::new(path))).map_err(from_gnos)?;
+        let len = data.len();
+        let data = Box::into_raw(data.to_vec().into_boxed_slice());
+        *out = GnosBuffer { data: data as *mut u8, len };
+        Ok(())
+    })
+}
+
+/// Replace a resource with `len` bytes from `data`
+///
+/// # Safety
+/// `handle` must be open, `path` NUL-terminated and `data` valid for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gnos_write(handle: *const GnosHandle, path: *const c_char, data: *const u8, len: usize) -> c_int {
+    guard(|| {
+        let handle = handle_ref(handle)?;
+        let path = c_str(path)?;
+        let data = if len == 0 {
+            &[][..]
+        } else if data.is_null() {
+            return Err(invalid_argument("data is NULL"));
+        } else {
+            std::slice::from_raw_parts(data, len)
+        };
+        
+        handle.runtime.block_on(handle.client.write(Path::new(path), data)).map_err(from_gnos)
+    })
+}
+
+/// Entry names of a directory-like resource
+///
+/// # Safety
+/// `handle` must be open, `path` NUL-terminated and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn gnos_list(handle: *const GnosHandle, path: *const c_char, out: *mut GnosStringList) -> c_int {
+    guard(|| {
+        let handle = handle_ref(handle)?;
+        let path = c_str(path)?;
+        if out.is_null() {
+            return Err(invalid_argument("out is NULL"));
+        }
+        
+        let names = handle.runtime.block_on(handle.client.list(Path::new(path))).map_err(from_gnos)?;
+        let items: Box<[*mut c_char]> = names.into_iter()
+            .map(|name| CString::new(name).map_or(ptr::null_mut(), CString::into_raw))
+            .collect();
+        let len = items.len();
+        let items = Box::into_raw(items);
+        *out = GnosStringList { items: items as *mut *mut c_char, len };
+        Ok(())
+    })
+}
+
+/// Size, kind and modification time of a resource
+///
+/// # Safety
+/// `handle` must be open, `path` NUL-terminated and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn gnos_metadata(handle: *const GnosHandle, path: *const c_char, out: *mut GnosMetadata) -> c_int {
+    guard(|| {
+        let handle = handle_ref(handle)?;
+        let path = c_str(path)?;
+        if out.is_null() {
+            return Err(invalid_argument("out is NULL"));
+        }
+        
+        let metadata = handle.runtime.block_on(handle.client.metadata(Path::new(path))).map_err(from_gnos)?;
+        let mtime = metadata.last_modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        *out = GnosMetadata {
+            size: metadata.size,
+            is_directory: metadata.is_directory as c_int,
+            mtime_seconds: mtime.as_secs() as i64,
+            mtime_nanos: mtime.subsec_nanos(),
+        };
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `buffer` must be NULL or filled in by `gnos_read`, and freed only once.
+#[no_mangle]
+pub unsafe extern "C" fn gnos_buffer_free(buffer: *mut GnosBuffer) {
+    let Some(buffer) = buffer.as_mut() else {
+        return;
+    };
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)));
+    }
+    *buffer = GnosBuffer { data: ptr::null_mut(), len: 0 };
+}
+
+/// # Safety
+/// `list` must be NULL or filled in by `gnos_list`, and freed only once.
+#[no_mangle]
+pub unsafe extern "C" fn gnos_string_list_free(list: *mut GnosStringList) {
+    let Some(list) = list.as_mut() else {
+        return;
+    };
+    if !list.items.is_null() {
+        let items = Box::from_raw(ptr::slice_from_raw_parts_mut(list.items, list.len));
+        for item in items.iter().filter(|item| !item.is_null()) {
+            drop(CString::from_raw(*item));
+        }
+    }
+    *list = GnosStringList { items: ptr::null_mut(), len: 0 };
+}
+
+/// Message for the last failed call on this thread, or NULL
+///
+/// The string stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn gnos_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Library version, e.g. "0.1.0"
+#[no_mangle]
+pub extern "C" fn gnos_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+struct Failure {
+    code: c_int,
+    message: String,
+}
+
+fn fail(code: c_int, message: String) -> Failure {
+    Failure { code, message }
+}
+
+fn invalid_argument(message: &str) -> Failure {
+    fail(GNOS_ERR_INVALID_ARGUMENT, message.to_string())
+}
+
+fn from_gnos(error: GnosError) -> Failure {
+    let code = match error.root() {
+        GnosError::PermissionDenied(_) => GNOS_ERR_PERMISSION_DENIED,
+        GnosError::PathNotFound(_) => GNOS_ERR_NOT_FOUND,
+        GnosError::Driver(_) => GNOS_ERR_DRIVER,
+        GnosError::Io(_) => GNOS_ERR_IO,
+        GnosError::CapabilityExpired => GNOS_ERR_CAPABILITY_EXPIRED,
+        GnosError::InvalidPath(_) => GNOS_ERR_INVALID_PATH,
+        GnosError::ResourceBusy(_) => GNOS_ERR_BUSY,
+        GnosError::Unreachable(_) => GNOS_ERR_UNREACHABLE,
+        GnosError::QuotaExceeded(_) => GNOS_ERR_QUOTA_EXCEEDED,
+        GnosError::ChecksumMismatch(_) => GNOS_ERR_CHECKSUM_MISMATCH,
+        GnosError::Immutable(_) => GNOS_ERR_IMMUTABLE,
+        GnosError::RateLimited { .. } => GNOS_ERR_RATE_LIMITED,
+        GnosError::Config(_) => GNOS_ERR_CONFIG,
+        GnosError::Context { .. } => unreachable!("root() unwraps context"),
+    };
+    fail(code, error.to_string())
+}
+
+/// Run an entry point, recording its error and keeping panics from unwinding into C
+fn guard<F>(call: F) -> c_int
+where
+    F: FnOnce() -> Result<(), Failure>,
+{
+    let failure = match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => return GNOS_OK,
+        Ok(Err(failure)) => failure,
+        Err(_) => fail(GNOS_ERR_PANIC, "internal panic".to_string()),
+    };
+    
+    let message = CString::new(failure.message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    failure.code
+}
+
+unsafe fn handle_ref<'a>(handle: *const GnosHandle) -> Result<&'a GnosHandle, Failure> {
+    handle.as_ref().ok_or_else(|| invalid_argument("handle is NULL"))
+}
+
+unsafe fn c_str<'a>(value: *const c_char) -> Result<&'a str, Failure> {
+    if value.is_null() {
+        return Err(invalid_argument("path is NULL"));
+    }
+    CStr::from_ptr(value).to_str().map_err(|_| invalid_argument("path is not UTF-8"))
+}
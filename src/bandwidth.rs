@@ -0,0 +1,351 @@
+//! Bandwidth caps
+//!
+//! With a rate set under `[bandwidth]`, every driver's transfers are paced
+//! by token buckets: one per direction across all drivers, and one per
+//! direction for each driver given its own cap. A transfer is charged to
+//! every bucket it passes through and waits for the slowest. Writes wait
+//! before they are sent; reads are charged once their size is known, so
+//! they are held back before reaching the caller.
+//!
+//! A transfer larger than the burst isn't refused or split: it leaves its
+//! buckets in debt, and the transfers after it wait the debt off, so the
+//! average rate holds either way.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tracing::debug;
+
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::{BandwidthConfig, CacheMode};
+use crate::drivers::{BatchOp, ChangeStream, GnosDriver, Growth, PartPolicy, PathParams, ResourceMetadata, WriteOptions};
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Read,
+    Write,
+}
+
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    /// Tokens available, negative while in debt, and when they were counted
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(bytes_per_sec: u64, burst_bytes: u64) -> Option<Self> {
+        (bytes_per_sec > 0).then(|| Self {
+            rate: bytes_per_sec as f64,
+            burst: burst_bytes as f64,
+            state: Mutex::new((burst_bytes as f64, Instant::now())),
+        })
+    }
+    
+    /// Take `bytes` and return how long the caller has to wait for them
+    fn charge(&self, bytes: u64) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, counted) = &mut *state;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*counted).as_secs_f64() * self.rate).min(self.burst);
+        *counted = now;
+        *tokens -= bytes as f64;
+        
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / self.rate)
+        }
+    }
+}
+
+/// A read and a write bucket; either may be unlimited
+struct Buckets {
+    read: Option<TokenBucket>,
+    write: Option<TokenBucket>,
+}
+
+impl Buckets {
+    fn new(read_bytes_per_sec: u64, write_bytes_per_sec: u64, burst_bytes: u64) -> Self {
+        Self {
+            read: TokenBucket::new(read_bytes_per_sec, burst_bytes),
+            write: TokenBucket::new(write_bytes_per_sec, burst_bytes),
+        }
+    }
+    
+    fn charge(&self, direction: Direction, bytes: u64) -> Duration {
+        let bucket = match direction {
+            Direction::Read => &self.read,
+            Direction::Write => &self.write,
+        };
+        bucket.as_ref().map_or(Duration::ZERO, |bucket| bucket.charge(bytes))
+    }
+}
+
+/// Traffic through one direction, for `/proc/gnos/bandwidth`
+#[derive(Default)]
+struct Traffic {
+    bytes: AtomicU64,
+    waited_micros: AtomicU64,
+}
+
+/// The caps in force; cheap to share between drivers
+pub struct BandwidthLimiter {
+    config: BandwidthConfig,
+    global: Buckets,
+    drivers: HashMap<String, Buckets>,
+    read: Traffic,
+    written: Traffic,
+}
+
+impl BandwidthLimiter {
+    pub fn new(config: &BandwidthConfig) -> Arc<Self> {
+        let drivers = config.drivers.iter()
+            .map(|limit| {
+                let burst = limit.burst_bytes.unwrap_or(config.burst_bytes);
+                (limit.driver.clone(), Buckets::new(limit.read_bytes_per_sec, limit.write_bytes_per_sec, burst))
+            })
+            .collect();
+        
+        Arc::new(Self {
+            config: config.clone(),
+            global: Buckets::new(config.read_bytes_per_sec, config.write_bytes_per_sec, config.burst_bytes),
+            drivers,
+            read: Traffic::default(),
+            written: Traffic::default(),
+        })
+    }
+    
+    /// Whether any cap is set at all
+    pub fn enabled(&self) -> bool {
+        self.config.read_bytes_per_sec > 0
+            || self.config.write_bytes_per_sec > 0
+            || self.config.drivers.iter().any(|limit| limit.read_bytes_per_sec > 0 || limit.write_bytes_per_sec > 0)
+    }
+    
+    /// Charge a transfer through `driver` and wait until it fits under the caps
+    async fn pace(&self, driver: &str, direction: Direction, bytes: u64) {
+        let mut wait = self.global.charge(direction, bytes);
+        if let Some(buckets) = self.drivers.get(driver) {
+            wait = wait.max(buckets.charge(direction, bytes));
+        }
+        
+        let traffic = match direction {
+            Direction::Read => &self.read,
+            Direction::Write => &self.written,
+        };
+        traffic.bytes.fetch_add(bytes, Ordering::Relaxed);
+        if !wait.is_zero() {
+            debug!("Holding {} bytes through {} for {:?}", bytes, driver, wait);
+            traffic.waited_micros.fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+            tokio::time::sleep(wait).await;
+        }
+    }
+    
+    /// Plain-text view for `/proc/gnos/bandwidth`
+    pub fn status_report(&self) -> String {
+        let cap = |rate: u64| if rate == 0 { "unlimited".to_string() } else { format!("{} B/s", rate) };
+        let mut report = format!(
+            "read: {}\nwrite: {}\nburst: {} bytes\nbytes_read: {}\nbytes_written: {}\nread_held: {:.3}s\nwrite_held: {:.3}s\n",
+            cap(self.config.read_bytes_per_sec),
+            cap(self.config.write_bytes_per_sec),
+            self.config.burst_bytes,
+            self.read.bytes.load(Ordering::Relaxed),
+            self.written.bytes.load(Ordering::Relaxed),
+            self.read.waited_micros.load(Ordering::Relaxed) as f64 / 1e6,
+            self.written.waited_micros.load(Ordering::Relaxed) as f64 / 1e6,
+        );
+        for limit in &self.config.drivers {
+            report.push_str(&format!("driver\t{}\tread {}\twrite {}\n",
+                                     limit.driver, cap(limit.read_bytes_per_sec), cap(limit.write_bytes_per_sec)));
+        }
+        report
+    }
+}
+
+/// A driver whose transfers are paced by the bandwidth caps
+pub struct ThrottleDriver {
+    /// Config name per-driver caps match on, e.g. `http` or `http@acme`
+    driver: String,
+    inner: Arc<dyn GnosDriver>,
+    limiter: Arc<BandwidthLimiter>,
+}
+
+impl ThrottleDriver {
+    pub fn new(driver: &str, inner: Arc<dyn GnosDriver>, limiter: Arc<BandwidthLimiter>) -> Self {
+        Self { driver: driver.to_string(), inner, limiter }
+    }
+    
+    async fn pace(&self, direction: Direction, bytes: usize) {
+        self.limiter.pace(&self.driver, direction, bytes as u64).await;
+    }
+}
+
+#[async_trait]
+impl GnosDriver for ThrottleDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let data = self.inner.read(path).await?;
+        self.pace(Direction::Read, data.len()).await;
+        Ok(data)
+    }
+    
+    async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+        let (data, checksum) = self.inner.read_checked(path).await?;
+        self.pace(Direction::Read, data.len()).await;
+        Ok((data, checksum))
+    }
+    
+    async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        let data = self.inner.read_range(path, offset, len).await?;
+        self.pace(Direction::Read, data.len()).await;
+        Ok(data)
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.inner.streams(path)
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        let growth = self.inner.read_growing(path, have).await?;
+        // Only what lies past `have` is new traffic
+        let new = (growth.offset + growth.data.len() as u64).saturating_sub(have.max(growth.offset));
+        self.pace(Direction::Read, new as usize).await;
+        Ok(growth)
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.pace(Direction::Write, data.len()).await;
+        self.inner.write(path, data).await
+    }
+    
+    fn upload_checksums(&self, path: &Path) -> &[ChecksumAlgorithm] {
+        self.inner.upload_checksums(path)
+    }
+    
+    async fn write_with(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+        self.pace(Direction::Write, data.len()).await;
+        self.inner.write_with(path, data, options).await
+    }
+    
+    fn conditional_writes(&self, path: &Path) -> bool {
+        self.inner.conditional_writes(path)
+    }
+    
+    fn accepted_encodings(&self) -> &[&'static str] {
+        self.inner.accepted_encodings()
+    }
+    
+    async fn write_encoded(&self, path: &Path, data: &[u8], encoding: &str) -> Result<()> {
+        self.pace(Direction::Write, data.len()).await;
+        self.inner.write_encoded(path, data, encoding).await
+    }
+    
+    fn invokes(&self, path: &Path) -> bool {
+        self.inner.invokes(path)
+    }
+    
+    async fn invoke(&self, path: &Path, payload: &[u8]) -> Result<Bytes> {
+        self.pace(Direction::Write, payload.len()).await;
+        let reply = self.inner.invoke(path, payload).await?;
+        self.pace(Direction::Read, reply.len()).await;
+        Ok(reply)
+    }
+    
+    fn supports_parts(&self, path: &Path) -> bool {
+        self.inner.supports_parts(path)
+    }
+    
+    fn part_policy(&self, path: &Path) -> PartPolicy {
+        self.inner.part_policy(path)
+    }
+    
+    async fn begin_parts(&self, path: &Path) -> Result<String> {
+        self.inner.begin_parts(path).await
+    }
+    
+    async fn write_part(&self, path: &Path, upload_id: &str, part: u64, data: &[u8]) -> Result<()> {
+        self.pace(Direction::Write, data.len()).await;
+        self.inner.write_part(path, upload_id, part, data).await
+    }
+    
+    async fn complete_parts(&self, path: &Path, upload_id: &str, parts: u64) -> Result<()> {
+        self.inner.complete_parts(path, upload_id, parts).await
+    }
+    
+    async fn abort_parts(&self, path: &Path, upload_id: &str) -> Result<()> {
+        self.inner.abort_parts(path, upload_id).await
+    }
+    
+    fn supports_batches(&self) -> bool {
+        self.inner.supports_batches()
+    }
+    
+    async fn commit_batch(&self, ops: &[BatchOp]) -> Result<()> {
+        let bytes = ops.iter()
+            .map(|op| match op {
+                BatchOp::Write { data, .. } => data.len(),
+                BatchOp::Delete { .. } => 0,
+            })
+            .sum();
+        self.pace(Direction::Write, bytes).await;
+        self.inner.commit_batch(ops).await
+    }
+    
+    async fn materialize(&self, path: &Path, params: &PathParams) -> Result<()> {
+        self.inner.materialize(path, params).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        self.inner.create_dir(path).await
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        self.inner.delete(path).await
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        self.inner.list(path).await
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        self.inner.list_with_metadata(path).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        self.inner.exists(path).await
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        self.inner.metadata(path).await
+    }
+    
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        self.inner.supports(path)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        self.inner.prefixes()
+    }
+    
+    fn cache_mode(&self, path: &Path) -> CacheMode {
+        self.inner.cache_mode(path)
+    }
+    
+    fn private(&self, path: &Path) -> bool {
+        self.inner.private(path)
+    }
+    
+    fn watch(&self, path: &Path) -> Option<ChangeStream> {
+        self.inner.watch(path)
+    }
+}
@@ -0,0 +1,79 @@
+//! Content-defined chunking
+//!
+//! Splits data at positions chosen by a rolling gear hash rather than at
+//! fixed offsets, so identical content produces identical blocks even when
+//! it sits at different offsets in different objects.
+
+use std::ops::Range;
+
+/// Gear table derived from a fixed seed so block boundaries are stable across builds
+const GEAR: [u64; 256] = build_gear_table();
+
+const fn build_gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state = crate::GNOS_MAGIC;
+    let mut i = 0;
+    
+    // splitmix64
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    
+    table
+}
+
+#[derive(Debug, Clone)]
+pub struct Chunker {
+    min_size: usize,
+    max_size: usize,
+    mask: u64,
+}
+
+impl Chunker {
+    /// Blocks average `avg_size` bytes and stay within a quarter to four times that
+    pub fn new(avg_size: usize) -> Self {
+        let avg_size = std::cmp::max(avg_size, 256).next_power_of_two();
+        
+        Self {
+            min_size: avg_size / 4,
+            max_size: avg_size * 4,
+            mask: (avg_size - 1) as u64,
+        }
+    }
+    
+    pub fn split(&self, data: &[u8]) -> Vec<Range<usize>> {
+        let mut blocks = Vec::new();
+        let mut start = 0;
+        
+        while start < data.len() {
+            let end = start + self.cut_point(&data[start..]);
+            blocks.push(start..end);
+            start = end;
+        }
+        
+        blocks
+    }
+    
+    fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        
+        let limit = std::cmp::min(data.len(), self.max_size);
+        let mut hash = 0u64;
+        
+        for (i, &byte) in data[..limit].iter().enumerate().skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            if hash & self.mask == 0 {
+                return i + 1;
+            }
+        }
+        
+        limit
+    }
+}
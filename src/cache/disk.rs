@@ -0,0 +1,768 @@
+//! Disk-backed chunk cache
+//!
+//! Objects are cached as fixed-size chunks so partial reads of large objects
+//! only pull the ranges actually touched. Each chunk is stored as a manifest
+//! of content-addressed blocks: identical blocks from different objects or
+//! backends are written once and shared, optionally zstd-compressed on disk.
+//! Manifests record their origin path so the index can be rebuilt after a
+//! restart, and blocks are verified against the SHA-256 of their uncompressed
+//! content so corrupted data is discarded, never served.
+//!
+//! Each chunk also records the version of the object it was read from: its
+//! ETag, or its modification time and size for backends without one. A read
+//! names the version the VFS last saw, and an object whose version moved on
+//! has all its chunks dropped and fetched again. A read that can't name one,
+//! e.g. offline with no metadata seen yet, is served whatever is cached but
+//! caches nothing new. Objects whose backend reports neither an ETag nor a
+//! modification time have no version at all and aren't cached.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+use bytes::{Bytes, BytesMut};
+use ring::digest;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, info_span, instrument, warn, Instrument};
+
+use crate::cache::chunker::Chunker;
+use crate::cache::compress::CompressionPolicy;
+use crate::config::CacheConfig;
+use crate::drivers::{GnosDriver, ResourceMetadata};
+use crate::vfs::path::is_within;
+use crate::Result;
+
+const MANIFEST_MAGIC: &[u8; 8] = b"GNOSCHK4";
+/// Manifests from before versions were recorded, which can't be revalidated
+const MANIFEST_MAGIC_V3: &[u8; 8] = b"GNOSCHK3";
+const HASH_LEN: usize = 32;
+const MAX_PATH_LEN: usize = 4096;
+const BLOCKS_DIR: &str = "blocks";
+
+// First byte of every block file
+const BLOCK_RAW: u8 = 0;
+const BLOCK_ZSTD: u8 = 1;
+
+/// Numbers temporary files, which `write_atomically` names per write
+static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+
+type BlockHash = [u8; HASH_LEN];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ChunkId {
+    path: PathBuf,
+    index: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BlockRef {
+    hash: BlockHash,
+    /// Uncompressed length
+    len: u32,
+    /// Size of the block file on disk
+    stored_len: u32,
+}
+
+#[derive(Debug)]
+struct Manifest {
+    id: ChunkId,
+    /// Version of the object the chunk was read from, see `object_version`
+    version: String,
+    blocks: Vec<BlockRef>,
+}
+
+impl Manifest {
+    fn logical_size(&self) -> u64 {
+        self.blocks.iter().map(|block| block.len as u64).sum()
+    }
+}
+
+#[derive(Debug)]
+struct IndexEntry {
+    manifest_size: u64,
+    version: String,
+    blocks: Vec<BlockRef>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct BlockEntry {
+    refs: u32,
+    size: u64,
+}
+
+/// Snapshot of how much the cache holds and how much dedup saved
+#[derive(Debug, Clone, Copy)]
+pub struct CacheUsage {
+    pub chunks: usize,
+    pub blocks: usize,
+    pub logical_bytes: u64,
+    pub physical_bytes: u64,
+}
+
+/// In-memory view of what is on disk, ordered for LRU eviction
+#[derive(Debug, Default)]
+struct CacheIndex {
+    entries: HashMap<ChunkId, IndexEntry>,
+    lru: BTreeMap<u64, ChunkId>,
+    objects: HashMap<PathBuf, BTreeSet<u64>>,
+    blocks: HashMap<BlockHash, BlockEntry>,
+    logical_bytes: u64,
+    physical_bytes: u64,
+    tick: u64,
+}
+
+/// What the index holds for a chunk of a given version of its object
+enum Lookup {
+    Hit(Vec<BlockRef>),
+    /// Cached from another version
+    Stale,
+}
+
+impl CacheIndex {
+    /// A chunk's blocks, if it is cached from `version` of its object, or
+    /// from any version when that isn't known
+    fn touch(&mut self, id: &ChunkId, version: Option<&str>) -> Option<Lookup> {
+        self.tick += 1;
+        let tick = self.tick;
+        
+        let entry = self.entries.get_mut(id)?;
+        if version.is_some_and(|version| version != entry.version) {
+            return Some(Lookup::Stale);
+        }
+        self.lru.remove(&entry.tick);
+        entry.tick = tick;
+        self.lru.insert(tick, id.clone());
+        Some(Lookup::Hit(entry.blocks.clone()))
+    }
+    
+    fn has_block(&self, hash: &BlockHash) -> bool {
+        self.blocks.contains_key(hash)
+    }
+    
+    fn stored_len(&self, hash: &BlockHash) -> Option<u32> {
+        self.blocks.get(hash).map(|block| block.size as u32)
+    }
+    
+    /// Record a chunk; returns blocks orphaned by a chunk it replaced
+    fn insert(&mut self, manifest: Manifest, manifest_size: u64) -> Vec<BlockHash> {
+        let orphaned = self.remove(&manifest.id);
+        
+        for block_ref in &manifest.blocks {
+            let block = self.blocks.entry(block_ref.hash)
+                .or_insert(BlockEntry { refs: 0, size: block_ref.stored_len as u64 });
+            if block.refs == 0 {
+                self.physical_bytes += block.size;
+            }
+            block.refs += 1;
+        }
+        
+        self.tick += 1;
+        self.logical_bytes += manifest.logical_size();
+        self.physical_bytes += manifest_size;
+        self.lru.insert(self.tick, manifest.id.clone());
+        self.objects.entry(manifest.id.path.clone()).or_default().insert(manifest.id.index);
+        self.entries.insert(manifest.id, IndexEntry {
+            manifest_size,
+            version: manifest.version,
+            blocks: manifest.blocks,
+            tick: self.tick,
+        });
+        
+        // Blocks of the replaced chunk that the new one reuses are still alive
+        orphaned.into_iter().filter(|hash| !self.has_block(hash)).collect()
+    }
+    
+    /// Forget a chunk; returns blocks no other chunk references any more
+    fn remove(&mut self, id: &ChunkId) -> Vec<BlockHash> {
+        let Some(entry) = self.entries.remove(id) else {
+            return Vec::new();
+        };
+        self.lru.remove(&entry.tick);
+        
+        if let Some(indices) = self.objects.get_mut(&id.path) {
+            indices.remove(&id.index);
+            if indices.is_empty() {
+                self.objects.remove(&id.path);
+            }
+        }
+        
+        self.physical_bytes -= entry.manifest_size;
+        
+        let mut orphaned = Vec::new();
+        for block_ref in entry.blocks {
+            self.logical_bytes -= block_ref.len as u64;
+            if let Some(block) = self.blocks.get_mut(&block_ref.hash) {
+                block.refs -= 1;
+                if block.refs == 0 {
+                    self.physical_bytes -= block.size;
+                    self.blocks.remove(&block_ref.hash);
+                    orphaned.push(block_ref.hash);
+                }
+            }
+        }
+        
+        orphaned
+    }
+    
+    fn pop_lru(&mut self) -> Option<(ChunkId, Vec<BlockHash>)> {
+        let id = self.lru.values().next()?.clone();
+        let orphaned = self.remove(&id);
+        Some((id, orphaned))
+    }
+}
+
+pub struct DiskCache {
+    dir: PathBuf,
+    chunk_size: u64,
+    max_bytes: u64,
+    prefixes: Vec<String>,
+    chunker: Option<Chunker>,
+    compression: Arc<CompressionPolicy>,
+    index: Mutex<CacheIndex>,
+}
+
+impl DiskCache {
+    /// Open (or create) the cache directory and index any chunks left by a previous mount
+    pub async fn open(config: CacheConfig, compression: Arc<CompressionPolicy>) -> Result<Self> {
+        tokio::fs::create_dir_all(config.dir.join(BLOCKS_DIR)).await?;
+        
+        let chunker = config.dedup
+            .then(|| Chunker::new((config.dedup_avg_block_kb * 1024) as usize));
+        
+        let cache = Self {
+            dir: config.dir,
+            chunk_size: std::cmp::max(config.chunk_size_kb, 1) * 1024,
+            max_bytes: config.max_size_mb * 1024 * 1024,
+            prefixes: config.prefixes,
+            chunker,
+            compression,
+            index: Mutex::new(CacheIndex::default()),
+        };
+        
+        cache.rebuild_index().await?;
+        
+        let usage = cache.usage();
+        info!("💾 Disk cache at {}: {} chunks in {} blocks, {} bytes cached in {} bytes on disk",
+              cache.dir.display(), usage.chunks, usage.blocks, usage.logical_bytes, usage.physical_bytes);
+        
+        Ok(cache)
+    }
+    
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+    
+    /// Whether reads of this path should go through the cache
+    pub fn handles(&self, path: &Path) -> bool {
+        self.prefixes.iter().any(|prefix| is_within(path, prefix))
+    }
+    
+    pub fn usage(&self) -> CacheUsage {
+        let index = self.index.lock().unwrap();
+        CacheUsage {
+            chunks: index.entries.len(),
+            blocks: index.blocks.len(),
+            logical_bytes: index.logical_bytes,
+            physical_bytes: index.physical_bytes,
+        }
+    }
+    
+    /// A cached chunk of `version` of the object; chunks of any other
+    /// version are stale, and the whole object is dropped
+    pub async fn get(&self, path: &Path, version: Option<&str>, index: u64) -> Option<Bytes> {
+        let id = ChunkId { path: path.to_path_buf(), index };
+        let touched = self.index.lock().unwrap().touch(&id, version);
+        let blocks = match touched? {
+            Lookup::Hit(blocks) => blocks,
+            Lookup::Stale => {
+                debug!("Cached chunks of {} are of an older version", path.display());
+                self.invalidate(path).await;
+                return None;
+            }
+        };
+        
+        let mut payload = BytesMut::new();
+        for block_ref in &blocks {
+            match self.read_block(&block_ref.hash).await {
+                Some(block) if blocks.len() == 1 => return Some(block),
+                Some(block) => payload.extend_from_slice(&block),
+                None => {
+                    warn!("🧨 Discarding cache chunk {}#{} with a missing or corrupt block",
+                          path.display(), index);
+                    let _ = tokio::fs::remove_file(self.block_file(&block_ref.hash)).await;
+                    self.discard(&id).await;
+                    return None;
+                }
+            }
+        }
+        
+        Some(payload.freeze())
+    }
+    
+    pub async fn put(&self, path: &Path, version: &str, index: u64, data: &Bytes) -> Result<()> {
+        let id = ChunkId { path: path.to_path_buf(), index };
+        
+        let ranges = match &self.chunker {
+            Some(chunker) => chunker.split(data),
+            None => std::iter::once(0..data.len()).collect(),
+        };
+        
+        // Only blocks the cache has never seen are written
+        let compress = self.compression.compress_cache(path);
+        let mut blocks = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            let block = &data[range];
+            let hash = block_hash(block);
+            
+            let known = self.index.lock().unwrap().stored_len(&hash);
+            let stored_len = match known {
+                Some(stored_len) => stored_len,
+                None => {
+                    let stored = self.encode_block(block, compress)?;
+                    write_atomically(&self.block_file(&hash), &stored).await?;
+                    stored.len() as u32
+                }
+            };
+            blocks.push(BlockRef { hash, len: block.len() as u32, stored_len });
+        }
+        
+        let manifest = Manifest { id, version: version.to_string(), blocks };
+        let encoded = encode_manifest(&manifest);
+        let manifest_file = self.manifest_file(&manifest.id);
+        write_atomically(&manifest_file, &encoded).await?;
+        
+        let (orphaned, evicted) = {
+            let mut cache_index = self.index.lock().unwrap();
+            let mut orphaned = cache_index.insert(manifest, encoded.len() as u64);
+            
+            let mut evicted = Vec::new();
+            while cache_index.physical_bytes > self.max_bytes {
+                match cache_index.pop_lru() {
+                    Some((victim, victim_blocks)) => {
+                        evicted.push(victim);
+                        orphaned.extend(victim_blocks);
+                    }
+                    None => break,
+                }
+            }
+            (orphaned, evicted)
+        };
+        
+        for victim in evicted {
+            debug!("Evicting cache chunk {}#{}", victim.path.display(), victim.index);
+            let _ = tokio::fs::remove_file(self.manifest_file(&victim)).await;
+        }
+        self.remove_blocks(orphaned).await;
+        
+        Ok(())
+    }
+    
+    /// Drop every cached chunk of an object, e.g. after it was written
+    pub async fn invalidate(&self, path: &Path) {
+        let (ids, orphaned) = {
+            let mut cache_index = self.index.lock().unwrap();
+            let indices = cache_index.objects.get(path).cloned().unwrap_or_default();
+            
+            let mut ids = Vec::new();
+            let mut orphaned = Vec::new();
+            for index in indices {
+                let id = ChunkId { path: path.to_path_buf(), index };
+                orphaned.extend(cache_index.remove(&id));
+                ids.push(id);
+            }
+            (ids, orphaned)
+        };
+        
+        for id in ids {
+            let _ = tokio::fs::remove_file(self.manifest_file(&id)).await;
+        }
+        self.remove_blocks(orphaned).await;
+    }
+    
+    /// Serve a read window from cached chunks of `version` of the object,
+    /// fetching missing or stale ones from the driver
+    #[instrument(name = "cache.read_through", skip_all, fields(path = %path.display(), offset = offset, size = size))]
+    pub async fn read_through(
+        &self,
+        driver: &dyn GnosDriver,
+        path: &Path,
+        version: Option<&str>,
+        offset: u64,
+        size: u32,
+    ) -> Result<Bytes> {
+        if size == 0 {
+            return Ok(Bytes::new());
+        }
+        
+        let end = offset + size as u64;
+        let first = offset / self.chunk_size;
+        let last = (end - 1) / self.chunk_size;
+        let mut window = BytesMut::new();
+        
+        for index in first..=last {
+            let chunk = match self.get(path, version, index).await {
+                Some(chunk) => chunk,
+                None => {
+                    let chunk = driver.read_range(path, index * self.chunk_size, self.chunk_size)
+                        .instrument(info_span!("driver.read_range", driver = driver.name(), index = index))
+                        .await?;
+                    if let Some(version) = version {
+                        if let Err(e) = self.put(path, version, index, &chunk).await {
+                            warn!("⚠️  Failed to cache chunk {}#{}: {}", path.display(), index, e);
+                        }
+                    }
+                    chunk
+                }
+            };
+            
+            let chunk_start = index * self.chunk_size;
+            let from = std::cmp::min(offset.saturating_sub(chunk_start) as usize, chunk.len());
+            let to = std::cmp::min((end - chunk_start) as usize, chunk.len());
+            
+            // Single-chunk reads hand back a view of the chunk without copying
+            if first == last {
+                return Ok(chunk.slice(from..to));
+            }
+            window.extend_from_slice(&chunk[from..to]);
+            
+            // A short chunk marks the end of the object
+            if (chunk.len() as u64) < self.chunk_size {
+                break;
+            }
+        }
+        
+        Ok(window.freeze())
+    }
+    
+    fn encode_block(&self, block: &[u8], compress: bool) -> Result<Vec<u8>> {
+        if compress {
+            let compressed = self.compression.compress(block)?;
+            if compressed.len() < block.len() {
+                let mut stored = Vec::with_capacity(compressed.len() + 1);
+                stored.push(BLOCK_ZSTD);
+                stored.extend_from_slice(&compressed);
+                return Ok(stored);
+            }
+        }
+        
+        let mut stored = Vec::with_capacity(block.len() + 1);
+        stored.push(BLOCK_RAW);
+        stored.extend_from_slice(block);
+        Ok(stored)
+    }
+    
+    async fn read_block(&self, hash: &BlockHash) -> Option<Bytes> {
+        let stored = Bytes::from(tokio::fs::read(self.block_file(hash)).await.ok()?);
+        
+        let block = match *stored.first()? {
+            BLOCK_RAW => stored.slice(1..),
+            BLOCK_ZSTD => Bytes::from(CompressionPolicy::decompress(&stored[1..]).ok()?),
+            _ => return None,
+        };
+        
+        (block_hash(&block) == *hash).then_some(block)
+    }
+    
+    async fn remove_blocks(&self, hashes: Vec<BlockHash>) {
+        for hash in hashes {
+            let _ = tokio::fs::remove_file(self.block_file(&hash)).await;
+        }
+    }
+    
+    async fn discard(&self, id: &ChunkId) {
+        let orphaned = self.index.lock().unwrap().remove(id);
+        let _ = tokio::fs::remove_file(self.manifest_file(id)).await;
+        self.remove_blocks(orphaned).await;
+    }
+    
+    fn manifest_file(&self, id: &ChunkId) -> PathBuf {
+        let mut ctx = digest::Context::new(&digest::SHA256);
+        ctx.update(id.path.as_os_str().as_bytes());
+        ctx.update(&[0]);
+        ctx.update(&id.index.to_le_bytes());
+        let name = hex(ctx.finish().as_ref());
+        
+        self.dir.join(&name[..2]).join(format!("{}.chunk", name))
+    }
+    
+    fn block_file(&self, hash: &BlockHash) -> PathBuf {
+        let name = hex(hash);
+        self.dir.join(BLOCKS_DIR).join(&name[..2]).join(format!("{}.blk", name))
+    }
+    
+    async fn rebuild_index(&self) -> Result<()> {
+        let mut shards = tokio::fs::read_dir(&self.dir).await?;
+        
+        while let Some(shard) = shards.next_entry().await? {
+            if !shard.file_type().await?.is_dir() || shard.file_name() == BLOCKS_DIR {
+                continue;
+            }
+            
+            let mut files = tokio::fs::read_dir(shard.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let file_path = file.path();
+                
+                match file_path.extension().and_then(|ext| ext.to_str()) {
+                    Some("chunk") => {}
+                    Some("tmp") => {
+                        let _ = tokio::fs::remove_file(&file_path).await;
+                        continue;
+                    }
+                    _ => continue,
+                }
+                
+                let raw = tokio::fs::read(&file_path).await?;
+                match decode_manifest(&raw) {
+                    Some(manifest) => {
+                        self.index.lock().unwrap().insert(manifest, raw.len() as u64);
+                    }
+                    None if raw.starts_with(MANIFEST_MAGIC_V3) => {
+                        debug!("Dropping unversioned cache manifest {}", file_path.display());
+                        let _ = tokio::fs::remove_file(&file_path).await;
+                    }
+                    None => {
+                        warn!("🧨 Removing unreadable cache manifest {}", file_path.display());
+                        let _ = tokio::fs::remove_file(&file_path).await;
+                    }
+                }
+            }
+        }
+        
+        self.sweep_orphaned_blocks().await
+    }
+    
+    /// Delete block files no manifest refers to, e.g. after a crash mid-put
+    async fn sweep_orphaned_blocks(&self) -> Result<()> {
+        let live: HashSet<String> = self.index.lock().unwrap()
+            .blocks.keys()
+            .map(|hash| format!("{}.blk", hex(hash)))
+            .collect();
+        
+        let mut shards = tokio::fs::read_dir(self.dir.join(BLOCKS_DIR)).await?;
+        while let Some(shard) = shards.next_entry().await? {
+            if !shard.file_type().await?.is_dir() {
+                continue;
+            }
+            
+            let mut files = tokio::fs::read_dir(shard.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let name = file.file_name().to_string_lossy().to_string();
+                if !live.contains(&name) {
+                    debug!("Removing orphaned cache block {}", name);
+                    let _ = tokio::fs::remove_file(file.path()).await;
+                }
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+/// Write to a temporary file of this write's own, synced before it is
+/// renamed into place, so concurrent puts of one block or manifest don't
+/// share it and a crash never leaves a torn or empty file behind
+async fn write_atomically(file: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = file.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    
+    let tmp = file.with_extension(format!("{}.{}.tmp", std::process::id(), NEXT_TMP.fetch_add(1, Ordering::Relaxed)));
+    let written = async {
+        let mut out = tokio::fs::File::create(&tmp).await?;
+        out.write_all(data).await?;
+        out.sync_all().await?;
+        tokio::fs::rename(&tmp, file).await
+    }.await;
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(&tmp).await;
+    }
+    Ok(written?)
+}
+
+fn block_hash(data: &[u8]) -> BlockHash {
+    let mut hash = [0u8; HASH_LEN];
+    hash.copy_from_slice(digest::digest(&digest::SHA256, data).as_ref());
+    hash
+}
+
+// Manifest layout: magic | index (u64 LE) | path len (u32 LE) | path | version len (u32 LE)
+//                  | version | block count (u32 LE) | (sha256, len u32 LE, stored len u32 LE)*
+//                  | sha256 of all preceding bytes
+fn encode_manifest(manifest: &Manifest) -> Vec<u8> {
+    let path_bytes = manifest.id.path.as_os_str().as_bytes();
+    
+    let version = manifest.version.as_bytes();
+    
+    let mut out = Vec::with_capacity(28 + path_bytes.len() + version.len() + manifest.blocks.len() * (HASH_LEN + 8) + HASH_LEN);
+    out.extend_from_slice(MANIFEST_MAGIC);
+    out.extend_from_slice(&manifest.id.index.to_le_bytes());
+    out.extend_from_slice(&(path_bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(path_bytes);
+    out.extend_from_slice(&(version.len() as u32).to_le_bytes());
+    out.extend_from_slice(version);
+    out.extend_from_slice(&(manifest.blocks.len() as u32).to_le_bytes());
+    for block in &manifest.blocks {
+        out.extend_from_slice(&block.hash);
+        out.extend_from_slice(&block.len.to_le_bytes());
+        out.extend_from_slice(&block.stored_len.to_le_bytes());
+    }
+    
+    let checksum = block_hash(&out);
+    out.extend_from_slice(&checksum);
+    out
+}
+
+fn decode_manifest(raw: &[u8]) -> Option<Manifest> {
+    let body_len = raw.len().checked_sub(HASH_LEN)?;
+    let (body, checksum) = raw.split_at(body_len);
+    if block_hash(body) != checksum || body.get(..8)? != MANIFEST_MAGIC {
+        return None;
+    }
+    
+    let index = u64::from_le_bytes(body.get(8..16)?.try_into().ok()?);
+    let path_len = u32::from_le_bytes(body.get(16..20)?.try_into().ok()?) as usize;
+    if path_len > MAX_PATH_LEN {
+        return None;
+    }
+    let path = PathBuf::from(std::ffi::OsStr::from_bytes(body.get(20..20 + path_len)?));
+    
+    let mut cursor = 20 + path_len;
+    let version_len = u32::from_le_bytes(body.get(cursor..cursor + 4)?.try_into().ok()?) as usize;
+    let version = String::from_utf8(body.get(cursor + 4..cursor.checked_add(4 + version_len)?)?.to_vec()).ok()?;
+    cursor += 4 + version_len;
+    let count = u32::from_le_bytes(body.get(cursor..cursor + 4)?.try_into().ok()?) as usize;
+    cursor += 4;
+    
+    let mut blocks = Vec::new();
+    for _ in 0..count {
+        let hash: BlockHash = body.get(cursor..cursor + HASH_LEN)?.try_into().ok()?;
+        cursor += HASH_LEN;
+        let len = u32::from_le_bytes(body.get(cursor..cursor + 4)?.try_into().ok()?);
+        let stored_len = u32::from_le_bytes(body.get(cursor + 4..cursor + 8)?.try_into().ok()?);
+        cursor += 8;
+        blocks.push(BlockRef { hash, len, stored_len });
+    }
+    
+    Some(Manifest { id: ChunkId { path, index }, version, blocks })
+}
+
+/// The version a chunk is cached from: the object's ETag, or for backends
+/// without one its modification time and size. `None` when the backend
+/// reports neither, as the time then only says when the metadata was made
+pub fn object_version(metadata: &ResourceMetadata) -> Option<String> {
+    match &metadata.etag {
+        Some(etag) => Some(etag.clone()),
+        None if metadata.reports_modified() => {
+            let mtime = metadata.last_modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+            Some(format!("{}:{}", mtime, metadata.size))
+        }
+        None => None,
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    
+    #[test]
+    fn versions_come_from_etags_or_reported_times() {
+        let tagged = ResourceMetadata { etag: Some("\"9b2cf535\"".to_string()), ..ResourceMetadata::default() };
+        assert_eq!(object_version(&tagged).as_deref(), Some("\"9b2cf535\""));
+        
+        let dated = ResourceMetadata {
+            size: 3,
+            last_modified: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            ..ResourceMetadata::default()
+        };
+        assert_eq!(object_version(&dated).as_deref(), Some("1700000000000000000:3"));
+    }
+    
+    #[test]
+    fn stand_in_times_are_no_version() {
+        // The time `default()` leaves only says when the metadata was made
+        assert_eq!(object_version(&ResourceMetadata::default()), None);
+        assert_eq!(object_version(&ResourceMetadata { size: 3, ..ResourceMetadata::default() }), None);
+    }
+    
+    async fn open(dir: &Path) -> DiskCache {
+        let config = CacheConfig {
+            enabled: true,
+            dir: dir.to_path_buf(),
+            chunk_size_kb: 256,
+            prefixes: vec!["/".to_string()],
+            dedup: true,
+            dedup_avg_block_kb: 8,
+            ..CacheConfig::default()
+        };
+        DiskCache::open(config, Arc::new(CompressionPolicy::default())).await.unwrap()
+    }
+    
+    /// Bytes that don't repeat, so content-defined chunking cuts many blocks
+    fn shard() -> Bytes {
+        (0..200_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect::<Vec<u8>>().into()
+    }
+    
+    fn block_files(dir: &Path) -> usize {
+        std::fs::read_dir(dir.join(BLOCKS_DIR)).unwrap()
+            .flat_map(|shard| std::fs::read_dir(shard.unwrap().path()).unwrap())
+            .count()
+    }
+    
+    #[tokio::test]
+    async fn shared_blocks_live_until_their_last_chunk_goes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = open(dir.path()).await;
+        let data = shard();
+        let (original, copy) = (Path::new("/a/shard-0"), Path::new("/b/copy-of-shard-0"));
+        
+        cache.put(original, "v1", 0, &data).await.unwrap();
+        let first = cache.usage();
+        assert!(first.blocks > 1);
+        assert_eq!(block_files(dir.path()), first.blocks);
+        
+        // The copy's blocks are already stored, so only its manifest is new
+        cache.put(copy, "v1", 0, &data).await.unwrap();
+        let both = cache.usage();
+        let manifest = std::fs::metadata(cache.manifest_file(&ChunkId { path: copy.to_path_buf(), index: 0 })).unwrap().len();
+        assert_eq!((both.chunks, both.blocks), (2, first.blocks));
+        assert_eq!(both.logical_bytes, 2 * data.len() as u64);
+        assert_eq!(both.physical_bytes, first.physical_bytes + manifest);
+        
+        cache.invalidate(original).await;
+        assert_eq!(cache.usage().blocks, first.blocks);
+        assert_eq!(block_files(dir.path()), first.blocks);
+        assert_eq!(cache.get(copy, Some("v1"), 0).await, Some(data));
+        
+        cache.invalidate(copy).await;
+        assert_eq!(cache.usage().blocks, 0);
+        assert_eq!(block_files(dir.path()), 0);
+    }
+    
+    #[tokio::test]
+    async fn concurrent_puts_of_one_chunk_both_land() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = open(dir.path()).await;
+        let data = shard();
+        let path = Path::new("/a/shard-0");
+        
+        let (first, second) = tokio::join!(cache.put(path, "v1", 0, &data), cache.put(path, "v1", 0, &data));
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(cache.get(path, Some("v1"), 0).await, Some(data.clone()));
+        
+        // Nothing half-written is left for the next mount to trip over
+        drop(cache);
+        let reopened = open(dir.path()).await;
+        assert_eq!(reopened.usage().chunks, 1);
+        assert_eq!(reopened.get(path, Some("v1"), 0).await, Some(data));
+    }
+}
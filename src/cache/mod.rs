@@ -0,0 +1,9 @@
+//! Local caching layers that sit between the VFS and the drivers.
+
+pub mod chunker;
+pub mod compress;
+pub mod disk;
+
+pub use chunker::Chunker;
+pub use compress::CompressionPolicy;
+pub use disk::{object_version, CacheUsage, DiskCache};
@@ -0,0 +1,482 @@
+//! Checksums on transfers
+//!
+//! With `[checksums]` enabled every driver is wrapped so that uploads carry
+//! digests of their payload in the algorithms the backend checks, and whole
+//! downloads are compared with the digest the backend reports for the
+//! object. A mismatch either way fails the call with `ChecksumMismatch`,
+//! which the mount answers with EIO, and is recorded in the audit log, so
+//! corrupted data is neither stored nor handed to a reader unnoticed.
+//! `/proc/gnos/checksums` counts what was checked and lists recent
+//! mismatches.
+//!
+//! Ranged and streaming reads can't be checked against a whole-object
+//! digest and pass through as they are, as do downloads from backends that
+//! report no digest.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use bytes::Bytes;
+use md5::{Digest as _, Md5};
+use reqwest::header::HeaderMap;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::cache::disk::hex;
+use crate::config::{CacheMode, ChecksumConfig};
+use crate::drivers::{BatchOp, ChangeStream, GnosDriver, Growth, PartPolicy, PathParams, ResourceMetadata, WriteOptions};
+use crate::security::{CapabilityManager, Operation};
+use crate::{GnosError, Result};
+
+/// Mismatches kept for `/proc/gnos/checksums`; the oldest are dropped first
+const MAX_MISMATCHES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha256,
+    Crc32c,
+}
+
+impl ChecksumAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Md5 => "md5",
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Crc32c => "crc32c",
+        }
+    }
+    
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ChecksumAlgorithm::Md5 => Md5::digest(data).to_vec(),
+            ChecksumAlgorithm::Sha256 => digest::digest(&digest::SHA256, data).as_ref().to_vec(),
+            // Big-endian, as S3 and GCS encode it
+            ChecksumAlgorithm::Crc32c => crc32c::crc32c(data).to_be_bytes().to_vec(),
+        }
+    }
+    
+    fn len(self) -> usize {
+        match self {
+            ChecksumAlgorithm::Md5 => 16,
+            ChecksumAlgorithm::Sha256 => 32,
+            ChecksumAlgorithm::Crc32c => 4,
+        }
+    }
+    
+    /// Preference when a backend reports several digests
+    fn strength(self) -> u8 {
+        match self {
+            ChecksumAlgorithm::Crc32c => 0,
+            ChecksumAlgorithm::Md5 => 1,
+            ChecksumAlgorithm::Sha256 => 2,
+        }
+    }
+}
+
+/// A digest of a payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub digest: Vec<u8>,
+    /// Where the digest came from: a header name such as `etag`, or
+    /// `computed` for one made here
+    pub source: &'static str,
+}
+
+impl Checksum {
+    pub fn compute(algorithm: ChecksumAlgorithm, data: &[u8]) -> Self {
+        Self {
+            algorithm,
+            digest: algorithm.digest(data),
+            source: "computed",
+        }
+    }
+    
+    /// A base64 digest, as digest headers carry it
+    pub fn from_base64(algorithm: ChecksumAlgorithm, value: &str, source: &'static str) -> Option<Self> {
+        let digest = STANDARD.decode(value.trim()).ok()?;
+        (digest.len() == algorithm.len()).then_some(Self { algorithm, digest, source })
+    }
+    
+    /// A hex digest, as listings and manifests carry it
+    pub fn from_hex(algorithm: ChecksumAlgorithm, value: &str, source: &'static str) -> Option<Self> {
+        let value = value.trim();
+        if value.len() != algorithm.len() * 2 || !value.is_ascii() {
+            return None;
+        }
+        let digest = (0..value.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&value[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .ok()?;
+        Some(Self { algorithm, digest, source })
+    }
+    
+    /// An ETag that is the object's MD5; weak and multipart (`-N`) ETags
+    /// aren't digests of the content
+    pub fn from_etag(etag: &str) -> Option<Self> {
+        let etag = etag.trim();
+        if etag.starts_with("W/") {
+            return None;
+        }
+        Self::from_hex(ChecksumAlgorithm::Md5, etag.trim_matches('"'), "etag")
+    }
+    
+    /// The strongest digest among a response's headers, falling back to
+    /// its ETag
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut found = Vec::new();
+        
+        // RFC 9530 `sha-256=:<base64>:` dictionaries and RFC 3230 `SHA-256=<base64>` lists
+        for name in ["repr-digest", "digest"] {
+            for value in headers.get_all(name).iter().filter_map(|value| value.to_str().ok()) {
+                for item in value.split(',') {
+                    let Some((algorithm, digest)) = item.split_once('=') else {
+                        continue;
+                    };
+                    let algorithm = match algorithm.trim().to_ascii_lowercase().as_str() {
+                        "sha-256" => ChecksumAlgorithm::Sha256,
+                        "md5" => ChecksumAlgorithm::Md5,
+                        _ => continue,
+                    };
+                    found.extend(Self::from_base64(algorithm, digest.trim().trim_matches(':'), name));
+                }
+            }
+        }
+        
+        for (name, algorithm) in [
+            ("x-amz-checksum-sha256", ChecksumAlgorithm::Sha256),
+            ("x-amz-checksum-crc32c", ChecksumAlgorithm::Crc32c),
+            ("content-md5", ChecksumAlgorithm::Md5),
+        ] {
+            if let Some(value) = headers.get(name).and_then(|value| value.to_str().ok()) {
+                found.extend(Self::from_base64(algorithm, value, name));
+            }
+        }
+        
+        // GCS: `x-goog-hash: crc32c=<base64>,md5=<base64>`, possibly split over several headers
+        for value in headers.get_all("x-goog-hash").iter().filter_map(|value| value.to_str().ok()) {
+            for item in value.split(',') {
+                match item.trim().split_once('=') {
+                    Some(("crc32c", digest)) => found.extend(Self::from_base64(ChecksumAlgorithm::Crc32c, digest, "x-goog-hash")),
+                    Some(("md5", digest)) => found.extend(Self::from_base64(ChecksumAlgorithm::Md5, digest, "x-goog-hash")),
+                    _ => {}
+                }
+            }
+        }
+        
+        found.into_iter()
+            .max_by_key(|checksum| checksum.algorithm.strength())
+            .or_else(|| {
+                headers.get("etag")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(Self::from_etag)
+            })
+    }
+    
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(&self.digest)
+    }
+    
+    pub fn matches(&self, data: &[u8]) -> bool {
+        self.algorithm.digest(data) == self.digest
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), hex(&self.digest))
+    }
+}
+
+/// A transfer whose data didn't match its digest
+#[derive(Debug, Clone)]
+struct Mismatch {
+    at: SystemTime,
+    op: &'static str,
+    driver: &'static str,
+    path: PathBuf,
+    expected: String,
+    actual: String,
+}
+
+/// Checksum policy and counters shared by every wrapped driver
+pub struct ChecksumVerifier {
+    trust_etags: bool,
+    capability_manager: Arc<CapabilityManager>,
+    /// Uploads sent with digests or acknowledged with one
+    uploads: AtomicU64,
+    /// Whole downloads checked against the backend's digest
+    verified: AtomicU64,
+    /// Whole downloads the backend reported no usable digest for
+    unverified: AtomicU64,
+    mismatch_count: AtomicU64,
+    mismatches: Mutex<VecDeque<Mismatch>>,
+}
+
+impl ChecksumVerifier {
+    pub fn new(config: &ChecksumConfig, capability_manager: Arc<CapabilityManager>) -> Arc<Self> {
+        Arc::new(Self {
+            trust_etags: config.trust_etags,
+            capability_manager,
+            uploads: AtomicU64::new(0),
+            verified: AtomicU64::new(0),
+            unverified: AtomicU64::new(0),
+            mismatch_count: AtomicU64::new(0),
+            mismatches: Mutex::new(VecDeque::new()),
+        })
+    }
+    
+    fn usable(&self, checksum: Option<Checksum>) -> Option<Checksum> {
+        checksum.filter(|checksum| self.trust_etags || checksum.source != "etag")
+    }
+    
+    fn check_download(&self, driver: &'static str, path: &Path, data: &[u8], expected: Option<Checksum>) -> Result<()> {
+        let Some(expected) = self.usable(expected) else {
+            self.unverified.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        };
+        let actual = expected.algorithm.digest(data);
+        if actual != expected.digest {
+            return Err(self.mismatch("read", Operation::Read, driver, path, &expected, &actual));
+        }
+        self.verified.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+    
+    /// Compare the digest the backend stored with what was sent
+    fn check_upload(&self, driver: &'static str, path: &Path, data: &[u8], sent: &[Checksum], stored: Option<Checksum>) -> Result<()> {
+        let stored = self.usable(stored);
+        if sent.is_empty() && stored.is_none() {
+            return Ok(());
+        }
+        self.uploads.fetch_add(1, Ordering::Relaxed);
+        
+        let Some(stored) = stored else {
+            return Ok(());
+        };
+        let actual = sent.iter()
+            .find(|checksum| checksum.algorithm == stored.algorithm)
+            .map_or_else(|| stored.algorithm.digest(data), |checksum| checksum.digest.clone());
+        if actual != stored.digest {
+            return Err(self.mismatch("write", Operation::Write, driver, path, &stored, &actual));
+        }
+        Ok(())
+    }
+    
+    fn mismatch(&self, op: &'static str, operation: Operation, driver: &'static str, path: &Path, expected: &Checksum, actual: &[u8]) -> GnosError {
+        let actual = Checksum { algorithm: expected.algorithm, digest: actual.to_vec(), source: "computed" };
+        let detail = format!("{} of {} through {}: {} is {}, data hashes to {}",
+                             op, path.display(), driver, expected.source, expected, actual);
+        warn!("🧬 Checksum mismatch on {}", detail);
+        self.capability_manager.audit_integrity(path, operation, driver, detail.clone());
+        
+        self.mismatch_count.fetch_add(1, Ordering::Relaxed);
+        let mut mismatches = self.mismatches.lock().unwrap();
+        if mismatches.len() >= MAX_MISMATCHES {
+            mismatches.pop_front();
+        }
+        mismatches.push_back(Mismatch {
+            at: SystemTime::now(),
+            op,
+            driver,
+            path: path.to_path_buf(),
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        });
+        
+        GnosError::ChecksumMismatch(detail)
+    }
+    
+    /// Plain-text view for `/proc/gnos/checksums`
+    pub fn status_report(&self) -> String {
+        let mut report = format!(
+            "uploads: {}\nverified: {}\nunverified: {}\nmismatches: {}\n",
+            self.uploads.load(Ordering::Relaxed),
+            self.verified.load(Ordering::Relaxed),
+            self.unverified.load(Ordering::Relaxed),
+            self.mismatch_count.load(Ordering::Relaxed),
+        );
+        for mismatch in self.mismatches.lock().unwrap().iter() {
+            let at = chrono::DateTime::<chrono::Utc>::from(mismatch.at);
+            report.push_str(&format!("{}\t{}\t{}\t{}\texpected {}\tgot {}\n",
+                                     at.to_rfc3339(), mismatch.op, mismatch.driver, mismatch.path.display(),
+                                     mismatch.expected, mismatch.actual));
+        }
+        report
+    }
+}
+
+/// A driver whose transfers are checked against their digests
+pub struct ChecksumDriver {
+    inner: Arc<dyn GnosDriver>,
+    verifier: Arc<ChecksumVerifier>,
+}
+
+impl ChecksumDriver {
+    pub fn new(inner: Arc<dyn GnosDriver>, verifier: Arc<ChecksumVerifier>) -> Self {
+        Self { inner, verifier }
+    }
+    
+    async fn upload(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+        let mut options = options.clone();
+        if options.checksums.is_empty() {
+            options.checksums = self.inner.upload_checksums(path).iter()
+                .map(|algorithm| Checksum::compute(*algorithm, data))
+                .collect();
+        }
+        
+        let stored = self.inner.write_with(path, data, &options).await?;
+        self.verifier.check_upload(self.inner.name(), path, data, &options.checksums, stored.clone())?;
+        Ok(stored)
+    }
+}
+
+#[async_trait]
+impl GnosDriver for ChecksumDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        Ok(self.read_checked(path).await?.0)
+    }
+    
+    async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+        let (data, expected) = self.inner.read_checked(path).await?;
+        self.verifier.check_download(self.inner.name(), path, &data, expected.clone())?;
+        Ok((data, expected))
+    }
+    
+    async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        self.inner.read_range(path, offset, len).await
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.inner.streams(path)
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        self.inner.read_growing(path, have).await
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.upload(path, data, &WriteOptions::default()).await.map(|_| ())
+    }
+    
+    fn upload_checksums(&self, path: &Path) -> &[ChecksumAlgorithm] {
+        self.inner.upload_checksums(path)
+    }
+    
+    async fn write_with(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+        self.upload(path, data, options).await
+    }
+    
+    fn conditional_writes(&self, path: &Path) -> bool {
+        self.inner.conditional_writes(path)
+    }
+    
+    fn accepted_encodings(&self) -> &[&'static str] {
+        self.inner.accepted_encodings()
+    }
+    
+    async fn write_encoded(&self, path: &Path, data: &[u8], encoding: &str) -> Result<()> {
+        self.inner.write_encoded(path, data, encoding).await
+    }
+    
+    fn invokes(&self, path: &Path) -> bool {
+        self.inner.invokes(path)
+    }
+    
+    async fn invoke(&self, path: &Path, payload: &[u8]) -> Result<Bytes> {
+        self.inner.invoke(path, payload).await
+    }
+    
+    fn supports_parts(&self, path: &Path) -> bool {
+        self.inner.supports_parts(path)
+    }
+    
+    fn part_policy(&self, path: &Path) -> PartPolicy {
+        self.inner.part_policy(path)
+    }
+    
+    async fn begin_parts(&self, path: &Path) -> Result<String> {
+        self.inner.begin_parts(path).await
+    }
+    
+    async fn write_part(&self, path: &Path, upload_id: &str, part: u64, data: &[u8]) -> Result<()> {
+        self.inner.write_part(path, upload_id, part, data).await
+    }
+    
+    async fn complete_parts(&self, path: &Path, upload_id: &str, parts: u64) -> Result<()> {
+        self.inner.complete_parts(path, upload_id, parts).await
+    }
+    
+    async fn abort_parts(&self, path: &Path, upload_id: &str) -> Result<()> {
+        self.inner.abort_parts(path, upload_id).await
+    }
+    
+    fn supports_batches(&self) -> bool {
+        self.inner.supports_batches()
+    }
+    
+    async fn commit_batch(&self, ops: &[BatchOp]) -> Result<()> {
+        self.inner.commit_batch(ops).await
+    }
+    
+    async fn materialize(&self, path: &Path, params: &PathParams) -> Result<()> {
+        self.inner.materialize(path, params).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        self.inner.create_dir(path).await
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        self.inner.delete(path).await
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        self.inner.list(path).await
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        self.inner.list_with_metadata(path).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        self.inner.exists(path).await
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        self.inner.metadata(path).await
+    }
+    
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        self.inner.supports(path)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        self.inner.prefixes()
+    }
+    
+    fn cache_mode(&self, path: &Path) -> CacheMode {
+        self.inner.cache_mode(path)
+    }
+    
+    fn private(&self, path: &Path) -> bool {
+        self.inner.private(path)
+    }
+    
+    fn watch(&self, path: &Path) -> Option<ChangeStream> {
+        self.inner.watch(path)
+    }
+}
@@ -0,0 +1,330 @@
+//! Library-mode access to the namespace
+//!
+//! `GnosClient` drives the driver registry and capability checks directly,
+//! so applications can embed GNOS without a FUSE mount or a daemon:
+//!
+//! ```no_run
+//! # async fn demo() -> gnos::Result<()> {
+//! use std::path::Path;
+//! use gnos::{config::GnosConfig, GnosClient};
+//!
+//! let client = GnosClient::new(&GnosConfig::default()).await?;
+//! client.write(Path::new("/proc/llama3"), b"Summarize this report").await?;
+//! let answer = client.read(Path::new("/proc/llama3")).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use futures::stream::{self, Stream};
+use tokio::time::{Interval, MissedTickBehavior};
+
+use crate::bandwidth::BandwidthLimiter;
+use crate::cache::CompressionPolicy;
+use crate::checksum::ChecksumVerifier;
+use crate::config::GnosConfig;
+use crate::copy::{CopyEngine, CopyProgress};
+use crate::dryrun::DryRun;
+use crate::drivers::{BatchOp, DriverRegistry, GnosDriver, ResourceMetadata};
+use crate::events::{EventBus, EventKind};
+use crate::plugins::PluginVerifier;
+use crate::pools::DriverPools;
+use crate::recovery::Supervisor;
+use crate::security::{CapabilityManager, Operation};
+use crate::telemetry::RequestId;
+use crate::txn::{self, Transaction};
+use crate::vfs::path::{join_name, normalize};
+use crate::{GnosError, Result};
+
+/// Change to a watched path
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    /// The resource appeared, or its size or modification time changed
+    Changed(ResourceMetadata),
+    Removed,
+}
+
+/// In-process handle on the namespace; cheap to clone
+#[derive(Clone)]
+pub struct GnosClient {
+    driver_registry: Arc<DriverRegistry>,
+    capability_manager: Arc<CapabilityManager>,
+    compression: Arc<CompressionPolicy>,
+    /// Capability presented on every call; when unset the mount's ambient
+    /// credentials are used, or `GNOS_TOKEN` without them
+    token: Option<Arc<str>>,
+    events: Option<Arc<EventBus>>,
+}
+
+impl GnosClient {
+    /// Load the drivers and security policy described by `config`
+    pub async fn new(config: &GnosConfig) -> Result<Self> {
+        let capability_manager = Arc::new(CapabilityManager::new(config.security.clone()));
+        let plugins = PluginVerifier::new(config.plugins.clone(), capability_manager.clone());
+        let mut driver_registry = plugins.load_all(DriverRegistry::new(config.drivers.clone()).await?).await?
+            .with_tenants(&config.tenants).await?
+            .with_recovery(Supervisor::new(&config.recovery));
+        if config.dry_run.enabled {
+            driver_registry = driver_registry.with_dry_run(DryRun::new(&config.dry_run, capability_manager.clone()));
+        }
+        if config.checksums.enabled {
+            driver_registry = driver_registry.with_checksums(
+                ChecksumVerifier::new(&config.checksums, capability_manager.clone())
+            );
+        }
+        let bandwidth = BandwidthLimiter::new(&config.bandwidth);
+        if bandwidth.enabled() {
+            driver_registry = driver_registry.with_bandwidth(bandwidth);
+        }
+        let driver_registry = Arc::new(driver_registry.with_pools(DriverPools::new(&config.pools)));
+        
+        Ok(Self::with_components(driver_registry, capability_manager)
+            .with_compression(Arc::new(CompressionPolicy::new(config.compression.clone()))))
+    }
+    
+    /// Share a registry and capability manager with a mount in the same process
+    pub fn with_components(
+        driver_registry: Arc<DriverRegistry>,
+        capability_manager: Arc<CapabilityManager>,
+    ) -> Self {
+        Self {
+            driver_registry,
+            capability_manager,
+            compression: Arc::new(CompressionPolicy::default()),
+            token: None,
+            events: None,
+        }
+    }
+    
+    /// Compress writes for path classes and backends that allow it
+    pub fn with_compression(mut self, compression: Arc<CompressionPolicy>) -> Self {
+        self.compression = compression;
+        self
+    }
+    
+    /// Publish every change made through this client on `events`
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.events = Some(events);
+        self
+    }
+    
+    /// Act with `token` instead of the process's `GNOS_TOKEN`, e.g. for a remote caller
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(Arc::from(token));
+        self
+    }
+    
+    pub async fn read(&self, path: &Path) -> Result<Bytes> {
+        RequestId::next().scope(async {
+            let (path, driver) = self.driver_for(path, Operation::Read).await?;
+            driver.read(&path).await
+        }).await
+    }
+    
+    /// Up to `len` bytes of a resource starting at `offset`
+    pub async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        RequestId::next().scope(async {
+            let (path, driver) = self.driver_for(path, Operation::Read).await?;
+            driver.read_range(&path, offset, len).await
+        }).await
+    }
+    
+    /// Replace the resource at `path` with `data`
+    pub async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        RequestId::next().scope(async {
+            let (path, driver) = self.driver_for(path, Operation::Write).await?;
+            let kind = self.write_kind(driver.as_ref(), &path).await;
+            self.compression.write_through(driver.as_ref(), &path, data).await?;
+            self.publish(kind, &path, driver.as_ref());
+            Ok(())
+        }).await
+    }
+    
+    /// Whether the capability behind this client still holds for a write
+    /// under way to `path`, checked before each chunk a remote caller
+    /// streams in; revoked or lapsed capabilities are refused
+    pub fn fence(&self, path: &Path, operation: Operation) -> Result<()> {
+        let path = normalize(path)?;
+        let token = match &self.token {
+            Some(token) => Some(token.to_string()),
+            None => self.capability_manager.local_grant(&path, operation),
+        };
+        match token {
+            Some(token) => self.capability_manager.fence(&token, &path, operation),
+            None => Ok(()),
+        }
+    }
+    
+    /// Entry names of a directory-like resource
+    pub async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        RequestId::next().scope(async {
+            let (path, driver) = self.driver_for(path, Operation::List).await?;
+            driver.list(&path).await
+        }).await
+    }
+    
+    /// Remove a resource, for backends that support it
+    pub async fn delete(&self, path: &Path) -> Result<()> {
+        RequestId::next().scope(async {
+            let (path, driver) = self.driver_for(path, Operation::Write).await?;
+            driver.delete(&path).await?;
+            self.publish(EventKind::Deleted, &path, driver.as_ref());
+            Ok(())
+        }).await
+    }
+    
+    /// Entries of a directory with their metadata, asking the driver for any
+    /// its listing didn't include; entries that vanish meanwhile are skipped
+    pub async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, ResourceMetadata)>> {
+        RequestId::next().scope(async {
+            let (path, driver) = self.driver_for(path, Operation::List).await?;
+            let mut entries = Vec::new();
+            for (name, metadata) in driver.list_with_metadata(&path).await? {
+                let Ok(child) = join_name(&path, OsStr::new(&name)) else {
+                    continue;
+                };
+                let metadata = match metadata {
+                    Some(metadata) => metadata,
+                    None => match driver.metadata(&child).await {
+                        Ok(metadata) => metadata,
+                        Err(_) => continue,
+                    },
+                };
+                entries.push((name, metadata));
+            }
+            Ok(entries)
+        }).await
+    }
+    
+    /// Copy one resource to another, possibly on a different driver, through `engine`
+    pub async fn copy(&self, source: &Path, dest: &Path, engine: &CopyEngine, progress: &CopyProgress) -> Result<u64> {
+        RequestId::next().scope(async {
+            let (source, source_driver) = self.driver_for(source, Operation::Read).await?;
+            let (dest, dest_driver) = self.driver_for(dest, Operation::Write).await?;
+            let kind = self.write_kind(dest_driver.as_ref(), &dest).await;
+            let copied = engine.copy(source_driver.as_ref(), &source, dest_driver.as_ref(), &dest, progress).await?;
+            self.publish(kind, &dest, dest_driver.as_ref());
+            Ok(copied)
+        }).await
+    }
+    
+    /// Stage writes and deletes to apply together
+    pub fn transaction(&self) -> Transaction {
+        Transaction::new(self.clone())
+    }
+    
+    /// Check write access to every path, then apply `ops` as one transaction
+    pub(crate) async fn commit_batch(&self, ops: Vec<BatchOp>) -> Result<usize> {
+        RequestId::next().scope(async {
+            let mut changes = Vec::with_capacity(ops.len());
+            let mut normalized = Vec::with_capacity(ops.len());
+            for op in ops {
+                let (path, driver) = self.driver_for(op.path(), Operation::Write).await?;
+                let (kind, op) = match op {
+                    BatchOp::Write { data, .. } => {
+                        (self.write_kind(driver.as_ref(), &path).await, BatchOp::Write { path: path.clone(), data })
+                    }
+                    BatchOp::Delete { .. } => (EventKind::Deleted, BatchOp::Delete { path: path.clone() }),
+                };
+                changes.push((kind, path, driver));
+                normalized.push(op);
+            }
+            
+            let applied = txn::apply(&self.driver_registry, &self.compression, normalized).await?;
+            for (kind, path, driver) in changes {
+                self.publish(kind, &path, driver.as_ref());
+            }
+            Ok(applied)
+        }).await
+    }
+    
+    pub async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        RequestId::next().scope(async {
+            let (path, driver) = self.driver_for(path, Operation::Read).await?;
+            driver.metadata(&path).await
+        }).await
+    }
+    
+    /// Poll `path` every `interval` and yield an event whenever it changes
+    ///
+    /// The first poll only records the current state. Errors other than the
+    /// path disappearing are yielded and polling carries on.
+    pub fn watch(&self, path: &Path, interval: Duration) -> impl Stream<Item = Result<WatchEvent>> {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        
+        let watch = Watch {
+            client: self.clone(),
+            path: path.to_path_buf(),
+            ticker,
+            seen: None,
+        };
+        
+        stream::unfold(watch, |mut watch| async move {
+            loop {
+                watch.ticker.tick().await;
+                
+                let current = match watch.client.metadata(&watch.path).await {
+                    Ok(metadata) => Some(metadata),
+                    Err(GnosError::PathNotFound(_)) => None,
+                    Err(e) => return Some((Err(e), watch)),
+                };
+                
+                let version = current.as_ref().map(|m| (m.size, m.last_modified));
+                match watch.seen.replace(version) {
+                    Some(previous) if previous != version => {}
+                    _ => continue,
+                }
+                
+                let event = match current {
+                    Some(metadata) => WatchEvent::Changed(metadata),
+                    None => WatchEvent::Removed,
+                };
+                return Some((Ok(event), watch));
+            }
+        })
+    }
+    
+    async fn write_kind(&self, driver: &dyn GnosDriver, path: &Path) -> EventKind {
+        match &self.events {
+            Some(events) => events.write_kind(driver, path).await,
+            None => EventKind::Modified,
+        }
+    }
+    
+    fn publish(&self, kind: EventKind, path: &Path, driver: &dyn GnosDriver) {
+        if let Some(events) = self.events.as_ref().filter(|events| events.is_active()) {
+            let principal = match &self.token {
+                Some(token) => self.capability_manager.principal(Some(token)),
+                None => self.capability_manager.local_principal(),
+            };
+            events.publish(kind, path, driver.name(), principal);
+        }
+    }
+    
+    /// Canonical form of `path`, once the caller may perform `operation`
+    /// on it, with the driver that owns it
+    async fn driver_for(&self, path: &Path, operation: Operation) -> Result<(PathBuf, Arc<dyn GnosDriver>)> {
+        let path = normalize(path)?;
+        match &self.token {
+            Some(token) => self.capability_manager.check_token(Some(token), &path, operation).await?,
+            None => self.capability_manager.check_permission(&path, operation).await?,
+        }
+        let driver = self.driver_registry.get_driver(&path)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        Ok((path, driver))
+    }
+}
+
+struct Watch {
+    client: GnosClient,
+    path: PathBuf,
+    ticker: Interval,
+    /// Size and mtime at the last poll; `Some(None)` once seen missing
+    seen: Option<Option<(u64, SystemTime)>>,
+}
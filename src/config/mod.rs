@@ -1,29 +1,272 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
-use crate::security::SecurityConfig;
-use crate::Result;
+use crate::events::EventKind;
+use crate::security::{CapabilityConfig, SecurityConfig};
+use crate::{Result, ResultExt};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GnosConfig {
     pub security: SecurityConfig,
+    #[serde(default)]
+    pub capability: CapabilityConfig,
     pub drivers: DriverConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub writeback: WriteBackConfig,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub vfs: VfsConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    #[serde(default)]
+    pub alerts: AlertConfig,
+    #[serde(default)]
+    pub ninep: NinePConfig,
+    #[serde(default)]
+    pub offline: OfflineConfig,
+    #[serde(default)]
+    pub copy: CopyConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub index: IndexConfig,
+    #[serde(default)]
+    pub events: EventsConfig,
+    #[serde(default)]
+    pub triggers: TriggerConfig,
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub lifecycle: LifecycleConfig,
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    #[serde(default)]
+    pub plugins: PluginConfig,
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+    #[serde(default)]
+    pub faults: FaultConfig,
+    #[serde(default)]
+    pub checksums: ChecksumConfig,
+    #[serde(default)]
+    pub bandwidth: BandwidthConfig,
+    #[serde(default)]
+    pub pools: PoolConfig,
+    #[serde(default)]
+    pub costs: CostConfig,
+    #[serde(default)]
+    pub dry_run: DryRunConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub recovery: RecoveryConfig,
+    #[serde(default)]
+    pub memory: MemoryConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DriverConfig {
     pub ai: AiDriverConfig,
     pub cloud: CloudDriverConfig,
+    #[serde(default)]
+    pub dynamodb: DynamoDbDriverConfig,
+    #[serde(default)]
+    pub sqs: SqsDriverConfig,
+    #[serde(default)]
+    pub lambda: LambdaDriverConfig,
+    #[serde(default)]
+    pub logs: LogsDriverConfig,
+    #[serde(default)]
+    pub ec2: Ec2DriverConfig,
+    #[serde(default)]
+    pub secrets: SecretsDriverConfig,
+    #[serde(default)]
+    pub vault: VaultDriverConfig,
+    #[serde(default)]
+    pub msgraph: MsGraphDriverConfig,
     pub http: HttpDriverConfig,
+    #[serde(default)]
+    pub sftp: SftpDriverConfig,
+    #[serde(default)]
+    pub smb: SmbDriverConfig,
+    #[serde(default)]
+    pub models: ModelsDriverConfig,
+    #[serde(default)]
+    pub sensors: SensorsDriverConfig,
+    #[serde(default)]
+    pub git: GitDriverConfig,
+    #[serde(default)]
+    pub github: GithubDriverConfig,
+    #[serde(default)]
+    pub gitlab: GitlabDriverConfig,
+    #[serde(default)]
+    pub docker: DockerDriverConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    /// Transport policy per driver name, e.g. `[drivers.tls.http]`; drivers
+    /// without an entry use system roots and accept plain HTTP
+    #[serde(default)]
+    pub tls: HashMap<String, TlsConfig>,
+    /// Outbound proxy per driver name, e.g. `[drivers.proxy.models]`, in
+    /// place of `network.proxy`
+    #[serde(default)]
+    pub proxy: HashMap<String, ProxyConfig>,
+    /// Path patterns sent to a named driver ahead of the prefix routes,
+    /// e.g. `[[drivers.routes]]`
+    #[serde(default)]
+    pub routes: Vec<RouteRule>,
+}
+
+/// Paths matching a pattern, routed to a driver whatever it reserves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteRule {
+    /// Glob over the whole path: `*` and `?` within one component, `**`
+    /// across any number of them
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Regular expression the whole path must match, in place of `pattern`
+    #[serde(default)]
+    pub regex: Option<String>,
+    /// Label of the driver, e.g. "cloud", or a plugin's
+    pub driver: String,
+    /// Higher wins where rules overlap; equal ones go by config order
+    #[serde(default)]
+    pub priority: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AiDriverConfig {
     pub enabled: bool,
+    /// Tokens of conversation a chat session sends with each prompt
+    pub context_size: usize,
+    /// Idle time after which a chat session is dropped; 0 keeps sessions forever
+    pub session_ttl_seconds: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CloudDriverConfig {
     pub enabled: bool,
+    #[serde(default)]
+    pub aws: AwsConfig,
+    /// Geo-replicated buckets served under `/cloud/<alias>`
+    #[serde(default)]
+    pub buckets: Vec<BucketAlias>,
+    /// How often each replica's endpoint is probed for reachability and latency
+    #[serde(default = "default_probe_interval_seconds")]
+    pub probe_interval_seconds: u64,
+    /// How long the URLs read from `<object>.presign` files stay valid
+    #[serde(default = "default_presign_seconds")]
+    pub presign_seconds: u64,
+    #[serde(default)]
+    pub multipart: MultipartConfig,
+    /// Server-side encryption requested on writes, per bucket
+    #[serde(default)]
+    pub encryption: Vec<BucketEncryption>,
+}
+
+/// How S3 encrypts the objects written to one bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketEncryption {
+    /// Bucket name, as in the path or an alias's replica; "*" covers every
+    /// bucket not listed by name
+    pub bucket: String,
+    pub algorithm: SseAlgorithm,
+    /// KMS key ID, ARN or alias for `kms`; unset uses the account's aws/s3 key
+    #[serde(default)]
+    pub kms_key_id: Option<String>,
+    /// Have S3 use a bucket key for `kms`, cutting requests to KMS
+    #[serde(default)]
+    pub bucket_key: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SseAlgorithm {
+    /// SSE-S3, keys managed by S3 (AES256)
+    S3,
+    /// SSE-KMS, keys in AWS KMS (aws:kms)
+    Kms,
+}
+
+/// Multipart uploads of large writes to object storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MultipartConfig {
+    /// Writes from this size on are uploaded in parts as they are written
+    pub threshold_mb: u64,
+    /// At least 5, S3's smallest part; objects are limited to 10,000 parts
+    pub part_size_mb: u64,
+    /// Parts of one write uploaded at the same time
+    pub concurrency: usize,
+}
+
+/// One name for a bucket replicated across regions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketAlias {
+    pub alias: String,
+    #[serde(default)]
+    pub routing: RoutingPolicy,
+    pub replicas: Vec<BucketReplica>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketReplica {
+    pub region: String,
+    pub bucket: String,
+    /// Defaults to the S3 endpoint of `region`
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+/// Which reachable replica of an alias requests go to
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingPolicy {
+    /// The one with the lowest measured round trip
+    #[default]
+    Latency,
+    /// The first in the listed order, falling back down the list
+    Ordered,
+}
+
+/// Where the cloud driver gets AWS credentials and how it keeps them fresh
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AwsConfig {
+    pub region: String,
+    /// Providers tried in order until one yields credentials
+    pub credential_sources: Vec<CredentialSource>,
+    /// Profile in ~/.aws/config and ~/.aws/credentials; unset follows AWS_PROFILE
+    pub profile: Option<String>,
+    /// Role assumed with the chain's credentials; its sessions are renewed
+    /// before they expire
+    pub role_arn: Option<String>,
+    pub external_id: Option<String>,
+    pub session_name: String,
+    pub session_seconds: u64,
+    /// Temporary credentials are replaced this long before they expire
+    pub refresh_before_seconds: u64,
+}
+
+/// A link in the AWS credential chain
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialSource {
+    /// AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN
+    Env,
+    /// Shared config and credentials files, including SSO and source_profile
+    Profile,
+    /// AWS_WEB_IDENTITY_TOKEN_FILE and AWS_ROLE_ARN, as set up by IRSA on EKS
+    WebIdentity,
+    /// The EC2 instance metadata service
+    Imds,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,49 +274,1739 @@ pub struct HttpDriverConfig {
     pub enabled: bool,
 }
 
-impl Default for GnosConfig {
-    fn default() -> Self {
-        Self {
-            security: SecurityConfig::default(),
-            drivers: DriverConfig::default(),
-        }
-    }
+/// `/proc/models`: listing, pulling and removing local models
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelsDriverConfig {
+    pub enabled: bool,
+    /// Ollama server; unset leaves Ollama out
+    pub ollama_url: Option<String>,
+    /// Models offered for pulling from Ollama, whose library can't be listed
+    pub ollama_catalog: Vec<String>,
+    /// Where Hugging Face repositories are downloaded; unset leaves Hugging Face out
+    pub huggingface_dir: Option<PathBuf>,
+    pub huggingface_endpoint: String,
+    /// Most-downloaded repositories listed as available
+    pub huggingface_limit: usize,
+    /// Only list repositories with this pipeline tag, e.g. "text-generation"
+    pub huggingface_filter: Option<String>,
+    /// Environment variable holding the Hugging Face access token
+    pub huggingface_token_env: String,
 }
 
-impl Default for DriverConfig {
-    fn default() -> Self {
-        Self {
-            ai: AiDriverConfig::default(),
-            cloud: CloudDriverConfig::default(),
-            http: HttpDriverConfig::default(),
-        }
-    }
+/// TLS settings for one driver's outbound connections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM file of extra root certificates, e.g. a private CA
+    pub ca_bundle: Option<PathBuf>,
+    /// Trust the system's roots as well as `ca_bundle`
+    pub system_roots: bool,
+    /// PEM client certificate chain for mutual TLS, with its PKCS#8 key
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub min_version: Option<TlsVersion>,
+    /// Refuse http:// endpoints instead of sending requests in the clear
+    pub https_only: bool,
 }
 
-impl Default for AiDriverConfig {
-    fn default() -> Self {
-        Self { enabled: true }
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
 }
 
-impl Default for CloudDriverConfig {
-    fn default() -> Self {
-        Self { enabled: true }
-    }
+/// `/cloud/aws/dynamodb/<table>/<key>`: items read and written as JSON,
+/// signed with the cloud driver's AWS credentials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DynamoDbDriverConfig {
+    pub enabled: bool,
+    /// Defaults to `drivers.cloud.aws.region`
+    pub region: Option<String>,
+    /// e.g. DynamoDB Local at http://localhost:8000
+    pub endpoint: Option<String>,
+    /// Most keys a table or partition lists; the Scan or Query stops there
+    pub max_list: usize,
 }
 
-impl Default for HttpDriverConfig {
-    fn default() -> Self {
-        Self { enabled: true }
+/// `/cloud/aws/sqs/<queue>`: lines written are sent as messages and reads
+/// receive the next one, signed with the cloud driver's AWS credentials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SqsDriverConfig {
+    pub enabled: bool,
+    /// Defaults to `drivers.cloud.aws.region`
+    pub region: Option<String>,
+    /// e.g. ElasticMQ or LocalStack at http://localhost:9324
+    pub endpoint: Option<String>,
+    /// Delete a message once a read has returned it; otherwise it comes
+    /// back after the queue's visibility timeout
+    pub delete_on_read: bool,
+    /// How long a read of an empty queue waits for a message, at most 20
+    pub wait_seconds: u32,
+}
+
+/// `/cloud/aws/lambda/<function>`: a JSON payload written through a handle
+/// invokes the function, and reading the handle returns its response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LambdaDriverConfig {
+    pub enabled: bool,
+    /// Defaults to `drivers.cloud.aws.region`
+    pub region: Option<String>,
+    /// e.g. LocalStack at http://localhost:4566
+    pub endpoint: Option<String>,
+    /// Most functions the directory lists
+    pub max_list: usize,
+}
+
+/// `/cloud/aws/logs/<group>/<stream>`: CloudWatch Logs streams as text files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogsDriverConfig {
+    pub enabled: bool,
+    /// Defaults to `drivers.cloud.aws.region`
+    pub region: Option<String>,
+    /// e.g. LocalStack at http://localhost:4566
+    pub endpoint: Option<String>,
+    /// Most groups or streams a directory lists, streams newest first
+    pub max_list: usize,
+    /// Newest events a stream file starts with
+    pub tail_events: u32,
+    /// Keep stream files open for new events, as `tail -f` expects,
+    /// instead of ending after the newest ones
+    pub follow: bool,
+    /// Pause between polls for new events while following
+    pub poll_seconds: u64,
+}
+
+/// `/cloud/aws/ec2/instances/<id>`: instances as JSON, started, stopped and
+/// rebooted by writing to `<id>.state`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ec2DriverConfig {
+    pub enabled: bool,
+    /// Defaults to `drivers.cloud.aws.region`
+    pub region: Option<String>,
+    /// e.g. LocalStack at http://localhost:4566
+    pub endpoint: Option<String>,
+    /// Most instances the directory lists
+    pub max_list: usize,
+}
+
+/// `/cloud/aws/secrets/<name>`: Secrets Manager values, readable and
+/// writable by their owner only
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecretsDriverConfig {
+    pub enabled: bool,
+    /// Defaults to `drivers.cloud.aws.region`
+    pub region: Option<String>,
+    /// e.g. LocalStack at http://localhost:4566
+    pub endpoint: Option<String>,
+    /// Most secrets a listing looks at
+    pub max_list: usize,
+}
+
+/// `/dev/vault/<mount>/<path>`: HashiCorp Vault KV version 2 secrets as
+/// JSON objects
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VaultDriverConfig {
+    pub enabled: bool,
+    /// e.g. https://vault.example.com:8200
+    pub address: String,
+    /// Vault Enterprise namespace, sent as X-Vault-Namespace
+    pub namespace: Option<String>,
+    /// KV version 2 mounts shown under /dev/vault, e.g. "secret"
+    pub mounts: Vec<String>,
+    pub auth: VaultAuthMethod,
+    /// Environment variable holding the token for `token` auth
+    pub token_env: String,
+    /// Where the AppRole auth method is mounted
+    pub approle_mount: String,
+    pub role_id: Option<String>,
+    /// Environment variable holding the AppRole secret ID
+    pub secret_id_env: String,
+    /// How long before the token expires to renew it
+    pub renew_before_seconds: u64,
+}
+
+/// How the Vault driver logs in
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultAuthMethod {
+    /// A token from the environment, renewed while it is renewable
+    #[default]
+    Token,
+    /// A role ID and secret ID, logging in again when renewal runs out
+    AppRole,
+}
+
+/// `/cloud/msgraph/<library>`: OneDrive and SharePoint document libraries
+/// through Microsoft Graph, signed in as an app with client credentials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MsGraphDriverConfig {
+    pub enabled: bool,
+    /// Directory (tenant) ID of the app registration
+    pub tenant_id: String,
+    /// Application (client) ID of the app registration
+    pub client_id: String,
+    /// Environment variable holding the app's client secret
+    pub client_secret_env: String,
+    /// e.g. https://graph.microsoft.us/v1.0 for national clouds
+    pub endpoint: String,
+    pub login_endpoint: String,
+    pub libraries: Vec<MsGraphLibrary>,
+    /// How old a library's cached tree may get before a listing replays
+    /// the changes since with a delta query
+    pub delta_interval_seconds: u64,
+}
+
+/// A drive shown as `/cloud/msgraph/<name>`, given by exactly one of
+/// `drive_id`, `user` and `site`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MsGraphLibrary {
+    pub name: String,
+    #[serde(default)]
+    pub drive_id: Option<String>,
+    /// Whose OneDrive, by user principal name, e.g. alice@contoso.com
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Whose default document library, by site ID or by host and path,
+    /// e.g. contoso.sharepoint.com:/sites/finance
+    #[serde(default)]
+    pub site: Option<String>,
+}
+
+/// `/net/sftp/<host>/<path>`: files on remote hosts over SFTP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SftpDriverConfig {
+    pub enabled: bool,
+    /// OpenSSH known_hosts file every host's key is checked against
+    pub known_hosts: PathBuf,
+    /// For connecting and for each SFTP request
+    pub timeout_seconds: u64,
+    pub hosts: Vec<SftpHostConfig>,
+}
+
+/// A remote host shown as `/net/sftp/<name>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpHostConfig {
+    pub name: String,
+    /// DNS name or address, as it appears in known_hosts
+    pub address: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub user: String,
+    /// Private key to log in with
+    pub key: PathBuf,
+    /// Environment variable holding the key's passphrase, if it has one
+    #[serde(default)]
+    pub passphrase_env: Option<String>,
+    /// Remote directory the entry shows
+    #[serde(default = "default_sftp_root")]
+    pub root: PathBuf,
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+fn default_sftp_root() -> PathBuf {
+    PathBuf::from("/")
+}
+
+/// `/net/smb/<share>`: Windows and Samba shares, over libsmbclient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmbDriverConfig {
+    pub enabled: bool,
+    /// NTLM account for shares that don't name their own
+    pub user: Option<String>,
+    /// NTLM domain or workgroup for shares that don't name their own
+    pub domain: String,
+    /// Environment variable holding `user`'s password
+    pub password_env: Option<String>,
+    pub shares: Vec<SmbShareConfig>,
+}
+
+/// A share shown as `/net/smb/<name>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmbShareConfig {
+    pub name: String,
+    /// File server's DNS name or address
+    pub server: String,
+    #[serde(default = "default_smb_port")]
+    pub port: u16,
+    /// Share name on the server, e.g. "finance" for `\\server\finance`
+    pub share: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub password_env: Option<String>,
+}
+
+fn default_smb_port() -> u16 {
+    445
+}
+
+/// `/dev/sensors`: sampled readings kept in a ring buffer per sensor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SensorsDriverConfig {
+    pub enabled: bool,
+    pub sample_interval_ms: u64,
+    /// Samples kept per sensor for `history.csv`
+    pub history_size: usize,
+    /// Add thermal zones and hwmon inputs found under /sys/class
+    pub discover: bool,
+    pub sources: Vec<SensorSource>,
+}
+
+/// A file holding one number, read on every sample
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorSource {
+    pub name: String,
+    pub path: PathBuf,
+    /// Multiplier applied to the raw reading, e.g. 0.001 for millidegrees
+    #[serde(default = "default_sensor_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+fn default_sensor_scale() -> f64 {
+    1.0
+}
+
+/// `/dev/git/<repo>`: local repositories by branch, tag and commit, read-only
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitDriverConfig {
+    pub enabled: bool,
+    /// Commits from HEAD listed under `commits/`; any other can still be
+    /// opened by its hash
+    pub commit_listing: usize,
+    pub repos: Vec<GitRepoConfig>,
+}
+
+/// A repository shown as `/dev/git/<name>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitRepoConfig {
+    pub name: String,
+    /// Working tree or bare repository
+    pub path: PathBuf,
+}
+
+/// `/net/github/<org>/<repo>`: issues and pull requests as markdown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GithubDriverConfig {
+    pub enabled: bool,
+    /// e.g. https://github.example.com/api/v3 for GitHub Enterprise Server
+    pub endpoint: String,
+    /// Environment variable holding a token; without one, public
+    /// repositories can be read but not commented on
+    pub token_env: String,
+    /// Repositories shown, as "org/repo"
+    pub repos: Vec<String>,
+    /// Open issues and pull requests listed per repository; any other
+    /// still opens by its number
+    pub listing: usize,
+}
+
+/// `/net/gitlab/<group>/<project>`: issues, merge requests and pipelines
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitlabDriverConfig {
+    pub enabled: bool,
+    /// Instance URL, e.g. https://gitlab.example.com for a self-hosted one
+    pub endpoint: String,
+    /// Environment variable holding a personal access token; without one,
+    /// public projects can be read but not commented on
+    pub token_env: String,
+    /// Projects shown, by full path, e.g. "group/subgroup/project"
+    pub projects: Vec<String>,
+    /// Open issues and merge requests, and recent pipelines, listed per
+    /// project; any other still opens by its number
+    pub listing: usize,
+}
+
+/// `/dev/docker/containers/<id>`: logs, inspect output and stop/restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DockerDriverConfig {
+    pub enabled: bool,
+    /// The daemon's API socket
+    pub socket: PathBuf,
+    /// Lines of output `logs` starts with
+    pub tail_lines: usize,
+    /// Keep `logs` open for new output instead of ending at the last line
+    pub follow: bool,
+}
+
+/// Shared HTTP client used by all network-backed drivers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub timeout_seconds: u64,
+    pub connect_timeout_seconds: u64,
+    pub pool_idle_timeout_seconds: u64,
+    pub pool_max_idle_per_host: usize,
+    pub max_connections_per_host: usize,
+    pub tcp_keepalive_seconds: u64,
+    pub http2_adaptive_window: bool,
+    pub dns_cache_ttl_seconds: u64,
+    /// Times a 429 or 503 with `Retry-After` is retried before the caller
+    /// sees it as a rate-limit error
+    pub rate_limit_retries: u32,
+    /// Longest `Retry-After` waited out in place; longer ones go straight
+    /// back to the caller with the hint
+    pub max_retry_after_seconds: u64,
+    /// Proxy for drivers without one of their own; unset connects directly
+    pub proxy: Option<ProxyConfig>,
+    pub egress: EgressConfig,
+    pub dns: DnsConfig,
+}
+
+/// How drivers resolve host names, for networks where the system's DNS
+/// gives the wrong answers or none
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    /// Servers asked instead of the system resolver, "10.0.0.2",
+    /// "10.0.0.2:5353" or "[fd00::53]:53"
+    pub servers: Vec<String>,
+    /// DNS-over-HTTPS endpoint asked instead, "https://<address>/dns-query";
+    /// a named server needs its address under `hosts`
+    pub doh: Option<String>,
+    /// Order, or the only family, of the addresses connected to
+    pub ip: IpPreference,
+    /// Names answered without asking DNS, e.g. a split-horizon S3 endpoint
+    pub hosts: HashMap<String, Vec<std::net::IpAddr>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpPreference {
+    /// As the resolver returns them
+    #[default]
+    Any,
+    Ipv4First,
+    Ipv6First,
+    Ipv4Only,
+    Ipv6Only,
+}
+
+/// An outbound HTTP or SOCKS proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    /// http://, https://, socks5:// or socks5h:// (resolving through the
+    /// proxy), with `user:password@` if it needs them
+    pub url: String,
+    /// Hosts reached directly, e.g. "169.254.169.254" or ".corp.example"
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+/// Where drivers may connect
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EgressConfig {
+    /// Hosts requests may go to, "*.example.com" for its subdomains; empty
+    /// allows every host
+    pub allow: Vec<String>,
+}
+
+/// Persistent on-disk chunk cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub dir: PathBuf,
+    pub max_size_mb: u64,
+    pub chunk_size_kb: u64,
+    /// Path prefixes whose reads are cached
+    pub prefixes: Vec<String>,
+    /// Split chunks into content-defined blocks shared across objects
+    pub dedup: bool,
+    pub dedup_avg_block_kb: u64,
+}
+
+/// Asynchronous write-back of file contents to drivers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriteBackConfig {
+    pub enabled: bool,
+    pub journal_dir: PathBuf,
+    pub max_retries: u32,
+    pub retry_backoff_ms: u64,
+    /// What to do when the remote changed underneath a queued write
+    pub conflict_strategy: ConflictStrategy,
+    /// Per-prefix overrides of `conflict_strategy`
+    pub conflicts: Vec<ConflictRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictRule {
+    pub prefix: String,
+    pub strategy: ConflictStrategy,
+}
+
+/// Resolution of a write whose remote changed since it was made
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Upload the local write over the remote change
+    LastWriterWins,
+    /// Leave the remote alone and upload the local write next to it under a new name
+    KeepBoth,
+    /// Hold the write in the journal and report the conflict on the path
+    #[default]
+    Fail,
+}
+
+/// Keep working from the disk cache and write-back journal while backends are unreachable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OfflineConfig {
+    pub enabled: bool,
+    /// How often an unreachable backend is tried again
+    pub probe_interval_seconds: u64,
+}
+
+/// Cross-driver copies made by `gnos cp`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CopyConfig {
+    /// Size of each ranged read, and of each part for multipart destinations
+    pub chunk_size_mb: u64,
+    /// Chunks in flight at once; memory use is about this many chunks
+    pub concurrency: usize,
+    /// Checkpoints for resuming interrupted copies, and spool files
+    pub checkpoint_dir: PathBuf,
+    /// Largest object copied to a destination without multipart writes,
+    /// which has to receive it in one piece
+    pub max_buffered_mb: u64,
+}
+
+/// Namespace searches started through `/proc/search`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Directories listed, and files read, concurrently
+    pub concurrency: usize,
+    pub max_depth: usize,
+    /// A search stops once it has this many matches
+    pub max_results: usize,
+    /// Larger files are skipped by content searches
+    pub max_content_mb: u64,
+}
+
+/// Background content index that searches consult instead of the backends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexConfig {
+    pub enabled: bool,
+    /// Namespace prefixes crawled into the index
+    pub prefixes: Vec<String>,
+    /// Where the index is persisted between runs
+    pub index_dir: PathBuf,
+    /// How often each prefix is re-listed for changes
+    pub refresh_interval_seconds: u64,
+    pub max_depth: usize,
+    /// Larger files are indexed by name and metadata only
+    pub max_file_mb: u64,
+    /// Directories listed, and files read, concurrently
+    pub concurrency: usize,
+}
+
+/// Where namespace change events are delivered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventsConfig {
+    pub enabled: bool,
+    /// Unix socket streaming every event as a JSON line
+    pub socket_path: Option<PathBuf>,
+    /// Events a slow subscriber may fall behind by before losing some
+    pub buffer: usize,
+    pub webhooks: Vec<WebhookSink>,
+}
+
+/// Events for paths under `prefix` are POSTed to `url` as JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSink {
+    pub url: String,
+    #[serde(default = "default_webhook_prefix")]
+    pub prefix: String,
+}
+
+fn default_webhook_prefix() -> String {
+    "/".to_string()
+}
+
+/// Limits on what namespace prefixes may hold, whichever drivers back them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// Walk each quota prefix at mount to count what it already holds
+    pub scan_on_mount: bool,
+    pub max_depth: usize,
+    pub rules: Vec<QuotaRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaRule {
+    pub prefix: String,
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    #[serde(default)]
+    pub max_objects: Option<u64>,
+}
+
+/// Prefixes whose objects can't be rewritten or removed through the mount
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    pub rules: Vec<RetentionRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionRule {
+    pub prefix: String,
+    pub mode: RetentionMode,
+    /// How long an object stays protected after its last change; unset
+    /// protects it for good
+    #[serde(default)]
+    pub retain_seconds: Option<u64>,
+}
+
+/// Expiry of scratch areas by a background janitor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifecycleConfig {
+    /// Only report what would be deleted, at `/proc/gnos/lifecycle`
+    pub dry_run: bool,
+    pub interval_seconds: u64,
+    /// Directory levels walked below each rule's root
+    pub max_depth: usize,
+    /// Deletions per sweep, so a mistaken rule can't empty a bucket in one go
+    pub max_deletes_per_sweep: usize,
+    pub rules: Vec<LifecycleRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LifecycleRule {
+    /// Glob over full paths; `*` also matches across `/`, e.g. `/tmp/gnos/**`
+    pub pattern: String,
+    /// Time since an object last changed after which it is deleted
+    pub max_age_seconds: u64,
+    /// Expire matching directories whole, e.g. chat sessions, rather than
+    /// descending into them
+    #[serde(default)]
+    pub directories: bool,
+}
+
+/// Telling the kernel, and file watchers on the mount, about changes made
+/// behind the mount's back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub enabled: bool,
+    /// Directories whose drivers' change streams are followed
+    pub watch: Vec<String>,
+    /// Wait before following a change stream again once it ends
+    pub resubscribe_seconds: u64,
+    /// Changed files up to this size have their new contents pushed into the
+    /// page cache rather than just dropped from it; 0 only invalidates
+    pub store_max_kb: u64,
+    /// Open each changed file this process may write through the mount and
+    /// close it unchanged, so inotify and fanotify watchers see `IN_CLOSE_WRITE`
+    pub touch: bool,
+}
+
+/// What a retention rule lets happen to an existing object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionMode {
+    /// Bytes can be added at the end, never changed or removed
+    AppendOnly,
+    /// Written once, then left alone
+    WriteOnce,
+}
+
+/// Which driver plugins may be loaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginConfig {
+    /// Shared-library driver plugins loaded at startup
+    pub paths: Vec<PathBuf>,
+    /// Refuse plugins without a detached signature from a trusted publisher
+    pub require_signatures: bool,
+    pub publishers: Vec<PublisherKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublisherKey {
+    pub name: String,
+    /// Base64 Ed25519 public key
+    pub public_key: String,
+}
+
+/// Latency, errors and short reads injected into driver calls, for
+/// testing how applications cope with flaky backends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaultConfig {
+    pub enabled: bool,
+    /// Unix socket taking JSON commands to add, list and remove rules at runtime
+    pub socket_path: PathBuf,
+    /// Rules in force from mount
+    pub rules: Vec<FaultRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultRule {
+    /// Driver name, e.g. "cloud" or "cloud@acme"; unset matches every driver
+    #[serde(default)]
+    pub driver: Option<String>,
+    /// Unset matches every path
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// Calls affected: read, write, list, metadata, delete, create_dir, commit; empty matches all
+    #[serde(default)]
+    pub ops: Vec<String>,
+    /// Chance that a matching call is affected, from 0 to 1
+    #[serde(default = "default_fault_probability")]
+    pub probability: f64,
+    /// Retire the rule after affecting this many calls
+    #[serde(default)]
+    pub count: Option<u64>,
+    #[serde(flatten)]
+    pub fault: InjectedFault,
+}
+
+/// Caps on transfer rates, in bytes per second; 0 leaves a direction unlimited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandwidthConfig {
+    /// Across every driver
+    pub read_bytes_per_sec: u64,
+    pub write_bytes_per_sec: u64,
+    /// Bytes that may pass at full speed after a quiet spell
+    pub burst_bytes: u64,
+    /// Tighter caps for single drivers, on top of the global ones
+    pub drivers: Vec<DriverBandwidth>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriverBandwidth {
+    /// Driver name, e.g. "http" or "http@acme"
+    pub driver: String,
+    #[serde(default)]
+    pub read_bytes_per_sec: u64,
+    #[serde(default)]
+    pub write_bytes_per_sec: u64,
+    /// Defaults to the global burst
+    #[serde(default)]
+    pub burst_bytes: Option<u64>,
+}
+
+/// Driver calls in flight at once, per driver, so a backlog on one backend
+/// can't hold up the others
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    /// Pool size of drivers not listed below; 0 leaves them unbounded
+    pub concurrency: usize,
+    /// Calls that may wait for a slot before more are refused with EAGAIN;
+    /// 0 lets them queue without limit
+    pub max_queue: usize,
+    pub qos: QosConfig,
+    pub drivers: Vec<DriverPool>,
+}
+
+/// Share of freed pool slots each priority class gets while calls queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QosConfig {
+    /// Metadata, listings, deletes and directory creation
+    pub interactive: u64,
+    /// Reads and writes up to `small_kb`
+    pub small: u64,
+    /// Larger transfers and multipart uploads
+    pub bulk: u64,
+    /// Write-back, index crawls, lifecycle sweeps and change polling
+    pub background: u64,
+    /// Largest transfer counted as small
+    pub small_kb: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriverPool {
+    /// Driver name, e.g. "ai" or "ai@acme"
+    pub driver: String,
+    pub concurrency: usize,
+    /// Defaults to the global queue limit
+    #[serde(default)]
+    pub max_queue: Option<usize>,
+}
+
+/// Rehearsal mode: mutations are checked and recorded but never sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DryRunConfig {
+    pub enabled: bool,
+    /// Error suppressed mutations fail with; unset reports success
+    pub error: Option<InjectedError>,
+    /// Suppressed mutations listed under `/proc/gnos/dry_run`
+    pub max_entries: usize,
+}
+
+/// What the daemon does on SIGTERM or SIGINT before it unmounts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// How long driver calls already running get to finish
+    pub drain_seconds: u64,
+    /// How long committing open handles and the write-back queue may take
+    pub flush_seconds: u64,
+}
+
+/// What happens to a driver after one of its calls panics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecoveryConfig {
+    /// Build a degraded driver again after `restart_delay_seconds`; without
+    /// it the driver stays degraded until the next mount
+    pub restart: bool,
+    pub restart_delay_seconds: u64,
+    /// Restarts each driver gets over the mount's lifetime, after which a
+    /// driver that keeps panicking is left degraded
+    pub max_restarts: u32,
+}
+
+/// One ceiling on what buffered writes and in-memory caches hold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryConfig {
+    /// Shared by open-file write buffers, write-back payloads waiting for
+    /// upload and the read-your-writes cache; 0 leaves memory unbounded
+    pub budget_mb: u64,
+    pub on_exhausted: MemoryPressure,
+    /// Longest a blocked write waits for memory before failing with ENOSPC
+    pub wait_ms: u64,
+}
+
+/// What a write that would overrun the memory budget does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryPressure {
+    /// Wait for buffers to be committed or uploads to drain
+    #[default]
+    Block,
+    /// Fail with ENOSPC straight away
+    Fail,
+}
+
+/// Request and transfer accounting, priced into an estimated spend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostConfig {
+    pub enabled: bool,
+    /// Path components a prefix is cut to when grouping spend, e.g. 5 for
+    /// `/cloud/aws/s3/<bucket>`
+    pub prefix_depth: usize,
+    /// Only shown in reports; prices are taken as given
+    pub currency: String,
+    pub pricing: Vec<DriverPricing>,
+    pub owners: Vec<CostOwner>,
+}
+
+/// Prices for one driver; tenant instances (`cloud@acme`) fall back to
+/// their driver's prices
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DriverPricing {
+    pub driver: String,
+    /// Per thousand reads (GET)
+    pub per_1k_reads: f64,
+    /// Per thousand writes, deletes and multipart calls (PUT/POST/DELETE)
+    pub per_1k_writes: f64,
+    /// Per thousand listings
+    pub per_1k_lists: f64,
+    /// Per thousand metadata lookups (HEAD)
+    pub per_1k_heads: f64,
+    /// Per GiB downloaded
+    pub per_gb_read: f64,
+    /// Per GiB uploaded
+    pub per_gb_written: f64,
+}
+
+/// Who spend under a prefix is billed to; the longest matching prefix wins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostOwner {
+    pub prefix: String,
+    pub owner: String,
+}
+
+/// Digests sent with uploads and checked on downloads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChecksumConfig {
+    pub enabled: bool,
+    /// Take an ETag of 32 hex digits as the object's MD5, as S3, GCS and
+    /// Azure report it for objects uploaded in one piece
+    pub trust_etags: bool,
+}
+
+fn default_probe_interval_seconds() -> u64 {
+    60
+}
+
+fn default_presign_seconds() -> u64 {
+    3600
+}
+
+fn default_fault_probability() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "fault", rename_all = "snake_case")]
+pub enum InjectedFault {
+    /// Delay the call
+    Latency { ms: u64 },
+    /// Fail the call
+    Error { error: InjectedError },
+    /// Return at most this many bytes from a read
+    PartialRead { max_bytes: u64 },
+}
+
+/// Error an injected failure surfaces as
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectedError {
+    /// EIO
+    Io,
+    /// EHOSTUNREACH, and offline mode if enabled
+    Unreachable,
+    NotFound,
+    PermissionDenied,
+    Busy,
+    QuotaExceeded,
+    /// EAGAIN, as if the backend answered 429
+    RateLimited,
+}
+
+/// A tenant namespace served at `/tenants/<id>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantConfig {
+    pub id: String,
+    /// The tenant's own driver instances; credentials come from the
+    /// environment variables these name, so each tenant can use its own
+    #[serde(default)]
+    pub drivers: DriverConfig,
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    #[serde(default)]
+    pub max_objects: Option<u64>,
+}
+
+impl TenantConfig {
+    /// Quota over the tenant's whole subtree, if it has limits
+    pub fn quota_rule(&self) -> Option<QuotaRule> {
+        if self.max_bytes.is_none() && self.max_objects.is_none() {
+            return None;
+        }
+        Some(QuotaRule {
+            prefix: crate::tenants::tenant_root(&self.id).display().to_string(),
+            max_bytes: self.max_bytes,
+            max_objects: self.max_objects,
+        })
+    }
+}
+
+/// Actions run when paths matching a pattern change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerConfig {
+    /// Actions run concurrently
+    pub workers: usize,
+    /// Changes waiting for a worker; further ones are dropped and logged
+    pub queue_size: usize,
+    pub max_retries: u32,
+    pub retry_backoff_ms: u64,
+    pub rules: Vec<TriggerRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerRule {
+    /// Glob over the full path; `*` also matches across `/`
+    pub pattern: String,
+    #[serde(default = "default_trigger_events")]
+    pub on: Vec<EventKind>,
+    #[serde(flatten)]
+    pub action: TriggerAction,
+}
+
+fn default_trigger_events() -> Vec<EventKind> {
+    vec![EventKind::Created, EventKind::Modified]
+}
+
+/// What a trigger does; templates may use `{path}`, `{name}`, `{event}` and `{content}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TriggerAction {
+    /// Write the rendered template to an AI model path and keep its answer
+    Prompt {
+        model: PathBuf,
+        template: String,
+        /// Where the answer is written, e.g. `{path}.summary`; discarded when unset
+        output: Option<String>,
+    },
+    /// POST the change as JSON
+    Webhook { url: String },
+}
+
+/// FUSE-facing behaviour of the filesystem
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VfsConfig {
+    /// How long driver metadata is trusted before getattr asks again
+    pub attr_cache_ttl_seconds: u64,
+    /// Page cache behaviour per path prefix, overriding the driver's choice
+    pub cache_modes: Vec<CacheModeRule>,
+    /// Prefixes listed into the inode and attr caches at mount time
+    pub warm_prefixes: Vec<String>,
+    pub warm_max_depth: usize,
+    /// Directories listed concurrently while warming
+    pub warm_concurrency: usize,
+    /// Templates like `/net/http/{host}/{endpoint}` whose unlisted names
+    /// are materialized on lookup; the first match wins
+    pub path_templates: Vec<String>,
+    /// Prefixes the mount exposes; everything else is hidden. Empty exposes the whole tree
+    pub only: Vec<String>,
+    /// Record every operation on each open handle, for `/proc/gnos/handles/<fh>`
+    pub trace_handles: bool,
+    /// Events kept per handle trace; older ones are dropped first
+    pub trace_max_events: usize,
+    /// Prefixes whose writes only land if the object hasn't changed since
+    /// it was opened, e.g. buckets shared between hosts
+    pub conditional_writes: Vec<String>,
+    /// Prefixes where reads and `stat` see this mount's writes before an
+    /// eventually consistent backend does
+    pub read_your_writes: Vec<String>,
+    /// Longest a write is served locally while the backend doesn't show it
+    pub read_your_writes_seconds: u64,
+    /// Memory for payloads held for read-your-writes
+    pub read_your_writes_max_mb: usize,
+    /// Entries from which a directory gets a `.shards` view; 0 disables sharding
+    pub shard_threshold: usize,
+    /// Characters of a name that pick its shard
+    pub shard_prefix_len: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheModeRule {
+    pub prefix: String,
+    pub mode: CacheMode,
+}
+
+/// How the kernel page cache treats an opened file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheMode {
+    /// Cached until the file is reopened
+    #[default]
+    Auto,
+    /// Bypass the page cache so every read reaches the driver
+    DirectIo,
+    /// Keep cached pages across opens, for content that never changes
+    KeepCache,
+}
+
+/// Span export for FUSE operations and driver calls
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// OTLP/gRPC collector, e.g. http://localhost:4317; unset disables export
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    /// FUSE operations slower than this are logged with a timing breakdown; 0 disables
+    pub slow_op_threshold_ms: u64,
+    /// Keep busy time per stack of FUSE, 9P and driver spans for `gnos
+    /// profile`; costs a little on every call
+    pub profiling: bool,
+}
+
+/// Error budget and anomaly alerting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertConfig {
+    pub enabled: bool,
+    /// Length of the window each check looks back over
+    pub interval_seconds: u64,
+    /// Fraction of failed calls per driver that raises an alert
+    pub driver_error_rate: f64,
+    /// Windows with fewer calls than this are too small to judge
+    pub min_driver_calls: u64,
+    pub max_denials_per_principal: usize,
+    /// Quiet period before the same alert fires again
+    pub cooldown_seconds: u64,
+    /// Alerts are POSTed here as JSON in addition to being logged
+    pub webhook_url: Option<String>,
+}
+
+/// 9P2000.L server for sharing the namespace into VMs and WSL2 guests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NinePConfig {
+    pub enabled: bool,
+    /// TCP address, e.g. 127.0.0.1:5640 for QEMU `-virtfs` or WSL2 `mount -t 9p`
+    pub listen: String,
+    /// Largest message the server will negotiate
+    pub msize: u32,
+}
+
+/// zstd compression per path class
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub level: i32,
+    pub rules: Vec<CompressionRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressionRule {
+    pub prefix: String,
+    /// Store cache blocks for this prefix compressed
+    #[serde(default)]
+    pub cache: bool,
+    /// Send writes compressed to drivers that accept zstd bodies
+    #[serde(default)]
+    pub transfer: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            ca_bundle: None,
+            system_roots: true,
+            client_cert: None,
+            client_key: None,
+            min_version: None,
+            https_only: false,
+        }
+    }
+}
+
+impl Default for AiDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            context_size: 4096,
+            session_ttl_seconds: 3600,
+        }
+    }
+}
+
+impl Default for CloudDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            aws: AwsConfig::default(),
+            buckets: Vec::new(),
+            probe_interval_seconds: default_probe_interval_seconds(),
+            presign_seconds: default_presign_seconds(),
+            multipart: MultipartConfig::default(),
+            encryption: Vec::new(),
+        }
+    }
+}
+
+impl Default for MultipartConfig {
+    fn default() -> Self {
+        Self {
+            threshold_mb: 64,
+            part_size_mb: 16,
+            concurrency: 4,
+        }
+    }
+}
+
+impl Default for HttpDriverConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+impl Default for ModelsDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ollama_url: Some("http://localhost:11434".to_string()),
+            ollama_catalog: ["llama3", "llama3:70b", "mistral", "phi3", "gemma2", "qwen2"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            huggingface_dir: Some(PathBuf::from("/var/lib/gnos/models")),
+            huggingface_endpoint: "https://huggingface.co".to_string(),
+            huggingface_limit: 50,
+            huggingface_filter: Some("text-generation".to_string()),
+            huggingface_token_env: "HF_TOKEN".to_string(),
+        }
+    }
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            paths: Vec::new(),
+            require_signatures: true,
+            publishers: Vec::new(),
+        }
+    }
+}
+
+impl Default for BandwidthConfig {
+    fn default() -> Self {
+        Self {
+            read_bytes_per_sec: 0,
+            write_bytes_per_sec: 0,
+            burst_bytes: 4 * 1024 * 1024,
+            drivers: Vec::new(),
+        }
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 64,
+            max_queue: 0,
+            qos: QosConfig::default(),
+            drivers: Vec::new(),
+        }
+    }
+}
+
+impl Default for QosConfig {
+    fn default() -> Self {
+        Self {
+            interactive: 8,
+            small: 4,
+            bulk: 2,
+            background: 1,
+            small_kb: 1024,
+        }
+    }
+}
+
+impl Default for AwsConfig {
+    fn default() -> Self {
+        Self {
+            region: "us-east-1".to_string(),
+            credential_sources: vec![
+                CredentialSource::Env,
+                CredentialSource::Profile,
+                CredentialSource::WebIdentity,
+                CredentialSource::Imds,
+            ],
+            profile: None,
+            role_arn: None,
+            external_id: None,
+            session_name: "gnos".to_string(),
+            session_seconds: 3600,
+            refresh_before_seconds: 300,
+        }
+    }
+}
+
+impl Default for DryRunConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            error: None,
+            max_entries: 1000,
+        }
+    }
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_seconds: 30,
+            flush_seconds: 300,
+        }
+    }
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        Self {
+            restart: false,
+            restart_delay_seconds: 30,
+            max_restarts: 3,
+        }
+    }
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            budget_mb: 0,
+            on_exhausted: MemoryPressure::Block,
+            wait_ms: 30_000,
+        }
+    }
+}
+
+impl Default for CostConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefix_depth: 5,
+            currency: "USD".to_string(),
+            pricing: Vec::new(),
+            owners: Vec::new(),
+        }
+    }
+}
+
+impl Default for ChecksumConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trust_etags: true,
+        }
+    }
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            socket_path: PathBuf::from("/run/gnos/faults.sock"),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            interval_seconds: 3600,
+            max_depth: 16,
+            max_deletes_per_sweep: 1000,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            watch: Vec::new(),
+            resubscribe_seconds: 10,
+            store_max_kb: 0,
+            touch: false,
+        }
+    }
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            scan_on_mount: true,
+            max_depth: 32,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for DynamoDbDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: None,
+            endpoint: None,
+            max_list: 1000,
+        }
+    }
+}
+
+impl Default for SqsDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: None,
+            endpoint: None,
+            delete_on_read: true,
+            wait_seconds: 5,
+        }
+    }
+}
+
+impl Default for LambdaDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: None,
+            endpoint: None,
+            max_list: 1000,
+        }
+    }
+}
+
+impl Default for LogsDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: None,
+            endpoint: None,
+            max_list: 1000,
+            tail_events: 100,
+            follow: false,
+            poll_seconds: 2,
+        }
+    }
+}
+
+impl Default for Ec2DriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: None,
+            endpoint: None,
+            max_list: 1000,
+        }
+    }
+}
+
+impl Default for SecretsDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: None,
+            endpoint: None,
+            max_list: 1000,
+        }
+    }
+}
+
+impl Default for VaultDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "http://127.0.0.1:8200".to_string(),
+            namespace: None,
+            mounts: vec!["secret".to_string()],
+            auth: VaultAuthMethod::Token,
+            token_env: "VAULT_TOKEN".to_string(),
+            approle_mount: "approle".to_string(),
+            role_id: None,
+            secret_id_env: "VAULT_SECRET_ID".to_string(),
+            renew_before_seconds: 300,
+        }
+    }
+}
+
+impl Default for MsGraphDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tenant_id: String::new(),
+            client_id: String::new(),
+            client_secret_env: "MSGRAPH_CLIENT_SECRET".to_string(),
+            endpoint: "https://graph.microsoft.com/v1.0".to_string(),
+            login_endpoint: "https://login.microsoftonline.com".to_string(),
+            libraries: Vec::new(),
+            delta_interval_seconds: 60,
+        }
+    }
+}
+
+impl Default for SftpDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            known_hosts: std::env::var_os("HOME")
+                .map_or_else(|| PathBuf::from("/etc/ssh/ssh_known_hosts"), |home| PathBuf::from(home).join(".ssh/known_hosts")),
+            timeout_seconds: 30,
+            hosts: Vec::new(),
+        }
+    }
+}
+
+impl Default for SmbDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            user: None,
+            domain: "WORKGROUP".to_string(),
+            password_env: None,
+            shares: Vec::new(),
+        }
+    }
+}
+
+impl Default for GithubDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "https://api.github.com".to_string(),
+            token_env: "GITHUB_TOKEN".to_string(),
+            repos: Vec::new(),
+            listing: 100,
+        }
+    }
+}
+
+impl Default for GitlabDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "https://gitlab.com".to_string(),
+            token_env: "GITLAB_TOKEN".to_string(),
+            projects: Vec::new(),
+            listing: 100,
+        }
+    }
+}
+
+impl Default for DockerDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            socket: PathBuf::from("/var/run/docker.sock"),
+            tail_lines: 1000,
+            follow: true,
+        }
+    }
+}
+
+impl Default for GitDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            commit_listing: 100,
+            repos: Vec::new(),
+        }
+    }
+}
+
+impl Default for SensorsDriverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sample_interval_ms: 1000,
+            history_size: 3600,
+            discover: true,
+            sources: Vec::new(),
+        }
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            timeout_seconds: 30,
+            connect_timeout_seconds: 10,
+            pool_idle_timeout_seconds: 90,
+            pool_max_idle_per_host: 16,
+            max_connections_per_host: 32,
+            tcp_keepalive_seconds: 60,
+            http2_adaptive_window: true,
+            dns_cache_ttl_seconds: 300,
+            rate_limit_retries: 3,
+            max_retry_after_seconds: 30,
+            proxy: None,
+            egress: EgressConfig::default(),
+            dns: DnsConfig::default(),
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: PathBuf::from("/var/cache/gnos"),
+            max_size_mb: 1024,
+            chunk_size_kb: 1024,
+            prefixes: vec!["/cloud/".to_string()],
+            dedup: true,
+            dedup_avg_block_kb: 64,
+        }
+    }
+}
+
+impl Default for WriteBackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            journal_dir: PathBuf::from("/var/lib/gnos/journal"),
+            max_retries: 5,
+            retry_backoff_ms: 500,
+            conflict_strategy: ConflictStrategy::Fail,
+            conflicts: Vec::new(),
+        }
+    }
+}
+
+impl Default for OfflineConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            probe_interval_seconds: 30,
+        }
+    }
+}
+
+impl Default for CopyConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size_mb: 8,
+            concurrency: 4,
+            checkpoint_dir: PathBuf::from("/var/lib/gnos/copy"),
+            max_buffered_mb: 1024,
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            max_depth: 8,
+            max_results: 1000,
+            max_content_mb: 8,
+        }
+    }
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefixes: Vec::new(),
+            index_dir: PathBuf::from("/var/lib/gnos/index"),
+            refresh_interval_seconds: 300,
+            max_depth: 16,
+            max_file_mb: 8,
+            concurrency: 8,
+        }
+    }
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            socket_path: Some(PathBuf::from("/run/gnos/events.sock")),
+            buffer: 1024,
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            queue_size: 1024,
+            max_retries: 3,
+            retry_backoff_ms: 1000,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for VfsConfig {
+    fn default() -> Self {
+        Self {
+            attr_cache_ttl_seconds: 5,
+            cache_modes: Vec::new(),
+            warm_prefixes: Vec::new(),
+            warm_max_depth: 3,
+            warm_concurrency: 8,
+            path_templates: Vec::new(),
+            only: Vec::new(),
+            trace_handles: false,
+            trace_max_events: 10_000,
+            conditional_writes: Vec::new(),
+            read_your_writes: vec!["/cloud".to_string()],
+            read_your_writes_seconds: 30,
+            read_your_writes_max_mb: 64,
+            shard_threshold: 0,
+            shard_prefix_len: 2,
+        }
+    }
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: 60,
+            driver_error_rate: 0.2,
+            min_driver_calls: 20,
+            max_denials_per_principal: 10,
+            cooldown_seconds: 900,
+            webhook_url: None,
+        }
+    }
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "gnos".to_string(),
+            slow_op_threshold_ms: 1000,
+            profiling: false,
+        }
+    }
+}
+
+impl Default for NinePConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:5640".to_string(),
+            msize: 512 * 1024,
+        }
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            level: 3,
+            rules: Vec::new(),
+        }
     }
 }
 
 impl GnosConfig {
     pub async fn load(path: &Path) -> Result<Self> {
         if path.exists() {
-            let content = tokio::fs::read_to_string(path).await?;
+            let content = tokio::fs::read_to_string(path).await
+                .map_err(crate::GnosError::from)
+                .context(format!("Failed to read {}", path.display()))?;
             let config: GnosConfig = toml::from_str(&content)
-                .map_err(|e| crate::GnosError::Driver(format!("Invalid config: {}", e)))?;
+                .map_err(|e| crate::GnosError::Config(format!("{}: {}", path.display(), e)))?;
             Ok(config)
         } else {
             // Create default config
@@ -0,0 +1,246 @@
+//! Cross-driver copies
+//!
+//! `CopyEngine` moves an object between any two drivers, e.g. S3 to Azure
+//! through one namespace, reading it in ranged chunks with a bounded number
+//! in flight. Destinations that take multipart writes receive each chunk as
+//! a part as soon as it arrives, so memory stays at `concurrency` chunks
+//! whatever the object size; other destinations are fed from a spool file
+//! in one piece. Completed chunks are checkpointed, so an interrupted copy
+//! picks up where it stopped when run again.
+
+use std::collections::BTreeSet;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use bytes::Bytes;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument};
+
+use crate::cache::CompressionPolicy;
+use crate::config::CopyConfig;
+use crate::drivers::GnosDriver;
+use crate::{GnosError, Result};
+
+/// Byte counts of a running copy, readable from another task
+#[derive(Debug, Default)]
+pub struct CopyProgress {
+    total: AtomicU64,
+    copied: AtomicU64,
+}
+
+impl CopyProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Object size; zero until the source has been inspected
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+    
+    /// Bytes delivered so far, including any from a resumed run
+    pub fn copied(&self) -> u64 {
+        self.copied.load(Ordering::Relaxed)
+    }
+}
+
+/// What has been delivered for one source/destination pair
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    source: PathBuf,
+    dest: PathBuf,
+    size: u64,
+    source_mtime_ns: u64,
+    chunk_size: u64,
+    /// Multipart upload the parts belong to; `None` when spooling
+    upload_id: Option<String>,
+    done: BTreeSet<u64>,
+}
+
+pub struct CopyEngine {
+    chunk_size: u64,
+    concurrency: usize,
+    checkpoint_dir: PathBuf,
+    max_buffered: u64,
+    compression: Arc<CompressionPolicy>,
+}
+
+impl CopyEngine {
+    pub fn new(config: CopyConfig, compression: Arc<CompressionPolicy>) -> Self {
+        Self {
+            chunk_size: std::cmp::max(config.chunk_size_mb, 1) * 1024 * 1024,
+            concurrency: std::cmp::max(config.concurrency, 1),
+            checkpoint_dir: config.checkpoint_dir,
+            max_buffered: config.max_buffered_mb * 1024 * 1024,
+            compression,
+        }
+    }
+    
+    /// Copy `source_path` on `source` to `dest_path` on `dest`, returning the bytes copied
+    ///
+    /// On failure the checkpoint is kept, and the next copy of the same pair
+    /// skips the chunks already delivered as long as the source is unchanged.
+    #[instrument(name = "copy", skip_all, fields(source = %source_path.display(), dest = %dest_path.display()))]
+    pub async fn copy(
+        &self,
+        source: &dyn GnosDriver,
+        source_path: &Path,
+        dest: &dyn GnosDriver,
+        dest_path: &Path,
+        progress: &CopyProgress,
+    ) -> Result<u64> {
+        let metadata = source.metadata(source_path).await?;
+        if metadata.is_directory {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", source_path.display())));
+        }
+        
+        let size = metadata.size;
+        let multipart = dest.supports_parts(dest_path);
+        if !multipart && size > self.max_buffered {
+            return Err(GnosError::Driver(format!(
+                "{} takes no multipart writes and {} ({} bytes) exceeds max_buffered_mb",
+                dest.name(), source_path.display(), size
+            )));
+        }
+        
+        tokio::fs::create_dir_all(&self.checkpoint_dir).await?;
+        let key = pair_key(source_path, dest_path);
+        let checkpoint_file = self.checkpoint_dir.join(format!("{}.json", key));
+        let spool_file = self.checkpoint_dir.join(format!("{}.spool", key));
+        
+        let source_mtime_ns = metadata.last_modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        let resumable = load_checkpoint(&checkpoint_file).await.filter(|checkpoint| {
+            checkpoint.source == source_path
+                && checkpoint.dest == dest_path
+                && checkpoint.size == size
+                && checkpoint.source_mtime_ns == source_mtime_ns
+                && checkpoint.chunk_size == self.chunk_size
+                && checkpoint.upload_id.is_some() == multipart
+                && (multipart || spool_file.exists())
+        });
+        let mut checkpoint = match resumable {
+            Some(checkpoint) => {
+                info!("⏯️  Resuming copy of {} with {} chunks done", source_path.display(), checkpoint.done.len());
+                checkpoint
+            }
+            None => Checkpoint {
+                source: source_path.to_path_buf(),
+                dest: dest_path.to_path_buf(),
+                size,
+                source_mtime_ns,
+                chunk_size: self.chunk_size,
+                upload_id: None,
+                done: BTreeSet::new(),
+            },
+        };
+        if multipart && checkpoint.upload_id.is_none() {
+            checkpoint.upload_id = Some(dest.begin_parts(dest_path).await?);
+        }
+        save_checkpoint(&checkpoint_file, &checkpoint).await?;
+        
+        let sink = match &checkpoint.upload_id {
+            Some(upload_id) => Sink::Parts(upload_id.clone()),
+            None => {
+                let file = std::fs::OpenOptions::new().create(true).truncate(false).write(true).open(&spool_file)?;
+                file.set_len(size)?;
+                Sink::Spool(Arc::new(file))
+            }
+        };
+        
+        let chunk_size = self.chunk_size;
+        let chunk_len = |index: u64| std::cmp::min(chunk_size, size - index * chunk_size);
+        let chunks = size.div_ceil(chunk_size);
+        
+        progress.total.store(size, Ordering::Relaxed);
+        progress.copied.store(checkpoint.done.iter().map(|&index| chunk_len(index)).sum(), Ordering::Relaxed);
+        
+        let pending: Vec<u64> = (0..chunks).filter(|index| !checkpoint.done.contains(index)).collect();
+        let mut delivered = stream::iter(pending)
+            .map(|index| {
+                let sink = &sink;
+                async move {
+                    let len = chunk_len(index);
+                    let data = source.read_range(source_path, index * chunk_size, len).await?;
+                    if data.len() as u64 != len {
+                        return Err(GnosError::Driver(format!(
+                            "short read of {} at chunk {}: {} of {} bytes",
+                            source_path.display(), index, data.len(), len
+                        )));
+                    }
+                    
+                    match sink {
+                        Sink::Parts(upload_id) => dest.write_part(dest_path, upload_id, index, &data).await?,
+                        Sink::Spool(spool) => spool_chunk(spool.clone(), index * chunk_size, data).await?,
+                    }
+                    Ok(index)
+                }
+            })
+            .buffer_unordered(self.concurrency);
+        
+        while let Some(index) = delivered.try_next().await? {
+            checkpoint.done.insert(index);
+            save_checkpoint(&checkpoint_file, &checkpoint).await?;
+            progress.copied.fetch_add(chunk_len(index), Ordering::Relaxed);
+            debug!("Copied chunk {}/{} of {}", checkpoint.done.len(), chunks, source_path.display());
+        }
+        drop(delivered);
+        
+        match &sink {
+            Sink::Parts(upload_id) => dest.complete_parts(dest_path, upload_id, chunks).await?,
+            Sink::Spool(_) => {
+                let data = tokio::fs::read(&spool_file).await?;
+                self.compression.write_through(dest, dest_path, &data).await?;
+            }
+        }
+        
+        let _ = tokio::fs::remove_file(&checkpoint_file).await;
+        let _ = tokio::fs::remove_file(&spool_file).await;
+        info!("📦 Copied {} to {} ({} bytes)", source_path.display(), dest_path.display(), size);
+        
+        Ok(size)
+    }
+}
+
+/// Where delivered chunks go
+enum Sink {
+    /// Parts of a multipart upload on the destination
+    Parts(String),
+    /// A local file assembled for a one-piece write
+    Spool(Arc<std::fs::File>),
+}
+
+async fn spool_chunk(spool: Arc<std::fs::File>, offset: u64, data: Bytes) -> Result<()> {
+    tokio::task::spawn_blocking(move || spool.write_all_at(&data, offset))
+        .await
+        .map_err(|e| GnosError::Driver(format!("spool writer failed: {}", e)))??;
+    Ok(())
+}
+
+async fn load_checkpoint(path: &Path) -> Option<Checkpoint> {
+    let raw = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+/// Replace the checkpoint atomically so a crash never leaves half of one
+async fn save_checkpoint(path: &Path, checkpoint: &Checkpoint) -> Result<()> {
+    let raw = serde_json::to_vec(checkpoint)
+        .map_err(|e| GnosError::Driver(format!("Failed to encode copy checkpoint: {}", e)))?;
+    let staging = path.with_extension("json.tmp");
+    tokio::fs::write(&staging, raw).await?;
+    tokio::fs::rename(&staging, path).await?;
+    Ok(())
+}
+
+/// Stable file name for a source/destination pair
+fn pair_key(source: &Path, dest: &Path) -> String {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    context.update(source.as_os_str().as_bytes());
+    context.update(b"\0");
+    context.update(dest.as_os_str().as_bytes());
+    context.finish().as_ref()[..16].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,410 @@
+//! Cloud cost estimation
+//!
+//! With `[costs]` enabled, every call a driver makes to its backend is
+//! counted by request class (read, write, list, head) along with the bytes
+//! moved, keyed by driver, by path prefix cut to `prefix_depth` components,
+//! and by owner. `/proc/gnos/costs` prices the counts with the configured
+//! tables and ranks drivers, prefixes and owners by estimated spend.
+//!
+//! Requests are counted whether or not they succeed, as providers bill them
+//! either way; bytes are counted once a transfer completes. The estimate
+//! leaves out storage, tiering and free allowances, so it is for spotting
+//! expensive access patterns, not for reconciling invoices.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use dashmap::mapref::one::RefMut;
+use dashmap::DashMap;
+
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::{CacheMode, CostConfig, DriverPricing};
+use crate::drivers::{BatchOp, ChangeStream, GnosDriver, Growth, PartPolicy, PathParams, ResourceMetadata, WriteOptions};
+use crate::Result;
+
+/// Rows shown per section of the report
+const MAX_ROWS: usize = 20;
+
+/// Owner of spend no `[[costs.owners]]` prefix or tenant claims
+const UNASSIGNED: &str = "unassigned";
+
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Billing class of a backend call
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Request {
+    Read,
+    Write,
+    List,
+    Head,
+}
+
+#[derive(Default)]
+struct Usage {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    lists: AtomicU64,
+    heads: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+/// A snapshot of [`Usage`], summed over whatever a report row groups
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    reads: u64,
+    writes: u64,
+    lists: u64,
+    heads: u64,
+    bytes_read: u64,
+    bytes_written: u64,
+    cost: f64,
+}
+
+impl Totals {
+    fn add(&mut self, other: &Totals) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+        self.lists += other.lists;
+        self.heads += other.heads;
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+        self.cost += other.cost;
+    }
+}
+
+/// What a row of usage is charged to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Account {
+    driver: String,
+    prefix: PathBuf,
+    owner: String,
+}
+
+/// Request and byte counters, priced on demand
+pub struct CostTracker {
+    config: CostConfig,
+    usage: DashMap<Account, Usage>,
+}
+
+impl CostTracker {
+    pub fn new(config: &CostConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config: config.clone(),
+            usage: DashMap::new(),
+        })
+    }
+    
+    fn usage(&self, driver: &str, path: &Path) -> RefMut<'_, Account, Usage> {
+        let account = Account {
+            driver: driver.to_string(),
+            prefix: truncate(path, self.config.prefix_depth),
+            owner: self.owner_of(driver, path),
+        };
+        self.usage.entry(account).or_default()
+    }
+    
+    fn count(&self, driver: &str, path: &Path, request: Request) {
+        let usage = self.usage(driver, path);
+        let counter = match request {
+            Request::Read => &usage.reads,
+            Request::Write => &usage.writes,
+            Request::List => &usage.lists,
+            Request::Head => &usage.heads,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    fn count_read(&self, driver: &str, path: &Path, bytes: usize) {
+        self.usage(driver, path).bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    
+    fn count_write(&self, driver: &str, path: &Path, bytes: usize) {
+        let usage = self.usage(driver, path);
+        usage.writes.fetch_add(1, Ordering::Relaxed);
+        usage.bytes_written.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    
+    /// Longest `[[costs.owners]]` prefix, then the tenant a driver serves
+    fn owner_of(&self, driver: &str, path: &Path) -> String {
+        self.config.owners.iter()
+            .filter(|owner| path.starts_with(&owner.prefix))
+            .max_by_key(|owner| owner.prefix.len())
+            .map(|owner| owner.owner.clone())
+            .or_else(|| driver.split_once('@').map(|(_, tenant)| tenant.to_string()))
+            .unwrap_or_else(|| UNASSIGNED.to_string())
+    }
+    
+    /// Prices for `driver`, or for the driver a tenant's instance is made from
+    fn pricing(&self, driver: &str) -> Option<&DriverPricing> {
+        let base = driver.split_once('@').map_or(driver, |(base, _)| base);
+        self.config.pricing.iter().find(|pricing| pricing.driver == driver)
+            .or_else(|| self.config.pricing.iter().find(|pricing| pricing.driver == base))
+    }
+    
+    fn totals(&self, account: &Account, usage: &Usage) -> Totals {
+        let mut totals = Totals {
+            reads: usage.reads.load(Ordering::Relaxed),
+            writes: usage.writes.load(Ordering::Relaxed),
+            lists: usage.lists.load(Ordering::Relaxed),
+            heads: usage.heads.load(Ordering::Relaxed),
+            bytes_read: usage.bytes_read.load(Ordering::Relaxed),
+            bytes_written: usage.bytes_written.load(Ordering::Relaxed),
+            cost: 0.0,
+        };
+        if let Some(pricing) = self.pricing(&account.driver) {
+            totals.cost = totals.reads as f64 / 1000.0 * pricing.per_1k_reads
+                + totals.writes as f64 / 1000.0 * pricing.per_1k_writes
+                + totals.lists as f64 / 1000.0 * pricing.per_1k_lists
+                + totals.heads as f64 / 1000.0 * pricing.per_1k_heads
+                + totals.bytes_read as f64 / GIB * pricing.per_gb_read
+                + totals.bytes_written as f64 / GIB * pricing.per_gb_written;
+        }
+        totals
+    }
+    
+    /// Plain-text view for `/proc/gnos/costs`, most expensive rows first
+    pub fn status_report(&self) -> String {
+        let mut total = Totals::default();
+        let mut by_driver: HashMap<String, Totals> = HashMap::new();
+        let mut by_prefix: HashMap<String, Totals> = HashMap::new();
+        let mut by_owner: HashMap<String, Totals> = HashMap::new();
+        for entry in self.usage.iter() {
+            let totals = self.totals(entry.key(), entry.value());
+            total.add(&totals);
+            by_driver.entry(entry.key().driver.clone()).or_default().add(&totals);
+            by_prefix.entry(entry.key().prefix.display().to_string()).or_default().add(&totals);
+            by_owner.entry(entry.key().owner.clone()).or_default().add(&totals);
+        }
+        
+        let mut report = format!("currency: {}\nestimated: {:.4}\n", self.config.currency, total.cost);
+        report.push_str(&format!("requests: reads={} writes={} lists={} heads={}\n",
+                                 total.reads, total.writes, total.lists, total.heads));
+        report.push_str(&format!("transferred: read={} written={}\n", total.bytes_read, total.bytes_written));
+        for (section, rows) in [("driver", by_driver), ("prefix", by_prefix), ("owner", by_owner)] {
+            let mut rows: Vec<_> = rows.into_iter().collect();
+            rows.sort_by(|a, b| b.1.cost.total_cmp(&a.1.cost).then_with(|| a.0.cmp(&b.0)));
+            for (name, totals) in rows.iter().take(MAX_ROWS) {
+                report.push_str(&format!(
+                    "{}\t{}\t{:.4}\treads={}\twrites={}\tlists={}\theads={}\tread={}\twritten={}\n",
+                    section, name, totals.cost, totals.reads, totals.writes, totals.lists, totals.heads,
+                    totals.bytes_read, totals.bytes_written,
+                ));
+            }
+        }
+        report
+    }
+}
+
+/// `path` cut to its first `depth` components, root included
+fn truncate(path: &Path, depth: usize) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .take(depth.max(1))
+        .collect()
+}
+
+/// A driver whose backend calls are counted for cost estimates
+pub struct MeteredDriver {
+    /// Config name, e.g. `cloud` or `cloud@acme`
+    driver: String,
+    inner: Arc<dyn GnosDriver>,
+    costs: Arc<CostTracker>,
+}
+
+impl MeteredDriver {
+    pub fn new(driver: &str, inner: Arc<dyn GnosDriver>, costs: Arc<CostTracker>) -> Self {
+        Self { driver: driver.to_string(), inner, costs }
+    }
+    
+    fn count(&self, path: &Path, request: Request) {
+        self.costs.count(&self.driver, path, request);
+    }
+    
+    fn read_bytes(&self, path: &Path, bytes: usize) {
+        self.costs.count_read(&self.driver, path, bytes);
+    }
+    
+    fn written(&self, path: &Path, bytes: usize) {
+        self.costs.count_write(&self.driver, path, bytes);
+    }
+}
+
+#[async_trait]
+impl GnosDriver for MeteredDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        self.count(path, Request::Read);
+        let data = self.inner.read(path).await?;
+        self.read_bytes(path, data.len());
+        Ok(data)
+    }
+    
+    async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+        self.count(path, Request::Read);
+        let (data, checksum) = self.inner.read_checked(path).await?;
+        self.read_bytes(path, data.len());
+        Ok((data, checksum))
+    }
+    
+    async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        self.count(path, Request::Read);
+        let data = self.inner.read_range(path, offset, len).await?;
+        self.read_bytes(path, data.len());
+        Ok(data)
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.inner.streams(path)
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        self.count(path, Request::Read);
+        let growth = self.inner.read_growing(path, have).await?;
+        self.read_bytes(path, growth.data.len());
+        Ok(growth)
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.written(path, data.len());
+        self.inner.write(path, data).await
+    }
+    
+    fn upload_checksums(&self, path: &Path) -> &[ChecksumAlgorithm] {
+        self.inner.upload_checksums(path)
+    }
+    
+    async fn write_with(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+        self.written(path, data.len());
+        self.inner.write_with(path, data, options).await
+    }
+    
+    fn conditional_writes(&self, path: &Path) -> bool {
+        self.inner.conditional_writes(path)
+    }
+    
+    fn accepted_encodings(&self) -> &[&'static str] {
+        self.inner.accepted_encodings()
+    }
+    
+    async fn write_encoded(&self, path: &Path, data: &[u8], encoding: &str) -> Result<()> {
+        self.written(path, data.len());
+        self.inner.write_encoded(path, data, encoding).await
+    }
+    
+    fn invokes(&self, path: &Path) -> bool {
+        self.inner.invokes(path)
+    }
+    
+    async fn invoke(&self, path: &Path, payload: &[u8]) -> Result<Bytes> {
+        self.written(path, payload.len());
+        let reply = self.inner.invoke(path, payload).await?;
+        self.read_bytes(path, reply.len());
+        Ok(reply)
+    }
+    
+    fn supports_parts(&self, path: &Path) -> bool {
+        self.inner.supports_parts(path)
+    }
+    
+    fn part_policy(&self, path: &Path) -> PartPolicy {
+        self.inner.part_policy(path)
+    }
+    
+    async fn begin_parts(&self, path: &Path) -> Result<String> {
+        self.count(path, Request::Write);
+        self.inner.begin_parts(path).await
+    }
+    
+    async fn write_part(&self, path: &Path, upload_id: &str, part: u64, data: &[u8]) -> Result<()> {
+        self.written(path, data.len());
+        self.inner.write_part(path, upload_id, part, data).await
+    }
+    
+    async fn complete_parts(&self, path: &Path, upload_id: &str, parts: u64) -> Result<()> {
+        self.count(path, Request::Write);
+        self.inner.complete_parts(path, upload_id, parts).await
+    }
+    
+    async fn abort_parts(&self, path: &Path, upload_id: &str) -> Result<()> {
+        self.count(path, Request::Write);
+        self.inner.abort_parts(path, upload_id).await
+    }
+    
+    fn supports_batches(&self) -> bool {
+        self.inner.supports_batches()
+    }
+    
+    async fn commit_batch(&self, ops: &[BatchOp]) -> Result<()> {
+        // Charged per operation, the way batch APIs are billed
+        for op in ops {
+            match op {
+                BatchOp::Write { path, data } => self.written(path, data.len()),
+                BatchOp::Delete { path } => self.count(path, Request::Write),
+            }
+        }
+        self.inner.commit_batch(ops).await
+    }
+    
+    async fn materialize(&self, path: &Path, params: &PathParams) -> Result<()> {
+        self.count(path, Request::Write);
+        self.inner.materialize(path, params).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        self.count(path, Request::Write);
+        self.inner.create_dir(path).await
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        self.count(path, Request::Write);
+        self.inner.delete(path).await
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        self.count(path, Request::List);
+        self.inner.list(path).await
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        self.count(path, Request::List);
+        self.inner.list_with_metadata(path).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        self.count(path, Request::Head);
+        self.inner.exists(path).await
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        self.count(path, Request::Head);
+        self.inner.metadata(path).await
+    }
+    
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        self.inner.supports(path)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        self.inner.prefixes()
+    }
+    
+    fn cache_mode(&self, path: &Path) -> CacheMode {
+        self.inner.cache_mode(path)
+    }
+    
+    fn private(&self, path: &Path) -> bool {
+        self.inner.private(path)
+    }
+    
+    fn watch(&self, path: &Path) -> Option<ChangeStream> {
+        self.inner.watch(path)
+    }
+}
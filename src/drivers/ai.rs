@@ -1,25 +1,184 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{watch, RwLock};
 use async_trait::async_trait;
+use bytes::Bytes;
 use tracing::{debug, info};
 
-use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::config::{AiDriverConfig, CacheMode};
+use crate::drivers::chat::{ChatSession, Role, SESSIONS_ROOT, SESSION_FILES};
+use crate::drivers::models::MODELS_ROOT;
+use crate::drivers::traits::{GnosDriver, Growth, ResourceMetadata};
 use crate::{GnosError, Result};
 
+/// Pause between simulated tokens
+const TOKEN_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Models that can hold chat sessions
+const MODELS: [&str; 1] = ["llama3"];
+
+/// A response as far as it has been generated
+#[derive(Debug, Clone, Default)]
+struct Completion {
+    text: Bytes,
+    done: bool,
+}
+
 /// AI Model Driver - Treats LLMs as files you can read/write to
+///
+/// Writing a prompt starts generation and returns at once; the response
+/// grows token by token. `read` waits for the whole answer, while the mount
+/// hands out tokens as they arrive, so `tail -f /proc/llama3` follows along.
+/// Chat sessions under `/proc/sessions` keep context across prompts.
 pub struct AiDriver {
-    cache: Arc<RwLock<HashMap<String, String>>>,
+    responses: Arc<RwLock<HashMap<String, Arc<watch::Sender<Completion>>>>>,
+    /// Chat sessions by `<model>/<name>`
+    sessions: Arc<RwLock<HashMap<String, ChatSession>>>,
+    context_size: usize,
+    /// Idle time after which a session is dropped; zero keeps them forever
+    session_ttl: Duration,
+}
+
+/// Place in the `/proc/sessions` tree
+enum SessionNode<'a> {
+    Root,
+    Model(&'a str),
+    Session { model: &'a str, name: &'a str },
+    File { model: &'a str, name: &'a str, file: &'a str },
+}
+
+impl SessionNode<'_> {
+    fn parse(path: &Path) -> Option<SessionNode<'_>> {
+        let rest = path.strip_prefix(SESSIONS_ROOT).ok()?;
+        let names: Vec<&str> = rest.iter().map(|name| name.to_str()).collect::<Option<_>>()?;
+        match names[..] {
+            [] => Some(SessionNode::Root),
+            [model] => Some(SessionNode::Model(model)),
+            [model, name] => Some(SessionNode::Session { model, name }),
+            [model, name, file] => Some(SessionNode::File { model, name, file }),
+            _ => None,
+        }
+    }
 }
 
 impl AiDriver {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(config: &AiDriverConfig) -> Result<Self> {
         Ok(Self {
-            cache: Arc::new(RwLock::new(HashMap::new())),
+            responses: Arc::new(RwLock::new(HashMap::new())),
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            context_size: config.context_size,
+            session_ttl: Duration::from_secs(config.session_ttl_seconds),
         })
     }
     
+    /// Replace the response at `path` with one that streams `response` in
+    fn start_response(&self, responses: &mut HashMap<String, Arc<watch::Sender<Completion>>>, path: &Path, response: String) -> Arc<watch::Sender<Completion>> {
+        // A new prompt replaces the response; readers of the old one see it finish
+        let sender = Arc::new(watch::Sender::new(Completion::default()));
+        responses.insert(path.to_string_lossy().to_string(), sender.clone());
+        
+        let streaming = sender.clone();
+        tokio::spawn(async move {
+            let mut text = String::with_capacity(response.len());
+            for token in response.split_inclusive(' ') {
+                tokio::time::sleep(TOKEN_INTERVAL).await;
+                text.push_str(token);
+                let chunk = Bytes::from(text.clone());
+                streaming.send_modify(|completion| completion.text = chunk);
+            }
+            streaming.send_modify(|completion| completion.done = true);
+            info!("✅ AI inference completed");
+        });
+        sender
+    }
+    
+    /// Drop sessions idle for longer than the expiry
+    fn expire(&self, sessions: &mut HashMap<String, ChatSession>) {
+        if self.session_ttl.is_zero() {
+            return;
+        }
+        sessions.retain(|key, session| {
+            let live = session.last_used.elapsed() < self.session_ttl;
+            if !live {
+                info!("💬 Chat session {} expired", key);
+            }
+            live
+        });
+    }
+    
+    /// Answer a prompt written to a session, with the conversation so far as context
+    async fn session_prompt(&self, path: &Path, key: String, prompt: String) -> Result<()> {
+        let response_path = path.with_file_name("response");
+        
+        let context = {
+            let mut sessions = self.sessions.write().await;
+            self.expire(&mut sessions);
+            let session = sessions.get_mut(&key)
+                .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+            if let Some(completion) = self.completion(&response_path).await {
+                if !completion.borrow().done {
+                    return Err(GnosError::ResourceBusy(format!("{} is still answering", key)));
+                }
+            }
+            
+            let context = session.context(&prompt, self.context_size);
+            session.push(Role::User, prompt);
+            context
+        };
+        debug!("Session {} prompt with {} bytes of context", key, context.len());
+        
+        let response = self.simulate_ai_response(&context).await?;
+        let sender = self.start_response(&mut *self.responses.write().await, &response_path, response);
+        
+        // The answer joins the history once it is complete
+        let sessions = self.sessions.clone();
+        let mut completion = sender.subscribe();
+        tokio::spawn(async move {
+            let Ok(answer) = completion.wait_for(|completion| completion.done).await.map(|c| c.text.clone()) else {
+                return;
+            };
+            if let Some(session) = sessions.write().await.get_mut(&key) {
+                session.push(Role::Assistant, String::from_utf8_lossy(&answer).into_owned());
+            }
+        });
+        Ok(())
+    }
+    
+    /// Contents of a session's prompt or history file; `None` for `response`,
+    /// which streams like any other response
+    async fn session_file(&self, path: &Path) -> Result<Option<Bytes>> {
+        let Some(node) = SessionNode::parse(path) else {
+            return Err(GnosError::PathNotFound(path.display().to_string()));
+        };
+        let SessionNode::File { model, name, file } = node else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        
+        let mut sessions = self.sessions.write().await;
+        self.expire(&mut sessions);
+        let session = sessions.get(&format!("{}/{}", model, name))
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        match file {
+            "prompt" => Ok(Some(Bytes::from(session.last_prompt().unwrap_or_default().to_string()))),
+            "history.jsonl" => Ok(Some(Bytes::from(session.history_jsonl()))),
+            "response" => Ok(None),
+            _ => Err(GnosError::PathNotFound(path.display().to_string())),
+        }
+    }
+    
+    async fn completion(&self, path: &Path) -> Option<watch::Receiver<Completion>> {
+        let responses = self.responses.read().await;
+        responses.get(path.to_string_lossy().as_ref()).map(|sender| sender.subscribe())
+    }
+    
+    fn idle_status(path: &Path) -> Bytes {
+        // Default status when no previous interaction
+        let status = format!("🧠 GNOS AI Model: LLaMA3-7B (Simulated)\n📍 Status: Ready\n🎯 Context: 4096 tokens\n🌡️  Temperature: 0.7\n📝 Max Output: 1024 tokens\n\n💡 Usage: echo 'your prompt' > {}\n📖 Then: cat {} to read response\n\n🚀 Try: echo 'Explain quantum computing' > {}\n", path.display(), path.display(), path.display());
+        Bytes::from(status)
+    }
+    
     async fn simulate_ai_response(&self, prompt: &str) -> Result<String> {
         debug!("Simulating AI inference for: {}", &prompt[..std::cmp::min(50, prompt.len())]);
         
@@ -28,11 +187,11 @@ impl AiDriver {
         
         // Smart pattern matching for realistic responses
         let response = if prompt.to_lowercase().contains("diagnos") {
-            format!("Based on the medical information provided, here are key observations:\n\n1. The described symptoms suggest further evaluation is needed\n2. Recommend consulting with a specialist\n3. Additional imaging may be beneficial\n\nThis analysis is for informational purposes only and should not replace professional medical advice.\n\nGenerated by GNOS AI Engine (Simulated)")
+            "Based on the medical information provided, here are key observations:\n\n1. The described symptoms suggest further evaluation is needed\n2. Recommend consulting with a specialist\n3. Additional imaging may be beneficial\n\nThis analysis is for informational purposes only and should not replace professional medical advice.\n\nGenerated by GNOS AI Engine (Simulated)".to_string()
         } else if prompt.to_lowercase().contains("code") || prompt.to_lowercase().contains("function") {
-            format!("```python\ndef gnos_example():\n    # GNOS makes infrastructure feel like files\n    with open('/cloud/aws/s3/my-bucket/data.json', 'r') as f:\n        data = json.load(f)\n    \n    # Process with AI\n    with open('/proc/llama3', 'w') as ai:\n        ai.write(f'Analyze this: {{data}}')\n    \n    with open('/proc/llama3', 'r') as ai:\n        result = ai.read()\n    \n    return result\n```\n\nThis demonstrates GNOS's revolutionary approach to infrastructure as filesystem.\n\nGenerated by GNOS AI Engine (Simulated)")
+            "```python\ndef gnos_example():\n    # GNOS makes infrastructure feel like files\n    with open('/cloud/aws/s3/my-bucket/data.json', 'r') as f:\n        data = json.load(f)\n    \n    # Process with AI\n    with open('/proc/llama3', 'w') as ai:\n        ai.write(f'Analyze this: {data}')\n    \n    with open('/proc/llama3', 'r') as ai:\n        result = ai.read()\n    \n    return result\n```\n\nThis demonstrates GNOS's revolutionary approach to infrastructure as filesystem.\n\nGenerated by GNOS AI Engine (Simulated)".to_string()
         } else if prompt.to_lowercase().contains("explain") || prompt.to_lowercase().contains("what") {
-            format!("GNOS (GlobalNamespace OS) is a revolutionary operating system concept that treats all computing resources as files in a unified filesystem.\n\nKey benefits:\n• Cloud services become simple file operations\n• AI models accessible via read/write\n• No more SDK complexity\n• Universal POSIX interface\n• 10x faster development\n\nExample: `cp file.txt /cloud/aws/s3/bucket/` uploads to S3\n\nThis represents the future of infrastructure interaction.\n\nGenerated by GNOS AI Engine (Simulated)")
+            "GNOS (GlobalNamespace OS) is a revolutionary operating system concept that treats all computing resources as files in a unified filesystem.\n\nKey benefits:\n• Cloud services become simple file operations\n• AI models accessible via read/write\n• No more SDK complexity\n• Universal POSIX interface\n• 10x faster development\n\nExample: `cp file.txt /cloud/aws/s3/bucket/` uploads to S3\n\nThis represents the future of infrastructure interaction.\n\nGenerated by GNOS AI Engine (Simulated)".to_string()
         } else {
             format!("I understand you're asking about: \"{}\"\n\nAs an AI model running within the GNOS ecosystem, I can help you with:\n- Code generation and analysis\n- Data processing and insights\n- Documentation and explanations\n- Creative problem solving\n\nGNOS enables this seamless AI integration through its revolutionary filesystem interface.\n\nGenerated by GNOS AI Engine (Simulated)", prompt)
         };
@@ -43,17 +202,44 @@ impl AiDriver {
 
 #[async_trait]
 impl GnosDriver for AiDriver {
-    async fn read(&self, path: &Path) -> Result<Vec<u8>> {
-        let cache = self.cache.read().await;
-        let path_str = path.to_string_lossy();
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        if path.starts_with(SESSIONS_ROOT) {
+            if let Some(contents) = self.session_file(path).await? {
+                return Ok(contents);
+            }
+        }
+        let Some(mut completion) = self.completion(path).await else {
+            return Ok(Self::idle_status(path));
+        };
         
-        if let Some(response) = cache.get(&path_str.to_string()) {
-            Ok(response.clone().into_bytes())
-        } else {
-            // Default status when no previous interaction
-            let status = format!("🧠 GNOS AI Model: LLaMA3-7B (Simulated)\n📍 Status: Ready\n🎯 Context: 4096 tokens\n🌡️  Temperature: 0.7\n📝 Max Output: 1024 tokens\n\n💡 Usage: echo 'your prompt' > {}\n📖 Then: cat {} to read response\n\n🚀 Try: echo 'Explain quantum computing' > {}\n", path.display(), path.display(), path.display());
-            Ok(status.into_bytes())
+        // Library callers get the whole answer, as if generation were instant
+        let response = completion.wait_for(|completion| completion.done).await
+            .map_err(|_| GnosError::Driver(format!("generation for {} was abandoned", path.display())))?;
+        // Cheap refcount bump - the response buffer is shared, not copied
+        Ok(response.text.clone())
+    }
+    
+    fn streams(&self, _path: &Path) -> bool {
+        true
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        if path.starts_with(SESSIONS_ROOT) {
+            if let Some(contents) = self.session_file(path).await? {
+                return Ok(Growth { offset: 0, data: contents, growing: false });
+            }
         }
+        let Some(mut completion) = self.completion(path).await else {
+            return Ok(Growth { offset: 0, data: Self::idle_status(path), growing: false });
+        };
+        
+        let response = completion.wait_for(|completion| completion.done || completion.text.len() as u64 > have).await
+            .map_err(|_| GnosError::Driver(format!("generation for {} was abandoned", path.display())))?;
+        Ok(Growth {
+            offset: 0,
+            data: response.text.clone(),
+            growing: !response.done,
+        })
     }
     
     async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
@@ -62,49 +248,135 @@ impl GnosDriver for AiDriver {
         
         info!("🎯 AI inference request: {}", &prompt[..std::cmp::min(50, prompt.len())]);
         
+        match SessionNode::parse(path) {
+            Some(SessionNode::File { model, name, file: "prompt" }) => {
+                return self.session_prompt(path, format!("{}/{}", model, name), prompt).await;
+            }
+            Some(_) => return Err(GnosError::PermissionDenied(format!("{} is read-only", path.display()))),
+            None => {}
+        }
+        
         // Run simulated inference
         let response = self.simulate_ai_response(&prompt).await?;
+        self.start_response(&mut *self.responses.write().await, path, response);
+        Ok(())
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        let Some(SessionNode::Session { model, name }) = SessionNode::parse(path) else {
+            return Err(GnosError::PermissionDenied(format!("can't create {}", path.display())));
+        };
+        if !MODELS.contains(&model) {
+            return Err(GnosError::PathNotFound(format!("no model {}", model)));
+        }
         
-        // Cache the result
-        let path_str = path.to_string_lossy().to_string();
-        self.cache.write().await.insert(path_str, response);
-        
-        info!("✅ AI inference completed");
+        let mut sessions = self.sessions.write().await;
+        self.expire(&mut sessions);
+        let key = format!("{}/{}", model, name);
+        if sessions.contains_key(&key) {
+            return Err(GnosError::ResourceBusy(format!("session {} exists", key)));
+        }
+        sessions.insert(key.clone(), ChatSession::new());
+        info!("💬 Chat session {} started", key);
+        Ok(())
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        let Some(SessionNode::Session { model, name }) = SessionNode::parse(path) else {
+            return Err(GnosError::PermissionDenied(format!("can't remove {}", path.display())));
+        };
+        let key = format!("{}/{}", model, name);
+        if self.sessions.write().await.remove(&key).is_none() {
+            return Err(GnosError::PathNotFound(path.display().to_string()));
+        }
+        self.responses.write().await.remove(path.join("response").to_string_lossy().as_ref());
+        info!("💬 Chat session {} ended", key);
         Ok(())
     }
     
     async fn list(&self, path: &Path) -> Result<Vec<String>> {
         if path.to_string_lossy() == "/proc" {
-            Ok(vec!["llama3".to_string()])
-        } else {
-            Ok(vec![])
+            return Ok(vec!["llama3".to_string()]);
+        }
+        
+        match SessionNode::parse(path) {
+            Some(SessionNode::Root) => Ok(MODELS.iter().map(|model| model.to_string()).collect()),
+            Some(SessionNode::Model(model)) => {
+                let mut sessions = self.sessions.write().await;
+                self.expire(&mut sessions);
+                let prefix = format!("{}/", model);
+                let mut names: Vec<String> = sessions.keys()
+                    .filter_map(|key| key.strip_prefix(&prefix).map(str::to_string))
+                    .collect();
+                names.sort();
+                Ok(names)
+            }
+            Some(SessionNode::Session { .. }) => Ok(SESSION_FILES.iter().map(|file| file.to_string()).collect()),
+            _ => Ok(vec![]),
         }
     }
     
     async fn exists(&self, path: &Path) -> Result<bool> {
+        if path.starts_with(SESSIONS_ROOT) {
+            return Ok(self.metadata(path).await.is_ok());
+        }
         let path_str = path.to_string_lossy();
         Ok(path_str.contains("/proc/llama3"))
     }
     
     async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
-        let cache = self.cache.read().await;
-        let path_str = path.to_string_lossy().to_string();
+        match SessionNode::parse(path) {
+            Some(SessionNode::Root) => return Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }),
+            Some(SessionNode::Model(model)) if MODELS.contains(&model) => {
+                return Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() });
+            }
+            Some(SessionNode::Session { model, name }) => {
+                let mut sessions = self.sessions.write().await;
+                self.expire(&mut sessions);
+                let Some(session) = sessions.get(&format!("{}/{}", model, name)) else {
+                    return Err(GnosError::PathNotFound(path.display().to_string()));
+                };
+                return Ok(ResourceMetadata {
+                    is_directory: true,
+                    last_modified: session.last_active,
+                    ..ResourceMetadata::default()
+                });
+            }
+            Some(SessionNode::File { file, .. }) if file != "response" => {
+                let contents = self.session_file(path).await?.unwrap_or_default();
+                return Ok(ResourceMetadata {
+                    size: contents.len() as u64,
+                    mime_type: Some("text/plain".to_string()),
+                    ..ResourceMetadata::default()
+                });
+            }
+            Some(SessionNode::File { .. }) => {
+                self.session_file(path).await?;
+            }
+            Some(SessionNode::Model(_)) => return Err(GnosError::PathNotFound(path.display().to_string())),
+            None => {}
+        }
         
-        let size = if let Some(response) = cache.get(&path_str) {
-            response.len() as u64
-        } else {
-            512 // Default status size
+        // A response still being generated reports what has arrived so far
+        let size = match self.completion(path).await {
+            Some(completion) => completion.borrow().text.len() as u64,
+            None => 512, // Default status size
         };
         
         let mut custom_fields = std::collections::HashMap::new();
         custom_fields.insert("model_name".to_string(), "LLaMA3-7B".to_string());
         custom_fields.insert("status".to_string(), "simulated".to_string());
+        if let Some(completion) = self.completion(path).await {
+            let state = if completion.borrow().done { "complete" } else { "generating" };
+            custom_fields.insert("response".to_string(), state.to_string());
+        }
         
         Ok(ResourceMetadata {
             size,
             is_directory: false,
             last_modified: std::time::SystemTime::now(),
             mime_type: Some("text/plain".to_string()),
+            etag: None,
             custom_fields,
         })
     }
@@ -114,7 +386,19 @@ impl GnosDriver for AiDriver {
     }
     
     fn supports(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
-        path_str.starts_with("/proc/") && path_str.contains("llama")
+        (path.starts_with("/proc") && path.to_string_lossy().contains("llama") && !path.starts_with(MODELS_ROOT))
+            || path.starts_with(SESSIONS_ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        MODELS.iter()
+            .map(|model| Path::new("/proc").join(model))
+            .chain([PathBuf::from(SESSIONS_ROOT)])
+            .collect()
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Every write replaces the response, so reads must never hit stale pages
+        CacheMode::DirectIo
     }
 }
\ No newline at end of file
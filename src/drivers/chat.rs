@@ -0,0 +1,116 @@
+//! Chat sessions for AI models
+//!
+//! `mkdir /proc/sessions/llama3/<name>` starts a conversation whose turns
+//! carry over: each prompt written to its `prompt` file is answered with
+//! the conversation so far as context, trimmed from the oldest turns to fit
+//! the model's context window. `response` streams the latest answer and
+//! `history.jsonl` holds every turn. Sessions idle past their expiry are
+//! dropped.
+
+use std::time::{Instant, SystemTime};
+
+use serde::Serialize;
+
+pub const SESSIONS_ROOT: &str = "/proc/sessions";
+
+/// Files in every session directory
+pub const SESSION_FILES: [&str; 3] = ["prompt", "response", "history.jsonl"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Turn {
+    pub role: Role,
+    pub content: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+#[derive(Debug)]
+pub struct ChatSession {
+    pub history: Vec<Turn>,
+    pub last_used: Instant,
+    /// Wall-clock `last_used`, reported as the session directory's mtime
+    pub last_active: SystemTime,
+}
+
+impl ChatSession {
+    pub fn new() -> Self {
+        Self {
+            history: Vec::new(),
+            last_used: Instant::now(),
+            last_active: SystemTime::now(),
+        }
+    }
+    
+    pub fn push(&mut self, role: Role, content: String) {
+        self.history.push(Turn {
+            role,
+            content,
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        });
+        self.last_used = Instant::now();
+        self.last_active = SystemTime::now();
+    }
+    
+    pub fn last_prompt(&self) -> Option<&str> {
+        self.history.iter().rev()
+            .find(|turn| turn.role == Role::User)
+            .map(|turn| turn.content.as_str())
+    }
+    
+    /// The conversation ending in `prompt`, keeping only the most recent
+    /// turns that fit in `context_tokens`; the prompt itself always stays
+    pub fn context(&self, prompt: &str, context_tokens: usize) -> String {
+        let mut budget = context_tokens.saturating_sub(estimate_tokens(prompt));
+        let mut kept = Vec::new();
+        for turn in self.history.iter().rev() {
+            let cost = estimate_tokens(&turn.content);
+            if cost > budget {
+                break;
+            }
+            budget -= cost;
+            kept.push(turn);
+        }
+        
+        let mut context = String::new();
+        for turn in kept.into_iter().rev() {
+            let speaker = match turn.role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+            };
+            context.push_str(&format!("{}: {}\n", speaker, turn.content));
+        }
+        context.push_str(&format!("user: {}\n", prompt));
+        context
+    }
+    
+    pub fn history_jsonl(&self) -> String {
+        let mut lines = String::new();
+        for turn in &self.history {
+            if let Ok(line) = serde_json::to_string(turn) {
+                lines.push_str(&line);
+                lines.push('\n');
+            }
+        }
+        lines
+    }
+}
+
+impl Default for ChatSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rough token count, at about four bytes of English per token
+fn estimate_tokens(text: &str) -> usize {
+    text.len() / 4 + 1
+}
@@ -1,44 +1,777 @@
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 use async_trait::async_trait;
-use crate::drivers::traits::{GnosDriver, ResourceMetadata};
-use crate::Result;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_s3::config::{IdentityCache, RequestChecksumCalculation};
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::operation::get_object::builders::GetObjectFluentBuilder;
+use aws_sdk_s3::operation::put_object::builders::PutObjectFluentBuilder;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{ChecksumMode, CompletedMultipartUpload, CompletedPart, ServerSideEncryption};
+use bytes::Bytes;
+use tracing::{debug, info};
+use crate::cache::compress::ZSTD;
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::{BucketEncryption, CacheMode, CloudDriverConfig, SseAlgorithm};
+use crate::drivers::context::DriverContext;
+use crate::drivers::credentials::CloudCredentials;
+use crate::drivers::network::EgressPolicy;
+use crate::drivers::regions::RegionRouter;
+use crate::drivers::traits::{GnosDriver, PartPolicy, Precondition, ResourceMetadata, WriteOptions};
+use crate::{GnosError, Result};
 
-pub struct CloudDriver;
+/// S3 refuses parts below this size, except an object's last
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// S3's limit on the parts of one object
+const MAX_PARTS: u64 = 10_000;
+
+/// Reading `<object>.presign` gives a download URL for `<object>`
+const PRESIGN_SUFFIX: &str = ".presign";
+
+/// `<bucket>/.versions/<key>/` lists the stored versions of `<key>`, each
+/// a read-only file named by its version ID
+const VERSIONS_DIR: &str = ".versions";
+
+/// Longest validity S3 accepts for a presigned URL
+const MAX_PRESIGN: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// User metadata holding the size of an object stored compressed, sent as
+/// `x-amz-meta-gnos-original-size`
+const ORIGINAL_SIZE_META: &str = "gnos-original-size";
+
+/// Custom metadata field naming the encoding an object is stored with
+const ENCODING_FIELD: &str = "content-encoding";
+
+/// A multipart upload in progress: its object and the size and ETag of
+/// each part received
+struct Upload {
+   path: PathBuf,
+   object: Object,
+   parts: BTreeMap<u64, (u64, String)>,
+}
+
+pub struct CloudDriver {
+   credentials: Arc<CloudCredentials>,
+   /// One client for every bucket; requests override its region and
+   /// endpoint with their bucket's
+   s3: aws_sdk_s3::Client,
+   regions: Arc<RegionRouter>,
+   /// Region of buckets under `/cloud/aws/s3`
+   region: String,
+   presign_expiry: Duration,
+   parts: PartPolicy,
+   uploads: Mutex<HashMap<String, Upload>>,
+   encryption: Vec<BucketEncryption>,
+   egress: Arc<EgressPolicy>,
+}
 
 impl CloudDriver {
-   pub async fn new() -> Result<Self> {
-       Ok(Self)
+   pub async fn new(config: &CloudDriverConfig, context: &DriverContext) -> Result<Self> {
+       for sse in &config.encryption {
+           if sse.algorithm == SseAlgorithm::S3 && (sse.kms_key_id.is_some() || sse.bucket_key) {
+               return Err(GnosError::InvalidPath(format!(
+                   "encryption of bucket {} sets KMS options but its algorithm is s3", sse.bucket
+               )));
+           }
+       }
+       
+       let credentials = CloudCredentials::new(&config.aws).await?;
+       let http = context.http_for("cloud");
+       // Credentials are cached and renewed by CloudCredentials already.
+       // Requests go out through the shared client, which sends bodies
+       // whole, so checksums are only added where S3 requires them rather
+       // than as aws-chunked trailers
+       let s3 = aws_sdk_s3::Client::from_conf(aws_sdk_s3::Config::builder()
+           .behavior_version(BehaviorVersion::latest())
+           .region(Region::new(config.aws.region.clone()))
+           .credentials_provider(credentials.provider())
+           .identity_cache(IdentityCache::no_cache())
+           .http_client(http.for_sdk())
+           .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+           .build());
+       
+       Ok(Self {
+           credentials,
+           s3,
+           regions: RegionRouter::new(&config.buckets, http,
+                                      Duration::from_secs(config.probe_interval_seconds))?,
+           region: config.aws.region.clone(),
+           presign_expiry: Duration::from_secs(config.presign_seconds).min(MAX_PRESIGN),
+           parts: PartPolicy {
+               threshold: config.multipart.threshold_mb * 1024 * 1024,
+               part_size: (config.multipart.part_size_mb * 1024 * 1024).max(MIN_PART_SIZE),
+               concurrency: config.multipart.concurrency.max(1),
+           },
+           uploads: Mutex::new(HashMap::new()),
+           encryption: config.encryption.clone(),
+           egress: context.egress.clone(),
+       })
+   }
+   
+   /// Credentials requests are signed with, renewed in the background
+   pub fn credentials(&self) -> Arc<CloudCredentials> {
+       self.credentials.clone()
+   }
+   
+   /// Where each bucket alias is routed
+   pub fn regions(&self) -> Arc<RegionRouter> {
+       self.regions.clone()
+   }
+   
+   /// The bucket alias `path` falls under, e.g. `data` for `/cloud/data/x`
+   fn alias_of(&self, path: &Path) -> Option<String> {
+       let Some(Component::Normal(name)) = path.strip_prefix("/cloud").ok()?.components().next() else {
+           return None;
+       };
+       let name = name.to_str()?;
+       self.regions.aliases().into_iter().find(|alias| alias == name)
+   }
+   
+   /// The bucket and key `path` names: `/cloud/aws/s3/<bucket>/<key>` or
+   /// `/cloud/<alias>/<key>`; the key is empty at the bucket itself
+   fn object_at(&self, path: &Path) -> Option<Object> {
+       let rest = path.strip_prefix("/cloud").ok()?;
+       match rest.strip_prefix("aws/s3") {
+           Ok(rest) => {
+               let mut components = rest.iter();
+               let bucket = components.next()?.to_str()?.to_string();
+               Some(Object {
+                   region: self.region.clone(),
+                   endpoint: None,
+                   bucket,
+                   key: components.as_path().to_str()?.to_string(),
+               })
+           }
+           Err(_) => {
+               let alias = self.alias_of(path)?;
+               let route = self.regions.route(&alias)?;
+               Some(Object {
+                   region: route.region,
+                   endpoint: Some(route.endpoint),
+                   bucket: route.bucket,
+                   key: rest.strip_prefix(&alias).ok()?.to_str()?.to_string(),
+               })
+           }
+       }
+   }
+   
+   /// What a request to `object`'s bucket overrides in the shared client's
+   /// config: its region, and the endpoint it is routed to
+   fn route(&self, object: &Object) -> Result<aws_sdk_s3::config::Builder> {
+       // The SDK connects on its own, so egress is checked here
+       match &object.endpoint {
+           Some(endpoint) => self.egress.check_endpoint(endpoint)?,
+           None => self.egress.check(&format!("{}.s3.{}.amazonaws.com", object.bucket, object.region))?,
+       }
+       let mut config = aws_sdk_s3::config::Builder::default().region(Region::new(object.region.clone()));
+       if let Some(endpoint) = &object.endpoint {
+           config = config.endpoint_url(endpoint);
+       }
+       Ok(config)
+   }
+   
+   /// Encryption headers for writes to `object`: algorithm, KMS key and
+   /// bucket key, from its bucket's entry or else the `*` one
+   fn encryption(&self, object: &Object) -> (Option<ServerSideEncryption>, Option<String>, Option<bool>) {
+       let sse = self.encryption.iter().find(|sse| sse.bucket == object.bucket)
+           .or_else(|| self.encryption.iter().find(|sse| sse.bucket == "*"));
+       let Some(sse) = sse else {
+           return (None, None, None);
+       };
+       let algorithm = match sse.algorithm {
+           SseAlgorithm::S3 => ServerSideEncryption::Aes256,
+           SseAlgorithm::Kms => ServerSideEncryption::AwsKms,
+       };
+       (Some(algorithm), sse.kms_key_id.clone(), sse.bucket_key.then_some(true))
+   }
+   
+   /// The object `path` names, for writing; the key must not be empty
+   fn object_to_write(&self, path: &Path) -> Option<Object> {
+       self.object_at(path).filter(|object| !object.key.is_empty())
+   }
+   
+   /// The object `path` holds the bytes of, unless it is a generated
+   /// `.presign` file or an old version
+   fn stored_object(&self, path: &Path) -> Option<Object> {
+       if path.to_string_lossy().ends_with(PRESIGN_SUFFIX) || self.versions_at(path).is_some() {
+           return None;
+       }
+       self.object_to_write(path)
+   }
+   
+   /// The object a write to `path` lands in, if it is an S3 object
+   fn upload_target(&self, path: &Path) -> Result<Option<Object>> {
+       if path.to_string_lossy().ends_with(PRESIGN_SUFFIX) {
+           return Err(GnosError::PermissionDenied(format!("{} is generated", path.display())));
+       }
+       if self.versions_at(path).is_some() {
+           return Err(GnosError::PermissionDenied(format!("{} is an old version; copy it out to restore it", path.display())));
+       }
+       // GCP and Azure are still simulated
+       Ok(self.object_to_write(path))
+   }
+   
+   /// A PutObject of `data` to `object`, encrypted as its bucket asks
+   fn put(&self, object: &Object, data: &[u8]) -> PutObjectFluentBuilder {
+       let (algorithm, kms_key_id, bucket_key) = self.encryption(object);
+       self.s3.put_object()
+           .bucket(&object.bucket)
+           .key(&object.key)
+           .body(ByteStream::from(data.to_vec()))
+           .set_server_side_encryption(algorithm)
+           .set_ssekms_key_id(kms_key_id)
+           .set_bucket_key_enabled(bucket_key)
+   }
+   
+   /// A GetObject of `object`, or of one of its versions
+   fn get_request(&self, object: &Object, version: Option<&str>) -> GetObjectFluentBuilder {
+       self.s3.get_object().bucket(&object.bucket).key(&object.key).set_version_id(version.map(str::to_string))
+   }
+   
+   /// An object's content, or a version's, with the digest S3 holds for
+   /// it; objects stored compressed are decompressed, and their digests
+   /// describe the compressed bytes, so none is reported
+   async fn get(&self, object: &Object, version: Option<&str>) -> Result<(Bytes, Option<Checksum>)> {
+       let route = self.route(object)?;
+       let response = self.get_request(object, version).checksum_mode(ChecksumMode::Enabled)
+           .customize().config_override(route).send().await
+           .map_err(|e| s3_error(object, e))?;
+       if response.content_encoding() == Some(ZSTD) {
+           let data = collect(object, response.body).await?;
+           let data = zstd::stream::decode_all(&data[..])
+               .map_err(|e| GnosError::Driver(format!("decompressing {} failed: {}", object, e)))?;
+           return Ok((Bytes::from(data), None));
+       }
+       
+       let checksum = stored_checksum(
+           response.checksum_sha256(),
+           response.checksum_crc32_c(),
+           response.e_tag(),
+           response.server_side_encryption(),
+       );
+       Ok((collect(object, response.body).await?, checksum))
+   }
+   
+   /// A time-limited GET URL for the object behind a `.presign` file
+   async fn presign(&self, path: &Path) -> Result<Bytes> {
+       let object = path.to_str()
+           .and_then(|object| object.strip_suffix(PRESIGN_SUFFIX))
+           .and_then(|object| self.object_at(Path::new(object)))
+           .filter(|object| !object.key.is_empty())
+           .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+       let route = self.route(&object)?;
+       
+       let presigning = PresigningConfig::expires_in(self.presign_expiry)
+           .map_err(|e| GnosError::Driver(format!("invalid presign duration: {}", e)))?;
+       let request = self.s3.get_object().bucket(&object.bucket).key(&object.key)
+           .customize().config_override(route)
+           .presigned(presigning).await
+           .map_err(|e| s3_error(&object, e))?;
+       debug!("Presigned {} for {}s", object, self.presign_expiry.as_secs());
+       Ok(Bytes::from(format!("{}\n", request.uri())))
+   }
+   
+   /// Where `path` falls in a bucket's `.versions` directory: the object with
+   /// the key that follows `.versions`, empty at the directory itself
+   fn versions_at(&self, path: &Path) -> Option<Object> {
+       let object = self.object_at(path)?;
+       let key = Path::new(&object.key).strip_prefix(VERSIONS_DIR).ok()?.to_str()?.to_string();
+       Some(Object { key, ..object })
+   }
+   
+   /// Every stored version of one object, newest first; delete markers
+   /// have nothing to read and are left out
+   async fn versions(&self, object: &Object) -> Result<Vec<(String, ResourceMetadata)>> {
+       let route = self.route(object)?;
+       let mut versions = Vec::new();
+       // The SDK has no paginator for this call; pages carry key and version markers
+       let mut marker: Option<(String, Option<String>)> = None;
+       loop {
+           let mut request = self.s3.list_object_versions().bucket(&object.bucket).prefix(&object.key);
+           if let Some((key, version)) = marker.take() {
+               request = request.key_marker(key).set_version_id_marker(version);
+           }
+           let page = request.customize().config_override(route.clone()).send().await.map_err(|e| s3_error(object, e))?;
+           for version in page.versions() {
+               let (Some(key), Some(id)) = (version.key(), version.version_id()) else {
+                   continue;
+               };
+               // The prefix also matches longer keys
+               if key != object.key {
+                   continue;
+               }
+               let mut metadata = ResourceMetadata {
+                   size: version.size().unwrap_or(0).max(0) as u64,
+                   etag: version.e_tag().map(str::to_string),
+                   ..ResourceMetadata::default()
+               };
+               if let Some(modified) = version.last_modified().and_then(|time| SystemTime::try_from(*time).ok()) {
+                   metadata.last_modified = modified;
+               }
+               if version.is_latest() == Some(true) {
+                   metadata.custom_fields.insert("latest".to_string(), "true".to_string());
+               }
+               versions.push((id.to_string(), metadata));
+           }
+           match page.next_key_marker() {
+               Some(key) if page.is_truncated() == Some(true) => {
+                   marker = Some((key.to_string(), page.next_version_id_marker().map(str::to_string)));
+               }
+               _ => break,
+           }
+       }
+       Ok(versions)
+   }
+   
+   /// What S3 holds for an object: its size, ETag, modification time and
+   /// content type
+   async fn head(&self, object: &Object) -> Result<ResourceMetadata> {
+       let route = self.route(object)?;
+       let response = self.s3.head_object().bucket(&object.bucket).key(&object.key)
+           .customize().config_override(route)
+           .send().await
+           .map_err(|e| s3_error(object, e))?;
+       let mut metadata = ResourceMetadata {
+           size: response.content_length().unwrap_or(0).max(0) as u64,
+           mime_type: response.content_type().map(str::to_string),
+           etag: response.e_tag().map(str::to_string),
+           ..ResourceMetadata::default()
+       };
+       if let Some(modified) = response.last_modified().and_then(|time| SystemTime::try_from(*time).ok()) {
+           metadata.last_modified = modified;
+       }
+       // Compressed objects are read decompressed, so report their size before compression
+       if let Some(encoding) = response.content_encoding() {
+           metadata.custom_fields.insert(ENCODING_FIELD.to_string(), encoding.to_string());
+           let original = response.metadata()
+               .and_then(|meta| meta.get(ORIGINAL_SIZE_META))
+               .and_then(|size| size.parse().ok());
+           if let Some(size) = original.filter(|_| encoding == ZSTD) {
+               metadata.size = size;
+           }
+       }
+       Ok(metadata)
+   }
+   
+   /// One version of an object, from `.versions/<key>/<version>`
+   async fn read_version(&self, path: &Path, versions: Object) -> Result<(Bytes, Option<Checksum>)> {
+       let not_found = || GnosError::PathNotFound(path.display().to_string());
+       let key = Path::new(&versions.key);
+       let id = key.file_name().and_then(|id| id.to_str()).ok_or_else(not_found)?.to_string();
+       let key = key.parent().and_then(Path::to_str).filter(|key| !key.is_empty()).ok_or_else(not_found)?;
+       let object = Object { key: key.to_string(), ..versions };
+       self.get(&object, Some(&id)).await
+   }
+   
+   /// A path in `.versions` is a version when its parent key has one by
+   /// that ID, and otherwise the directory of an object's versions
+   async fn version_metadata(&self, path: &Path, versions: Object) -> Result<ResourceMetadata> {
+       let directory = ResourceMetadata { is_directory: true, ..ResourceMetadata::default() };
+       let key = Path::new(&versions.key);
+       let (Some(id), Some(parent)) = (key.file_name().and_then(|id| id.to_str()), key.parent().and_then(Path::to_str)) else {
+           return Ok(directory);
+       };
+       if parent.is_empty() {
+           return Ok(directory);
+       }
+       let object = Object { key: parent.to_string(), ..versions.clone() };
+       let version = self.versions(&object).await?.into_iter().find(|(version, _)| version == id);
+       debug!("{} is {}", path.display(), if version.is_some() { "a version" } else { "a versions directory" });
+       Ok(version.map_or(directory, |(_, metadata)| metadata))
+   }
+}
+
+/// One S3 object, or a prefix of keys when `key` is a directory
+#[derive(Debug, Clone)]
+struct Object {
+   region: String,
+   endpoint: Option<String>,
+   bucket: String,
+   key: String,
+}
+
+impl fmt::Display for Object {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+       write!(f, "s3://{}/{}", self.bucket, self.key)
+   }
+}
+
+/// The digest S3 reports for an object: the SHA-256 or CRC32C it was
+/// uploaded with, else its ETag, which under SSE-KMS is no MD5. Multipart
+/// objects report digests of their parts, which don't decode
+fn stored_checksum(sha256: Option<&str>, crc32c: Option<&str>, etag: Option<&str>, sse: Option<&ServerSideEncryption>) -> Option<Checksum> {
+   sha256.and_then(|value| Checksum::from_base64(ChecksumAlgorithm::Sha256, value, "x-amz-checksum-sha256"))
+       .or_else(|| crc32c.and_then(|value| Checksum::from_base64(ChecksumAlgorithm::Crc32c, value, "x-amz-checksum-crc32c")))
+       .or_else(|| {
+           let kms = matches!(sse, Some(ServerSideEncryption::AwsKms | ServerSideEncryption::AwsKmsDsse));
+           etag.filter(|_| !kms).and_then(Checksum::from_etag)
+       })
+}
+
+/// The whole body of a GetObject response
+async fn collect(object: &Object, body: ByteStream) -> Result<Bytes> {
+   let body = body.collect().await
+       .map_err(|e| GnosError::Driver(format!("reading {} failed: {}", object, e)))?;
+   Ok(body.into_bytes())
+}
+
+/// Up to `len` bytes of `data` from `offset`
+fn slice(data: Bytes, offset: u64, len: u64) -> Bytes {
+   let start = std::cmp::min(offset, data.len() as u64) as usize;
+   let end = std::cmp::min(offset.saturating_add(len), data.len() as u64) as usize;
+   data.slice(start..end)
+}
+
+/// Missing keys and versions are `PathNotFound`, other failures driver
+/// errors; HEAD responses have no body, so a missing key is just `NotFound`
+fn s3_error<E: ProvideErrorMetadata + fmt::Display>(object: &Object, error: E) -> GnosError {
+   match error.code() {
+       Some("NoSuchKey" | "NoSuchVersion" | "NoSuchBucket" | "NotFound") => GnosError::PathNotFound(object.to_string()),
+       _ => GnosError::Driver(format!("{} failed: {}", object, error)),
    }
 }
 
 #[async_trait]
 impl GnosDriver for CloudDriver {
-   async fn read(&self, path: &Path) -> Result<Vec<u8>> {
-       let status = format!("☁️ GNOS Cloud Driver\n📍 Path: {}\n🔄 Status: Simulated\n💡 AWS S3, GCP, Azure support coming soon!\n", path.display());
-       Ok(status.into_bytes())
+   async fn read(&self, path: &Path) -> Result<Bytes> {
+       Ok(self.read_checked(path).await?.0)
+   }
+   
+   async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+       if path.to_string_lossy().ends_with(PRESIGN_SUFFIX) {
+           return Ok((self.presign(path).await?, None));
+       }
+       if let Some(versions) = self.versions_at(path) {
+           return self.read_version(path, versions).await;
+       }
+       if let Some(object) = self.object_to_write(path) {
+           return self.get(&object, None).await;
+       }
+       
+       // GCP and Azure are still simulated
+       let credentials = match self.credentials.current().await {
+           Ok(credentials) => format!("{}…", &credentials.access_key_id()[..credentials.access_key_id().len().min(8)]),
+           Err(e) => format!("unavailable ({})", e),
+       };
+       let route = self.alias_of(path)
+           .and_then(|alias| self.regions.route(&alias))
+           .map_or_else(String::new, |route| format!("🌍 Routed to: {} in {} via {}\n", route.bucket, route.region, route.endpoint));
+       let status = format!("☁️ GNOS Cloud Driver\n📍 Path: {}\n🔄 Status: Simulated\n🔑 AWS credentials: {}\n{}💡 GCP and Azure support coming soon!\n",
+                            path.display(), credentials, route);
+       Ok((Bytes::from(status), None))
+   }
+   
+   /// S3 objects are fetched with a Range request; an offset at or past
+   /// the end reads nothing. Ranges of a compressed object would be ranges
+   /// of its compressed bytes, so those are read whole and sliced
+   async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+       let Some(object) = self.stored_object(path) else {
+           return Ok(slice(self.read(path).await?, offset, len));
+       };
+       if len == 0 {
+           return Ok(Bytes::new());
+       }
+       
+       let route = self.route(&object)?;
+       let response = self.get_request(&object, None)
+           .range(format!("bytes={}-{}", offset, offset.saturating_add(len - 1)))
+           .customize().config_override(route).send().await;
+       let encoded = match response {
+           Ok(response) if response.content_encoding() != Some(ZSTD) => return collect(&object, response.body).await,
+           Ok(_) => true,
+           // Past the stored bytes, which a compressed object's content may outrun
+           Err(e) if e.code() == Some("InvalidRange") => {
+               self.head(&object).await?.custom_fields.get(ENCODING_FIELD).is_some_and(|encoding| encoding == ZSTD)
+           }
+           Err(e) => return Err(s3_error(&object, e)),
+       };
+       if !encoded {
+           return Ok(Bytes::new());
+       }
+       Ok(slice(self.get(&object, None).await?.0, offset, len))
+   }
+   
+   async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+       self.write_with(path, data, &WriteOptions::default()).await.map(|_| ())
+   }
+   
+   fn upload_checksums(&self, path: &Path) -> &[ChecksumAlgorithm] {
+       if self.stored_object(path).is_some() {
+           &[ChecksumAlgorithm::Sha256]
+       } else {
+           &[]
+       }
+   }
+   
+   fn conditional_writes(&self, path: &Path) -> bool {
+       self.stored_object(path).is_some()
+   }
+   
+   fn accepted_encodings(&self) -> &[&'static str] {
+       &[ZSTD]
+   }
+   
+   /// Stored with its Content-Encoding, which reads decompress, and its
+   /// size before compression as user metadata for `metadata` to report
+   async fn write_encoded(&self, path: &Path, data: &[u8], encoding: &str) -> Result<()> {
+       let Some(object) = self.upload_target(path)? else {
+           return Ok(());
+       };
+       if encoding != ZSTD {
+           return Err(GnosError::Driver(format!("{} does not accept {} payloads for {}", self.name(), encoding, path.display())));
+       }
+       // Frames from `CompressionPolicy::compress` record their content size
+       let size = zstd::zstd_safe::get_frame_content_size(data).ok().flatten()
+           .ok_or_else(|| GnosError::Driver(format!("zstd payload for {} does not record its size", object)))?;
+       
+       let route = self.route(&object)?;
+       self.put(&object, data)
+           .content_encoding(ZSTD)
+           .set_content_type(crate::mime::from_extension(path).map(str::to_string))
+           .metadata(ORIGINAL_SIZE_META, size.to_string())
+           .checksum_sha256(Checksum::compute(ChecksumAlgorithm::Sha256, data).to_base64())
+           .customize().config_override(route).send().await
+           .map_err(|e| s3_error(&object, e))?;
+       debug!("Wrote {} ({} bytes, {} compressed)", object, size, data.len());
+       Ok(())
+   }
+   
+   /// S3 checks the payload against the digests sent with it and the
+   /// precondition against the object, and reports the SHA-256 it stored
+   async fn write_with(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+       let Some(object) = self.upload_target(path)? else {
+           return Ok(None);
+       };
+       
+       let route = self.route(&object)?;
+       let mut request = self.put(&object, data).set_content_type(options.content_type.map(str::to_string));
+       request = match &options.precondition {
+           Some(Precondition::Matches(etag)) => request.if_match(etag),
+           Some(Precondition::Absent) => request.if_none_match("*"),
+           None => request,
+       };
+       for checksum in &options.checksums {
+           request = match checksum.algorithm {
+               ChecksumAlgorithm::Sha256 => request.checksum_sha256(checksum.to_base64()),
+               ChecksumAlgorithm::Md5 => request.content_md5(checksum.to_base64()),
+               ChecksumAlgorithm::Crc32c => request.checksum_crc32_c(checksum.to_base64()),
+           };
+       }
+       let response = request.customize().config_override(route).send().await
+           .map_err(|e| match e.code() {
+               // 412 when the object moved on, 409 when a concurrent write won
+               Some("PreconditionFailed" | "ConditionalRequestConflict") => {
+                   GnosError::ResourceBusy(format!("{} changed since it was opened", object))
+               }
+               _ => s3_error(&object, e),
+           })?;
+       debug!("Wrote {} ({} bytes, encryption {:?})", object, data.len(), response.server_side_encryption());
+       Ok(stored_checksum(response.checksum_sha256(), response.checksum_crc32_c(), None, None))
+   }
+   
+   async fn delete(&self, path: &Path) -> Result<()> {
+       if self.versions_at(path).is_some() {
+           return Err(GnosError::PermissionDenied(format!("versions in {} are read-only", path.display())));
+       }
+       Err(GnosError::Driver(format!("{} does not support deleting {}", self.name(), path.display())))
+   }
+   
+   /// Only S3 objects, and generated files are never uploaded
+   fn supports_parts(&self, path: &Path) -> bool {
+       self.stored_object(path).is_some()
+   }
+   
+   fn part_policy(&self, _path: &Path) -> PartPolicy {
+       self.parts
+   }
+   
+   /// Encryption and content type are set when the upload is created and
+   /// apply to every part; with no payload yet, the type is the extension's
+   async fn begin_parts(&self, path: &Path) -> Result<String> {
+       let object = self.object_to_write(path)
+           .ok_or_else(|| GnosError::InvalidPath(format!("{} is not an S3 object", path.display())))?;
+       let route = self.route(&object)?;
+       let (algorithm, kms_key_id, bucket_key) = self.encryption(&object);
+       let response = self.s3.create_multipart_upload()
+           .bucket(&object.bucket)
+           .key(&object.key)
+           .set_content_type(crate::mime::from_extension(path).map(str::to_string))
+           .set_server_side_encryption(algorithm)
+           .set_ssekms_key_id(kms_key_id)
+           .set_bucket_key_enabled(bucket_key)
+           .customize().config_override(route).send().await
+           .map_err(|e| s3_error(&object, e))?;
+       let upload_id = response.upload_id()
+           .ok_or_else(|| GnosError::Driver(format!("S3 returned no upload ID for {}", object)))?
+           .to_string();
+       
+       debug!("Started multipart upload {} of {}", upload_id, object);
+       self.uploads.lock().unwrap().insert(upload_id.clone(), Upload {
+           path: path.to_path_buf(),
+           object,
+           parts: BTreeMap::new(),
+       });
+       Ok(upload_id)
    }
    
-   async fn write(&self, _path: &Path, _data: &[u8]) -> Result<()> {
+   async fn write_part(&self, path: &Path, upload_id: &str, part: u64, data: &[u8]) -> Result<()> {
+       if part >= MAX_PARTS {
+           return Err(GnosError::Driver(format!(
+               "{} needs more than {} parts; raise drivers.cloud.multipart.part_size_mb", path.display(), MAX_PARTS
+           )));
+       }
+       let object = self.uploads.lock().unwrap().get(upload_id)
+           .filter(|upload| upload.path == path)
+           .map(|upload| upload.object.clone())
+           .ok_or_else(|| GnosError::Driver(format!("no multipart upload {} of {}", upload_id, path.display())))?;
+       
+       let route = self.route(&object)?;
+       let response = self.s3.upload_part()
+           .bucket(&object.bucket)
+           .key(&object.key)
+           .upload_id(upload_id)
+           // S3 numbers parts from 1
+           .part_number(part as i32 + 1)
+           .body(ByteStream::from(data.to_vec()))
+           .customize().config_override(route).send().await
+           .map_err(|e| s3_error(&object, e))?;
+       let etag = response.e_tag().unwrap_or_default().to_string();
+       
+       if let Some(upload) = self.uploads.lock().unwrap().get_mut(upload_id) {
+           upload.parts.insert(part, (data.len() as u64, etag));
+       }
        Ok(())
    }
    
-   async fn list(&self, _path: &Path) -> Result<Vec<String>> {
-       Ok(vec!["aws".to_string(), "gcp".to_string(), "azure".to_string()])
+   async fn complete_parts(&self, path: &Path, upload_id: &str, parts: u64) -> Result<()> {
+       let (object, completed, size) = {
+           let mut uploads = self.uploads.lock().unwrap();
+           let upload = uploads.get(upload_id)
+               .filter(|upload| upload.path == path)
+               .ok_or_else(|| GnosError::Driver(format!("no multipart upload {} of {}", upload_id, path.display())))?;
+           
+           if let Some(missing) = (0..parts).find(|part| !upload.parts.contains_key(part)) {
+               return Err(GnosError::Driver(format!("part {} of {} was never uploaded", missing, path.display())));
+           }
+           if let Some((part, (size, _))) = upload.parts.range(..parts.saturating_sub(1)).find(|(_, (size, _))| *size < MIN_PART_SIZE) {
+               return Err(GnosError::Driver(format!(
+                   "part {} of {} is {} bytes, under S3's 5 MiB minimum", part, path.display(), size
+               )));
+           }
+           
+           let completed: Vec<CompletedPart> = upload.parts.range(..parts)
+               .map(|(part, (_, etag))| CompletedPart::builder().part_number(*part as i32 + 1).e_tag(etag).build())
+               .collect();
+           let size: u64 = upload.parts.range(..parts).map(|(_, (size, _))| size).sum();
+           let object = upload.object.clone();
+           uploads.remove(upload_id);
+           (object, completed, size)
+       };
+       
+       let route = self.route(&object)?;
+       self.s3.complete_multipart_upload()
+           .bucket(&object.bucket)
+           .key(&object.key)
+           .upload_id(upload_id)
+           .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(completed)).build())
+           .customize().config_override(route).send().await
+           .map_err(|e| s3_error(&object, e))?;
+       info!("☁️ Assembled {} from {} parts ({} bytes)", object, parts, size);
+       Ok(())
+   }
+   
+   /// S3 keeps and bills uploaded parts until the upload is aborted
+   async fn abort_parts(&self, path: &Path, upload_id: &str) -> Result<()> {
+       let object = self.uploads.lock().unwrap().remove(upload_id)
+           .filter(|upload| upload.path == path)
+           .map(|upload| upload.object)
+           .ok_or_else(|| GnosError::Driver(format!("no multipart upload {} of {}", upload_id, path.display())))?;
+       
+       let route = self.route(&object)?;
+       self.s3.abort_multipart_upload()
+           .bucket(&object.bucket)
+           .key(&object.key)
+           .upload_id(upload_id)
+           .customize().config_override(route).send().await
+           .map_err(|e| s3_error(&object, e))?;
+       debug!("Aborted multipart upload {} of {}", upload_id, object);
+       Ok(())
+   }
+   
+   async fn list(&self, path: &Path) -> Result<Vec<String>> {
+       Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+   }
+   
+   async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+       if let Some(versions) = self.versions_at(path).filter(|versions| !versions.key.is_empty()) {
+           return Ok(self.versions(&versions).await?.into_iter()
+               .map(|(id, metadata)| (id, Some(metadata)))
+               .collect());
+       }
+       if path != Path::new("/cloud") {
+           return Ok(vec![]);
+       }
+       
+       let provider = ResourceMetadata {
+           is_directory: true,
+           ..ResourceMetadata::default()
+       };
+       
+       Ok(["aws", "gcp", "azure"].iter()
+           .map(|name| name.to_string())
+           .chain(self.regions.aliases())
+           .map(|name| (name, Some(provider.clone())))
+           .collect())
    }
    
    async fn exists(&self, _path: &Path) -> Result<bool> {
        Ok(true)
    }
    
-   async fn metadata(&self, _path: &Path) -> Result<ResourceMetadata> {
-       Ok(ResourceMetadata::default())
+   async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+       if let Some(versions) = self.versions_at(path) {
+           return self.version_metadata(path, versions).await;
+       }
+       match self.stored_object(path) {
+           Some(object) => self.head(&object).await,
+           None => Ok(ResourceMetadata::default()),
+       }
    }
    
    fn name(&self) -> &'static str {
        "Cloud Storage Driver"
    }
    
+   /// DynamoDB, SQS, Lambda, CloudWatch Logs, EC2 and Secrets Manager have
+   /// drivers of their own
    fn supports(&self, path: &Path) -> bool {
-       path.to_string_lossy().starts_with("/cloud/")
+       path.starts_with("/cloud")
+           && !path.starts_with("/cloud/aws/dynamodb")
+           && !path.starts_with("/cloud/aws/sqs")
+           && !path.starts_with("/cloud/aws/lambda")
+           && !path.starts_with("/cloud/aws/logs")
+           && !path.starts_with("/cloud/aws/ec2")
+           && !path.starts_with("/cloud/aws/secrets")
+   }
+   
+   fn prefixes(&self) -> Vec<PathBuf> {
+       vec![PathBuf::from("/cloud")]
+   }
+   
+   /// Every read of a `.presign` file signs a fresh URL, while old
+   /// versions never change
+   fn cache_mode(&self, path: &Path) -> CacheMode {
+       if path.to_string_lossy().ends_with(PRESIGN_SUFFIX) {
+           CacheMode::DirectIo
+       } else if self.versions_at(path).is_some() {
+           CacheMode::KeepCache
+       } else {
+           CacheMode::Auto
+       }
    }
 }
@@ -0,0 +1,296 @@
+//! Conformance suite for drivers
+//!
+//! [`run`] puts a driver through what the VFS relies on: round trips of
+//! writes, ranged reads, listings that agree with `exists` and metadata,
+//! missing paths reported as `PathNotFound`, unicode names, large payloads,
+//! and each capability the driver has. It only writes below the scratch
+//! directory it is given and removes what it created when the driver can
+//! delete. Run it against a configured driver with
+//!
+//! ```text
+//! gnos-mount driver test cloud --scratch /cloud/aws/s3/ci/conformance
+//! ```
+//!
+//! or from a driver's own tests through `gnos_driver_sdk::conformance::check`.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::drivers::{GnosDriver, Precondition, WriteOptions};
+use crate::GnosError;
+
+const PAYLOAD: &[u8] = b"gnos driver conformance payload";
+
+/// Larger than a multipart part and most buffers a driver might keep
+const LARGE_PAYLOAD: usize = 8 * 1024 * 1024 + 17;
+
+/// Accents, CJK, an emoji and a space, each written as one path component
+const UNICODE_NAME: &str = "conformance-ünïcødé 名前 🦀.txt";
+
+/// Operations a driver supports beyond reading and listing; the suite only
+/// exercises what is declared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities {
+    pub write: bool,
+    pub delete: bool,
+    pub create_dir: bool,
+    /// Multipart writes through `begin_parts`, `write_part` and `complete_parts`
+    pub parts: bool,
+    /// Atomic `commit_batch`
+    pub batches: bool,
+    /// Writes honouring `WriteOptions::precondition`
+    pub conditional_writes: bool,
+    /// Resources read as they grow through `read_growing`
+    pub streams: bool,
+}
+
+impl Capabilities {
+    /// What a driver reports for `scratch` through its trait methods; whether
+    /// it writes and deletes isn't reported, so that is `writable`
+    pub fn probe(driver: &dyn GnosDriver, scratch: &Path, writable: bool) -> Self {
+        Self {
+            write: writable,
+            delete: writable,
+            create_dir: false,
+            parts: driver.supports_parts(&scratch.join("conformance-parts.txt")),
+            batches: driver.supports_batches(),
+            conditional_writes: driver.conditional_writes(scratch),
+            streams: driver.streams(scratch),
+        }
+    }
+}
+
+/// Outcome of one check
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    /// Why the check failed
+    pub failure: Option<String>,
+}
+
+/// Outcome of the whole suite
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub driver: String,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn new(driver: &str) -> Self {
+        Self { driver: driver.to_string(), checks: Vec::new() }
+    }
+    
+    pub fn record(&mut self, name: &'static str, result: Result<(), String>) {
+        self.checks.push(Check { name, failure: result.err() });
+    }
+    
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.failure.is_none())
+    }
+    
+    pub fn failures(&self) -> usize {
+        self.checks.iter().filter(|check| check.failure.is_some()).count()
+    }
+    
+    /// Panic with the report unless every check passed, for use in tests
+    pub fn assert_passed(&self) {
+        assert!(self.passed(), "{}", self);
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "conformance of {}:", self.driver)?;
+        for check in &self.checks {
+            match &check.failure {
+                None => writeln!(f, "  ok    {}", check.name)?,
+                Some(failure) => writeln!(f, "  FAIL  {}: {}", check.name, failure)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run every check that applies to `driver` into `report`, writing below `scratch`
+pub async fn run(driver: &dyn GnosDriver, capabilities: Capabilities, scratch: &Path, report: &mut Report) {
+    let file = scratch.join("conformance.txt");
+    let unicode = scratch.join(UNICODE_NAME);
+    let large = scratch.join("conformance-large.bin");
+    let parts_file = scratch.join("conformance-parts.txt");
+    
+    if !driver.supports(scratch) {
+        report.record("supports", Err(format!("doesn't support the scratch directory {}", scratch.display())));
+        return;
+    }
+    report.record("missing paths", missing(driver, scratch, capabilities).await);
+    if capabilities.write {
+        report.record("write and read", round_trip(driver, &file, PAYLOAD).await);
+        report.record("ranged reads", ranges(driver, &file).await);
+        report.record("listing", listing(driver, scratch, &file, PAYLOAD.len()).await);
+        report.record("unicode paths", unicode_paths(driver, scratch, &unicode).await);
+        report.record("large payloads", round_trip(driver, &large, &large_payload()).await);
+    } else {
+        report.record("read-only", read_only(driver, &file).await);
+    }
+    if capabilities.parts {
+        report.record("multipart writes", parts(driver, &parts_file).await);
+    }
+    if capabilities.conditional_writes && capabilities.write {
+        report.record("conditional writes", conditional(driver, &file).await);
+    }
+    if capabilities.delete && capabilities.write {
+        report.record("delete", delete(driver, &file).await);
+        for path in [&unicode, &large, &parts_file] {
+            let _ = driver.delete(path).await;
+        }
+    }
+}
+
+/// Bytes that differ along the payload, so misplaced chunks show up
+fn large_payload() -> Vec<u8> {
+    (0..LARGE_PAYLOAD).map(|i| (i % 251) as u8).collect()
+}
+
+async fn missing(driver: &dyn GnosDriver, scratch: &Path, capabilities: Capabilities) -> Result<(), String> {
+    let path = scratch.join("conformance-missing");
+    if driver.exists(&path).await.map_err(|e| format!("exists failed: {}", e))? {
+        return Err(format!("{} exists", path.display()));
+    }
+    expect_not_found("metadata", driver.metadata(&path).await.map(drop))?;
+    expect_not_found("read", driver.read(&path).await.map(drop))?;
+    expect_not_found("read_range", driver.read_range(&path, 0, 4).await.map(drop))?;
+    if capabilities.delete {
+        expect_not_found("delete", driver.delete(&path).await)?;
+    }
+    Ok(())
+}
+
+async fn round_trip(driver: &dyn GnosDriver, file: &Path, payload: &[u8]) -> Result<(), String> {
+    driver.write(file, payload).await.map_err(|e| format!("write failed: {}", e))?;
+    let data = driver.read(file).await.map_err(|e| format!("read failed: {}", e))?;
+    if data.as_ref() != payload {
+        return Err(format!("read {} bytes back after writing {}", data.len(), payload.len()));
+    }
+    let metadata = driver.metadata(file).await.map_err(|e| format!("metadata failed: {}", e))?;
+    if metadata.is_directory || metadata.size != payload.len() as u64 {
+        return Err(format!("metadata reports {} bytes, directory: {}", metadata.size, metadata.is_directory));
+    }
+    if !driver.exists(file).await.map_err(|e| format!("exists failed: {}", e))? {
+        return Err("exists is false after a write".to_string());
+    }
+    Ok(())
+}
+
+async fn ranges(driver: &dyn GnosDriver, file: &Path) -> Result<(), String> {
+    let cases = [(0, 4), (5, 6), (PAYLOAD.len() as u64 - 3, 10), (PAYLOAD.len() as u64 + 5, 4)];
+    for (offset, len) in cases {
+        let data = driver.read_range(file, offset, len).await
+            .map_err(|e| format!("read_range({}, {}) failed: {}", offset, len, e))?;
+        let start = (offset as usize).min(PAYLOAD.len());
+        let end = (offset.saturating_add(len) as usize).min(PAYLOAD.len());
+        if data[..] != PAYLOAD[start..end] {
+            return Err(format!("read_range({}, {}) returned {} bytes instead of {}", offset, len, data.len(), end - start));
+        }
+    }
+    Ok(())
+}
+
+async fn listing(driver: &dyn GnosDriver, scratch: &Path, file: &Path, size: usize) -> Result<(), String> {
+    let name = file.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let names = driver.list(scratch).await.map_err(|e| format!("list failed: {}", e))?;
+    if !names.contains(&name) {
+        return Err(format!("list of {} misses {}", scratch.display(), name));
+    }
+    if let Some(bad) = names.iter().find(|name| name.is_empty() || name.contains('/')) {
+        return Err(format!("list returned {:?}, which is not a single path component", bad));
+    }
+    
+    let entries = driver.list_with_metadata(scratch).await.map_err(|e| format!("list_with_metadata failed: {}", e))?;
+    let mut detailed: Vec<&String> = entries.iter().map(|(name, _)| name).collect();
+    let mut plain: Vec<&String> = names.iter().collect();
+    detailed.sort();
+    plain.sort();
+    if detailed != plain {
+        return Err("list and list_with_metadata name different entries".to_string());
+    }
+    match entries.iter().find(|(entry, _)| *entry == name) {
+        Some((_, Some(metadata))) if metadata.size != size as u64 => {
+            Err(format!("list_with_metadata reports {} bytes for {}", metadata.size, name))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn unicode_paths(driver: &dyn GnosDriver, scratch: &Path, file: &Path) -> Result<(), String> {
+    round_trip(driver, file, PAYLOAD).await?;
+    // Names come back exactly as written, not normalized or escaped
+    let names = driver.list(scratch).await.map_err(|e| format!("list failed: {}", e))?;
+    if !names.iter().any(|name| name == UNICODE_NAME) {
+        return Err(format!("list of {} misses {:?}", scratch.display(), UNICODE_NAME));
+    }
+    Ok(())
+}
+
+async fn read_only(driver: &dyn GnosDriver, file: &Path) -> Result<(), String> {
+    if driver.write(file, PAYLOAD).await.is_ok() {
+        return Err("write succeeded without the write capability".to_string());
+    }
+    Ok(())
+}
+
+async fn parts(driver: &dyn GnosDriver, file: &Path) -> Result<(), String> {
+    if !driver.supports_parts(file) {
+        return Err("supports_parts is false".to_string());
+    }
+    let upload = driver.begin_parts(file).await.map_err(|e| format!("begin_parts failed: {}", e))?;
+    let chunks: Vec<&[u8]> = PAYLOAD.chunks(8).collect();
+    // Parts may arrive in any order
+    for (part, chunk) in chunks.iter().enumerate().rev() {
+        driver.write_part(file, &upload, part as u64, chunk).await
+            .map_err(|e| format!("write_part({}) failed: {}", part, e))?;
+    }
+    driver.complete_parts(file, &upload, chunks.len() as u64).await
+        .map_err(|e| format!("complete_parts failed: {}", e))?;
+    let data = driver.read(file).await.map_err(|e| format!("read failed: {}", e))?;
+    if data.as_ref() != PAYLOAD {
+        return Err("parts weren't assembled in order".to_string());
+    }
+    Ok(())
+}
+
+async fn conditional(driver: &dyn GnosDriver, file: &Path) -> Result<(), String> {
+    let options = WriteOptions { precondition: Some(Precondition::Absent), ..WriteOptions::default() };
+    match driver.write_with(file, PAYLOAD, &options).await {
+        Err(GnosError::ResourceBusy(_)) => {}
+        Err(e) => return Err(format!("a write expecting no object failed with {} rather than ResourceBusy", e)),
+        Ok(_) => return Err("a write expecting no object replaced an existing one".to_string()),
+    }
+    
+    let stale = WriteOptions {
+        precondition: Some(Precondition::Matches("\"gnos-conformance-stale\"".to_string())),
+        ..WriteOptions::default()
+    };
+    match driver.write_with(file, PAYLOAD, &stale).await {
+        Err(GnosError::ResourceBusy(_)) => Ok(()),
+        Err(e) => Err(format!("a write with a stale entity tag failed with {} rather than ResourceBusy", e)),
+        Ok(_) => Err("a write with a stale entity tag landed".to_string()),
+    }
+}
+
+async fn delete(driver: &dyn GnosDriver, file: &Path) -> Result<(), String> {
+    driver.delete(file).await.map_err(|e| format!("delete failed: {}", e))?;
+    if driver.exists(file).await.map_err(|e| format!("exists failed: {}", e))? {
+        return Err("exists is true after delete".to_string());
+    }
+    expect_not_found("metadata after delete", driver.metadata(file).await.map(drop))
+}
+
+fn expect_not_found(operation: &str, result: crate::Result<()>) -> Result<(), String> {
+    match result {
+        Err(GnosError::PathNotFound(_)) => Ok(()),
+        Err(e) => Err(format!("{} of a missing path failed with {} rather than PathNotFound", operation, e)),
+        Ok(()) => Err(format!("{} of a missing path succeeded", operation)),
+    }
+}
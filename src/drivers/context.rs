@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tracing::{info, warn};
+
+use crate::config::{DriverConfig, TlsConfig};
+use crate::drivers::network::{EgressPolicy, SharedHttpClient};
+use crate::Result;
+
+/// Drivers that make outbound HTTP requests, and so take a TLS policy
+const HTTP_DRIVERS: &[&str] = &["cloud", "http", "models", "msgraph", "vault"];
+
+/// Shared resources handed to every driver at construction
+#[derive(Clone)]
+pub struct DriverContext {
+    pub http: Arc<SharedHttpClient>,
+    /// Clients for drivers with a TLS policy or proxy of their own, by driver name
+    own_clients: HashMap<String, Arc<SharedHttpClient>>,
+    /// Hosts every driver may reach, shared by all the clients
+    pub egress: Arc<EgressPolicy>,
+}
+
+impl DriverContext {
+    pub fn new(config: &DriverConfig) -> Result<Self> {
+        let egress = EgressPolicy::new(&config.network.egress);
+        if !config.network.egress.allow.is_empty() {
+            info!("🚧 Outbound requests limited to {} allowed hosts", config.network.egress.allow.len());
+        }
+        
+        let mut own_clients = HashMap::new();
+        for driver in config.tls.keys().chain(config.proxy.keys()) {
+            if !HTTP_DRIVERS.contains(&driver.as_str()) {
+                warn!("⚠️ Transport settings for {} ignored: it makes no HTTP requests", driver);
+                continue;
+            }
+            if own_clients.contains_key(driver) {
+                continue;
+            }
+            let tls = config.tls.get(driver).cloned().unwrap_or_default();
+            let proxy = config.proxy.get(driver).or(config.network.proxy.as_ref());
+            if config.tls.contains_key(driver) {
+                info!("🔒 {} uses its own TLS policy", driver);
+            }
+            if config.proxy.contains_key(driver) {
+                info!("🔀 {} uses its own proxy", driver);
+            }
+            own_clients.insert(driver.clone(), Arc::new(SharedHttpClient::new(&config.network, &tls, proxy, egress.clone())?));
+        }
+        
+        Ok(Self {
+            http: Arc::new(SharedHttpClient::new(&config.network, &TlsConfig::default(), config.network.proxy.as_ref(), egress.clone())?),
+            own_clients,
+            egress,
+        })
+    }
+    
+    /// The client `driver` should send its requests through
+    pub fn http_for(&self, driver: &str) -> Arc<SharedHttpClient> {
+        self.own_clients.get(driver).unwrap_or(&self.http).clone()
+    }
+}
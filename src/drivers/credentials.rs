@@ -0,0 +1,234 @@
+//! AWS credentials for the cloud driver
+//!
+//! Credentials come from a chain of providers tried in order (environment,
+//! shared config files, web identity for IRSA, instance metadata), optionally
+//! exchanged for a role's session through STS AssumeRole. A background task
+//! renews them `refresh_before_seconds` ahead of expiry, so a mount running
+//! for days keeps signing requests with valid keys; if a renewal fails the
+//! old credentials stay in use until they actually expire, and renewal is
+//! retried meanwhile.
+
+use std::fmt;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, SystemTime};
+
+use aws_config::environment::credentials::EnvironmentVariableCredentialsProvider;
+use aws_config::imds::credentials::ImdsCredentialsProvider;
+use aws_config::meta::credentials::CredentialsProviderChain;
+use aws_config::profile::ProfileFileCredentialsProvider;
+use aws_config::provider_config::ProviderConfig;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::web_identity_token::WebIdentityTokenCredentialsProvider;
+use aws_config::Region;
+use aws_credential_types::provider::error::CredentialsError;
+use aws_credential_types::provider::{future, ProvideCredentials, SharedCredentialsProvider};
+use aws_credential_types::Credentials;
+use tracing::{info, warn};
+
+use crate::config::{AwsConfig, CredentialSource};
+use crate::{GnosError, Result};
+
+/// Shortest wait between renewals, however close the expiry
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often credentials without an expiry are re-read, to pick up rotated keys
+const STATIC_RECHECK_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Wait before retrying a failed renewal
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct State {
+    current: Option<Credentials>,
+    refreshed_at: Option<SystemTime>,
+    failures: u64,
+    last_error: Option<String>,
+}
+
+/// The credentials one cloud driver signs with, kept fresh in the background
+pub struct CloudCredentials {
+    provider: SharedCredentialsProvider,
+    /// The chain's links and the assumed role, for reports
+    source: String,
+    refresh_before: Duration,
+    state: RwLock<State>,
+}
+
+impl CloudCredentials {
+    /// Build the provider chain `config` describes and start renewing its credentials
+    pub async fn new(config: &AwsConfig) -> Result<Arc<Self>> {
+        let region = Region::new(config.region.clone());
+        let provider_config = ProviderConfig::without_region().with_region(Some(region.clone()));
+        
+        let mut chain: Option<CredentialsProviderChain> = None;
+        for source in &config.credential_sources {
+            let (name, provider) = match source {
+                CredentialSource::Env => ("env", SharedCredentialsProvider::new(EnvironmentVariableCredentialsProvider::new())),
+                CredentialSource::Profile => {
+                    let mut builder = ProfileFileCredentialsProvider::builder().configure(&provider_config);
+                    if let Some(profile) = &config.profile {
+                        builder = builder.profile_name(profile);
+                    }
+                    ("profile", SharedCredentialsProvider::new(builder.build()))
+                }
+                CredentialSource::WebIdentity => {
+                    let provider = WebIdentityTokenCredentialsProvider::builder().configure(&provider_config).build();
+                    ("web_identity", SharedCredentialsProvider::new(provider))
+                }
+                CredentialSource::Imds => {
+                    let provider = ImdsCredentialsProvider::builder().configure(&provider_config).build();
+                    ("imds", SharedCredentialsProvider::new(provider))
+                }
+            };
+            chain = Some(match chain {
+                None => CredentialsProviderChain::first_try(name, provider),
+                Some(chain) => chain.or_else(name, provider),
+            });
+        }
+        let chain = chain.ok_or_else(|| GnosError::Driver("no AWS credential sources configured".to_string()))?;
+        
+        let mut source = config.credential_sources.iter()
+            .map(|source| format!("{:?}", source).to_lowercase())
+            .collect::<Vec<_>>()
+            .join(",");
+        let provider = match &config.role_arn {
+            Some(role_arn) => {
+                source = format!("{} -> {}", source, role_arn);
+                let mut builder = AssumeRoleProvider::builder(role_arn)
+                    .session_name(&config.session_name)
+                    .session_length(Duration::from_secs(config.session_seconds))
+                    .region(region);
+                if let Some(external_id) = &config.external_id {
+                    builder = builder.external_id(external_id);
+                }
+                SharedCredentialsProvider::new(builder.build_from_provider(chain).await)
+            }
+            None => SharedCredentialsProvider::new(chain),
+        };
+        
+        let credentials = Arc::new(Self {
+            provider,
+            source,
+            refresh_before: Duration::from_secs(config.refresh_before_seconds),
+            state: RwLock::new(State::default()),
+        });
+        credentials.spawn_refresher();
+        Ok(credentials)
+    }
+    
+    /// Credentials to sign a request with, renewed first if they are about to expire
+    pub async fn current(&self) -> Result<Credentials> {
+        let cached = self.state.read().unwrap().current.clone();
+        match cached {
+            Some(credentials) if !self.due(&credentials) => Ok(credentials),
+            _ => self.refresh().await,
+        }
+    }
+    
+    /// A provider for SDK clients built once and kept, which signs each
+    /// request with whatever the credentials are by then
+    pub fn provider(self: &Arc<Self>) -> SharedCredentialsProvider {
+        SharedCredentialsProvider::new(Current(self.clone()))
+    }
+    
+    /// Whether `credentials` are inside their renewal window
+    fn due(&self, credentials: &Credentials) -> bool {
+        credentials.expiry()
+            .is_some_and(|expiry| SystemTime::now() + self.refresh_before >= expiry)
+    }
+    
+    async fn refresh(&self) -> Result<Credentials> {
+        match self.provider.provide_credentials().await {
+            Ok(credentials) => {
+                let mut state = self.state.write().unwrap();
+                state.current = Some(credentials.clone());
+                state.refreshed_at = Some(SystemTime::now());
+                state.last_error = None;
+                Ok(credentials)
+            }
+            Err(e) => {
+                let mut state = self.state.write().unwrap();
+                state.failures += 1;
+                state.last_error = Some(e.to_string());
+                // Keys that haven't expired yet still sign fine
+                match &state.current {
+                    Some(credentials) if credentials.expiry().is_none_or(|expiry| SystemTime::now() < expiry) => {
+                        Ok(credentials.clone())
+                    }
+                    _ => Err(GnosError::PermissionDenied(format!("AWS credentials ({}): {}", self.source, e))),
+                }
+            }
+        }
+    }
+    
+    /// Renew ahead of every expiry until the credentials are dropped
+    fn spawn_refresher(self: &Arc<Self>) {
+        let credentials: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                let Some(this) = credentials.upgrade() else { break };
+                let wait = match this.refresh().await {
+                    Ok(current) if this.state.read().unwrap().last_error.is_none() => {
+                        match current.expiry() {
+                            Some(expiry) => {
+                                let renew_at = expiry.checked_sub(this.refresh_before).unwrap_or(expiry);
+                                info!("🔑 AWS credentials from {} valid until {}",
+                                      this.source, chrono::DateTime::<chrono::Utc>::from(expiry).to_rfc3339());
+                                renew_at.duration_since(SystemTime::now()).unwrap_or_default().max(MIN_REFRESH_INTERVAL)
+                            }
+                            None => STATIC_RECHECK_INTERVAL,
+                        }
+                    }
+                    _ => {
+                        warn!("🔑 Renewing AWS credentials from {} failed: {}",
+                              this.source, this.state.read().unwrap().last_error.as_deref().unwrap_or("unknown error"));
+                        RETRY_INTERVAL
+                    }
+                };
+                drop(this);
+                tokio::time::sleep(wait).await;
+            }
+        });
+    }
+    
+    /// Plain-text view for `/proc/gnos/credentials`; secrets are never shown
+    pub fn status_report(&self) -> String {
+        let state = self.state.read().unwrap();
+        let timestamp = |time: SystemTime| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339();
+        let mut report = format!("source: {}\n", self.source);
+        match &state.current {
+            Some(credentials) => {
+                let key = credentials.access_key_id();
+                report.push_str(&format!("access_key: {}…\n", &key[..key.len().min(8)]));
+                report.push_str(&format!("expires: {}\n", credentials.expiry().map_or_else(|| "never".to_string(), timestamp)));
+            }
+            None => report.push_str("access_key: none\n"),
+        }
+        report.push_str(&format!("refreshed: {}\n", state.refreshed_at.map_or_else(|| "never".to_string(), timestamp)));
+        report.push_str(&format!("failures: {}\n", state.failures));
+        if let Some(error) = &state.last_error {
+            report.push_str(&format!("last_error: {}\n", error));
+        }
+        report
+    }
+}
+
+/// `CloudCredentials` as an SDK credentials provider
+struct Current(Arc<CloudCredentials>);
+
+impl fmt::Debug for Current {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CloudCredentials({})", self.0.source)
+    }
+}
+
+impl ProvideCredentials for Current {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(async move {
+            self.0.current().await.map_err(CredentialsError::provider_error)
+        })
+    }
+}
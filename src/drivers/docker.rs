@@ -0,0 +1,501 @@
+//! `/dev/docker`: containers of the local Docker daemon
+//!
+//! ```text
+//! ls /dev/docker/containers                       # 3f4e1c2a9b7d  81c0d2e4f5a6 ...
+//! cat /dev/docker/containers/3f4e1c2a9b7d/inspect.json
+//! tail -f /dev/docker/containers/3f4e1c2a9b7d/logs
+//! echo restart > /dev/docker/containers/web/control
+//! ```
+//!
+//! Containers are listed by short ID, stopped ones included, but any ID
+//! prefix or name Docker accepts opens one. `logs` reads as the newest
+//! `tail_lines` lines of stdout and stderr; with `follow` on, a reader that
+//! reaches the end waits for more, from one stream of the daemon's shared by
+//! every reader of the container and closed once none has read it for a
+//! while. `control` reads as the container's state and takes `stop` or
+//! `restart`. Watching the root or `containers` follows the daemon's event
+//! stream: containers appear and disappear as they are created and removed,
+//! and `control` changes as they start and stop.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use async_trait::async_trait;
+use bollard::container::{
+    InspectContainerOptions, ListContainersOptions, LogsOptions, RestartContainerOptions, StopContainerOptions,
+};
+use bollard::errors::Error as DockerError;
+use bollard::models::{EventMessage, EventMessageTypeEnum};
+use bollard::system::EventsOptions;
+use bollard::Docker;
+use bytes::Bytes;
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+use crate::config::{CacheMode, DockerDriverConfig};
+use crate::drivers::traits::{ChangeKind, ChangeStream, GnosDriver, Growth, RemoteChange, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/dev/docker";
+
+/// Files in every container directory
+const CONTAINER_FILES: [&str; 3] = ["logs", "inspect.json", "control"];
+
+/// Seconds Docker has to answer, stopping and restarting included
+const TIMEOUT_SECONDS: u64 = 120;
+
+/// Bytes of a followed log kept for its readers
+const FOLLOW_BUFFER: usize = 1024 * 1024;
+
+/// A followed log nobody has read for this long is closed
+const FOLLOW_IDLE: Duration = Duration::from_secs(60);
+
+/// What a path below the root names
+enum Target {
+    Root,
+    Containers,
+    Container(String),
+    File(String, &'static str),
+}
+
+/// A container's log as it is being followed, from `start` on
+struct Tail {
+    buffer: Mutex<(u64, Vec<u8>)>,
+    /// End of the log; closed when the stream ends
+    end: watch::Receiver<u64>,
+    last_read: Mutex<Instant>,
+}
+
+impl Tail {
+    fn from(&self, have: u64, growing: bool) -> Growth {
+        let (start, data) = &*self.buffer.lock().unwrap();
+        let end = start + data.len() as u64;
+        let skip = have.saturating_sub(*start).min(data.len() as u64) as usize;
+        Growth {
+            offset: (*start).max(have.min(end)),
+            data: Bytes::copy_from_slice(&data[skip..]),
+            growing,
+        }
+    }
+    
+    /// Add `text`, dropping the oldest bytes past the buffer; returns the new end
+    fn append(&self, text: &[u8]) -> u64 {
+        let (start, data) = &mut *self.buffer.lock().unwrap();
+        data.extend_from_slice(text);
+        if data.len() > FOLLOW_BUFFER {
+            let dropped = data.len() - FOLLOW_BUFFER;
+            data.drain(..dropped);
+            *start += dropped as u64;
+        }
+        *start + data.len() as u64
+    }
+}
+
+pub struct DockerDriver {
+    docker: Docker,
+    socket: PathBuf,
+    tail_lines: usize,
+    follow: bool,
+    tails: Arc<Mutex<HashMap<String, Arc<Tail>>>>,
+}
+
+impl DockerDriver {
+    pub fn new(config: &DockerDriverConfig) -> Result<Self> {
+        let socket = config.socket.to_str()
+            .ok_or_else(|| GnosError::Config(format!("drivers.docker.socket {} is not UTF-8", config.socket.display())))?;
+        let docker = Docker::connect_with_socket(socket, TIMEOUT_SECONDS, bollard::API_DEFAULT_VERSION)
+            .map_err(|e| GnosError::Config(format!("Docker socket {}: {}", socket, e)))?;
+        info!("🐳 Serving Docker containers from {}", config.socket.display());
+        Ok(Self {
+            docker,
+            socket: config.socket.clone(),
+            tail_lines: config.tail_lines.max(1),
+            follow: config.follow,
+            tails: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+    
+    fn target(&self, path: &Path) -> Result<Target> {
+        let not_found = || GnosError::PathNotFound(path.display().to_string());
+        let rest = path.strip_prefix(ROOT).map_err(|_| not_found())?;
+        let names: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        
+        match names[..] {
+            [] => Ok(Target::Root),
+            ["containers"] => Ok(Target::Containers),
+            ["containers", id] => Ok(Target::Container(id.to_string())),
+            ["containers", id, file] => CONTAINER_FILES.iter()
+                .find(|known| **known == file)
+                .map(|file| Target::File(id.to_string(), file))
+                .ok_or_else(not_found),
+            _ => Err(not_found()),
+        }
+    }
+    
+    /// `docker inspect` of a container, as Docker's own JSON
+    async fn inspect(&self, id: &str) -> Result<Value> {
+        let inspect = self.docker.inspect_container(id, None::<InspectContainerOptions>).await
+            .map_err(|e| self.docker_error(e, id))?;
+        serde_json::to_value(inspect)
+            .map_err(|e| GnosError::Driver(format!("Docker inspect of {}: {}", id, e)))
+    }
+    
+    fn logs_options(&self, follow: bool) -> LogsOptions<String> {
+        LogsOptions {
+            follow,
+            stdout: true,
+            stderr: true,
+            tail: self.tail_lines.to_string(),
+            ..Default::default()
+        }
+    }
+    
+    /// The newest `tail_lines` lines of a container's output
+    async fn logs(&self, id: &str) -> Result<Bytes> {
+        let mut stream = std::pin::pin!(self.docker.logs(id, Some(self.logs_options(false))));
+        let mut text = Vec::new();
+        while let Some(output) = stream.next().await {
+            text.extend_from_slice(&output.map_err(|e| self.docker_error(e, id))?.into_bytes());
+        }
+        debug!("Read {} bytes of logs of {}", text.len(), id);
+        Ok(Bytes::from(text))
+    }
+    
+    /// The container's followed log, started for the first reader
+    fn tail(&self, id: &str) -> Arc<Tail> {
+        let mut tails = self.tails.lock().unwrap();
+        if let Some(tail) = tails.get(id) {
+            return tail.clone();
+        }
+        
+        let (end, receiver) = watch::channel(0);
+        let tail = Arc::new(Tail {
+            buffer: Mutex::new((0, Vec::new())),
+            end: receiver,
+            last_read: Mutex::new(Instant::now()),
+        });
+        tails.insert(id.to_string(), tail.clone());
+        
+        spawn_follow(self.docker.clone(), id.to_string(), self.logs_options(true), tail.clone(), end, self.tails.clone());
+        tail
+    }
+    
+    /// 404 is `PathNotFound`, 409 (e.g. already stopped) `ResourceBusy`;
+    /// otherwise Docker's message
+    fn docker_error(&self, error: DockerError, what: &str) -> GnosError {
+        match error {
+            DockerError::DockerResponseServerError { status_code: 404, .. } => {
+                GnosError::PathNotFound(format!("{}/containers/{}", ROOT, what))
+            }
+            DockerError::DockerResponseServerError { status_code: 401 | 403, message } => {
+                GnosError::PermissionDenied(format!("Docker refused {}: {}", what, message))
+            }
+            DockerError::DockerResponseServerError { status_code: 409, message } => GnosError::ResourceBusy(message),
+            DockerError::DockerResponseServerError { message, .. } => {
+                GnosError::Driver(format!("Docker {} failed: {}", what, message))
+            }
+            other => GnosError::Driver(format!("Docker at {}: {}", self.socket.display(), other)),
+        }
+    }
+}
+
+fn directory() -> ResourceMetadata {
+    ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }
+}
+
+/// What a container event changed: the container's directory when it is
+/// created or removed, its `control` file when its state moves
+fn change_of(event: &EventMessage) -> Option<RemoteChange> {
+    if event.typ != Some(EventMessageTypeEnum::CONTAINER) {
+        return None;
+    }
+    let id: String = event.actor.as_ref()?.id.as_ref()?.chars().take(12).collect();
+    let container = Path::new(ROOT).join("containers").join(id);
+    let (kind, path, metadata) = match event.action.as_deref()? {
+        "create" => {
+            let mut metadata = directory();
+            metadata.last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(event.time.unwrap_or_default().max(0) as u64);
+            (ChangeKind::Created, container, Some(metadata))
+        }
+        "destroy" => (ChangeKind::Deleted, container, None),
+        // Stopping and restarting go through `die` and `start` too
+        "start" | "die" | "pause" | "unpause" => (ChangeKind::Modified, container.join("control"), None),
+        _ => return None,
+    };
+    Some(RemoteChange { kind, path, metadata })
+}
+
+fn timestamp(rfc3339: Option<&str>) -> SystemTime {
+    rfc3339.and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+        .map_or(SystemTime::UNIX_EPOCH, SystemTime::from)
+}
+
+/// A container's name, image and state, from its inspect JSON
+fn container_fields(inspect: &Value) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let name = inspect["Name"].as_str().map(|name| name.trim_start_matches('/'));
+    let image = inspect["Config"]["Image"].as_str();
+    let state = inspect["State"]["Status"].as_str();
+    for (field, value) in [("name", name), ("image", image), ("state", state)] {
+        if let Some(value) = value {
+            fields.insert(field.to_string(), value.to_string());
+        }
+    }
+    fields
+}
+
+/// Feed `tail` from the daemon's log stream until it ends or nobody reads
+/// it, then let its readers know and forget it
+fn spawn_follow(
+    docker: Docker,
+    id: String,
+    options: LogsOptions<String>,
+    tail: Arc<Tail>,
+    end: watch::Sender<u64>,
+    tails: Arc<Mutex<HashMap<String, Arc<Tail>>>>,
+) {
+    tokio::spawn(async move {
+        let mut stream = std::pin::pin!(docker.logs(&id, Some(options)));
+        let mut idle_check = tokio::time::interval(FOLLOW_IDLE);
+        loop {
+            tokio::select! {
+                output = stream.next() => match output {
+                    Some(Ok(output)) => {
+                        end.send_replace(tail.append(&output.into_bytes()));
+                    }
+                    Some(Err(e)) => {
+                        debug!("Following logs of {} stopped: {}", id, e);
+                        break;
+                    }
+                    None => break,
+                },
+                _ = idle_check.tick() => {
+                    if tail.last_read.lock().unwrap().elapsed() >= FOLLOW_IDLE {
+                        break;
+                    }
+                }
+            }
+        }
+        
+        // Dropping `end` tells readers the log is finished
+        let mut tails = tails.lock().unwrap();
+        if tails.get(&id).is_some_and(|current| Arc::ptr_eq(current, &tail)) {
+            tails.remove(&id);
+        }
+        debug!("Closed the followed logs of {}", id);
+    });
+}
+
+#[async_trait]
+impl GnosDriver for DockerDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        match self.target(path)? {
+            Target::File(id, "logs") => self.logs(&id).await,
+            Target::File(id, "inspect.json") => {
+                let inspect = self.inspect(&id).await?;
+                let mut json = serde_json::to_vec_pretty(&inspect)
+                    .map_err(|e| GnosError::Driver(format!("Docker inspect of {}: {}", id, e)))?;
+                json.push(b'\n');
+                Ok(Bytes::from(json))
+            }
+            Target::File(id, _) => {
+                let inspect = self.inspect(&id).await?;
+                Ok(Bytes::from(format!("{}\n", inspect["State"]["Status"].as_str().unwrap_or("unknown"))))
+            }
+            _ => Err(GnosError::InvalidPath(format!("{} is a directory", path.display()))),
+        }
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.follow && matches!(self.target(path), Ok(Target::File(_, "logs")))
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        let Target::File(id, "logs") = self.target(path)? else {
+            return Ok(Growth { offset: 0, data: self.read(path).await?, growing: false });
+        };
+        if !self.follow {
+            return Ok(Growth { offset: 0, data: self.logs(&id).await?, growing: false });
+        }
+        
+        let tail = self.tail(&id);
+        *tail.last_read.lock().unwrap() = Instant::now();
+        let mut end = tail.end.clone();
+        let finished = end.wait_for(|&end| end > have).await.is_err();
+        Ok(tail.from(have, !finished))
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let Target::File(id, "control") = self.target(path)? else {
+            return Err(GnosError::PermissionDenied(format!("{} is read-only", path.display())));
+        };
+        let command = std::str::from_utf8(data).unwrap_or_default().trim();
+        match command {
+            "stop" => {
+                self.docker.stop_container(&id, None::<StopContainerOptions>).await
+                    .map_err(|e| self.docker_error(e, &id))?;
+                info!("🐳 Stopped container {}", id);
+            }
+            "restart" => {
+                self.docker.restart_container(&id, None::<RestartContainerOptions>).await
+                    .map_err(|e| self.docker_error(e, &id))?;
+                info!("🐳 Restarted container {}", id);
+            }
+            _ => {
+                return Err(GnosError::InvalidPath(format!("{} takes stop or restart, not {:?}", path.display(), command)));
+            }
+        }
+        Ok(())
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        match self.target(path)? {
+            Target::Root => Ok(vec![("containers".to_string(), Some(directory()))]),
+            Target::Containers => {
+                let options = ListContainersOptions::<String> { all: true, ..Default::default() };
+                let containers = self.docker.list_containers(Some(options)).await
+                    .map_err(|e| self.docker_error(e, "containers"))?;
+                Ok(containers.into_iter()
+                    .filter_map(|container| {
+                        let id: String = container.id?.chars().take(12).collect();
+                        let mut metadata = directory();
+                        metadata.last_modified = SystemTime::UNIX_EPOCH
+                            + Duration::from_secs(container.created.unwrap_or_default().max(0) as u64);
+                        let name = container.names.unwrap_or_default().into_iter().next();
+                        for (field, value) in [("name", name), ("image", container.image), ("state", container.state)] {
+                            if let Some(value) = value {
+                                metadata.custom_fields.insert(field.to_string(), value.trim_start_matches('/').to_string());
+                            }
+                        }
+                        Some((id, Some(metadata)))
+                    })
+                    .collect())
+            }
+            Target::Container(_) => Ok(CONTAINER_FILES.iter().map(|file| (file.to_string(), None)).collect()),
+            Target::File(..) => Ok(Vec::new()),
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let (id, file) = match self.target(path)? {
+            Target::Root | Target::Containers => return Ok(directory()),
+            Target::Container(id) => (id, None),
+            Target::File(id, file) => (id, Some(file)),
+        };
+        let inspect = self.inspect(&id).await?;
+        let mut metadata = ResourceMetadata {
+            is_directory: file.is_none(),
+            last_modified: timestamp(inspect["Created"].as_str()),
+            custom_fields: container_fields(&inspect),
+            ..ResourceMetadata::default()
+        };
+        metadata.mime_type = match file {
+            Some("inspect.json") => Some("application/json".to_string()),
+            Some(_) => Some("text/plain".to_string()),
+            None => None,
+        };
+        Ok(metadata)
+    }
+    
+    fn name(&self) -> &'static str {
+        "Docker Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // State changes under every file, and none knows its size before it is read
+        CacheMode::DirectIo
+    }
+    
+    fn watch(&self, path: &Path) -> Option<ChangeStream> {
+        if !matches!(self.target(path), Ok(Target::Root | Target::Containers)) {
+            return None;
+        }
+        let options = EventsOptions::<String> {
+            filters: HashMap::from([("type".to_string(), vec!["container".to_string()])]),
+            ..Default::default()
+        };
+        let path = path.to_path_buf();
+        let changes = self.docker.events(Some(options))
+            .take_while(|event| {
+                if let Err(e) = event {
+                    debug!("Docker event stream ended: {}", e);
+                }
+                futures::future::ready(event.is_ok())
+            })
+            .filter_map(move |event| {
+                let change = event.ok().as_ref().and_then(change_of).filter(|change| change.path.starts_with(&path));
+                futures::future::ready(change)
+            });
+        Some(changes.boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bollard::models::EventActor;
+    
+    use super::*;
+    
+    fn event(typ: EventMessageTypeEnum, action: &str) -> EventMessage {
+        EventMessage {
+            typ: Some(typ),
+            action: Some(action.to_string()),
+            actor: Some(EventActor { id: Some("3f4e1c2a9b7d5e6f7a8b".to_string()), attributes: None }),
+            time: Some(1_700_000_000),
+            ..Default::default()
+        }
+    }
+    
+    #[test]
+    fn container_events_become_changes_under_its_short_id() {
+        let created = change_of(&event(EventMessageTypeEnum::CONTAINER, "create")).unwrap();
+        assert_eq!(created.kind, ChangeKind::Created);
+        assert_eq!(created.path, Path::new("/dev/docker/containers/3f4e1c2a9b7d"));
+        assert!(created.metadata.is_some_and(|metadata| metadata.is_directory));
+        
+        let destroyed = change_of(&event(EventMessageTypeEnum::CONTAINER, "destroy")).unwrap();
+        assert_eq!((destroyed.kind, destroyed.path.as_path()),
+                   (ChangeKind::Deleted, Path::new("/dev/docker/containers/3f4e1c2a9b7d")));
+        
+        for action in ["start", "die", "pause", "unpause"] {
+            let moved = change_of(&event(EventMessageTypeEnum::CONTAINER, action)).unwrap();
+            assert_eq!((moved.kind, moved.path.as_path()),
+                       (ChangeKind::Modified, Path::new("/dev/docker/containers/3f4e1c2a9b7d/control")));
+        }
+    }
+    
+    #[test]
+    fn other_events_are_ignored() {
+        assert!(change_of(&event(EventMessageTypeEnum::CONTAINER, "exec_start: sh")).is_none());
+        assert!(change_of(&event(EventMessageTypeEnum::IMAGE, "create")).is_none());
+        let mut anonymous = event(EventMessageTypeEnum::CONTAINER, "create");
+        anonymous.actor = None;
+        assert!(change_of(&anonymous).is_none());
+    }
+}
@@ -0,0 +1,424 @@
+//! `/cloud/aws/dynamodb`: tables as directories, items as JSON files
+//!
+//! ```text
+//! ls /cloud/aws/dynamodb                         # tables
+//! ls /cloud/aws/dynamodb/users                   # partition keys, from Scan
+//! cat /cloud/aws/dynamodb/users/u-42             # GetItem, as JSON
+//! echo '{"name":"Ada"}' > /cloud/aws/dynamodb/users/u-42   # PutItem
+//! cat /cloud/aws/dynamodb/orders/u-42/2024-05-01 # partition and sort key
+//! ```
+//!
+//! Items are plain JSON rather than DynamoDB's typed JSON: strings,
+//! numbers, booleans, null, arrays and objects map to S, N, BOOL, NULL, L
+//! and M, string and number sets read as arrays and binary as base64. A
+//! written item gets its key attributes from the path. In a table with a
+//! sort key each partition key is a directory of the sort keys under it.
+//! Listings stop after `max_list` keys so a large table can't stall `ls`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_dynamodb::error::ProvideErrorMetadata;
+use aws_sdk_dynamodb::primitives::Blob;
+use aws_sdk_dynamodb::types::{AttributeValue, KeyType, ScalarAttributeType};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use bytes::Bytes;
+use serde_json::{Map, Number, Value};
+use tracing::debug;
+
+use crate::config::{CloudDriverConfig, DynamoDbDriverConfig};
+use crate::drivers::credentials::CloudCredentials;
+use crate::drivers::network::EgressPolicy;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/cloud/aws/dynamodb";
+
+/// A key attribute of a table: its name and scalar type
+#[derive(Debug, Clone)]
+struct KeyAttribute {
+    name: String,
+    kind: ScalarAttributeType,
+}
+
+impl KeyAttribute {
+    /// The attribute value a path component stands for
+    fn value(&self, component: &str) -> Result<AttributeValue> {
+        match self.kind {
+            ScalarAttributeType::N => {
+                component.parse::<f64>()
+                    .map_err(|_| GnosError::InvalidPath(format!("{} is a number key, not {:?}", self.name, component)))?;
+                Ok(AttributeValue::N(component.to_string()))
+            }
+            ScalarAttributeType::B => STANDARD.decode(component)
+                .map(|bytes| AttributeValue::B(Blob::new(bytes)))
+                .map_err(|_| GnosError::InvalidPath(format!("{} is a binary key, not base64 {:?}", self.name, component))),
+            _ => Ok(AttributeValue::S(component.to_string())),
+        }
+    }
+    
+    /// The path component an item's key attribute is listed as
+    fn component(&self, item: &HashMap<String, AttributeValue>) -> Option<String> {
+        match item.get(&self.name)? {
+            AttributeValue::S(value) | AttributeValue::N(value) => Some(value.clone()),
+            AttributeValue::B(bytes) => Some(STANDARD.encode(bytes.as_ref())),
+            _ => None,
+        }
+    }
+}
+
+/// How a table's items are keyed, from DescribeTable
+#[derive(Debug, Clone)]
+struct KeySchema {
+    partition: KeyAttribute,
+    sort: Option<KeyAttribute>,
+}
+
+/// What a path below the root names
+enum Target<'a> {
+    Tables,
+    Table(&'a str),
+    /// The partition key of a table with a sort key, listed as a directory
+    Partition(&'a str, &'a str),
+    Item { table: &'a str, partition: &'a str, sort: Option<&'a str> },
+}
+
+pub struct DynamoDbDriver {
+    credentials: Arc<CloudCredentials>,
+    region: String,
+    endpoint: Option<String>,
+    max_list: usize,
+    schemas: Mutex<HashMap<String, KeySchema>>,
+    egress: Arc<EgressPolicy>,
+}
+
+impl DynamoDbDriver {
+    pub fn new(
+        config: &DynamoDbDriverConfig,
+        cloud: &CloudDriverConfig,
+        credentials: Arc<CloudCredentials>,
+        egress: Arc<EgressPolicy>,
+    ) -> Self {
+        Self {
+            credentials,
+            region: config.region.clone().unwrap_or_else(|| cloud.aws.region.clone()),
+            endpoint: config.endpoint.clone(),
+            max_list: config.max_list.max(1),
+            schemas: Mutex::new(HashMap::new()),
+            egress,
+        }
+    }
+    
+    async fn client(&self) -> Result<aws_sdk_dynamodb::Client> {
+        match &self.endpoint {
+            Some(endpoint) => self.egress.check_endpoint(endpoint)?,
+            None => self.egress.check(&format!("dynamodb.{}.amazonaws.com", self.region))?,
+        }
+        let credentials = self.credentials.current().await?;
+        let mut config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(self.region.clone()))
+            .credentials_provider(credentials);
+        if let Some(endpoint) = &self.endpoint {
+            config = config.endpoint_url(endpoint);
+        }
+        Ok(aws_sdk_dynamodb::Client::from_conf(config.build()))
+    }
+    
+    /// Split `path` into table and keys; what an item path names depends on
+    /// whether its table has a sort key
+    async fn target<'a>(&self, path: &'a Path) -> Result<Target<'a>> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let components: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        
+        match components[..] {
+            [] => Ok(Target::Tables),
+            [table] => Ok(Target::Table(table)),
+            [table, partition] => match self.schema(table).await?.sort {
+                Some(_) => Ok(Target::Partition(table, partition)),
+                None => Ok(Target::Item { table, partition, sort: None }),
+            },
+            [table, partition, sort] => match self.schema(table).await?.sort {
+                Some(_) => Ok(Target::Item { table, partition, sort: Some(sort) }),
+                None => Err(GnosError::PathNotFound(path.display().to_string())),
+            },
+            _ => Err(GnosError::PathNotFound(path.display().to_string())),
+        }
+    }
+    
+    /// The key schema of `table`, described once and then remembered
+    async fn schema(&self, table: &str) -> Result<KeySchema> {
+        if let Some(schema) = self.schemas.lock().unwrap().get(table) {
+            return Ok(schema.clone());
+        }
+        
+        let response = self.client().await?.describe_table().table_name(table).send().await
+            .map_err(|e| dynamodb_error(table, e))?;
+        let description = response.table()
+            .ok_or_else(|| GnosError::Driver(format!("DynamoDB returned no description of {}", table)))?;
+        let attribute = |key_type: KeyType| {
+            let name = description.key_schema().iter()
+                .find(|element| *element.key_type() == key_type)?
+                .attribute_name()
+                .to_string();
+            let kind = description.attribute_definitions().iter()
+                .find(|definition| definition.attribute_name() == name)?
+                .attribute_type()
+                .clone();
+            Some(KeyAttribute { name, kind })
+        };
+        let schema = KeySchema {
+            partition: attribute(KeyType::Hash)
+                .ok_or_else(|| GnosError::Driver(format!("{} has no partition key", table)))?,
+            sort: attribute(KeyType::Range),
+        };
+        
+        debug!("DynamoDB table {} is keyed by {:?}", table, schema);
+        self.schemas.lock().unwrap().insert(table.to_string(), schema.clone());
+        Ok(schema)
+    }
+    
+    /// The primary key of one item
+    async fn key(&self, table: &str, partition: &str, sort: Option<&str>) -> Result<HashMap<String, AttributeValue>> {
+        let schema = self.schema(table).await?;
+        let mut key = HashMap::from([(schema.partition.name.clone(), schema.partition.value(partition)?)]);
+        if let (Some(attribute), Some(sort)) = (&schema.sort, sort) {
+            key.insert(attribute.name.clone(), attribute.value(sort)?);
+        }
+        Ok(key)
+    }
+    
+    async fn get_item(&self, path: &Path, table: &str, partition: &str, sort: Option<&str>) -> Result<Bytes> {
+        let key = self.key(table, partition, sort).await?;
+        let response = self.client().await?.get_item().table_name(table).set_key(Some(key)).send().await
+            .map_err(|e| dynamodb_error(table, e))?;
+        let item = response.item()
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        let mut json = serde_json::to_vec_pretty(&item_to_json(item))
+            .map_err(|e| GnosError::Driver(format!("encoding {} failed: {}", path.display(), e)))?;
+        json.push(b'\n');
+        Ok(Bytes::from(json))
+    }
+    
+    async fn list_tables(&self) -> Result<Vec<String>> {
+        let client = self.client().await?;
+        let mut tables = Vec::new();
+        let mut start = None;
+        loop {
+            let response = client.list_tables().set_exclusive_start_table_name(start).send().await
+                .map_err(|e| dynamodb_error(ROOT, e))?;
+            tables.extend(response.table_names().iter().cloned());
+            start = response.last_evaluated_table_name().map(str::to_string);
+            if start.is_none() || tables.len() >= self.max_list {
+                break;
+            }
+        }
+        tables.truncate(self.max_list);
+        Ok(tables)
+    }
+    
+    /// Partition keys of `table`, from a Scan of the key attribute alone;
+    /// those of a table with a sort key repeat, so only distinct ones count
+    async fn list_partitions(&self, table: &str) -> Result<Vec<String>> {
+        let schema = self.schema(table).await?;
+        let client = self.client().await?;
+        let mut keys = Vec::new();
+        let mut start = None;
+        loop {
+            let response = client.scan()
+                .table_name(table)
+                .projection_expression("#pk")
+                .expression_attribute_names("#pk", &schema.partition.name)
+                .set_exclusive_start_key(start)
+                .send().await
+                .map_err(|e| dynamodb_error(table, e))?;
+            for item in response.items() {
+                if let Some(key) = schema.partition.component(item) {
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+            }
+            start = response.last_evaluated_key().cloned();
+            if start.is_none() || keys.len() >= self.max_list {
+                break;
+            }
+        }
+        if keys.len() >= self.max_list {
+            debug!("Listing of {} stopped at {} keys", table, self.max_list);
+        }
+        keys.truncate(self.max_list);
+        Ok(keys)
+    }
+    
+    /// Sort keys under one partition key, from a Query
+    async fn list_sort_keys(&self, table: &str, partition: &str) -> Result<Vec<String>> {
+        let schema = self.schema(table).await?;
+        let Some(sort) = &schema.sort else {
+            return Ok(Vec::new());
+        };
+        let client = self.client().await?;
+        let mut keys = Vec::new();
+        let mut start = None;
+        loop {
+            let response = client.query()
+                .table_name(table)
+                .key_condition_expression("#pk = :pk")
+                .expression_attribute_names("#pk", &schema.partition.name)
+                .expression_attribute_values(":pk", schema.partition.value(partition)?)
+                .set_exclusive_start_key(start)
+                .send().await
+                .map_err(|e| dynamodb_error(table, e))?;
+            keys.extend(response.items().iter().filter_map(|item| sort.component(item)));
+            start = response.last_evaluated_key().cloned();
+            if start.is_none() || keys.len() >= self.max_list {
+                break;
+            }
+        }
+        keys.truncate(self.max_list);
+        Ok(keys)
+    }
+}
+
+/// Missing tables are `PathNotFound`, other failures driver errors
+fn dynamodb_error<E: ProvideErrorMetadata + std::fmt::Display>(table: &str, error: E) -> GnosError {
+    match error.code() {
+        Some("ResourceNotFoundException") => GnosError::PathNotFound(format!("{}/{}", ROOT, table)),
+        _ => GnosError::Driver(format!("DynamoDB {} failed: {}", table, error)),
+    }
+}
+
+fn item_to_json(item: &HashMap<String, AttributeValue>) -> Value {
+    Value::Object(item.iter().map(|(name, value)| (name.clone(), to_json(value))).collect())
+}
+
+fn to_json(value: &AttributeValue) -> Value {
+    let number = |n: &String| n.parse::<Number>().map_or_else(|_| Value::String(n.clone()), Value::Number);
+    match value {
+        AttributeValue::S(s) => Value::String(s.clone()),
+        AttributeValue::N(n) => number(n),
+        AttributeValue::Bool(b) => Value::Bool(*b),
+        AttributeValue::Null(_) => Value::Null,
+        AttributeValue::B(bytes) => Value::String(STANDARD.encode(bytes.as_ref())),
+        AttributeValue::L(values) => Value::Array(values.iter().map(to_json).collect()),
+        AttributeValue::M(map) => item_to_json(map),
+        AttributeValue::Ss(strings) => Value::Array(strings.iter().cloned().map(Value::String).collect()),
+        AttributeValue::Ns(numbers) => Value::Array(numbers.iter().map(number).collect()),
+        AttributeValue::Bs(blobs) => Value::Array(blobs.iter().map(|bytes| Value::String(STANDARD.encode(bytes.as_ref()))).collect()),
+        _ => Value::Null,
+    }
+}
+
+fn from_json(value: &Value) -> AttributeValue {
+    match value {
+        Value::Null => AttributeValue::Null(true),
+        Value::Bool(b) => AttributeValue::Bool(*b),
+        Value::Number(n) => AttributeValue::N(n.to_string()),
+        Value::String(s) => AttributeValue::S(s.clone()),
+        Value::Array(values) => AttributeValue::L(values.iter().map(from_json).collect()),
+        Value::Object(map) => AttributeValue::M(map.iter().map(|(name, value)| (name.clone(), from_json(value))).collect()),
+    }
+}
+
+#[async_trait]
+impl GnosDriver for DynamoDbDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        match self.target(path).await? {
+            Target::Item { table, partition, sort } => self.get_item(path, table, partition, sort).await,
+            _ => Err(GnosError::InvalidPath(format!("{} is a directory", path.display()))),
+        }
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let Target::Item { table, partition, sort } = self.target(path).await? else {
+            return Err(GnosError::InvalidPath(format!("{} is not an item", path.display())));
+        };
+        let item: Map<String, Value> = serde_json::from_slice(data)
+            .map_err(|e| GnosError::InvalidPath(format!("{} must be a JSON object: {}", path.display(), e)))?;
+        
+        let key = self.key(table, partition, sort).await?;
+        let mut attributes: HashMap<String, AttributeValue> = item.iter()
+            .map(|(name, value)| (name.clone(), from_json(value)))
+            .collect();
+        for (name, value) in key {
+            if let Some(written) = attributes.get(&name) {
+                if *written != value {
+                    return Err(GnosError::InvalidPath(format!(
+                        "{} sets key attribute {} to something other than its path", path.display(), name
+                    )));
+                }
+            }
+            attributes.insert(name, value);
+        }
+        
+        self.client().await?.put_item().table_name(table).set_item(Some(attributes)).send().await
+            .map_err(|e| dynamodb_error(table, e))?;
+        debug!("Put {}", path.display());
+        Ok(())
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        let Target::Item { table, partition, sort } = self.target(path).await? else {
+            return Err(GnosError::PermissionDenied(format!("{} is not an item", path.display())));
+        };
+        let key = self.key(table, partition, sort).await?;
+        self.client().await?.delete_item().table_name(table).set_key(Some(key)).send().await
+            .map_err(|e| dynamodb_error(table, e))?;
+        Ok(())
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        match self.target(path).await? {
+            Target::Tables => self.list_tables().await,
+            Target::Table(table) => self.list_partitions(table).await,
+            Target::Partition(table, partition) => self.list_sort_keys(table, partition).await,
+            Target::Item { .. } => Err(GnosError::InvalidPath(format!("{} is an item", path.display()))),
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let directory = ResourceMetadata { is_directory: true, ..ResourceMetadata::default() };
+        match self.target(path).await? {
+            Target::Tables => Ok(directory),
+            Target::Table(table) => {
+                self.schema(table).await?;
+                Ok(directory)
+            }
+            Target::Partition(..) => Ok(directory),
+            Target::Item { table, partition, sort } => {
+                let item = self.get_item(path, table, partition, sort).await?;
+                Ok(ResourceMetadata {
+                    size: item.len() as u64,
+                    mime_type: Some("application/json".to_string()),
+                    ..ResourceMetadata::default()
+                })
+            }
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "DynamoDB Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+}
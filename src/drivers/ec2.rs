@@ -0,0 +1,319 @@
+//! `/cloud/aws/ec2`: instances as JSON files with a writable power state
+//!
+//! ```text
+//! ls /cloud/aws/ec2/instances                    # i-0abc..., i-0abc....state
+//! cat /cloud/aws/ec2/instances/i-0abc            # type, state, addresses, tags
+//! cat /cloud/aws/ec2/instances/i-0abc.state      # running
+//! echo stop > /cloud/aws/ec2/instances/i-0abc.state
+//! ```
+//!
+//! `<id>.state` reads as the instance's state name and takes `start`,
+//! `stop` or `reboot`. The request returns once EC2 has accepted it, so a
+//! read right after a write shows the transition (`stopping`) rather than
+//! its end. Termination is deliberately not on offer.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_ec2::error::ProvideErrorMetadata;
+use aws_sdk_ec2::types::Instance;
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+use tracing::debug;
+
+use crate::config::{CacheMode, CloudDriverConfig, Ec2DriverConfig};
+use crate::drivers::credentials::CloudCredentials;
+use crate::drivers::network::EgressPolicy;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/cloud/aws/ec2";
+
+/// Directory of instances under the root
+const INSTANCES: &str = "instances";
+
+/// Suffix of the file holding an instance's power state
+const STATE_SUFFIX: &str = ".state";
+
+/// Instances DescribeInstances returns at once
+const PAGE: i32 = 1000;
+
+/// What a path below the root names
+enum Target<'a> {
+    Root,
+    Instances,
+    Instance(&'a str),
+    State(&'a str),
+}
+
+/// What a write to `<id>.state` asks for
+enum Action {
+    Start,
+    Stop,
+    Reboot,
+}
+
+pub struct Ec2Driver {
+    credentials: Arc<CloudCredentials>,
+    region: String,
+    endpoint: Option<String>,
+    max_list: usize,
+    egress: Arc<EgressPolicy>,
+}
+
+impl Ec2Driver {
+    pub fn new(
+        config: &Ec2DriverConfig,
+        cloud: &CloudDriverConfig,
+        credentials: Arc<CloudCredentials>,
+        egress: Arc<EgressPolicy>,
+    ) -> Self {
+        Self {
+            credentials,
+            region: config.region.clone().unwrap_or_else(|| cloud.aws.region.clone()),
+            endpoint: config.endpoint.clone(),
+            max_list: config.max_list.max(1),
+            egress,
+        }
+    }
+    
+    async fn client(&self) -> Result<aws_sdk_ec2::Client> {
+        match &self.endpoint {
+            Some(endpoint) => self.egress.check_endpoint(endpoint)?,
+            None => self.egress.check(&format!("ec2.{}.amazonaws.com", self.region))?,
+        }
+        let credentials = self.credentials.current().await?;
+        let mut config = aws_sdk_ec2::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(self.region.clone()))
+            .credentials_provider(credentials);
+        if let Some(endpoint) = &self.endpoint {
+            config = config.endpoint_url(endpoint);
+        }
+        Ok(aws_sdk_ec2::Client::from_conf(config.build()))
+    }
+    
+    fn target<'a>(&self, path: &'a Path) -> Result<Target<'a>> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let components: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        
+        match components[..] {
+            [] => Ok(Target::Root),
+            [INSTANCES] => Ok(Target::Instances),
+            [INSTANCES, name] => match name.strip_suffix(STATE_SUFFIX) {
+                Some(id) if !id.is_empty() => Ok(Target::State(id)),
+                _ => Ok(Target::Instance(name)),
+            },
+            _ => Err(GnosError::PathNotFound(path.display().to_string())),
+        }
+    }
+    
+    async fn instance(&self, id: &str) -> Result<Instance> {
+        let response = self.client().await?.describe_instances().instance_ids(id).send().await
+            .map_err(|e| ec2_error(id, e))?;
+        response.reservations().iter()
+            .flat_map(|reservation| reservation.instances())
+            .find(|instance| instance.instance_id() == Some(id))
+            .cloned()
+            .ok_or_else(|| GnosError::PathNotFound(format!("{}/{}/{}", ROOT, INSTANCES, id)))
+    }
+    
+    async fn list_instances(&self) -> Result<Vec<Instance>> {
+        let client = self.client().await?;
+        let mut instances = Vec::new();
+        let mut token = None;
+        loop {
+            let response = client.describe_instances().max_results(PAGE).set_next_token(token).send().await
+                .map_err(|e| ec2_error(INSTANCES, e))?;
+            instances.extend(response.reservations().iter()
+                .flat_map(|reservation| reservation.instances())
+                .filter(|instance| instance.instance_id().is_some())
+                .cloned());
+            token = response.next_token().map(str::to_string);
+            if token.is_none() || instances.len() >= self.max_list {
+                break;
+            }
+        }
+        instances.truncate(self.max_list);
+        Ok(instances)
+    }
+    
+    async fn change_state(&self, id: &str, data: &[u8]) -> Result<()> {
+        let action = match String::from_utf8_lossy(data).trim() {
+            "start" => Action::Start,
+            "stop" => Action::Stop,
+            "reboot" => Action::Reboot,
+            other => return Err(GnosError::InvalidPath(format!(
+                "{}/{}/{}{} takes start, stop or reboot, not {:?}", ROOT, INSTANCES, id, STATE_SUFFIX, other))),
+        };
+        
+        let client = self.client().await?;
+        match action {
+            Action::Start => {
+                client.start_instances().instance_ids(id).send().await.map_err(|e| ec2_error(id, e))?;
+            }
+            Action::Stop => {
+                client.stop_instances().instance_ids(id).send().await.map_err(|e| ec2_error(id, e))?;
+            }
+            Action::Reboot => {
+                client.reboot_instances().instance_ids(id).send().await.map_err(|e| ec2_error(id, e))?;
+            }
+        }
+        debug!("Asked EC2 to {} {}", String::from_utf8_lossy(data).trim(), id);
+        Ok(())
+    }
+}
+
+fn state_name(instance: &Instance) -> &str {
+    instance.state().and_then(|state| state.name()).map_or("unknown", |name| name.as_str())
+}
+
+/// Attributes of `<id>` or `<id>.state`, dated by the instance's launch
+fn instance_metadata(instance: &Instance, mime_type: &str) -> ResourceMetadata {
+    let mut metadata = ResourceMetadata {
+        last_modified: instance.launch_time()
+            .and_then(|time| SystemTime::try_from(*time).ok())
+            .unwrap_or(SystemTime::UNIX_EPOCH),
+        mime_type: Some(mime_type.to_string()),
+        ..ResourceMetadata::default()
+    };
+    metadata.custom_fields.insert("state".to_string(), state_name(instance).to_string());
+    if let Some(kind) = instance.instance_type() {
+        metadata.custom_fields.insert("instance_type".to_string(), kind.as_str().to_string());
+    }
+    metadata
+}
+
+/// What `<id>` reads as
+fn describe(instance: &Instance) -> Result<Bytes> {
+    let tags: Map<String, Value> = instance.tags().iter()
+        .filter_map(|tag| Some((tag.key()?.to_string(), Value::from(tag.value().unwrap_or_default()))))
+        .collect();
+    let launch_time = instance.launch_time()
+        .and_then(|time| chrono::DateTime::from_timestamp(time.secs(), time.subsec_nanos()))
+        .map(|time| time.to_rfc3339());
+    
+    let description = json!({
+        "id": instance.instance_id(),
+        "type": instance.instance_type().map(|kind| kind.as_str()),
+        "state": state_name(instance),
+        "image": instance.image_id(),
+        "architecture": instance.architecture().map(|architecture| architecture.as_str()),
+        "availability_zone": instance.placement().and_then(|placement| placement.availability_zone()),
+        "vpc": instance.vpc_id(),
+        "subnet": instance.subnet_id(),
+        "private_ip": instance.private_ip_address(),
+        "private_dns": instance.private_dns_name(),
+        "public_ip": instance.public_ip_address(),
+        "public_dns": instance.public_dns_name(),
+        "key_name": instance.key_name(),
+        "launch_time": launch_time,
+        "tags": tags,
+    });
+    let mut json = serde_json::to_vec_pretty(&description)
+        .map_err(|e| GnosError::Driver(format!("encoding {} failed: {}", instance.instance_id().unwrap_or_default(), e)))?;
+    json.push(b'\n');
+    Ok(Bytes::from(json))
+}
+
+/// Unknown or malformed instance IDs are `PathNotFound`, throttling
+/// `RateLimited` and other failures driver errors
+fn ec2_error<E: ProvideErrorMetadata + std::fmt::Display>(id: &str, error: E) -> GnosError {
+    match error.code() {
+        Some("InvalidInstanceID.NotFound" | "InvalidInstanceID.Malformed") => {
+            GnosError::PathNotFound(format!("{}/{}/{}", ROOT, INSTANCES, id))
+        }
+        Some("RequestLimitExceeded") => GnosError::RateLimited {
+            message: format!("EC2 requests for {} are throttled", id),
+            retry_after: None,
+        },
+        Some("IncorrectInstanceState" | "IncorrectState") => {
+            GnosError::InvalidPath(format!("{}: {}", id, error.message().unwrap_or("not in a state that allows this")))
+        }
+        _ => GnosError::Driver(format!("EC2 {} failed: {}", id, error)),
+    }
+}
+
+#[async_trait]
+impl GnosDriver for Ec2Driver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        match self.target(path)? {
+            Target::Instance(id) => describe(&self.instance(id).await?),
+            Target::State(id) => Ok(Bytes::from(format!("{}\n", state_name(&self.instance(id).await?)))),
+            Target::Root | Target::Instances => {
+                Err(GnosError::InvalidPath(format!("{} is a directory", path.display())))
+            }
+        }
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        match self.target(path)? {
+            Target::State(id) => self.change_state(id, data).await,
+            _ => Err(GnosError::PermissionDenied(format!("{} can't be written", path.display()))),
+        }
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        match self.target(path)? {
+            Target::Root => Ok(vec![INSTANCES.to_string()]),
+            Target::Instances => Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect()),
+            _ => Err(GnosError::InvalidPath(format!("{} is not a directory", path.display()))),
+        }
+    }
+    
+    /// One DescribeInstances answers for every instance, so `ls -l` needs
+    /// no call per file
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        let Target::Instances = self.target(path)? else {
+            return Ok(self.list(path).await?.into_iter().map(|name| (name, None)).collect());
+        };
+        Ok(self.list_instances().await?.iter()
+            .flat_map(|instance| {
+                let id = instance.instance_id().unwrap_or_default();
+                [
+                    (id.to_string(), Some(instance_metadata(instance, "application/json"))),
+                    (format!("{}{}", id, STATE_SUFFIX), Some(instance_metadata(instance, "text/plain"))),
+                ]
+            })
+            .collect())
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        match self.target(path)? {
+            Target::Root | Target::Instances => Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }),
+            Target::Instance(id) => Ok(instance_metadata(&self.instance(id).await?, "application/json")),
+            Target::State(id) => Ok(instance_metadata(&self.instance(id).await?, "text/plain")),
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "EC2 Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Instances change state under us
+        CacheMode::DirectIo
+    }
+}
@@ -0,0 +1,374 @@
+//! `/dev/git/<repo>`: repositories by branch, tag and commit
+//!
+//! ```text
+//! ls /dev/git/gnos/branches                  # main  release  feature
+//! cat /dev/git/gnos/branches/main/Cargo.toml
+//! ls /dev/git/gnos/tags/v0.1.0/src
+//! ls /dev/git/gnos/commits                   # newest commits on HEAD
+//! diff /dev/git/gnos/commits/3f2a9c1/src/lib.rs src/lib.rs
+//! ```
+//!
+//! Below each ref is the tree of the commit it points at: directories are
+//! trees, files read as their blobs, and everything carries the commit's
+//! time and hash. Branch and tag names with slashes are nested directories,
+//! so `feature/login` is `branches/feature/login/`. `commits/` lists only the
+//! newest commits on HEAD, but any commit opens by its hash, full or
+//! abbreviated. Commits never change, so their files stay in the page cache;
+//! branches and tags are looked up again on every call.
+//!
+//! Repositories are read through libgit2 and never written to. libgit2
+//! blocks, so calls run on the blocking pool, one at a time per repository.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use git2::{BranchType, Commit, ErrorCode, ObjectType, Oid, Repository, Sort, TreeEntry};
+use tracing::info;
+
+use crate::config::{CacheMode, GitDriverConfig};
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/dev/git";
+
+/// Directories in every repository
+const KINDS: [&str; 3] = ["branches", "tags", "commits"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Branches,
+    Tags,
+    Commits,
+}
+
+/// What a path below the root names
+enum Target<'a> {
+    Repos,
+    Repo,
+    /// A ref directory and the names below it
+    Ref(&'a Arc<Repo>, Kind, Vec<String>),
+}
+
+/// What the names below a ref directory come to
+enum Resolved {
+    /// Ref names, or what follows a prefix they share
+    Refs(Vec<String>),
+    /// `path` within the tree of a commit; empty for the tree itself
+    Commit { id: Oid, time: SystemTime, path: PathBuf },
+}
+
+struct Repo {
+    repository: Mutex<Repository>,
+}
+
+pub struct GitDriver {
+    repos: BTreeMap<String, Arc<Repo>>,
+    commit_listing: usize,
+}
+
+impl GitDriver {
+    pub fn new(config: &GitDriverConfig) -> Result<Self> {
+        let mut repos = BTreeMap::new();
+        for repo in &config.repos {
+            if repo.name.is_empty() || repo.name.contains('/') {
+                return Err(GnosError::Config(format!("invalid git repository name: {:?}", repo.name)));
+            }
+            let repository = Repository::open(&repo.path).map_err(|e| {
+                GnosError::Config(format!("{} is not a git repository: {}", repo.path.display(), e.message()))
+            })?;
+            let entry = Arc::new(Repo { repository: Mutex::new(repository) });
+            if repos.insert(repo.name.clone(), entry).is_some() {
+                return Err(GnosError::Config(format!("git repository {} is configured twice", repo.name)));
+            }
+        }
+        info!("🌿 Serving {} git repositories", repos.len());
+        Ok(Self { repos, commit_listing: config.commit_listing })
+    }
+    
+    fn target<'a>(&'a self, path: &Path) -> Result<Target<'a>> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let names = rest.components()
+            .map(|component| match component {
+                Component::Normal(name) => name.to_str().map(str::to_string),
+                _ => None,
+            })
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| GnosError::InvalidPath(path.display().to_string()))?;
+        
+        let Some((repo, rest)) = names.split_first() else {
+            return Ok(Target::Repos);
+        };
+        let repo = self.repos.get(repo)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        let kind = match rest.first().map(String::as_str) {
+            None => return Ok(Target::Repo),
+            Some("branches") => Kind::Branches,
+            Some("tags") => Kind::Tags,
+            Some("commits") => Kind::Commits,
+            Some(_) => return Err(GnosError::PathNotFound(path.display().to_string())),
+        };
+        Ok(Target::Ref(repo, kind, rest[1..].to_vec()))
+    }
+    
+    /// Resolve the names below a ref directory on the blocking pool and run
+    /// `op` on the result
+    async fn at_ref<T: Send + 'static>(
+        &self,
+        path: &Path,
+        repo: &Arc<Repo>,
+        kind: Kind,
+        names: Vec<String>,
+        op: impl FnOnce(&Repository, Resolved, &str) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let repo = repo.clone();
+        let shown = path.display().to_string();
+        let commit_listing = self.commit_listing;
+        tokio::task::spawn_blocking(move || {
+            let repository = repo.repository.lock().unwrap();
+            let resolved = resolve(&repository, kind, &names, commit_listing, &shown)?;
+            op(&repository, resolved, &shown)
+        })
+        .await
+        .map_err(|e| GnosError::Driver(format!("git task failed: {}", e)))?
+    }
+}
+
+fn resolve(repository: &Repository, kind: Kind, names: &[String], commit_listing: usize, shown: &str) -> Result<Resolved> {
+    let fail = |e: git2::Error| git_error(e, shown);
+    
+    let (commit, consumed) = match kind {
+        Kind::Commits => {
+            let Some(hash) = names.first() else {
+                return recent_commits(repository, commit_listing).map(Resolved::Refs).map_err(fail);
+            };
+            // Only hashes, not revision expressions like `HEAD~2`
+            if !(4..=40).contains(&hash.len()) || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(GnosError::PathNotFound(shown.to_string()));
+            }
+            (repository.find_commit_by_prefix(hash).map_err(fail)?, 1)
+        }
+        Kind::Branches | Kind::Tags => {
+            let refs = ref_names(repository, kind).map_err(fail)?;
+            // Git won't keep both `a` and `a/b`, so at most one of these is a ref
+            let Some(consumed) = (1..=names.len()).find(|&n| refs.contains(&names[..n].join("/"))) else {
+                let prefix: String = names.iter().map(|name| format!("{}/", name)).collect();
+                let mut children: Vec<String> = refs.iter()
+                    .filter_map(|name| name.strip_prefix(&prefix))
+                    .filter_map(|tail| tail.split('/').next())
+                    .map(str::to_string)
+                    .collect();
+                children.dedup();
+                if children.is_empty() && !names.is_empty() {
+                    return Err(GnosError::PathNotFound(shown.to_string()));
+                }
+                return Ok(Resolved::Refs(children));
+            };
+            let name = names[..consumed].join("/");
+            let reference = match kind {
+                Kind::Branches => repository.find_branch(&name, BranchType::Local).map(|branch| branch.into_reference()),
+                _ => repository.find_reference(&format!("refs/tags/{}", name)),
+            };
+            (reference.and_then(|reference| reference.peel_to_commit()).map_err(fail)?, consumed)
+        }
+    };
+    
+    Ok(Resolved::Commit {
+        id: commit.id(),
+        time: commit_time(&commit),
+        path: names[consumed..].iter().collect(),
+    })
+}
+
+/// Local branch or tag names, sorted
+fn ref_names(repository: &Repository, kind: Kind) -> std::result::Result<Vec<String>, git2::Error> {
+    let mut names = Vec::new();
+    if kind == Kind::Branches {
+        for branch in repository.branches(Some(BranchType::Local))? {
+            if let Some(name) = branch?.0.name()? {
+                names.push(name.to_string());
+            }
+        }
+    } else {
+        names.extend(repository.tag_names(None)?.iter().flatten().map(str::to_string));
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Hashes of the newest `limit` commits reachable from HEAD
+fn recent_commits(repository: &Repository, limit: usize) -> std::result::Result<Vec<String>, git2::Error> {
+    let mut walk = repository.revwalk()?;
+    walk.set_sorting(Sort::TIME)?;
+    match walk.push_head() {
+        Ok(()) => {}
+        // A fresh repository has no commits yet
+        Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    }
+    walk.take(limit).map(|id| id.map(|id| id.to_string())).collect()
+}
+
+/// The entry at `path` in a commit's tree; `None` for the tree itself
+fn entry_at(repository: &Repository, id: Oid, path: &Path) -> std::result::Result<Option<TreeEntry<'static>>, git2::Error> {
+    if path.as_os_str().is_empty() {
+        return Ok(None);
+    }
+    repository.find_commit(id)?.tree()?.get_path(path).map(Some)
+}
+
+fn git_error(e: git2::Error, shown: &str) -> GnosError {
+    match e.code() {
+        ErrorCode::NotFound => GnosError::PathNotFound(shown.to_string()),
+        ErrorCode::Ambiguous => GnosError::InvalidPath(format!("{}: {}", shown, e.message())),
+        _ => GnosError::Driver(format!("{}: {}", shown, e.message())),
+    }
+}
+
+fn commit_time(commit: &Commit) -> SystemTime {
+    let seconds = commit.time().seconds();
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH
+    }
+}
+
+fn directory(last_modified: SystemTime) -> ResourceMetadata {
+    ResourceMetadata { is_directory: true, last_modified, ..ResourceMetadata::default() }
+}
+
+/// Metadata for something in a commit's tree; submodules show as empty directories
+fn entry_metadata(repository: &Repository, entry: Option<&TreeEntry>, id: Oid, time: SystemTime) -> ResourceMetadata {
+    let mut metadata = match entry {
+        Some(entry) if entry.kind() == Some(ObjectType::Blob) => ResourceMetadata {
+            // The header has the size without inflating the blob
+            size: repository.odb()
+                .and_then(|odb| odb.read_header(entry.id()))
+                .map_or(0, |(size, _)| size as u64),
+            last_modified: time,
+            etag: Some(entry.id().to_string()),
+            ..ResourceMetadata::default()
+        },
+        _ => directory(time),
+    };
+    metadata.custom_fields.insert("commit".to_string(), id.to_string());
+    metadata
+}
+
+#[async_trait]
+impl GnosDriver for GitDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let Target::Ref(repo, kind, names) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        self.at_ref(path, repo, kind, names, |repository, resolved, shown| {
+            let is_directory = || GnosError::InvalidPath(format!("{} is a directory", shown));
+            let Resolved::Commit { id, path, .. } = resolved else {
+                return Err(is_directory());
+            };
+            let entry = entry_at(repository, id, &path).map_err(|e| git_error(e, shown))?
+                .filter(|entry| entry.kind() == Some(ObjectType::Blob))
+                .ok_or_else(is_directory)?;
+            let blob = repository.find_blob(entry.id()).map_err(|e| git_error(e, shown))?;
+            Ok(Bytes::copy_from_slice(blob.content()))
+        }).await
+    }
+    
+    async fn write(&self, path: &Path, _data: &[u8]) -> Result<()> {
+        Err(GnosError::PermissionDenied(format!("{} is read-only", path.display())))
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        Err(GnosError::PermissionDenied(format!("{} is read-only", path.display())))
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        Err(GnosError::PermissionDenied(format!("{} is read-only", path.display())))
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        let (repo, kind, names) = match self.target(path)? {
+            Target::Repos => {
+                return Ok(self.repos.keys().map(|name| (name.clone(), Some(directory(SystemTime::now())))).collect());
+            }
+            Target::Repo => {
+                return Ok(KINDS.iter().map(|kind| (kind.to_string(), Some(directory(SystemTime::now())))).collect());
+            }
+            Target::Ref(repo, kind, names) => (repo, kind, names),
+        };
+        self.at_ref(path, repo, kind, names, |repository, resolved, shown| {
+            let (id, time, path) = match resolved {
+                Resolved::Refs(names) => {
+                    return Ok(names.into_iter().map(|name| (name, Some(directory(SystemTime::now())))).collect());
+                }
+                Resolved::Commit { id, time, path } => (id, time, path),
+            };
+            
+            let fail = |e: git2::Error| git_error(e, shown);
+            let tree = match entry_at(repository, id, &path).map_err(fail)? {
+                None => repository.find_commit(id).and_then(|commit| commit.tree()).map_err(fail)?,
+                Some(entry) => match entry.kind() {
+                    Some(ObjectType::Tree) => repository.find_tree(entry.id()).map_err(fail)?,
+                    // A submodule's commits live in another repository
+                    Some(ObjectType::Commit) => return Ok(Vec::new()),
+                    _ => return Err(GnosError::InvalidPath(format!("{} is not a directory", shown))),
+                },
+            };
+            Ok(tree.iter()
+                .filter_map(|entry| {
+                    let name = entry.name()?.to_string();
+                    Some((name, Some(entry_metadata(repository, Some(&entry), id, time))))
+                })
+                .collect())
+        }).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let Target::Ref(repo, kind, names) = self.target(path)? else {
+            return Ok(directory(SystemTime::now()));
+        };
+        self.at_ref(path, repo, kind, names, |repository, resolved, shown| {
+            let Resolved::Commit { id, time, path } = resolved else {
+                return Ok(directory(SystemTime::now()));
+            };
+            let entry = entry_at(repository, id, &path).map_err(|e| git_error(e, shown))?;
+            Ok(entry_metadata(repository, entry.as_ref(), id, time))
+        }).await
+    }
+    
+    fn name(&self) -> &'static str {
+        "Git Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, path: &Path) -> CacheMode {
+        match self.target(path) {
+            Ok(Target::Ref(_, Kind::Commits, _)) => CacheMode::KeepCache,
+            _ => CacheMode::Auto,
+        }
+    }
+}
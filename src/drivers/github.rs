@@ -0,0 +1,385 @@
+//! `/net/github/<org>/<repo>`: issues and pull requests as markdown
+//!
+//! ```text
+//! ls /net/github/gnos-os/rust-core/issues          # 12.md  15.md ...
+//! cat /net/github/gnos-os/rust-core/pulls/14.md    # the PR and its comments
+//! echo 'Reproduced on 0.3 too' > /net/github/gnos-os/rust-core/issues/12.md
+//! printf '# Crash on unmount\n\nSteps: ...\n' > /net/github/gnos-os/rust-core/new
+//! ```
+//!
+//! Reading `<n>.md` renders the issue or pull request with its comments;
+//! writing one posts what was written as a new comment, and writing `new`
+//! opens an issue titled by its first line. `issues/` and `pulls/` list the
+//! most recently updated open ones, but any number opens, closed or not.
+//! Requests carry the token from the environment variable the config names;
+//! without one public repositories can still be read.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::config::{CacheMode, GithubDriverConfig};
+use crate::drivers::context::DriverContext;
+use crate::drivers::network::SharedHttpClient;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/net/github";
+
+/// Entries in every repository directory
+const REPO_ENTRIES: [&str; 3] = ["issues", "pulls", "new"];
+
+const API_VERSION: &str = "2022-11-28";
+
+/// Most GitHub allows per page
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Issues,
+    Pulls,
+}
+
+/// What a path below the root names; repositories are "org/repo"
+enum Target {
+    Orgs,
+    Repos(String),
+    Repo,
+    Items(String, Kind),
+    Item(String, Kind, u64),
+    New(String),
+}
+
+pub struct GithubDriver {
+    http: Arc<SharedHttpClient>,
+    endpoint: String,
+    token_env: String,
+    repos: BTreeSet<String>,
+    listing: usize,
+}
+
+impl GithubDriver {
+    pub fn new(config: &GithubDriverConfig, context: &DriverContext) -> Result<Self> {
+        let mut repos = BTreeSet::new();
+        for repo in &config.repos {
+            let valid = repo.split_once('/')
+                .is_some_and(|(org, name)| !org.is_empty() && !name.is_empty() && !name.contains('/'));
+            if !valid {
+                return Err(GnosError::Config(format!("drivers.github.repos wants \"org/repo\", not {:?}", repo)));
+            }
+            repos.insert(repo.clone());
+        }
+        info!("🐙 Serving issues and pull requests of {} GitHub repositories", repos.len());
+        Ok(Self {
+            http: context.http_for("github"),
+            endpoint: config.endpoint.trim_end_matches('/').to_string(),
+            token_env: config.token_env.clone(),
+            repos,
+            listing: config.listing.clamp(1, PAGE_SIZE),
+        })
+    }
+    
+    fn target(&self, path: &Path) -> Result<Target> {
+        let not_found = || GnosError::PathNotFound(path.display().to_string());
+        let rest = path.strip_prefix(ROOT).map_err(|_| not_found())?;
+        let names: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        
+        let (org, names) = match names.split_first() {
+            None => return Ok(Target::Orgs),
+            Some((org, names)) => (*org, names),
+        };
+        let Some((repo, names)) = names.split_first() else {
+            return match self.repos.iter().any(|known| known.split('/').next() == Some(org)) {
+                true => Ok(Target::Repos(org.to_string())),
+                false => Err(not_found()),
+            };
+        };
+        let repo = format!("{}/{}", org, repo);
+        if !self.repos.contains(&repo) {
+            return Err(not_found());
+        }
+        
+        let kind = match names.first() {
+            None => return Ok(Target::Repo),
+            Some(&"new") if names.len() == 1 => return Ok(Target::New(repo)),
+            Some(&"issues") => Kind::Issues,
+            Some(&"pulls") => Kind::Pulls,
+            Some(_) => return Err(not_found()),
+        };
+        match names[1..] {
+            [] => Ok(Target::Items(repo, kind)),
+            [file] => file.strip_suffix(".md")
+                .and_then(|number| number.parse().ok())
+                .map(|number| Target::Item(repo, kind, number))
+                .ok_or_else(not_found),
+            _ => Err(not_found()),
+        }
+    }
+    
+    /// Send an API request and return the JSON body of a successful response
+    async fn call(&self, method: Method, api: &str, body: Option<Value>, what: &str) -> Result<Value> {
+        let mut request = self.http.client().request(method.clone(), format!("{}/{}", self.endpoint, api))
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", API_VERSION);
+        match std::env::var(&self.token_env) {
+            Ok(token) => request = request.bearer_auth(token),
+            Err(_) if method != Method::GET => {
+                return Err(GnosError::PermissionDenied(format!("{} needs a GitHub token in ${}", what, self.token_env)));
+            }
+            Err(_) => {}
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        
+        let (status, body) = self.http.fetch(request).await?;
+        if !status.is_success() {
+            return Err(github_error(status, &body, what));
+        }
+        serde_json::from_slice(&body)
+            .map_err(|e| GnosError::Driver(format!("GitHub answered {} with invalid JSON: {}", what, e)))
+    }
+    
+    /// The issue or pull request, with the pull request's own fields for one
+    async fn item(&self, repo: &str, kind: Kind, number: u64, what: &str) -> Result<(Value, Option<Value>)> {
+        let issue = self.call(Method::GET, &format!("repos/{}/issues/{}", repo, number), None, what).await?;
+        // Pull requests are issues too, but each shows up only under its own directory
+        match (kind, issue.get("pull_request").is_some()) {
+            (Kind::Issues, false) => Ok((issue, None)),
+            (Kind::Pulls, true) => {
+                let pull = self.call(Method::GET, &format!("repos/{}/pulls/{}", repo, number), None, what).await?;
+                Ok((issue, Some(pull)))
+            }
+            _ => Err(GnosError::PathNotFound(what.to_string())),
+        }
+    }
+    
+    /// Every comment on an issue or pull request, oldest first
+    async fn comments(&self, repo: &str, number: u64, what: &str) -> Result<Vec<Value>> {
+        let mut comments = Vec::new();
+        for page in 1.. {
+            let api = format!("repos/{}/issues/{}/comments?per_page={}&page={}", repo, number, PAGE_SIZE, page);
+            let batch = match self.call(Method::GET, &api, None, what).await? {
+                Value::Array(batch) => batch,
+                _ => Vec::new(),
+            };
+            let last = batch.len() < PAGE_SIZE;
+            comments.extend(batch);
+            if last {
+                break;
+            }
+        }
+        Ok(comments)
+    }
+    
+    /// Open issues or pull requests, most recently updated first
+    async fn open_items(&self, repo: &str, kind: Kind, what: &str) -> Result<Vec<Value>> {
+        let api = match kind {
+            Kind::Issues => format!("repos/{}/issues?state=open&sort=updated&per_page={}", repo, self.listing),
+            Kind::Pulls => format!("repos/{}/pulls?state=open&sort=updated&direction=desc&per_page={}", repo, self.listing),
+        };
+        let Value::Array(items) = self.call(Method::GET, &api, None, what).await? else {
+            return Ok(Vec::new());
+        };
+        Ok(items.into_iter()
+            .filter(|item| kind == Kind::Pulls || item.get("pull_request").is_none())
+            .collect())
+    }
+}
+
+/// 404 and 410 (issues turned off) are `PathNotFound`, 401 and 403
+/// `PermissionDenied`, 422 `InvalidPath`; otherwise GitHub's message
+fn github_error(status: StatusCode, body: &[u8], what: &str) -> GnosError {
+    let message = serde_json::from_slice::<Value>(body).ok()
+        .and_then(|body| body["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| status.to_string());
+    match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE => GnosError::PathNotFound(what.to_string()),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            GnosError::PermissionDenied(format!("GitHub refused {}: {}", what, message))
+        }
+        StatusCode::UNPROCESSABLE_ENTITY => GnosError::InvalidPath(format!("GitHub rejected {}: {}", what, message)),
+        _ => GnosError::Driver(format!("GitHub {} failed: {}", what, message)),
+    }
+}
+
+fn text<'a>(value: &'a Value, field: &str) -> &'a str {
+    value[field].as_str().unwrap_or_default()
+}
+
+fn timestamp(value: &Value, field: &str) -> SystemTime {
+    chrono::DateTime::parse_from_rfc3339(text(value, field))
+        .map_or(SystemTime::UNIX_EPOCH, SystemTime::from)
+}
+
+/// The issue or pull request and its comments as one markdown document
+fn render(issue: &Value, pull: Option<&Value>, comments: &[Value]) -> String {
+    let state = match pull {
+        Some(pull) if pull["merged"].as_bool() == Some(true) => "merged",
+        _ => text(issue, "state"),
+    };
+    let mut out = format!("# {} (#{})\n\n", text(issue, "title"), issue["number"]);
+    out.push_str(&format!("**State:** {} · **Author:** @{} · **Opened:** {}\n",
+                          state, text(&issue["user"], "login"), text(issue, "created_at")));
+    if let Some(pull) = pull {
+        out.push_str(&format!("**Branch:** {} → {}\n", text(&pull["head"], "label"), text(&pull["base"], "ref")));
+    }
+    let labels: Vec<&str> = issue["labels"].as_array().into_iter().flatten()
+        .filter_map(|label| label["name"].as_str())
+        .collect();
+    if !labels.is_empty() {
+        out.push_str(&format!("**Labels:** {}\n", labels.join(", ")));
+    }
+    
+    let body = text(issue, "body").trim();
+    if !body.is_empty() {
+        out.push_str(&format!("\n{}\n", body));
+    }
+    for comment in comments {
+        out.push_str(&format!("\n---\n\n### @{} · {}\n\n{}\n",
+                              text(&comment["user"], "login"), text(comment, "created_at"), text(comment, "body").trim()));
+    }
+    out
+}
+
+fn directory() -> ResourceMetadata {
+    ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }
+}
+
+/// Size is unknown until rendered, so reads go straight to the driver
+fn item_metadata(issue: &Value) -> ResourceMetadata {
+    let mut metadata = ResourceMetadata {
+        last_modified: timestamp(issue, "updated_at"),
+        mime_type: Some("text/markdown".to_string()),
+        ..ResourceMetadata::default()
+    };
+    for field in ["state", "html_url"] {
+        metadata.custom_fields.insert(field.to_string(), text(issue, field).to_string());
+    }
+    metadata
+}
+
+#[async_trait]
+impl GnosDriver for GithubDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let what = path.display().to_string();
+        match self.target(path)? {
+            Target::Item(repo, kind, number) => {
+                let (issue, pull) = self.item(&repo, kind, number, &what).await?;
+                let comments = self.comments(&repo, number, &what).await?;
+                Ok(Bytes::from(render(&issue, pull.as_ref(), &comments)))
+            }
+            // Only ever written to
+            Target::New(_) => Ok(Bytes::new()),
+            _ => Err(GnosError::InvalidPath(format!("{} is a directory", what))),
+        }
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let what = path.display().to_string();
+        let markdown = std::str::from_utf8(data)
+            .map_err(|_| GnosError::InvalidPath(format!("{} takes UTF-8 markdown", what)))?
+            .trim();
+        
+        match self.target(path)? {
+            Target::Item(repo, kind, number) => {
+                if markdown.is_empty() {
+                    return Err(GnosError::InvalidPath(format!("{}: an empty comment can't be posted", what)));
+                }
+                // Comments on the conversation go through the issues API for both
+                self.item(&repo, kind, number, &what).await?;
+                let api = format!("repos/{}/issues/{}/comments", repo, number);
+                let comment = self.call(Method::POST, &api, Some(json!({ "body": markdown })), &what).await?;
+                debug!("Commented on {}#{}: {}", repo, number, text(&comment, "html_url"));
+                Ok(())
+            }
+            Target::New(repo) => {
+                let (title, body) = markdown.split_once('\n').unwrap_or((markdown, ""));
+                let title = title.trim_start_matches('#').trim();
+                if title.is_empty() {
+                    return Err(GnosError::InvalidPath(format!("{}: the first line is the issue's title", what)));
+                }
+                let api = format!("repos/{}/issues", repo);
+                let issue = self.call(Method::POST, &api, Some(json!({ "title": title, "body": body.trim() })), &what).await?;
+                info!("🐙 Opened {}#{}: {}", repo, issue["number"], title);
+                Ok(())
+            }
+            _ => Err(GnosError::InvalidPath(format!("{} is a directory", what))),
+        }
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        match self.target(path)? {
+            Target::Orgs => {
+                let orgs: BTreeSet<&str> = self.repos.iter().filter_map(|repo| repo.split('/').next()).collect();
+                Ok(orgs.into_iter().map(|org| (org.to_string(), Some(directory()))).collect())
+            }
+            Target::Repos(org) => Ok(self.repos.iter()
+                .filter_map(|repo| repo.strip_prefix(&format!("{}/", org)).map(str::to_string))
+                .map(|name| (name, Some(directory())))
+                .collect()),
+            Target::Repo => Ok(REPO_ENTRIES.iter()
+                .map(|entry| (entry.to_string(), (*entry != "new").then(directory)))
+                .collect()),
+            Target::Items(repo, kind) => {
+                let items = self.open_items(&repo, kind, &path.display().to_string()).await?;
+                Ok(items.iter()
+                    .map(|item| (format!("{}.md", item["number"]), Some(item_metadata(item))))
+                    .collect())
+            }
+            Target::Item(..) | Target::New(_) => Ok(Vec::new()),
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        match self.target(path)? {
+            Target::Item(repo, kind, number) => {
+                let (issue, _) = self.item(&repo, kind, number, &path.display().to_string()).await?;
+                Ok(item_metadata(&issue))
+            }
+            Target::New(_) => Ok(ResourceMetadata {
+                mime_type: Some("text/markdown".to_string()),
+                ..ResourceMetadata::default()
+            }),
+            _ => Ok(directory()),
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "GitHub Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Threads gain comments between reads, and their length isn't known until rendered
+        CacheMode::DirectIo
+    }
+}
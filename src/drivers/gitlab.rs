@@ -0,0 +1,484 @@
+//! `/net/gitlab/<group>/<project>`: issues, merge requests and pipelines
+//!
+//! ```text
+//! ls /net/gitlab/infra/platform/deploy/merge_requests   # 41.md  44.md ...
+//! cat /net/gitlab/infra/platform/deploy/issues/7.md      # the issue and its notes
+//! head -1 /net/gitlab/infra/platform/deploy/pipelines/90211   # success
+//! echo 'Fixed by !44' > /net/gitlab/infra/platform/deploy/issues/7.md
+//! printf '# Flaky deploy job\n\nSeen twice today\n' > /net/gitlab/infra/platform/deploy/new
+//! ```
+//!
+//! The same layout as `/net/github`: reading `<iid>.md` renders the issue
+//! or merge request with its notes, writing one posts what was written as a
+//! note, and writing `new` opens an issue titled by its first line.
+//! `pipelines/<id>` starts with the pipeline's status, followed by its ref,
+//! timing and jobs. Projects keep their full path, subgroups included, and
+//! the instance can be self-hosted; requests carry the personal access token
+//! from the environment variable the config names.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::config::{CacheMode, GitlabDriverConfig};
+use crate::drivers::context::DriverContext;
+use crate::drivers::network::SharedHttpClient;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/net/gitlab";
+
+/// Entries in every project directory
+const PROJECT_ENTRIES: [&str; 4] = ["issues", "merge_requests", "pipelines", "new"];
+
+/// Most GitLab allows per page
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Issues,
+    MergeRequests,
+}
+
+impl Kind {
+    /// Directory name, which is also the API's
+    fn dir(self) -> &'static str {
+        match self {
+            Kind::Issues => "issues",
+            Kind::MergeRequests => "merge_requests",
+        }
+    }
+    
+    /// How GitLab refers to one in text, as in #7 or !44
+    fn sigil(self) -> char {
+        match self {
+            Kind::Issues => '#',
+            Kind::MergeRequests => '!',
+        }
+    }
+}
+
+/// What a path below the root names; projects are their full path
+enum Target {
+    /// A group or subgroup leading to configured projects; "" for the root
+    Namespace(String),
+    Project,
+    Items(String, Kind),
+    Item(String, Kind, u64),
+    New(String),
+    Pipelines(String),
+    Pipeline(String, u64),
+}
+
+pub struct GitlabDriver {
+    http: Arc<SharedHttpClient>,
+    api: String,
+    token_env: String,
+    projects: BTreeSet<String>,
+    listing: usize,
+}
+
+impl GitlabDriver {
+    pub fn new(config: &GitlabDriverConfig, context: &DriverContext) -> Result<Self> {
+        let mut projects = BTreeSet::new();
+        for project in &config.projects {
+            let valid = project.contains('/') && project.split('/').all(|name| !name.is_empty());
+            if !valid {
+                return Err(GnosError::Config(format!(
+                    "drivers.gitlab.projects wants \"group/project\" or \"group/subgroup/project\", not {:?}", project,
+                )));
+            }
+            projects.insert(project.clone());
+        }
+        info!("🦊 Serving issues, merge requests and pipelines of {} GitLab projects", projects.len());
+        Ok(Self {
+            http: context.http_for("gitlab"),
+            api: format!("{}/api/v4", config.endpoint.trim_end_matches('/')),
+            token_env: config.token_env.clone(),
+            projects,
+            listing: config.listing.clamp(1, PAGE_SIZE),
+        })
+    }
+    
+    fn target(&self, path: &Path) -> Result<Target> {
+        let not_found = || GnosError::PathNotFound(path.display().to_string());
+        let rest = path.strip_prefix(ROOT).map_err(|_| not_found())?;
+        let names: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        let joined = names.join("/");
+        
+        // A group and a project never share a path, so at most one project
+        // leads the way
+        let Some(project) = self.projects.iter().find(|project| is_under(&joined, project)) else {
+            let namespace = joined.is_empty() || self.projects.iter().any(|project| is_under(project, &joined));
+            return match namespace {
+                true => Ok(Target::Namespace(joined)),
+                false => Err(not_found()),
+            };
+        };
+        let project = project.clone();
+        
+        let names = &names[project.split('/').count()..];
+        let kind = match names.first() {
+            None => return Ok(Target::Project),
+            Some(&"new") if names.len() == 1 => return Ok(Target::New(project)),
+            Some(&"pipelines") => {
+                return match names[1..] {
+                    [] => Ok(Target::Pipelines(project)),
+                    [id] => id.parse().map(|id| Target::Pipeline(project, id)).map_err(|_| not_found()),
+                    _ => Err(not_found()),
+                };
+            }
+            Some(&"issues") => Kind::Issues,
+            Some(&"merge_requests") => Kind::MergeRequests,
+            Some(_) => return Err(not_found()),
+        };
+        match names[1..] {
+            [] => Ok(Target::Items(project, kind)),
+            [file] => file.strip_suffix(".md")
+                .and_then(|iid| iid.parse().ok())
+                .map(|iid| Target::Item(project, kind, iid))
+                .ok_or_else(not_found),
+            _ => Err(not_found()),
+        }
+    }
+    
+    /// Send an API request and return the JSON body of a successful response
+    async fn call(&self, method: Method, api: &str, body: Option<Value>, what: &str) -> Result<Value> {
+        let mut request = self.http.client().request(method.clone(), format!("{}/{}", self.api, api));
+        match std::env::var(&self.token_env) {
+            Ok(token) => request = request.header("PRIVATE-TOKEN", token),
+            Err(_) if method != Method::GET => {
+                return Err(GnosError::PermissionDenied(format!("{} needs a GitLab token in ${}", what, self.token_env)));
+            }
+            Err(_) => {}
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        
+        let (status, body) = self.http.fetch(request).await?;
+        if !status.is_success() {
+            return Err(gitlab_error(status, &body, what));
+        }
+        serde_json::from_slice(&body)
+            .map_err(|e| GnosError::Driver(format!("GitLab answered {} with invalid JSON: {}", what, e)))
+    }
+    
+    /// Every entry of a paged listing; `api` already has a query string
+    async fn all_pages(&self, api: &str, what: &str) -> Result<Vec<Value>> {
+        let mut entries = Vec::new();
+        for page in 1.. {
+            let api = format!("{}&per_page={}&page={}", api, PAGE_SIZE, page);
+            let batch = match self.call(Method::GET, &api, None, what).await? {
+                Value::Array(batch) => batch,
+                _ => Vec::new(),
+            };
+            let last = batch.len() < PAGE_SIZE;
+            entries.extend(batch);
+            if last {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+    
+    async fn item(&self, project: &str, kind: Kind, iid: u64, what: &str) -> Result<Value> {
+        let api = format!("projects/{}/{}/{}", project_id(project), kind.dir(), iid);
+        self.call(Method::GET, &api, None, what).await
+    }
+    
+    /// Notes people left on an issue or merge request, oldest first; those
+    /// GitLab adds itself (label changes and the like) are left out
+    async fn notes(&self, project: &str, kind: Kind, iid: u64, what: &str) -> Result<Vec<Value>> {
+        let api = format!("projects/{}/{}/{}/notes?sort=asc&order_by=created_at", project_id(project), kind.dir(), iid);
+        let notes = self.all_pages(&api, what).await?;
+        Ok(notes.into_iter().filter(|note| note["system"].as_bool() != Some(true)).collect())
+    }
+    
+    /// Open issues or merge requests, most recently updated first
+    async fn open_items(&self, project: &str, kind: Kind, what: &str) -> Result<Vec<Value>> {
+        let api = format!("projects/{}/{}?state=opened&order_by=updated_at&sort=desc&per_page={}",
+                          project_id(project), kind.dir(), self.listing);
+        match self.call(Method::GET, &api, None, what).await? {
+            Value::Array(items) => Ok(items),
+            _ => Ok(Vec::new()),
+        }
+    }
+    
+    /// The most recent pipelines, newest first
+    async fn recent_pipelines(&self, project: &str, what: &str) -> Result<Vec<Value>> {
+        let api = format!("projects/{}/pipelines?order_by=id&sort=desc&per_page={}", project_id(project), self.listing);
+        match self.call(Method::GET, &api, None, what).await? {
+            Value::Array(pipelines) => Ok(pipelines),
+            _ => Ok(Vec::new()),
+        }
+    }
+    
+    async fn pipeline(&self, project: &str, id: u64, what: &str) -> Result<Value> {
+        let api = format!("projects/{}/pipelines/{}", project_id(project), id);
+        self.call(Method::GET, &api, None, what).await
+    }
+}
+
+/// Whether `path` is `parent` or below it, both as "a/b/c"
+fn is_under(path: &str, parent: &str) -> bool {
+    path.strip_prefix(parent).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The project's path as the API takes it in place of a numeric ID
+fn project_id(project: &str) -> String {
+    project.replace('/', "%2F")
+}
+
+/// 404 is `PathNotFound`, 401 and 403 `PermissionDenied`, 400 and 422
+/// `InvalidPath`; otherwise GitLab's message
+fn gitlab_error(status: StatusCode, body: &[u8], what: &str) -> GnosError {
+    // `message` is a string, or field names mapped to their problems
+    let message = serde_json::from_slice::<Value>(body).ok()
+        .and_then(|body| match (&body["message"], &body["error"]) {
+            (Value::String(message), _) | (Value::Null, Value::String(message)) => Some(message.clone()),
+            (Value::Null, _) => None,
+            (message, _) => Some(message.to_string()),
+        })
+        .unwrap_or_else(|| status.to_string());
+    match status {
+        StatusCode::NOT_FOUND => GnosError::PathNotFound(what.to_string()),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            GnosError::PermissionDenied(format!("GitLab refused {}: {}", what, message))
+        }
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+            GnosError::InvalidPath(format!("GitLab rejected {}: {}", what, message))
+        }
+        _ => GnosError::Driver(format!("GitLab {} failed: {}", what, message)),
+    }
+}
+
+fn text<'a>(value: &'a Value, field: &str) -> &'a str {
+    value[field].as_str().unwrap_or_default()
+}
+
+fn timestamp(value: &Value, field: &str) -> SystemTime {
+    chrono::DateTime::parse_from_rfc3339(text(value, field))
+        .map_or(SystemTime::UNIX_EPOCH, SystemTime::from)
+}
+
+/// The issue or merge request and its notes as one markdown document
+fn render(item: &Value, kind: Kind, notes: &[Value]) -> String {
+    let mut out = format!("# {} ({}{})\n\n", text(item, "title"), kind.sigil(), item["iid"]);
+    out.push_str(&format!("**State:** {} · **Author:** @{} · **Opened:** {}\n",
+                          text(item, "state"), text(&item["author"], "username"), text(item, "created_at")));
+    if kind == Kind::MergeRequests {
+        out.push_str(&format!("**Branch:** {} → {}\n", text(item, "source_branch"), text(item, "target_branch")));
+        if let Some(pipeline) = item["head_pipeline"].as_object() {
+            out.push_str(&format!("**Pipeline:** {} {}\n",
+                                  pipeline["id"], pipeline["status"].as_str().unwrap_or_default()));
+        }
+    }
+    let labels: Vec<&str> = item["labels"].as_array().into_iter().flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    if !labels.is_empty() {
+        out.push_str(&format!("**Labels:** {}\n", labels.join(", ")));
+    }
+    
+    let description = text(item, "description").trim();
+    if !description.is_empty() {
+        out.push_str(&format!("\n{}\n", description));
+    }
+    for note in notes {
+        out.push_str(&format!("\n---\n\n### @{} · {}\n\n{}\n",
+                              text(&note["author"], "username"), text(note, "created_at"), text(note, "body").trim()));
+    }
+    out
+}
+
+/// A pipeline's status on the first line, so `head -1` answers "did it
+/// pass", then its ref, timing and one line per job
+fn render_pipeline(pipeline: &Value, jobs: &[Value]) -> String {
+    let mut out = format!("{}\n", text(pipeline, "status"));
+    out.push_str(&format!("ref: {}\nsha: {}\ncreated: {}\n",
+                          text(pipeline, "ref"), text(pipeline, "sha"), text(pipeline, "created_at")));
+    if let Some(finished) = pipeline["finished_at"].as_str() {
+        out.push_str(&format!("finished: {}\n", finished));
+    }
+    if let Some(duration) = pipeline["duration"].as_u64() {
+        out.push_str(&format!("duration: {}s\n", duration));
+    }
+    out.push_str(&format!("url: {}\n", text(pipeline, "web_url")));
+    
+    if !jobs.is_empty() {
+        out.push_str("\njobs:\n");
+    }
+    for job in jobs {
+        let duration = job["duration"].as_f64().map_or_else(|| "-".to_string(), |seconds| format!("{:.0}s", seconds));
+        out.push_str(&format!("{}\t{}\t{}\t{}\n", text(job, "stage"), text(job, "name"), text(job, "status"), duration));
+    }
+    out
+}
+
+fn directory() -> ResourceMetadata {
+    ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }
+}
+
+/// Size is unknown until rendered, so reads go straight to the driver
+fn item_metadata(item: &Value, mime_type: &str) -> ResourceMetadata {
+    let mut metadata = ResourceMetadata {
+        last_modified: timestamp(item, "updated_at"),
+        mime_type: Some(mime_type.to_string()),
+        ..ResourceMetadata::default()
+    };
+    for field in ["state", "status", "web_url"] {
+        if let Some(value) = item[field].as_str() {
+            metadata.custom_fields.insert(field.to_string(), value.to_string());
+        }
+    }
+    metadata
+}
+
+#[async_trait]
+impl GnosDriver for GitlabDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let what = path.display().to_string();
+        match self.target(path)? {
+            Target::Item(project, kind, iid) => {
+                let item = self.item(&project, kind, iid, &what).await?;
+                let notes = self.notes(&project, kind, iid, &what).await?;
+                Ok(Bytes::from(render(&item, kind, &notes)))
+            }
+            Target::Pipeline(project, id) => {
+                let pipeline = self.pipeline(&project, id, &what).await?;
+                let api = format!("projects/{}/pipelines/{}/jobs?include_retried=false", project_id(&project), id);
+                let mut jobs = self.all_pages(&api, &what).await?;
+                // Jobs are created stage by stage, so their IDs follow the pipeline
+                jobs.sort_by_key(|job| job["id"].as_u64());
+                Ok(Bytes::from(render_pipeline(&pipeline, &jobs)))
+            }
+            // Only ever written to
+            Target::New(_) => Ok(Bytes::new()),
+            _ => Err(GnosError::InvalidPath(format!("{} is a directory", what))),
+        }
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let what = path.display().to_string();
+        let markdown = std::str::from_utf8(data)
+            .map_err(|_| GnosError::InvalidPath(format!("{} takes UTF-8 markdown", what)))?
+            .trim();
+        
+        match self.target(path)? {
+            Target::Item(project, kind, iid) => {
+                if markdown.is_empty() {
+                    return Err(GnosError::InvalidPath(format!("{}: an empty note can't be posted", what)));
+                }
+                let api = format!("projects/{}/{}/{}/notes", project_id(&project), kind.dir(), iid);
+                self.call(Method::POST, &api, Some(json!({ "body": markdown })), &what).await?;
+                debug!("Commented on {}{}{}", project, kind.sigil(), iid);
+                Ok(())
+            }
+            Target::New(project) => {
+                let (title, description) = markdown.split_once('\n').unwrap_or((markdown, ""));
+                let title = title.trim_start_matches('#').trim();
+                if title.is_empty() {
+                    return Err(GnosError::InvalidPath(format!("{}: the first line is the issue's title", what)));
+                }
+                let api = format!("projects/{}/issues", project_id(&project));
+                let body = json!({ "title": title, "description": description.trim() });
+                let issue = self.call(Method::POST, &api, Some(body), &what).await?;
+                info!("🦊 Opened {}#{}: {}", project, issue["iid"], title);
+                Ok(())
+            }
+            Target::Pipeline(..) => Err(GnosError::PermissionDenied(format!("{} is read-only", what))),
+            _ => Err(GnosError::InvalidPath(format!("{} is a directory", what))),
+        }
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        let what = path.display().to_string();
+        match self.target(path)? {
+            Target::Namespace(namespace) => {
+                let children: BTreeSet<&str> = self.projects.iter()
+                    .filter_map(|project| match namespace.is_empty() {
+                        true => Some(project.as_str()),
+                        false => project.strip_prefix(&format!("{}/", namespace)),
+                    })
+                    .filter_map(|rest| rest.split('/').next())
+                    .collect();
+                Ok(children.into_iter().map(|name| (name.to_string(), Some(directory()))).collect())
+            }
+            Target::Project => Ok(PROJECT_ENTRIES.iter()
+                .map(|entry| (entry.to_string(), (*entry != "new").then(directory)))
+                .collect()),
+            Target::Items(project, kind) => {
+                let items = self.open_items(&project, kind, &what).await?;
+                Ok(items.iter()
+                    .map(|item| (format!("{}.md", item["iid"]), Some(item_metadata(item, "text/markdown"))))
+                    .collect())
+            }
+            Target::Pipelines(project) => {
+                let pipelines = self.recent_pipelines(&project, &what).await?;
+                Ok(pipelines.iter()
+                    .map(|pipeline| (pipeline["id"].to_string(), Some(item_metadata(pipeline, "text/plain"))))
+                    .collect())
+            }
+            Target::Item(..) | Target::New(_) | Target::Pipeline(..) => Ok(Vec::new()),
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let what = path.display().to_string();
+        match self.target(path)? {
+            Target::Item(project, kind, iid) => {
+                let item = self.item(&project, kind, iid, &what).await?;
+                Ok(item_metadata(&item, "text/markdown"))
+            }
+            Target::Pipeline(project, id) => {
+                let pipeline = self.pipeline(&project, id, &what).await?;
+                Ok(item_metadata(&pipeline, "text/plain"))
+            }
+            Target::New(_) => Ok(ResourceMetadata {
+                mime_type: Some("text/markdown".to_string()),
+                ..ResourceMetadata::default()
+            }),
+            _ => Ok(directory()),
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "GitLab Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Threads gain notes and pipelines move on between reads, and
+        // neither's length is known until rendered
+        CacheMode::DirectIo
+    }
+}
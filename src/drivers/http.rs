@@ -1,29 +1,123 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use async_trait::async_trait;
-use crate::drivers::traits::{GnosDriver, ResourceMetadata};
-use crate::Result;
+use bytes::Bytes;
+use crate::cache::compress::ZSTD;
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::drivers::context::DriverContext;
+use crate::drivers::network::SharedHttpClient;
+use crate::config::CacheMode;
+use crate::drivers::traits::{GnosDriver, PathParams, ResourceMetadata, WriteOptions};
+use crate::{GnosError, Result};
 
-pub struct HttpDriver;
+/// HTTP Driver - `/net/http/<host>/<path>` maps to `https://<host>/<path>`
+pub struct HttpDriver {
+   http: Arc<SharedHttpClient>,
+}
 
 impl HttpDriver {
-   pub async fn new() -> Result<Self> {
-       Ok(Self)
+   pub async fn new(context: &DriverContext) -> Result<Self> {
+       Ok(Self {
+           http: context.http_for("http"),
+       })
+   }
+   
+   /// POST `data`, sent with `encoding` as its Content-Encoding if given
+   async fn post(&self, path: &Path, data: &[u8], options: &WriteOptions, encoding: Option<&str>) -> Result<()> {
+       let url = Self::url_for(path)
+           .ok_or_else(|| GnosError::InvalidPath(path.display().to_string()))?;
+       
+       let mut request = self.http.client().post(&url).body(data.to_vec());
+       if let Some(encoding) = encoding {
+           request = request.header(reqwest::header::CONTENT_ENCODING, encoding);
+       }
+       if let Some(content_type) = options.content_type {
+           request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+       }
+       for checksum in &options.checksums {
+           request = match checksum.algorithm {
+               ChecksumAlgorithm::Sha256 => request.header("repr-digest", format!("sha-256=:{}:", checksum.to_base64())),
+               ChecksumAlgorithm::Md5 => request.header("content-md5", checksum.to_base64()),
+               ChecksumAlgorithm::Crc32c => request,
+           };
+       }
+       let (status, _) = self.http.fetch(request).await?;
+       if !status.is_success() {
+           return Err(GnosError::Driver(format!("POST {} returned {}", url, status)));
+       }
+       
+       Ok(())
+   }
+   
+   fn url_for(path: &Path) -> Option<String> {
+       let rest = path.strip_prefix("/net/http").ok()?;
+       let mut components = rest.components();
+       let host = components.next()?.as_os_str().to_str()?;
+       Some(format!("https://{}/{}", host, components.as_path().to_string_lossy()))
    }
 }
 
 #[async_trait]
 impl GnosDriver for HttpDriver {
-   async fn read(&self, path: &Path) -> Result<Vec<u8>> {
-       let status = format!("🌐 GNOS HTTP Driver\n📍 Path: {}\n🔄 Status: Simulated\n💡 REST API integration coming soon!\n", path.display());
-       Ok(status.into_bytes())
+   async fn read(&self, path: &Path) -> Result<Bytes> {
+       Ok(self.read_checked(path).await?.0)
    }
    
-   async fn write(&self, _path: &Path, _data: &[u8]) -> Result<()> {
-       Ok(())
+   async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+       let Some(url) = Self::url_for(path) else {
+           let status = format!("🌐 GNOS HTTP Driver\n📍 Path: {}\n💡 Usage: cat /net/http/<host>/<path>\n", path.display());
+           return Ok((Bytes::from(status), None));
+       };
+       
+       let (status, headers, body) = self.http.fetch_with_headers(self.http.client().get(&url)).await?;
+       if !status.is_success() {
+           return Err(GnosError::Driver(format!("GET {} returned {}", url, status)));
+       }
+       
+       Ok((body, Checksum::from_headers(&headers)))
+   }
+   
+   async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+       self.post(path, data, &WriteOptions::default(), None).await
+   }
+   
+   fn upload_checksums(&self, _path: &Path) -> &[ChecksumAlgorithm] {
+       &[ChecksumAlgorithm::Sha256]
+   }
+   
+   async fn write_with(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+       // A POST response's digests describe the response, not what was stored
+       self.post(path, data, options, None).await?;
+       Ok(None)
+   }
+   
+   fn accepted_encodings(&self) -> &[&'static str] {
+       &[ZSTD]
+   }
+   
+   /// Sent with `Content-Encoding: zstd`; endpoints that can't decode it
+   /// answer 415, which fails the write
+   async fn write_encoded(&self, path: &Path, data: &[u8], encoding: &str) -> Result<()> {
+       if encoding != ZSTD {
+           return Err(GnosError::Driver(format!("{} does not accept {} payloads for {}", self.name(), encoding, path.display())));
+       }
+       let options = WriteOptions {
+           content_type: crate::mime::from_extension(path),
+           ..WriteOptions::default()
+       };
+       self.post(path, data, &options, Some(encoding)).await
+   }
+   
+   async fn materialize(&self, _path: &Path, params: &PathParams) -> Result<()> {
+       match params.get("host") {
+           Some(host) if !is_host(host) => Err(GnosError::InvalidPath(format!("{} is not a host name", host))),
+           _ => Ok(()),
+       }
    }
    
    async fn list(&self, _path: &Path) -> Result<Vec<String>> {
-       Ok(vec!["http".to_string()])
+       // Hosts and endpoints can't be enumerated
+       Ok(vec![])
    }
    
    async fn exists(&self, _path: &Path) -> Result<bool> {
@@ -39,6 +133,22 @@ impl GnosDriver for HttpDriver {
    }
    
    fn supports(&self, path: &Path) -> bool {
-       path.to_string_lossy().starts_with("/net/")
+       path.starts_with("/net") && path != Path::new("/net")
    }
+   
+   fn prefixes(&self) -> Vec<PathBuf> {
+       vec![PathBuf::from("/net")]
+   }
+   
+   fn cache_mode(&self, _path: &Path) -> CacheMode {
+       // Responses are live and their length is unknown until fetched
+       CacheMode::DirectIo
+   }
+}
+
+/// A DNS name or address, optionally with a port
+fn is_host(host: &str) -> bool {
+   !host.is_empty()
+       && !host.starts_with(['.', '-'])
+       && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
 }
@@ -0,0 +1,236 @@
+//! `/cloud/aws/lambda`: functions as files that answer what is written to them
+//!
+//! ```text
+//! ls /cloud/aws/lambda                           # functions
+//! cat /cloud/aws/lambda/thumbnail                # its configuration, as JSON
+//! exec 3<>/cloud/aws/lambda/thumbnail
+//! echo '{"key":"photos/42.png"}' >&3             # Invoke with this payload
+//! cat <&3                                        # the function's response
+//! ```
+//!
+//! A payload written through a handle is sent as a synchronous Invoke when
+//! the handle is flushed or read, and the response is what that handle
+//! reads next, so concurrent callers each get their own. `<function>:<alias>`
+//! or `<function>:<version>` invokes a qualified function. A function that
+//! fails fails the call with its error message rather than returning the
+//! error document.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_lambda::error::ProvideErrorMetadata;
+use aws_sdk_lambda::primitives::Blob;
+use bytes::Bytes;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::config::{CacheMode, CloudDriverConfig, LambdaDriverConfig};
+use crate::drivers::credentials::CloudCredentials;
+use crate::drivers::network::EgressPolicy;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/cloud/aws/lambda";
+
+/// Functions ListFunctions returns at once
+const PAGE: i32 = 50;
+
+pub struct LambdaDriver {
+    credentials: Arc<CloudCredentials>,
+    region: String,
+    endpoint: Option<String>,
+    max_list: usize,
+    egress: Arc<EgressPolicy>,
+}
+
+impl LambdaDriver {
+    pub fn new(
+        config: &LambdaDriverConfig,
+        cloud: &CloudDriverConfig,
+        credentials: Arc<CloudCredentials>,
+        egress: Arc<EgressPolicy>,
+    ) -> Self {
+        Self {
+            credentials,
+            region: config.region.clone().unwrap_or_else(|| cloud.aws.region.clone()),
+            endpoint: config.endpoint.clone(),
+            max_list: config.max_list.max(1),
+            egress,
+        }
+    }
+    
+    async fn client(&self) -> Result<aws_sdk_lambda::Client> {
+        match &self.endpoint {
+            Some(endpoint) => self.egress.check_endpoint(endpoint)?,
+            None => self.egress.check(&format!("lambda.{}.amazonaws.com", self.region))?,
+        }
+        let credentials = self.credentials.current().await?;
+        let mut config = aws_sdk_lambda::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(self.region.clone()))
+            .credentials_provider(credentials);
+        if let Some(endpoint) = &self.endpoint {
+            config = config.endpoint_url(endpoint);
+        }
+        Ok(aws_sdk_lambda::Client::from_conf(config.build()))
+    }
+    
+    /// The function `path` names, or `None` for the directory itself
+    fn function<'a>(&self, path: &'a Path) -> Result<Option<&'a str>> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let components: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        
+        match components[..] {
+            [] => Ok(None),
+            [function] => Ok(Some(function)),
+            _ => Err(GnosError::PathNotFound(path.display().to_string())),
+        }
+    }
+    
+    /// What a fresh read of a function shows: its configuration
+    async fn configuration(&self, function: &str) -> Result<Bytes> {
+        let response = self.client().await?.get_function_configuration().function_name(function).send().await
+            .map_err(|e| lambda_error(function, e))?;
+        let configuration = json!({
+            "name": response.function_name(),
+            "arn": response.function_arn(),
+            "version": response.version(),
+            "runtime": response.runtime().map(|runtime| runtime.as_str()),
+            "handler": response.handler(),
+            "description": response.description(),
+            "memory_mb": response.memory_size(),
+            "timeout_seconds": response.timeout(),
+            "state": response.state().map(|state| state.as_str()),
+            "last_modified": response.last_modified(),
+        });
+        let mut json = serde_json::to_vec_pretty(&configuration)
+            .map_err(|e| GnosError::Driver(format!("encoding {} failed: {}", function, e)))?;
+        json.push(b'\n');
+        Ok(Bytes::from(json))
+    }
+    
+    async fn list_functions(&self) -> Result<Vec<String>> {
+        let client = self.client().await?;
+        let mut functions = Vec::new();
+        let mut marker = None;
+        loop {
+            let response = client.list_functions().max_items(PAGE).set_marker(marker).send().await
+                .map_err(|e| lambda_error(ROOT, e))?;
+            functions.extend(response.functions().iter().filter_map(|function| function.function_name().map(str::to_string)));
+            marker = response.next_marker().map(str::to_string);
+            if marker.is_none() || functions.len() >= self.max_list {
+                break;
+            }
+        }
+        functions.truncate(self.max_list);
+        Ok(functions)
+    }
+}
+
+/// Missing functions are `PathNotFound`, throttling `RateLimited` and other
+/// failures driver errors
+fn lambda_error<E: ProvideErrorMetadata + std::fmt::Display>(function: &str, error: E) -> GnosError {
+    match error.code() {
+        Some("ResourceNotFoundException") => GnosError::PathNotFound(format!("{}/{}", ROOT, function)),
+        Some("TooManyRequestsException") => GnosError::RateLimited {
+            message: format!("Lambda {} is throttled", function),
+            retry_after: None,
+        },
+        _ => GnosError::Driver(format!("Lambda {} failed: {}", function, error)),
+    }
+}
+
+#[async_trait]
+impl GnosDriver for LambdaDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        match self.function(path)? {
+            Some(function) => self.configuration(function).await,
+            None => Err(GnosError::InvalidPath(format!("{} is a directory", path.display()))),
+        }
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        // Only reachable by bypassing the mount, which invokes instead
+        self.invoke(path, data).await.map(|_| ())
+    }
+    
+    fn invokes(&self, path: &Path) -> bool {
+        matches!(self.function(path), Ok(Some(_)))
+    }
+    
+    async fn invoke(&self, path: &Path, payload: &[u8]) -> Result<Bytes> {
+        let function = self.function(path)?
+            .ok_or_else(|| GnosError::InvalidPath(format!("{} is a directory", path.display())))?;
+        let payload = if payload.iter().all(u8::is_ascii_whitespace) { b"{}".as_slice() } else { payload };
+        serde_json::from_slice::<Value>(payload)
+            .map_err(|e| GnosError::InvalidPath(format!("payload for {} must be JSON: {}", function, e)))?;
+        
+        let response = self.client().await?.invoke()
+            .function_name(function)
+            .payload(Blob::new(payload))
+            .send().await
+            .map_err(|e| lambda_error(function, e))?;
+        let reply = response.payload().map_or_else(Vec::new, |payload| payload.as_ref().to_vec());
+        if let Some(kind) = response.function_error() {
+            let message = serde_json::from_slice::<Value>(&reply).ok()
+                .and_then(|error| error.get("errorMessage").and_then(Value::as_str).map(str::to_string))
+                .unwrap_or_else(|| String::from_utf8_lossy(&reply).into_owned());
+            return Err(GnosError::Driver(format!("{} failed ({}): {}", function, kind, message)));
+        }
+        
+        debug!("Invoked {}: status {}, {} bytes back", function, response.status_code(), reply.len());
+        let mut reply = reply;
+        if !reply.is_empty() && !reply.ends_with(b"\n") {
+            reply.push(b'\n');
+        }
+        Ok(Bytes::from(reply))
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        match self.function(path)? {
+            None => self.list_functions().await,
+            Some(_) => Err(GnosError::InvalidPath(format!("{} is a function", path.display()))),
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        match self.function(path)? {
+            None => Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }),
+            Some(function) => {
+                self.client().await?.get_function_configuration().function_name(function).send().await
+                    .map_err(|e| lambda_error(function, e))?;
+                Ok(ResourceMetadata { mime_type: Some("application/json".to_string()), ..ResourceMetadata::default() })
+            }
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "Lambda Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Every handle sees its own response
+        CacheMode::DirectIo
+    }
+}
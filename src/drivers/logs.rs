@@ -0,0 +1,391 @@
+//! `/cloud/aws/logs`: CloudWatch Logs groups and streams as text files
+//!
+//! ```text
+//! ls /cloud/aws/logs                                   # log groups
+//! ls /cloud/aws/logs/%2Faws%2Flambda%2Fthumbnail       # streams, newest first
+//! cat /cloud/aws/logs/app/web-1                        # its newest events
+//! tail -f /cloud/aws/logs/app/web-1                    # with follow = true
+//! ```
+//!
+//! Group and stream names may hold "/", which a path component can't, so
+//! it is shown as `%2F` and a literal "%" as `%25`. A stream reads as its
+//! newest `tail_events` events, one `<timestamp> <message>` line each. With
+//! `follow` on, a reader that reaches the end waits while the stream is
+//! polled with its forward token every `poll_seconds`, and gets each batch
+//! of new events as it arrives; followed streams keep their last
+//! `FOLLOW_BUFFER` bytes for readers that fall behind.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_cloudwatchlogs::error::ProvideErrorMetadata;
+use aws_sdk_cloudwatchlogs::types::OrderBy;
+use bytes::Bytes;
+use tracing::debug;
+
+use crate::config::{CacheMode, CloudDriverConfig, LogsDriverConfig};
+use crate::drivers::credentials::CloudCredentials;
+use crate::drivers::network::EgressPolicy;
+use crate::drivers::traits::{GnosDriver, Growth, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/cloud/aws/logs";
+
+/// Groups or streams a Describe call returns at once
+const PAGE: i32 = 50;
+
+/// Bytes of a followed stream kept for its readers
+const FOLLOW_BUFFER: usize = 1024 * 1024;
+
+/// What a path below the root names
+enum Target {
+    Groups,
+    Group(String),
+    Stream { group: String, stream: String },
+}
+
+/// Events of a followed stream, from `start` on, and where to poll next
+struct Tail {
+    start: u64,
+    data: Vec<u8>,
+    token: Option<String>,
+}
+
+impl Tail {
+    fn end(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+    
+    fn from(&self, have: u64) -> Growth {
+        let skip = have.saturating_sub(self.start).min(self.data.len() as u64) as usize;
+        Growth {
+            offset: self.start.max(have.min(self.end())),
+            data: Bytes::copy_from_slice(&self.data[skip..]),
+            growing: true,
+        }
+    }
+    
+    fn append(&mut self, text: &[u8]) {
+        self.data.extend_from_slice(text);
+        if self.data.len() > FOLLOW_BUFFER {
+            let dropped = self.data.len() - FOLLOW_BUFFER;
+            self.data.drain(..dropped);
+            self.start += dropped as u64;
+        }
+    }
+}
+
+/// One page of a stream's events, as lines
+struct Events {
+    text: Vec<u8>,
+    forward: Option<String>,
+}
+
+pub struct LogsDriver {
+    credentials: Arc<CloudCredentials>,
+    region: String,
+    endpoint: Option<String>,
+    max_list: usize,
+    tail_events: i32,
+    follow: bool,
+    poll: Duration,
+    tails: Mutex<HashMap<PathBuf, Tail>>,
+    egress: Arc<EgressPolicy>,
+}
+
+impl LogsDriver {
+    pub fn new(
+        config: &LogsDriverConfig,
+        cloud: &CloudDriverConfig,
+        credentials: Arc<CloudCredentials>,
+        egress: Arc<EgressPolicy>,
+    ) -> Self {
+        Self {
+            credentials,
+            region: config.region.clone().unwrap_or_else(|| cloud.aws.region.clone()),
+            endpoint: config.endpoint.clone(),
+            max_list: config.max_list.max(1),
+            // GetLogEvents returns at most 10,000 events at once
+            tail_events: config.tail_events.clamp(1, 10_000) as i32,
+            follow: config.follow,
+            poll: Duration::from_secs(config.poll_seconds.max(1)),
+            tails: Mutex::new(HashMap::new()),
+            egress,
+        }
+    }
+    
+    async fn client(&self) -> Result<aws_sdk_cloudwatchlogs::Client> {
+        match &self.endpoint {
+            Some(endpoint) => self.egress.check_endpoint(endpoint)?,
+            None => self.egress.check(&format!("logs.{}.amazonaws.com", self.region))?,
+        }
+        let credentials = self.credentials.current().await?;
+        let mut config = aws_sdk_cloudwatchlogs::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(self.region.clone()))
+            .credentials_provider(credentials);
+        if let Some(endpoint) = &self.endpoint {
+            config = config.endpoint_url(endpoint);
+        }
+        Ok(aws_sdk_cloudwatchlogs::Client::from_conf(config.build()))
+    }
+    
+    fn target(&self, path: &Path) -> Result<Target> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let components: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        
+        match components[..] {
+            [] => Ok(Target::Groups),
+            [group] => Ok(Target::Group(decode(group))),
+            [group, stream] => Ok(Target::Stream { group: decode(group), stream: decode(stream) }),
+            _ => Err(GnosError::PathNotFound(path.display().to_string())),
+        }
+    }
+    
+    /// The newest events of a stream, or those after `token` when polling
+    async fn events(&self, group: &str, stream: &str, token: Option<String>) -> Result<Events> {
+        let response = self.client().await?.get_log_events()
+            .log_group_name(group)
+            .log_stream_name(stream)
+            .start_from_head(token.is_some())
+            .limit(self.tail_events)
+            .set_next_token(token)
+            .send().await
+            .map_err(|e| logs_error(group, e))?;
+        
+        let mut text = Vec::new();
+        for event in response.events() {
+            let at = event.timestamp().and_then(chrono::DateTime::from_timestamp_millis).unwrap_or_default();
+            let message = event.message().unwrap_or_default().trim_end_matches(['\r', '\n']);
+            text.extend_from_slice(format!("{} {}\n", at.format("%Y-%m-%dT%H:%M:%S%.3fZ"), message).as_bytes());
+        }
+        Ok(Events { text, forward: response.next_forward_token().map(str::to_string) })
+    }
+    
+    /// Wait until the followed stream at `path` extends past `have`
+    async fn follow(&self, path: &Path, group: &str, stream: &str, have: u64) -> Result<Growth> {
+        loop {
+            let polled = {
+                let tails = self.tails.lock().unwrap();
+                match tails.get(path) {
+                    Some(tail) if tail.end() > have => return Ok(tail.from(have)),
+                    Some(tail) => Some(tail.token.clone()),
+                    None => None,
+                }
+            };
+            
+            let Some(token) = polled else {
+                // The first reader starts the stream at its newest events
+                let events = self.events(group, stream, None).await?;
+                let mut tails = self.tails.lock().unwrap();
+                tails.entry(path.to_path_buf()).or_insert_with(|| Tail { start: 0, data: events.text, token: events.forward });
+                continue;
+            };
+            let Some(token) = token else {
+                // CloudWatch always hands back a forward token; without one there is nothing to poll
+                tokio::time::sleep(self.poll).await;
+                continue;
+            };
+            
+            let events = self.events(group, stream, Some(token.clone())).await?;
+            let idle = events.text.is_empty();
+            {
+                let mut tails = self.tails.lock().unwrap();
+                // Another reader may have polled meanwhile; its page stands
+                if let Some(tail) = tails.get_mut(path).filter(|tail| tail.token.as_deref() == Some(token.as_str())) {
+                    tail.append(&events.text);
+                    tail.token = events.forward.or(Some(token));
+                }
+            }
+            if idle {
+                tokio::time::sleep(self.poll).await;
+            }
+        }
+    }
+    
+    async fn list_groups(&self) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        let client = self.client().await?;
+        let mut groups = Vec::new();
+        let mut token = None;
+        loop {
+            let response = client.describe_log_groups().limit(PAGE).set_next_token(token).send().await
+                .map_err(|e| logs_error(ROOT, e))?;
+            groups.extend(response.log_groups().iter().filter_map(|group| {
+                let metadata = ResourceMetadata {
+                    is_directory: true,
+                    last_modified: group.creation_time().map_or(SystemTime::UNIX_EPOCH, at),
+                    ..ResourceMetadata::default()
+                };
+                Some((encode(group.log_group_name()?), Some(metadata)))
+            }));
+            token = response.next_token().map(str::to_string);
+            if token.is_none() || groups.len() >= self.max_list {
+                break;
+            }
+        }
+        groups.truncate(self.max_list);
+        Ok(groups)
+    }
+    
+    async fn list_streams(&self, group: &str) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        let client = self.client().await?;
+        let mut streams = Vec::new();
+        let mut token = None;
+        loop {
+            let response = client.describe_log_streams()
+                .log_group_name(group)
+                .order_by(OrderBy::LastEventTime)
+                .descending(true)
+                .limit(PAGE)
+                .set_next_token(token)
+                .send().await
+                .map_err(|e| logs_error(group, e))?;
+            streams.extend(response.log_streams().iter().filter_map(|stream| {
+                let metadata = ResourceMetadata {
+                    last_modified: stream.last_event_timestamp().map_or(SystemTime::UNIX_EPOCH, at),
+                    mime_type: Some("text/plain".to_string()),
+                    ..ResourceMetadata::default()
+                };
+                Some((encode(stream.log_stream_name()?), Some(metadata)))
+            }));
+            token = response.next_token().map(str::to_string);
+            if token.is_none() || streams.len() >= self.max_list {
+                break;
+            }
+        }
+        streams.truncate(self.max_list);
+        Ok(streams)
+    }
+}
+
+/// A name as a path component: "/" can't appear in one
+fn encode(name: &str) -> String {
+    name.replace('%', "%25").replace('/', "%2F")
+}
+
+fn decode(component: &str) -> String {
+    component.replace("%2F", "/").replace("%2f", "/").replace("%25", "%")
+}
+
+/// A CloudWatch timestamp, in milliseconds since the epoch
+fn at(millis: i64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+/// Missing groups and streams are `PathNotFound`, throttling `RateLimited`
+/// and other failures driver errors
+fn logs_error<E: ProvideErrorMetadata + std::fmt::Display>(group: &str, error: E) -> GnosError {
+    match error.code() {
+        Some("ResourceNotFoundException") => GnosError::PathNotFound(format!("{}/{}", ROOT, encode(group))),
+        Some("ThrottlingException") => GnosError::RateLimited {
+            message: format!("CloudWatch Logs {} is throttled", group),
+            retry_after: None,
+        },
+        _ => GnosError::Driver(format!("CloudWatch Logs {} failed: {}", group, error)),
+    }
+}
+
+#[async_trait]
+impl GnosDriver for LogsDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        match self.target(path)? {
+            Target::Stream { group, stream } => {
+                let events = self.events(&group, &stream, None).await?;
+                debug!("Read {} bytes of {}/{}", events.text.len(), group, stream);
+                Ok(Bytes::from(events.text))
+            }
+            _ => Err(GnosError::InvalidPath(format!("{} is a directory", path.display()))),
+        }
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.follow && matches!(self.target(path), Ok(Target::Stream { .. }))
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        match self.target(path)? {
+            Target::Stream { group, stream } if self.follow => self.follow(path, &group, &stream, have).await,
+            _ => Ok(Growth { offset: 0, data: self.read(path).await?, growing: false }),
+        }
+    }
+    
+    async fn write(&self, path: &Path, _data: &[u8]) -> Result<()> {
+        Err(GnosError::PermissionDenied(format!("{} is read-only", path.display())))
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        match self.target(path)? {
+            Target::Groups => self.list_groups().await,
+            Target::Group(group) => self.list_streams(&group).await,
+            Target::Stream { .. } => Err(GnosError::InvalidPath(format!("{} is a stream", path.display()))),
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let directory = ResourceMetadata { is_directory: true, ..ResourceMetadata::default() };
+        match self.target(path)? {
+            Target::Groups => Ok(directory),
+            Target::Group(group) => {
+                // Fails with ResourceNotFoundException for a missing group
+                self.client().await?.describe_log_streams().log_group_name(&group).limit(1).send().await
+                    .map_err(|e| logs_error(&group, e))?;
+                Ok(directory)
+            }
+            Target::Stream { group, stream } => {
+                let response = self.client().await?.describe_log_streams()
+                    .log_group_name(&group)
+                    .log_stream_name_prefix(&stream)
+                    .limit(PAGE)
+                    .send().await
+                    .map_err(|e| logs_error(&group, e))?;
+                let found = response.log_streams().iter()
+                    .find(|candidate| candidate.log_stream_name() == Some(stream.as_str()))
+                    .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+                // A followed stream reports how far it has got, so `tail` starts near its end
+                let size = self.tails.lock().unwrap().get(path).map_or(0, Tail::end);
+                Ok(ResourceMetadata {
+                    size,
+                    last_modified: found.last_event_timestamp().map_or(SystemTime::UNIX_EPOCH, at),
+                    mime_type: Some("text/plain".to_string()),
+                    ..ResourceMetadata::default()
+                })
+            }
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "CloudWatch Logs Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Streams gain events between reads
+        CacheMode::DirectIo
+    }
+}
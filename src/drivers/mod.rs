@@ -1,30 +1,186 @@
 pub mod traits;
+pub mod conformance;
+pub mod context;
+pub mod network;
 pub mod ai;
+pub mod chat;
 pub mod cloud;
+pub mod credentials;
+pub mod docker;
+pub mod dynamodb;
+pub mod ec2;
+pub mod git;
+pub mod github;
+pub mod gitlab;
 pub mod http;
+pub mod lambda;
+pub mod logs;
+pub mod models;
+pub mod msgraph;
+pub mod patterns;
+pub mod regions;
+pub mod secrets;
+pub mod sensors;
+pub mod sftp;
+pub mod smb;
+pub mod sqs;
+pub mod tenant;
+pub mod vault;
 
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::{info, warn};
 
-pub use traits::{GnosDriver, ResourceMetadata};
-use crate::config::DriverConfig;
-use crate::Result;
+pub use traits::{
+    BatchOp, ChangeKind, ChangeStream, GnosDriver, Growth, PartPolicy, PathParams, Precondition, RemoteChange,
+    ResourceMetadata, WriteOptions,
+};
+pub use context::DriverContext;
+use credentials::CloudCredentials;
+use patterns::PatternRouter;
+use network::{EgressPolicy, SharedHttpClient};
+use regions::RegionRouter;
+use crate::bandwidth::{BandwidthLimiter, ThrottleDriver};
+use crate::checksum::{ChecksumDriver, ChecksumVerifier};
+use crate::config::{DriverConfig, TenantConfig};
+use crate::costs::{CostTracker, MeteredDriver};
+use crate::dryrun::{DryRun, DryRunDriver};
+use crate::faults::{FaultDriver, FaultInjector};
+use crate::pools::{DriverPools, PooledDriver};
+use crate::recovery::{IsolatedDriver, Rebuild, Supervisor};
+use crate::telemetry::LabelledGauge;
+use crate::tenants::tenant_of;
+use crate::{GnosError, Result};
 
 pub struct DriverRegistry {
     drivers: HashMap<String, Arc<dyn GnosDriver>>,
+    /// Each tenant's own driver instances, by tenant ID
+    tenants: HashMap<String, TenantDrivers>,
+    /// Cloud credentials in use, labelled like drivers
+    credentials: Vec<(String, Arc<CloudCredentials>)>,
+    /// Bucket alias routing, labelled like drivers
+    regions: Vec<(String, Arc<RegionRouter>)>,
+    /// Outbound hosts allowed and contacted, the configured drivers' first
+    /// and then each tenant's, by tenant ID
+    egress: Vec<(String, Arc<EgressPolicy>)>,
+    pools: Option<Arc<DriverPools>>,
+    /// Where paths go, most specific reservation first; tenants' under
+    /// their ID, the configured drivers' under ""
+    routes: HashMap<String, Vec<Route>>,
+    /// Pattern routes, consulted before `routes` and keyed like them
+    patterns: HashMap<String, PatternRouter>,
+    /// Labels of drivers added with `with_driver` rather than from config
+    plugins: HashSet<String>,
+    /// What the configured drivers were built from, keyed like `routes`
+    sources: HashMap<String, Arc<Source>>,
+    recovery: Option<Arc<Supervisor>>,
+}
+
+/// A config and the shared resources its drivers were built with, kept so
+/// one of them can be built again
+struct Source {
+    config: DriverConfig,
+    context: DriverContext,
+}
+
+/// A tenant's driver instances, labelled `<driver>@<tenant>`
+type TenantDrivers = Vec<(String, Arc<dyn GnosDriver>)>;
+
+/// Drivers by label, and the credentials and bucket routing they use
+type Loaded = (
+    HashMap<String, Arc<dyn GnosDriver>>,
+    Vec<(String, Arc<CloudCredentials>)>,
+    Vec<(String, Arc<RegionRouter>)>,
+);
+
+/// A prefix and the driver it is reserved by
+struct Route {
+    prefix: PathBuf,
+    label: String,
+    /// From `with_driver`; loses ties to configured drivers
+    plugin: bool,
+    /// The driver reserves nothing and is routed as if it reserved `/`
+    reserved: bool,
+    driver: Arc<dyn GnosDriver>,
+}
+
+/// Routes for `drivers`: longer prefixes first, then on the same prefix
+/// configured drivers before plugins, then by label
+fn route<'a>(drivers: impl Iterator<Item = (&'a String, &'a Arc<dyn GnosDriver>)>, plugins: &HashSet<String>) -> Vec<Route> {
+    let mut routes: Vec<Route> = drivers
+        .flat_map(|(label, driver)| {
+            let prefixes = driver.prefixes();
+            let reserved = !prefixes.is_empty();
+            let prefixes = if reserved { prefixes } else { vec![PathBuf::from("/")] };
+            let plugin = plugins.contains(label.split('@').next().unwrap_or(label));
+            prefixes.into_iter().map(move |prefix| Route {
+                prefix,
+                label: label.clone(),
+                plugin,
+                reserved,
+                driver: driver.clone(),
+            })
+        })
+        .collect();
+    routes.sort_by(|a, b| {
+        b.prefix.components().count().cmp(&a.prefix.components().count())
+            .then_with(|| a.prefix.cmp(&b.prefix))
+            .then_with(|| b.reserved.cmp(&a.reserved))
+            .then_with(|| a.plugin.cmp(&b.plugin))
+            .then_with(|| a.label.cmp(&b.label))
+    });
+    routes
+}
+
+/// The route that gets `routes[at]`'s prefix instead, when another driver
+/// reserves it too
+fn shadowing(routes: &[Route], at: usize) -> Option<&Route> {
+    let route = &routes[at];
+    routes[..at].iter().find(|earlier| route.reserved && earlier.reserved && earlier.prefix == route.prefix)
 }
 
 impl DriverRegistry {
     pub async fn new(config: DriverConfig) -> Result<Self> {
-        let mut drivers: HashMap<String, Arc<dyn GnosDriver>> = HashMap::new();
-        
         info!("🔌 Initializing GNOS drivers...");
         
+        let patterns = PatternRouter::new(&config.routes)?;
+        let context = DriverContext::new(&config)?;
+        
+        let (drivers, credentials, regions) = Self::load(&config, &context, None).await;
+        
+        info!("🎯 Driver registry initialized with {} drivers", drivers.len());
+        
+        let egress = vec![(String::new(), context.egress.clone())];
+        let mut registry = Self {
+            drivers,
+            tenants: HashMap::new(),
+            credentials,
+            regions,
+            egress,
+            pools: None,
+            routes: HashMap::new(),
+            patterns: HashMap::from([(String::new(), patterns)]),
+            plugins: HashSet::new(),
+            sources: HashMap::from([(String::new(), Arc::new(Source { config, context }))]),
+            recovery: None,
+        };
+        registry.route();
+        registry.report_collisions("", None);
+        Ok(registry)
+    }
+    
+    /// Build the drivers `config` enables, or with `only` just that one,
+    /// along with the credentials and bucket routing they use
+    async fn load(config: &DriverConfig, context: &DriverContext, only: Option<&str>) -> Loaded {
+        let mut drivers: HashMap<String, Arc<dyn GnosDriver>> = HashMap::new();
+        let mut credentials = Vec::new();
+        let mut regions = Vec::new();
+        let wanted = |label: &str| only.is_none_or(|only| only == label);
+        
         // Initialize AI driver
-        if config.ai.enabled {
-            match ai::AiDriver::new().await {
+        if config.ai.enabled && wanted("ai") {
+            match ai::AiDriver::new(&config.ai).await {
                 Ok(driver) => {
                     info!("✅ AI driver initialized");
                     drivers.insert("ai".to_string(), Arc::new(driver));
@@ -36,10 +192,14 @@ impl DriverRegistry {
         }
         
         // Initialize Cloud driver
-        if config.cloud.enabled {
-            match cloud::CloudDriver::new().await {
+        if config.cloud.enabled && wanted("cloud") {
+            match cloud::CloudDriver::new(&config.cloud, context).await {
                 Ok(driver) => {
                     info!("✅ Cloud driver initialized");
+                    credentials.push(("cloud".to_string(), driver.credentials()));
+                    if !config.cloud.buckets.is_empty() {
+                        regions.push(("cloud".to_string(), driver.regions()));
+                    }
                     drivers.insert("cloud".to_string(), Arc::new(driver));
                 }
                 Err(e) => {
@@ -48,9 +208,119 @@ impl DriverRegistry {
             }
         }
         
+        // Initialize DynamoDB driver, sharing the cloud driver's credentials
+        if config.dynamodb.enabled && wanted("dynamodb") {
+            match aws_credentials(config, &mut credentials).await {
+                Ok(signing) => {
+                    info!("✅ DynamoDB driver initialized");
+                    let driver = dynamodb::DynamoDbDriver::new(&config.dynamodb, &config.cloud, signing, context.egress.clone());
+                    drivers.insert("dynamodb".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize DynamoDB driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize SQS driver
+        if config.sqs.enabled && wanted("sqs") {
+            match aws_credentials(config, &mut credentials).await {
+                Ok(signing) => {
+                    info!("✅ SQS driver initialized");
+                    let driver = sqs::SqsDriver::new(&config.sqs, &config.cloud, signing, context.egress.clone());
+                    drivers.insert("sqs".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize SQS driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize Lambda driver
+        if config.lambda.enabled && wanted("lambda") {
+            match aws_credentials(config, &mut credentials).await {
+                Ok(signing) => {
+                    info!("✅ Lambda driver initialized");
+                    let driver = lambda::LambdaDriver::new(&config.lambda, &config.cloud, signing, context.egress.clone());
+                    drivers.insert("lambda".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize Lambda driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize CloudWatch Logs driver
+        if config.logs.enabled && wanted("logs") {
+            match aws_credentials(config, &mut credentials).await {
+                Ok(signing) => {
+                    info!("✅ CloudWatch Logs driver initialized");
+                    let driver = logs::LogsDriver::new(&config.logs, &config.cloud, signing, context.egress.clone());
+                    drivers.insert("logs".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize CloudWatch Logs driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize EC2 driver
+        if config.ec2.enabled && wanted("ec2") {
+            match aws_credentials(config, &mut credentials).await {
+                Ok(signing) => {
+                    info!("✅ EC2 driver initialized");
+                    let driver = ec2::Ec2Driver::new(&config.ec2, &config.cloud, signing, context.egress.clone());
+                    drivers.insert("ec2".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize EC2 driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize Secrets Manager driver
+        if config.secrets.enabled && wanted("secrets") {
+            match aws_credentials(config, &mut credentials).await {
+                Ok(signing) => {
+                    info!("✅ Secrets Manager driver initialized");
+                    let driver = secrets::SecretsDriver::new(&config.secrets, &config.cloud, signing, context.egress.clone());
+                    drivers.insert("secrets".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize Secrets Manager driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize Vault driver
+        if config.vault.enabled && wanted("vault") {
+            match vault::VaultDriver::new(&config.vault, context).await {
+                Ok(driver) => {
+                    info!("✅ Vault driver initialized");
+                    drivers.insert("vault".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize Vault driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize Microsoft Graph driver
+        if config.msgraph.enabled && wanted("msgraph") {
+            match msgraph::MsGraphDriver::new(&config.msgraph, context).await {
+                Ok(driver) => {
+                    info!("✅ Microsoft Graph driver initialized");
+                    drivers.insert("msgraph".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize Microsoft Graph driver: {}", e);
+                }
+            }
+        }
+        
         // Initialize HTTP driver
-        if config.http.enabled {
-            match http::HttpDriver::new().await {
+        if config.http.enabled && wanted("http") {
+            match http::HttpDriver::new(context).await {
                 Ok(driver) => {
                     info!("✅ HTTP driver initialized");
                     drivers.insert("http".to_string(), Arc::new(driver));
@@ -61,22 +331,444 @@ impl DriverRegistry {
             }
         }
         
-        info!("🎯 Driver registry initialized with {} drivers", drivers.len());
+        // Initialize SFTP driver
+        if config.sftp.enabled && wanted("sftp") {
+            match sftp::SftpDriver::new(&config.sftp, context) {
+                Ok(driver) => {
+                    info!("✅ SFTP driver initialized");
+                    drivers.insert("sftp".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize SFTP driver: {}", e);
+                }
+            }
+        }
         
-        Ok(Self { drivers })
+        // Initialize SMB driver
+        if config.smb.enabled && wanted("smb") {
+            match smb::SmbDriver::new(&config.smb, context) {
+                Ok(driver) => {
+                    info!("✅ SMB driver initialized");
+                    drivers.insert("smb".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize SMB driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize model management driver
+        if config.models.enabled && wanted("models") {
+            match models::ModelsDriver::new(&config.models, context).await {
+                Ok(driver) => {
+                    info!("✅ Models driver initialized");
+                    drivers.insert("models".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize Models driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize sensor driver
+        if config.sensors.enabled && wanted("sensors") {
+            match sensors::SensorDriver::new(&config.sensors).await {
+                Ok(driver) => {
+                    info!("✅ Sensors driver initialized");
+                    drivers.insert("sensors".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize Sensors driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize git repository driver
+        if config.git.enabled && wanted("git") {
+            match git::GitDriver::new(&config.git) {
+                Ok(driver) => {
+                    info!("✅ Git driver initialized");
+                    drivers.insert("git".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize Git driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize GitHub issues driver
+        if config.github.enabled && wanted("github") {
+            match github::GithubDriver::new(&config.github, context) {
+                Ok(driver) => {
+                    info!("✅ GitHub driver initialized");
+                    drivers.insert("github".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize GitHub driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize GitLab issues and pipelines driver
+        if config.gitlab.enabled && wanted("gitlab") {
+            match gitlab::GitlabDriver::new(&config.gitlab, context) {
+                Ok(driver) => {
+                    info!("✅ GitLab driver initialized");
+                    drivers.insert("gitlab".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize GitLab driver: {}", e);
+                }
+            }
+        }
+        
+        // Initialize local Docker containers driver
+        if config.docker.enabled && wanted("docker") {
+            match docker::DockerDriver::new(&config.docker) {
+                Ok(driver) => {
+                    info!("✅ Docker driver initialized");
+                    drivers.insert("docker".to_string(), Arc::new(driver));
+                }
+                Err(e) => {
+                    warn!("❌ Failed to initialize Docker driver: {}", e);
+                }
+            }
+        }
+        
+        (drivers, credentials, regions)
+    }
+    
+    /// Serve `driver` alongside the configured ones, e.g. one written with
+    /// `gnos-driver-sdk`; a driver already registered as `label` is replaced
+    pub fn with_driver(mut self, label: &str, driver: Arc<dyn GnosDriver>) -> Self {
+        if self.drivers.insert(label.to_string(), driver).is_some() {
+            warn!("🔌 Driver {} registered twice; keeping the last", label);
+        } else {
+            info!("✅ {} driver registered", label);
+        }
+        self.plugins.insert(label.to_string());
+        self.route();
+        self.report_collisions("", Some(label));
+        self
+    }
+    
+    /// Build each tenant's drivers from its own config, serving `/tenants/<id>`
+    pub async fn with_tenants(mut self, tenants: &[TenantConfig]) -> Result<Self> {
+        for tenant in tenants {
+            if !crate::tenants::is_valid_id(&tenant.id) {
+                return Err(GnosError::Config(format!("invalid tenant ID: {:?}", tenant.id)));
+            }
+            if self.tenants.contains_key(&tenant.id) {
+                return Err(GnosError::Config(format!("tenant {} is configured twice", tenant.id)));
+            }
+            
+            info!("🏢 Initializing drivers for tenant {}", tenant.id);
+            let mut registry = DriverRegistry::new(tenant.drivers.clone()).await?;
+            if let Some(patterns) = registry.patterns.remove("") {
+                self.patterns.insert(tenant.id.clone(), patterns);
+            }
+            if let Some(source) = registry.sources.remove("") {
+                self.sources.insert(tenant.id.clone(), source);
+            }
+            self.credentials.extend(registry.credentials.into_iter()
+                .map(|(name, credentials)| (format!("{}@{}", name, tenant.id), credentials)));
+            self.regions.extend(registry.regions.into_iter()
+                .map(|(name, regions)| (format!("{}@{}", name, tenant.id), regions)));
+            self.egress.extend(registry.egress.into_iter().map(|(_, egress)| (tenant.id.clone(), egress)));
+            let drivers = registry.drivers.into_iter()
+                .map(|(name, driver)| {
+                    let driver: Arc<dyn GnosDriver> = Arc::new(tenant::TenantDriver::new(&tenant.id, driver));
+                    (format!("{}@{}", name, tenant.id), driver)
+                })
+                .collect();
+            self.tenants.insert(tenant.id.clone(), drivers);
+        }
+        self.route();
+        for tenant in tenants {
+            self.report_collisions(&tenant.id, None);
+        }
+        Ok(self)
+    }
+    
+    /// Pass every driver call, tenants' included, through `faults` first
+    pub fn with_faults(mut self, faults: Arc<FaultInjector>) -> Self {
+        self.wrap_all(|label, driver| Arc::new(FaultDriver::new(label, driver, faults.clone())));
+        self
+    }
+    
+    /// Check every transfer, tenants' included, against its digests
+    pub fn with_checksums(mut self, verifier: Arc<ChecksumVerifier>) -> Self {
+        self.wrap_all(|_, driver| Arc::new(ChecksumDriver::new(driver, verifier.clone())));
+        self
+    }
+    
+    /// Count every driver's backend calls, tenants' included, for cost estimates
+    pub fn with_costs(mut self, costs: Arc<CostTracker>) -> Self {
+        self.wrap_all(|label, driver| Arc::new(MeteredDriver::new(label, driver, costs.clone())));
+        self
+    }
+    
+    /// Keep every driver's mutations, tenants' included, from reaching its backend
+    pub fn with_dry_run(mut self, dry_run: Arc<DryRun>) -> Self {
+        self.wrap_all(|label, driver| Arc::new(DryRunDriver::new(label, driver, dry_run.clone())));
+        self
+    }
+    
+    /// Pace every driver's transfers, tenants' included, under the bandwidth caps
+    pub fn with_bandwidth(mut self, limiter: Arc<BandwidthLimiter>) -> Self {
+        self.wrap_all(|label, driver| Arc::new(ThrottleDriver::new(label, driver, limiter.clone())));
+        self
+    }
+    
+    /// Run every driver's calls, tenants' included, in that driver's own pool
+    pub fn with_pools(mut self, pools: Arc<DriverPools>) -> Self {
+        self.wrap_all(|label, driver| Arc::new(PooledDriver::new(label, driver, &pools)));
+        self.pools = Some(pools);
+        self
+    }
+    
+    /// Catch panics in every driver's calls, tenants' included, degrading
+    /// just that driver; call it before the other `with_*` layers so a
+    /// restart replaces only the driver itself
+    pub fn with_recovery(mut self, supervisor: Arc<Supervisor>) -> Self {
+        let sources = self.sources.clone();
+        let plugins = self.plugins.clone();
+        self.wrap_all(|label, driver| {
+            let (name, tenant) = label.split_once('@').map_or((label, None), |(name, tenant)| (name, Some(tenant)));
+            // Plugins have no config to be built from again
+            let rebuild = sources.get(tenant.unwrap_or_default())
+                .filter(|_| !plugins.contains(name))
+                .map(|source| rebuild(source.clone(), name, tenant));
+            Arc::new(IsolatedDriver::new(label, driver, rebuild, &supervisor))
+        });
+        self.recovery = Some(supervisor);
+        self
+    }
+    
+    /// Replace every driver, tenants' included, with `wrap(label, driver)`;
+    /// labels are config names such as `cloud`, or `cloud@acme` for a tenant's
+    fn wrap_all(&mut self, wrap: impl Fn(&str, Arc<dyn GnosDriver>) -> Arc<dyn GnosDriver>) {
+        for (name, driver) in self.drivers.iter_mut() {
+            *driver = wrap(name, driver.clone());
+        }
+        for drivers in self.tenants.values_mut() {
+            for (label, driver) in drivers.iter_mut() {
+                *driver = wrap(label, driver.clone());
+            }
+        }
+        self.route();
+    }
+    
+    /// Rebuild the routing tables from the drivers as they now are
+    fn route(&mut self) {
+        let mut routes = HashMap::new();
+        routes.insert(String::new(), route(self.drivers.iter(), &self.plugins));
+        for (tenant, drivers) in &self.tenants {
+            routes.insert(tenant.clone(), route(drivers.iter().map(|(label, driver)| (label, driver)), &self.plugins));
+        }
+        self.routes = routes;
+    }
+    
+    /// Warn about prefixes reserved by more than one driver, naming the one
+    /// that gets them; with `label`, only those it is party to
+    fn report_collisions(&self, tenant: &str, label: Option<&str>) {
+        let Some(routes) = self.routes.get(tenant) else {
+            return;
+        };
+        for (at, loser) in routes.iter().enumerate() {
+            let winner = shadowing(routes, at)
+                .filter(|winner| label.is_none_or(|label| winner.label == label || loser.label == label));
+            if let Some(winner) = winner {
+                warn!("🔀 {} and {} both reserve {}; {} serves it ({})",
+                      winner.label, loser.label, loser.prefix.display(), winner.label,
+                      if winner.plugin == loser.plugin { "first by label" } else { "configured drivers before plugins" });
+            }
+        }
     }
     
     pub fn get_driver(&self, path: &Path) -> Option<Arc<dyn GnosDriver>> {
-        // Find the best matching driver for this path
-        for driver in self.drivers.values() {
-            if driver.supports(path) {
-                return Some(driver.clone());
+        // Tenant paths only ever reach that tenant's own drivers
+        let tenant = tenant_of(path);
+        let routes = self.routes.get(tenant.unwrap_or_default())?;
+        if let Some(driver) = self.pattern_route(tenant, path) {
+            return Some(driver);
+        }
+        // A driver may support paths beyond what it reserves, e.g. a model
+        // name it serves under /proc; those are matched in the same order
+        routes.iter()
+            .find(|route| path.starts_with(&route.prefix) && route.driver.supports(path))
+            .or_else(|| routes.iter().find(|route| route.driver.supports(path)))
+            .map(|route| route.driver.clone())
+    }
+    
+    /// The driver the best matching pattern route names; a tenant's rules
+    /// see paths in its own namespace and name its own drivers
+    fn pattern_route(&self, tenant: Option<&str>, path: &Path) -> Option<Arc<dyn GnosDriver>> {
+        let patterns = self.patterns.get(tenant.unwrap_or_default()).filter(|patterns| !patterns.is_empty())?;
+        match tenant {
+            None => self.drivers.get(patterns.route(path.to_str()?)?).cloned(),
+            Some(id) => {
+                let label = format!("{}@{}", patterns.route(crate::tenants::tenant_path(id, path)?.to_str()?)?, id);
+                self.tenants.get(id)?.iter()
+                    .find(|(candidate, _)| *candidate == label)
+                    .map(|(_, driver)| driver.clone())
+            }
+        }
+    }
+    
+    /// Plain-text view for `/proc/gnos/routes`: pattern routes by priority,
+    /// then every prefix in the order paths are matched against them, with a
+    /// section per tenant
+    pub fn routes_report(&self) -> String {
+        let mut tenants: Vec<&String> = self.routes.keys().collect();
+        tenants.sort();
+        let mut report = String::from("rule: patterns by priority, then longest prefix; on a tie configured drivers before plugins, then by label\n");
+        for tenant in tenants {
+            if !tenant.is_empty() {
+                report.push_str(&format!("[tenant {}]\n", tenant));
+            }
+            if let Some(patterns) = self.patterns.get(tenant).filter(|patterns| !patterns.is_empty()) {
+                report.push_str("pattern\tpriority\tdriver\tnote\n");
+                let mut rules: Vec<_> = patterns.rules().collect();
+                rules.sort_by_key(|&(_, priority, _)| std::cmp::Reverse(priority));
+                for (pattern, priority, label) in rules {
+                    let known = if tenant.is_empty() {
+                        self.drivers.contains_key(label)
+                    } else {
+                        let label = format!("{}@{}", label, tenant);
+                        self.tenants.get(tenant.as_str()).is_some_and(|drivers| drivers.iter().any(|(candidate, _)| *candidate == label))
+                    };
+                    let note = if known { "-" } else { "no such driver; falls through to prefixes" };
+                    report.push_str(&format!("{}\t{}\t{}\t{}\n", pattern, priority, label, note));
+                }
+            }
+            report.push_str("prefix\tdriver\tsource\tnote\n");
+            let routes = &self.routes[tenant];
+            for (at, route) in routes.iter().enumerate() {
+                let source = if route.plugin { "plugin" } else { "configured" };
+                let note = match shadowing(routes, at) {
+                    Some(winner) => format!("shadowed by {}", winner.label),
+                    None if !route.reserved => "reserves nothing".to_string(),
+                    None => "-".to_string(),
+                };
+                report.push_str(&format!("{}\t{}\t{}\t{}\n", route.prefix.display(), route.label, source, note));
             }
         }
-        None
+        report
+    }
+    
+    /// The driver registered as `label`, e.g. `cloud`
+    pub fn driver(&self, label: &str) -> Option<Arc<dyn GnosDriver>> {
+        self.drivers.get(label).cloned()
+    }
+    
+    /// Labels of the configured drivers, sorted
+    pub fn labels(&self) -> Vec<String> {
+        let mut labels: Vec<String> = self.drivers.keys().cloned().collect();
+        labels.sort();
+        labels
+    }
+    
+    /// The client with the configured proxy, TLS and egress policy, for
+    /// requests made outside any driver, e.g. webhooks
+    pub fn http(&self) -> Arc<SharedHttpClient> {
+        self.sources[""].context.http.clone()
     }
     
     pub fn count(&self) -> usize {
-        self.drivers.len()
+        self.drivers.len() + self.tenants.values().map(Vec::len).sum::<usize>()
     }
-}
\ No newline at end of file
+    
+    /// Plain-text view for `/proc/gnos/credentials`, one section per driver
+    pub fn credentials_report(&self) -> String {
+        self.credentials.iter()
+            .map(|(label, credentials)| format!("[{}]\n{}", label, credentials.status_report()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    
+    pub fn has_credentials(&self) -> bool {
+        !self.credentials.is_empty()
+    }
+    
+    /// Plain-text view for `/proc/gnos/regions`, one section per driver
+    pub fn regions_report(&self) -> String {
+        self.regions.iter()
+            .map(|(label, regions)| format!("[{}]\n{}", label, regions.status_report()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    
+    pub fn has_regions(&self) -> bool {
+        !self.regions.is_empty()
+    }
+    
+    /// Plain-text view for `/proc/gnos/egress`, with a section per tenant
+    pub fn egress_report(&self) -> String {
+        self.egress.iter()
+            .map(|(tenant, egress)| if tenant.is_empty() {
+                egress.status_report()
+            } else {
+                format!("[tenant {}]\n{}", tenant, egress.status_report())
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    
+    /// Plain-text view for `/proc/gnos/pools`
+    pub fn pools_report(&self) -> Option<String> {
+        self.pools.as_ref().map(|pools| pools.status_report())
+    }
+    
+    /// Pool occupancy per driver, for `/proc/gnos/metrics`
+    pub fn pool_gauges(&self) -> Vec<LabelledGauge> {
+        self.pools.as_ref().map_or_else(Vec::new, |pools| pools.gauges())
+    }
+    
+    /// Plain-text view for `/proc/gnos/recovery`
+    pub fn recovery_report(&self) -> Option<String> {
+        self.recovery.as_ref().map(|recovery| recovery.status_report())
+    }
+    
+    /// Panics and degraded state per driver, for `/proc/gnos/metrics`
+    pub fn recovery_gauges(&self) -> Vec<LabelledGauge> {
+        self.recovery.as_ref().map_or_else(Vec::new, |recovery| recovery.gauges())
+    }
+    
+    /// Labels of the drivers degraded by a panic, sorted
+    pub fn degraded(&self) -> Vec<String> {
+        self.recovery.as_ref().map_or_else(Vec::new, |recovery| recovery.degraded())
+    }
+}
+
+/// Builds the driver `name` from `source` again, as `tenant`'s if given
+fn rebuild(source: Arc<Source>, name: &str, tenant: Option<&str>) -> Rebuild {
+    let name = name.to_string();
+    let tenant = tenant.map(str::to_string);
+    Box::new(move || {
+        let (source, name, tenant) = (source.clone(), name.clone(), tenant.clone());
+        Box::pin(async move {
+            let (mut drivers, _, _) = DriverRegistry::load(&source.config, &source.context, Some(&name)).await;
+            let driver = drivers.remove(&name)
+                .ok_or_else(|| GnosError::Driver(format!("{} driver failed to initialize", name)))?;
+            Ok(match tenant {
+                Some(tenant) => Arc::new(tenant::TenantDriver::new(&tenant, driver)) as Arc<dyn GnosDriver>,
+                None => driver,
+            })
+        })
+    })
+}
+
+/// Credentials for the AWS service drivers: the cloud driver's, or one set
+/// of their own when it is off
+async fn aws_credentials(
+    config: &DriverConfig,
+    credentials: &mut Vec<(String, Arc<CloudCredentials>)>,
+) -> Result<Arc<CloudCredentials>> {
+    if let Some((_, shared)) = credentials.iter().find(|(label, _)| label == "cloud" || label == "aws") {
+        return Ok(shared.clone());
+    }
+    let signing = CloudCredentials::new(&config.cloud.aws).await?;
+    credentials.push(("aws".to_string(), signing.clone()));
+    Ok(signing)
+}
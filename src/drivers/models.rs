@@ -0,0 +1,681 @@
+//! `/proc/models`: local model management
+//!
+//! ```text
+//! ls /proc/models/available            # models that can be pulled
+//! echo llama3:8b > /proc/models/pull   # download in the background
+//! cat /proc/models/progress
+//! ls /proc/models/                     # installed models are directories
+//! rmdir /proc/models/llama3:8b         # remove the local weights
+//! ```
+//!
+//! Ollama and Hugging Face look the same from here. Hugging Face
+//! repositories are named `owner--repo`, the form the Hub's own cache uses,
+//! since a name can't hold a slash; `owner/repo` is accepted when pulling.
+//! Names with an owner go to Hugging Face, the rest to Ollama.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::{CacheMode, ModelsDriverConfig};
+use crate::drivers::context::DriverContext;
+use crate::drivers::network::SharedHttpClient;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+pub const MODELS_ROOT: &str = "/proc/models";
+
+/// Model downloads run far past the shared client's request timeout
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A model present locally
+#[derive(Debug, Clone)]
+struct InstalledModel {
+    name: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Progress callback: status, bytes done, bytes expected (0 if unknown)
+type Report<'a> = &'a (dyn Fn(&str, u64, u64) + Send + Sync);
+
+#[async_trait]
+trait ModelBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    
+    /// Whether `model` is named the way this backend names models
+    fn claims(&self, model: &str) -> bool;
+    
+    async fn available(&self) -> Result<Vec<String>>;
+    
+    async fn installed(&self) -> Result<Vec<InstalledModel>>;
+    
+    async fn pull(&self, model: &str, report: Report<'_>) -> Result<()>;
+    
+    async fn remove(&self, model: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone)]
+struct PullState {
+    backend: &'static str,
+    status: String,
+    completed: u64,
+    total: u64,
+    /// `None` while running, then the outcome
+    outcome: Option<std::result::Result<(), String>>,
+}
+
+/// Driver for `/proc/models`
+pub struct ModelsDriver {
+    backends: Vec<Arc<dyn ModelBackend>>,
+    pulls: Arc<DashMap<String, PullState>>,
+}
+
+enum Node<'a> {
+    Root,
+    Available,
+    AvailableModel(&'a str),
+    Pull,
+    Progress,
+    Model(&'a str),
+    ModelInfo(&'a str),
+}
+
+impl ModelsDriver {
+    pub async fn new(config: &ModelsDriverConfig, context: &DriverContext) -> Result<Self> {
+        let mut backends: Vec<Arc<dyn ModelBackend>> = Vec::new();
+        if let Some(url) = &config.ollama_url {
+            backends.push(Arc::new(Ollama {
+                http: context.http_for("models"),
+                url: url.trim_end_matches('/').to_string(),
+                catalog: config.ollama_catalog.clone(),
+            }));
+        }
+        if let Some(dir) = &config.huggingface_dir {
+            tokio::fs::create_dir_all(dir).await?;
+            backends.push(Arc::new(HuggingFace {
+                http: context.http_for("models"),
+                endpoint: config.huggingface_endpoint.trim_end_matches('/').to_string(),
+                dir: dir.clone(),
+                limit: config.huggingface_limit,
+                filter: config.huggingface_filter.clone(),
+                token: std::env::var(&config.huggingface_token_env).ok(),
+            }));
+        }
+        if backends.is_empty() {
+            return Err(GnosError::Driver("no model backends configured".to_string()));
+        }
+        
+        Ok(Self {
+            backends,
+            pulls: Arc::new(DashMap::new()),
+        })
+    }
+    
+    fn node(path: &Path) -> Option<Node<'_>> {
+        let rest = path.strip_prefix(MODELS_ROOT).ok()?;
+        let mut names = rest.iter().map(|name| name.to_str());
+        
+        let node = match (names.next(), names.next()) {
+            (None, _) => Node::Root,
+            (Some(Some("available")), None) => Node::Available,
+            (Some(Some("available")), Some(Some(model))) => Node::AvailableModel(model),
+            (Some(Some("pull")), None) => Node::Pull,
+            (Some(Some("progress")), None) => Node::Progress,
+            (Some(Some(model)), None) => Node::Model(model),
+            (Some(Some(model)), Some(Some("info"))) => Node::ModelInfo(model),
+            _ => return None,
+        };
+        match names.next() {
+            Some(_) => None,
+            None => Some(node),
+        }
+    }
+    
+    /// Backend that handles `model`; a lone backend handles everything
+    fn backend_for(&self, model: &str) -> Arc<dyn ModelBackend> {
+        self.backends.iter()
+            .find(|backend| backend.claims(model))
+            .unwrap_or(&self.backends[0])
+            .clone()
+    }
+    
+    async fn installed(&self) -> Result<Vec<(&'static str, InstalledModel)>> {
+        let mut models = Vec::new();
+        for backend in &self.backends {
+            match backend.installed().await {
+                Ok(installed) => models.extend(installed.into_iter().map(|model| (backend.name(), model))),
+                Err(e) => warn!("❌ Listing {} models failed: {}", backend.name(), e),
+            }
+        }
+        Ok(models)
+    }
+    
+    async fn find_installed(&self, name: &str) -> Result<(&'static str, InstalledModel)> {
+        self.installed().await?
+            .into_iter()
+            .find(|(_, model)| model.name == name)
+            .ok_or_else(|| GnosError::PathNotFound(format!("{}/{}", MODELS_ROOT, name)))
+    }
+    
+    /// Start a pull per model name; each runs in the background
+    fn start_pulls(&self, names: &str) -> Result<()> {
+        for name in names.lines().map(str::trim).filter(|name| !name.is_empty()) {
+            let name = name.replace('/', "--");
+            if name.starts_with('.') || name.contains(['\0', '\\']) {
+                return Err(GnosError::InvalidPath(format!("bad model name: {}", name)));
+            }
+            if matches!(name.as_str(), "available" | "pull" | "progress") {
+                return Err(GnosError::InvalidPath(format!("{} is reserved", name)));
+            }
+            
+            let backend = self.backend_for(&name);
+            if self.pulls.get(&name).is_some_and(|pull| pull.outcome.is_none()) {
+                return Err(GnosError::ResourceBusy(format!("{} is already being pulled", name)));
+            }
+            self.pulls.insert(name.clone(), PullState {
+                backend: backend.name(),
+                status: "starting".to_string(),
+                completed: 0,
+                total: 0,
+                outcome: None,
+            });
+            info!("📥 Pulling {} from {}", name, backend.name());
+            
+            let pulls = self.pulls.clone();
+            tokio::spawn(async move {
+                let report = |status: &str, completed: u64, total: u64| {
+                    if let Some(mut pull) = pulls.get_mut(&name) {
+                        pull.status = status.to_string();
+                        pull.completed = completed;
+                        pull.total = total;
+                    }
+                };
+                let result = backend.pull(&name, &report).await;
+                
+                match &result {
+                    Ok(()) => info!("✅ Pulled {}", name),
+                    Err(e) => warn!("❌ Pulling {} failed: {}", name, e),
+                }
+                if let Some(mut pull) = pulls.get_mut(&name) {
+                    pull.outcome = Some(result.map_err(|e| e.to_string()));
+                }
+            });
+        }
+        Ok(())
+    }
+    
+    fn progress_report(&self) -> String {
+        let mut pulls: Vec<(String, PullState)> = self.pulls.iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        pulls.sort_by(|a, b| a.0.cmp(&b.0));
+        
+        let mut report = String::new();
+        for (name, pull) in pulls {
+            let state = match &pull.outcome {
+                None => "pulling".to_string(),
+                Some(Ok(())) => "done".to_string(),
+                Some(Err(e)) => format!("failed: {}", e),
+            };
+            let amount = match pull.total {
+                0 => format!("{} bytes", pull.completed),
+                total => format!("{}/{} bytes ({}%)", pull.completed, total, pull.completed * 100 / total),
+            };
+            report.push_str(&format!("{}\t{}\t{}\t{}\t{}\n", name, pull.backend, state, amount, pull.status));
+        }
+        report
+    }
+}
+
+fn directory() -> ResourceMetadata {
+    ResourceMetadata {
+        is_directory: true,
+        ..ResourceMetadata::default()
+    }
+}
+
+#[async_trait]
+impl GnosDriver for ModelsDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let text = match Self::node(path) {
+            Some(Node::AvailableModel(name)) => format!("{}\t{}\n", name, self.backend_for(name).name()),
+            Some(Node::Pull) => format!("💡 Usage: echo <model> > {}/pull\n📖 Then: cat {}/progress\n", MODELS_ROOT, MODELS_ROOT),
+            Some(Node::Progress) => self.progress_report(),
+            Some(Node::ModelInfo(name)) => {
+                let (backend, model) = self.find_installed(name).await?;
+                let modified = chrono::DateTime::<chrono::Utc>::from(model.modified).format("%Y-%m-%dT%H:%M:%SZ");
+                format!("name: {}\nbackend: {}\nsize: {}\nmodified: {}\n", model.name, backend, model.size, modified)
+            }
+            Some(_) => return Err(GnosError::InvalidPath(format!("{} is a directory", path.display()))),
+            None => return Err(GnosError::PathNotFound(path.display().to_string())),
+        };
+        Ok(Bytes::from(text))
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        match Self::node(path) {
+            Some(Node::Pull) => {
+                let names = std::str::from_utf8(data)
+                    .map_err(|_| GnosError::InvalidPath("model names must be UTF-8".to_string()))?;
+                self.start_pulls(names)
+            }
+            _ => Err(GnosError::PermissionDenied(format!("{} is read-only", path.display()))),
+        }
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        let Some(Node::Model(name)) = Self::node(path) else {
+            return Err(GnosError::PermissionDenied(format!("can't remove {}", path.display())));
+        };
+        if self.pulls.get(name).is_some_and(|pull| pull.outcome.is_none()) {
+            return Err(GnosError::ResourceBusy(format!("{} is being pulled", name)));
+        }
+        
+        let (backend, _) = self.find_installed(name).await?;
+        let backend = self.backends.iter()
+            .find(|candidate| candidate.name() == backend)
+            .ok_or_else(|| GnosError::Driver(format!("no {} backend for {}", backend, name)))?;
+        backend.remove(name).await?;
+        self.pulls.remove(name);
+        info!("🗑️  Removed model {}", name);
+        Ok(())
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        match Self::node(path) {
+            Some(Node::Root) => {
+                let mut entries = vec![
+                    ("available".to_string(), Some(directory())),
+                    ("pull".to_string(), Some(ResourceMetadata::default())),
+                    ("progress".to_string(), Some(ResourceMetadata::default())),
+                ];
+                for (_, model) in self.installed().await? {
+                    let metadata = ResourceMetadata {
+                        last_modified: model.modified,
+                        ..directory()
+                    };
+                    entries.push((model.name, Some(metadata)));
+                }
+                Ok(entries)
+            }
+            Some(Node::Available) => {
+                let mut names = Vec::new();
+                for backend in &self.backends {
+                    match backend.available().await {
+                        Ok(available) => names.extend(available),
+                        Err(e) => warn!("❌ Listing {} catalog failed: {}", backend.name(), e),
+                    }
+                }
+                names.sort();
+                names.dedup();
+                Ok(names.into_iter().map(|name| (name, Some(ResourceMetadata::default()))).collect())
+            }
+            Some(Node::Model(name)) => {
+                self.find_installed(name).await?;
+                Ok(vec![("info".to_string(), Some(ResourceMetadata::default()))])
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        match Self::node(path) {
+            Some(Node::Root | Node::Available) => Ok(directory()),
+            Some(Node::AvailableModel(_) | Node::Pull | Node::Progress) => Ok(ResourceMetadata::default()),
+            Some(Node::Model(name)) => {
+                let (_, model) = self.find_installed(name).await?;
+                Ok(ResourceMetadata {
+                    last_modified: model.modified,
+                    ..directory()
+                })
+            }
+            Some(Node::ModelInfo(name)) => {
+                let (_, model) = self.find_installed(name).await?;
+                Ok(ResourceMetadata {
+                    last_modified: model.modified,
+                    ..ResourceMetadata::default()
+                })
+            }
+            None => Err(GnosError::PathNotFound(path.display().to_string())),
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "Models Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(MODELS_ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(MODELS_ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Every file here is generated and changes as pulls progress
+        CacheMode::DirectIo
+    }
+}
+
+/// Ollama server API
+struct Ollama {
+    http: Arc<SharedHttpClient>,
+    url: String,
+    /// Ollama's library has no listing API, so pullable models are configured
+    catalog: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaTags {
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Deserialize)]
+struct OllamaModel {
+    name: String,
+    size: u64,
+    modified_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+struct OllamaPullLine {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    total: u64,
+    #[serde(default)]
+    completed: u64,
+    error: Option<String>,
+}
+
+#[async_trait]
+impl ModelBackend for Ollama {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+    
+    fn claims(&self, model: &str) -> bool {
+        !model.contains("--")
+    }
+    
+    async fn available(&self) -> Result<Vec<String>> {
+        Ok(self.catalog.clone())
+    }
+    
+    async fn installed(&self) -> Result<Vec<InstalledModel>> {
+        let (status, body) = self.http.fetch(self.http.client().get(format!("{}/api/tags", self.url))).await?;
+        if !status.is_success() {
+            return Err(GnosError::Driver(format!("ollama returned {}", status)));
+        }
+        let tags: OllamaTags = serde_json::from_slice(&body)
+            .map_err(|e| GnosError::Driver(format!("bad ollama tags: {}", e)))?;
+        
+        Ok(tags.models.into_iter().map(|model| InstalledModel {
+            name: model.name,
+            size: model.size,
+            modified: model.modified_at.into(),
+        }).collect())
+    }
+    
+    async fn pull(&self, model: &str, report: Report<'_>) -> Result<()> {
+        let response = self.http.client()
+            .post(format!("{}/api/pull", self.url))
+            .json(&serde_json::json!({ "name": model, "stream": true }))
+            .timeout(DOWNLOAD_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| GnosError::Driver(format!("ollama pull failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(GnosError::Driver(format!("ollama pull returned {}", response.status())));
+        }
+        
+        // Progress arrives as one JSON object per line
+        let mut body = response.bytes_stream();
+        let mut pending = Vec::new();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| GnosError::Driver(format!("ollama pull interrupted: {}", e)))?;
+            pending.extend_from_slice(&chunk);
+            
+            while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                let Ok(line) = serde_json::from_slice::<OllamaPullLine>(&line) else {
+                    continue;
+                };
+                if let Some(error) = line.error {
+                    return Err(GnosError::Driver(format!("ollama: {}", error)));
+                }
+                report(&line.status, line.completed, line.total);
+            }
+        }
+        Ok(())
+    }
+    
+    async fn remove(&self, model: &str) -> Result<()> {
+        let request = self.http.client()
+            .delete(format!("{}/api/delete", self.url))
+            .json(&serde_json::json!({ "name": model }));
+        let (status, _) = self.http.fetch(request).await?;
+        match status.as_u16() {
+            200..=299 => Ok(()),
+            404 => Err(GnosError::PathNotFound(format!("{}/{}", MODELS_ROOT, model))),
+            _ => Err(GnosError::Driver(format!("ollama delete returned {}", status))),
+        }
+    }
+}
+
+/// Hugging Face Hub repositories downloaded into a local directory
+struct HuggingFace {
+    http: Arc<SharedHttpClient>,
+    endpoint: String,
+    dir: PathBuf,
+    limit: usize,
+    filter: Option<String>,
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct HubModel {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct HubRepo {
+    #[serde(default)]
+    siblings: Vec<HubFile>,
+}
+
+#[derive(Deserialize)]
+struct HubFile {
+    rfilename: String,
+    #[serde(default)]
+    size: u64,
+    /// Present for files stored in Git LFS, i.e. the weights
+    #[serde(default)]
+    lfs: Option<HubLfs>,
+}
+
+#[derive(Deserialize)]
+struct HubLfs {
+    sha256: String,
+}
+
+impl HuggingFace {
+    fn get(&self, url: String) -> reqwest::RequestBuilder {
+        let request = self.http.client().get(url);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+    
+    async fn download(&self, repo: &str, file: &HubFile, dest: &Path, done: &mut u64, total: u64, report: Report<'_>) -> Result<()> {
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        
+        let response = self.get(format!("{}/{}/resolve/main/{}", self.endpoint, repo, file.rfilename))
+            .timeout(DOWNLOAD_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| GnosError::Driver(format!("downloading {} failed: {}", file.rfilename, e)))?;
+        if !response.status().is_success() {
+            return Err(GnosError::Driver(format!("downloading {} returned {}", file.rfilename, response.status())));
+        }
+        
+        // Weights are checked against the SHA-256 the Hub lists for them
+        let expected = file.lfs.as_ref()
+            .and_then(|lfs| Checksum::from_hex(ChecksumAlgorithm::Sha256, &lfs.sha256, "lfs"));
+        let mut hasher = ring::digest::Context::new(&ring::digest::SHA256);
+        
+        let mut out = tokio::fs::File::create(dest).await?;
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| GnosError::Driver(format!("downloading {} interrupted: {}", file.rfilename, e)))?;
+            out.write_all(&chunk).await?;
+            if expected.is_some() {
+                hasher.update(&chunk);
+            }
+            *done += chunk.len() as u64;
+            report(&format!("downloading {}", file.rfilename), *done, total);
+        }
+        out.flush().await?;
+        
+        if let Some(expected) = expected {
+            let actual = hasher.finish();
+            if actual.as_ref() != expected.digest.as_slice() {
+                return Err(GnosError::ChecksumMismatch(format!(
+                    "{} of {} should be {}", file.rfilename, repo, expected
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ModelBackend for HuggingFace {
+    fn name(&self) -> &'static str {
+        "huggingface"
+    }
+    
+    fn claims(&self, model: &str) -> bool {
+        model.contains("--")
+    }
+    
+    async fn available(&self) -> Result<Vec<String>> {
+        let mut url = format!("{}/api/models?sort=downloads&direction=-1&limit={}", self.endpoint, self.limit);
+        if let Some(filter) = &self.filter {
+            url.push_str(&format!("&filter={}", filter));
+        }
+        let (status, body) = self.http.fetch(self.get(url)).await?;
+        if !status.is_success() {
+            return Err(GnosError::Driver(format!("hugging face returned {}", status)));
+        }
+        let models: Vec<HubModel> = serde_json::from_slice(&body)
+            .map_err(|e| GnosError::Driver(format!("bad hugging face listing: {}", e)))?;
+        Ok(models.into_iter().map(|model| model.id.replace('/', "--")).collect())
+    }
+    
+    async fn installed(&self) -> Result<Vec<InstalledModel>> {
+        let mut models = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Downloads in flight live in dot directories until complete
+            if name.starts_with('.') || !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            let metadata = entry.metadata().await?;
+            models.push(InstalledModel {
+                size: tree_size(&entry.path()).await,
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                name,
+            });
+        }
+        Ok(models)
+    }
+    
+    async fn pull(&self, model: &str, report: Report<'_>) -> Result<()> {
+        let repo = model.replacen("--", "/", 1);
+        let (status, body) = self.http.fetch(self.get(format!("{}/api/models/{}?blobs=true", self.endpoint, repo))).await?;
+        match status.as_u16() {
+            200..=299 => {}
+            401 | 404 => return Err(GnosError::PathNotFound(format!("no hugging face repository {}", repo))),
+            _ => return Err(GnosError::Driver(format!("hugging face returned {}", status))),
+        }
+        let manifest: HubRepo = serde_json::from_slice(&body)
+            .map_err(|e| GnosError::Driver(format!("bad hugging face manifest: {}", e)))?;
+        
+        let staging = self.dir.join(format!(".{}.partial", model));
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+        let total = manifest.siblings.iter().map(|file| file.size).sum();
+        let mut done = 0;
+        
+        for file in &manifest.siblings {
+            let relative = Path::new(&file.rfilename);
+            if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+                return Err(GnosError::Driver(format!("refusing to download {}", file.rfilename)));
+            }
+            self.download(&repo, file, &staging.join(relative), &mut done, total, report).await?;
+        }
+        
+        // Only finished downloads appear as installed
+        let dest = self.dir.join(model);
+        let _ = tokio::fs::remove_dir_all(&dest).await;
+        tokio::fs::rename(&staging, &dest).await?;
+        report("success", done, total);
+        Ok(())
+    }
+    
+    async fn remove(&self, model: &str) -> Result<()> {
+        match tokio::fs::remove_dir_all(self.dir.join(model)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(GnosError::PathNotFound(format!("{}/{}", MODELS_ROOT, model)))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Total size of the files under `dir`
+async fn tree_size(dir: &Path) -> u64 {
+    let mut size = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            match entry.metadata().await {
+                Ok(metadata) if metadata.is_dir() => pending.push(entry.path()),
+                Ok(metadata) => size += metadata.len(),
+                Err(_) => {}
+            }
+        }
+    }
+    size
+}
@@ -0,0 +1,551 @@
+//! `/cloud/msgraph`: OneDrive and SharePoint document libraries through
+//! Microsoft Graph
+//!
+//! ```text
+//! ls /cloud/msgraph                              # configured libraries
+//! ls /cloud/msgraph/finance/Reports              # folders and files
+//! cat /cloud/msgraph/finance/Reports/q3.xlsx
+//! cp budget.xlsx /cloud/msgraph/finance/Reports/
+//! ```
+//!
+//! Each library in `[[drivers.msgraph.libraries]]` is a drive: a user's
+//! OneDrive, a SharePoint site's default document library, or any drive by
+//! ID. The driver signs in as an app with client credentials.
+//!
+//! Listings and metadata come from a copy of each drive's tree kept in
+//! memory. The first listing fills it with a delta query; once the copy is
+//! older than `delta_interval_seconds`, the next one replays only the
+//! changes since the last delta link, so a large library is never rescanned
+//! whole. Writes, new folders and deletes through the mount update the copy
+//! as they go.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::{debug, info, warn};
+
+use crate::config::{CacheMode, MsGraphDriverConfig, MsGraphLibrary};
+use crate::drivers::context::DriverContext;
+use crate::drivers::network::SharedHttpClient;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/cloud/msgraph";
+
+/// App-only access to whatever the app registration was granted
+const GRAPH_SCOPE: &str = "https://graph.microsoft.com/.default";
+
+/// A token this close to expiry is replaced before use
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+/// Largest upload Graph takes in one request; bigger ones need an upload
+/// session
+const MAX_SIMPLE_UPLOAD: usize = 250 * 1024 * 1024;
+
+/// Fields the tree is kept from
+const ITEM_FIELDS: &str = "id,name,parentReference,folder,file,size,lastModifiedDateTime,eTag,webUrl,root,deleted";
+
+/// What a path below the root names
+enum Target<'a> {
+    Libraries,
+    /// A library and the path within it, empty for its root
+    Entry(&'a Library, String),
+}
+
+struct Library {
+    name: String,
+    drive_id: String,
+    tree: Mutex<Tree>,
+}
+
+/// A drive's items as of its last delta query
+#[derive(Default)]
+struct Tree {
+    items: HashMap<String, Item>,
+    /// Item IDs by parent ID and name
+    children: HashMap<String, BTreeMap<String, String>>,
+    root: Option<String>,
+    /// Where the next delta query picks up
+    delta_link: Option<String>,
+    refreshed: Option<Instant>,
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    id: String,
+    name: String,
+    parent: Option<String>,
+    folder: bool,
+    size: u64,
+    modified: SystemTime,
+    etag: Option<String>,
+    mime_type: Option<String>,
+    web_url: Option<String>,
+}
+
+impl Item {
+    fn from_graph(value: &Value) -> Option<Self> {
+        Some(Self {
+            id: value["id"].as_str()?.to_string(),
+            name: value["name"].as_str().unwrap_or_default().to_string(),
+            parent: value["parentReference"]["id"].as_str().map(str::to_string),
+            folder: value["folder"].is_object() || value["root"].is_object(),
+            size: value["size"].as_u64().unwrap_or(0),
+            modified: value["lastModifiedDateTime"].as_str()
+                .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+                .map_or(SystemTime::UNIX_EPOCH, SystemTime::from),
+            etag: value["eTag"].as_str().map(str::to_string),
+            mime_type: value["file"]["mimeType"].as_str().map(str::to_string),
+            web_url: value["webUrl"].as_str().map(str::to_string),
+        })
+    }
+    
+    fn metadata(&self) -> ResourceMetadata {
+        let mut metadata = ResourceMetadata {
+            size: if self.folder { 0 } else { self.size },
+            is_directory: self.folder,
+            last_modified: self.modified,
+            mime_type: self.mime_type.clone(),
+            etag: self.etag.clone(),
+            ..ResourceMetadata::default()
+        };
+        metadata.custom_fields.insert("id".to_string(), self.id.clone());
+        if let Some(url) = &self.web_url {
+            metadata.custom_fields.insert("web_url".to_string(), url.clone());
+        }
+        metadata
+    }
+}
+
+impl Tree {
+    /// Take in a driveItem from a delta page or a write
+    fn apply(&mut self, value: &Value) {
+        let Some(item) = Item::from_graph(value) else {
+            return;
+        };
+        if value["deleted"].is_object() {
+            self.remove(&item.id);
+            return;
+        }
+        if value["root"].is_object() {
+            self.root = Some(item.id.clone());
+        }
+        // A rename or move leaves the old entry behind otherwise
+        if let Some(old) = self.items.get(&item.id) {
+            if let Some(siblings) = old.parent.as_ref().and_then(|parent| self.children.get_mut(parent)) {
+                siblings.remove(&old.name);
+            }
+        }
+        if let Some(parent) = &item.parent {
+            if !value["root"].is_object() {
+                self.children.entry(parent.clone()).or_default().insert(item.name.clone(), item.id.clone());
+            }
+        }
+        self.items.insert(item.id.clone(), item);
+    }
+    
+    /// Drop an item and everything below it
+    fn remove(&mut self, id: &str) {
+        let Some(item) = self.items.remove(id) else {
+            return;
+        };
+        if let Some(siblings) = item.parent.as_ref().and_then(|parent| self.children.get_mut(parent)) {
+            siblings.remove(&item.name);
+        }
+        for child in self.children.remove(id).unwrap_or_default().into_values() {
+            self.remove(&child);
+        }
+    }
+    
+    /// The item at `path` within the drive, "" being its root
+    fn resolve(&self, path: &str) -> Option<&Item> {
+        let mut id = self.root.as_ref()?;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            id = self.children.get(id)?.get(name)?;
+        }
+        self.items.get(id)
+    }
+    
+    fn children_of(&self, id: &str) -> impl Iterator<Item = &Item> {
+        self.children.get(id).into_iter().flatten().filter_map(|(_, child)| self.items.get(child))
+    }
+}
+
+/// An app-only access token
+struct AppToken {
+    token: String,
+    expires: Instant,
+}
+
+pub struct MsGraphDriver {
+    http: Arc<SharedHttpClient>,
+    endpoint: String,
+    token_url: String,
+    client_id: String,
+    client_secret_env: String,
+    delta_interval: Duration,
+    token: Mutex<Option<AppToken>>,
+    libraries: Vec<Library>,
+}
+
+impl MsGraphDriver {
+    pub async fn new(config: &MsGraphDriverConfig, context: &DriverContext) -> Result<Self> {
+        if config.libraries.is_empty() {
+            return Err(GnosError::InvalidPath("drivers.msgraph.libraries lists no libraries".to_string()));
+        }
+        let mut driver = Self {
+            http: context.http_for("msgraph"),
+            endpoint: config.endpoint.trim_end_matches('/').to_string(),
+            token_url: format!("{}/{}/oauth2/v2.0/token", config.login_endpoint.trim_end_matches('/'), config.tenant_id),
+            client_id: config.client_id.clone(),
+            client_secret_env: config.client_secret_env.clone(),
+            delta_interval: Duration::from_secs(config.delta_interval_seconds),
+            token: Mutex::new(None),
+            libraries: Vec::new(),
+        };
+        
+        // Drive IDs are looked up now, so bad credentials and libraries show
+        // up at startup rather than on the first listing
+        for library in &config.libraries {
+            let drive = driver.call(driver.request(reqwest::Method::GET, &format!("{}?$select=id", drive_api(library)?)).await?,
+                                    &format!("library {}", library.name)).await?;
+            let drive_id = drive["id"].as_str()
+                .ok_or_else(|| GnosError::Driver(format!("Graph returned no drive ID for library {}", library.name)))?;
+            info!("📚 Library {} is drive {}", library.name, drive_id);
+            driver.libraries.push(Library {
+                name: library.name.clone(),
+                drive_id: drive_id.to_string(),
+                tree: Mutex::new(Tree::default()),
+            });
+        }
+        Ok(driver)
+    }
+    
+    /// A current access token, signing in again when the last is about to expire
+    async fn access_token(&self) -> Result<String> {
+        let mut cached = self.token.lock().await;
+        if let Some(token) = cached.as_ref().filter(|token| token.expires > Instant::now() + TOKEN_MARGIN) {
+            return Ok(token.token.clone());
+        }
+        
+        let secret = std::env::var(&self.client_secret_env).map_err(|_| {
+            GnosError::PermissionDenied(format!("Microsoft Graph needs the app's client secret in ${}", self.client_secret_env))
+        })?;
+        let request = self.http.client().post(&self.token_url).form(&[
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", secret.as_str()),
+            ("scope", GRAPH_SCOPE),
+            ("grant_type", "client_credentials"),
+        ]);
+        let (status, body) = self.http.fetch(request).await?;
+        let response: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        if !status.is_success() {
+            let reason = response["error_description"].as_str().unwrap_or(status.as_str());
+            return Err(GnosError::PermissionDenied(format!("Microsoft identity platform refused the app: {}", reason)));
+        }
+        let token = response["access_token"].as_str()
+            .ok_or_else(|| GnosError::PermissionDenied("Microsoft identity platform returned no access token".to_string()))?
+            .to_string();
+        let lifetime = Duration::from_secs(response["expires_in"].as_u64().unwrap_or(3600));
+        debug!("Signed in to Microsoft Graph for {}s", lifetime.as_secs());
+        *cached = Some(AppToken { token: token.clone(), expires: Instant::now() + lifetime });
+        Ok(token)
+    }
+    
+    /// A signed request to `api`, a path under the Graph endpoint or a full
+    /// URL such as a delta link
+    async fn request(&self, method: reqwest::Method, api: &str) -> Result<reqwest::RequestBuilder> {
+        let url = if api.starts_with("https://") || api.starts_with("http://") {
+            api.to_string()
+        } else {
+            format!("{}/{}", self.endpoint, api)
+        };
+        Ok(self.http.client().request(method, url).bearer_auth(self.access_token().await?))
+    }
+    
+    /// Send a request and return the JSON body of a successful response
+    async fn call(&self, request: reqwest::RequestBuilder, what: &str) -> Result<Value> {
+        let (status, body) = self.http.fetch(request).await?;
+        if !status.is_success() {
+            return Err(graph_error(status, &body, what));
+        }
+        if body.is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_slice(&body)
+            .map_err(|e| GnosError::Driver(format!("Graph answered {} with invalid JSON: {}", what, e)))
+    }
+    
+    fn target<'a>(&'a self, path: &Path) -> Result<Target<'a>> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let components: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        
+        match components.split_first() {
+            None => Ok(Target::Libraries),
+            Some((name, rest)) => {
+                let library = self.libraries.iter().find(|library| library.name == *name)
+                    .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+                Ok(Target::Entry(library, rest.join("/")))
+            }
+        }
+    }
+    
+    /// The library's tree, brought up to date first if it is older than
+    /// `delta_interval`
+    async fn tree<'a>(&self, library: &'a Library) -> Result<MutexGuard<'a, Tree>> {
+        let mut tree = library.tree.lock().await;
+        if tree.refreshed.is_some_and(|at| at.elapsed() < self.delta_interval) {
+            return Ok(tree);
+        }
+        
+        let initial = format!("drives/{}/root/delta?$select={}", library.drive_id, ITEM_FIELDS);
+        // The link is kept until a refresh succeeds, so a failed one is retried
+        // from the same point rather than from a full listing
+        let mut url = match tree.delta_link.clone() {
+            Some(link) => link,
+            None => {
+                *tree = Tree::default();
+                initial.clone()
+            }
+        };
+        let full = url == initial;
+        let mut changes = 0;
+        loop {
+            let (status, body) = self.http.fetch(self.request(reqwest::Method::GET, &url).await?).await?;
+            // An expired delta link means starting over from a full listing
+            if status == StatusCode::GONE && url != initial {
+                warn!("📚 Delta link of library {} expired; listing it again in full", library.name);
+                *tree = Tree::default();
+                url = initial.clone();
+                continue;
+            }
+            if !status.is_success() {
+                return Err(graph_error(status, &body, &format!("library {}", library.name)));
+            }
+            let page: Value = serde_json::from_slice(&body)
+                .map_err(|e| GnosError::Driver(format!("Graph answered a delta query with invalid JSON: {}", e)))?;
+            for item in page["value"].as_array().into_iter().flatten() {
+                tree.apply(item);
+                changes += 1;
+            }
+            match (page["@odata.nextLink"].as_str(), page["@odata.deltaLink"].as_str()) {
+                (Some(next), _) => url = next.to_string(),
+                (None, link) => {
+                    tree.delta_link = link.map(str::to_string);
+                    break;
+                }
+            }
+        }
+        tree.refreshed = Some(Instant::now());
+        if full {
+            info!("📚 Listed library {}: {} items", library.name, tree.items.len());
+        } else {
+            debug!("Applied {} changes to library {}", changes, library.name);
+        }
+        Ok(tree)
+    }
+    
+    /// Where a new item named like the last component of `path` goes: its
+    /// parent folder's ID and its name
+    fn parent_of(tree: &Tree, path: &str, full: &Path) -> Result<(String, String)> {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() {
+            return Err(GnosError::InvalidPath(format!("{} is a library", full.display())));
+        }
+        let parent = tree.resolve(parent)
+            .filter(|parent| parent.folder)
+            .ok_or_else(|| GnosError::PathNotFound(format!("{} has no parent folder", full.display())))?;
+        Ok((parent.id.clone(), name.to_string()))
+    }
+}
+
+/// The Graph path of a library's drive
+fn drive_api(library: &MsGraphLibrary) -> Result<String> {
+    match (&library.drive_id, &library.user, &library.site) {
+        (Some(id), None, None) => Ok(format!("drives/{}", id)),
+        (None, Some(user), None) => Ok(format!("users/{}/drive", user)),
+        // A site by host and path, e.g. contoso.sharepoint.com:/sites/finance
+        (None, None, Some(site)) if site.contains(":/") => Ok(format!("sites/{}:/drive", site.trim_end_matches('/'))),
+        (None, None, Some(site)) => Ok(format!("sites/{}/drive", site)),
+        _ => Err(GnosError::InvalidPath(format!(
+            "library {} needs exactly one of drive_id, user and site", library.name
+        ))),
+    }
+}
+
+/// `segments` appended to the Graph path `api`, each percent-encoded
+fn api_path(api: &str, segments: &[&str]) -> String {
+    let mut url = url::Url::parse("http://graph/").expect("static URL");
+    url.path_segments_mut().expect("base URL").extend(api.split('/')).extend(segments);
+    url.path().trim_start_matches('/').to_string()
+}
+
+/// 404 is `PathNotFound`, 401 and 403 `PermissionDenied`, 409 and 412
+/// `ResourceBusy`, 429 and 503 `RateLimited`; otherwise Graph's message
+fn graph_error(status: StatusCode, body: &[u8], what: &str) -> GnosError {
+    let message = serde_json::from_slice::<Value>(body).ok()
+        .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| status.to_string());
+    match status {
+        StatusCode::NOT_FOUND => GnosError::PathNotFound(what.to_string()),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            GnosError::PermissionDenied(format!("Graph refused {}: {}", what, message))
+        }
+        StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => GnosError::ResourceBusy(format!("{}: {}", what, message)),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => GnosError::RateLimited {
+            message: format!("{}: {}", what, message),
+            retry_after: None,
+        },
+        StatusCode::INSUFFICIENT_STORAGE => GnosError::QuotaExceeded(format!("{}: {}", what, message)),
+        _ => GnosError::Driver(format!("Graph {} failed: {}", what, message)),
+    }
+}
+
+#[async_trait]
+impl GnosDriver for MsGraphDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let Target::Entry(library, within) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        let id = {
+            let tree = self.tree(library).await?;
+            match tree.resolve(&within) {
+                Some(item) if item.folder => return Err(GnosError::InvalidPath(format!("{} is a directory", path.display()))),
+                Some(item) => item.id.clone(),
+                None => return Err(GnosError::PathNotFound(path.display().to_string())),
+            }
+        };
+        
+        // Graph redirects to a short-lived download URL
+        let api = api_path(&format!("drives/{}/items", library.drive_id), &[&id, "content"]);
+        let (status, body) = self.http.fetch(self.request(reqwest::Method::GET, &api).await?).await?;
+        if !status.is_success() {
+            return Err(graph_error(status, &body, &path.display().to_string()));
+        }
+        Ok(body)
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let Target::Entry(library, within) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        if data.len() > MAX_SIMPLE_UPLOAD {
+            return Err(GnosError::InvalidPath(format!(
+                "{} is {} bytes; Graph takes at most {} in one upload", path.display(), data.len(), MAX_SIMPLE_UPLOAD
+            )));
+        }
+        let (parent, name) = Self::parent_of(&*self.tree(library).await?, &within, path)?;
+        
+        let api = api_path(&format!("drives/{}/items", library.drive_id), &[&format!("{}:", parent), &format!("{}:", name), "content"]);
+        let request = self.request(reqwest::Method::PUT, &api).await?.body(data.to_vec());
+        let item = self.call(request, &path.display().to_string()).await?;
+        library.tree.lock().await.apply(&item);
+        debug!("Uploaded {} ({} bytes)", path.display(), data.len());
+        Ok(())
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        let Target::Entry(library, within) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} exists", path.display())));
+        };
+        let (parent, name) = Self::parent_of(&*self.tree(library).await?, &within, path)?;
+        
+        let api = api_path(&format!("drives/{}/items", library.drive_id), &[&parent, "children"]);
+        let request = self.request(reqwest::Method::POST, &api).await?.json(&json!({
+            "name": name,
+            "folder": {},
+            "@microsoft.graph.conflictBehavior": "fail",
+        }));
+        let item = self.call(request, &path.display().to_string()).await?;
+        library.tree.lock().await.apply(&item);
+        Ok(())
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        let Target::Entry(library, within) = self.target(path)? else {
+            return Err(GnosError::PermissionDenied(format!("{} can't be deleted", path.display())));
+        };
+        let id = match self.tree(library).await?.resolve(&within) {
+            Some(item) if item.parent.is_none() => {
+                return Err(GnosError::PermissionDenied(format!("{} is a library", path.display())));
+            }
+            Some(item) => item.id.clone(),
+            None => return Err(GnosError::PathNotFound(path.display().to_string())),
+        };
+        
+        let api = api_path(&format!("drives/{}/items", library.drive_id), &[&id]);
+        self.call(self.request(reqwest::Method::DELETE, &api).await?, &path.display().to_string()).await?;
+        library.tree.lock().await.remove(&id);
+        Ok(())
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        let (library, within) = match self.target(path)? {
+            Target::Libraries => {
+                return Ok(self.libraries.iter()
+                    .map(|library| (library.name.clone(), Some(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() })))
+                    .collect());
+            }
+            Target::Entry(library, within) => (library, within),
+        };
+        let tree = self.tree(library).await?;
+        let folder = tree.resolve(&within)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        if !folder.folder {
+            return Err(GnosError::InvalidPath(format!("{} is not a directory", path.display())));
+        }
+        Ok(tree.children_of(&folder.id)
+            .map(|item| (item.name.clone(), Some(item.metadata())))
+            .collect())
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let (library, within) = match self.target(path)? {
+            Target::Libraries => return Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }),
+            Target::Entry(library, within) => (library, within),
+        };
+        self.tree(library).await?
+            .resolve(&within)
+            .map(Item::metadata)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))
+    }
+    
+    fn name(&self) -> &'static str {
+        "Microsoft Graph Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Documents are edited in Office and by sync clients under us
+        CacheMode::DirectIo
+    }
+}
@@ -0,0 +1,678 @@
+//! Shared outbound HTTP client
+//!
+//! Every HTTP-speaking driver gets the same pooled client from the
+//! `DriverContext` instead of building its own, so TCP/TLS handshakes and
+//! DNS lookups are amortized across drivers and requests. A driver with its
+//! own `[drivers.tls.<name>]` policy gets a client of its own instead, built
+//! with that policy's roots, client certificate and minimum version; its
+//! requests to plain http:// endpoints fail before anything is sent when
+//! the policy is `https_only`.
+//!
+//! A 429, or a 503 carrying `Retry-After`, is retried after the wait the
+//! backend asked for, up to `rate_limit_retries` times and only for waits
+//! within `max_retry_after_seconds`. Past that the caller gets
+//! `GnosError::RateLimited` with the hint, which surfaces as EAGAIN and
+//! tells background work how long to hold off, instead of a bare EIO.
+//!
+//! Requests go through `network.proxy`, or the driver's own
+//! `[drivers.proxy.<name>]`, when one is set. With an egress allow-list
+//! every request, and every redirect, is checked against it before a
+//! connection is made; `/proc/gnos/egress` lists the hosts contacted and
+//! refused, so an audit can see where the mount actually talked to.
+//!
+//! Names are resolved as `network.dns` says: pinned addresses first, then
+//! the configured DNS or DNS-over-HTTPS servers, or the system resolver,
+//! with addresses ordered or filtered by family for IPv6-only networks.
+//!
+//! Drivers speaking other protocols over TCP, such as SFTP, connect with
+//! `connect_tcp`, which applies the same allow-list, resolver and proxy,
+//! tunnelling through an http:// proxy with CONNECT or a SOCKS5 one. AWS
+//! SDK clients are given `for_sdk` as their HTTP client, so their requests
+//! go out through this client too.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use aws_smithy_runtime_api::client::http::{
+    HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector,
+};
+use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+use aws_smithy_runtime_api::client::result::ConnectorError;
+use aws_smithy_runtime_api::client::runtime_components::RuntimeComponents;
+use aws_smithy_runtime_api::http::StatusCode as SdkStatusCode;
+use aws_smithy_types::byte_stream::ByteStream;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use bytes::Bytes;
+use dashmap::DashMap;
+use hickory_resolver::config::{LookupIpStrategy, NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use reqwest::StatusCode;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+use crate::config::{DnsConfig, EgressConfig, IpPreference, NetworkConfig, ProxyConfig, TlsConfig, TlsVersion};
+use crate::telemetry::RequestId;
+use crate::{GnosError, Result};
+
+/// Carries the FUSE operation's request ID to backends that log it
+pub const REQUEST_ID_HEADER: &str = "x-gnos-request-id";
+
+/// Redirects followed before giving up, as reqwest does by default
+const MAX_REDIRECTS: usize = 10;
+
+/// The hosts drivers may connect to, and a tally of those they tried
+pub struct EgressPolicy {
+    allow: Vec<String>,
+    /// Requests per host, allowed and refused
+    contacted: Mutex<BTreeMap<String, (u64, u64)>>,
+}
+
+impl EgressPolicy {
+    pub fn new(config: &EgressConfig) -> Arc<Self> {
+        Arc::new(Self {
+            allow: config.allow.iter().map(|host| host.to_ascii_lowercase()).collect(),
+            contacted: Mutex::new(BTreeMap::new()),
+        })
+    }
+    
+    /// Whether `host` is on the allow-list; "*.example.com" takes its subdomains
+    fn allows(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.allow.is_empty() || self.allow.iter().any(|pattern| match pattern.strip_prefix("*.") {
+            Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+            None => *pattern == host,
+        })
+    }
+    
+    /// Let a request to `host` through, or refuse it; either way it's counted
+    pub fn check(&self, host: &str) -> Result<()> {
+        let allowed = self.allows(host);
+        let mut contacted = self.contacted.lock().unwrap();
+        let (sent, refused) = contacted.entry(host.to_string()).or_default();
+        if allowed {
+            *sent += 1;
+            return Ok(());
+        }
+        *refused += 1;
+        warn!("🚧 Refused egress to {}, which isn't on the allow-list", host);
+        Err(GnosError::PermissionDenied(format!("egress to {} is not allowed", host)))
+    }
+    
+    /// `check` for the host of `endpoint`, for SDKs that bring their own
+    /// HTTP stack and so never pass through the shared client
+    pub fn check_endpoint(&self, endpoint: &str) -> Result<()> {
+        let url = url::Url::parse(endpoint)
+            .map_err(|e| GnosError::Driver(format!("Invalid endpoint {}: {}", endpoint, e)))?;
+        self.check(url.host_str().unwrap_or_default())
+    }
+    
+    /// Plain-text view for `/proc/gnos/egress`
+    pub fn status_report(&self) -> String {
+        let mut report = if self.allow.is_empty() {
+            "allow: *\n".to_string()
+        } else {
+            format!("allow: {}\n", self.allow.join(", "))
+        };
+        report.push_str("host\trequests\trefused\n");
+        for (host, (sent, refused)) in self.contacted.lock().unwrap().iter() {
+            report.push_str(&format!("{}\t{}\t{}\n", host, sent, refused));
+        }
+        report
+    }
+}
+
+pub struct SharedHttpClient {
+    client: reqwest::Client,
+    host_permits: DashMap<String, Arc<Semaphore>>,
+    max_connections_per_host: usize,
+    https_only: bool,
+    rate_limit_retries: u32,
+    max_retry_after: Duration,
+    egress: Arc<EgressPolicy>,
+    resolver: CachingResolver,
+    proxy: Option<ProxyConfig>,
+}
+
+impl SharedHttpClient {
+    pub fn new(config: &NetworkConfig, tls: &TlsConfig, proxy: Option<&ProxyConfig>, egress: Arc<EgressPolicy>) -> Result<Self> {
+        let resolver = CachingResolver::new(config)?;
+        
+        let builder = reqwest::Client::builder()
+            .user_agent(format!("gnos/{}", crate::VERSION))
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .connect_timeout(Duration::from_secs(config.connect_timeout_seconds))
+            .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_seconds))
+            .pool_max_idle_per_host(config.pool_max_idle_per_host)
+            .tcp_keepalive(Duration::from_secs(config.tcp_keepalive_seconds))
+            .http2_adaptive_window(config.http2_adaptive_window)
+            .dns_resolver(Arc::new(resolver.clone()))
+            .redirect(redirects(egress.clone()));
+        let client = with_proxy(with_tls(builder, tls)?, proxy)?
+            .build()
+            .map_err(|e| GnosError::Driver(format!("Failed to build HTTP client: {}", e)))?;
+        
+        Ok(Self {
+            client,
+            host_permits: DashMap::new(),
+            max_connections_per_host: std::cmp::max(config.max_connections_per_host, 1),
+            https_only: tls.https_only,
+            rate_limit_retries: config.rate_limit_retries,
+            max_retry_after: Duration::from_secs(config.max_retry_after_seconds),
+            egress,
+            resolver,
+            proxy: proxy.cloned(),
+        })
+    }
+    
+    /// A TCP connection to `host`:`port` for a protocol other than HTTP,
+    /// made the way this client's requests are: checked against the egress
+    /// allow-list, through the proxy unless `no_proxy` exempts the host, and
+    /// otherwise to the addresses `network.dns` resolves it to
+    pub async fn connect_tcp(&self, host: &str, port: u16, timeout: Duration) -> Result<TcpStream> {
+        self.egress.check(host)?;
+        let connect = async {
+            match self.proxy.as_ref().filter(|proxy| !bypasses(proxy, host)) {
+                Some(proxy) => tunnel(proxy, &self.resolver, host, port).await,
+                None => connect_any(&self.resolver.addresses(host).await?, port, host).await,
+            }
+        };
+        tokio::time::timeout(timeout, connect).await
+            .map_err(|_| GnosError::Unreachable(format!("{}:{}: timed out connecting", host, port)))?
+    }
+    
+    /// The underlying pooled client, for building requests
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+    
+    /// This client as an AWS SDK client's HTTP client
+    pub fn for_sdk(self: &Arc<Self>) -> SdkHttpClient {
+        SdkHttpClient(self.clone())
+    }
+    
+    /// Send a request within the per-host connection limit and collect the body
+    pub async fn fetch(&self, request: reqwest::RequestBuilder) -> Result<(StatusCode, Bytes)> {
+        let (status, _, body) = self.fetch_with_headers(request).await?;
+        Ok((status, body))
+    }
+    
+    /// `fetch`, also returning the response headers, e.g. to verify the body
+    /// against a digest header
+    pub async fn fetch_with_headers(&self, request: reqwest::RequestBuilder) -> Result<(StatusCode, HeaderMap, Bytes)> {
+        let mut request = request.build()
+            .map_err(|e| GnosError::Driver(format!("Invalid HTTP request: {}", e)))?;
+        if let Some(id) = RequestId::current() {
+            if let Ok(value) = HeaderValue::from_str(&id.to_string()) {
+                request.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+        }
+        let host = request.url().host_str().unwrap_or_default().to_string();
+        if self.https_only && request.url().scheme() != "https" {
+            return Err(GnosError::PermissionDenied(format!("{} is not HTTPS, which this driver's TLS policy requires", request.url())));
+        }
+        self.egress.check(&host)?;
+        let target = format!("{} {}", request.method(), request.url());
+        
+        let mut retries = 0;
+        loop {
+            // A streamed body can't be sent twice, so such requests get one attempt
+            let next = if retries < self.rate_limit_retries { request.try_clone() } else { None };
+            let (status, headers, body) = self.send(&host, request).await?;
+            if status != StatusCode::TOO_MANY_REQUESTS
+                && !(status == StatusCode::SERVICE_UNAVAILABLE && headers.contains_key(RETRY_AFTER))
+            {
+                return Ok((status, headers, body));
+            }
+            
+            let retry_after = retry_after(&headers);
+            match (next, retry_after) {
+                (Some(next), Some(wait)) if wait <= self.max_retry_after => {
+                    retries += 1;
+                    warn!("⏳ {} rate limited {} (attempt {}); retrying in {:?}", host, target, retries, wait);
+                    tokio::time::sleep(wait).await;
+                    request = next;
+                }
+                _ => {
+                    return Err(GnosError::RateLimited {
+                        message: format!("{} returned {}", target, status),
+                        retry_after,
+                    });
+                }
+            }
+        }
+    }
+    
+    /// One attempt, within the per-host connection limit
+    async fn send(&self, host: &str, request: reqwest::Request) -> Result<(StatusCode, HeaderMap, Bytes)> {
+        let permits = self.host_permits
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_connections_per_host)))
+            .clone();
+        let _permit = permits.acquire_owned().await
+            .map_err(|_| GnosError::ResourceBusy(host.to_string()))?;
+        
+        debug!("HTTP {} {}", request.method(), request.url());
+        
+        let response = self.client.execute(request).await.map_err(|e| {
+            // Offline mode keeps working from local state when a backend can't be reached
+            if e.is_connect() || e.is_timeout() {
+                GnosError::Unreachable(format!("{}: {}", host, e))
+            } else {
+                GnosError::Driver(format!("HTTP request to {} failed: {}", host, e))
+            }
+        })?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await
+            .map_err(|e| GnosError::Driver(format!("Reading response from {} failed: {}", host, e)))?;
+        
+        Ok((status, headers, body))
+    }
+}
+
+/// Sends an AWS SDK's requests with `fetch_with_headers`, under the same
+/// proxy, TLS policy, resolver, allow-list and per-host limit as the rest
+#[derive(Clone)]
+pub struct SdkHttpClient(Arc<SharedHttpClient>);
+
+impl fmt::Debug for SdkHttpClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SdkHttpClient")
+    }
+}
+
+impl HttpClient for SdkHttpClient {
+    fn http_connector(&self, _settings: &HttpConnectorSettings, _components: &RuntimeComponents) -> SharedHttpConnector {
+        SharedHttpConnector::new(self.clone())
+    }
+}
+
+impl HttpConnector for SdkHttpClient {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let http = self.0.clone();
+        HttpConnectorFuture::new(async move {
+            let method = reqwest::Method::from_bytes(request.method().as_bytes())
+                .map_err(|e| ConnectorError::user(e.into()))?;
+            let mut builder = http.client().request(method, request.uri());
+            for (name, value) in request.headers() {
+                builder = builder.header(name, value);
+            }
+            // Bodies are sent whole, as `fetch_with_headers` may retry them
+            let body = ByteStream::new(request.into_body()).collect().await
+                .map_err(|e| ConnectorError::io(e.into()))?;
+            
+            let (status, headers, body) = http.fetch_with_headers(builder.body(body.into_bytes())).await
+                .map_err(|e| match e {
+                    GnosError::Unreachable(_) => ConnectorError::io(e.into()),
+                    e => ConnectorError::other(e.into(), None),
+                })?;
+            let status = SdkStatusCode::try_from(status.as_u16()).map_err(|e| ConnectorError::other(e.into(), None))?;
+            let mut response = HttpResponse::new(status, body.into());
+            for (name, value) in &headers {
+                if let Ok(value) = value.to_str() {
+                    response.headers_mut().append(name.as_str().to_string(), value.to_string());
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// The wait `Retry-After` asks for, given as seconds or as an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    // A date already past means "now"
+    Some((at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Follow redirects only to hosts the egress policy allows
+fn redirects(egress: Arc<EgressPolicy>) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error(format!("more than {} redirects", MAX_REDIRECTS));
+        }
+        let host = attempt.url().host_str().unwrap_or_default().to_string();
+        match egress.check(&host) {
+            Ok(()) => attempt.follow(),
+            Err(e) => attempt.error(e.to_string()),
+        }
+    })
+}
+
+/// Send a client's requests through `proxy`, if there is one
+fn with_proxy(builder: reqwest::ClientBuilder, proxy: Option<&ProxyConfig>) -> Result<reqwest::ClientBuilder> {
+    let Some(proxy) = proxy else {
+        return Ok(builder);
+    };
+    let mut all = reqwest::Proxy::all(&proxy.url)
+        // The URL may carry the proxy's password, so it stays out of the error
+        .map_err(|e| GnosError::Driver(format!("Invalid proxy URL: {}", e)))?;
+    if !proxy.no_proxy.is_empty() {
+        all = all.no_proxy(reqwest::NoProxy::from_string(&proxy.no_proxy.join(",")));
+    }
+    Ok(builder.proxy(all))
+}
+
+/// Apply a driver's TLS policy to a client under construction
+fn with_tls(mut builder: reqwest::ClientBuilder, tls: &TlsConfig) -> Result<reqwest::ClientBuilder> {
+    let read = |path: &std::path::Path| std::fs::read(path)
+        .map_err(|e| GnosError::Driver(format!("Reading {}: {}", path.display(), e)));
+    
+    builder = builder
+        .tls_built_in_root_certs(tls.system_roots)
+        .https_only(tls.https_only);
+    if let Some(bundle) = &tls.ca_bundle {
+        let certificates = reqwest::Certificate::from_pem_bundle(&read(bundle)?)
+            .map_err(|e| GnosError::Driver(format!("CA bundle {}: {}", bundle.display(), e)))?;
+        if certificates.is_empty() {
+            return Err(GnosError::Driver(format!("CA bundle {} holds no certificates", bundle.display())));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => {
+            let identity = reqwest::Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+                .map_err(|e| GnosError::Driver(format!("Client certificate {}: {}", cert.display(), e)))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => return Err(GnosError::Driver("client_cert and client_key must be set together".to_string())),
+    }
+    if let Some(version) = tls.min_version {
+        builder = builder.min_tls_version(match version {
+            TlsVersion::Tls12 => reqwest::tls::Version::TLS_1_2,
+            TlsVersion::Tls13 => reqwest::tls::Version::TLS_1_3,
+        });
+    }
+    Ok(builder)
+}
+
+/// Whether `proxy.no_proxy` has `host` reached directly
+fn bypasses(proxy: &ProxyConfig, host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    proxy.no_proxy.iter().any(|entry| {
+        let entry = entry.trim().to_ascii_lowercase();
+        let domain = entry.trim_start_matches('.');
+        entry == "*" || host == domain || host.ends_with(&format!(".{}", domain))
+    })
+}
+
+/// The first of `addrs` that takes a connection on `port`
+async fn connect_any(addrs: &[SocketAddr], port: u16, host: &str) -> Result<TcpStream> {
+    let mut failure = format!("{} has no address", host);
+    for addr in addrs {
+        match TcpStream::connect(SocketAddr::new(addr.ip(), port)).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => failure = format!("{}: {}", addr.ip(), e),
+        }
+    }
+    Err(GnosError::Unreachable(format!("{}:{}: {}", host, port, failure)))
+}
+
+/// A connection to `host`:`port` through `proxy`, an HTTP proxy taking
+/// CONNECT or a SOCKS5 one; socks5h:// leaves resolving `host` to the proxy
+async fn tunnel(proxy: &ProxyConfig, resolver: &CachingResolver, host: &str, port: u16) -> Result<TcpStream> {
+    // The URL may carry the proxy's password, so it stays out of errors
+    let url = url::Url::parse(&proxy.url).map_err(|e| GnosError::Config(format!("Invalid proxy URL: {}", e)))?;
+    let proxy_host = url.host_str().map(|host| host.trim_matches(['[', ']']))
+        .ok_or_else(|| GnosError::Config("proxy URL has no host".to_string()))?;
+    let proxy_port = url.port().unwrap_or(if url.scheme() == "http" { 80 } else { 1080 });
+    let failed = |e: &dyn fmt::Display| GnosError::Unreachable(format!("proxy {}:{}: {}", proxy_host, proxy_port, e));
+    let (user, password) = (url.username(), url.password().unwrap_or_default());
+    
+    let mut stream = connect_any(&resolver.addresses(proxy_host).await?, proxy_port, proxy_host).await?;
+    match url.scheme() {
+        "http" => {
+            let authority = match host.parse::<IpAddr>() {
+                Ok(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+                _ => format!("{}:{}", host, port),
+            };
+            let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+            if !user.is_empty() {
+                request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", STANDARD.encode(format!("{}:{}", user, password))));
+            }
+            request.push_str("\r\n");
+            stream.write_all(request.as_bytes()).await.map_err(|e| failed(&e))?;
+            
+            // The tunnel starts right after the reply's blank line
+            let mut reply = Vec::new();
+            while !reply.ends_with(b"\r\n\r\n") {
+                if reply.len() > 8192 {
+                    return Err(failed(&"CONNECT reply too long"));
+                }
+                reply.push(stream.read_u8().await.map_err(|e| failed(&e))?);
+            }
+            let reply = String::from_utf8_lossy(&reply);
+            let status = reply.lines().next().unwrap_or_default();
+            if status.split_whitespace().nth(1) != Some("200") {
+                return Err(failed(&format!("CONNECT to {} refused: {}", authority, status)));
+            }
+        }
+        "socks5" | "socks5h" => {
+            let method = if user.is_empty() { 0 } else { 2 };
+            stream.write_all(&[5, 1, method]).await.map_err(|e| failed(&e))?;
+            let mut chosen = [0u8; 2];
+            stream.read_exact(&mut chosen).await.map_err(|e| failed(&e))?;
+            if chosen != [5, method] {
+                return Err(failed(&"no acceptable SOCKS5 authentication method"));
+            }
+            if method == 2 {
+                let mut login = vec![1];
+                for field in [user.as_bytes(), password.as_bytes()] {
+                    login.push(u8::try_from(field.len()).map_err(|_| GnosError::Config("SOCKS5 credentials too long".to_string()))?);
+                    login.extend_from_slice(field);
+                }
+                stream.write_all(&login).await.map_err(|e| failed(&e))?;
+                let mut status = [0u8; 2];
+                stream.read_exact(&mut status).await.map_err(|e| failed(&e))?;
+                if status[1] != 0 {
+                    return Err(GnosError::PermissionDenied(format!("proxy {} refused the credentials", proxy_host)));
+                }
+            }
+            
+            let mut request = vec![5, 1, 0];
+            if url.scheme() == "socks5h" {
+                let name = u8::try_from(host.len()).map_err(|_| failed(&format!("{} is too long a name", host)))?;
+                request.push(3);
+                request.push(name);
+                request.extend_from_slice(host.as_bytes());
+            } else {
+                match resolver.addresses(host).await?.first().map(SocketAddr::ip) {
+                    Some(IpAddr::V4(ip)) => {
+                        request.push(1);
+                        request.extend_from_slice(&ip.octets());
+                    }
+                    Some(IpAddr::V6(ip)) => {
+                        request.push(4);
+                        request.extend_from_slice(&ip.octets());
+                    }
+                    None => return Err(GnosError::Unreachable(format!("{} has no address", host))),
+                }
+            }
+            request.extend_from_slice(&port.to_be_bytes());
+            stream.write_all(&request).await.map_err(|e| failed(&e))?;
+            
+            let mut reply = [0u8; 4];
+            stream.read_exact(&mut reply).await.map_err(|e| failed(&e))?;
+            if reply[1] != 0 {
+                return Err(failed(&format!("SOCKS5 connect to {}:{} failed with status {}", host, port, reply[1])));
+            }
+            // The address the proxy bound, which nothing here needs
+            let bound = match reply[3] {
+                1 => 4,
+                4 => 16,
+                3 => stream.read_u8().await.map_err(|e| failed(&e))? as usize,
+                _ => return Err(failed(&"malformed SOCKS5 reply")),
+            };
+            let mut rest = vec![0u8; bound + 2];
+            stream.read_exact(&mut rest).await.map_err(|e| failed(&e))?;
+        }
+        other => return Err(GnosError::Config(format!("{}:// proxies can't carry TCP connections; use http:// or socks5://", other))),
+    }
+    Ok(stream)
+}
+
+/// Resolver that remembers answers for a fixed TTL, asking the system or
+/// the servers under `network.dns` and answering pinned names itself
+#[derive(Clone)]
+struct CachingResolver {
+    ttl: Duration,
+    entries: Arc<DashMap<String, (Instant, Vec<SocketAddr>)>>,
+    hosts: Arc<HashMap<String, Vec<IpAddr>>>,
+    dns: Option<Arc<TokioAsyncResolver>>,
+    preference: IpPreference,
+}
+
+impl CachingResolver {
+    fn new(config: &NetworkConfig) -> Result<Self> {
+        let dns = &config.dns;
+        let hosts: HashMap<String, Vec<IpAddr>> = dns.hosts.iter()
+            .map(|(host, addrs)| (host.to_ascii_lowercase(), addrs.clone()))
+            .collect();
+        Ok(Self {
+            ttl: Duration::from_secs(config.dns_cache_ttl_seconds),
+            entries: Arc::new(DashMap::new()),
+            dns: name_servers(dns, &hosts)?.map(|servers| {
+                let mut options = ResolverOpts::default();
+                options.ip_strategy = match dns.ip {
+                    IpPreference::Ipv4Only => LookupIpStrategy::Ipv4Only,
+                    IpPreference::Ipv6Only => LookupIpStrategy::Ipv6Only,
+                    _ => LookupIpStrategy::Ipv4AndIpv6,
+                };
+                Arc::new(TokioAsyncResolver::tokio(ResolverConfig::from_parts(None, Vec::new(), servers), options))
+            }),
+            hosts: Arc::new(hosts),
+            preference: dns.ip,
+        })
+    }
+    
+    /// Addresses for `host`, with port 0, as requests would connect to
+    async fn addresses(&self, host: &str) -> Result<Vec<SocketAddr>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, 0)]);
+        }
+        let addrs = lookup(
+            self.entries.clone(),
+            self.ttl,
+            self.hosts.clone(),
+            self.dns.clone(),
+            self.preference,
+            host.to_ascii_lowercase(),
+        )
+        .await
+        .map_err(|e| GnosError::Unreachable(format!("resolving {}: {}", host, e)))?;
+        Ok(addrs.collect())
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(lookup(
+            self.entries.clone(),
+            self.ttl,
+            self.hosts.clone(),
+            self.dns.clone(),
+            self.preference,
+            name.as_str().to_ascii_lowercase(),
+        ))
+    }
+}
+
+/// The servers `network.dns` names, or `None` to use the system's
+fn name_servers(config: &DnsConfig, hosts: &HashMap<String, Vec<IpAddr>>) -> Result<Option<NameServerConfigGroup>> {
+    if let Some(doh) = &config.doh {
+        let url = url::Url::parse(doh)
+            .map_err(|e| GnosError::InvalidPath(format!("Invalid DNS-over-HTTPS endpoint {}: {}", doh, e)))?;
+        // The resolver always asks this path
+        if url.scheme() != "https" || url.path() != "/dns-query" {
+            return Err(GnosError::InvalidPath(format!("DNS-over-HTTPS endpoint {} must be https://<host>/dns-query", doh)));
+        }
+        let host = url.host_str().unwrap_or_default().trim_matches(|c| c == '[' || c == ']').to_string();
+        let addrs = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => hosts.get(&host.to_ascii_lowercase()).cloned()
+                .ok_or_else(|| GnosError::InvalidPath(format!("DNS-over-HTTPS server {} needs its address under dns.hosts", host)))?,
+        };
+        let port = url.port().unwrap_or(443);
+        let servers: Vec<NameServerConfig> = addrs.into_iter()
+            .map(|ip| {
+                let mut server = NameServerConfig::new(SocketAddr::new(ip, port), Protocol::Https);
+                server.tls_dns_name = Some(host.clone());
+                server
+            })
+            .collect();
+        return Ok(Some(NameServerConfigGroup::from(servers)));
+    }
+    
+    if config.servers.is_empty() {
+        return Ok(None);
+    }
+    let mut servers = Vec::new();
+    for server in &config.servers {
+        let addr = server.parse::<SocketAddr>()
+            .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+            .map_err(|_| GnosError::InvalidPath(format!("DNS server {} is not an address", server)))?;
+        // TCP for answers too large for a UDP datagram
+        servers.push(NameServerConfig::new(addr, Protocol::Udp));
+        servers.push(NameServerConfig::new(addr, Protocol::Tcp));
+    }
+    Ok(Some(NameServerConfigGroup::from(servers)))
+}
+
+async fn lookup(
+    entries: Arc<DashMap<String, (Instant, Vec<SocketAddr>)>>,
+    ttl: Duration,
+    hosts: Arc<HashMap<String, Vec<IpAddr>>>,
+    dns: Option<Arc<TokioAsyncResolver>>,
+    preference: IpPreference,
+    host: String,
+) -> std::result::Result<Addrs, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(pinned) = hosts.get(&host) {
+        let addrs = prefer(pinned.iter().map(|ip| SocketAddr::new(*ip, 0)).collect(), preference);
+        return Ok(Box::new(addrs.into_iter()));
+    }
+    
+    let cached = entries.get(&host)
+        .filter(|entry| entry.0.elapsed() < ttl)
+        .map(|entry| entry.1.clone());
+    if let Some(addrs) = cached {
+        return Ok(Box::new(addrs.into_iter()));
+    }
+    
+    let addrs: Vec<SocketAddr> = match &dns {
+        Some(dns) => dns.lookup_ip(host.as_str()).await?.iter().map(|ip| SocketAddr::new(ip, 0)).collect(),
+        None => tokio::net::lookup_host((host.as_str(), 0)).await?.collect(),
+    };
+    let addrs = prefer(addrs, preference);
+    if addrs.is_empty() {
+        return Err(format!("{} has no address of the family network.dns.ip allows", host).into());
+    }
+    entries.insert(host, (Instant::now(), addrs.clone()));
+    
+    Ok(Box::new(addrs.into_iter()))
+}
+
+/// `addrs` in the order, or of the family, `preference` asks for
+fn prefer(mut addrs: Vec<SocketAddr>, preference: IpPreference) -> Vec<SocketAddr> {
+    match preference {
+        IpPreference::Any => {}
+        IpPreference::Ipv4First => addrs.sort_by_key(|addr| addr.is_ipv6()),
+        IpPreference::Ipv6First => addrs.sort_by_key(|addr| addr.is_ipv4()),
+        IpPreference::Ipv4Only => addrs.retain(SocketAddr::is_ipv4),
+        IpPreference::Ipv6Only => addrs.retain(SocketAddr::is_ipv6),
+    }
+    addrs
+}
@@ -0,0 +1,111 @@
+//! Path-pattern routes, consulted before the drivers' prefixes
+//!
+//! ```toml
+//! [[drivers.routes]]
+//! pattern = "/cloud/aws/s3/*/logs/**"
+//! driver = "archive"
+//! priority = 10
+//! ```
+//!
+//! A rule gives a glob (`*` and `?` stay within one path component, `**`
+//! spans any number of them) or a regular expression the whole path must
+//! match. All of a table's rules are compiled into one `RegexSet`, so a
+//! lookup is a single pass over the path however many rules there are.
+//! Where rules overlap the highest priority wins, and on equal priorities
+//! the one configured first.
+
+use regex::RegexSet;
+
+use crate::config::RouteRule;
+use crate::{GnosError, Result};
+
+struct Rule {
+    /// As configured, for reports
+    pattern: String,
+    priority: i32,
+    driver: String,
+}
+
+/// A table of pattern routes, compiled
+#[derive(Default)]
+pub struct PatternRouter {
+    rules: Vec<Rule>,
+    set: Option<RegexSet>,
+}
+
+impl PatternRouter {
+    pub fn new(rules: &[RouteRule]) -> Result<Self> {
+        let mut compiled = Vec::new();
+        let mut regexes = Vec::new();
+        for rule in rules {
+            let (pattern, regex) = match (&rule.pattern, &rule.regex) {
+                (Some(glob), None) => (glob.clone(), glob_regex(glob)),
+                (None, Some(regex)) => (format!("~{}", regex), format!("^(?:{})$", regex)),
+                _ => {
+                    return Err(GnosError::Config(format!(
+                        "route to {} needs exactly one of pattern and regex", rule.driver
+                    )));
+                }
+            };
+            compiled.push(Rule { pattern, priority: rule.priority, driver: rule.driver.clone() });
+            regexes.push(regex);
+        }
+        
+        let set = if regexes.is_empty() {
+            None
+        } else {
+            let set = RegexSet::new(&regexes)
+                .map_err(|e| GnosError::Config(format!("bad route pattern: {}", e)))?;
+            Some(set)
+        };
+        Ok(Self { rules: compiled, set })
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    
+    /// Label of the driver the best matching rule names, if any matches
+    pub fn route(&self, path: &str) -> Option<&str> {
+        let set = self.set.as_ref()?;
+        set.matches(path).iter()
+            // Matches come in config order, so `min_by_key` keeps the first
+            // of equal priorities
+            .min_by_key(|&at| std::cmp::Reverse(self.rules[at].priority))
+            .map(|at| self.rules[at].driver.as_str())
+    }
+    
+    /// Each rule as (pattern, priority, driver label), in config order;
+    /// regular expressions are shown with a leading `~`
+    pub fn rules(&self) -> impl Iterator<Item = (&str, i32, &str)> {
+        self.rules.iter().map(|rule| (rule.pattern.as_str(), rule.priority, rule.driver.as_str()))
+    }
+}
+
+/// An anchored regular expression matching what `glob` does
+fn glob_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut rest = glob;
+    while let Some(c) = rest.chars().next() {
+        // `/**` ending the glob or followed by `/` also matches no
+        // components at all, so `/a/**` covers `/a` and `/a/**/b` covers `/a/b`
+        if let Some(after) = rest.strip_prefix("/**").filter(|after| after.is_empty() || after.starts_with('/')) {
+            regex.push_str("(?:/.*)?");
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("**") {
+            regex.push_str(".*");
+            rest = after;
+            continue;
+        }
+        match c {
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            _ => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    regex.push('$');
+    regex
+}
@@ -0,0 +1,260 @@
+//! Multi-region routing for bucket aliases
+//!
+//! An alias under `/cloud/<alias>` names a bucket replicated across
+//! regions. Every replica's endpoint is probed in the background; requests
+//! go to the fastest reachable replica (`latency`) or the first reachable
+//! one in config order (`ordered`). A replica that fails a request is taken
+//! out of rotation until its next successful probe, so traffic fails over
+//! without the caller noticing.
+//!
+//! S3 answers a request sent to the wrong region with a 301 or 400 carrying
+//! `x-amz-bucket-region`; the router moves the replica to that region's
+//! endpoint and remembers it, so only the first request pays for the detour.
+
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use tracing::{debug, info, warn};
+
+use crate::config::{BucketAlias, RoutingPolicy};
+use crate::drivers::network::SharedHttpClient;
+use crate::{GnosError, Result};
+
+/// Header S3 names a bucket's actual region in
+const BUCKET_REGION_HEADER: &str = "x-amz-bucket-region";
+
+/// Weight of the newest probe in a replica's smoothed round trip
+const RTT_SMOOTHING: f64 = 0.3;
+
+/// Where one request for an alias goes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub region: String,
+    pub bucket: String,
+    pub endpoint: String,
+}
+
+struct Replica {
+    route: Route,
+    /// Whether the config pinned the endpoint, so redirects leave it alone
+    pinned: bool,
+    healthy: bool,
+    rtt: Option<Duration>,
+    last_error: Option<String>,
+}
+
+struct Alias {
+    name: String,
+    policy: RoutingPolicy,
+    replicas: Vec<Replica>,
+}
+
+/// Routes requests for bucket aliases to one of their replicas
+pub struct RegionRouter {
+    http: Arc<SharedHttpClient>,
+    aliases: RwLock<Vec<Alias>>,
+}
+
+impl RegionRouter {
+    /// Start routing `buckets`, probing their replicas every `probe_interval`
+    pub fn new(buckets: &[BucketAlias], http: Arc<SharedHttpClient>, probe_interval: Duration) -> Result<Arc<Self>> {
+        let mut aliases = Vec::new();
+        for bucket in buckets {
+            if bucket.replicas.is_empty() {
+                return Err(GnosError::Config(format!("bucket alias {} has no replicas", bucket.alias)));
+            }
+            if aliases.iter().any(|alias: &Alias| alias.name == bucket.alias) {
+                return Err(GnosError::Config(format!("bucket alias {} is configured twice", bucket.alias)));
+            }
+            aliases.push(Alias {
+                name: bucket.alias.clone(),
+                policy: bucket.routing,
+                replicas: bucket.replicas.iter()
+                    .map(|replica| Replica {
+                        route: Route {
+                            region: replica.region.clone(),
+                            bucket: replica.bucket.clone(),
+                            endpoint: replica.endpoint.clone().unwrap_or_else(|| s3_endpoint(&replica.region)),
+                        },
+                        pinned: replica.endpoint.is_some(),
+                        // Optimistic until the first probe says otherwise
+                        healthy: true,
+                        rtt: None,
+                        last_error: None,
+                    })
+                    .collect(),
+            });
+        }
+        
+        let router = Arc::new(Self { http, aliases: RwLock::new(aliases) });
+        if !buckets.is_empty() {
+            router.spawn_prober(probe_interval);
+        }
+        Ok(router)
+    }
+    
+    /// Names of the configured aliases
+    pub fn aliases(&self) -> Vec<String> {
+        self.aliases.read().unwrap().iter().map(|alias| alias.name.clone()).collect()
+    }
+    
+    /// Replica a request for `alias` should go to now
+    pub fn route(&self, alias: &str) -> Option<Route> {
+        let aliases = self.aliases.read().unwrap();
+        aliases.iter().find(|candidate| candidate.name == alias).map(Alias::route)
+    }
+    
+    /// Take a replica out of rotation after a request to it failed
+    pub fn report_failure(&self, alias: &str, route: &Route, error: &GnosError) {
+        if !matches!(error, GnosError::Unreachable(_)) {
+            return;
+        }
+        let marked = self.replica_mut(alias, route, |replica| {
+            replica.healthy = false;
+            replica.last_error = Some(error.to_string());
+        });
+        if marked.is_some() {
+            warn!("🌍 {} in {} unreachable, failing over: {}", alias, route.region, error);
+        }
+    }
+    
+    /// Follow a wrong-region answer by moving the replica to the region S3
+    /// names; true if the request should be retried on the new route
+    pub fn follow_redirect(&self, alias: &str, route: &Route, status: StatusCode, headers: &HeaderMap) -> bool {
+        if status != StatusCode::MOVED_PERMANENTLY && status != StatusCode::BAD_REQUEST {
+            return false;
+        }
+        let Some(region) = headers.get(BUCKET_REGION_HEADER).and_then(|value| value.to_str().ok()) else {
+            return false;
+        };
+        if region == route.region {
+            return false;
+        }
+        
+        let mut moved = false;
+        self.replica_mut(alias, route, |replica| {
+            if !replica.pinned {
+                replica.route.endpoint = s3_endpoint(region);
+                replica.route.region = region.to_string();
+                moved = true;
+            }
+        });
+        if moved {
+            info!("🌍 {} bucket {} lives in {}, not {}; rerouted", alias, route.bucket, region, route.region);
+        }
+        moved
+    }
+    
+    /// Apply `update` to the replica `route` was taken from, if it still routes there
+    fn replica_mut(&self, alias: &str, route: &Route, update: impl FnOnce(&mut Replica)) -> Option<()> {
+        let mut aliases = self.aliases.write().unwrap();
+        let replica = aliases.iter_mut()
+            .find(|candidate| candidate.name == alias)?
+            .replicas.iter_mut()
+            .find(|replica| replica.route == *route)?;
+        update(replica);
+        Some(())
+    }
+    
+    /// Probe every replica until the router is dropped
+    fn spawn_prober(self: &Arc<Self>, interval: Duration) {
+        let router: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+            loop {
+                ticker.tick().await;
+                let Some(router) = router.upgrade() else { break };
+                router.probe_all().await;
+            }
+        });
+    }
+    
+    async fn probe_all(&self) {
+        let targets: Vec<(String, Route)> = self.aliases.read().unwrap().iter()
+            .flat_map(|alias| alias.replicas.iter().map(|replica| (alias.name.clone(), replica.route.clone())))
+            .collect();
+        
+        for (alias, route) in targets {
+            let url = format!("{}/{}", route.endpoint.trim_end_matches('/'), route.bucket);
+            let started = Instant::now();
+            let result = self.http.fetch_with_headers(self.http.client().head(&url)).await;
+            let rtt = started.elapsed();
+            
+            match result {
+                Ok((status, headers, _)) => {
+                    // Any answer, even 403 without credentials, means the endpoint is reachable
+                    if self.follow_redirect(&alias, &route, status, &headers) {
+                        continue;
+                    }
+                    debug!("Probed {} in {}: {} in {:?}", alias, route.region, status, rtt);
+                    self.replica_mut(&alias, &route, |replica| {
+                        if !replica.healthy {
+                            info!("🌍 {} in {} reachable again", alias, replica.route.region);
+                        }
+                        replica.healthy = true;
+                        replica.last_error = None;
+                        replica.rtt = Some(match replica.rtt {
+                            Some(previous) => previous.mul_f64(1.0 - RTT_SMOOTHING) + rtt.mul_f64(RTT_SMOOTHING),
+                            None => rtt,
+                        });
+                    });
+                }
+                // Being told to slow down is an answer too
+                Err(GnosError::RateLimited { .. }) => {
+                    debug!("Probed {} in {}: rate limited", alias, route.region);
+                }
+                Err(e) => {
+                    self.replica_mut(&alias, &route, |replica| {
+                        if replica.healthy {
+                            warn!("🌍 {} in {} failed its probe: {}", alias, replica.route.region, e);
+                        }
+                        replica.healthy = false;
+                        replica.last_error = Some(e.to_string());
+                    });
+                }
+            }
+        }
+    }
+    
+    /// Plain-text view for `/proc/gnos/regions`
+    pub fn status_report(&self) -> String {
+        let mut report = String::new();
+        for alias in self.aliases.read().unwrap().iter() {
+            let chosen = alias.route();
+            report.push_str(&format!("{}\t{:?}\n", alias.name, alias.policy));
+            for replica in &alias.replicas {
+                report.push_str(&format!(
+                    "\t{}{}\t{}\t{}\t{}\t{}\n",
+                    if replica.route == chosen { "*" } else { "" },
+                    replica.route.region,
+                    replica.route.bucket,
+                    replica.route.endpoint,
+                    replica.rtt.map_or_else(|| "-".to_string(), |rtt| format!("{}ms", rtt.as_millis())),
+                    if replica.healthy { "up".to_string() } else {
+                        format!("down ({})", replica.last_error.as_deref().unwrap_or("unknown"))
+                    },
+                ));
+            }
+        }
+        report
+    }
+}
+
+impl Alias {
+    fn route(&self) -> Route {
+        let mut healthy = self.replicas.iter().filter(|replica| replica.healthy);
+        let chosen = match self.policy {
+            RoutingPolicy::Latency => healthy.min_by_key(|replica| replica.rtt.unwrap_or(Duration::MAX)),
+            RoutingPolicy::Ordered => healthy.next(),
+        };
+        // With every replica down, the preferred one is still worth a try
+        chosen.unwrap_or(&self.replicas[0]).route.clone()
+    }
+}
+
+/// Public S3 endpoint of `region`
+fn s3_endpoint(region: &str) -> String {
+    format!("https://s3.{}.amazonaws.com", region)
+}
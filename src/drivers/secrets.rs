@@ -0,0 +1,337 @@
+//! `/cloud/aws/secrets`: Secrets Manager secrets as owner-only files
+//!
+//! ```text
+//! ls /cloud/aws/secrets/prod/db                  # secrets named prod/db/...
+//! cat /cloud/aws/secrets/prod/db/password        # the AWSCURRENT value
+//! printf %s "$new" > /cloud/aws/secrets/prod/db/password
+//! ```
+//!
+//! "/" in secret names becomes directories. A read returns the current
+//! value byte for byte, and a write stores what was written as a new
+//! current version, creating the secret if there isn't one, so write with
+//! `printf` rather than `echo` unless the newline belongs in the value.
+//! Metadata carries the secret's rotation settings and dates. The files are
+//! private: mode 0600, and kept out of the disk cache, journal and index.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_secretsmanager::error::ProvideErrorMetadata;
+use aws_sdk_secretsmanager::operation::describe_secret::DescribeSecretOutput;
+use aws_sdk_secretsmanager::primitives::{Blob, DateTime};
+use aws_sdk_secretsmanager::types::{Filter, FilterNameStringType, RotationRulesType, SecretListEntry};
+use bytes::Bytes;
+use tracing::debug;
+
+use crate::config::{CacheMode, CloudDriverConfig, SecretsDriverConfig};
+use crate::drivers::credentials::CloudCredentials;
+use crate::drivers::network::EgressPolicy;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/cloud/aws/secrets";
+
+/// Secrets ListSecrets returns at once
+const PAGE: i32 = 100;
+
+/// Staging label of the version reads return
+const CURRENT: &str = "AWSCURRENT";
+
+/// What DescribeSecret and ListSecrets both report about a secret
+struct Described<'a> {
+    rotation_enabled: Option<bool>,
+    rotation_lambda: Option<&'a str>,
+    rules: Option<&'a RotationRulesType>,
+    last_rotated: Option<&'a DateTime>,
+    next_rotation: Option<&'a DateTime>,
+    last_changed: Option<&'a DateTime>,
+    stages: Option<&'a HashMap<String, Vec<String>>>,
+}
+
+impl<'a> From<&'a DescribeSecretOutput> for Described<'a> {
+    fn from(secret: &'a DescribeSecretOutput) -> Self {
+        Self {
+            rotation_enabled: secret.rotation_enabled(),
+            rotation_lambda: secret.rotation_lambda_arn(),
+            rules: secret.rotation_rules(),
+            last_rotated: secret.last_rotated_date(),
+            next_rotation: secret.next_rotation_date(),
+            last_changed: secret.last_changed_date(),
+            stages: secret.version_ids_to_stages(),
+        }
+    }
+}
+
+impl<'a> From<&'a SecretListEntry> for Described<'a> {
+    fn from(secret: &'a SecretListEntry) -> Self {
+        Self {
+            rotation_enabled: secret.rotation_enabled(),
+            rotation_lambda: secret.rotation_lambda_arn(),
+            rules: secret.rotation_rules(),
+            last_rotated: secret.last_rotated_date(),
+            next_rotation: secret.next_rotation_date(),
+            last_changed: secret.last_changed_date(),
+            stages: secret.secret_versions_to_stages(),
+        }
+    }
+}
+
+impl Described<'_> {
+    /// Rotation settings and dates as custom fields, the current version as
+    /// the ETag
+    fn metadata(&self) -> ResourceMetadata {
+        let mut metadata = ResourceMetadata {
+            last_modified: self.last_changed.and_then(|time| SystemTime::try_from(*time).ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+            etag: self.stages.and_then(|stages| {
+                stages.iter()
+                    .find(|(_, labels)| labels.iter().any(|label| label == CURRENT))
+                    .map(|(version, _)| version.clone())
+            }),
+            ..ResourceMetadata::default()
+        };
+        
+        let fields = &mut metadata.custom_fields;
+        fields.insert("rotation_enabled".to_string(), self.rotation_enabled.unwrap_or(false).to_string());
+        if let Some(lambda) = self.rotation_lambda {
+            fields.insert("rotation_lambda".to_string(), lambda.to_string());
+        }
+        if let Some(days) = self.rules.and_then(|rules| rules.automatically_after_days()) {
+            fields.insert("rotation_days".to_string(), days.to_string());
+        }
+        if let Some(schedule) = self.rules.and_then(|rules| rules.schedule_expression()) {
+            fields.insert("rotation_schedule".to_string(), schedule.to_string());
+        }
+        for (field, time) in [("last_rotated", self.last_rotated), ("next_rotation", self.next_rotation)] {
+            if let Some(time) = time.and_then(|time| chrono::DateTime::from_timestamp(time.secs(), time.subsec_nanos())) {
+                fields.insert(field.to_string(), time.to_rfc3339());
+            }
+        }
+        metadata
+    }
+}
+
+pub struct SecretsDriver {
+    credentials: Arc<CloudCredentials>,
+    region: String,
+    endpoint: Option<String>,
+    max_list: usize,
+    egress: Arc<EgressPolicy>,
+}
+
+impl SecretsDriver {
+    pub fn new(
+        config: &SecretsDriverConfig,
+        cloud: &CloudDriverConfig,
+        credentials: Arc<CloudCredentials>,
+        egress: Arc<EgressPolicy>,
+    ) -> Self {
+        Self {
+            credentials,
+            region: config.region.clone().unwrap_or_else(|| cloud.aws.region.clone()),
+            endpoint: config.endpoint.clone(),
+            max_list: config.max_list.max(1),
+            egress,
+        }
+    }
+    
+    async fn client(&self) -> Result<aws_sdk_secretsmanager::Client> {
+        match &self.endpoint {
+            Some(endpoint) => self.egress.check_endpoint(endpoint)?,
+            None => self.egress.check(&format!("secretsmanager.{}.amazonaws.com", self.region))?,
+        }
+        let credentials = self.credentials.current().await?;
+        let mut config = aws_sdk_secretsmanager::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(self.region.clone()))
+            .credentials_provider(credentials);
+        if let Some(endpoint) = &self.endpoint {
+            config = config.endpoint_url(endpoint);
+        }
+        Ok(aws_sdk_secretsmanager::Client::from_conf(config.build()))
+    }
+    
+    /// The secret name `path` spells, empty for the root
+    fn secret_name(&self, path: &Path) -> Result<String> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let components: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        Ok(components.join("/"))
+    }
+    
+    /// Secrets whose names start with `prefix`, with what ListSecrets says
+    /// about them
+    async fn secrets_under(&self, prefix: &str) -> Result<Vec<SecretListEntry>> {
+        let client = self.client().await?;
+        let mut secrets = Vec::new();
+        let mut token = None;
+        loop {
+            let mut request = client.list_secrets().max_results(PAGE).set_next_token(token);
+            if !prefix.is_empty() {
+                request = request.filters(Filter::builder().key(FilterNameStringType::Name).values(prefix).build());
+            }
+            let response = request.send().await.map_err(|e| secrets_error(prefix, e))?;
+            // The name filter ignores case; the namespace doesn't
+            secrets.extend(response.secret_list().iter()
+                .filter(|secret| secret.name().is_some_and(|name| name.starts_with(prefix)))
+                .cloned());
+            token = response.next_token().map(str::to_string);
+            if token.is_none() || secrets.len() >= self.max_list {
+                break;
+            }
+        }
+        secrets.truncate(self.max_list);
+        Ok(secrets)
+    }
+    
+    /// Entries of the directory `dir`: secrets directly in it, and the
+    /// first component of names that go deeper
+    async fn entries(&self, dir: &str) -> Result<BTreeMap<String, ResourceMetadata>> {
+        let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+        let mut entries = BTreeMap::new();
+        for secret in self.secrets_under(&prefix).await? {
+            let Some(rest) = secret.name().and_then(|name| name.strip_prefix(&prefix)) else {
+                continue;
+            };
+            match rest.split_once('/') {
+                Some((child, _)) => {
+                    entries.entry(child.to_string())
+                        .or_insert_with(|| ResourceMetadata { is_directory: true, ..ResourceMetadata::default() });
+                }
+                None if !rest.is_empty() => {
+                    entries.insert(rest.to_string(), Described::from(&secret).metadata());
+                }
+                None => {}
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Missing secrets are `PathNotFound`, throttling `RateLimited` and other
+/// failures driver errors
+fn secrets_error<E: ProvideErrorMetadata + std::fmt::Display>(name: &str, error: E) -> GnosError {
+    match error.code() {
+        Some("ResourceNotFoundException") => GnosError::PathNotFound(format!("{}/{}", ROOT, name)),
+        Some("ThrottlingException") => GnosError::RateLimited {
+            message: format!("Secrets Manager requests for {} are throttled", name),
+            retry_after: None,
+        },
+        Some("AccessDeniedException") => GnosError::PermissionDenied(format!("{}/{}: {}", ROOT, name, error)),
+        _ => GnosError::Driver(format!("Secrets Manager {} failed: {}", name, error)),
+    }
+}
+
+#[async_trait]
+impl GnosDriver for SecretsDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let name = self.secret_name(path)?;
+        if name.is_empty() {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        }
+        let response = self.client().await?.get_secret_value().secret_id(&name).send().await
+            .map_err(|e| secrets_error(&name, e))?;
+        match (response.secret_string(), response.secret_binary()) {
+            (Some(value), _) => Ok(Bytes::copy_from_slice(value.as_bytes())),
+            (None, Some(value)) => Ok(Bytes::copy_from_slice(value.as_ref())),
+            (None, None) => Ok(Bytes::new()),
+        }
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let name = self.secret_name(path)?;
+        if name.is_empty() {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        }
+        let client = self.client().await?;
+        let text = std::str::from_utf8(data).ok();
+        let response = client.put_secret_value()
+            .secret_id(&name)
+            .set_secret_string(text.map(str::to_string))
+            .set_secret_binary(text.is_none().then(|| Blob::new(data)))
+            .send().await;
+        
+        match response.map_err(|e| secrets_error(&name, e)) {
+            Ok(response) => {
+                debug!("Stored version {} of secret {}", response.version_id().unwrap_or("?"), name);
+                Ok(())
+            }
+            Err(GnosError::PathNotFound(_)) => {
+                client.create_secret()
+                    .name(&name)
+                    .set_secret_string(text.map(str::to_string))
+                    .set_secret_binary(text.is_none().then(|| Blob::new(data)))
+                    .send().await
+                    .map_err(|e| secrets_error(&name, e))?;
+                debug!("Created secret {}", name);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        let entries = self.entries(&self.secret_name(path)?).await?;
+        Ok(entries.into_iter().map(|(name, metadata)| (name, Some(metadata))).collect())
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    /// A secret's rotation info, or a directory when other names continue
+    /// below this one
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let name = self.secret_name(path)?;
+        if name.is_empty() {
+            return Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() });
+        }
+        match self.client().await?.describe_secret().secret_id(&name).send().await {
+            Ok(secret) => Ok(Described::from(&secret).metadata()),
+            Err(e) => match secrets_error(&name, e) {
+                GnosError::PathNotFound(missing) => {
+                    if self.secrets_under(&format!("{}/", name)).await?.is_empty() {
+                        return Err(GnosError::PathNotFound(missing));
+                    }
+                    Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() })
+                }
+                e => Err(e),
+            },
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "Secrets Manager Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Rotation replaces values under us, and they shouldn't linger in memory
+        CacheMode::DirectIo
+    }
+    
+    fn private(&self, _path: &Path) -> bool {
+        true
+    }
+}
@@ -0,0 +1,352 @@
+//! `/dev/sensors`: sampled readings with recent history
+//!
+//! Every sensor is sampled on a fixed interval into a ring buffer and shows
+//! up as a directory:
+//!
+//! ```text
+//! cat /dev/sensors/thermal_zone0/latest        # 41.5
+//! cat /dev/sensors/thermal_zone0/history.csv   # timestamp,value per sample
+//! tail -f /dev/sensors/thermal_zone0/live      # one CSV line per new sample
+//! ```
+//!
+//! Thermal zones and hwmon temperature and fan inputs are found on their
+//! own; any file holding a number can be added as a source. A `live`
+//! reader joins at the newest sample and waits for the next instead of
+//! seeing end of file.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info};
+
+use crate::config::{CacheMode, SensorSource, SensorsDriverConfig};
+use crate::drivers::traits::{GnosDriver, Growth, ResourceMetadata};
+use crate::{GnosError, Result};
+
+pub const SENSORS_ROOT: &str = "/dev/sensors";
+
+/// Files in every sensor directory
+const SENSOR_FILES: [&str; 3] = ["latest", "history.csv", "live"];
+
+#[derive(Debug, Clone)]
+struct Sample {
+    timestamp: SystemTime,
+    value: f64,
+    /// The sample as a `live` line
+    line: Bytes,
+    /// Where `line` starts in the `live` stream
+    offset: u64,
+}
+
+struct Sensor {
+    source: SensorSource,
+    capacity: usize,
+    samples: Mutex<VecDeque<Sample>>,
+    /// End of the `live` stream, bumped on every sample
+    live_end: watch::Sender<u64>,
+}
+
+impl Sensor {
+    fn new(source: SensorSource, capacity: usize) -> Self {
+        Self {
+            source,
+            capacity: capacity.max(1),
+            samples: Mutex::new(VecDeque::new()),
+            live_end: watch::Sender::new(0),
+        }
+    }
+    
+    async fn sample(&self) -> Result<()> {
+        let raw = tokio::fs::read_to_string(&self.source.path).await?;
+        let value: f64 = raw.trim().parse()
+            .map_err(|_| GnosError::Driver(format!("{} is not a number: {:?}", self.source.path.display(), raw.trim())))?;
+        let value = value * self.source.scale;
+        let timestamp = SystemTime::now();
+        
+        let line = Bytes::from(format!("{},{}\n", rfc3339(timestamp), value));
+        let offset = *self.live_end.borrow();
+        let end = offset + line.len() as u64;
+        {
+            let mut samples = self.samples.lock().unwrap();
+            if samples.len() >= self.capacity {
+                samples.pop_front();
+            }
+            samples.push_back(Sample { timestamp, value, line, offset });
+        }
+        self.live_end.send_replace(end);
+        Ok(())
+    }
+    
+    fn latest(&self) -> Option<Sample> {
+        self.samples.lock().unwrap().back().cloned()
+    }
+    
+    fn history_csv(&self) -> String {
+        let mut csv = String::from("timestamp,value\n");
+        for sample in self.samples.lock().unwrap().iter() {
+            csv.push_str(&format!("{},{}\n", rfc3339(sample.timestamp), sample.value));
+        }
+        csv
+    }
+    
+    /// `live` from `have` on; a reader behind the oldest kept sample starts
+    /// at the newest one
+    fn live_from(&self, have: u64) -> Growth {
+        let samples = self.samples.lock().unwrap();
+        let lagging = samples.front().is_none_or(|oldest| have < oldest.offset);
+        
+        let pending: Vec<&Sample> = if lagging {
+            samples.back().into_iter().collect()
+        } else {
+            samples.iter().filter(|sample| sample.offset + sample.line.len() as u64 > have).collect()
+        };
+        
+        let offset = pending.first().map_or(have, |sample| sample.offset);
+        let mut data = Vec::new();
+        for sample in pending {
+            data.extend_from_slice(&sample.line);
+        }
+        Growth {
+            offset,
+            data: Bytes::from(data),
+            growing: true,
+        }
+    }
+}
+
+/// Driver for `/dev/sensors`
+pub struct SensorDriver {
+    sensors: Arc<HashMap<String, Arc<Sensor>>>,
+}
+
+impl SensorDriver {
+    pub async fn new(config: &SensorsDriverConfig) -> Result<Self> {
+        let mut sources = config.sources.clone();
+        if config.discover {
+            sources.extend(discover().await);
+        }
+        
+        let mut sensors = HashMap::new();
+        for source in sources {
+            if source.name.is_empty() || source.name.contains('/') {
+                return Err(GnosError::InvalidPath(format!("bad sensor name: {:?}", source.name)));
+            }
+            let name = source.name.clone();
+            if sensors.insert(name.clone(), Arc::new(Sensor::new(source, config.history_size))).is_some() {
+                return Err(GnosError::InvalidPath(format!("duplicate sensor name: {}", name)));
+            }
+        }
+        info!("🌡️  Sampling {} sensors every {}ms", sensors.len(), config.sample_interval_ms);
+        
+        let sensors = Arc::new(sensors);
+        spawn_sampler(sensors.clone(), Duration::from_millis(config.sample_interval_ms.max(1)));
+        Ok(Self { sensors })
+    }
+    
+    /// Sensor and file named by `path`; the file is `None` for the sensor's directory
+    fn locate<'a>(&self, path: &'a Path) -> Result<Option<(Arc<Sensor>, Option<&'a str>)>> {
+        let rest = path.strip_prefix(SENSORS_ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let names: Vec<&str> = rest.iter().filter_map(|name| name.to_str()).collect();
+        
+        let (name, file) = match names[..] {
+            [] => return Ok(None),
+            [name] => (name, None),
+            [name, file] if SENSOR_FILES.contains(&file) => (name, Some(file)),
+            _ => return Err(GnosError::PathNotFound(path.display().to_string())),
+        };
+        let sensor = self.sensors.get(name)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        Ok(Some((sensor.clone(), file)))
+    }
+}
+
+#[async_trait]
+impl GnosDriver for SensorDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let Some((sensor, Some(file))) = self.locate(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        
+        let text = match file {
+            "latest" => sensor.latest().map_or_else(String::new, |sample| format!("{}\n", sample.value)),
+            "history.csv" => sensor.history_csv(),
+            // Outside the mount there is no reader to keep following, so just the newest line
+            _ => return Ok(sensor.latest().map_or_else(Bytes::new, |sample| sample.line)),
+        };
+        Ok(Bytes::from(text))
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        path.file_name().is_some_and(|name| name == "live")
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        let Some((sensor, Some("live"))) = self.locate(path)? else {
+            return Ok(Growth { offset: 0, data: self.read(path).await?, growing: false });
+        };
+        
+        let mut live_end = sensor.live_end.subscribe();
+        live_end.wait_for(|&end| end > have).await
+            .map_err(|_| GnosError::Driver(format!("{} stopped sampling", path.display())))?;
+        Ok(sensor.live_from(have))
+    }
+    
+    async fn write(&self, path: &Path, _data: &[u8]) -> Result<()> {
+        Err(GnosError::PermissionDenied(format!("{} is read-only", path.display())))
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        match self.locate(path)? {
+            None => {
+                let mut names: Vec<&String> = self.sensors.keys().collect();
+                names.sort();
+                Ok(names.into_iter()
+                    .map(|name| (name.clone(), Some(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() })))
+                    .collect())
+            }
+            Some((_, None)) => Ok(SENSOR_FILES.iter().map(|file| (file.to_string(), None)).collect()),
+            Some((_, Some(_))) => Ok(Vec::new()),
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        Ok(self.locate(path).is_ok())
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let (sensor, file) = match self.locate(path)? {
+            None => return Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }),
+            Some((sensor, None)) => {
+                let mut custom_fields = HashMap::new();
+                custom_fields.insert("source".to_string(), sensor.source.path.display().to_string());
+                if let Some(unit) = &sensor.source.unit {
+                    custom_fields.insert("unit".to_string(), unit.clone());
+                }
+                return Ok(ResourceMetadata { is_directory: true, custom_fields, ..ResourceMetadata::default() });
+            }
+            Some((sensor, Some(file))) => (sensor, file),
+        };
+        
+        // `live` reports where the stream has got to, so `tail` starts near its end
+        let size = match file {
+            "live" => *sensor.live_end.borrow(),
+            _ => self.read(path).await?.len() as u64,
+        };
+        let last_modified = sensor.latest().map_or_else(SystemTime::now, |sample| sample.timestamp);
+        Ok(ResourceMetadata {
+            size,
+            last_modified,
+            mime_type: Some(if file == "history.csv" { "text/csv" } else { "text/plain" }.to_string()),
+            ..ResourceMetadata::default()
+        })
+    }
+    
+    fn name(&self) -> &'static str {
+        "Sensors Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(SENSORS_ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(SENSORS_ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        CacheMode::DirectIo
+    }
+}
+
+fn spawn_sampler(sensors: Arc<HashMap<String, Arc<Sensor>>>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            for (name, sensor) in sensors.iter() {
+                if let Err(e) = sensor.sample().await {
+                    debug!("Sampling sensor {} failed: {}", name, e);
+                }
+            }
+        }
+    });
+}
+
+/// Thermal zones plus hwmon temperature and fan inputs
+async fn discover() -> Vec<SensorSource> {
+    let mut sources = Vec::new();
+    
+    for zone in read_dir_sorted(Path::new("/sys/class/thermal")).await {
+        let Some(name) = zone.file_name().and_then(|name| name.to_str()).map(str::to_string) else {
+            continue;
+        };
+        if name.starts_with("thermal_zone") && zone.join("temp").exists() {
+            sources.push(SensorSource {
+                name,
+                path: zone.join("temp"),
+                scale: 0.001,
+                unit: Some("°C".to_string()),
+            });
+        }
+    }
+    
+    for hwmon in read_dir_sorted(Path::new("/sys/class/hwmon")).await {
+        let chip = match tokio::fs::read_to_string(hwmon.join("name")).await {
+            Ok(chip) => chip.trim().to_string(),
+            Err(_) => continue,
+        };
+        let hwmon_name = hwmon.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        
+        for input in read_dir_sorted(&hwmon).await {
+            let Some(file) = input.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let Some(channel) = file.strip_suffix("_input") else {
+                continue;
+            };
+            let (scale, unit) = if channel.starts_with("temp") {
+                (0.001, "°C")
+            } else if channel.starts_with("fan") {
+                (1.0, "RPM")
+            } else {
+                continue;
+            };
+            sources.push(SensorSource {
+                // Chips like coretemp can appear more than once
+                name: format!("{}_{}_{}", chip, hwmon_name, channel),
+                path: input.clone(),
+                scale,
+                unit: Some(unit.to_string()),
+            });
+        }
+    }
+    
+    sources
+}
+
+async fn read_dir_sorted(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Ok(mut entries) = tokio::fs::read_dir(dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    paths
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
@@ -0,0 +1,330 @@
+//! `/net/sftp/<host>/<path>`: files on remote hosts over SFTP
+//!
+//! ```text
+//! ls /net/sftp                          # configured hosts
+//! ls /net/sftp/build/var/log
+//! cat /net/sftp/build/var/log/syslog
+//! cp report.csv /net/sftp/build/srv/drop/
+//! ```
+//!
+//! Each host in `[[drivers.sftp.hosts]]` is logged in to with the private
+//! key its entry names. Its server key must already be in `known_hosts`:
+//! an unknown or changed key is refused rather than trusted. A session per
+//! host is opened on first use and kept; one that breaks is dropped and
+//! opened again by the next call. libssh2 blocks, so calls run on the
+//! blocking pool, one at a time per host. Connections go through
+//! `network.proxy` and `network.dns`, as HTTP requests do.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use ssh2::{CheckResult, ErrorCode, FileStat, KnownHostFileKind, Session, Sftp};
+use tracing::{debug, info, warn};
+
+use crate::config::{SftpDriverConfig, SftpHostConfig};
+use crate::drivers::context::DriverContext;
+use crate::drivers::network::SharedHttpClient;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/net/sftp";
+
+/// SFTP status codes from draft-ietf-secsh-filexfer, as libssh2 reports them
+const FX_NO_SUCH_FILE: i32 = 2;
+const FX_PERMISSION_DENIED: i32 = 3;
+const FX_NO_SUCH_PATH: i32 = 10;
+const FX_FILE_ALREADY_EXISTS: i32 = 11;
+const FX_WRITE_PROTECT: i32 = 12;
+const FX_NO_SPACE_ON_FILESYSTEM: i32 = 14;
+const FX_QUOTA_EXCEEDED: i32 = 15;
+const FX_DIR_NOT_EMPTY: i32 = 18;
+
+/// What a path below the root names
+enum Target<'a> {
+    Hosts,
+    /// A host and the remote path
+    Remote(&'a Arc<Host>, PathBuf),
+}
+
+struct Host {
+    config: SftpHostConfig,
+    known_hosts: PathBuf,
+    timeout: Duration,
+    /// Egress policy, resolver and proxy the connection is made with
+    network: Arc<SharedHttpClient>,
+    connection: Mutex<Option<Connection>>,
+}
+
+/// An authenticated session and its SFTP channel
+struct Connection {
+    _session: Session,
+    sftp: Sftp,
+}
+
+impl Host {
+    /// Connect, check the server's key against `known_hosts` and log in;
+    /// called on the blocking pool
+    fn connect(&self) -> Result<Connection> {
+        let config = &self.config;
+        let unreachable = |e: &dyn std::fmt::Display| GnosError::Unreachable(format!("{}: {}", config.name, e));
+        
+        let stream = tokio::runtime::Handle::current()
+            .block_on(self.network.connect_tcp(&config.address, config.port, self.timeout))?
+            .into_std()
+            .map_err(|e| unreachable(&e))?;
+        // Tokio's sockets are non-blocking, and libssh2 expects a blocking one
+        stream.set_nonblocking(false).map_err(|e| unreachable(&e))?;
+        
+        let mut session = Session::new().map_err(|e| unreachable(&e))?;
+        session.set_timeout(self.timeout.as_millis().min(u32::MAX as u128) as u32);
+        session.set_tcp_stream(stream);
+        session.handshake().map_err(|e| unreachable(&e))?;
+        
+        // Never trust a server on first sight: its key has to be known already
+        let (key, _) = session.host_key()
+            .ok_or_else(|| unreachable(&"server sent no host key"))?;
+        let mut known_hosts = session.known_hosts().map_err(|e| unreachable(&e))?;
+        known_hosts.read_file(&self.known_hosts, KnownHostFileKind::OpenSSH)
+            .map_err(|e| GnosError::PermissionDenied(format!("can't read {}: {}", self.known_hosts.display(), e)))?;
+        match known_hosts.check_port(&config.address, config.port, key) {
+            CheckResult::Match => {}
+            CheckResult::Mismatch => {
+                warn!("🚨 Host key of {} ({}) does not match {}", config.name, config.address, self.known_hosts.display());
+                return Err(GnosError::PermissionDenied(format!("host key of {} has changed", config.address)));
+            }
+            CheckResult::NotFound | CheckResult::Failure => {
+                return Err(GnosError::PermissionDenied(format!(
+                    "{} is not in {}; add its key with ssh-keyscan", config.address, self.known_hosts.display()
+                )));
+            }
+        }
+        
+        let passphrase = match &config.passphrase_env {
+            Some(env) => Some(std::env::var(env).map_err(|_| {
+                GnosError::PermissionDenied(format!("key for {} needs its passphrase in ${}", config.name, env))
+            })?),
+            None => None,
+        };
+        session.userauth_pubkey_file(&config.user, None, &config.key, passphrase.as_deref())
+            .map_err(|e| GnosError::PermissionDenied(format!("{}@{} refused the key: {}", config.user, config.address, e)))?;
+        let sftp = session.sftp().map_err(|e| unreachable(&e))?;
+        info!("🔐 SFTP session open to {}@{}", config.user, config.address);
+        Ok(Connection { _session: session, sftp })
+    }
+}
+
+pub struct SftpDriver {
+    hosts: BTreeMap<String, Arc<Host>>,
+}
+
+impl SftpDriver {
+    pub fn new(config: &SftpDriverConfig, context: &DriverContext) -> Result<Self> {
+        let mut hosts = BTreeMap::new();
+        for host in &config.hosts {
+            if host.name.is_empty() || host.name.contains('/') {
+                return Err(GnosError::Config(format!("invalid SFTP host name: {:?}", host.name)));
+            }
+            let entry = Arc::new(Host {
+                config: host.clone(),
+                known_hosts: config.known_hosts.clone(),
+                timeout: Duration::from_secs(config.timeout_seconds),
+                network: context.http.clone(),
+                connection: Mutex::new(None),
+            });
+            if hosts.insert(host.name.clone(), entry).is_some() {
+                return Err(GnosError::Config(format!("SFTP host {} is configured twice", host.name)));
+            }
+        }
+        Ok(Self { hosts })
+    }
+    
+    fn target<'a>(&'a self, path: &Path) -> Result<Target<'a>> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let mut components = rest.components();
+        let host = match components.next() {
+            None => return Ok(Target::Hosts),
+            Some(Component::Normal(name)) => name.to_str()
+                .and_then(|name| self.hosts.get(name))
+                .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?,
+            Some(_) => return Err(GnosError::InvalidPath(path.display().to_string())),
+        };
+        
+        let mut remote = host.config.root.clone();
+        for component in components {
+            match component {
+                Component::Normal(name) => remote.push(name),
+                _ => return Err(GnosError::InvalidPath(path.display().to_string())),
+            }
+        }
+        Ok(Target::Remote(host, remote))
+    }
+    
+    /// Run `op` against the host's SFTP channel on the blocking pool,
+    /// connecting first if there is no session; a session that fails is
+    /// dropped so the next call starts a fresh one
+    async fn with_sftp<T: Send + 'static>(
+        &self,
+        host: &Arc<Host>,
+        op: impl FnOnce(&Sftp) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let host = host.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = host.connection.lock().unwrap();
+            if connection.is_none() {
+                *connection = Some(host.connect()?);
+            }
+            let result = op(&connection.as_ref().expect("connected above").sftp);
+            if let Err(GnosError::Unreachable(_) | GnosError::Io(_)) = &result {
+                debug!("Dropping SFTP session to {}", host.config.name);
+                *connection = None;
+            }
+            result
+        })
+        .await
+        .map_err(|e| GnosError::Driver(format!("SFTP task failed: {}", e)))?
+    }
+}
+
+/// An SFTP status as the matching error; session-level failures are
+/// `Unreachable`, so the session gets replaced
+fn sftp_error(e: ssh2::Error, path: &Path) -> GnosError {
+    let what = format!("{}: {}", path.display(), e.message());
+    match e.code() {
+        ErrorCode::SFTP(FX_NO_SUCH_FILE | FX_NO_SUCH_PATH) => GnosError::PathNotFound(path.display().to_string()),
+        ErrorCode::SFTP(FX_PERMISSION_DENIED | FX_WRITE_PROTECT) => GnosError::PermissionDenied(what),
+        ErrorCode::SFTP(FX_FILE_ALREADY_EXISTS | FX_DIR_NOT_EMPTY) => GnosError::ResourceBusy(what),
+        ErrorCode::SFTP(FX_NO_SPACE_ON_FILESYSTEM | FX_QUOTA_EXCEEDED) => GnosError::QuotaExceeded(what),
+        ErrorCode::SFTP(_) => GnosError::Driver(what),
+        ErrorCode::Session(_) => GnosError::Unreachable(what),
+    }
+}
+
+fn stat_metadata(stat: &FileStat) -> ResourceMetadata {
+    let mut metadata = ResourceMetadata {
+        size: if stat.is_dir() { 0 } else { stat.size.unwrap_or(0) },
+        is_directory: stat.is_dir(),
+        last_modified: stat.mtime
+            .map_or(SystemTime::UNIX_EPOCH, |mtime| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime)),
+        ..ResourceMetadata::default()
+    };
+    if let Some(perm) = stat.perm {
+        metadata.custom_fields.insert("mode".to_string(), format!("{:o}", perm & 0o7777));
+    }
+    if let Some(uid) = stat.uid {
+        metadata.custom_fields.insert("uid".to_string(), uid.to_string());
+    }
+    if let Some(gid) = stat.gid {
+        metadata.custom_fields.insert("gid".to_string(), gid.to_string());
+    }
+    metadata
+}
+
+#[async_trait]
+impl GnosDriver for SftpDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let Target::Remote(host, remote) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        self.with_sftp(host, move |sftp| {
+            let mut file = sftp.open(&remote).map_err(|e| sftp_error(e, &remote))?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            Ok(Bytes::from(data))
+        }).await
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let Target::Remote(host, remote) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        let data = data.to_vec();
+        self.with_sftp(host, move |sftp| {
+            let mut file = sftp.create(&remote).map_err(|e| sftp_error(e, &remote))?;
+            file.write_all(&data)?;
+            debug!("Wrote {} bytes to {}", data.len(), remote.display());
+            Ok(())
+        }).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        let Target::Remote(host, remote) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} exists", path.display())));
+        };
+        self.with_sftp(host, move |sftp| sftp.mkdir(&remote, 0o755).map_err(|e| sftp_error(e, &remote))).await
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        let Target::Remote(host, remote) = self.target(path)? else {
+            return Err(GnosError::PermissionDenied(format!("{} can't be deleted", path.display())));
+        };
+        self.with_sftp(host, move |sftp| {
+            let stat = sftp.lstat(&remote).map_err(|e| sftp_error(e, &remote))?;
+            if stat.is_dir() {
+                sftp.rmdir(&remote)
+            } else {
+                sftp.unlink(&remote)
+            }
+            .map_err(|e| sftp_error(e, &remote))
+        }).await
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        Ok(self.list_with_metadata(path).await?.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        let (host, remote) = match self.target(path)? {
+            Target::Hosts => {
+                return Ok(self.hosts.keys()
+                    .map(|name| (name.clone(), Some(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() })))
+                    .collect());
+            }
+            Target::Remote(host, remote) => (host, remote),
+        };
+        self.with_sftp(host, move |sftp| {
+            let entries = sftp.readdir(&remote).map_err(|e| sftp_error(e, &remote))?;
+            Ok(entries.into_iter()
+                .filter_map(|(path, stat)| {
+                    let name = path.file_name()?.to_str()?.to_string();
+                    Some((name, Some(stat_metadata(&stat))))
+                })
+                .collect())
+        }).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let (host, remote) = match self.target(path)? {
+            Target::Hosts => return Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }),
+            Target::Remote(host, remote) => (host, remote),
+        };
+        self.with_sftp(host, move |sftp| {
+            sftp.stat(&remote).map(|stat| stat_metadata(&stat)).map_err(|e| sftp_error(e, &remote))
+        }).await
+    }
+    
+    fn name(&self) -> &'static str {
+        "SFTP Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+}
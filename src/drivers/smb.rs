@@ -0,0 +1,332 @@
+//! `/net/smb/<share>/<path>`: Windows and Samba file shares
+//!
+//! ```text
+//! ls /net/smb                           # configured shares
+//! ls /net/smb/finance/reports
+//! cat /net/smb/finance/reports/q3.xlsx > q3.xlsx
+//! cp budget.csv /net/smb/finance/drop/
+//! ```
+//!
+//! Shares are reached through libsmbclient in user space, so no kernel
+//! CIFS mount or root is needed. Each share in `[[drivers.smb.shares]]`
+//! logs in with NTLM as its own account, or the section's, with the
+//! password taken from the environment variable the config names. A client
+//! per share is opened on first use and kept; one that fails is dropped and
+//! opened again by the next call. libsmbclient blocks, so calls run on the
+//! blocking pool, one at a time per share.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pavao::{SmbClient, SmbCredentials, SmbDirentType, SmbError, SmbMode, SmbOpenOptions, SmbOptions};
+use tracing::{debug, info};
+
+use crate::config::{SmbDriverConfig, SmbShareConfig};
+use crate::drivers::context::DriverContext;
+use crate::drivers::network::EgressPolicy;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/net/smb";
+
+/// What a path below the root names
+enum Target<'a> {
+    Shares,
+    /// A share and the path within it, starting with `/`
+    Remote(&'a Arc<Share>, String),
+}
+
+struct Share {
+    config: SmbShareConfig,
+    user: Option<String>,
+    domain: String,
+    password_env: Option<String>,
+    client: Mutex<Option<SmbClient>>,
+}
+
+impl Share {
+    /// Open a client for the share and log in
+    fn connect(&self) -> Result<SmbClient> {
+        let config = &self.config;
+        let password = match &self.password_env {
+            Some(env) => std::env::var(env).map_err(|_| {
+                GnosError::PermissionDenied(format!("share {} needs its password in ${}", config.name, env))
+            })?,
+            None => String::new(),
+        };
+        // Without an account libsmbclient logs in as guest
+        let credentials = SmbCredentials::default()
+            .server(format!("smb://{}:{}", config.server, config.port))
+            .share(format!("/{}", config.share))
+            .username(self.user.clone().unwrap_or_else(|| "guest".to_string()))
+            .password(password)
+            .workgroup(self.domain.clone());
+        let client = SmbClient::new(credentials, SmbOptions::default().one_share_per_server(true))
+            .map_err(|e| GnosError::Unreachable(format!("{}: {}", config.name, e)))?;
+        info!("🗄️ SMB client open to \\\\{}\\{} as {}\\{}",
+              config.server, config.share, self.domain, self.user.as_deref().unwrap_or("guest"));
+        Ok(client)
+    }
+}
+
+pub struct SmbDriver {
+    shares: BTreeMap<String, Arc<Share>>,
+    egress: Arc<EgressPolicy>,
+}
+
+impl SmbDriver {
+    pub fn new(config: &SmbDriverConfig, context: &DriverContext) -> Result<Self> {
+        let mut shares = BTreeMap::new();
+        for share in &config.shares {
+            if share.name.is_empty() || share.name.contains('/') {
+                return Err(GnosError::Config(format!("invalid SMB share name: {:?}", share.name)));
+            }
+            // A share's own account comes with its own password, never the section's
+            let (user, password_env) = match &share.user {
+                Some(user) => (Some(user.clone()), share.password_env.clone()),
+                None => (config.user.clone(), share.password_env.clone().or_else(|| config.password_env.clone())),
+            };
+            let entry = Arc::new(Share {
+                config: share.clone(),
+                user,
+                domain: share.domain.clone().unwrap_or_else(|| config.domain.clone()),
+                password_env,
+                client: Mutex::new(None),
+            });
+            if shares.insert(share.name.clone(), entry).is_some() {
+                return Err(GnosError::Config(format!("SMB share {} is configured twice", share.name)));
+            }
+        }
+        Ok(Self { shares, egress: context.egress.clone() })
+    }
+    
+    fn target<'a>(&'a self, path: &Path) -> Result<Target<'a>> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let mut components = rest.components();
+        let share = match components.next() {
+            None => return Ok(Target::Shares),
+            Some(Component::Normal(name)) => name.to_str()
+                .and_then(|name| self.shares.get(name))
+                .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?,
+            Some(_) => return Err(GnosError::InvalidPath(path.display().to_string())),
+        };
+        
+        let mut remote = String::new();
+        for component in components {
+            match component.as_os_str().to_str() {
+                Some(name) if matches!(component, Component::Normal(_)) => {
+                    remote.push('/');
+                    remote.push_str(name);
+                }
+                _ => return Err(GnosError::InvalidPath(path.display().to_string())),
+            }
+        }
+        if remote.is_empty() {
+            remote.push('/');
+        }
+        Ok(Target::Remote(share, remote))
+    }
+    
+    /// Run `op` with the share's client on the blocking pool, connecting
+    /// first if there is none; a client that fails is dropped so the next
+    /// call opens a fresh one
+    async fn with_client<T: Send + 'static>(
+        &self,
+        share: &Arc<Share>,
+        op: impl FnOnce(&SmbClient) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        self.egress.check(&share.config.server)?;
+        let share = share.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut client = share.client.lock().unwrap();
+            if client.is_none() {
+                *client = Some(share.connect()?);
+            }
+            let result = op(client.as_ref().expect("connected above"));
+            if let Err(GnosError::Unreachable(_) | GnosError::Io(_)) = &result {
+                debug!("Dropping SMB client for {}", share.config.name);
+                *client = None;
+            }
+            result
+        })
+        .await
+        .map_err(|e| GnosError::Driver(format!("SMB task failed: {}", e)))?
+    }
+}
+
+/// libsmbclient's errno as the matching error; connection failures are
+/// `Unreachable`, so the client gets replaced
+fn smb_error(e: SmbError, path: &str) -> GnosError {
+    let SmbError::Io(e) = e else {
+        return GnosError::Driver(format!("{}: {}", path, e));
+    };
+    let what = format!("{}: {}", path, e);
+    match e.raw_os_error() {
+        Some(libc::ENOENT | libc::ENOTDIR) => GnosError::PathNotFound(path.to_string()),
+        Some(libc::EACCES | libc::EPERM | libc::EROFS) => GnosError::PermissionDenied(what),
+        Some(libc::EEXIST | libc::ENOTEMPTY | libc::EBUSY) => GnosError::ResourceBusy(what),
+        Some(libc::ENOSPC | libc::EDQUOT) => GnosError::QuotaExceeded(what),
+        Some(libc::ETIMEDOUT | libc::ECONNREFUSED | libc::ECONNRESET | libc::EHOSTUNREACH
+             | libc::ENETUNREACH | libc::EPIPE | libc::ENOTCONN) => GnosError::Unreachable(what),
+        _ => GnosError::Driver(what),
+    }
+}
+
+/// libsmbclient's stat has no file type, so directories are told apart by
+/// whether they can be listed
+fn is_dir(client: &SmbClient, remote: &str) -> bool {
+    client.list_dir(remote).is_ok()
+}
+
+fn directory() -> ResourceMetadata {
+    ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }
+}
+
+fn file_metadata(client: &SmbClient, remote: &str) -> Result<ResourceMetadata> {
+    let stat = client.stat(remote).map_err(|e| smb_error(e, remote))?;
+    Ok(ResourceMetadata {
+        size: stat.size,
+        last_modified: stat.modified,
+        ..ResourceMetadata::default()
+    })
+}
+
+/// `remote/name` within a share
+fn child(remote: &str, name: &str) -> String {
+    format!("{}/{}", remote.trim_end_matches('/'), name)
+}
+
+#[async_trait]
+impl GnosDriver for SmbDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let Target::Remote(share, remote) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        self.with_client(share, move |client| {
+            let mut file = client.open_with(&remote, SmbOpenOptions::default().read(true))
+                .map_err(|e| smb_error(e, &remote))?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            Ok(Bytes::from(data))
+        }).await
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let Target::Remote(share, remote) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        let data = data.to_vec();
+        self.with_client(share, move |client| {
+            let options = SmbOpenOptions::default().create(true).write(true).truncate(true);
+            let mut file = client.open_with(&remote, options).map_err(|e| smb_error(e, &remote))?;
+            file.write_all(&data)?;
+            debug!("Wrote {} bytes to {}", data.len(), remote);
+            Ok(())
+        }).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        let Target::Remote(share, remote) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} exists", path.display())));
+        };
+        self.with_client(share, move |client| {
+            client.mkdir(&remote, SmbMode::from(0o755)).map_err(|e| smb_error(e, &remote))
+        }).await
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        let Target::Remote(share, remote) = self.target(path)? else {
+            return Err(GnosError::PermissionDenied(format!("{} can't be deleted", path.display())));
+        };
+        if remote == "/" {
+            return Err(GnosError::PermissionDenied(format!("{} can't be deleted", path.display())));
+        }
+        self.with_client(share, move |client| {
+            if is_dir(client, &remote) {
+                client.rmdir(&remote)
+            } else {
+                client.unlink(&remote)
+            }
+            .map_err(|e| smb_error(e, &remote))
+        }).await
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        let (share, remote) = match self.target(path)? {
+            Target::Shares => return Ok(self.shares.keys().cloned().collect()),
+            Target::Remote(share, remote) => (share, remote),
+        };
+        self.with_client(share, move |client| {
+            let entries = client.list_dir(&remote).map_err(|e| smb_error(e, &remote))?;
+            Ok(entries.iter()
+                .map(|entry| entry.name().to_string())
+                .filter(|name| name != "." && name != "..")
+                .collect())
+        }).await
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        let (share, remote) = match self.target(path)? {
+            Target::Shares => return Ok(self.shares.keys().map(|name| (name.clone(), Some(directory()))).collect()),
+            Target::Remote(share, remote) => (share, remote),
+        };
+        self.with_client(share, move |client| {
+            let entries = client.list_dir(&remote).map_err(|e| smb_error(e, &remote))?;
+            let mut listing = Vec::with_capacity(entries.len());
+            for entry in &entries {
+                let name = entry.name();
+                if name == "." || name == ".." {
+                    continue;
+                }
+                // Listings say what each entry is but not its size or times
+                let metadata = match entry.get_type() {
+                    SmbDirentType::Dir => Some(directory()),
+                    SmbDirentType::File => file_metadata(client, &child(&remote, name)).ok(),
+                    _ => None,
+                };
+                listing.push((name.to_string(), metadata));
+            }
+            Ok(listing)
+        }).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let (share, remote) = match self.target(path)? {
+            Target::Shares => return Ok(directory()),
+            Target::Remote(share, remote) => (share, remote),
+        };
+        self.with_client(share, move |client| {
+            let metadata = file_metadata(client, &remote)?;
+            if is_dir(client, &remote) {
+                Ok(ResourceMetadata { last_modified: metadata.last_modified, ..directory() })
+            } else {
+                Ok(metadata)
+            }
+        }).await
+    }
+    
+    fn name(&self) -> &'static str {
+        "SMB Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+}
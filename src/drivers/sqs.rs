@@ -0,0 +1,316 @@
+//! `/cloud/aws/sqs`: queues as files that lines are appended to and popped from
+//!
+//! ```text
+//! ls /cloud/aws/sqs                              # queues
+//! echo "resize 42.png" >> /cloud/aws/sqs/jobs    # one message per line
+//! cat /cloud/aws/sqs/jobs                        # receive the next message
+//! cat /cloud/aws/sqs/jobs.peek                   # look without taking it
+//! while job=$(cat /cloud/aws/sqs/jobs); [ -n "$job" ]; do run "$job"; done
+//! ```
+//!
+//! A read receives one message, waiting up to `wait_seconds` on an empty
+//! queue, and returns its body with a trailing newline; an empty read means
+//! nothing arrived. The message is deleted once read unless
+//! `delete_on_read` is off, in which case it comes back after the queue's
+//! visibility timeout. Reading `<queue>.peek` receives with a visibility
+//! timeout of zero, so the message stays at the front for the next reader.
+//! Every non-empty line written is sent as its own message, ten to a batch;
+//! messages to a FIFO queue share one message group so they keep their order.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_sqs::error::ProvideErrorMetadata;
+use aws_sdk_sqs::types::{QueueAttributeName, SendMessageBatchRequestEntry};
+use bytes::Bytes;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::config::{CacheMode, CloudDriverConfig, SqsDriverConfig};
+use crate::drivers::credentials::CloudCredentials;
+use crate::drivers::network::EgressPolicy;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/cloud/aws/sqs";
+
+/// Suffix of the file that reads a queue without taking its messages
+const PEEK_SUFFIX: &str = ".peek";
+
+/// Messages SendMessageBatch takes at once
+const BATCH: usize = 10;
+
+/// Longest long poll SQS allows
+const MAX_WAIT_SECONDS: u32 = 20;
+
+/// Message group of lines written to a FIFO queue
+const FIFO_GROUP: &str = "gnos";
+
+/// What a path below the root names
+enum Target<'a> {
+    Queues,
+    Queue(&'a str),
+    Peek(&'a str),
+}
+
+pub struct SqsDriver {
+    credentials: Arc<CloudCredentials>,
+    region: String,
+    endpoint: Option<String>,
+    delete_on_read: bool,
+    wait_seconds: i32,
+    urls: Mutex<HashMap<String, String>>,
+    egress: Arc<EgressPolicy>,
+}
+
+impl SqsDriver {
+    pub fn new(
+        config: &SqsDriverConfig,
+        cloud: &CloudDriverConfig,
+        credentials: Arc<CloudCredentials>,
+        egress: Arc<EgressPolicy>,
+    ) -> Self {
+        Self {
+            credentials,
+            region: config.region.clone().unwrap_or_else(|| cloud.aws.region.clone()),
+            endpoint: config.endpoint.clone(),
+            delete_on_read: config.delete_on_read,
+            wait_seconds: config.wait_seconds.min(MAX_WAIT_SECONDS) as i32,
+            urls: Mutex::new(HashMap::new()),
+            egress,
+        }
+    }
+    
+    async fn client(&self) -> Result<aws_sdk_sqs::Client> {
+        match &self.endpoint {
+            Some(endpoint) => self.egress.check_endpoint(endpoint)?,
+            None => self.egress.check(&format!("sqs.{}.amazonaws.com", self.region))?,
+        }
+        let credentials = self.credentials.current().await?;
+        let mut config = aws_sdk_sqs::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(self.region.clone()))
+            .credentials_provider(credentials);
+        if let Some(endpoint) = &self.endpoint {
+            config = config.endpoint_url(endpoint);
+        }
+        Ok(aws_sdk_sqs::Client::from_conf(config.build()))
+    }
+    
+    fn target<'a>(&self, path: &'a Path) -> Result<Target<'a>> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let components: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        
+        match components[..] {
+            [] => Ok(Target::Queues),
+            [name] => match name.strip_suffix(PEEK_SUFFIX) {
+                Some(queue) if !queue.is_empty() => Ok(Target::Peek(queue)),
+                _ => Ok(Target::Queue(name)),
+            },
+            _ => Err(GnosError::PathNotFound(path.display().to_string())),
+        }
+    }
+    
+    /// The URL of `queue`, looked up once and then remembered
+    async fn queue_url(&self, client: &aws_sdk_sqs::Client, queue: &str) -> Result<String> {
+        if let Some(url) = self.urls.lock().unwrap().get(queue) {
+            return Ok(url.clone());
+        }
+        
+        let response = client.get_queue_url().queue_name(queue).send().await
+            .map_err(|e| sqs_error(queue, e))?;
+        let url = response.queue_url()
+            .ok_or_else(|| GnosError::PathNotFound(format!("{}/{}", ROOT, queue)))?
+            .to_string();
+        self.urls.lock().unwrap().insert(queue.to_string(), url.clone());
+        Ok(url)
+    }
+    
+    /// The body of the next message, taken off the queue unless `peek`
+    async fn receive(&self, queue: &str, peek: bool) -> Result<Bytes> {
+        let client = self.client().await?;
+        let url = self.queue_url(&client, queue).await?;
+        let mut request = client.receive_message()
+            .queue_url(&url)
+            .max_number_of_messages(1)
+            .wait_time_seconds(self.wait_seconds);
+        if peek {
+            request = request.visibility_timeout(0);
+        }
+        let response = request.send().await.map_err(|e| sqs_error(queue, e))?;
+        let Some(message) = response.messages().first() else {
+            return Ok(Bytes::new());
+        };
+        
+        if !peek && self.delete_on_read {
+            if let Some(receipt) = message.receipt_handle() {
+                client.delete_message().queue_url(&url).receipt_handle(receipt).send().await
+                    .map_err(|e| sqs_error(queue, e))?;
+            }
+        }
+        let mut body = message.body().unwrap_or_default().as_bytes().to_vec();
+        if !body.ends_with(b"\n") {
+            body.push(b'\n');
+        }
+        debug!("Received {} bytes from queue {}", body.len(), queue);
+        Ok(Bytes::from(body))
+    }
+    
+    /// Send every non-empty line of `data` as a message
+    async fn send(&self, queue: &str, data: &[u8]) -> Result<()> {
+        let text = std::str::from_utf8(data)
+            .map_err(|_| GnosError::InvalidPath(format!("messages to {} must be UTF-8 text", queue)))?;
+        let lines: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+        if lines.is_empty() {
+            return Ok(());
+        }
+        
+        let client = self.client().await?;
+        let url = self.queue_url(&client, queue).await?;
+        let fifo = queue.ends_with(".fifo");
+        for batch in lines.chunks(BATCH) {
+            let entries = batch.iter().enumerate()
+                .map(|(index, line)| {
+                    let mut entry = SendMessageBatchRequestEntry::builder()
+                        .id(index.to_string())
+                        .message_body(*line);
+                    if fifo {
+                        entry = entry.message_group_id(FIFO_GROUP)
+                            .message_deduplication_id(Uuid::new_v4().simple().to_string());
+                    }
+                    entry.build().map_err(|e| GnosError::Driver(format!("message to {} is malformed: {}", queue, e)))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let response = client.send_message_batch().queue_url(&url).set_entries(Some(entries)).send().await
+                .map_err(|e| sqs_error(queue, e))?;
+            if let Some(failed) = response.failed().first() {
+                return Err(GnosError::Driver(format!(
+                    "{} of {} messages to {} were refused: {}",
+                    response.failed().len(), batch.len(), queue,
+                    failed.message().unwrap_or(failed.code()),
+                )));
+            }
+        }
+        debug!("Sent {} messages to queue {}", lines.len(), queue);
+        Ok(())
+    }
+    
+    async fn list_queues(&self) -> Result<Vec<String>> {
+        let client = self.client().await?;
+        let mut queues = Vec::new();
+        let mut token = None;
+        loop {
+            let response = client.list_queues().max_results(1000).set_next_token(token).send().await
+                .map_err(|e| sqs_error(ROOT, e))?;
+            for url in response.queue_urls() {
+                if let Some(name) = url.rsplit('/').next() {
+                    self.urls.lock().unwrap().insert(name.to_string(), url.clone());
+                    queues.push(name.to_string());
+                }
+            }
+            token = response.next_token().map(str::to_string);
+            if token.is_none() {
+                break;
+            }
+        }
+        Ok(queues)
+    }
+    
+    /// Approximate counts of a queue's waiting and in-flight messages
+    async fn queue_metadata(&self, queue: &str) -> Result<ResourceMetadata> {
+        let client = self.client().await?;
+        let url = self.queue_url(&client, queue).await?;
+        let response = client.get_queue_attributes()
+            .queue_url(&url)
+            .attribute_names(QueueAttributeName::ApproximateNumberOfMessages)
+            .attribute_names(QueueAttributeName::ApproximateNumberOfMessagesNotVisible)
+            .send().await
+            .map_err(|e| sqs_error(queue, e))?;
+        
+        let mut metadata = ResourceMetadata { mime_type: Some("text/plain".to_string()), ..ResourceMetadata::default() };
+        if let Some(attributes) = response.attributes() {
+            for (name, field) in [
+                (QueueAttributeName::ApproximateNumberOfMessages, "messages"),
+                (QueueAttributeName::ApproximateNumberOfMessagesNotVisible, "in_flight"),
+            ] {
+                if let Some(value) = attributes.get(&name) {
+                    metadata.custom_fields.insert(field.to_string(), value.clone());
+                }
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+/// Missing queues are `PathNotFound`, other failures driver errors
+fn sqs_error<E: ProvideErrorMetadata + std::fmt::Display>(queue: &str, error: E) -> GnosError {
+    match error.code() {
+        Some("AWS.SimpleQueueService.NonExistentQueue" | "QueueDoesNotExist") => {
+            GnosError::PathNotFound(format!("{}/{}", ROOT, queue))
+        }
+        _ => GnosError::Driver(format!("SQS {} failed: {}", queue, error)),
+    }
+}
+
+#[async_trait]
+impl GnosDriver for SqsDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        match self.target(path)? {
+            Target::Queue(queue) => self.receive(queue, false).await,
+            Target::Peek(queue) => self.receive(queue, true).await,
+            Target::Queues => Err(GnosError::InvalidPath(format!("{} is a directory", path.display()))),
+        }
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        match self.target(path)? {
+            Target::Queue(queue) => self.send(queue, data).await,
+            _ => Err(GnosError::PermissionDenied(format!("{} can't be written", path.display()))),
+        }
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        match self.target(path)? {
+            Target::Queues => self.list_queues().await,
+            _ => Err(GnosError::InvalidPath(format!("{} is a queue", path.display()))),
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        match self.target(path)? {
+            Target::Queues => Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }),
+            Target::Queue(queue) | Target::Peek(queue) => self.queue_metadata(queue).await,
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "SQS Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Every read takes a different message
+        CacheMode::DirectIo
+    }
+}
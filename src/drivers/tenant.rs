@@ -0,0 +1,210 @@
+//! A driver instance serving one tenant's subtree
+//!
+//! The wrapped driver was built from the tenant's own config and sees the
+//! namespace as if it were mounted at `/`: `/tenants/acme/cloud/aws/x`
+//! reaches it as `/cloud/aws/x`.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::StreamExt;
+
+use super::traits::{
+    BatchOp, ChangeStream, GnosDriver, Growth, PartPolicy, PathParams, RemoteChange, ResourceMetadata, WriteOptions,
+};
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::CacheMode;
+use crate::tenants::{tenant_path, tenant_root};
+use crate::{GnosError, Result};
+
+pub struct TenantDriver {
+    tenant: String,
+    root: PathBuf,
+    /// `<driver>@<tenant>`, so offline state and atomic batches never mix
+    /// one tenant's backend with another's
+    name: &'static str,
+    inner: Arc<dyn GnosDriver>,
+}
+
+impl TenantDriver {
+    pub fn new(tenant: &str, inner: Arc<dyn GnosDriver>) -> Self {
+        // Tenants are built once at startup, so the leaked names are bounded
+        let name: &'static str = Box::leak(format!("{}@{}", inner.name(), tenant).into_boxed_str());
+        Self {
+            tenant: tenant.to_string(),
+            root: tenant_root(tenant),
+            name,
+            inner,
+        }
+    }
+    
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+    
+    /// `path` in the tenant's own namespace
+    fn inner_path(&self, path: &Path) -> Result<PathBuf> {
+        tenant_path(&self.tenant, path).ok_or_else(|| GnosError::PermissionDenied(format!(
+            "{} is outside tenant {}", path.display(), self.tenant
+        )))
+    }
+    
+    fn inner_op(&self, op: &BatchOp) -> Result<BatchOp> {
+        Ok(match op {
+            BatchOp::Write { path, data } => BatchOp::Write { path: self.inner_path(path)?, data: data.clone() },
+            BatchOp::Delete { path } => BatchOp::Delete { path: self.inner_path(path)? },
+        })
+    }
+}
+
+#[async_trait]
+impl GnosDriver for TenantDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        self.inner.read(&self.inner_path(path)?).await
+    }
+    
+    async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        self.inner.read_range(&self.inner_path(path)?, offset, len).await
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.inner_path(path).is_ok_and(|path| self.inner.streams(&path))
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        self.inner.read_growing(&self.inner_path(path)?, have).await
+    }
+    
+    async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+        self.inner.read_checked(&self.inner_path(path)?).await
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.inner.write(&self.inner_path(path)?, data).await
+    }
+    
+    fn upload_checksums(&self, path: &Path) -> &[ChecksumAlgorithm] {
+        match self.inner_path(path) {
+            Ok(path) => self.inner.upload_checksums(&path),
+            Err(_) => &[],
+        }
+    }
+    
+    async fn write_with(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+        self.inner.write_with(&self.inner_path(path)?, data, options).await
+    }
+    
+    fn conditional_writes(&self, path: &Path) -> bool {
+        self.inner_path(path).is_ok_and(|path| self.inner.conditional_writes(&path))
+    }
+    
+    fn accepted_encodings(&self) -> &[&'static str] {
+        self.inner.accepted_encodings()
+    }
+    
+    async fn write_encoded(&self, path: &Path, data: &[u8], encoding: &str) -> Result<()> {
+        self.inner.write_encoded(&self.inner_path(path)?, data, encoding).await
+    }
+    
+    fn invokes(&self, path: &Path) -> bool {
+        self.inner_path(path).is_ok_and(|path| self.inner.invokes(&path))
+    }
+    
+    async fn invoke(&self, path: &Path, payload: &[u8]) -> Result<Bytes> {
+        self.inner.invoke(&self.inner_path(path)?, payload).await
+    }
+    
+    fn supports_parts(&self, path: &Path) -> bool {
+        self.inner_path(path).is_ok_and(|path| self.inner.supports_parts(&path))
+    }
+    
+    fn part_policy(&self, path: &Path) -> PartPolicy {
+        self.inner_path(path).map_or_else(|_| PartPolicy::default(), |path| self.inner.part_policy(&path))
+    }
+    
+    async fn begin_parts(&self, path: &Path) -> Result<String> {
+        self.inner.begin_parts(&self.inner_path(path)?).await
+    }
+    
+    async fn write_part(&self, path: &Path, upload_id: &str, part: u64, data: &[u8]) -> Result<()> {
+        self.inner.write_part(&self.inner_path(path)?, upload_id, part, data).await
+    }
+    
+    async fn complete_parts(&self, path: &Path, upload_id: &str, parts: u64) -> Result<()> {
+        self.inner.complete_parts(&self.inner_path(path)?, upload_id, parts).await
+    }
+    
+    async fn abort_parts(&self, path: &Path, upload_id: &str) -> Result<()> {
+        self.inner.abort_parts(&self.inner_path(path)?, upload_id).await
+    }
+    
+    fn supports_batches(&self) -> bool {
+        self.inner.supports_batches()
+    }
+    
+    async fn commit_batch(&self, ops: &[BatchOp]) -> Result<()> {
+        let ops = ops.iter().map(|op| self.inner_op(op)).collect::<Result<Vec<_>>>()?;
+        self.inner.commit_batch(&ops).await
+    }
+    
+    async fn materialize(&self, path: &Path, params: &PathParams) -> Result<()> {
+        self.inner.materialize(&self.inner_path(path)?, params).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        self.inner.create_dir(&self.inner_path(path)?).await
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        self.inner.delete(&self.inner_path(path)?).await
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        self.inner.list(&self.inner_path(path)?).await
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        self.inner.list_with_metadata(&self.inner_path(path)?).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        self.inner.exists(&self.inner_path(path)?).await
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        self.inner.metadata(&self.inner_path(path)?).await
+    }
+    
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(&self.root) && self.inner_path(path).is_ok_and(|path| self.inner.supports(&path))
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        self.inner.prefixes().iter()
+            .map(|prefix| self.root.join(prefix.strip_prefix("/").unwrap_or(prefix)))
+            .collect()
+    }
+    
+    fn cache_mode(&self, path: &Path) -> CacheMode {
+        self.inner_path(path).map_or(CacheMode::Auto, |path| self.inner.cache_mode(&path))
+    }
+    
+    fn private(&self, path: &Path) -> bool {
+        self.inner_path(path).is_ok_and(|path| self.inner.private(&path))
+    }
+    
+    fn watch(&self, path: &Path) -> Option<ChangeStream> {
+        let changes = self.inner.watch(&self.inner_path(path).ok()?)?;
+        let root = self.root.clone();
+        Some(changes.map(move |change| RemoteChange {
+            path: root.join(change.path.strip_prefix("/").unwrap_or(&change.path)),
+            ..change
+        }).boxed())
+    }
+}
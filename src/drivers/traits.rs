@@ -1,19 +1,206 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use async_trait::async_trait;
-use crate::Result;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::CacheMode;
+use crate::{GnosError, Result};
 
 /// Core driver trait - every resource type implements this
 #[async_trait]
 pub trait GnosDriver: Send + Sync {
     /// Read data from the resource
-    async fn read(&self, path: &Path) -> Result<Vec<u8>>;
+    ///
+    /// Returned buffers are shared, so callers can cache and slice them
+    /// without copying.
+    async fn read(&self, path: &Path) -> Result<Bytes>;
+    
+    /// Read up to `len` bytes starting at `offset`
+    ///
+    /// Drivers backed by ranged APIs should override this; the default reads
+    /// the whole resource and slices it.
+    async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        let data = self.read(path).await?;
+        let start = std::cmp::min(offset, data.len() as u64) as usize;
+        let end = std::cmp::min(offset.saturating_add(len), data.len() as u64) as usize;
+        Ok(data.slice(start..end))
+    }
+    
+    /// Whether reads of `path` should go through `read_growing`
+    ///
+    /// Drivers whose output is produced over time, like AI completions,
+    /// should answer true so readers of the mount see it as it arrives.
+    fn streams(&self, _path: &Path) -> bool {
+        false
+    }
+    
+    /// Wait until the resource extends past offset `have` or is finished,
+    /// then return what it holds from `have` or earlier
+    ///
+    /// Only called when `streams` is true. Endless streams may drop their
+    /// oldest bytes, answering with a later offset; the reader then skips
+    /// ahead. The default reads the finished resource.
+    async fn read_growing(&self, path: &Path, _have: u64) -> Result<Growth> {
+        Ok(Growth {
+            offset: 0,
+            data: self.read(path).await?,
+            growing: false,
+        })
+    }
+    
+    /// Read the resource together with the digest the backend holds for it
+    ///
+    /// Drivers whose backends report one (a digest header, an ETag that is
+    /// an MD5) should override this so downloads can be verified; the
+    /// default reports none.
+    async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+        Ok((self.read(path).await?, None))
+    }
     
     /// Write data to the resource
     async fn write(&self, path: &Path, data: &[u8]) -> Result<()>;
     
+    /// Digests the backend checks uploads to `path` against
+    fn upload_checksums(&self, _path: &Path) -> &[ChecksumAlgorithm] {
+        &[]
+    }
+    
+    /// Write data along with what `options` says about it, returning the
+    /// digest the backend stored if it reports one
+    ///
+    /// The backend should refuse a payload that doesn't match its digests
+    /// and store the content type with the object. The default drops the
+    /// options and writes.
+    async fn write_with(&self, path: &Path, data: &[u8], _options: &WriteOptions) -> Result<Option<Checksum>> {
+        self.write(path, data).await?;
+        Ok(None)
+    }
+    
+    /// Whether writes to `path` honour `WriteOptions::precondition`, failing
+    /// with `ResourceBusy` when the object no longer matches it
+    ///
+    /// Backends with `If-Match` (S3, GCS, Azure) should override this so
+    /// conditional writes are checked atomically; otherwise the VFS compares
+    /// the object's metadata just before writing.
+    fn conditional_writes(&self, _path: &Path) -> bool {
+        false
+    }
+    
+    /// Content encodings (e.g. "zstd") the backend accepts on write
+    fn accepted_encodings(&self) -> &[&'static str] {
+        &[]
+    }
+    
+    /// Write a payload already compressed with `encoding`
+    ///
+    /// Only called for encodings listed in `accepted_encodings`.
+    async fn write_encoded(&self, path: &Path, _data: &[u8], encoding: &str) -> Result<()> {
+        Err(GnosError::Driver(format!(
+            "{} does not accept {} payloads for {}", self.name(), encoding, path.display()
+        )))
+    }
+    
+    /// Whether writes to `path` are calls that are answered
+    ///
+    /// A call written through the mount is sent with `invoke` when its
+    /// handle is flushed or first read, bypassing the write-back journal
+    /// and transactions, and the reply is what that handle reads next.
+    fn invokes(&self, _path: &Path) -> bool {
+        false
+    }
+    
+    /// Send the call in `payload` to `path` and return the reply
+    async fn invoke(&self, path: &Path, _payload: &[u8]) -> Result<Bytes> {
+        Err(GnosError::Driver(format!("{} does not support invoking {}", self.name(), path.display())))
+    }
+    
+    /// Whether `path` can be written in parts with `begin_parts`,
+    /// `write_part` and `complete_parts`
+    ///
+    /// Backends with multipart uploads (S3, Azure block blobs) should
+    /// override these so large copies stream instead of being buffered whole.
+    fn supports_parts(&self, _path: &Path) -> bool {
+        false
+    }
+    
+    /// How writes to `path` are split when it supports parts
+    ///
+    /// Writes through the mount larger than `threshold` are sent in parts
+    /// of `part_size` while they are still being written, up to
+    /// `concurrency` parts at a time.
+    fn part_policy(&self, _path: &Path) -> PartPolicy {
+        PartPolicy::default()
+    }
+    
+    /// Start a multipart write and return its upload ID
+    async fn begin_parts(&self, path: &Path) -> Result<String> {
+        Err(GnosError::Driver(format!("{} does not support multipart writes to {}", self.name(), path.display())))
+    }
+    
+    /// Upload part `part` (counting from zero); parts may arrive in any order
+    async fn write_part(&self, path: &Path, _upload_id: &str, _part: u64, _data: &[u8]) -> Result<()> {
+        Err(GnosError::Driver(format!("{} does not support multipart writes to {}", self.name(), path.display())))
+    }
+    
+    /// Assemble parts `0..parts` into the resource
+    async fn complete_parts(&self, path: &Path, _upload_id: &str, _parts: u64) -> Result<()> {
+        Err(GnosError::Driver(format!("{} does not support multipart writes to {}", self.name(), path.display())))
+    }
+    
+    /// Give up on a multipart write, discarding the parts sent so far
+    async fn abort_parts(&self, _path: &Path, _upload_id: &str) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Whether `commit_batch` can apply several changes atomically
+    fn supports_batches(&self) -> bool {
+        false
+    }
+    
+    /// Apply every operation in `ops` or none of them
+    ///
+    /// Only called with paths this driver owns. Backends without atomic
+    /// batches keep the default, and transactions fall back to applying
+    /// operations one by one with rollback.
+    async fn commit_batch(&self, _ops: &[BatchOp]) -> Result<()> {
+        Err(GnosError::Driver(format!("{} does not support atomic batches", self.name())))
+    }
+    
+    /// Accept or refuse a path a template is about to materialize
+    ///
+    /// `params` holds the values its `{name}` components took. Drivers
+    /// that can tell a bad value early (a malformed host name) should
+    /// refuse it, so probes like `.Trash` or `autorun.inf` don't become
+    /// inodes; the default accepts everything.
+    async fn materialize(&self, _path: &Path, _params: &PathParams) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Create a directory-like resource, e.g. a chat session
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        Err(GnosError::Driver(format!("{} does not support creating {}", self.name(), path.display())))
+    }
+    
+    /// Remove the resource
+    ///
+    /// Drivers whose backends can't delete keep this default.
+    async fn delete(&self, path: &Path) -> Result<()> {
+        Err(GnosError::Driver(format!("{} does not support deleting {}", self.name(), path.display())))
+    }
+    
     /// List resources (for directory-like resources)
     async fn list(&self, path: &Path) -> Result<Vec<String>>;
     
+    /// List resources together with their metadata
+    ///
+    /// Backends whose listing calls already return sizes and timestamps
+    /// should override this so `readdir` can prime the attribute cache; the
+    /// default returns names only.
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        Ok(self.list(path).await?.into_iter().map(|name| (name, None)).collect())
+    }
+    
     /// Check if resource exists
     async fn exists(&self, path: &Path) -> Result<bool>;
     
@@ -25,6 +212,131 @@ pub trait GnosDriver: Send + Sync {
     
     /// Supported path patterns
     fn supports(&self, path: &Path) -> bool;
+    
+    /// Namespace the driver reserves, e.g. `/cloud/aws/sqs`
+    ///
+    /// A path goes to the driver with the most specific reservation
+    /// covering it, and two drivers reserving the same prefix are reported
+    /// when the registry is built. Drivers reserving nothing are tried last.
+    fn prefixes(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+    
+    /// Page cache behaviour for files opened under `path`
+    ///
+    /// Live data such as sensor readings should answer `DirectIo`, immutable
+    /// objects `KeepCache`.
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        CacheMode::Auto
+    }
+    
+    /// Whether `path` holds something only its owner should see, such as a
+    /// secret
+    ///
+    /// Such inodes are shown as mode 0600 (0700 for directories) whatever
+    /// the capability grants, and their contents stay out of the disk cache,
+    /// the write-back journal and the content index.
+    fn private(&self, _path: &Path) -> bool {
+        false
+    }
+    
+    /// Changes other clients make under `path`, as the backend reports them
+    ///
+    /// Backends with a change feed (Docker's event stream, say) should
+    /// override this so `[notify]` can pass changes on as they happen. The
+    /// default has no feed, and `path` can't be watched.
+    fn watch(&self, _path: &Path) -> Option<ChangeStream> {
+        None
+    }
+}
+
+/// Changes under a watched path; ends when the backend stops reporting, as
+/// when its connection drops
+pub type ChangeStream = BoxStream<'static, RemoteChange>;
+
+/// A change a backend reported
+#[derive(Debug, Clone)]
+pub struct RemoteChange {
+    pub kind: ChangeKind,
+    pub path: PathBuf,
+    /// What the entry is now, if the backend said; always `None` for deletes
+    pub metadata: Option<ResourceMetadata>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// Part of a resource that is still being produced
+#[derive(Debug, Clone, Default)]
+pub struct Growth {
+    /// Where `data` starts in the resource
+    pub offset: u64,
+    pub data: Bytes,
+    /// More is still coming
+    pub growing: bool,
+}
+
+/// How a driver wants large writes split into parts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartPolicy {
+    /// Writes smaller than this go in one piece
+    pub threshold: u64,
+    /// Size of every part but the last
+    pub part_size: u64,
+    /// Parts sent at the same time
+    pub concurrency: usize,
+}
+
+impl Default for PartPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 64 * 1024 * 1024,
+            part_size: 8 * 1024 * 1024,
+            concurrency: 4,
+        }
+    }
+}
+
+/// What accompanies an upload besides its bytes
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Digests of the payload in the algorithms from `upload_checksums`
+    pub checksums: Vec<Checksum>,
+    /// MIME type detected from the payload and its name
+    pub content_type: Option<&'static str>,
+    /// What the object must still be for the write to land
+    pub precondition: Option<Precondition>,
+}
+
+/// State a conditional write expects the backend to hold
+#[derive(Debug, Clone, PartialEq)]
+pub enum Precondition {
+    /// `If-Match`: the object still has this entity tag
+    Matches(String),
+    /// `If-None-Match: *`: nothing has been created at the path
+    Absent,
+}
+
+/// Values bound to a path template's `{name}` components
+pub type PathParams = BTreeMap<String, String>;
+
+/// One staged change in a transaction
+#[derive(Debug, Clone)]
+pub enum BatchOp {
+    Write { path: PathBuf, data: Bytes },
+    Delete { path: PathBuf },
+}
+
+impl BatchOp {
+    pub fn path(&self) -> &Path {
+        match self {
+            BatchOp::Write { path, .. } | BatchOp::Delete { path } => path,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -33,16 +345,33 @@ pub struct ResourceMetadata {
     pub is_directory: bool,
     pub last_modified: std::time::SystemTime,
     pub mime_type: Option<String>,
+    /// Entity tag of this version, for backends that report one
+    pub etag: Option<String>,
     pub custom_fields: std::collections::HashMap<String, String>,
 }
 
+/// Sub-second part of the stand-in modification time `default()` gives, by
+/// which it is told from one a backend reported
+const STAND_IN_NANOS: u32 = 1;
+
+impl ResourceMetadata {
+    /// Whether `last_modified` came from the backend rather than being the
+    /// stand-in `default()` leaves there: the second the metadata was made
+    pub fn reports_modified(&self) -> bool {
+        self.last_modified.duration_since(std::time::UNIX_EPOCH)
+            .map_or(true, |since| since.subsec_nanos() != STAND_IN_NANOS)
+    }
+}
+
 impl Default for ResourceMetadata {
     fn default() -> Self {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
         Self {
             size: 0,
             is_directory: false,
-            last_modified: std::time::SystemTime::now(),
+            last_modified: std::time::UNIX_EPOCH + std::time::Duration::new(now.as_secs(), STAND_IN_NANOS),
             mime_type: None,
+            etag: None,
             custom_fields: std::collections::HashMap::new(),
         }
     }
@@ -0,0 +1,405 @@
+//! `/dev/vault`: HashiCorp Vault KV version 2 secrets as JSON files
+//!
+//! ```text
+//! ls /dev/vault                                  # configured mounts
+//! ls /dev/vault/secret/app                       # keys; subpaths are directories
+//! cat /dev/vault/secret/app/db                   # {"password": "...", ...}
+//! echo '{"password":"hunter2"}' > /dev/vault/secret/app/db
+//! ```
+//!
+//! A read returns the latest version's key/value pairs and a write stores
+//! a JSON object as a new version. The driver logs in with a token from the
+//! environment or with AppRole, and a background task renews the token
+//! ahead of its expiry, logging in again when it can no longer be renewed.
+//! Secret files are private, like those of the Secrets Manager driver.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::config::{CacheMode, VaultAuthMethod, VaultDriverConfig};
+use crate::drivers::context::DriverContext;
+use crate::drivers::network::SharedHttpClient;
+use crate::drivers::traits::{GnosDriver, ResourceMetadata};
+use crate::{GnosError, Result};
+
+const ROOT: &str = "/dev/vault";
+
+const TOKEN_HEADER: &str = "X-Vault-Token";
+const NAMESPACE_HEADER: &str = "X-Vault-Namespace";
+
+/// Wait before trying again after a failed renewal or login
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Tokens without a TTL are looked up again this often, in case one was set
+const STATIC_RECHECK_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// What a path below the root names
+enum Target<'a> {
+    Mounts,
+    /// A mount and a path within it, empty for the mount itself
+    Entry(&'a str, String),
+}
+
+/// The token requests are sent with
+#[derive(Default)]
+struct Lease {
+    token: Option<String>,
+    /// Unset for tokens that never expire
+    expires: Option<SystemTime>,
+    renewable: bool,
+}
+
+/// Login and renewal, shared with the background renewer
+struct VaultSession {
+    http: Arc<SharedHttpClient>,
+    address: String,
+    namespace: Option<String>,
+    auth: VaultAuthMethod,
+    token_env: String,
+    approle_mount: String,
+    role_id: Option<String>,
+    secret_id_env: String,
+    renew_before: Duration,
+    lease: RwLock<Lease>,
+}
+
+impl VaultSession {
+    fn request(&self, method: reqwest::Method, api: &str, token: Option<&str>) -> reqwest::RequestBuilder {
+        let mut request = self.http.client().request(method, format!("{}/v1/{}", self.address, api));
+        if let Some(token) = token {
+            request = request.header(TOKEN_HEADER, token);
+        }
+        if let Some(namespace) = &self.namespace {
+            request = request.header(NAMESPACE_HEADER, namespace);
+        }
+        request
+    }
+    
+    /// Send a request and return the JSON body of a successful response
+    async fn call(&self, request: reqwest::RequestBuilder, what: &str) -> Result<Value> {
+        let (status, body) = self.http.fetch(request).await?;
+        if !status.is_success() {
+            return Err(vault_error(status, &body, what));
+        }
+        if body.is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_slice(&body)
+            .map_err(|e| GnosError::Driver(format!("Vault answered {} with invalid JSON: {}", what, e)))
+    }
+    
+    /// The current token, logging in first if there is none
+    async fn token(&self) -> Result<String> {
+        if let Some(token) = self.lease.read().unwrap().token.clone() {
+            return Ok(token);
+        }
+        self.login().await?;
+        self.lease.read().unwrap().token.clone()
+            .ok_or_else(|| GnosError::PermissionDenied("Vault login returned no token".to_string()))
+    }
+    
+    /// Obtain a fresh token with the configured method
+    async fn login(&self) -> Result<()> {
+        let lease = match self.auth {
+            VaultAuthMethod::Token => {
+                let token = std::env::var(&self.token_env).map_err(|_| {
+                    GnosError::PermissionDenied(format!("Vault token auth needs a token in ${}", self.token_env))
+                })?;
+                let response = self.call(self.request(reqwest::Method::GET, "auth/token/lookup-self", Some(&token)),
+                                         "token lookup").await?;
+                let ttl = response["data"]["ttl"].as_u64().unwrap_or(0);
+                Lease {
+                    token: Some(token),
+                    expires: (ttl > 0).then(|| SystemTime::now() + Duration::from_secs(ttl)),
+                    renewable: response["data"]["renewable"].as_bool().unwrap_or(false),
+                }
+            }
+            VaultAuthMethod::AppRole => {
+                let role_id = self.role_id.as_deref()
+                    .ok_or_else(|| GnosError::Config("Vault AppRole auth needs drivers.vault.role_id".to_string()))?;
+                let secret_id = std::env::var(&self.secret_id_env).map_err(|_| {
+                    GnosError::PermissionDenied(format!("Vault AppRole auth needs a secret ID in ${}", self.secret_id_env))
+                })?;
+                let request = self.request(reqwest::Method::POST, &format!("auth/{}/login", self.approle_mount), None)
+                    .json(&json!({ "role_id": role_id, "secret_id": secret_id }));
+                lease_from(&self.call(request, "AppRole login").await?)?
+            }
+        };
+        debug!("Logged in to Vault at {}", self.address);
+        *self.lease.write().unwrap() = lease;
+        Ok(())
+    }
+    
+    /// Extend the current token's TTL
+    async fn renew(&self) -> Result<()> {
+        let token = self.token().await?;
+        let request = self.request(reqwest::Method::POST, "auth/token/renew-self", Some(&token)).json(&json!({}));
+        let lease = lease_from(&self.call(request, "token renewal").await?)?;
+        *self.lease.write().unwrap() = lease;
+        Ok(())
+    }
+    
+    /// Keep the token alive until the driver is dropped: renew it ahead of
+    /// expiry, and log in again once renewal stops working
+    fn spawn_renewer(self: &Arc<Self>) {
+        let session: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                let Some(this) = session.upgrade() else { break };
+                let (expires, renewable) = {
+                    let lease = this.lease.read().unwrap();
+                    (lease.expires, lease.renewable)
+                };
+                let due = expires.map(|expires| expires.checked_sub(this.renew_before).unwrap_or(expires));
+                let wait = match due {
+                    Some(due) if SystemTime::now() >= due => {
+                        let renewed = match renewable {
+                            true => this.renew().await,
+                            false => Err(GnosError::CapabilityExpired),
+                        };
+                        // Log in again only when the token couldn't be renewed
+                        let renewed = match renewed {
+                            Ok(()) => Ok(()),
+                            Err(_) => this.login().await,
+                        };
+                        match renewed {
+                            Ok(()) => {
+                                if let Some(expires) = this.lease.read().unwrap().expires {
+                                    info!("🔑 Vault token valid until {}", chrono::DateTime::<chrono::Utc>::from(expires).to_rfc3339());
+                                }
+                                Duration::ZERO
+                            }
+                            Err(e) => {
+                                warn!("🔑 Renewing the Vault token failed: {}", e);
+                                RETRY_INTERVAL
+                            }
+                        }
+                    }
+                    Some(due) => due.duration_since(SystemTime::now()).unwrap_or_default(),
+                    None if this.lease.read().unwrap().token.is_none() => match this.login().await {
+                        Ok(()) => Duration::ZERO,
+                        Err(e) => {
+                            warn!("🔑 Vault login failed: {}", e);
+                            RETRY_INTERVAL
+                        }
+                    },
+                    None => STATIC_RECHECK_INTERVAL,
+                };
+                drop(this);
+                // A zero wait goes straight back to schedule the new lease
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        });
+    }
+}
+
+/// The token in a login or renewal response's `auth` block
+fn lease_from(response: &Value) -> Result<Lease> {
+    let auth = &response["auth"];
+    let token = auth["client_token"].as_str()
+        .ok_or_else(|| GnosError::PermissionDenied("Vault returned no client token".to_string()))?;
+    let ttl = auth["lease_duration"].as_u64().unwrap_or(0);
+    Ok(Lease {
+        token: Some(token.to_string()),
+        expires: (ttl > 0).then(|| SystemTime::now() + Duration::from_secs(ttl)),
+        renewable: auth["renewable"].as_bool().unwrap_or(false),
+    })
+}
+
+/// 404 is `PathNotFound`, 403 `PermissionDenied`; otherwise the first of
+/// Vault's error messages
+fn vault_error(status: StatusCode, body: &[u8], what: &str) -> GnosError {
+    let message = serde_json::from_slice::<Value>(body).ok()
+        .and_then(|body| body["errors"].as_array()?.first()?.as_str().map(str::to_string))
+        .unwrap_or_else(|| status.to_string());
+    match status {
+        StatusCode::NOT_FOUND => GnosError::PathNotFound(what.to_string()),
+        StatusCode::FORBIDDEN => GnosError::PermissionDenied(format!("Vault refused {}: {}", what, message)),
+        _ => GnosError::Driver(format!("Vault {} failed: {}", what, message)),
+    }
+}
+
+pub struct VaultDriver {
+    session: Arc<VaultSession>,
+    mounts: Vec<String>,
+}
+
+impl VaultDriver {
+    pub async fn new(config: &VaultDriverConfig, context: &DriverContext) -> Result<Self> {
+        if config.mounts.is_empty() {
+            return Err(GnosError::Config("drivers.vault.mounts lists no KV mounts".to_string()));
+        }
+        let session = Arc::new(VaultSession {
+            http: context.http_for("vault"),
+            address: config.address.trim_end_matches('/').to_string(),
+            namespace: config.namespace.clone(),
+            auth: config.auth,
+            token_env: config.token_env.clone(),
+            approle_mount: config.approle_mount.clone(),
+            role_id: config.role_id.clone(),
+            secret_id_env: config.secret_id_env.clone(),
+            renew_before: Duration::from_secs(config.renew_before_seconds),
+            lease: RwLock::new(Lease::default()),
+        });
+        // Bad credentials show up at startup rather than on the first read
+        session.login().await?;
+        session.spawn_renewer();
+        
+        Ok(Self {
+            session,
+            mounts: config.mounts.iter().map(|mount| mount.trim_matches('/').to_string()).collect(),
+        })
+    }
+    
+    fn target<'a>(&self, path: &'a Path) -> Result<Target<'a>> {
+        let rest = path.strip_prefix(ROOT)
+            .map_err(|_| GnosError::PathNotFound(path.display().to_string()))?;
+        let components: Vec<&str> = rest.iter()
+            .map(|component| component.to_str().ok_or_else(|| GnosError::InvalidPath(path.display().to_string())))
+            .collect::<Result<_>>()?;
+        
+        match components.split_first() {
+            None => Ok(Target::Mounts),
+            Some((mount, _)) if !self.mounts.iter().any(|known| known == mount) => {
+                Err(GnosError::PathNotFound(path.display().to_string()))
+            }
+            Some((mount, rest)) => Ok(Target::Entry(mount, rest.join("/"))),
+        }
+    }
+    
+    async fn get(&self, api: &str, what: &str) -> Result<Value> {
+        let token = self.session.token().await?;
+        self.session.call(self.session.request(reqwest::Method::GET, api, Some(&token)), what).await
+    }
+    
+    /// Keys directly under `path`, subpaths ending in "/"
+    async fn keys(&self, mount: &str, path: &str) -> Result<Vec<String>> {
+        let api = format!("{}/metadata/{}?list=true", mount, path);
+        let response = self.get(&api, &format!("{}/{}/{}", ROOT, mount, path)).await?;
+        Ok(response["data"]["keys"].as_array()
+            .map(|keys| keys.iter().filter_map(|key| key.as_str().map(str::to_string)).collect())
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl GnosDriver for VaultDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let Target::Entry(mount, secret) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        let response = self.get(&format!("{}/data/{}", mount, secret), &path.display().to_string()).await?;
+        let mut json = serde_json::to_vec_pretty(&response["data"]["data"])
+            .map_err(|e| GnosError::Driver(format!("encoding {} failed: {}", path.display(), e)))?;
+        json.push(b'\n');
+        Ok(Bytes::from(json))
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let Target::Entry(mount, secret) = self.target(path)? else {
+            return Err(GnosError::InvalidPath(format!("{} is a directory", path.display())));
+        };
+        if secret.is_empty() {
+            return Err(GnosError::InvalidPath(format!("{} is a mount", path.display())));
+        }
+        let pairs: Value = serde_json::from_slice(data)
+            .ok()
+            .filter(Value::is_object)
+            .ok_or_else(|| GnosError::InvalidPath(format!("{} takes a JSON object of key/value pairs", path.display())))?;
+        
+        let token = self.session.token().await?;
+        let request = self.session.request(reqwest::Method::POST, &format!("{}/data/{}", mount, secret), Some(&token))
+            .json(&json!({ "data": pairs }));
+        let response = self.session.call(request, &path.display().to_string()).await?;
+        debug!("Stored version {} of {}", response["data"]["version"], path.display());
+        Ok(())
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        match self.target(path)? {
+            Target::Mounts => Ok(self.mounts.clone()),
+            Target::Entry(mount, dir) => {
+                let dir = if dir.is_empty() { dir } else { format!("{}/", dir) };
+                Ok(self.keys(mount, &dir).await?
+                    .into_iter()
+                    .map(|key| key.trim_end_matches('/').to_string())
+                    .collect())
+            }
+        }
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        match self.metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(GnosError::PathNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    /// A secret's version history dates it; a path with keys below is a
+    /// directory
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        let (mount, secret) = match self.target(path)? {
+            Target::Entry(mount, secret) if !secret.is_empty() => (mount, secret),
+            _ => return Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }),
+        };
+        
+        match self.get(&format!("{}/metadata/{}", mount, secret), &path.display().to_string()).await {
+            Ok(response) => {
+                let data = &response["data"];
+                let updated = data["updated_time"].as_str()
+                    .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+                    .map_or(SystemTime::UNIX_EPOCH, SystemTime::from);
+                let mut metadata = ResourceMetadata {
+                    last_modified: updated,
+                    mime_type: Some("application/json".to_string()),
+                    etag: data["current_version"].as_u64().map(|version| version.to_string()),
+                    ..ResourceMetadata::default()
+                };
+                for field in ["current_version", "oldest_version", "max_versions", "created_time"] {
+                    if let Some(value) = data.get(field).filter(|value| !value.is_null()) {
+                        let value = value.as_str().map_or_else(|| value.to_string(), str::to_string);
+                        metadata.custom_fields.insert(field.to_string(), value);
+                    }
+                }
+                Ok(metadata)
+            }
+            Err(GnosError::PathNotFound(missing)) => match self.keys(mount, &format!("{}/", secret)).await {
+                Ok(keys) if !keys.is_empty() => Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() }),
+                Ok(_) | Err(GnosError::PathNotFound(_)) => Err(GnosError::PathNotFound(missing)),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        "Vault Driver"
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(ROOT)]
+    }
+    
+    fn cache_mode(&self, _path: &Path) -> CacheMode {
+        // Other clients write new versions under us
+        CacheMode::DirectIo
+    }
+    
+    fn private(&self, path: &Path) -> bool {
+        matches!(self.target(path), Ok(Target::Entry(_, secret)) if !secret.is_empty())
+    }
+}
@@ -0,0 +1,273 @@
+//! Dry-run mode for mutations
+//!
+//! With `[dry_run]` enabled (or `gnos mount --dry-run`), every write,
+//! delete, directory creation and multipart or batch commit stops at the
+//! driver boundary. Reads, listings and metadata still reach the backends,
+//! so a script sees the real namespace; each mutation is checked against
+//! it, logged, audited and listed under `/proc/gnos/dry_run`, then reported
+//! as a success or as the configured error. Renames are a copy and a
+//! delete underneath, so they show up as both.
+//!
+//! Suppressed writes aren't kept anywhere: reading a file back after a
+//! dry-run write returns what the backend still holds once caches expire.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tracing::info;
+
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::{CacheMode, DryRunConfig};
+use crate::drivers::{BatchOp, ChangeStream, GnosDriver, Growth, PartPolicy, PathParams, ResourceMetadata, WriteOptions};
+use crate::faults::injected_error;
+use crate::security::{CapabilityManager, Operation};
+use crate::{GnosError, Result};
+
+/// Upload ID handed out for multipart uploads that never start
+const DRY_RUN_UPLOAD_ID: &str = "dry-run";
+
+/// A mutation that was kept from its backend
+struct Suppressed {
+    at: SystemTime,
+    driver: String,
+    op: &'static str,
+    path: PathBuf,
+    bytes: usize,
+}
+
+/// Records suppressed mutations and decides what they report
+pub struct DryRun {
+    config: DryRunConfig,
+    capability_manager: Arc<CapabilityManager>,
+    suppressed: AtomicU64,
+    recent: Mutex<VecDeque<Suppressed>>,
+}
+
+impl DryRun {
+    pub fn new(config: &DryRunConfig, capability_manager: Arc<CapabilityManager>) -> Arc<Self> {
+        Arc::new(Self {
+            config: config.clone(),
+            capability_manager,
+            suppressed: AtomicU64::new(0),
+            recent: Mutex::new(VecDeque::new()),
+        })
+    }
+    
+    /// Log and audit a mutation, then answer it as configured
+    fn suppress(&self, driver: &str, op: &'static str, path: &Path, bytes: usize) -> Result<()> {
+        info!("🧪 Dry run: {} {} {} ({} bytes) not sent", driver, op, path.display(), bytes);
+        self.capability_manager.audit_dry_run(path, Operation::Write, driver, format!("dry run: {} not sent", op));
+        self.suppressed.fetch_add(1, Ordering::Relaxed);
+        
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() >= self.config.max_entries {
+            recent.pop_front();
+        }
+        if self.config.max_entries > 0 {
+            recent.push_back(Suppressed {
+                at: SystemTime::now(),
+                driver: driver.to_string(),
+                op,
+                path: path.to_path_buf(),
+                bytes,
+            });
+        }
+        drop(recent);
+        
+        match self.config.error {
+            Some(error) => Err(injected_error(error, format!("dry run: {} {} not sent", op, path.display()))),
+            None => Ok(()),
+        }
+    }
+    
+    /// Plain-text view for `/proc/gnos/dry_run`, oldest first
+    pub fn status_report(&self) -> String {
+        let mut report = format!(
+            "suppressed: {}\nresponse: {}\n",
+            self.suppressed.load(Ordering::Relaxed),
+            self.config.error.map_or_else(|| "success".to_string(), |error| format!("{:?}", error)),
+        );
+        for entry in self.recent.lock().unwrap().iter() {
+            let timestamp: chrono::DateTime<chrono::Utc> = entry.at.into();
+            report.push_str(&format!("{}\t{}\t{}\t{}\t{}\n",
+                                     timestamp.to_rfc3339(), entry.driver, entry.op, entry.path.display(), entry.bytes));
+        }
+        report
+    }
+}
+
+/// A driver that checks and records mutations instead of sending them
+pub struct DryRunDriver {
+    /// Config name, e.g. `cloud` or `cloud@acme`
+    driver: String,
+    inner: Arc<dyn GnosDriver>,
+    dry_run: Arc<DryRun>,
+}
+
+impl DryRunDriver {
+    pub fn new(driver: &str, inner: Arc<dyn GnosDriver>, dry_run: Arc<DryRun>) -> Self {
+        Self { driver: driver.to_string(), inner, dry_run }
+    }
+    
+    /// Refuse what the backend would refuse outright, then suppress the rest
+    fn suppress(&self, op: &'static str, path: &Path, bytes: usize) -> Result<()> {
+        if !self.inner.supports(path) {
+            return Err(GnosError::InvalidPath(format!("{} does not serve {}", self.driver, path.display())));
+        }
+        self.dry_run.suppress(&self.driver, op, path, bytes)
+    }
+}
+
+#[async_trait]
+impl GnosDriver for DryRunDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        self.inner.read(path).await
+    }
+    
+    async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+        self.inner.read_checked(path).await
+    }
+    
+    async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        self.inner.read_range(path, offset, len).await
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.inner.streams(path)
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        self.inner.read_growing(path, have).await
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.suppress("write", path, data.len())
+    }
+    
+    fn upload_checksums(&self, path: &Path) -> &[ChecksumAlgorithm] {
+        self.inner.upload_checksums(path)
+    }
+    
+    async fn write_with(&self, path: &Path, data: &[u8], _options: &WriteOptions) -> Result<Option<Checksum>> {
+        // Nothing was stored, so there is no stored digest to check against
+        self.suppress("write", path, data.len())?;
+        Ok(None)
+    }
+    
+    fn accepted_encodings(&self) -> &[&'static str] {
+        self.inner.accepted_encodings()
+    }
+    
+    async fn write_encoded(&self, path: &Path, data: &[u8], _encoding: &str) -> Result<()> {
+        self.suppress("write", path, data.len())
+    }
+    
+    fn invokes(&self, path: &Path) -> bool {
+        self.inner.invokes(path)
+    }
+    
+    async fn invoke(&self, path: &Path, payload: &[u8]) -> Result<Bytes> {
+        // The call has side effects, so it isn't made and has no reply
+        self.suppress("invoke", path, payload.len())?;
+        Ok(Bytes::new())
+    }
+    
+    fn supports_parts(&self, path: &Path) -> bool {
+        self.inner.supports_parts(path)
+    }
+    
+    fn part_policy(&self, path: &Path) -> PartPolicy {
+        self.inner.part_policy(path)
+    }
+    
+    async fn begin_parts(&self, _path: &Path) -> Result<String> {
+        Ok(DRY_RUN_UPLOAD_ID.to_string())
+    }
+    
+    async fn write_part(&self, path: &Path, _upload_id: &str, _part: u64, data: &[u8]) -> Result<()> {
+        self.suppress("write_part", path, data.len())
+    }
+    
+    async fn complete_parts(&self, _path: &Path, _upload_id: &str, _parts: u64) -> Result<()> {
+        Ok(())
+    }
+    
+    async fn abort_parts(&self, _path: &Path, _upload_id: &str) -> Result<()> {
+        Ok(())
+    }
+    
+    fn supports_batches(&self) -> bool {
+        self.inner.supports_batches()
+    }
+    
+    async fn commit_batch(&self, ops: &[BatchOp]) -> Result<()> {
+        for op in ops {
+            match op {
+                BatchOp::Write { path, data } => self.suppress("write", path, data.len())?,
+                BatchOp::Delete { path } => self.suppress("delete", path, 0)?,
+            }
+        }
+        Ok(())
+    }
+    
+    async fn materialize(&self, path: &Path, params: &PathParams) -> Result<()> {
+        self.inner.materialize(path, params).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        self.suppress("create_dir", path, 0)
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        // Deleting something that isn't there fails for real, rehearsal or not
+        if !self.inner.exists(path).await? {
+            return Err(GnosError::PathNotFound(path.display().to_string()));
+        }
+        self.suppress("delete", path, 0)
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        self.inner.list(path).await
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        self.inner.list_with_metadata(path).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        self.inner.exists(path).await
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        self.inner.metadata(path).await
+    }
+    
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        self.inner.supports(path)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        self.inner.prefixes()
+    }
+    
+    fn cache_mode(&self, path: &Path) -> CacheMode {
+        self.inner.cache_mode(path)
+    }
+    
+    fn private(&self, path: &Path) -> bool {
+        self.inner.private(path)
+    }
+    
+    fn watch(&self, path: &Path) -> Option<ChangeStream> {
+        self.inner.watch(path)
+    }
+}
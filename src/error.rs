@@ -0,0 +1,264 @@
+//! `GnosError`, its categories and stable codes
+//!
+//! Every error belongs to one of four categories and carries a numeric code
+//! that stays fixed across releases, so scripts and API clients can branch
+//! on it rather than on message text:
+//!
+//! ```text
+//! auth     1001 PermissionDenied   1002 CapabilityExpired
+//! backend  2001 Driver             2002 Unreachable      2003 RateLimited
+//!          2004 QuotaExceeded      2005 ChecksumMismatch
+//! config   3001 Config
+//! vfs      4001 PathNotFound       4002 InvalidPath      4003 ResourceBusy
+//!          4004 Immutable          4005 Io
+//! ```
+//!
+//! Codes are only ever added. `Context` wraps another error with what was
+//! being done; it takes its code, category and mappings from the error it
+//! wraps, which `source()` also returns. The same mapping decides the errno
+//! FUSE and 9P report, the CLI's exit status (sysexits.h values), the S3
+//! gateway's status and error code, and the gRPC status, so a failure looks
+//! alike through every frontend.
+//!
+//! The enum stays flat, with the category derived from the code, rather than
+//! nesting an enum per category: drivers and frontends match on the variant
+//! they care about (`PathNotFound`, `RateLimited`) wherever it came from, and
+//! a variant moving between categories would otherwise break every such
+//! match. `InvalidPath` is for paths and arguments a caller sent; anything
+//! wrong in config files, token files or templates is `Config`.
+
+use std::fmt;
+use std::time::Duration;
+
+pub type Result<T> = std::result::Result<T, GnosError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GnosError {
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    
+    #[error("Path not found: {0}")]
+    PathNotFound(String),
+    
+    #[error("Driver error: {0}")]
+    Driver(String),
+    
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    
+    #[error("Capability expired")]
+    CapabilityExpired,
+    
+    #[error("Invalid path format: {0}")]
+    InvalidPath(String),
+    
+    #[error("Resource busy: {0}")]
+    ResourceBusy(String),
+    
+    #[error("Backend unreachable: {0}")]
+    Unreachable(String),
+    
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+    
+    #[error("Checksum mismatch: {0}")]
+    ChecksumMismatch(String),
+    
+    #[error("Immutable: {0}")]
+    Immutable(String),
+    
+    /// The backend asked to be left alone for a while, e.g. with a 429
+    #[error("Rate limited: {message}")]
+    RateLimited {
+        message: String,
+        /// How long the backend asked callers to wait, from `Retry-After`
+        retry_after: Option<Duration>,
+    },
+    
+    /// A configuration file or section that can't be used
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+    
+    /// `source` with what was being done when it happened; the message
+    /// includes the source's, since most callers only print the top error
+    #[error("{message}: {source}")]
+    Context {
+        message: String,
+        #[source]
+        source: Box<GnosError>,
+    },
+}
+
+/// Which part of GNOS an error comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Capabilities, tokens and policy
+    Auth,
+    /// A driver or the service behind it
+    Backend,
+    /// Configuration
+    Config,
+    /// The namespace itself: paths, handles, local files
+    Vfs,
+}
+
+impl ErrorCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCategory::Auth => "auth",
+            ErrorCategory::Backend => "backend",
+            ErrorCategory::Config => "config",
+            ErrorCategory::Vfs => "vfs",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl GnosError {
+    /// Wrap this error with what was being done
+    pub fn context(self, message: impl Into<String>) -> Self {
+        GnosError::Context { message: message.into(), source: Box::new(self) }
+    }
+    
+    /// The error under any `Context` layers, for matching on what went wrong
+    pub fn root(&self) -> &GnosError {
+        match self {
+            GnosError::Context { source, .. } => source.root(),
+            error => error,
+        }
+    }
+    
+    /// Stable numeric code, see the table above
+    pub fn code(&self) -> u32 {
+        match self.root() {
+            GnosError::PermissionDenied(_) => 1001,
+            GnosError::CapabilityExpired => 1002,
+            GnosError::Driver(_) => 2001,
+            GnosError::Unreachable(_) => 2002,
+            GnosError::RateLimited { .. } => 2003,
+            GnosError::QuotaExceeded(_) => 2004,
+            GnosError::ChecksumMismatch(_) => 2005,
+            GnosError::Config(_) => 3001,
+            GnosError::PathNotFound(_) => 4001,
+            GnosError::InvalidPath(_) => 4002,
+            GnosError::ResourceBusy(_) => 4003,
+            GnosError::Immutable(_) => 4004,
+            GnosError::Io(_) => 4005,
+            GnosError::Context { .. } => unreachable!("root() unwraps context"),
+        }
+    }
+    
+    pub fn category(&self) -> ErrorCategory {
+        match self.code() / 1000 {
+            1 => ErrorCategory::Auth,
+            2 => ErrorCategory::Backend,
+            3 => ErrorCategory::Config,
+            _ => ErrorCategory::Vfs,
+        }
+    }
+    
+    /// Whether the same call may succeed if made again later, unchanged
+    pub fn is_retryable(&self) -> bool {
+        match self.root() {
+            GnosError::Unreachable(_) | GnosError::RateLimited { .. } => true,
+            // Corrupted in transit, most likely; a fresh transfer can be clean
+            GnosError::ChecksumMismatch(_) => true,
+            GnosError::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+            ),
+            _ => false,
+        }
+    }
+    
+    /// How long to wait before trying again, if the backend said
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self.root() {
+            GnosError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+    
+    /// errno a frontend should report
+    pub fn errno(&self) -> i32 {
+        match self.root() {
+            GnosError::PathNotFound(_) => libc::ENOENT,
+            GnosError::PermissionDenied(_) | GnosError::CapabilityExpired => libc::EACCES,
+            GnosError::InvalidPath(_) | GnosError::Config(_) => libc::EINVAL,
+            GnosError::ResourceBusy(_) => libc::EBUSY,
+            GnosError::Unreachable(_) => libc::EHOSTUNREACH,
+            GnosError::QuotaExceeded(_) => libc::EDQUOT,
+            GnosError::Immutable(_) => libc::EPERM,
+            // Worth trying again later, unlike EIO
+            GnosError::RateLimited { .. } => libc::EAGAIN,
+            GnosError::Io(e) => e.raw_os_error().unwrap_or(libc::EIO),
+            GnosError::Driver(_) | GnosError::ChecksumMismatch(_) => libc::EIO,
+            GnosError::Context { .. } => unreachable!("root() unwraps context"),
+        }
+    }
+    
+    /// Exit status for the CLI, from sysexits.h
+    pub fn exit_code(&self) -> u8 {
+        match self.root() {
+            // EX_NOPERM
+            GnosError::PermissionDenied(_) | GnosError::CapabilityExpired | GnosError::Immutable(_) => 77,
+            // EX_CONFIG
+            GnosError::Config(_) => 78,
+            // EX_NOINPUT
+            GnosError::PathNotFound(_) => 66,
+            // EX_DATAERR
+            GnosError::InvalidPath(_) => 65,
+            // EX_UNAVAILABLE
+            GnosError::Unreachable(_) => 69,
+            // EX_TEMPFAIL
+            GnosError::RateLimited { .. } => 75,
+            // EX_CANTCREAT: it exists, or changed under us
+            GnosError::ResourceBusy(_) => 73,
+            // EX_IOERR
+            GnosError::QuotaExceeded(_) | GnosError::Io(_) => 74,
+            // EX_SOFTWARE
+            GnosError::Driver(_) | GnosError::ChecksumMismatch(_) => 70,
+            GnosError::Context { .. } => unreachable!("root() unwraps context"),
+        }
+    }
+    
+    /// HTTP status and S3-style error code for API responses
+    pub fn http_status(&self) -> (u16, &'static str) {
+        match self.root() {
+            GnosError::PermissionDenied(_) | GnosError::Immutable(_) => (403, "AccessDenied"),
+            GnosError::CapabilityExpired => (403, "ExpiredToken"),
+            GnosError::QuotaExceeded(_) => (403, "QuotaExceeded"),
+            GnosError::PathNotFound(_) => (404, "NoSuchKey"),
+            GnosError::InvalidPath(_) => (400, "InvalidArgument"),
+            GnosError::ResourceBusy(_) => (409, "OperationAborted"),
+            GnosError::RateLimited { .. } => (503, "SlowDown"),
+            GnosError::Unreachable(_) => (503, "ServiceUnavailable"),
+            GnosError::Config(_) | GnosError::Driver(_) | GnosError::Io(_) | GnosError::ChecksumMismatch(_) => {
+                (500, "InternalError")
+            }
+            GnosError::Context { .. } => unreachable!("root() unwraps context"),
+        }
+    }
+}
+
+/// `context` for results
+pub trait ResultExt<T> {
+    fn context(self, message: impl Into<String>) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn context(self, message: impl Into<String>) -> Result<T> {
+        self.map_err(|e| e.context(message))
+    }
+}
+
+/// Exit status for an error the CLI ends with: the `GnosError`'s, when it
+/// is one, and 1 otherwise
+pub fn exit_code(error: &(dyn std::error::Error + 'static)) -> u8 {
+    error.downcast_ref::<GnosError>().map_or(1, GnosError::exit_code)
+}
@@ -0,0 +1,221 @@
+//! Namespace change events
+//!
+//! Every change made through GNOS — writes through the mount or 9P, the
+//! library API, gRPC, the S3 gateway and committed transactions — is
+//! published as a `NamespaceEvent`. Subscribers in the same process use
+//! `EventBus::subscribe` or `EventBus::stream`; other processes read one
+//! JSON event per line from the Unix socket:
+//!
+//! ```text
+//! socat - UNIX-CONNECT:/run/gnos/events.sock
+//! {"kind":"modified","path":"/cloud/aws/s3/cfg/app.toml","driver":"aws","principal":"ci","timestamp":1760000000}
+//! ```
+//!
+//! Webhooks receive each event under their prefix as a JSON POST. Events
+//! are only built while someone is subscribed, and a subscriber that falls
+//! more than `buffer` events behind loses the oldest ones.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::config::{EventsConfig, WebhookSink};
+use crate::drivers::network::SharedHttpClient;
+use crate::drivers::GnosDriver;
+use crate::{GnosError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Modified => "modified",
+            EventKind::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NamespaceEvent {
+    pub kind: EventKind,
+    pub path: PathBuf,
+    /// Driver that owns the path
+    pub driver: String,
+    /// Capability owner the change was made under
+    pub principal: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// Fan-out of namespace changes to every subscriber; cheap to share
+pub struct EventBus {
+    sender: broadcast::Sender<NamespaceEvent>,
+}
+
+impl EventBus {
+    /// A bus with no sinks, for library subscribers
+    pub fn new(buffer: usize) -> Arc<Self> {
+        let (sender, _) = broadcast::channel(buffer.max(1));
+        Arc::new(Self { sender })
+    }
+    
+    /// A bus with the socket and webhook sinks `config` asks for; webhooks
+    /// are posted through `http`
+    pub async fn start(config: &EventsConfig, http: Arc<SharedHttpClient>) -> Result<Arc<Self>> {
+        let bus = Self::new(config.buffer);
+        
+        if let Some(socket_path) = &config.socket_path {
+            bus.serve_socket(socket_path).await?;
+            info!("📣 Streaming namespace events on {}", socket_path.display());
+        }
+        for sink in &config.webhooks {
+            bus.spawn_webhook(sink.clone(), http.clone());
+            info!("📣 Posting events under {} to {}", sink.prefix, sink.url);
+        }
+        
+        Ok(bus)
+    }
+    
+    /// Whether anything would receive an event published now
+    pub fn is_active(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+    
+    pub fn publish(&self, kind: EventKind, path: &Path, driver: &str, principal: String) {
+        if !self.is_active() {
+            return;
+        }
+        
+        let event = NamespaceEvent {
+            kind,
+            path: path.to_path_buf(),
+            driver: driver.to_string(),
+            principal,
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        };
+        debug!("Event: {:?}", event);
+        let _ = self.sender.send(event);
+    }
+    
+    /// Whether writing `path` creates it; the driver is only asked while
+    /// someone is subscribed, and an unanswerable probe counts as a change
+    pub async fn write_kind(&self, driver: &dyn GnosDriver, path: &Path) -> EventKind {
+        if !self.is_active() {
+            return EventKind::Modified;
+        }
+        match driver.metadata(path).await {
+            Err(GnosError::PathNotFound(_)) => EventKind::Created,
+            _ => EventKind::Modified,
+        }
+    }
+    
+    pub fn subscribe(&self) -> broadcast::Receiver<NamespaceEvent> {
+        self.sender.subscribe()
+    }
+    
+    /// Events for paths under `prefix`, skipping any missed while lagging
+    pub fn stream(&self, prefix: &Path) -> impl Stream<Item = NamespaceEvent> {
+        let prefix = prefix.to_path_buf();
+        stream::unfold((self.subscribe(), prefix), |(mut events, prefix)| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.path.starts_with(&prefix) => return Some((event, (events, prefix))),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => warn!("📣 Event subscriber missed {} events", missed),
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+    
+    /// Accept socket clients and stream every event to each as JSON lines
+    async fn serve_socket(&self, socket_path: &Path) -> Result<()> {
+        if let Some(dir) = socket_path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // A socket left behind by an earlier run would make bind fail
+        if let Err(e) = tokio::fs::remove_file(socket_path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+        let listener = UnixListener::bind(socket_path)?;
+        
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(stream_events(stream, sender.subscribe()));
+                    }
+                    Err(e) => warn!("❌ Event socket accept failed: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+    
+    fn spawn_webhook(&self, sink: WebhookSink, http: Arc<SharedHttpClient>) {
+        let mut events = self.subscribe();
+        
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) if event.path.starts_with(&sink.prefix) => event,
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("📣 Webhook {} missed {} events", sink.url, missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                
+                match http.fetch(http.client().post(&sink.url).json(&event)).await {
+                    Ok((status, _)) if !status.is_success() => {
+                        warn!("❌ Event webhook {} returned {}", sink.url, status);
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("❌ Event webhook {} failed: {}", sink.url, e),
+                }
+            }
+        });
+    }
+}
+
+async fn stream_events(mut stream: UnixStream, mut events: broadcast::Receiver<NamespaceEvent>) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("📣 Event socket client missed {} events", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        
+        let Ok(mut line) = serde_json::to_vec(&event) else {
+            continue;
+        };
+        line.push(b'\n');
+        if stream.write_all(&line).await.is_err() {
+            debug!("Event socket client went away");
+            return;
+        }
+    }
+}
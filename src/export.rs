@@ -0,0 +1,384 @@
+//! Subtree export to tar and zip archives
+//!
+//! `export` lists a namespace prefix first, so the archive holds exactly
+//! the objects and sizes seen at that moment, then streams each object into
+//! the archive in ranged chunks rather than buffering it whole. An object
+//! whose size or modification time changes while it is being archived fails
+//! the export instead of leaving a torn copy in it.
+//!
+//! ```no_run
+//! # async fn demo(client: gnos::GnosClient) -> gnos::Result<()> {
+//! use std::path::Path;
+//! use std::sync::Arc;
+//! use gnos::export::{self, ArchiveFormat, ExportOptions, ExportProgress};
+//!
+//! let options = ExportOptions {
+//!     exclude: vec!["*.tmp".to_string()],
+//!     ..ExportOptions::new(ArchiveFormat::TarZst)
+//! };
+//! let progress = Arc::new(ExportProgress::new());
+//! export::export(&client, Path::new("/cloud/aws/s3/logs"), Path::new("logs.tar.zst"), &options, &progress).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use chrono::{Datelike, Timelike};
+use tokio::runtime::Handle;
+use tracing::{debug, info};
+
+use crate::client::GnosClient;
+use crate::search::glob_match;
+use crate::{GnosError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = GnosError;
+    
+    fn from_str(format: &str) -> Result<Self> {
+        match format {
+            "tar" => Ok(ArchiveFormat::Tar),
+            "tar.zst" | "tzst" => Ok(ArchiveFormat::TarZst),
+            "zip" => Ok(ArchiveFormat::Zip),
+            other => Err(GnosError::InvalidPath(format!("unknown archive format: {} (tar, tar.zst or zip)", other))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ArchiveFormat,
+    /// Globs over paths relative to the prefix; when set, only matching objects are exported
+    pub include: Vec<String>,
+    /// Globs over relative paths; a matching directory is skipped whole
+    pub exclude: Vec<String>,
+    /// Bytes read from a driver at a time
+    pub chunk_size: u64,
+}
+
+impl ExportOptions {
+    pub fn new(format: ArchiveFormat) -> Self {
+        Self {
+            format,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            chunk_size: 8 * 1024 * 1024,
+        }
+    }
+    
+    fn wants(&self, relative: &str, is_dir: bool) -> bool {
+        if self.exclude.iter().any(|pattern| glob_match(pattern.as_bytes(), relative.as_bytes())) {
+            return false;
+        }
+        is_dir
+            || self.include.is_empty()
+            || self.include.iter().any(|pattern| glob_match(pattern.as_bytes(), relative.as_bytes()))
+    }
+}
+
+/// Counts of a running export, readable from another task
+#[derive(Debug, Default)]
+pub struct ExportProgress {
+    total_files: AtomicU64,
+    total_bytes: AtomicU64,
+    files: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl ExportProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Objects in the snapshot; zero until the prefix has been listed
+    pub fn total_files(&self) -> u64 {
+        self.total_files.load(Ordering::Relaxed)
+    }
+    
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes.load(Ordering::Relaxed)
+    }
+    
+    /// Objects written to the archive so far
+    pub fn files(&self) -> u64 {
+        self.files.load(Ordering::Relaxed)
+    }
+    
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// What an export wrote
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExportStats {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// One archive member as listed when the export started
+#[derive(Debug, Clone)]
+struct Member {
+    path: PathBuf,
+    /// Name inside the archive
+    name: String,
+    is_dir: bool,
+    size: u64,
+    mtime: SystemTime,
+}
+
+/// Write `prefix` and everything below it to an archive at `dest`
+pub async fn export(
+    client: &GnosClient,
+    prefix: &Path,
+    dest: &Path,
+    options: &ExportOptions,
+    progress: &Arc<ExportProgress>,
+) -> Result<ExportStats> {
+    let started = SystemTime::now();
+    let members = snapshot(client, prefix, options).await?;
+    let files = members.iter().filter(|member| !member.is_dir).count() as u64;
+    progress.total_files.store(files, Ordering::Relaxed);
+    progress.total_bytes.store(members.iter().map(|member| member.size).sum(), Ordering::Relaxed);
+    info!("📦 Exporting {} objects from {}", files, prefix.display());
+    
+    // Archive writers are synchronous, so the archive is built on a blocking
+    // thread that pulls each chunk from the drivers as it needs it
+    let file = std::fs::File::create(dest)?;
+    let archive = Archive {
+        client: client.clone(),
+        runtime: Handle::current(),
+        options: options.clone(),
+        progress: progress.clone(),
+        started,
+    };
+    tokio::task::spawn_blocking(move || archive.write(file, &members))
+        .await
+        .map_err(|e| GnosError::Driver(format!("export task failed: {}", e)))??;
+    
+    Ok(ExportStats {
+        files: progress.files(),
+        bytes: progress.bytes(),
+    })
+}
+
+/// List the subtree breadth-first into archive members, applying the filters
+async fn snapshot(client: &GnosClient, prefix: &Path, options: &ExportOptions) -> Result<Vec<Member>> {
+    let root = client.metadata(prefix).await?;
+    let base = prefix.file_name().map_or_else(|| "root".to_string(), |name| name.to_string_lossy().into_owned());
+    if !root.is_directory {
+        return Ok(vec![Member {
+            path: prefix.to_path_buf(),
+            name: base,
+            is_dir: false,
+            size: root.size,
+            mtime: root.last_modified,
+        }]);
+    }
+    
+    let mut members = vec![Member {
+        path: prefix.to_path_buf(),
+        name: base,
+        is_dir: true,
+        size: 0,
+        mtime: root.last_modified,
+    }];
+    let mut level = vec![0];
+    while !level.is_empty() {
+        let mut next = Vec::new();
+        for parent in level {
+            let (dir, dir_name) = (members[parent].path.clone(), members[parent].name.clone());
+            let mut entries = client.list_with_metadata(&dir).await?;
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            
+            for (name, metadata) in entries {
+                let path = dir.join(&name);
+                let relative = path.strip_prefix(prefix).unwrap_or(&path).to_string_lossy().into_owned();
+                if !options.wants(&relative, metadata.is_directory) {
+                    debug!("Export skips {}", path.display());
+                    continue;
+                }
+                if metadata.is_directory {
+                    next.push(members.len());
+                }
+                members.push(Member {
+                    path,
+                    name: format!("{}/{}", dir_name, name),
+                    is_dir: metadata.is_directory,
+                    size: if metadata.is_directory { 0 } else { metadata.size },
+                    mtime: metadata.last_modified,
+                });
+            }
+        }
+        level = next;
+    }
+    
+    Ok(members)
+}
+
+struct Archive {
+    client: GnosClient,
+    runtime: Handle,
+    options: ExportOptions,
+    progress: Arc<ExportProgress>,
+    /// When the snapshot was taken
+    started: SystemTime,
+}
+
+impl Archive {
+    fn write(&self, file: std::fs::File, members: &[Member]) -> Result<()> {
+        match self.options.format {
+            ArchiveFormat::Tar => {
+                self.write_tar(io::BufWriter::new(file), members)?.flush()?;
+            }
+            ArchiveFormat::TarZst => {
+                let encoder = zstd::stream::write::Encoder::new(io::BufWriter::new(file), 3)?;
+                self.write_tar(encoder, members)?.finish()?.flush()?;
+            }
+            ArchiveFormat::Zip => self.write_zip(file, members)?,
+        }
+        Ok(())
+    }
+    
+    fn write_tar<W: Write>(&self, writer: W, members: &[Member]) -> Result<W> {
+        let mut builder = tar::Builder::new(writer);
+        for member in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_mtime(unix_seconds(member.mtime));
+            if member.is_dir {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                builder.append_data(&mut header, format!("{}/", member.name), io::empty())?;
+                continue;
+            }
+            
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(0o644);
+            header.set_size(member.size);
+            builder.append_data(&mut header, &member.name, self.reader(member))?;
+            self.finish_member(member)?;
+        }
+        Ok(builder.into_inner()?)
+    }
+    
+    fn write_zip(&self, file: std::fs::File, members: &[Member]) -> Result<()> {
+        let mut zip = zip::ZipWriter::new(io::BufWriter::new(file));
+        for member in members {
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .last_modified_time(zip_time(member.mtime))
+                .large_file(member.size >= u32::MAX as u64);
+            if member.is_dir {
+                zip.add_directory(format!("{}/", member.name), options).map_err(io::Error::from)?;
+                continue;
+            }
+            
+            zip.start_file(member.name.as_str(), options).map_err(io::Error::from)?;
+            io::copy(&mut self.reader(member), &mut zip)?;
+            self.finish_member(member)?;
+        }
+        zip.finish().map_err(io::Error::from)?.flush()?;
+        Ok(())
+    }
+    
+    fn reader(&self, member: &Member) -> RangeReader<'_> {
+        RangeReader {
+            archive: self,
+            path: member.path.clone(),
+            offset: 0,
+            size: member.size,
+            buffer: Bytes::new(),
+        }
+    }
+    
+    /// Fail the export if the object changed while it was being read
+    ///
+    /// Drivers without stored timestamps report the current time, so only
+    /// modification times from before the snapshot are compared.
+    fn finish_member(&self, member: &Member) -> Result<()> {
+        let now = self.runtime.block_on(self.client.metadata(&member.path))?;
+        let rewritten = member.mtime < self.started && now.last_modified != member.mtime;
+        if now.size != member.size || rewritten {
+            return Err(GnosError::ResourceBusy(format!("{} changed during export", member.path.display())));
+        }
+        self.progress.files.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Reads an object chunk by chunk, up to its size at snapshot time
+struct RangeReader<'a> {
+    archive: &'a Archive,
+    path: PathBuf,
+    offset: u64,
+    size: u64,
+    buffer: Bytes,
+}
+
+impl Read for RangeReader<'_> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            if self.offset >= self.size {
+                return Ok(0);
+            }
+            let len = std::cmp::min(self.archive.options.chunk_size, self.size - self.offset);
+            let chunk = self.archive.runtime
+                .block_on(self.archive.client.read_range(&self.path, self.offset, len))
+                .map_err(io::Error::other)?;
+            if chunk.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{} shrank during export", self.path.display()),
+                ));
+            }
+            self.offset += chunk.len() as u64;
+            self.archive.progress.bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            self.buffer = chunk;
+        }
+        
+        let n = std::cmp::min(out.len(), self.buffer.len());
+        out[..n].copy_from_slice(&self.buffer.split_to(n));
+        Ok(n)
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+/// Zip timestamps are local calendar time from 1980 on
+fn zip_time(time: SystemTime) -> zip::DateTime {
+    let time = chrono::DateTime::<chrono::Local>::from(time);
+    zip::DateTime::from_date_and_time(
+        time.year().clamp(1980, 2107) as u16,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    ).unwrap_or_default()
+}
@@ -0,0 +1,472 @@
+//! Fault injection for resilience testing
+//!
+//! With `[faults]` enabled, every driver call made by the mount first
+//! passes through the active fault rules, which can delay it, fail it with
+//! a chosen error, or cut a read short. Rules come from the config and can
+//! be added and removed at runtime over a Unix socket taking one JSON
+//! command per line:
+//!
+//! ```text
+//! socat - UNIX-CONNECT:/run/gnos/faults.sock
+//! {"command":"add","rule":{"driver":"cloud","prefix":"/cloud/aws","fault":"latency","ms":2000}}
+//! {"ok":true,"id":1}
+//! {"command":"add","rule":{"ops":["write"],"probability":0.1,"fault":"error","error":"unreachable"}}
+//! {"ok":true,"id":2}
+//! {"command":"list"}
+//! {"command":"remove","id":1}
+//! {"command":"clear"}
+//! ```
+//!
+//! `/proc/gnos/faults` lists the active rules and how often each has fired.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{debug, info, warn};
+
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::{CacheMode, FaultConfig, FaultRule, InjectedError, InjectedFault};
+use crate::drivers::{BatchOp, ChangeStream, GnosDriver, Growth, PartPolicy, PathParams, ResourceMetadata, WriteOptions};
+use crate::{GnosError, Result};
+
+/// A rule in force, with how often it has fired
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveFault {
+    pub id: u64,
+    pub rule: FaultRule,
+    pub hits: u64,
+}
+
+/// What a driver call should suffer
+enum Effect {
+    Delay(Duration),
+    Fail(GnosError),
+    Truncate(u64),
+}
+
+/// The active fault rules; cheap to share between drivers
+pub struct FaultInjector {
+    faults: RwLock<Vec<ActiveFault>>,
+    next_id: AtomicU64,
+    /// State of the generator deciding probabilistic faults
+    seed: AtomicU64,
+}
+
+impl FaultInjector {
+    pub fn new(config: &FaultConfig) -> Arc<Self> {
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        let injector = Arc::new(Self {
+            faults: RwLock::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+            seed: AtomicU64::new(seed),
+        });
+        for rule in &config.rules {
+            injector.add(rule.clone());
+        }
+        injector
+    }
+    
+    /// Put a rule in force and return its ID
+    pub fn add(&self, rule: FaultRule) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        info!("💥 Fault {} injected: {:?}", id, rule);
+        self.faults.write().unwrap().push(ActiveFault { id, rule, hits: 0 });
+        id
+    }
+    
+    pub fn remove(&self, id: u64) -> bool {
+        let mut faults = self.faults.write().unwrap();
+        let before = faults.len();
+        faults.retain(|fault| fault.id != id);
+        faults.len() != before
+    }
+    
+    pub fn clear(&self) {
+        self.faults.write().unwrap().clear();
+    }
+    
+    pub fn list(&self) -> Vec<ActiveFault> {
+        self.faults.read().unwrap().clone()
+    }
+    
+    /// Plain-text view for `/proc/gnos/faults`
+    pub fn status_report(&self) -> String {
+        let faults = self.faults.read().unwrap();
+        let mut report = format!("faults: {}\n", faults.len());
+        for fault in faults.iter() {
+            let rule = &fault.rule;
+            report.push_str(&format!(
+                "{}\t{}\t{}\t{}\tp={}\t{:?}\thits={}{}\n",
+                fault.id,
+                rule.driver.as_deref().unwrap_or("*"),
+                rule.prefix.as_deref().unwrap_or("/"),
+                if rule.ops.is_empty() { "*".to_string() } else { rule.ops.join(",") },
+                rule.probability,
+                rule.fault,
+                fault.hits,
+                rule.count.map_or_else(String::new, |count| format!("/{}", count)),
+            ));
+        }
+        report
+    }
+    
+    /// Effects of the rules matching a call, counting each that fires;
+    /// rules that used up their count are retired
+    fn effects(&self, driver: &str, op: &str, path: &Path) -> Vec<Effect> {
+        if self.faults.read().unwrap().is_empty() {
+            return Vec::new();
+        }
+        
+        let mut effects = Vec::new();
+        let mut faults = self.faults.write().unwrap();
+        for fault in faults.iter_mut() {
+            let rule = &fault.rule;
+            let matches = rule.driver.as_deref().is_none_or(|name| name == driver)
+                && rule.prefix.as_deref().is_none_or(|prefix| path.starts_with(prefix))
+                && (rule.ops.is_empty() || rule.ops.iter().any(|name| name == op))
+                && rule.count.is_none_or(|count| fault.hits < count);
+            if !matches || !self.roll(rule.probability) {
+                continue;
+            }
+            
+            fault.hits += 1;
+            debug!("Fault {} fires on {} {} {}", fault.id, driver, op, path.display());
+            effects.push(match &rule.fault {
+                InjectedFault::Latency { ms } => Effect::Delay(Duration::from_millis(*ms)),
+                InjectedFault::Error { error } => Effect::Fail(injected_error(*error, format!("injected by fault {} on {}", fault.id, path.display()))),
+                InjectedFault::PartialRead { max_bytes } => Effect::Truncate(*max_bytes),
+            });
+        }
+        faults.retain(|fault| fault.rule.count.is_none_or(|count| fault.hits < count));
+        effects
+    }
+    
+    /// Whether a fault with `probability` fires this time
+    fn roll(&self, probability: f64) -> bool {
+        if probability >= 1.0 {
+            return true;
+        }
+        // SplitMix64; good enough to spread faults over calls
+        let mut z = self.seed.fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+    
+    /// Delay or fail a call as its matching rules say; returns the most a
+    /// read may hand back
+    async fn apply(&self, driver: &str, op: &str, path: &Path) -> Result<Option<u64>> {
+        let mut limit: Option<u64> = None;
+        for effect in self.effects(driver, op, path) {
+            match effect {
+                Effect::Delay(delay) => tokio::time::sleep(delay).await,
+                Effect::Fail(error) => return Err(error),
+                Effect::Truncate(max_bytes) => limit = Some(limit.map_or(max_bytes, |limit| limit.min(max_bytes))),
+            }
+        }
+        Ok(limit)
+    }
+    
+    /// Accept control clients on `socket_path`
+    pub async fn serve(self: &Arc<Self>, socket_path: &Path) -> Result<()> {
+        if let Some(dir) = socket_path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // A socket left behind by an earlier run would make bind fail
+        if let Err(e) = tokio::fs::remove_file(socket_path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+        let listener = UnixListener::bind(socket_path)?;
+        
+        let injector = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(control(stream, injector.clone()));
+                    }
+                    Err(e) => warn!("❌ Fault socket accept failed: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+    Add { rule: FaultRule },
+    Remove { id: u64 },
+    Clear,
+    List,
+}
+
+#[derive(Debug, Serialize)]
+struct Reply {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    faults: Option<Vec<ActiveFault>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Reply {
+    fn ok() -> Self {
+        Self { ok: true, id: None, faults: None, error: None }
+    }
+    
+    fn error(error: String) -> Self {
+        Self { ok: false, id: None, faults: None, error: Some(error) }
+    }
+}
+
+/// Answer one control client's commands, a JSON reply per line
+async fn control(stream: UnixStream, injector: Arc<FaultInjector>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Command>(&line) {
+            Ok(Command::Add { rule }) => Reply { id: Some(injector.add(rule)), ..Reply::ok() },
+            Ok(Command::Remove { id }) if injector.remove(id) => {
+                info!("💥 Fault {} removed", id);
+                Reply::ok()
+            }
+            Ok(Command::Remove { id }) => Reply::error(format!("no fault {}", id)),
+            Ok(Command::Clear) => {
+                injector.clear();
+                info!("💥 Faults cleared");
+                Reply::ok()
+            }
+            Ok(Command::List) => Reply { faults: Some(injector.list()), ..Reply::ok() },
+            Err(e) => Reply::error(format!("bad command: {}", e)),
+        };
+        
+        let Ok(mut line) = serde_json::to_vec(&reply) else {
+            continue;
+        };
+        line.push(b'\n');
+        if writer.write_all(&line).await.is_err() {
+            debug!("Fault socket client went away");
+            return;
+        }
+    }
+}
+
+/// The error `error` stands for, carrying `message`
+pub(crate) fn injected_error(error: InjectedError, message: String) -> GnosError {
+    match error {
+        InjectedError::Io => GnosError::Driver(message),
+        InjectedError::Unreachable => GnosError::Unreachable(message),
+        InjectedError::NotFound => GnosError::PathNotFound(message),
+        InjectedError::PermissionDenied => GnosError::PermissionDenied(message),
+        InjectedError::Busy => GnosError::ResourceBusy(message),
+        InjectedError::QuotaExceeded => GnosError::QuotaExceeded(message),
+        InjectedError::RateLimited => GnosError::RateLimited { message, retry_after: None },
+    }
+}
+
+/// A driver whose calls pass through the fault rules first
+pub struct FaultDriver {
+    /// Config name rules match on, e.g. `cloud` or `cloud@acme`
+    driver: String,
+    inner: Arc<dyn GnosDriver>,
+    faults: Arc<FaultInjector>,
+}
+
+impl FaultDriver {
+    pub fn new(driver: &str, inner: Arc<dyn GnosDriver>, faults: Arc<FaultInjector>) -> Self {
+        Self { driver: driver.to_string(), inner, faults }
+    }
+    
+    async fn inject(&self, op: &str, path: &Path) -> Result<Option<u64>> {
+        self.faults.apply(&self.driver, op, path).await
+    }
+}
+
+fn truncate(data: Bytes, limit: Option<u64>) -> Bytes {
+    match limit {
+        Some(limit) if (data.len() as u64) > limit => data.slice(..limit as usize),
+        _ => data,
+    }
+}
+
+#[async_trait]
+impl GnosDriver for FaultDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        let limit = self.inject("read", path).await?;
+        Ok(truncate(self.inner.read(path).await?, limit))
+    }
+    
+    async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        let limit = self.inject("read", path).await?;
+        Ok(truncate(self.inner.read_range(path, offset, len).await?, limit))
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.inner.streams(path)
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        let limit = self.inject("read", path).await?;
+        let mut growth = self.inner.read_growing(path, have).await?;
+        growth.data = truncate(growth.data, limit);
+        Ok(growth)
+    }
+    
+    async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+        let limit = self.inject("read", path).await?;
+        let (data, checksum) = self.inner.read_checked(path).await?;
+        Ok((truncate(data, limit), checksum))
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.inject("write", path).await?;
+        self.inner.write(path, data).await
+    }
+    
+    fn upload_checksums(&self, path: &Path) -> &[ChecksumAlgorithm] {
+        self.inner.upload_checksums(path)
+    }
+    
+    async fn write_with(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+        self.inject("write", path).await?;
+        self.inner.write_with(path, data, options).await
+    }
+    
+    fn conditional_writes(&self, path: &Path) -> bool {
+        self.inner.conditional_writes(path)
+    }
+    
+    fn accepted_encodings(&self) -> &[&'static str] {
+        self.inner.accepted_encodings()
+    }
+    
+    async fn write_encoded(&self, path: &Path, data: &[u8], encoding: &str) -> Result<()> {
+        self.inject("write", path).await?;
+        self.inner.write_encoded(path, data, encoding).await
+    }
+    
+    fn invokes(&self, path: &Path) -> bool {
+        self.inner.invokes(path)
+    }
+    
+    async fn invoke(&self, path: &Path, payload: &[u8]) -> Result<Bytes> {
+        self.inject("write", path).await?;
+        self.inner.invoke(path, payload).await
+    }
+    
+    fn supports_parts(&self, path: &Path) -> bool {
+        self.inner.supports_parts(path)
+    }
+    
+    fn part_policy(&self, path: &Path) -> PartPolicy {
+        self.inner.part_policy(path)
+    }
+    
+    async fn begin_parts(&self, path: &Path) -> Result<String> {
+        self.inject("write", path).await?;
+        self.inner.begin_parts(path).await
+    }
+    
+    async fn write_part(&self, path: &Path, upload_id: &str, part: u64, data: &[u8]) -> Result<()> {
+        self.inject("write", path).await?;
+        self.inner.write_part(path, upload_id, part, data).await
+    }
+    
+    async fn complete_parts(&self, path: &Path, upload_id: &str, parts: u64) -> Result<()> {
+        self.inject("write", path).await?;
+        self.inner.complete_parts(path, upload_id, parts).await
+    }
+    
+    async fn abort_parts(&self, path: &Path, upload_id: &str) -> Result<()> {
+        self.inner.abort_parts(path, upload_id).await
+    }
+    
+    fn supports_batches(&self) -> bool {
+        self.inner.supports_batches()
+    }
+    
+    async fn commit_batch(&self, ops: &[BatchOp]) -> Result<()> {
+        for op in ops {
+            self.inject("commit", op.path()).await?;
+        }
+        self.inner.commit_batch(ops).await
+    }
+    
+    async fn materialize(&self, path: &Path, params: &PathParams) -> Result<()> {
+        self.inner.materialize(path, params).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        self.inject("create_dir", path).await?;
+        self.inner.create_dir(path).await
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        self.inject("delete", path).await?;
+        self.inner.delete(path).await
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        self.inject("list", path).await?;
+        self.inner.list(path).await
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        self.inject("list", path).await?;
+        self.inner.list_with_metadata(path).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        self.inject("metadata", path).await?;
+        self.inner.exists(path).await
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        self.inject("metadata", path).await?;
+        self.inner.metadata(path).await
+    }
+    
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        self.inner.supports(path)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        self.inner.prefixes()
+    }
+    
+    fn cache_mode(&self, path: &Path) -> CacheMode {
+        self.inner.cache_mode(path)
+    }
+    
+    fn private(&self, path: &Path) -> bool {
+        self.inner.private(path)
+    }
+    
+    fn watch(&self, path: &Path) -> Option<ChangeStream> {
+        self.inner.watch(path)
+    }
+}
@@ -0,0 +1,636 @@
+//! S3-compatible gateway
+//!
+//! Serves the namespace through path-style S3 requests so existing SDKs and
+//! tools can use it: each top-level directory is a bucket and the rest of
+//! the path is the key. Callers authenticate with a capability token passed
+//! as the session token (`x-amz-security-token`, or `X-Amz-Security-Token`
+//! in a presigned URL), and a request without one is refused. The token is
+//! the credential: it carries the daemon's HMAC signature, which is verified
+//! along with its expiry on every request, so an SDK's SigV4 signature over
+//! placeholder keys adds nothing and is ignored.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use axum::body::Body;
+use axum::extract::{DefaultBodyLimit, Path as UrlPath, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::StreamExt;
+use tracing::{debug, info};
+
+use crate::client::GnosClient;
+use crate::drivers::ResourceMetadata;
+use crate::mime;
+use crate::security::{Capability, Operation};
+use crate::{GnosError, Result};
+
+/// Namespace roots served as buckets
+const BUCKETS: [&str; 4] = ["cloud", "dev", "net", "proc"];
+
+/// Largest object accepted in a single PUT
+const MAX_PUT_BYTES: usize = 1024 * 1024 * 1024;
+
+/// Most entries a recursive listing visits before it stops descending
+const MAX_WALK_ENTRIES: usize = 10_000;
+
+const TOKEN_HEADER: &str = "x-amz-security-token";
+const TOKEN_PARAM: &str = "X-Amz-Security-Token";
+
+pub struct S3Gateway {
+    client: GnosClient,
+}
+
+impl S3Gateway {
+    pub fn new(client: GnosClient) -> Self {
+        Self { client }
+    }
+    
+    /// Serve until the process is stopped
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("🪣 Serving S3 gateway on {}", addr);
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+    
+    fn router(self) -> Router {
+        Router::new()
+            .route("/", get(list_buckets))
+            .route("/:bucket", get(list_objects).head(head_bucket))
+            .route("/:bucket/", get(list_objects).head(head_bucket))
+            .route("/:bucket/*key", get(get_object).head(head_object).put(put_object).delete(delete_object))
+            .layer(DefaultBodyLimit::max(MAX_PUT_BYTES))
+            .with_state(Arc::new(self))
+    }
+    
+    /// The client to act with, carrying the caller's capability; without
+    /// one the request is refused rather than served with the gateway's own
+    fn client_for(&self, headers: &HeaderMap, query: &HashMap<String, String>) -> Result<GnosClient> {
+        let token = headers.get(TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .or_else(|| query.get(TOKEN_PARAM).map(String::as_str));
+        
+        match token {
+            Some(token) => Ok(self.client.clone().with_token(token)),
+            None => Err(GnosError::PermissionDenied(format!("requests need a capability token in {}", TOKEN_HEADER))),
+        }
+    }
+}
+
+/// A URL granting the capability's access to one object until it expires;
+/// `token` is the capability, signed
+pub fn presign_url(endpoint: &str, capability: &Capability, token: &str) -> String {
+    let expires = capability.expiration.duration_since(SystemTime::now()).unwrap_or_default();
+    
+    format!(
+        "{}{}?{}={}&X-Amz-Expires={}",
+        endpoint.trim_end_matches('/'),
+        capability.path.display(),
+        TOKEN_PARAM,
+        token,
+        expires.as_secs(),
+    )
+}
+
+type Gateway = State<Arc<S3Gateway>>;
+type Params = Query<HashMap<String, String>>;
+
+async fn list_buckets() -> Response {
+    let created = timestamp(SystemTime::now());
+    let buckets: String = BUCKETS.iter()
+        .map(|bucket| format!("<Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket>", bucket, created))
+        .collect();
+    
+    xml(StatusCode::OK, format!(
+        "<ListAllMyBucketsResult><Owner><ID>gnos</ID></Owner><Buckets>{}</Buckets></ListAllMyBucketsResult>",
+        buckets,
+    ))
+}
+
+async fn head_bucket(UrlPath(bucket): UrlPath<String>) -> Response {
+    if BUCKETS.contains(&bucket.as_str()) {
+        StatusCode::OK.into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+/// ListObjectsV2; only "/" is understood as a delimiter
+async fn list_objects(
+    State(gateway): Gateway,
+    UrlPath(bucket): UrlPath<String>,
+    Query(params): Params,
+    headers: HeaderMap,
+) -> Response {
+    if !BUCKETS.contains(&bucket.as_str()) {
+        return error_response(StatusCode::NOT_FOUND, "NoSuchBucket", &bucket);
+    }
+    
+    let client = match gateway.client_for(&headers, &params) {
+        Ok(client) => client,
+        Err(e) => return gnos_error(e, &bucket),
+    };
+    let prefix = params.get("prefix").cloned().unwrap_or_default();
+    let delimited = params.get("delimiter").is_some_and(|delimiter| delimiter == "/");
+    let max_keys = params.get("max-keys").and_then(|keys| keys.parse().ok()).unwrap_or(1000usize);
+    let start_after = params.get("continuation-token").or_else(|| params.get("start-after")).cloned();
+    
+    // Listing starts at the directory holding the prefix
+    let dir = match prefix.rfind('/') {
+        Some(slash) => &prefix[..slash],
+        None => "",
+    };
+    
+    let listing = if delimited {
+        list_dir(&client, &bucket, dir).await
+    } else {
+        walk(&client, &bucket, dir).await
+    };
+    let mut entries = match listing {
+        Ok(entries) => entries,
+        Err(GnosError::PathNotFound(_)) => Vec::new(),
+        Err(e) => return gnos_error(e, &bucket),
+    };
+    
+    entries.retain(|(key, _)| key.starts_with(&prefix));
+    if let Some(start_after) = &start_after {
+        entries.retain(|(key, _)| key > start_after);
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    
+    let truncated = entries.len() > max_keys;
+    entries.truncate(max_keys);
+    
+    let mut body = format!(
+        "<ListBucketResult><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+        escape(&bucket), escape(&prefix), entries.len(), max_keys, truncated,
+    );
+    if delimited {
+        body.push_str("<Delimiter>/</Delimiter>");
+    }
+    if let Some(token) = params.get("continuation-token") {
+        body.push_str(&format!("<ContinuationToken>{}</ContinuationToken>", escape(token)));
+    }
+    if truncated {
+        if let Some((last, _)) = entries.last() {
+            body.push_str(&format!("<NextContinuationToken>{}</NextContinuationToken>", escape(last)));
+        }
+    }
+    for (key, metadata) in &entries {
+        match metadata {
+            Some(metadata) => body.push_str(&format!(
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                escape(key), timestamp(metadata.last_modified), metadata.size,
+            )),
+            None => body.push_str(&format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", escape(key))),
+        }
+    }
+    body.push_str("</ListBucketResult>");
+    
+    xml(StatusCode::OK, body)
+}
+
+async fn get_object(
+    State(gateway): Gateway,
+    UrlPath((bucket, key)): UrlPath<(String, String)>,
+    Query(params): Params,
+    headers: HeaderMap,
+) -> Response {
+    let client = match gateway.client_for(&headers, &params) {
+        Ok(client) => client,
+        Err(e) => return gnos_error(e, &key),
+    };
+    let path = object_path(&bucket, &key);
+    
+    let metadata = match client.metadata(&path).await {
+        Ok(metadata) if metadata.is_directory => return error_response(StatusCode::NOT_FOUND, "NoSuchKey", &key),
+        Ok(metadata) => metadata,
+        Err(e) => return gnos_error(e, &key),
+    };
+    match client.read(&path).await {
+        Ok(data) => {
+            let mut response = data.into_response();
+            object_headers(response.headers_mut(), &path, &metadata);
+            response
+        }
+        Err(e) => gnos_error(e, &key),
+    }
+}
+
+async fn head_object(
+    State(gateway): Gateway,
+    UrlPath((bucket, key)): UrlPath<(String, String)>,
+    Query(params): Params,
+    headers: HeaderMap,
+) -> Response {
+    let client = match gateway.client_for(&headers, &params) {
+        Ok(client) => client,
+        Err(e) => return gnos_error(e, &key).status().into_response(),
+    };
+    
+    let path = object_path(&bucket, &key);
+    
+    match client.metadata(&path).await {
+        Ok(metadata) if !metadata.is_directory => {
+            let mut response = StatusCode::OK.into_response();
+            object_headers(response.headers_mut(), &path, &metadata);
+            if let Ok(length) = HeaderValue::from_str(&metadata.size.to_string()) {
+                response.headers_mut().insert(header::CONTENT_LENGTH, length);
+            }
+            response
+        }
+        // HEAD responses carry no body, so only the status is meaningful
+        Ok(_) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => gnos_error(e, &key).status().into_response(),
+    }
+}
+
+async fn put_object(
+    State(gateway): Gateway,
+    UrlPath((bucket, key)): UrlPath<(String, String)>,
+    Query(params): Params,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let client = match gateway.client_for(&headers, &params) {
+        Ok(client) => client,
+        Err(e) => return gnos_error(e, &key),
+    };
+    let path = object_path(&bucket, &key);
+    
+    // The caller's capability is fenced as each chunk arrives, so revoking
+    // it stops an upload still coming in
+    let mut chunks = body.into_data_stream();
+    let mut data = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        if let Err(e) = client.fence(&path, Operation::Write) {
+            return gnos_error(e, &key);
+        }
+        let Ok(chunk) = chunk else {
+            return StatusCode::BAD_REQUEST.into_response();
+        };
+        if data.len() + chunk.len() > MAX_PUT_BYTES {
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+        data.extend_from_slice(&chunk);
+    }
+    
+    match client.write(&path, &data).await {
+        Ok(()) => {
+            debug!("S3 PUT {}/{} ({} bytes)", bucket, key, data.len());
+            StatusCode::OK.into_response()
+        }
+        Err(e) => gnos_error(e, &key),
+    }
+}
+
+async fn delete_object(
+    State(gateway): Gateway,
+    UrlPath((bucket, key)): UrlPath<(String, String)>,
+    Query(params): Params,
+    headers: HeaderMap,
+) -> Response {
+    let client = match gateway.client_for(&headers, &params) {
+        Ok(client) => client,
+        Err(e) => return gnos_error(e, &key),
+    };
+    
+    match client.delete(&object_path(&bucket, &key)).await {
+        // Deleting a missing key succeeds in S3
+        Ok(()) | Err(GnosError::PathNotFound(_)) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => gnos_error(e, &key),
+    }
+}
+
+/// One directory level: files as keys, subdirectories as common prefixes
+async fn list_dir(client: &GnosClient, bucket: &str, dir: &str) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+    let entries = client.list_with_metadata(&object_path(bucket, dir)).await?;
+    
+    Ok(entries.into_iter().map(|(name, metadata)| {
+        let key = join_key(dir, &name);
+        if metadata.is_directory {
+            (format!("{}/", key), None)
+        } else {
+            (key, Some(metadata))
+        }
+    }).collect())
+}
+
+/// Every file below `dir`, up to `MAX_WALK_ENTRIES` entries visited
+async fn walk(client: &GnosClient, bucket: &str, dir: &str) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_string()];
+    let mut visited = 0;
+    
+    while let Some(current) = pending.pop() {
+        let entries = match client.list_with_metadata(&object_path(bucket, &current)).await {
+            Ok(entries) => entries,
+            // Only the starting directory has to exist
+            Err(e) if current == dir => return Err(e),
+            Err(_) => continue,
+        };
+        
+        for (name, metadata) in entries {
+            visited += 1;
+            let key = join_key(&current, &name);
+            if metadata.is_directory {
+                pending.push(key);
+            } else {
+                files.push((key, Some(metadata)));
+            }
+        }
+        if visited >= MAX_WALK_ENTRIES {
+            break;
+        }
+    }
+    
+    Ok(files)
+}
+
+fn object_path(bucket: &str, key: &str) -> PathBuf {
+    Path::new("/").join(bucket).join(key.trim_end_matches('/'))
+}
+
+fn join_key(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn object_headers(headers: &mut HeaderMap, path: &Path, metadata: &ResourceMetadata) {
+    let content_type = metadata.mime_type.as_deref()
+        .or_else(|| mime::from_extension(path))
+        .unwrap_or(mime::OCTET_STREAM);
+    if let Ok(value) = HeaderValue::from_str(content_type) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    let modified = DateTime::<Utc>::from(metadata.last_modified).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    if let Ok(value) = HeaderValue::from_str(&modified) {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+}
+
+fn gnos_error(error: GnosError, resource: &str) -> Response {
+    let (status, code) = error.http_status();
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    debug!("S3 {} on {}: {}", code, resource, error);
+    
+    let mut response = xml(status, format!(
+        "<Error><Code>{}</Code><Message>{}</Message><Resource>{}</Resource></Error>",
+        code, escape(&error.to_string()), escape(resource),
+    ));
+    // S3 codes are coarse; ours tell clients exactly what failed
+    response.headers_mut().insert("x-gnos-error-code", HeaderValue::from(error.code()));
+    // Pass the backend's hint on, rounded up so clients never come back early
+    if let Some(wait) = error.retry_after() {
+        let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    }
+    response
+}
+
+fn error_response(status: StatusCode, code: &str, resource: &str) -> Response {
+    xml(status, format!(
+        "<Error><Code>{}</Code><Resource>{}</Resource></Error>",
+        code, escape(resource),
+    ))
+}
+
+fn xml(status: StatusCode, body: String) -> Response {
+    let body = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", body);
+    (status, [(header::CONTENT_TYPE, "application/xml")], body).into_response()
+}
+
+fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    
+    use crate::config::DriverConfig;
+    use crate::drivers::{DriverRegistry, GnosDriver};
+    use crate::security::{CapabilityManager, SecurityConfig};
+    
+    const ROOT: &str = "/net/mem";
+    
+    /// Objects kept in a map, as the gateway's backend
+    #[derive(Default)]
+    struct MemoryDriver {
+        objects: Mutex<HashMap<PathBuf, Bytes>>,
+    }
+    
+    #[async_trait]
+    impl GnosDriver for MemoryDriver {
+        async fn read(&self, path: &Path) -> Result<Bytes> {
+            self.objects.lock().unwrap().get(path).cloned()
+                .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))
+        }
+        
+        async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+            self.objects.lock().unwrap().insert(path.to_path_buf(), Bytes::copy_from_slice(data));
+            Ok(())
+        }
+        
+        async fn delete(&self, path: &Path) -> Result<()> {
+            self.objects.lock().unwrap().remove(path)
+                .map(|_| ())
+                .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))
+        }
+        
+        async fn list(&self, _path: &Path) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+        
+        async fn exists(&self, path: &Path) -> Result<bool> {
+            Ok(self.objects.lock().unwrap().contains_key(path))
+        }
+        
+        async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+            let size = self.read(path).await?.len() as u64;
+            Ok(ResourceMetadata { size, ..ResourceMetadata::default() })
+        }
+        
+        fn name(&self) -> &'static str {
+            "Memory Driver"
+        }
+        
+        fn supports(&self, path: &Path) -> bool {
+            path.starts_with(ROOT)
+        }
+        
+        fn prefixes(&self) -> Vec<PathBuf> {
+            vec![PathBuf::from(ROOT)]
+        }
+    }
+    
+    struct Served {
+        endpoint: String,
+        capabilities: Arc<CapabilityManager>,
+        driver: Arc<MemoryDriver>,
+        _key_dir: tempfile::TempDir,
+    }
+    
+    impl Served {
+        /// A token for `path` with rwx `permissions`
+        fn token(&self, path: &str, permissions: u8) -> String {
+            self.capabilities.issue(&Capability {
+                path: PathBuf::from(path),
+                permissions,
+                expiration: SystemTime::now() + Duration::from_secs(3600),
+                owner: "gateway-test".to_string(),
+                break_glass: None,
+                issued: Some(SystemTime::now()),
+                not_before: None,
+                max_idle_seconds: None,
+                deny: Vec::new(),
+            }).unwrap()
+        }
+    }
+    
+    async fn serve() -> Served {
+        let mut drivers = DriverConfig::default();
+        drivers.ai.enabled = false;
+        drivers.cloud.enabled = false;
+        drivers.http.enabled = false;
+        drivers.sensors.enabled = false;
+        let driver = Arc::new(MemoryDriver::default());
+        let registry = DriverRegistry::new(drivers).await.unwrap().with_driver("memory", driver.clone());
+        let key_dir = tempfile::tempdir().unwrap();
+        let capabilities = Arc::new(CapabilityManager::new(SecurityConfig {
+            signing_key_file: key_dir.path().join("signing.key"),
+            ..SecurityConfig::default()
+        }));
+        
+        let gateway = S3Gateway::new(GnosClient::with_components(Arc::new(registry), capabilities.clone()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, gateway.router()).await });
+        Served { endpoint, capabilities, driver, _key_dir: key_dir }
+    }
+    
+    #[tokio::test]
+    async fn requests_without_a_token_are_refused() {
+        let served = serve().await;
+        served.driver.write(Path::new("/net/mem/a"), b"secret").await.unwrap();
+        let http = reqwest::Client::new();
+        
+        let response = http.get(format!("{}/net/mem/a", served.endpoint)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(response.text().await.unwrap().contains("<Code>AccessDenied</Code>"));
+        
+        let response = http.put(format!("{}/net/mem/b", served.endpoint)).body("x").send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!served.driver.objects.lock().unwrap().contains_key(Path::new("/net/mem/b")));
+    }
+    
+    #[tokio::test]
+    async fn tokens_are_held_to_their_path_and_permissions() {
+        let served = serve().await;
+        served.driver.write(Path::new("/net/mem/public/a"), b"hello").await.unwrap();
+        served.driver.write(Path::new("/net/mem/private/a"), b"secret").await.unwrap();
+        let read_only = served.token("/net/mem/public", 0b100);
+        let http = reqwest::Client::new();
+        
+        let outside = http.get(format!("{}/net/mem/private/a", served.endpoint))
+            .header(TOKEN_HEADER, &read_only)
+            .send().await.unwrap();
+        assert_eq!(outside.status(), StatusCode::FORBIDDEN);
+        
+        let write = http.put(format!("{}/net/mem/public/b", served.endpoint))
+            .header(TOKEN_HEADER, &read_only)
+            .body("x")
+            .send().await.unwrap();
+        assert_eq!(write.status(), StatusCode::FORBIDDEN);
+        assert!(!served.driver.objects.lock().unwrap().contains_key(Path::new("/net/mem/public/b")));
+        
+        let forged = format!("{}x", read_only);
+        let response = http.get(format!("{}/net/mem/public/a", served.endpoint))
+            .header(TOKEN_HEADER, forged)
+            .send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+    
+    #[tokio::test]
+    async fn granted_requests_reach_the_driver() {
+        let served = serve().await;
+        let read_write = served.token("/net/mem/shared", 0b110);
+        let http = reqwest::Client::new();
+        
+        let put = http.put(format!("{}/net/mem/shared/notes.txt", served.endpoint))
+            .header(TOKEN_HEADER, &read_write)
+            .body("written through S3")
+            .send().await.unwrap();
+        assert_eq!(put.status(), StatusCode::OK);
+        
+        let get = http.get(format!("{}/net/mem/shared/notes.txt", served.endpoint))
+            .header(TOKEN_HEADER, &read_write)
+            .send().await.unwrap();
+        assert_eq!(get.status(), StatusCode::OK);
+        assert_eq!(get.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(get.text().await.unwrap(), "written through S3");
+        
+        // A presigned URL carries the token as a query parameter
+        let capability = served.capabilities.capability(&read_write).unwrap();
+        let url = presign_url(&served.endpoint, &Capability { path: PathBuf::from("/net/mem/shared/notes.txt"), ..capability }, &read_write);
+        let presigned = http.get(url).send().await.unwrap();
+        assert_eq!(presigned.status(), StatusCode::OK);
+        assert_eq!(presigned.text().await.unwrap(), "written through S3");
+        
+        let delete = http.delete(format!("{}/net/mem/shared/notes.txt", served.endpoint))
+            .header(TOKEN_HEADER, &read_write)
+            .send().await.unwrap();
+        assert_eq!(delete.status(), StatusCode::NO_CONTENT);
+        assert!(served.driver.objects.lock().unwrap().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn revoking_a_token_stops_its_upload() {
+        let served = serve().await;
+        let token = served.token("/net/mem/shared", 0b110);
+        let (chunks, body) = futures::channel::mpsc::unbounded::<std::io::Result<&'static [u8]>>();
+        chunks.unbounded_send(Ok(b"before")).unwrap();
+        let put = tokio::spawn(reqwest::Client::new().put(format!("{}/net/mem/shared/upload", served.endpoint))
+            .header(TOKEN_HEADER, &token)
+            .body(reqwest::Body::wrap_stream(body))
+            .send());
+        
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        served.capabilities.revoke(&token).unwrap();
+        chunks.unbounded_send(Ok(b"after")).unwrap();
+        drop(chunks);
+        
+        let put = put.await.unwrap().unwrap();
+        assert_eq!(put.status(), StatusCode::FORBIDDEN);
+        assert!(!served.driver.objects.lock().unwrap().contains_key(Path::new("/net/mem/shared/upload")));
+        
+        let get = reqwest::Client::new().get(format!("{}/net/mem/shared", served.endpoint))
+            .header(TOKEN_HEADER, &token)
+            .send().await.unwrap();
+        assert_eq!(get.status(), StatusCode::FORBIDDEN);
+    }
+}
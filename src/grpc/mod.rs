@@ -0,0 +1,335 @@
+//! gRPC remote access
+//!
+//! `gnos serve` exposes a `GnosClient` over the `gnos.v1.Namespace` service
+//! in `proto/gnos.proto`, so thin clients on other machines can use the
+//! namespace without a network filesystem. Each call is checked against the
+//! capability token in its `authorization: Bearer` metadata; a call
+//! without one is refused as unauthenticated.
+
+// tonic's streams carry `Status` by value, however large clippy finds it
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::time::{Duration, UNIX_EPOCH};
+
+use futures::stream::{self, Stream, StreamExt};
+use tonic::metadata::MetadataValue;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::info;
+
+use crate::client::{GnosClient, WatchEvent};
+use crate::drivers::ResourceMetadata;
+use crate::security::Operation;
+use crate::{GnosError, Result};
+
+pub mod pb {
+    tonic::include_proto!("gnos.v1");
+}
+
+use pb::namespace_server::{Namespace, NamespaceServer};
+
+/// Largest chunk sent in one Read message
+const CHUNK_SIZE: usize = 64 * 1024;
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = std::result::Result<T, Status>> + Send>>;
+
+pub struct GrpcServer {
+    client: GnosClient,
+}
+
+impl GrpcServer {
+    pub fn new(client: GnosClient) -> Self {
+        Self { client }
+    }
+    
+    /// Serve until the process is stopped
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        info!("📡 Serving gRPC on {}", addr);
+        
+        Server::builder()
+            .add_service(NamespaceServer::new(self))
+            .serve(addr)
+            .await
+            .map_err(|e| GnosError::Driver(format!("gRPC server failed: {}", e)))
+    }
+    
+    /// The client to act with for this call, carrying the caller's
+    /// capability; a call without one is unauthenticated, never served with
+    /// the server's own
+    fn client_for<T>(&self, request: &Request<T>) -> Option<GnosClient> {
+        let token = request.metadata().get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        
+        token.map(|token| self.client.clone().with_token(token))
+    }
+}
+
+#[tonic::async_trait]
+impl Namespace for GrpcServer {
+    type ReadStream = ResponseStream<pb::Chunk>;
+    type WatchStream = ResponseStream<pb::WatchEvent>;
+    
+    async fn read(&self, request: Request<pb::ReadRequest>) -> std::result::Result<Response<Self::ReadStream>, Status> {
+        let client = self.client_for(&request).ok_or_else(unauthenticated)?;
+        let data = client.read(Path::new(&request.get_ref().path)).await.map_err(status)?;
+        
+        let chunks = (0..data.len()).step_by(CHUNK_SIZE)
+            .map(move |start| Ok(pb::Chunk {
+                data: data.slice(start..std::cmp::min(start + CHUNK_SIZE, data.len())).to_vec(),
+            }))
+            .collect::<Vec<std::result::Result<_, Status>>>();
+        
+        Ok(Response::new(Box::pin(stream::iter(chunks))))
+    }
+    
+    async fn write(&self, request: Request<Streaming<pb::WriteRequest>>) -> std::result::Result<Response<pb::WriteResponse>, Status> {
+        let client = self.client_for(&request).ok_or_else(unauthenticated)?;
+        let mut messages = request.into_inner();
+        
+        let first = messages.message().await?
+            .ok_or_else(|| Status::invalid_argument("empty write stream"))?;
+        let path = first.path;
+        client.fence(Path::new(&path), Operation::Write).map_err(status)?;
+        let mut data = first.data;
+        while let Some(message) = messages.message().await? {
+            client.fence(Path::new(&path), Operation::Write).map_err(status)?;
+            data.extend_from_slice(&message.data);
+        }
+        
+        client.write(Path::new(&path), &data).await.map_err(status)?;
+        Ok(Response::new(pb::WriteResponse { bytes_written: data.len() as u64 }))
+    }
+    
+    async fn list(&self, request: Request<pb::ListRequest>) -> std::result::Result<Response<pb::ListResponse>, Status> {
+        let client = self.client_for(&request).ok_or_else(unauthenticated)?;
+        let entries = client.list(Path::new(&request.get_ref().path)).await.map_err(status)?;
+        Ok(Response::new(pb::ListResponse { entries }))
+    }
+    
+    async fn metadata(&self, request: Request<pb::MetadataRequest>) -> std::result::Result<Response<pb::ResourceMetadata>, Status> {
+        let client = self.client_for(&request).ok_or_else(unauthenticated)?;
+        let metadata = client.metadata(Path::new(&request.get_ref().path)).await.map_err(status)?;
+        Ok(Response::new(to_proto(metadata)))
+    }
+    
+    async fn watch(&self, request: Request<pb::WatchRequest>) -> std::result::Result<Response<Self::WatchStream>, Status> {
+        let client = self.client_for(&request).ok_or_else(unauthenticated)?;
+        let request = request.into_inner();
+        let interval = Duration::from_millis(match request.interval_ms {
+            0 => 1000,
+            ms => ms as u64,
+        });
+        
+        let events = client.watch(Path::new(&request.path), interval).map(|event| -> std::result::Result<pb::WatchEvent, Status> {
+            let event = match event.map_err(status)? {
+                WatchEvent::Changed(metadata) => pb::watch_event::Event::Changed(to_proto(metadata)),
+                WatchEvent::Removed => pb::watch_event::Event::Removed(true),
+            };
+            Ok(pb::WatchEvent { event: Some(event) })
+        });
+        
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+fn to_proto(metadata: ResourceMetadata) -> pb::ResourceMetadata {
+    let mtime = metadata.last_modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    pb::ResourceMetadata {
+        size: metadata.size,
+        is_directory: metadata.is_directory,
+        mtime_seconds: mtime.as_secs() as i64,
+        mtime_nanos: mtime.subsec_nanos(),
+        mime_type: metadata.mime_type.unwrap_or_default(),
+        custom_fields: metadata.custom_fields,
+    }
+}
+
+fn unauthenticated() -> Status {
+    Status::unauthenticated("a capability token is required as `authorization: Bearer` metadata")
+}
+
+fn status(error: GnosError) -> Status {
+    let message = error.to_string();
+    let mut status = match error.root() {
+        GnosError::PermissionDenied(_) | GnosError::CapabilityExpired => Status::permission_denied(message),
+        GnosError::PathNotFound(_) => Status::not_found(message),
+        GnosError::InvalidPath(_) | GnosError::Config(_) => Status::invalid_argument(message),
+        GnosError::ResourceBusy(_) => Status::aborted(message),
+        GnosError::Unreachable(_) | GnosError::RateLimited { .. } => Status::unavailable(message),
+        GnosError::QuotaExceeded(_) => Status::resource_exhausted(message),
+        GnosError::Immutable(_) => Status::failed_precondition(message),
+        GnosError::ChecksumMismatch(_) => Status::data_loss(message),
+        GnosError::Driver(_) | GnosError::Io(_) | GnosError::Context { .. } => Status::internal(message),
+    };
+    status.metadata_mut().insert("gnos-error-code", MetadataValue::from(error.code()));
+    status.metadata_mut().insert("gnos-error-category", MetadataValue::from_static(error.category().as_str()));
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+    
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use tonic::Code;
+    
+    use crate::config::DriverConfig;
+    use crate::drivers::{DriverRegistry, GnosDriver};
+    use crate::security::{Capability, CapabilityManager, SecurityConfig};
+    
+    const ROOT: &str = "/net/mem";
+    
+    #[derive(Default)]
+    struct MemoryDriver {
+        objects: Mutex<HashMap<PathBuf, Bytes>>,
+    }
+    
+    #[async_trait]
+    impl GnosDriver for MemoryDriver {
+        async fn read(&self, path: &Path) -> Result<Bytes> {
+            self.objects.lock().unwrap().get(path).cloned()
+                .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))
+        }
+        
+        async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+            self.objects.lock().unwrap().insert(path.to_path_buf(), Bytes::copy_from_slice(data));
+            Ok(())
+        }
+        
+        async fn list(&self, _path: &Path) -> Result<Vec<String>> {
+            Ok(self.objects.lock().unwrap().keys()
+                .filter_map(|path| path.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect())
+        }
+        
+        async fn exists(&self, path: &Path) -> Result<bool> {
+            Ok(self.objects.lock().unwrap().contains_key(path))
+        }
+        
+        async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+            let size = self.read(path).await?.len() as u64;
+            Ok(ResourceMetadata { size, ..ResourceMetadata::default() })
+        }
+        
+        fn name(&self) -> &'static str {
+            "Memory Driver"
+        }
+        
+        fn supports(&self, path: &Path) -> bool {
+            path.starts_with(ROOT)
+        }
+        
+        fn prefixes(&self) -> Vec<PathBuf> {
+            vec![PathBuf::from(ROOT)]
+        }
+    }
+    
+    struct Fixture {
+        server: GrpcServer,
+        capabilities: Arc<CapabilityManager>,
+        driver: Arc<MemoryDriver>,
+        _key_dir: tempfile::TempDir,
+    }
+    
+    impl Fixture {
+        async fn new() -> Self {
+            let mut drivers = DriverConfig::default();
+            drivers.ai.enabled = false;
+            drivers.cloud.enabled = false;
+            drivers.http.enabled = false;
+            drivers.sensors.enabled = false;
+            let driver = Arc::new(MemoryDriver::default());
+            let registry = DriverRegistry::new(drivers).await.unwrap().with_driver("memory", driver.clone());
+            let key_dir = tempfile::tempdir().unwrap();
+            let capabilities = Arc::new(CapabilityManager::new(SecurityConfig {
+                signing_key_file: key_dir.path().join("signing.key"),
+                ..SecurityConfig::default()
+            }));
+            let server = GrpcServer::new(GnosClient::with_components(Arc::new(registry), capabilities.clone()));
+            Self { server, capabilities, driver, _key_dir: key_dir }
+        }
+        
+        /// `message` presenting a read-only token for `path`
+        fn with_token<T>(&self, path: &str, message: T) -> Request<T> {
+            let token = self.capabilities.issue(&Capability {
+                path: PathBuf::from(path),
+                permissions: 0b100,
+                expiration: SystemTime::now() + Duration::from_secs(3600),
+                owner: "grpc-test".to_string(),
+                break_glass: None,
+                issued: Some(SystemTime::now()),
+                not_before: None,
+                max_idle_seconds: None,
+                deny: Vec::new(),
+            }).unwrap();
+            let mut request = Request::new(message);
+            request.metadata_mut().insert("authorization", format!("Bearer {}", token).parse().unwrap());
+            request
+        }
+    }
+    
+    fn read_request(path: &str) -> pb::ReadRequest {
+        pb::ReadRequest { path: path.to_string() }
+    }
+    
+    #[tokio::test]
+    async fn calls_without_a_bearer_token_are_unauthenticated() {
+        let fixture = Fixture::new().await;
+        fixture.driver.write(Path::new("/net/mem/a"), b"secret").await.unwrap();
+        let server = &fixture.server;
+        
+        let code = |result: std::result::Result<_, Status>| result.err().map(|status| status.code());
+        assert_eq!(code(server.read(Request::new(read_request("/net/mem/a"))).await.map(drop)), Some(Code::Unauthenticated));
+        assert_eq!(code(server.list(Request::new(pb::ListRequest { path: ROOT.to_string() })).await.map(drop)), Some(Code::Unauthenticated));
+        assert_eq!(code(server.metadata(Request::new(pb::MetadataRequest { path: "/net/mem/a".to_string() })).await.map(drop)), Some(Code::Unauthenticated));
+        let watch = pb::WatchRequest { path: "/net/mem/a".to_string(), interval_ms: 10 };
+        assert_eq!(code(server.watch(Request::new(watch)).await.map(drop)), Some(Code::Unauthenticated));
+        
+        // Only the Bearer scheme carries a token
+        let mut basic = Request::new(read_request("/net/mem/a"));
+        basic.metadata_mut().insert("authorization", "Basic Z25vczpnbm9z".parse().unwrap());
+        assert_eq!(code(server.read(basic).await.map(drop)), Some(Code::Unauthenticated));
+        
+        // A token for somewhere else is authenticated but refused
+        let elsewhere = fixture.with_token("/net/other", read_request("/net/mem/a"));
+        assert_eq!(code(server.read(elsewhere).await.map(drop)), Some(Code::PermissionDenied));
+    }
+    
+    #[tokio::test]
+    async fn reads_stream_in_bounded_chunks() {
+        let fixture = Fixture::new().await;
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        fixture.driver.write(Path::new("/net/mem/big"), &data).await.unwrap();
+        
+        let response = fixture.server.read(fixture.with_token(ROOT, read_request("/net/mem/big"))).await.unwrap();
+        let chunks: Vec<pb::Chunk> = response.into_inner()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let sizes: Vec<usize> = chunks.iter().map(|chunk| chunk.data.len()).collect();
+        assert_eq!(sizes, [CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE, 100]);
+        assert_eq!(chunks.into_iter().flat_map(|chunk| chunk.data).collect::<Vec<u8>>(), data);
+        
+        let missing = fixture.server.read(fixture.with_token(ROOT, read_request("/net/mem/missing"))).await;
+        assert_eq!(missing.err().map(|status| status.code()), Some(Code::NotFound));
+    }
+    
+    #[tokio::test]
+    async fn empty_objects_stream_no_chunks() {
+        let fixture = Fixture::new().await;
+        fixture.driver.write(Path::new("/net/mem/empty"), b"").await.unwrap();
+        
+        let response = fixture.server.read(fixture.with_token(ROOT, read_request("/net/mem/empty"))).await.unwrap();
+        assert_eq!(response.into_inner().count().await, 0);
+    }
+}
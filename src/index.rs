@@ -0,0 +1,462 @@
+//! Background content index
+//!
+//! The indexer crawls configured prefixes into a local inverted index of
+//! file names, metadata and content words, so searches inside them are
+//! answered from memory instead of listing and reading the backend per
+//! query. Each refresh re-lists the prefixes and only re-reads files whose
+//! size or modification time changed; writes made through the mount are
+//! reindexed as they happen.
+//!
+//! The index is persisted to `index_dir`, so `gnos find` and a restarted
+//! mount can use it before the first refresh has finished.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::config::IndexConfig;
+use crate::drivers::{DriverRegistry, ResourceMetadata};
+use crate::qos::{self, QosClass};
+use crate::search::SearchQuery;
+use crate::{GnosError, Result};
+
+const INDEX_FILE: &str = "index.json";
+
+/// Only this much of a file is inspected when deciding whether it is text
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// Words outside this length range are not indexed
+const MIN_WORD_LEN: usize = 2;
+const MAX_WORD_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Document {
+    size: u64,
+    modified: SystemTime,
+    /// Distinct lowercased words; empty for binary or oversized files
+    words: Vec<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct IndexState {
+    documents: HashMap<PathBuf, Document>,
+    /// Prefixes crawled to the end at least once; only these answer searches
+    complete: HashSet<PathBuf>,
+    /// Rebuilt from `documents` on load
+    #[serde(skip)]
+    postings: HashMap<String, HashSet<PathBuf>>,
+}
+
+impl IndexState {
+    fn insert(&mut self, path: PathBuf, document: Document) {
+        self.remove(&path);
+        for word in &document.words {
+            self.postings.entry(word.clone()).or_default().insert(path.clone());
+        }
+        self.documents.insert(path, document);
+    }
+    
+    fn remove(&mut self, path: &Path) {
+        let Some(old) = self.documents.remove(path) else {
+            return;
+        };
+        for word in &old.words {
+            if let Some(paths) = self.postings.get_mut(word) {
+                paths.remove(path);
+                if paths.is_empty() {
+                    self.postings.remove(word);
+                }
+            }
+        }
+    }
+    
+    fn rebuild_postings(&mut self) {
+        self.postings.clear();
+        for (path, document) in &self.documents {
+            for word in &document.words {
+                self.postings.entry(word.clone()).or_default().insert(path.clone());
+            }
+        }
+    }
+    
+    fn is_current(&self, path: &Path, metadata: &ResourceMetadata) -> bool {
+        self.documents.get(path)
+            .is_some_and(|doc| doc.size == metadata.size && doc.modified == metadata.last_modified)
+    }
+}
+
+/// What the last crawl of a prefix did
+#[derive(Debug, Default, Clone)]
+pub struct CrawlStats {
+    pub directories: usize,
+    pub indexed: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub errors: usize,
+    pub elapsed: Duration,
+}
+
+pub struct ContentIndex {
+    driver_registry: Arc<DriverRegistry>,
+    prefixes: Vec<PathBuf>,
+    index_file: PathBuf,
+    refresh_interval: Duration,
+    max_depth: usize,
+    max_file_bytes: u64,
+    concurrency: usize,
+    state: RwLock<IndexState>,
+    last_crawl: Mutex<HashMap<PathBuf, CrawlStats>>,
+    changes: mpsc::UnboundedSender<PathBuf>,
+    /// Taken by `start`
+    change_rx: Mutex<Option<mpsc::UnboundedReceiver<PathBuf>>>,
+}
+
+impl ContentIndex {
+    /// Load the persisted index, if any; nothing is crawled until `start`
+    pub async fn open(config: &IndexConfig, driver_registry: Arc<DriverRegistry>) -> Result<Arc<Self>> {
+        tokio::fs::create_dir_all(&config.index_dir).await?;
+        let index_file = config.index_dir.join(INDEX_FILE);
+        
+        let mut state = match tokio::fs::read(&index_file).await {
+            Ok(raw) => serde_json::from_slice::<IndexState>(&raw).unwrap_or_else(|e| {
+                warn!("🗂️  Discarding unreadable index {}: {}", index_file.display(), e);
+                IndexState::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => IndexState::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let prefixes: Vec<PathBuf> = config.prefixes.iter().map(PathBuf::from).collect();
+        
+        // Entries outside the configured prefixes are left over from an older config
+        state.documents.retain(|path, _| prefixes.iter().any(|prefix| path.starts_with(prefix)));
+        state.complete.retain(|prefix| prefixes.contains(prefix));
+        state.rebuild_postings();
+        info!("🗂️  Index loaded with {} documents", state.documents.len());
+        
+        let (changes, change_rx) = mpsc::unbounded_channel();
+        Ok(Arc::new(Self {
+            driver_registry,
+            prefixes,
+            index_file,
+            refresh_interval: Duration::from_secs(config.refresh_interval_seconds.max(1)),
+            max_depth: config.max_depth,
+            max_file_bytes: config.max_file_mb * 1024 * 1024,
+            concurrency: config.concurrency.max(1),
+            state: RwLock::new(state),
+            last_crawl: Mutex::new(HashMap::new()),
+            changes,
+            change_rx: Mutex::new(Some(change_rx)),
+        }))
+    }
+    
+    /// Crawl every prefix now and then every refresh interval, reindexing
+    /// changed paths in between
+    pub fn start(self: &Arc<Self>) {
+        let Some(mut change_rx) = self.change_rx.lock().unwrap().take() else {
+            return;
+        };
+        let index = self.clone();
+        
+        tokio::spawn(qos::scope(QosClass::Background, async move {
+            let mut ticker = tokio::time::interval(index.refresh_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        for prefix in &index.prefixes {
+                            index.crawl(prefix).await;
+                        }
+                        index.persist().await;
+                    }
+                    Some(path) = change_rx.recv() => {
+                        index.reindex(&path).await;
+                    }
+                }
+            }
+        }));
+    }
+    
+    /// Whether searches under `scope` can be answered from the index alone
+    pub fn covers(&self, scope: &Path) -> bool {
+        let state = self.state.read().unwrap();
+        self.prefixes.iter().any(|prefix| scope.starts_with(prefix) && state.complete.contains(prefix))
+    }
+    
+    /// Queue a path for reindexing after it was written or removed
+    pub fn notify(&self, path: &Path) {
+        if self.prefixes.iter().any(|prefix| path.starts_with(prefix)) {
+            let _ = self.changes.send(path.to_path_buf());
+        }
+    }
+    
+    /// Indexed files under the query's scope that pass its word, name and
+    /// metadata filters; content patterns are left to the caller
+    pub fn search(&self, query: &SearchQuery) -> Vec<(PathBuf, ResourceMetadata)> {
+        let state = self.state.read().unwrap();
+        
+        // Some word appearing nowhere rules everything out
+        let Some(mut postings) = query.words.iter()
+            .map(|word| state.postings.get(word))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Vec::new();
+        };
+        
+        // Start from the rarest word's postings when there are words to match
+        postings.sort_by_key(|paths| paths.len());
+        let candidates: Vec<&PathBuf> = match postings.split_first() {
+            Some((rarest, rest)) => rarest.iter()
+                .filter(|path| rest.iter().all(|paths| paths.contains(*path)))
+                .collect(),
+            None => state.documents.keys().collect(),
+        };
+        
+        let mut matches: Vec<(PathBuf, ResourceMetadata)> = candidates
+            .into_iter()
+            .filter(|path| path.starts_with(&query.scope) && query.matches_name(path))
+            .filter_map(|path| {
+                let document = state.documents.get(path)?;
+                let metadata = ResourceMetadata {
+                    size: document.size,
+                    last_modified: document.modified,
+                    ..ResourceMetadata::default()
+                };
+                query.matches_metadata(&metadata).then(|| (path.clone(), metadata))
+            })
+            .collect();
+        matches.sort_by(|a, b| a.0.cmp(&b.0));
+        matches
+    }
+    
+    pub fn status_report(&self) -> String {
+        let (documents, words) = {
+            let state = self.state.read().unwrap();
+            (state.documents.len(), state.postings.len())
+        };
+        let complete = self.state.read().unwrap().complete.clone();
+        let mut report = format!("documents: {}\nwords: {}\n", documents, words);
+        
+        let last_crawl = self.last_crawl.lock().unwrap();
+        for prefix in &self.prefixes {
+            let complete = complete.contains(prefix);
+            match last_crawl.get(prefix) {
+                Some(stats) => report.push_str(&format!(
+                    "{}\t{}\t{} dirs, {} indexed, {} unchanged, {} removed, {} errors in {:.1}s\n",
+                    prefix.display(),
+                    if complete { "ready" } else { "partial" },
+                    stats.directories, stats.indexed, stats.unchanged, stats.removed, stats.errors,
+                    stats.elapsed.as_secs_f64(),
+                )),
+                None => report.push_str(&format!(
+                    "{}\t{}\tnot crawled yet\n",
+                    prefix.display(),
+                    if complete { "ready" } else { "pending" },
+                )),
+            }
+        }
+        report
+    }
+    
+    /// Walk `prefix`, reindexing new and changed files and dropping vanished ones
+    async fn crawl(&self, prefix: &Path) -> CrawlStats {
+        let started = Instant::now();
+        let mut stats = CrawlStats::default();
+        let mut level = vec![prefix.to_path_buf()];
+        let mut listed: HashSet<PathBuf> = HashSet::new();
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        let mut changed = Vec::new();
+        
+        for depth in 0..=self.max_depth {
+            if level.is_empty() {
+                break;
+            }
+            
+            let listings: Vec<_> = stream::iter(std::mem::take(&mut level))
+                .map(|dir| async move {
+                    let result = self.list_dir(&dir).await;
+                    (dir, result)
+                })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+            
+            for (dir, result) in listings {
+                let entries = match result {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        stats.errors += 1;
+                        warn!("❌ Indexing {} failed: {}", dir.display(), e);
+                        continue;
+                    }
+                };
+                stats.directories += 1;
+                listed.insert(dir);
+                
+                let state = self.state.read().unwrap();
+                for (path, metadata) in entries {
+                    if metadata.is_directory {
+                        if depth < self.max_depth {
+                            level.push(path);
+                        }
+                        continue;
+                    }
+                    seen.insert(path.clone());
+                    if state.is_current(&path, &metadata) {
+                        stats.unchanged += 1;
+                    } else {
+                        changed.push((path, metadata));
+                    }
+                }
+            }
+        }
+        
+        let documents: Vec<_> = stream::iter(changed)
+            .map(|(path, metadata)| async move {
+                let document = self.document_for(&path, &metadata).await;
+                (path, document)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        
+        {
+            let mut state = self.state.write().unwrap();
+            for (path, document) in documents {
+                match document {
+                    Ok(document) => {
+                        state.insert(path, document);
+                        stats.indexed += 1;
+                    }
+                    Err(e) => {
+                        stats.errors += 1;
+                        debug!("Index skipped {}: {}", path.display(), e);
+                    }
+                }
+            }
+            
+            // Only entries of directories that were listed can be known to be gone
+            let vanished: Vec<PathBuf> = state.documents.keys()
+                .filter(|path| path.starts_with(prefix) && !seen.contains(*path))
+                .filter(|path| path.parent().is_some_and(|parent| listed.contains(parent)))
+                .cloned()
+                .collect();
+            stats.removed = vanished.len();
+            for path in vanished {
+                state.remove(&path);
+            }
+            
+            if listed.contains(prefix) {
+                state.complete.insert(prefix.to_path_buf());
+            }
+        }
+        
+        stats.elapsed = started.elapsed();
+        info!("🗂️  Indexed {}: {} changed, {} unchanged, {} removed in {:.1}s",
+              prefix.display(), stats.indexed, stats.unchanged, stats.removed, stats.elapsed.as_secs_f64());
+        self.last_crawl.lock().unwrap().insert(prefix.to_path_buf(), stats.clone());
+        stats
+    }
+    
+    /// Bring one path up to date after a change notification
+    async fn reindex(&self, path: &Path) {
+        let Some(driver) = self.driver_registry.get_driver(path) else {
+            return;
+        };
+        
+        let document = match driver.metadata(path).await {
+            Ok(metadata) if metadata.is_directory => return,
+            Ok(metadata) => self.document_for(path, &metadata).await,
+            Err(GnosError::PathNotFound(_)) => {
+                self.state.write().unwrap().remove(path);
+                return;
+            }
+            Err(e) => Err(e),
+        };
+        
+        match document {
+            Ok(document) => self.state.write().unwrap().insert(path.to_path_buf(), document),
+            Err(e) => debug!("Index skipped {}: {}", path.display(), e),
+        }
+    }
+    
+    async fn list_dir(&self, dir: &Path) -> Result<Vec<(PathBuf, ResourceMetadata)>> {
+        let driver = self.driver_registry.get_driver(dir)
+            .ok_or_else(|| GnosError::PathNotFound(dir.display().to_string()))?;
+        
+        let mut entries = Vec::new();
+        for (name, metadata) in driver.list_with_metadata(dir).await? {
+            let path = dir.join(&name);
+            let metadata = match metadata {
+                Some(metadata) => metadata,
+                None => match driver.metadata(&path).await {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                },
+            };
+            entries.push((path, metadata));
+        }
+        Ok(entries)
+    }
+    
+    async fn document_for(&self, path: &Path, metadata: &ResourceMetadata) -> Result<Document> {
+        let mut document = Document {
+            size: metadata.size,
+            modified: metadata.last_modified,
+            words: Vec::new(),
+        };
+        let driver = self.driver_registry.get_driver(path)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        // Secrets and the like are listed but never read into the index
+        if metadata.size > self.max_file_bytes || driver.private(path) {
+            return Ok(document);
+        }
+        
+        let data = driver.read(path).await?;
+        document.words = words(&data);
+        Ok(document)
+    }
+    
+    /// Write the index out atomically so a crash never leaves half of one
+    pub async fn persist(&self) {
+        let raw = match serde_json::to_vec(&*self.state.read().unwrap()) {
+            Ok(raw) => raw,
+            Err(e) => {
+                warn!("❌ Failed to encode index: {}", e);
+                return;
+            }
+        };
+        
+        let staging = self.index_file.with_extension("json.tmp");
+        let result = async {
+            tokio::fs::write(&staging, raw).await?;
+            tokio::fs::rename(&staging, &self.index_file).await
+        }.await;
+        if let Err(e) = result {
+            warn!("❌ Failed to save index to {}: {}", self.index_file.display(), e);
+        }
+    }
+}
+
+/// Distinct lowercased words of a text file; binary data has none
+pub(crate) fn words(data: &[u8]) -> Vec<String> {
+    if data[..data.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return Vec::new();
+    }
+    
+    let text = String::from_utf8_lossy(data);
+    let mut words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| (MIN_WORD_LEN..=MAX_WORD_LEN).contains(&word.chars().count()))
+        .map(str::to_lowercase)
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+    words
+}
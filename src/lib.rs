@@ -3,43 +3,48 @@
 //! Revolutionary POSIX filesystem interface for all computing resources.
 //! Transforms cloud services, AI models, and APIs into simple file operations.
 
+pub mod bandwidth;
+pub mod cache;
+pub mod checksum;
+pub mod client;
 pub mod config;
+pub mod copy;
+pub mod costs;
 pub mod drivers;
+pub mod dryrun;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod faults;
+pub mod gateway;
+pub mod grpc;
+pub mod index;
+pub mod lifecycle;
+pub mod mime;
+pub mod ninep;
+pub mod output;
+pub mod pipeline;
+pub mod plugins;
+pub mod pools;
+pub mod qos;
+pub mod recovery;
+pub mod search;
 pub mod security;
+pub mod shutdown;
+pub mod state;
+pub mod telemetry;
+pub mod tenants;
+pub mod triggers;
+pub mod txn;
 pub mod vfs;
 
 // Re-export core types
+pub use client::{GnosClient, WatchEvent};
+pub use error::{ErrorCategory, GnosError, Result, ResultExt};
 pub use drivers::{GnosDriver, DriverRegistry};
-pub use security::{Capability, CapabilityManager, Operation};
+pub use security::{AuditEntry, Capability, CapabilityManager, Operation};
 pub use vfs::{GnosFileSystem, InodeManager};
 
-// Core error types
-pub type Result<T> = std::result::Result<T, GnosError>;
-
-#[derive(Debug, thiserror::Error)]
-pub enum GnosError {
-    #[error("Permission denied: {0}")]
-    PermissionDenied(String),
-    
-    #[error("Path not found: {0}")]
-    PathNotFound(String),
-    
-    #[error("Driver error: {0}")]
-    Driver(String),
-    
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
-    
-    #[error("Capability expired")]
-    CapabilityExpired,
-    
-    #[error("Invalid path format: {0}")]
-    InvalidPath(String),
-    
-    #[error("Resource busy: {0}")]
-    ResourceBusy(String),
-}
-
 // Version information
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const GNOS_MAGIC: u64 = 0x474E4F53; // "GNOS" in hex
\ No newline at end of file
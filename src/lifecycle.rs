@@ -0,0 +1,270 @@
+//! Lifecycle rules for scratch areas
+//!
+//! `[[lifecycle.rules]]` expire whatever matches a glob once it hasn't
+//! changed for `max_age_seconds`:
+//!
+//! ```toml
+//! [[lifecycle.rules]]
+//! pattern = "/cloud/aws/s3/scratch/tmp/**"
+//! max_age_seconds = 86400
+//!
+//! [[lifecycle.rules]]
+//! pattern = "/proc/sessions/*/*"
+//! max_age_seconds = 604800
+//! directories = true
+//! ```
+//!
+//! A background janitor walks each rule's root every `interval_seconds`
+//! and deletes what has expired through a client, so capability checks
+//! apply and deletions are published as events. Objects under a retention
+//! rule are left alone until their protection lapses. With `dry_run` the
+//! janitor only reports what it would delete; either way the last sweep is
+//! listed at `/proc/gnos/lifecycle`, and `gnos sweep --dry-run` runs one
+//! sweep from the command line.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use tracing::{debug, info, warn};
+
+use crate::client::GnosClient;
+use crate::config::{LifecycleConfig, LifecycleRule};
+use crate::drivers::ResourceMetadata;
+use crate::qos::{self, QosClass};
+use crate::search;
+use crate::vfs::path::join_name;
+use crate::vfs::retention::RetentionTable;
+
+/// What became of one expired object
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Deleted,
+    /// Found in a dry run
+    WouldDelete,
+    /// Kept by a retention rule
+    Retained,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Expired {
+    pub path: PathBuf,
+    pub pattern: String,
+    pub age: Duration,
+    pub outcome: Outcome,
+}
+
+/// One pass over every rule
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    pub started: SystemTime,
+    pub elapsed: Duration,
+    pub dry_run: bool,
+    pub expired: Vec<Expired>,
+    /// Directories that couldn't be listed
+    pub errors: usize,
+    /// The per-sweep deletion limit cut the sweep short
+    pub truncated: bool,
+}
+
+impl Sweep {
+    fn removed(&self) -> usize {
+        self.expired.iter()
+            .filter(|expired| matches!(expired.outcome, Outcome::Deleted | Outcome::WouldDelete))
+            .count()
+    }
+    
+    /// Plain-text report, as `/proc/gnos/lifecycle` and `gnos sweep` print it
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "sweep: {}{}\nelapsed: {:.3}s\n{}: {}\nerrors: {}\n",
+            chrono::DateTime::<chrono::Utc>::from(self.started).to_rfc3339(),
+            if self.dry_run { " (dry run)" } else { "" },
+            self.elapsed.as_secs_f64(),
+            if self.dry_run { "would_delete" } else { "deleted" },
+            self.removed(),
+            self.errors,
+        );
+        if self.truncated {
+            report.push_str("truncated: deletion limit reached\n");
+        }
+        for expired in &self.expired {
+            let outcome = match &expired.outcome {
+                Outcome::Deleted => "deleted".to_string(),
+                Outcome::WouldDelete => "would delete".to_string(),
+                Outcome::Retained => "retained".to_string(),
+                Outcome::Failed(error) => format!("failed: {}", error),
+            };
+            report.push_str(&format!("{}\t{}s\t{}\t{}\n",
+                                     outcome, expired.age.as_secs(), expired.pattern, expired.path.display()));
+        }
+        report
+    }
+}
+
+pub struct Janitor {
+    config: LifecycleConfig,
+    client: GnosClient,
+    retention: Option<Arc<RetentionTable>>,
+    last: Mutex<Option<Sweep>>,
+}
+
+impl Janitor {
+    pub fn new(config: &LifecycleConfig, client: GnosClient) -> Self {
+        Self {
+            config: config.clone(),
+            client,
+            retention: None,
+            last: Mutex::new(None),
+        }
+    }
+    
+    /// Leave objects protected by these retention rules in place
+    pub fn with_retention(mut self, retention: Arc<RetentionTable>) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+    
+    /// Sweep every `interval_seconds`, starting now, until the runtime shuts down
+    pub fn spawn(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(qos::scope(QosClass::Background, async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval_seconds.max(60)));
+            loop {
+                interval.tick().await;
+                self.sweep().await;
+            }
+        }))
+    }
+    
+    /// Apply every rule once
+    pub async fn sweep(&self) -> Sweep {
+        let started = Instant::now();
+        let mut sweep = Sweep {
+            started: SystemTime::now(),
+            elapsed: Duration::ZERO,
+            dry_run: self.config.dry_run,
+            expired: Vec::new(),
+            errors: 0,
+            truncated: false,
+        };
+        
+        for rule in &self.config.rules {
+            if sweep.truncated {
+                break;
+            }
+            self.sweep_rule(rule, &mut sweep).await;
+        }
+        
+        sweep.elapsed = started.elapsed();
+        if sweep.truncated {
+            warn!("🧹 Sweep stopped after {} deletions; the rest waits for the next one", self.config.max_deletes_per_sweep);
+        }
+        if sweep.dry_run {
+            info!("🧹 Sweep found {} expired objects (dry run)", sweep.removed());
+        } else if sweep.removed() > 0 {
+            info!("🧹 Sweep deleted {} expired objects", sweep.removed());
+        } else {
+            debug!("Sweep found nothing expired");
+        }
+        *self.last.lock().unwrap() = Some(sweep.clone());
+        sweep
+    }
+    
+    /// Walk the rule's root breadth-first, expiring matches on the way
+    async fn sweep_rule(&self, rule: &LifecycleRule, sweep: &mut Sweep) {
+        let max_age = Duration::from_secs(rule.max_age_seconds);
+        let mut level = vec![root_of(&rule.pattern)];
+        
+        for _ in 0..=self.config.max_depth {
+            if level.is_empty() {
+                break;
+            }
+            let mut next = Vec::new();
+            for dir in level {
+                let entries = match self.client.list_with_metadata(&dir).await {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        sweep.errors += 1;
+                        warn!("❌ Sweeping {} failed: {}", dir.display(), e);
+                        continue;
+                    }
+                };
+                
+                for (name, metadata) in entries {
+                    let Ok(path) = join_name(&dir, OsStr::new(&name)) else {
+                        continue;
+                    };
+                    let age = SystemTime::now().duration_since(metadata.last_modified).unwrap_or_default();
+                    let expired = age >= max_age
+                        && (rule.directories || !metadata.is_directory)
+                        && search::glob_match(rule.pattern.as_bytes(), path.to_string_lossy().as_bytes());
+                    
+                    if expired {
+                        if sweep.removed() >= self.config.max_deletes_per_sweep {
+                            sweep.truncated = true;
+                            return;
+                        }
+                        let outcome = self.expire(&path, &metadata).await;
+                        sweep.expired.push(Expired { path, pattern: rule.pattern.clone(), age, outcome });
+                    } else if metadata.is_directory {
+                        next.push(path);
+                    }
+                }
+            }
+            level = next;
+        }
+    }
+    
+    async fn expire(&self, path: &Path, metadata: &ResourceMetadata) -> Outcome {
+        if let Some(retention) = &self.retention {
+            let protected = if metadata.is_directory {
+                retention.covers(path) || retention.contains_protected(path)
+            } else {
+                retention.protection(path, metadata.last_modified).is_some()
+            };
+            if protected {
+                return Outcome::Retained;
+            }
+        }
+        if self.config.dry_run {
+            return Outcome::WouldDelete;
+        }
+        
+        match self.client.delete(path).await {
+            Ok(()) => {
+                debug!("Expired {}", path.display());
+                Outcome::Deleted
+            }
+            Err(e) => {
+                warn!("❌ Expiring {} failed: {}", path.display(), e);
+                Outcome::Failed(e.to_string())
+            }
+        }
+    }
+    
+    /// Plain-text view for `/proc/gnos/lifecycle`
+    pub fn status_report(&self) -> String {
+        let mut report = format!("rules: {}\n", self.config.rules.len());
+        for rule in &self.config.rules {
+            report.push_str(&format!("rule\t{}\t{}s{}\n",
+                                     rule.pattern, rule.max_age_seconds, if rule.directories { "\tdirectories" } else { "" }));
+        }
+        match &*self.last.lock().unwrap() {
+            Some(sweep) => report.push_str(&sweep.report()),
+            None => report.push_str("sweep: pending\n"),
+        }
+        report
+    }
+}
+
+/// Deepest directory every match of `pattern` lies under
+fn root_of(pattern: &str) -> PathBuf {
+    let literal = pattern.find(['*', '?']).map_or(pattern, |at| &pattern[..at]);
+    // A partial name such as `/tmp/run-*` is found by listing its directory
+    match literal.rfind('/') {
+        Some(0) | None => PathBuf::from("/"),
+        Some(at) => PathBuf::from(&literal[..at]),
+    }
+}
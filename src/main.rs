@@ -1,7 +1,40 @@
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Instant;
 use clap::{Parser, Subcommand};
-use tracing::{info, error};
-use gnos::{GnosFileSystem, DriverRegistry, CapabilityManager, config::GnosConfig};
+use futures::stream::{self, StreamExt};
+use tracing::{info, error, warn};
+use gnos::{GnosClient, GnosFileSystem, DriverRegistry, CapabilityManager, config::{GnosConfig, TelemetryConfig}};
+use gnos::telemetry::{self as telemetry, Alert, AlertMonitor, Metrics, Profiler, Telemetry};
+use gnos::cache::{CompressionPolicy, DiskCache};
+use gnos::copy::{CopyEngine, CopyProgress};
+use gnos::events::EventBus;
+use gnos::export::{self, ArchiveFormat, ExportOptions, ExportProgress};
+use gnos::bandwidth::BandwidthLimiter;
+use gnos::checksum::ChecksumVerifier;
+use gnos::costs::CostTracker;
+use gnos::drivers::conformance::{self, Capabilities, Report};
+use gnos::drivers::context::DriverContext;
+use gnos::dryrun::DryRun;
+use gnos::faults::FaultInjector;
+use gnos::gateway::{presign_url, S3Gateway};
+use gnos::grpc::GrpcServer;
+use gnos::index::ContentIndex;
+use gnos::lifecycle::Janitor;
+use gnos::ninep::NinePServer;
+use gnos::output::{BreakGlassOutput, CostsOutput, DriverRow, DriversOutput, FindOutput, InfoOutput, MetricsOutput, OutputFormat, PresignOutput, TokenOutput};
+use gnos::plugins::PluginVerifier;
+use gnos::pools::DriverPools;
+use gnos::recovery::Supervisor;
+use gnos::qos::{self, QosClass};
+use gnos::shutdown::Shutdown;
+use gnos::triggers::TriggerEngine;
+use gnos::search::{SearchEngine, SearchQuery};
+use gnos::security::{parse_permissions, parse_ttl, BreakGlass, Capability, Deny};
+use gnos::state::{self, BackupOptions, RestoreOptions};
+use gnos::vfs::{ChangeBridge, Connectivity, MemoryBudget, RetentionTable, WriteBackQueue};
 
 #[derive(Parser)]
 #[command(name = "gnos-mount")]
@@ -31,87 +64,761 @@ enum Commands {
         /// Enable debug logging
         #[arg(short, long)]
         debug: bool,
+        
+        /// Expose only these prefixes, e.g. /cloud/aws/s3/ci,/proc
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<String>,
+        
+        /// Trace every open file handle, see /proc/gnos/handles
+        #[arg(long)]
+        trace_handles: bool,
+        
+        /// Check and log writes, deletes and renames without sending them, see /proc/gnos/dry_run
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// Capability tokens the mount itself holds, one per line, used in
+        /// place of GNOS_TOKEN; see /proc/gnos/keyring
+        #[arg(long)]
+        token_file: Option<PathBuf>,
     },
     
     /// Generate capability tokens
     Token {
-        /// Path to grant access to
+        /// Path to grant access to; with --template, a path within the template's
+        #[arg(short, long, required_unless_present = "template")]
+        path: Option<String>,
+        
+        /// Permissions (rwx format)
+        #[arg(short = 'p', long, default_value = "r", conflicts_with = "template")]
+        permissions: String,
+        
+        /// Expiration in hours
+        #[arg(short, long, default_value = "24", conflicts_with = "template")]
+        expires: u64,
+        
+        /// Capability template from [capability.templates] to instantiate
+        #[arg(short, long)]
+        template: Option<String>,
+        
+        /// Not valid before this time, in RFC 3339
+        #[arg(long)]
+        not_before: Option<String>,
+        
+        /// Revoke the token once unused for this long, e.g. 8h
+        #[arg(long)]
+        max_idle: Option<String>,
+        
+        /// Refuse a subtree within the path, as PATH or PATH:PERMS, e.g.
+        /// /cloud/prod/secrets or /cloud/prod:w; repeatable
+        #[arg(long)]
+        deny: Vec<String>,
+        
+        /// Configuration file holding the templates and signing key location
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+        
+        /// Output format: table for people, json or yaml for scripts
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    
+    /// List active drivers
+    Drivers {
+        /// Output format: table for people, json or yaml for scripts
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    
+    /// Work with one configured driver
+    Driver {
+        #[command(subcommand)]
+        command: DriverCommands,
+    },
+    
+    /// Benchmark the read path
+    Bench {
+        /// Object size in MiB for the large-read benchmark
+        #[arg(short, long, default_value = "64")]
+        size: usize,
+        
+        /// Number of passes over the object
+        #[arg(short, long, default_value = "10")]
+        iterations: usize,
+        
+        /// Concurrent threads for the metadata benchmark
+        #[arg(short, long, default_value = "8")]
+        threads: usize,
+    },
+    
+    /// Pre-walk part of a mounted namespace so later listings are cached
+    Warm {
+        /// Namespace prefix to warm, e.g. /cloud/aws
+        prefix: PathBuf,
+        
+        /// Mount point of the running filesystem
+        #[arg(short, long, default_value = "/mnt/gnos")]
+        mount_point: PathBuf,
+        
+        /// How many directory levels to descend
+        #[arg(short, long, default_value = "3")]
+        depth: usize,
+        
+        /// Directories listed concurrently
+        #[arg(short, long, default_value = "8")]
+        concurrency: usize,
+    },
+    
+    /// Serve the namespace over gRPC without mounting it
+    Serve {
+        /// Address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:7640")]
+        listen: SocketAddr,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+        
+        /// Enable debug logging
+        #[arg(short, long)]
+        debug: bool,
+    },
+    
+    /// Serve the namespace through an S3-compatible HTTP API
+    Gateway {
+        /// Address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:7641")]
+        listen: SocketAddr,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+        
+        /// Enable debug logging
         #[arg(short, long)]
+        debug: bool,
+    },
+    
+    /// Create a presigned S3 gateway URL backed by a capability token
+    Presign {
+        /// Object path, e.g. /cloud/aws/s3/reports/q3.pdf
         path: String,
         
+        /// Gateway endpoint
+        #[arg(short = 'u', long, default_value = "http://127.0.0.1:7641")]
+        endpoint: String,
+        
         /// Permissions (rwx format)
-        #[arg(short = 'p', long, default_value = "r")]
+        #[arg(short = 'p', long, default_value = "r", conflicts_with = "template")]
         permissions: String,
         
         /// Expiration in hours
-        #[arg(short, long, default_value = "24")]
+        #[arg(short, long, default_value = "1", conflicts_with = "template")]
         expires: u64,
+        
+        /// Capability template from [capability.templates] to instantiate
+        #[arg(short, long)]
+        template: Option<String>,
+        
+        /// Configuration file holding the templates and signing key location
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+        
+        /// Output format: table for people, json or yaml for scripts
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
     },
     
-    /// List active drivers
-    Drivers,
+    /// Issue a short-lived emergency capability; issuing and first use are
+    /// audited and alerted, and it is revoked once its session ends
+    Breakglass {
+        /// Path to grant access to
+        path: String,
+        
+        /// Why access is needed, recorded with every use
+        #[arg(short, long)]
+        reason: String,
+        
+        /// Permissions (rwx format)
+        #[arg(short = 'p', long, default_value = "rw")]
+        permissions: String,
+        
+        /// Expiration in minutes, at most security.break_glass.max_minutes
+        #[arg(short, long, default_value = "30")]
+        minutes: u64,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+        
+        /// Output format: table for people, json or yaml for scripts
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    
+    /// Copy an object between namespace paths, across drivers if needed
+    Cp {
+        /// Source path, e.g. /cloud/aws/s3/backups/db.tar
+        source: PathBuf,
+        
+        /// Destination path, e.g. /cloud/azure/blob/backups/db.tar
+        dest: PathBuf,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+        
+        /// Chunks in flight at once, overriding the config
+        #[arg(short = 'j', long)]
+        concurrency: Option<usize>,
+    },
+    
+    /// Write a namespace subtree to a tar or zip archive
+    Export {
+        /// Prefix to export, e.g. /cloud/aws/s3/logs
+        prefix: PathBuf,
+        
+        /// Archive format: tar, tar.zst or zip
+        #[arg(short, long, default_value = "tar.zst")]
+        format: String,
+        
+        /// Archive to write; defaults to the prefix's name with the format's extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        
+        /// Only export objects matching these globs, relative to the prefix
+        #[arg(long, value_delimiter = ',')]
+        include: Vec<String>,
+        
+        /// Skip objects and directories matching these globs
+        #[arg(long, value_delimiter = ',')]
+        exclude: Vec<String>,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+    },
+    
+    /// Back up daemon state (write-back journal, index, copy checkpoints) to a tar.zst
+    Backup {
+        /// Archive to write
+        output: PathBuf,
+        
+        /// Also back up the disk chunk cache
+        #[arg(long)]
+        include_cache: bool,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+    },
+    
+    /// Restore daemon state from a backup, with the daemon stopped
+    Restore {
+        /// Archive written by `backup`
+        archive: PathBuf,
+        
+        /// Merge into state directories that already hold files
+        #[arg(long)]
+        force: bool,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+    },
+    
+    /// Search the namespace, from the content index where it covers the scope
+    Find {
+        /// Query terms, e.g. scope=/cloud/aws name=*.log text="disk full"
+        #[arg(required = true)]
+        query: Vec<String>,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+        
+        /// Output format: table for people, json or yaml for scripts
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    
+    /// Apply the lifecycle rules once and list what expired
+    Sweep {
+        /// List what would be deleted without deleting it
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+    },
+    
+    /// Print a running mount's counters from /proc/gnos
+    Stats {
+        /// Mount point of the running filesystem
+        #[arg(short, long, default_value = "/mnt/gnos")]
+        mount_point: PathBuf,
+        
+        /// Estimated backend spend by driver, prefix and owner instead of metrics
+        #[arg(long)]
+        costs: bool,
+        
+        /// Output format: table for people, json or yaml for scripts
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    
+    /// Profile a running mount's FUSE, 9P and driver calls into a flamegraph
+    Profile {
+        /// Mount point of the running filesystem
+        #[arg(short, long, default_value = "/mnt/gnos")]
+        mount_point: PathBuf,
+        
+        /// How long to profile for, e.g. 30s or 5m
+        #[arg(short, long, default_value = "30s")]
+        duration: String,
+        
+        /// File to write: an SVG flamegraph if it ends in .svg, folded stacks otherwise
+        #[arg(short, long, default_value = "gnos-profile.svg")]
+        output: PathBuf,
+    },
     
     /// Show system info
-    Info,
+    Info {
+        /// Output format: table for people, json or yaml for scripts
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum DriverCommands {
+    /// Run the conformance suite against a driver
+    Test {
+        /// Driver to test, e.g. cloud
+        name: String,
+        
+        /// Directory the suite may create and delete files in
+        #[arg(short, long)]
+        scratch: PathBuf,
+        
+        /// Only check reads; the suite expects writes to be refused
+        #[arg(long)]
+        read_only: bool,
+        
+        /// Configuration file
+        #[arg(short, long, default_value = "gnos.toml")]
+        config: PathBuf,
+        
+        /// Output format: table for people, json or yaml for scripts
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            // Scripts can tell a refused token from a dead backend
+            ExitCode::from(gnos::error::exit_code(e.as_ref()))
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
     
     match cli.command {
-        Commands::Mount { mount_point, config, foreground, debug } => {
-            setup_logging(debug);
-            mount_filesystem(mount_point, config, foreground).await?;
+        Commands::Mount { mount_point, config: config_path, foreground, debug, only, trace_handles, dry_run, token_file } => {
+            // Loaded before logging starts, since it says where spans go
+            let mut config = GnosConfig::load(&config_path).await?;
+            let telemetry = setup_logging(debug, &config.telemetry)?;
+            info!("📋 Configuration loaded from {}", config_path.display());
+            
+            if !only.is_empty() {
+                config.vfs.only = only;
+            }
+            if trace_handles {
+                config.vfs.trace_handles = true;
+            }
+            if dry_run {
+                config.dry_run.enabled = true;
+            }
+            if let Some(token_file) = token_file {
+                // Daemonizing leaves the working directory behind
+                config.security.token_file = Some(std::env::current_dir()?.join(token_file));
+            }
+            
+            let result = mount_filesystem(mount_point, config, telemetry.metrics(), telemetry.profiler(), foreground).await;
+            telemetry.shutdown();
+            result?;
+        }
+        
+        Commands::Token { path, permissions, expires, template, not_before, max_idle, deny, config: config_path, output } => {
+            let config = GnosConfig::load(&config_path).await?;
+            let capability = capability_for(template, &config, path.as_deref(), &permissions, expires, "cli-user")?;
+            generate_token(constrain(capability, not_before, max_idle, deny)?, config, output).await?;
+        }
+        
+        Commands::Drivers { output } => {
+            list_drivers(output).await?;
+        }
+        
+        Commands::Driver { command: DriverCommands::Test { name, scratch, read_only, config: config_path, output } } => {
+            let config = GnosConfig::load(&config_path).await?;
+            setup_logging(false, &config.telemetry)?;
+            test_driver(&name, &scratch, read_only, config, output).await?;
         }
         
-        Commands::Token { path, permissions, expires } => {
-            generate_token(path, permissions, expires).await?;
+        Commands::Bench { size, iterations, threads } => {
+            run_bench(size, iterations).await?;
+            run_metadata_bench(threads).await?;
         }
         
-        Commands::Drivers => {
-            list_drivers().await?;
+        Commands::Warm { prefix, mount_point, depth, concurrency } => {
+            setup_logging(false, &TelemetryConfig::default())?;
+            warm_namespace(prefix, mount_point, depth, concurrency).await?;
         }
         
-        Commands::Info => {
-            show_info().await?;
+        Commands::Serve { listen, config: config_path, debug } => {
+            let config = GnosConfig::load(&config_path).await?;
+            let telemetry = setup_logging(debug, &config.telemetry)?;
+            
+            let result = serve_grpc(listen, config).await;
+            telemetry.shutdown();
+            result?;
+        }
+        
+        Commands::Gateway { listen, config: config_path, debug } => {
+            let config = GnosConfig::load(&config_path).await?;
+            let telemetry = setup_logging(debug, &config.telemetry)?;
+            
+            let result = serve_gateway(listen, config).await;
+            telemetry.shutdown();
+            result?;
+        }
+        
+        Commands::Presign { path, endpoint, permissions, expires, template, config: config_path, output } => {
+            let config = GnosConfig::load(&config_path).await?;
+            let capability = capability_for(template, &config, Some(&path), &permissions, expires, "presigned-url")?;
+            presign(endpoint, capability, config, output)?;
+        }
+        
+        Commands::Breakglass { path, reason, permissions, minutes, config: config_path, output } => {
+            let config = GnosConfig::load(&config_path).await?;
+            break_glass(path, reason, permissions, minutes, config, output).await?;
+        }
+        
+        Commands::Cp { source, dest, config: config_path, concurrency } => {
+            let mut config = GnosConfig::load(&config_path).await?;
+            setup_logging(false, &config.telemetry)?;
+            if let Some(concurrency) = concurrency {
+                config.copy.concurrency = concurrency;
+            }
+            copy_object(source, dest, config).await?;
+        }
+        
+        Commands::Export { prefix, format, output, include, exclude, config: config_path } => {
+            let config = GnosConfig::load(&config_path).await?;
+            setup_logging(false, &config.telemetry)?;
+            let options = ExportOptions {
+                include,
+                exclude,
+                chunk_size: std::cmp::max(config.copy.chunk_size_mb, 1) * 1024 * 1024,
+                ..ExportOptions::new(format.parse::<ArchiveFormat>()?)
+            };
+            export_subtree(prefix, output, options, config).await?;
+        }
+        
+        Commands::Backup { output, include_cache, config: config_path } => {
+            let config = GnosConfig::load(&config_path).await?;
+            setup_logging(false, &config.telemetry)?;
+            let manifest = state::backup(&config, &output, &BackupOptions { include_cache }).await?;
+            for component in &manifest.components {
+                eprintln!("💾 {}: {} files, {:.1} MiB", component.name, component.files, component.bytes as f64 / 1048576.0);
+            }
+            eprintln!("💾 State backed up to {}", output.display());
+        }
+        
+        Commands::Restore { archive, force, config: config_path } => {
+            let config = GnosConfig::load(&config_path).await?;
+            setup_logging(false, &config.telemetry)?;
+            let manifest = state::restore(&config, &archive, &RestoreOptions { force }).await?;
+            eprintln!("💾 Restored state backed up by GNOS {} at {}", manifest.version, manifest.created_at.to_rfc3339());
+        }
+        
+        Commands::Find { query, config: config_path, output } => {
+            let config = GnosConfig::load(&config_path).await?;
+            setup_logging(false, &config.telemetry)?;
+            find(query, config, output).await?;
+        }
+        
+        Commands::Sweep { dry_run, config: config_path } => {
+            let mut config = GnosConfig::load(&config_path).await?;
+            setup_logging(false, &config.telemetry)?;
+            config.lifecycle.dry_run |= dry_run;
+            sweep(config).await?;
+        }
+        
+        Commands::Stats { mount_point, costs, output } => {
+            show_stats(mount_point, costs, output).await?;
+        }
+        
+        Commands::Profile { mount_point, duration, output } => {
+            profile(mount_point, &duration, output).await?;
+        }
+        
+        Commands::Info { output } => {
+            show_info(output).await?;
         }
     }
     
     Ok(())
 }
 
-fn setup_logging(debug: bool) {
-    let level = if debug { "debug" } else { "info" };
-    
-    tracing_subscriber::fmt()
-        .with_env_filter(format!("gnos={},warn", level))
-        .with_target(false)
-        .init();
+fn setup_logging(debug: bool, telemetry: &TelemetryConfig) -> gnos::Result<Telemetry> {
+    Telemetry::init(debug, telemetry)
 }
 
 async fn mount_filesystem(
     mount_point: PathBuf, 
-    config_path: PathBuf, 
+    config: GnosConfig, 
+    metrics: Arc<Metrics>,
+    profiler: Option<Arc<Profiler>>,
     foreground: bool
 ) -> Result<(), Box<dyn std::error::Error>> {
     info!("🚀 Starting GNOS filesystem...");
     
-    // Load configuration
-    let config = GnosConfig::load(&config_path).await?;
-    info!("📋 Configuration loaded from {}", config_path.display());
-    
     // Initialize security
-    let capability_manager = CapabilityManager::new(config.security.clone());
+    let capability_manager = Arc::new(CapabilityManager::new(config.security.clone()));
+    if capability_manager.load_token_file()? > 0 && std::env::var_os("GNOS_TOKEN").is_some() {
+        info!("🔑 GNOS_TOKEN is ignored: the mount uses its ambient capabilities");
+    }
+    capability_manager.spawn_cleanup();
     info!("🔐 Security initialized");
     
     // Initialize driver registry
-    let driver_registry = DriverRegistry::new(config.drivers.clone()).await?;
+    // Panics are caught right at the driver, so a restart replaces nothing else
+    let plugins = PluginVerifier::new(config.plugins.clone(), capability_manager.clone());
+    let mut driver_registry = plugins.load_all(DriverRegistry::new(config.drivers.clone()).await?).await?
+        .with_tenants(&config.tenants).await?
+        .with_recovery(Supervisor::new(&config.recovery));
+    // Next, so only calls that reach a backend are billed
+    let costs = config.costs.enabled.then(|| CostTracker::new(&config.costs));
+    if let Some(costs) = &costs {
+        driver_registry = driver_registry.with_costs(costs.clone());
+    }
+    let dry_run = config.dry_run.enabled
+        .then(|| DryRun::new(&config.dry_run, capability_manager.clone()));
+    if let Some(dry_run) = &dry_run {
+        driver_registry = driver_registry.with_dry_run(dry_run.clone());
+    }
+    let faults = config.faults.enabled.then(|| FaultInjector::new(&config.faults));
+    if let Some(faults) = &faults {
+        driver_registry = driver_registry.with_faults(faults.clone());
+    }
+    // Outside the fault layer, so injected short reads show up as mismatches
+    let checksums = config.checksums.enabled
+        .then(|| ChecksumVerifier::new(&config.checksums, capability_manager.clone()));
+    if let Some(checksums) = &checksums {
+        driver_registry = driver_registry.with_checksums(checksums.clone());
+    }
+    // Outermost, so time spent waiting for a slot isn't charged to the backend
+    let bandwidth = Some(BandwidthLimiter::new(&config.bandwidth)).filter(|limiter| limiter.enabled());
+    if let Some(bandwidth) = &bandwidth {
+        driver_registry = driver_registry.with_bandwidth(bandwidth.clone());
+    }
+    // Outside even that, so a call queued for its pool holds nothing else up
+    let pools = DriverPools::new(&config.pools);
+    driver_registry = driver_registry.with_pools(pools.clone());
+    let driver_registry = Arc::new(driver_registry);
     info!("🔌 Drivers loaded: {}", driver_registry.count());
     
     // Create filesystem
-    let fs = GnosFileSystem::new(driver_registry, capability_manager);
+    let compression = Arc::new(CompressionPolicy::new(config.compression.clone()));
+    let memory = MemoryBudget::new(&config.memory);
+    if memory.bounded() {
+        info!("🧠 Memory budget: {} MB", config.memory.budget_mb);
+    }
+    let mut fs = GnosFileSystem::new(driver_registry.clone(), capability_manager.clone())
+        .with_memory(memory.clone())
+        .with_vfs_config(config.vfs.clone())
+        .with_compression(compression.clone());
     info!("📁 Filesystem created");
+    if !config.vfs.only.is_empty() {
+        info!("🙈 Exposing only {}", config.vfs.only.join(", "));
+    }
+    if config.vfs.trace_handles {
+        info!("🔍 Tracing open handles under /proc/gnos/handles");
+    }
+    if let Some(faults) = faults {
+        faults.serve(&config.faults.socket_path).await?;
+        fs.register_proc_file("faults", move || faults.status_report());
+        warn!("💥 Fault injection enabled, control socket at {}", config.faults.socket_path.display());
+    }
+    if let Some(checksums) = checksums {
+        fs.register_proc_file("checksums", move || checksums.status_report());
+        info!("🧬 Verifying transfer checksums");
+    }
+    if let Some(bandwidth) = bandwidth {
+        fs.register_proc_file("bandwidth", move || bandwidth.status_report());
+        info!("🚦 Bandwidth caps in force, see /proc/gnos/bandwidth");
+    }
+    let registry = driver_registry.clone();
+    fs.register_proc_file("pools", move || registry.pools_report().unwrap_or_default());
+    let registry = driver_registry.clone();
+    fs.register_proc_file("recovery", move || registry.recovery_report().unwrap_or_default());
+    let registry = driver_registry.clone();
+    fs.register_proc_file("egress", move || registry.egress_report());
+    let registry = driver_registry.clone();
+    fs.register_proc_file("routes", move || registry.routes_report());
+    if config.security.token_file.is_some() {
+        let capability_manager = capability_manager.clone();
+        fs.register_proc_file("keyring", move || capability_manager.keyring_report());
+    }
+    if driver_registry.has_credentials() {
+        let registry = driver_registry.clone();
+        fs.register_proc_file("credentials", move || registry.credentials_report());
+    }
+    if driver_registry.has_regions() {
+        let registry = driver_registry.clone();
+        fs.register_proc_file("regions", move || registry.regions_report());
+        info!("🌍 Routing bucket aliases across regions, see /proc/gnos/regions");
+    }
+    if let Some(dry_run) = dry_run {
+        fs.register_proc_file("dry_run", move || dry_run.status_report());
+        warn!("🧪 Dry run: writes, deletes and renames are logged, not sent");
+    }
+    if let Some(costs) = costs {
+        fs.register_proc_file("costs", move || costs.status_report());
+        info!("💸 Estimating backend costs, see /proc/gnos/costs");
+    }
+    if !config.tenants.is_empty() {
+        fs = fs.with_tenants(&config.tenants);
+        info!("🏢 Serving {} tenant namespaces under /tenants", config.tenants.len());
+    }
+    
+    // Triggers write straight to drivers, so their own changes raise no events
+    let trigger_client = GnosClient::with_components(driver_registry.clone(), capability_manager.clone())
+        .with_compression(compression.clone());
+    let (events, triggers) = start_events(&config, &trigger_client).await?;
+    if let Some(triggers) = triggers {
+        fs = fs.with_triggers(triggers);
+    }
+    if let Some(events) = &events {
+        fs = fs.with_events(events.clone());
+    }
+    
+    if config.index.enabled {
+        let index = ContentIndex::open(&config.index, driver_registry.clone()).await?;
+        index.start();
+        fs = fs.with_index(index);
+        info!("🗂️  Indexing {} prefixes", config.index.prefixes.len());
+    }
+    fs = fs.with_search(&config.search);
+    
+    if config.cache.enabled {
+        let disk_cache = DiskCache::open(config.cache.clone(), compression.clone()).await?;
+        fs = fs.with_disk_cache(Arc::new(disk_cache));
+    }
+    
+    // Offline writes live in the write-back journal until their backend returns
+    let connectivity = Arc::new(Connectivity::new(&config.offline));
+    if config.offline.enabled {
+        fs = fs.with_offline(connectivity.clone());
+        info!("📴 Offline mode enabled, probing every {}s", config.offline.probe_interval_seconds);
+    }
+    
+    if config.writeback.enabled || config.offline.enabled {
+        let queue = WriteBackQueue::start(
+            config.writeback.clone(),
+            driver_registry.clone(),
+            compression.clone(),
+            connectivity,
+            memory,
+        ).await?;
+        fs = fs.with_write_back(queue);
+        info!("📼 Write-back enabled, journal at {}", config.writeback.journal_dir.display());
+    }
+    
+    let mut quota = config.quota.clone();
+    quota.rules.extend(config.tenants.iter().filter_map(|tenant| tenant.quota_rule()));
+    if !quota.rules.is_empty() {
+        fs = fs.with_quotas(&quota);
+        info!("📏 Enforcing {} quotas", quota.rules.len());
+    }
+    
+    if !config.retention.rules.is_empty() {
+        fs = fs.with_retention(&config.retention);
+        info!("🔏 Protecting {} append-only or write-once prefixes", config.retention.rules.len());
+    }
+    
+    // Expiry goes through a client, so deletions are checked and published like any other
+    if !config.lifecycle.rules.is_empty() {
+        let mut client = GnosClient::with_components(driver_registry.clone(), capability_manager.clone())
+            .with_compression(compression.clone());
+        if let Some(events) = events {
+            client = client.with_events(events);
+        }
+        let janitor = Arc::new(Janitor::new(&config.lifecycle, client).with_retention(fs.retention()));
+        janitor.clone().spawn();
+        fs.register_proc_file("lifecycle", move || janitor.status_report());
+        info!("🧹 Expiring {} lifecycle rules every {}s{}", config.lifecycle.rules.len(),
+              config.lifecycle.interval_seconds, if config.lifecycle.dry_run { " (dry run)" } else { "" });
+    }
+    
+    // Notifications need the FUSE session, so the bridge starts with the mount
+    let bridge = config.notify.enabled
+        .then(|| ChangeBridge::new(fs.core(), &config.notify, &mount_point));
+    if let Some(bridge) = &bridge {
+        let status = bridge.clone();
+        fs.register_proc_file("notify", move || status.status_report());
+    }
+    
+    if let Some(profiler) = profiler {
+        fs.register_proc_file("profile", move || profiler.folded());
+        info!("🔬 Profiling FUSE, 9P and driver calls into /proc/gnos/profile");
+    }
+    
+    let shutdown = Shutdown::new(&config.shutdown);
+    let fs = fs.with_metrics(metrics.clone()).with_shutdown(shutdown.clone());
+    
+    if config.alerts.enabled {
+        AlertMonitor::new(config.alerts.clone(), metrics, capability_manager, driver_registry.http()).spawn();
+        info!("🚨 Alerting every {}s", config.alerts.interval_seconds);
+    }
+    
+    // Guests reach the same VFS core over 9P, sharing its caches and write-back queue
+    if config.ninep.enabled {
+        NinePServer::bind(fs.core(), &config.ninep).await?.spawn();
+    }
+    
+    // Existing usage is counted in the background; until then writes are
+    // measured against what has been seen
+    if !quota.rules.is_empty() && quota.scan_on_mount {
+        let quotas = fs.quotas();
+        let registry = driver_registry.clone();
+        let max_depth = quota.max_depth;
+        tokio::spawn(qos::scope(QosClass::Background, async move {
+            quotas.scan(&registry, max_depth).await;
+            info!("📏 Quota usage counted");
+        }));
+    }
+    
+    // Warm in the background so the mount itself isn't delayed
+    if !config.vfs.warm_prefixes.is_empty() {
+        let warmer = fs.warmer(&config.vfs);
+        let prefixes = config.vfs.warm_prefixes.clone();
+        tokio::spawn(qos::scope(QosClass::Background, async move {
+            for prefix in prefixes {
+                let stats = warmer.warm(Path::new(&prefix)).await;
+                info!("🔥 Warmed {}: {} directories, {} entries in {:?}",
+                      prefix, stats.directories, stats.entries, stats.elapsed);
+            }
+        }));
+    }
     
     // Mount options for FUSE
     let options = vec![
@@ -129,83 +836,799 @@ async fn mount_filesystem(
         info!("Running as daemon...");
     }
     
-    // This blocks until unmounted
-    fuser::mount2(fs, &mount_point, &options)?;
+    // This blocks until unmounted. FUSE callbacks are synchronous, so the
+    // session runs on a blocking thread where it can wait on driver futures.
+    let session_mount_point = mount_point.clone();
+    let (unmounter_tx, unmounter_rx) = tokio::sync::oneshot::channel();
+    let session = tokio::task::spawn_blocking(move || {
+        let mut session = fuser::Session::new(fs, &session_mount_point, &options)?;
+        if let Some(bridge) = bridge {
+            bridge.spawn(session.notifier());
+        }
+        let _ = unmounter_tx.send(session.unmount_callable());
+        session.run()
+    });
+    
+    // SIGTERM and Ctrl-C drain the mount before unmounting it; the session
+    // then commits what is still buffered on its way out
+    tokio::spawn(async move {
+        let Ok(mut unmounter) = unmounter_rx.await else {
+            return;
+        };
+        shutdown.wait(&pools).await;
+        if let Err(e) = unmounter.unmount() {
+            error!("❌ Unmounting failed: {}", e);
+        }
+    });
+    session.await??;
     
     info!("📴 GNOS unmounted");
     Ok(())
 }
 
-async fn generate_token(
-    path: String, 
-    permissions: String, 
-    expires_hours: u64
+async fn serve_grpc(listen: SocketAddr, config: GnosConfig) -> Result<(), Box<dyn std::error::Error>> {
+    info!("🚀 Starting GNOS gRPC service...");
+    
+    let mut client = GnosClient::new(&config).await?;
+    if let (Some(events), _) = start_events(&config, &client).await? {
+        client = client.with_events(events);
+    }
+    GrpcServer::new(client).serve(listen).await?;
+    Ok(())
+}
+
+async fn serve_gateway(listen: SocketAddr, config: GnosConfig) -> Result<(), Box<dyn std::error::Error>> {
+    info!("🚀 Starting GNOS S3 gateway...");
+    
+    let mut client = GnosClient::new(&config).await?;
+    if let (Some(events), _) = start_events(&config, &client).await? {
+        client = client.with_events(events);
+    }
+    S3Gateway::new(client).serve(listen).await?;
+    Ok(())
+}
+
+/// The event bus, when events are enabled or triggers need one, with the
+/// triggers listening on it; `client` acts for the triggers and must not publish
+async fn start_events(
+    config: &GnosConfig,
+    client: &GnosClient,
+) -> gnos::Result<(Option<Arc<EventBus>>, Option<Arc<TriggerEngine>>)> {
+    // Webhooks go through the configured proxy, TLS and egress policy, as driver requests do
+    let http = DriverContext::new(&config.drivers)?.http;
+    let events = if config.events.enabled {
+        EventBus::start(&config.events, http.clone()).await?
+    } else if !config.triggers.rules.is_empty() {
+        EventBus::new(config.events.buffer)
+    } else {
+        return Ok((None, None));
+    };
+    
+    let triggers = (!config.triggers.rules.is_empty()).then(|| {
+        let triggers = TriggerEngine::new(&config.triggers, client.clone(), http);
+        triggers.spawn(&events);
+        triggers
+    });
+    Ok((Some(events), triggers))
+}
+
+async fn copy_object(source: PathBuf, dest: PathBuf, config: GnosConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let client = GnosClient::new(&config).await?;
+    let engine = CopyEngine::new(config.copy.clone(), Arc::new(CompressionPolicy::new(config.compression.clone())));
+    let progress = CopyProgress::new();
+    let start = Instant::now();
+    
+    let copy = client.copy(&source, &dest, &engine, &progress);
+    tokio::pin!(copy);
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(1));
+    
+    let copied = loop {
+        tokio::select! {
+            result = &mut copy => break result?,
+            _ = ticker.tick() => {
+                let total = progress.total();
+                if total > 0 {
+                    eprint!("\r📦 {:.1} / {:.1} MiB ({:.0}%)",
+                            progress.copied() as f64 / 1048576.0, total as f64 / 1048576.0,
+                            progress.copied() as f64 * 100.0 / total as f64);
+                }
+            }
+        }
+    };
+    
+    let elapsed = start.elapsed().as_secs_f64();
+    eprintln!("\r📦 Copied {} to {}: {:.1} MiB in {:.1}s ({:.1} MiB/s)",
+              source.display(), dest.display(), copied as f64 / 1048576.0, elapsed,
+              copied as f64 / 1048576.0 / elapsed.max(0.001));
+    Ok(())
+}
+
+async fn export_subtree(
+    prefix: PathBuf,
+    output: Option<PathBuf>,
+    options: ExportOptions,
+    config: GnosConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use gnos::security::{Capability, Operation};
+    let client = GnosClient::new(&config).await?;
+    let output = output.unwrap_or_else(|| {
+        let name = prefix.file_name().map_or_else(|| "gnos".into(), |name| name.to_string_lossy().into_owned());
+        PathBuf::from(format!("{}.{}", name, options.format.extension()))
+    });
+    let progress = Arc::new(ExportProgress::new());
+    let start = Instant::now();
+    
+    let export = export::export(&client, &prefix, &output, &options, &progress);
+    tokio::pin!(export);
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(1));
+    
+    let stats = loop {
+        tokio::select! {
+            result = &mut export => break result?,
+            _ = ticker.tick() => {
+                if progress.total_files() > 0 {
+                    eprint!("\r📦 {} / {} objects, {:.1} / {:.1} MiB",
+                            progress.files(), progress.total_files(),
+                            progress.bytes() as f64 / 1048576.0, progress.total_bytes() as f64 / 1048576.0);
+                }
+            }
+        }
+    };
+    
+    eprintln!("\r📦 Exported {} objects ({:.1} MiB) from {} to {} in {:.1}s",
+              stats.files, stats.bytes as f64 / 1048576.0, prefix.display(), output.display(),
+              start.elapsed().as_secs_f64());
+    Ok(())
+}
+
+async fn sweep(config: GnosConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if config.lifecycle.rules.is_empty() {
+        eprintln!("🧹 No lifecycle rules configured");
+        return Ok(());
+    }
+    let mut client = GnosClient::new(&config).await?;
+    if let (Some(events), _) = start_events(&config, &client).await? {
+        client = client.with_events(events);
+    }
+    let janitor = Janitor::new(&config.lifecycle, client)
+        .with_retention(Arc::new(RetentionTable::new(config.retention.rules.clone())));
+    
+    let report = janitor.sweep().await.report();
+    print!("{}", report);
+    Ok(())
+}
+
+async fn find(terms: Vec<String>, config: GnosConfig, output: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    // The shell has already stripped quotes, so values with spaces get them back
+    let query: SearchQuery = terms.iter()
+        .map(|term| match term.split_once('=') {
+            Some((key, value)) if value.contains(char::is_whitespace) => format!("{}=\"{}\"", key, value),
+            _ => term.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+        .parse()?;
+    
+    let capability_manager = Arc::new(CapabilityManager::new(config.security.clone()));
+    let plugins = PluginVerifier::new(config.plugins.clone(), capability_manager.clone());
+    let driver_registry = Arc::new(plugins.load_all(DriverRegistry::new(config.drivers.clone()).await?).await?
+        .with_tenants(&config.tenants).await?);
+    let mut engine = SearchEngine::new(&config.search, driver_registry.clone(), capability_manager);
+    if config.index.enabled {
+        engine = engine.with_index(ContentIndex::open(&config.index, driver_registry).await?);
+    }
+    
+    // People see matches as they come; documents hold them all
+    let start = Instant::now();
+    let matches = std::sync::Mutex::new(Vec::new());
+    let stats = engine.run(&query, |path| if output.is_table() {
+        println!("{}", path.display());
+    } else {
+        matches.lock().unwrap().push(path.display().to_string());
+    }).await?;
+    
+    let found = FindOutput {
+        matches: matches.into_inner().unwrap(),
+        count: stats.matches,
+        directories: stats.directories,
+        errors: stats.errors,
+        truncated: stats.truncated,
+        seconds: start.elapsed().as_secs_f64(),
+    };
+    output.emit("gnos.find/v1", &found, |found| {
+        eprintln!("🔎 {} matches in {:.1}s{}", found.count, found.seconds,
+                  if found.truncated { " (truncated)" } else { "" });
+    })?;
+    Ok(())
+}
+
+/// The capability a token or presigned URL carries: instantiated from a
+/// configured template, or spelled out by the flags
+fn capability_for(
+    template: Option<String>,
+    config: &GnosConfig,
+    path: Option<&str>,
+    permissions: &str,
+    expires_hours: u64,
+    owner: &str,
+) -> Result<Capability, Box<dyn std::error::Error>> {
     use std::time::{SystemTime, Duration};
     
-    println!("🎫 Generating GNOS capability token...");
+    if let Some(template) = template {
+        return Ok(config.capability.instantiate(&template, path.map(Path::new))?);
+    }
+    Ok(Capability {
+        path: PathBuf::from(path.ok_or("a path or a --template is required")?),
+        permissions: parse_permissions(permissions)?,
+        expiration: SystemTime::now() + Duration::from_secs(expires_hours * 3600),
+        owner: owner.to_string(),
+        break_glass: None,
+        issued: Some(SystemTime::now()),
+        not_before: None,
+        max_idle_seconds: None,
+        deny: Vec::new(),
+    })
+}
+
+/// Apply `--not-before` and `--max-idle`, which override a template's, and
+/// `--deny`, which adds to its deny entries
+fn constrain(
+    mut capability: Capability,
+    not_before: Option<String>,
+    max_idle: Option<String>,
+    deny: Vec<String>,
+) -> Result<Capability, Box<dyn std::error::Error>> {
+    if let Some(not_before) = not_before {
+        let not_before = chrono::DateTime::parse_from_rfc3339(&not_before)
+            .map_err(|e| format!("--not-before {}: {}", not_before, e))?;
+        capability.not_before = Some(not_before.into());
+    }
+    if let Some(max_idle) = max_idle {
+        capability.max_idle_seconds = Some(parse_ttl(&max_idle)?.as_secs());
+    }
+    for spec in deny {
+        capability.deny.push(Deny::parse(&spec).map_err(|e| format!("--deny {}: {}", spec, e))?);
+    }
+    if capability.not_before.is_some_and(|not_before| not_before >= capability.expiration) {
+        return Err("--not-before is after the token expires".into());
+    }
+    Ok(capability)
+}
+
+async fn break_glass(
+    path: String,
+    reason: String,
+    permissions: String,
+    minutes: u64,
+    config: GnosConfig,
+    output: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::time::{SystemTime, Duration};
     
-    let perms = parse_permissions(&permissions)?;
-    let expiration = SystemTime::now() + Duration::from_secs(expires_hours * 3600);
+    let reason = reason.trim().to_string();
+    if reason.is_empty() {
+        return Err("break-glass access needs a --reason".into());
+    }
+    let limits = &config.security.break_glass;
+    if minutes == 0 || minutes > limits.max_minutes {
+        return Err(format!("--minutes must be between 1 and {} (security.break_glass.max_minutes)", limits.max_minutes).into());
+    }
     
+    let id = uuid::Uuid::new_v4().simple().to_string();
     let capability = Capability {
-        path: PathBuf::from(path.clone()),
-        permissions: perms,
-        expiration,
-        owner: "cli-user".to_string(),
+        path: PathBuf::from(&path),
+        permissions: parse_permissions(&permissions)?,
+        expiration: SystemTime::now() + Duration::from_secs(minutes * 60),
+        owner: std::env::var("USER").unwrap_or_else(|_| "on-call".to_string()),
+        break_glass: Some(BreakGlass { id: id.clone(), reason: reason.clone() }),
+        issued: Some(SystemTime::now()),
+        not_before: None,
+        max_idle_seconds: None,
+        deny: Vec::new(),
     };
     
-    let token = capability.to_token()?;
+    // Issuing is on record and announced before anyone can use the grant
+    let capability_manager = CapabilityManager::new(config.security.clone());
+    capability_manager.audit_break_glass(&capability);
+    capability_manager.flush_audit()?;
+    if let Some(url) = config.alerts.webhook_url.as_deref().filter(|_| config.alerts.enabled) {
+        let message = format!("break-glass {} issued for {} ({}, {} minutes): {}", id, path, permissions, minutes, reason);
+        // Through the configured proxy, TLS and egress policy, as driver requests are
+        let http = DriverContext::new(&config.drivers)?.http;
+        Alert::now("break_glass", capability.owner.clone(), message).post(&http, url).await;
+    }
+    
+    let grant = BreakGlassOutput {
+        token: capability_manager.issue(&capability)?,
+        id,
+        path,
+        permissions: permission_letters(capability.permissions),
+        owner: capability.owner.clone(),
+        reason,
+        expires_at: chrono::DateTime::<chrono::Utc>::from(capability.expiration).to_rfc3339(),
+        session_minutes: limits.session_minutes,
+    };
+    output.emit("gnos.breakglass/v1", &grant, |grant| {
+        println!("🚨 Break-glass grant {} for {}", grant.id, grant.owner);
+        println!("📄 Path: {}", grant.path);
+        println!("🔑 Permissions: {}", grant.permissions);
+        println!("📝 Reason: {}", grant.reason);
+        println!("⏰ Expires: {}, or {} minutes after first use", grant.expires_at, grant.session_minutes);
+        println!("🎟️  Token: {}", grant.token);
+        println!("\n💡 Usage: export GNOS_TOKEN=\"{}\"", grant.token);
+    })?;
+    Ok(())
+}
+
+/// rwx bits back as the letters they were given in
+fn permission_letters(bits: u8) -> String {
+    [(0b100, 'r'), (0b010, 'w'), (0b001, 'x')].iter()
+        .filter(|(bit, _)| bits & bit != 0)
+        .map(|(_, letter)| *letter)
+        .collect()
+}
+
+fn presign(endpoint: String, capability: Capability, config: GnosConfig, output: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let token = CapabilityManager::new(config.security).issue(&capability)?;
+    let presigned = PresignOutput {
+        url: presign_url(&endpoint, &capability, &token),
+        path: capability.path.display().to_string(),
+        permissions: permission_letters(capability.permissions),
+        expires_at: chrono::DateTime::<chrono::Utc>::from(capability.expiration).to_rfc3339(),
+    };
+    output.emit("gnos.presign/v1", &presigned, |presigned| println!("{}", presigned.url))?;
+    Ok(())
+}
+
+async fn generate_token(capability: Capability, config: GnosConfig, output: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    if output.is_table() {
+        println!("🎫 Generating GNOS capability token...");
+    }
     
-    println!("📄 Path: {}", path);
-    println!("🔑 Permissions: {}", permissions);
-    println!("⏰ Expires: {} hours", expires_hours);
-    println!("🎟️  Token: {}", token);
-    println!("\n💡 Usage: export GNOS_TOKEN=\"{}\"", token);
+    let lifetime = capability.expiration.duration_since(std::time::SystemTime::now()).unwrap_or_default();
+    let token = TokenOutput {
+        token: CapabilityManager::new(config.security).issue(&capability)?,
+        path: capability.path.display().to_string(),
+        permissions: permission_letters(capability.permissions),
+        expires_hours: lifetime.as_secs().div_ceil(3600),
+        expires_at: chrono::DateTime::<chrono::Utc>::from(capability.expiration).to_rfc3339(),
+        owner: capability.owner.clone(),
+        not_before: capability.not_before.map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339()),
+        max_idle_seconds: capability.max_idle_seconds,
+        deny: capability.deny.iter().map(|deny| format!("{}:{}", deny.path.display(), deny.perms)).collect(),
+    };
+    output.emit("gnos.token/v1", &token, |token| {
+        println!("📄 Path: {}", token.path);
+        println!("🔑 Permissions: {}", token.permissions);
+        println!("👤 Owner: {}", token.owner);
+        if let Some(not_before) = &token.not_before {
+            println!("🕒 Valid from: {}", not_before);
+        }
+        println!("⏰ Expires: {} hours", token.expires_hours);
+        if let Some(idle) = token.max_idle_seconds {
+            println!("💤 Revoked after {}s unused", idle);
+        }
+        for deny in &token.deny {
+            println!("🚫 Denied: {}", deny);
+        }
+        println!("🎟️  Token: {}", token.token);
+        println!("\n💡 Usage: export GNOS_TOKEN=\"{}\"", token.token);
+    })?;
     
     Ok(())
 }
 
-fn parse_permissions(perms: &str) -> Result<u8, Box<dyn std::error::Error>> {
-    let mut result = 0u8;
+async fn list_drivers(output: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let drivers = DriversOutput {
+        drivers: [
+            ("AI Models", "/proc/llama3"),
+            ("AWS S3", "/cloud/aws/s3"),
+            ("HTTP Services", "/net/http"),
+            ("IoT Sensors", "/dev/sensors"),
+        ]
+        .into_iter()
+        .map(|(name, path)| DriverRow { name, path, status: "ready" })
+        .collect(),
+    };
     
-    for ch in perms.chars() {
-        match ch {
-            'r' => result |= 0b100,
-            'w' => result |= 0b010,
-            'x' => result |= 0b001,
-            _ => return Err(format!("Invalid permission: {}", ch).into()),
+    output.emit("gnos.drivers/v1", &drivers, |drivers| {
+        println!("🔌 Available GNOS Drivers:");
+        println!("┌─────────────────┬──────────────────┬────────────┐");
+        println!("│ Name            │ Path             │ Status     │");
+        println!("├─────────────────┼──────────────────┼────────────┤");
+        for driver in &drivers.drivers {
+            println!("│ {:<15} │ {:<16} │ {:<10} │", driver.name, driver.path, "Ready");
         }
+        println!("└─────────────────┴──────────────────┴────────────┘");
+    })?;
+    
+    Ok(())
+}
+
+async fn test_driver(
+    name: &str,
+    scratch: &Path,
+    read_only: bool,
+    config: GnosConfig,
+    output: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let plugins = PluginVerifier::new(config.plugins.clone(), Arc::new(CapabilityManager::new(config.security.clone())));
+    let driver_registry = plugins.load_all(DriverRegistry::new(config.drivers.clone()).await?).await?;
+    let Some(driver) = driver_registry.driver(name) else {
+        return Err(format!("no driver {} is configured; configured: {}", name, driver_registry.labels().join(", ")).into());
+    };
+    
+    let capabilities = Capabilities::probe(driver.as_ref(), scratch, !read_only);
+    let mut report = Report::new(name);
+    conformance::run(driver.as_ref(), capabilities, scratch, &mut report).await;
+    output.emit("gnos.conformance/v1", &report, |report| print!("{}", report))?;
+    if !report.passed() {
+        return Err(format!("{} of {} checks failed", report.failures(), report.checks.len()).into());
+    }
+    Ok(())
+}
+
+/// Where `gnos bench` serves its object from
+const BENCH_ROOT: &str = "/bench";
+
+/// Serves one in-memory object under `BENCH_ROOT`, so the read-path
+/// benchmark measures GNOS rather than a backend
+struct BenchDriver {
+    object: bytes::Bytes,
+}
+
+impl BenchDriver {
+    fn object_path() -> PathBuf {
+        Path::new(BENCH_ROOT).join("object")
+    }
+}
+
+#[async_trait::async_trait]
+impl gnos::GnosDriver for BenchDriver {
+    async fn read(&self, _path: &Path) -> gnos::Result<bytes::Bytes> {
+        Ok(self.object.clone())
+    }
+    
+    async fn read_range(&self, _path: &Path, offset: u64, len: u64) -> gnos::Result<bytes::Bytes> {
+        let start = std::cmp::min(offset, self.object.len() as u64) as usize;
+        let end = std::cmp::min(offset.saturating_add(len), self.object.len() as u64) as usize;
+        Ok(self.object.slice(start..end))
+    }
+    
+    async fn write(&self, path: &Path, _data: &[u8]) -> gnos::Result<()> {
+        Err(gnos::GnosError::PermissionDenied(format!("{} is read-only", path.display())))
+    }
+    
+    async fn list(&self, _path: &Path) -> gnos::Result<Vec<String>> {
+        Ok(vec!["object".to_string()])
+    }
+    
+    async fn exists(&self, path: &Path) -> gnos::Result<bool> {
+        Ok(path == Path::new(BENCH_ROOT) || path == Self::object_path())
+    }
+    
+    async fn metadata(&self, path: &Path) -> gnos::Result<gnos::drivers::ResourceMetadata> {
+        Ok(gnos::drivers::ResourceMetadata {
+            size: self.object.len() as u64,
+            is_directory: path == Path::new(BENCH_ROOT),
+            last_modified: std::time::UNIX_EPOCH,
+            etag: Some("bench".to_string()),
+            ..Default::default()
+        })
+    }
+    
+    fn name(&self) -> &'static str {
+        "Bench Driver"
     }
     
-    Ok(result)
+    fn supports(&self, path: &Path) -> bool {
+        path.starts_with(BENCH_ROOT)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(BENCH_ROOT)]
+    }
 }
 
-async fn list_drivers() -> Result<(), Box<dyn std::error::Error>> {
-    println!("🔌 Available GNOS Drivers:");
-    println!("┌─────────────────┬──────────────────┬────────────┐");
-    println!("│ Name            │ Path             │ Status     │");
-    println!("├─────────────────┼──────────────────┼────────────┤");
-    println!("│ AI Models       │ /proc/llama3     │ Ready      │");
-    println!("│ AWS S3          │ /cloud/aws/s3    │ Ready      │");
-    println!("│ HTTP Services   │ /net/http        │ Ready      │");
-    println!("│ IoT Sensors     │ /dev/sensors     │ Future     │");
-    println!("└─────────────────┴──────────────────┴────────────┘");
+/// Read the bench object front to back `passes` times through `core`, in
+/// FUSE-sized requests on a fresh handle each pass; with `copy` every reply
+/// is also copied out, as replies were before they shared the driver's buffer
+async fn time_reads(core: &gnos::vfs::VfsCore, passes: usize, copy: bool) -> gnos::Result<std::time::Duration> {
+    // Largest read the kernel issues per FUSE request
+    const FUSE_READ_SIZE: u32 = 128 * 1024;
+    
+    let path = BenchDriver::object_path();
+    let ino = core.resolve(&path).ok_or_else(|| gnos::GnosError::PathNotFound(path.display().to_string()))?;
+    let start = Instant::now();
+    for _ in 0..passes {
+        let mut file = core.open(ino, false).await?;
+        let mut offset = 0;
+        loop {
+            let reply = core.read(&mut file, offset, FUSE_READ_SIZE).await?;
+            if reply.is_empty() {
+                break;
+            }
+            offset += reply.len() as u64;
+            if copy {
+                std::hint::black_box(reply.to_vec());
+            } else {
+                std::hint::black_box(reply);
+            }
+        }
+    }
+    Ok(start.elapsed())
+}
+
+async fn run_bench(size_mb: usize, iterations: usize) -> Result<(), Box<dyn std::error::Error>> {
+    use gnos::config::{CacheConfig, DriverConfig, VfsConfig};
+    use gnos::security::SecurityConfig;
+    
+    println!("⏱️  GNOS read-path benchmark ({} MiB x {} passes)", size_mb, iterations);
+    
+    let object = bytes::Bytes::from((0..size_mb * 1024 * 1024).map(|i| (i % 251) as u8).collect::<Vec<u8>>());
+    
+    // Only the bench driver, so nothing else is set up or routed to
+    let mut drivers = DriverConfig::default();
+    drivers.ai.enabled = false;
+    drivers.cloud.enabled = false;
+    drivers.http.enabled = false;
+    drivers.sensors.enabled = false;
+    let driver_registry = Arc::new(
+        DriverRegistry::new(drivers).await?.with_driver("bench", Arc::new(BenchDriver { object }))
+    );
+    let capability_manager = Arc::new(CapabilityManager::new(SecurityConfig::default()));
+    
+    let scratch = std::env::temp_dir().join(format!("gnos-bench-{}", std::process::id()));
+    let disk_cache = DiskCache::open(CacheConfig {
+        enabled: true,
+        dir: scratch.clone(),
+        max_size_mb: (size_mb as u64) * 2 + 64,
+        prefixes: vec![BENCH_ROOT.to_string()],
+        dedup: false,
+        ..CacheConfig::default()
+    }, Arc::new(CompressionPolicy::default())).await?;
+    
+    let direct = GnosFileSystem::new(driver_registry.clone(), capability_manager.clone());
+    let cached = GnosFileSystem::new(driver_registry, capability_manager).with_disk_cache(Arc::new(disk_cache));
+    for fs in [&direct, &cached] {
+        fs.warmer(&VfsConfig::default()).warm(Path::new(BENCH_ROOT)).await;
+    }
+    
+    // Driver → FUSE reply, with replies copied as before and shared as now
+    let copied = time_reads(&direct.core(), iterations, true).await;
+    let shared = time_reads(&direct.core(), iterations, false).await;
+    // Driver → disk cache → FUSE reply: the first pass fills the cache
+    let cold = time_reads(&cached.core(), 1, false).await;
+    let warm = time_reads(&cached.core(), iterations, false).await;
+    let _ = std::fs::remove_dir_all(&scratch);
+    
+    let row = |label: &str, passes: usize, elapsed: std::time::Duration| {
+        let mib = (size_mb * passes) as f64;
+        println!("│ {:<17} │ {:>9.2} ms │ {:>10.0} MiB/s │", label, elapsed.as_secs_f64() * 1000.0, mib / elapsed.as_secs_f64());
+    };
+    println!("┌───────────────────┬──────────────┬──────────────────┐");
+    println!("│ Read path         │ Time         │ Throughput       │");
+    println!("├───────────────────┼──────────────┼──────────────────┤");
+    row("Driver, copied", iterations, copied?);
+    row("Driver, shared", iterations, shared?);
+    row("Disk cache, cold", 1, cold?);
+    row("Disk cache, warm", iterations, warm?);
+    println!("└───────────────────┴──────────────┴──────────────────┘");
     
     Ok(())
 }
 
-async fn show_info() -> Result<(), Box<dyn std::error::Error>> {
-    println!("🌟 GNOS - GlobalNamespace OS");
-    println!("Version: {}", gnos::VERSION);
-    println!("Magic: 0x{:08X}", gnos::GNOS_MAGIC);
-    println!();
-    println!("🎯 Mission: Transform infrastructure complexity into file simplicity");
-    println!("🚀 Impact: 10x faster cloud/AI/edge development");
+async fn run_metadata_bench(threads: usize) -> Result<(), Box<dyn std::error::Error>> {
+    use std::collections::HashMap;
+    use std::sync::RwLock;
+    use std::time::Instant;
+    use gnos::InodeManager;
+    
+    const ENTRIES: u64 = 100_000;
+    const LOOKUPS_PER_THREAD: u64 = 200_000;
+    
     println!();
-    println!("📖 Documentation: https://github.com/gnos-os/rust-core");
-    println!("🐛 Issues: https://github.com/gnos-os/rust-core/issues");
+    println!("⏱️  GNOS metadata benchmark ({} inodes, {} threads)", ENTRIES, threads);
+    
+    let paths: Vec<PathBuf> = (0..ENTRIES)
+        .map(|i| PathBuf::from(format!("/cloud/aws/s3/bench/object-{}", i)))
+        .collect();
+    
+    // Baseline: the previous layout, one RwLock per map
+    let global_inodes: RwLock<HashMap<u64, PathBuf>> = RwLock::new(HashMap::new());
+    let global_paths: RwLock<HashMap<PathBuf, u64>> = RwLock::new(HashMap::new());
+    for (ino, path) in paths.iter().enumerate() {
+        global_inodes.write().unwrap().insert(ino as u64, path.clone());
+        global_paths.write().unwrap().insert(path.clone(), ino as u64);
+    }
+    
+    let sharded = InodeManager::new();
+    for (ino, path) in paths.iter().enumerate() {
+        sharded.create_file(ino as u64 + 1000, path.clone());
+    }
+    
+    // Each lookup resolves a path and then fetches its inode, like FUSE lookup + getattr.
+    // Every table gets an untimed pass first, so both are timed warm
+    let run = |lookup: &(dyn Fn(&Path) + Sync)| {
+        let start = Instant::now();
+        std::thread::scope(|scope| {
+            for t in 0..threads as u64 {
+                let paths = &paths;
+                scope.spawn(move || {
+                    for i in 0..LOOKUPS_PER_THREAD {
+                        lookup(&paths[((i * 7919 + t) % ENTRIES) as usize]);
+                    }
+                });
+            }
+        });
+        start.elapsed()
+    };
+    let global_lookup = |path: &Path| {
+        let ino = global_paths.read().unwrap().get(path).copied();
+        std::hint::black_box(ino.and_then(|ino| global_inodes.read().unwrap().get(&ino).cloned()));
+    };
+    let sharded_lookup = |path: &Path| {
+        std::hint::black_box(sharded.find_by_path(path).and_then(|ino| sharded.get(ino)));
+    };
+    run(&global_lookup);
+    let global = run(&global_lookup);
+    run(&sharded_lookup);
+    let sharded_elapsed = run(&sharded_lookup);
+    
+    let total_ops = (LOOKUPS_PER_THREAD * threads as u64) as f64;
+    println!("┌─────────────────┬──────────────┬────────────────┐");
+    println!("│ Inode table     │ Time         │ Lookups/s      │");
+    println!("├─────────────────┼──────────────┼────────────────┤");
+    println!("│ Global RwLocks  │ {:>9.2} ms │ {:>14.0} │", global.as_secs_f64() * 1000.0, total_ops / global.as_secs_f64());
+    println!("│ Sharded         │ {:>9.2} ms │ {:>14.0} │", sharded_elapsed.as_secs_f64() * 1000.0, total_ops / sharded_elapsed.as_secs_f64());
+    println!("└─────────────────┴──────────────┴────────────────┘");
+    
+    Ok(())
+}
+
+async fn warm_namespace(
+    prefix: PathBuf,
+    mount_point: PathBuf,
+    depth: usize,
+    concurrency: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let root = mount_point.join(prefix.strip_prefix("/").unwrap_or(&prefix));
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory under a GNOS mount", root.display()).into());
+    }
+    
+    println!("🔥 Warming {} ({} levels, {} at a time)", root.display(), depth, concurrency);
+    let started = Instant::now();
+    
+    // Listing and stat-ing through the mount primes the daemon's inode and
+    // attr caches as well as the kernel's dentry cache
+    let mut level = vec![root];
+    let mut directories = 0usize;
+    let mut entries = 0usize;
+    
+    for current in 0..=depth {
+        if level.is_empty() {
+            break;
+        }
+        
+        let results: Vec<_> = stream::iter(std::mem::take(&mut level))
+            .map(|dir| tokio::task::spawn_blocking(move || walk_dir(&dir)))
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        
+        for result in results {
+            match result? {
+                Ok((count, subdirs)) => {
+                    directories += 1;
+                    entries += count;
+                    if current < depth {
+                        level.extend(subdirs);
+                    }
+                }
+                Err(e) => warn!("❌ {}", e),
+            }
+        }
+    }
+    
+    println!("✅ {} directories, {} entries in {:?}", directories, entries, started.elapsed());
+    Ok(())
+}
+
+/// Stat every entry of one mounted directory, returning the entry count
+/// and the subdirectories to descend into
+fn walk_dir(dir: &Path) -> std::io::Result<(usize, Vec<PathBuf>)> {
+    let mut count = 0;
+    let mut subdirs = Vec::new();
+    
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        count += 1;
+        if entry.metadata()?.is_dir() {
+            subdirs.push(entry.path());
+        }
+    }
+    
+    Ok((count, subdirs))
+}
+
+async fn show_stats(mount_point: PathBuf, costs: bool, output: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let name = if costs { "costs" } else { "metrics" };
+    let path = mount_point.join("proc/gnos").join(name);
+    let report = match tokio::fs::read_to_string(&path).await {
+        Ok(report) => report,
+        Err(e) if costs && e.kind() == std::io::ErrorKind::NotFound && mount_point.join("proc/gnos").is_dir() => {
+            return Err(format!("{} is missing; set enabled = true under [costs] and remount", path.display()).into());
+        }
+        Err(e) => return Err(format!("{}: {} (is GNOS mounted at {}?)", path.display(), e, mount_point.display()).into()),
+    };
+    
+    if output.is_table() {
+        print!("{}", report);
+    } else if costs {
+        output.emit("gnos.costs/v1", &CostsOutput::parse(&report), |_| ())?;
+    } else {
+        output.emit("gnos.metrics/v1", &MetricsOutput::parse(&report), |_| ())?;
+    }
+    Ok(())
+}
+
+/// Read the mount's span profile before and after `duration` and write
+/// what accrued in between
+async fn profile(mount_point: PathBuf, duration: &str, output: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    let duration = parse_ttl(duration)?;
+    let before = read_profile(&mount_point).await?;
+    println!("🔬 Profiling {} for {}s...", mount_point.display(), duration.as_secs());
+    tokio::time::sleep(duration).await;
+    let folded = telemetry::folded_since(&before, &read_profile(&mount_point).await?);
+    if folded.is_empty() {
+        return Err(format!("no calls went through {} while profiling", mount_point.display()).into());
+    }
+    
+    let rendered = if output.extension().is_some_and(|extension| extension == "svg") {
+        let mut options = inferno::flamegraph::Options::default();
+        options.title = format!("gnos {}", mount_point.display());
+        options.count_name = "μs".to_string();
+        let mut svg = Vec::new();
+        inferno::flamegraph::from_lines(&mut options, folded.lines(), &mut svg)?;
+        svg
+    } else {
+        folded.clone().into_bytes()
+    };
+    tokio::fs::write(&output, rendered).await?;
+    println!("📝 Wrote {} stacks to {}", folded.lines().count(), output.display());
+    Ok(())
+}
+
+async fn read_profile(mount_point: &Path) -> Result<String, String> {
+    let path = mount_point.join("proc/gnos/profile");
+    match tokio::fs::read_to_string(&path).await {
+        Ok(folded) => Ok(folded),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && mount_point.join("proc/gnos").is_dir() => {
+            Err(format!("{} is missing; set profiling = true under [telemetry] and remount", path.display()))
+        }
+        Err(e) => Err(format!("{}: {} (is GNOS mounted at {}?)", path.display(), e, mount_point.display())),
+    }
+}
+
+async fn show_info(output: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let info = InfoOutput {
+        version: gnos::VERSION,
+        magic: format!("0x{:08X}", gnos::GNOS_MAGIC),
+        documentation: "https://github.com/gnos-os/rust-core",
+        issues: "https://github.com/gnos-os/rust-core/issues",
+    };
+    
+    output.emit("gnos.info/v1", &info, |info| {
+        println!("🌟 GNOS - GlobalNamespace OS");
+        println!("Version: {}", info.version);
+        println!("Magic: {}", info.magic);
+        println!();
+        println!("🎯 Mission: Transform infrastructure complexity into file simplicity");
+        println!("🚀 Impact: 10x faster cloud/AI/edge development");
+        println!();
+        println!("📖 Documentation: {}", info.documentation);
+        println!("🐛 Issues: {}", info.issues);
+    })?;
     
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,149 @@
+//! Content type detection
+//!
+//! Writes are typed from their leading bytes first and their extension
+//! second, so an object uploaded through the mount gets the Content-Type
+//! its content calls for even when its name says nothing. Container
+//! formats that only their name tells apart (a `.docx` is a zip) take the
+//! extension's type. Types reported by backends are shown as the
+//! `user.mime_type` xattr, falling back to the extension.
+
+use std::path::Path;
+
+/// Extended attribute with a file's MIME type, as shared-mime-info reads it
+pub const MIME_XATTR: &str = "user.mime_type";
+
+/// Type of anything that looks neither like text nor like a known format
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Leading bytes inspected when deciding whether data is text
+const TEXT_SNIFF_LEN: usize = 8192;
+
+/// Formats whose signature is shared with others, so the extension decides
+const CONTAINERS: &[&str] = &["application/zip", "application/xml"];
+
+/// MIME type of `data` written to `path`
+pub fn detect(path: &Path, data: &[u8]) -> &'static str {
+    match (from_magic(data), from_extension(path)) {
+        (Some(magic), Some(extension)) if CONTAINERS.contains(&magic) => extension,
+        (Some(magic), _) => magic,
+        (None, Some(extension)) => extension,
+        (None, None) if is_text(data) => "text/plain",
+        (None, None) => OCTET_STREAM,
+    }
+}
+
+/// MIME type from a format signature at the start of `data`
+pub fn from_magic(data: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"PK\x05\x06", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (b"BZh", "application/x-bzip2"),
+        (b"\xfd7zXZ\x00", "application/x-xz"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"\x7fELF", "application/x-elf"),
+        (b"\x00asm", "application/wasm"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"ID3", "audio/mpeg"),
+        (b"PAR1", "application/vnd.apache.parquet"),
+        (b"SQLite format 3\x00", "application/vnd.sqlite3"),
+        (b"%!PS", "application/postscript"),
+        (b"{\\rtf", "application/rtf"),
+        (b"<?xml", "application/xml"),
+    ];
+    
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(signature, _)| data.starts_with(signature)) {
+        return Some(mime);
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" {
+        match &data[8..12] {
+            b"WEBP" => return Some("image/webp"),
+            b"WAVE" => return Some("audio/wav"),
+            _ => {}
+        }
+    }
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return Some(if &data[8..12] == b"qt  " { "video/quicktime" } else { "video/mp4" });
+    }
+    if data.get(257..262) == Some(&b"ustar"[..]) {
+        return Some("application/x-tar");
+    }
+    
+    let head = &data[..data.len().min(64)];
+    let head = String::from_utf8_lossy(head).trim_start().to_ascii_lowercase();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return Some("text/html");
+    }
+    None
+}
+
+/// MIME type conventionally given to `path`'s extension
+pub fn from_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "csv" => "text/csv",
+        "tsv" => "text/tab-separated-values",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "py" => "text/x-python",
+        "rs" => "text/x-rust",
+        "sh" => "application/x-sh",
+        "json" => "application/json",
+        "jsonl" | "ndjson" => "application/x-ndjson",
+        "yaml" | "yml" => "application/yaml",
+        "toml" => "application/toml",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "zst" => "application/zstd",
+        "bz2" => "application/x-bzip2",
+        "xz" => "application/x-xz",
+        "7z" => "application/x-7z-compressed",
+        "tar" => "application/x-tar",
+        "jar" => "application/java-archive",
+        "epub" => "application/epub+zip",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "odt" => "application/vnd.oasis.opendocument.text",
+        "wasm" => "application/wasm",
+        "parquet" => "application/vnd.apache.parquet",
+        "sqlite" | "db" => "application/vnd.sqlite3",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        _ => return None,
+    })
+}
+
+/// UTF-8 without NUL bytes, allowing a character cut off at the sniff limit
+fn is_text(data: &[u8]) -> bool {
+    let head = &data[..data.len().min(TEXT_SNIFF_LEN)];
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() == TEXT_SNIFF_LEN,
+    }
+}
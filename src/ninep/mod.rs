@@ -0,0 +1,1180 @@
+//! 9P2000.L server
+//!
+//! Shares the namespace with guests that can't run GNOS themselves, such as
+//! QEMU VMs over virtio-9p or WSL2. Requests go through the same `VfsCore`
+//! as the FUSE mount, so capability checks, caching and write-back apply
+//! whichever way a path is reached.
+//!
+//! A client attaches with a capability token: written to the auth fid from
+//! Tauth, or, for clients such as the Linux kernel's that can't
+//! authenticate, given as the uname (`-o uname=gnos....`). Each open,
+//! listing, write, create, removal and rename is then checked against it as
+//! well as against the mount's own credentials, and writes through a fid are
+//! fenced by it, so revoking it stops them.
+
+mod proto;
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::config::NinePConfig;
+use crate::security::Operation;
+use crate::telemetry::RequestId;
+use crate::vfs::core::{self, NodeAttr, OpenFile, VfsCore, ROOT_INODE};
+use crate::vfs::inode::GnosInode;
+use crate::vfs::path::join_name;
+use crate::{GnosError, Result};
+use proto::{Decoder, Encoder, Qid};
+
+/// What a fid refers to
+enum Fid {
+    /// A walked-to file or directory, with I/O state once Tlopen succeeds;
+    /// `root` is the attach point, which `..` doesn't walk above
+    Node { ino: u64, root: u64, open: Option<Box<OpenFile>> },
+    /// The token a client writes after Tauth, taken up by Tattach
+    Auth(Vec<u8>),
+    /// An extended attribute value, read back after Txattrwalk
+    Xattr(Bytes),
+}
+
+type FidRef = Arc<tokio::sync::Mutex<Fid>>;
+
+/// errno a request is answered with in Rlerror
+struct Errno(i32);
+
+impl From<GnosError> for Errno {
+    fn from(error: GnosError) -> Self {
+        Errno(core::errno(&error))
+    }
+}
+
+impl From<io::Error> for Errno {
+    // Only malformed messages reach here; filesystem I/O errors arrive as GnosError
+    fn from(_: io::Error) -> Self {
+        Errno(libc::EPROTO)
+    }
+}
+
+type Reply = std::result::Result<Encoder, Errno>;
+
+pub struct NinePServer {
+    core: VfsCore,
+    listener: TcpListener,
+    msize: u32,
+}
+
+impl NinePServer {
+    /// Bind the listen address now so a bad address fails the mount
+    pub async fn bind(core: VfsCore, config: &NinePConfig) -> Result<Self> {
+        let listener = TcpListener::bind(&config.listen).await?;
+        info!("🧵 Serving 9P2000.L on {}", config.listen);
+        
+        Ok(Self {
+            core,
+            listener,
+            msize: config.msize,
+        })
+    }
+    
+    /// Accept clients until the runtime shuts down, one task per connection
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match self.listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("❌ 9P accept failed: {}", e);
+                        continue;
+                    }
+                };
+                
+                let session = Arc::new(Session::new(self.core.clone(), self.msize));
+                tokio::spawn(async move {
+                    info!("🔗 9P client connected from {}", peer);
+                    match session.run(stream).await {
+                        Ok(()) => info!("👋 9P client {} disconnected", peer),
+                        Err(e) => warn!("❌ 9P session with {} ended: {}", peer, e),
+                    }
+                });
+            }
+        })
+    }
+}
+
+/// One client connection
+struct Session {
+    core: VfsCore,
+    max_msize: u32,
+    msize: AtomicU32,
+    fids: Mutex<HashMap<u32, FidRef>>,
+    /// Requests still being served, by tag, so Tflush can cancel them
+    inflight: Mutex<HashMap<u16, (RequestId, AbortHandle)>>,
+    /// uid the client attached as, reported as every file's owner
+    owner: AtomicU32,
+    /// Capability the client attached with
+    token: Mutex<Option<String>>,
+}
+
+/// Owner reported to clients that attach without a numeric uid
+const DEFAULT_OWNER: u32 = 1000;
+
+impl Session {
+    fn new(core: VfsCore, max_msize: u32) -> Self {
+        Self {
+            core,
+            max_msize,
+            msize: AtomicU32::new(max_msize),
+            fids: Mutex::new(HashMap::new()),
+            inflight: Mutex::new(HashMap::new()),
+            owner: AtomicU32::new(DEFAULT_OWNER),
+            token: Mutex::new(None),
+        }
+    }
+    
+    async fn run(self: Arc<Self>, stream: TcpStream) -> Result<()> {
+        stream.set_nodelay(true)?;
+        let (mut reader, mut writer) = stream.into_split();
+        
+        // Replies are written by one task so concurrent requests can't interleave frames
+        let (replies, mut outgoing) = mpsc::unbounded_channel::<Bytes>();
+        tokio::spawn(async move {
+            while let Some(frame) = outgoing.recv().await {
+                if writer.write_all(&frame).await.is_err() {
+                    break;
+                }
+            }
+        });
+        
+        let result = loop {
+            let message = match self.read_message(&mut reader).await {
+                Ok(Some(message)) => message,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            
+            let kind = message[0];
+            let tag = u16::from_le_bytes([message[1], message[2]]);
+            let body = message[3..].to_vec();
+            
+            match kind {
+                // Both change session state, so they're answered in arrival order
+                proto::TVERSION => {
+                    let _ = replies.send(self.version(&body).frame(tag));
+                }
+                proto::TFLUSH => self.flush(&body, tag, &replies),
+                _ => self.spawn_request(kind, tag, body, replies.clone()),
+            }
+        };
+        
+        self.clunk_all().await;
+        result
+    }
+    
+    /// Next message without its size prefix, or None at end of stream
+    async fn read_message(&self, reader: &mut OwnedReadHalf) -> Result<Option<Vec<u8>>> {
+        let size = match reader.read_u32_le().await {
+            Ok(size) => size as usize,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        
+        if size < proto::HEADER_LEN || size > self.msize.load(Ordering::Relaxed) as usize {
+            return Err(GnosError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("9P message of {} bytes", size),
+            )));
+        }
+        
+        let mut message = vec![0; size - 4];
+        reader.read_exact(&mut message).await?;
+        Ok(Some(message))
+    }
+    
+    fn spawn_request(self: &Arc<Self>, kind: u8, tag: u16, body: Vec<u8>, replies: mpsc::UnboundedSender<Bytes>) {
+        let session = self.clone();
+        let id = RequestId::next();
+        let span = info_span!("ninep.request", request_id = %id, op = proto::message_name(kind), tag = tag);
+        
+        // Held across spawn so the task can't finish before it is registered
+        let mut inflight = self.inflight.lock().unwrap();
+        let task = tokio::spawn(id.scope(async move {
+            let reply = match session.handle(kind, &body).await {
+                Ok(reply) => reply,
+                Err(Errno(errno)) => {
+                    debug!("9P {} failed with errno {}", proto::message_name(kind), errno);
+                    Encoder::error(errno)
+                }
+            };
+            
+            // A flushed request must not answer; the tag may already be reused
+            let mut inflight = session.inflight.lock().unwrap();
+            if inflight.get(&tag).is_some_and(|(owner, _)| *owner == id) {
+                inflight.remove(&tag);
+                let _ = replies.send(reply.frame(tag));
+            }
+        }.instrument(span)));
+        inflight.insert(tag, (id, task.abort_handle()));
+    }
+    
+    async fn handle(&self, kind: u8, body: &[u8]) -> Reply {
+        let mut request = Decoder::new(body);
+        
+        match kind {
+            proto::TAUTH => self.auth(&mut request),
+            proto::TATTACH => self.attach(&mut request).await,
+            proto::TWALK => self.walk(&mut request).await,
+            proto::TGETATTR => self.getattr(&mut request).await,
+            proto::TSETATTR => self.setattr(&mut request).await,
+            proto::TLOPEN => self.lopen(&mut request).await,
+            proto::TLCREATE => self.lcreate(&mut request).await,
+            proto::TMKDIR => self.mkdir(&mut request).await,
+            proto::TUNLINKAT => self.unlinkat(&mut request).await,
+            proto::TREMOVE => self.remove(&mut request).await,
+            proto::TRENAME => self.rename(&mut request).await,
+            proto::TRENAMEAT => self.renameat(&mut request).await,
+            proto::TREADDIR => self.readdir(&mut request).await,
+            proto::TREADLINK => self.readlink(&mut request).await,
+            proto::TREAD => self.read(&mut request).await,
+            proto::TWRITE => self.write(&mut request).await,
+            proto::TCLUNK => self.clunk(&mut request).await,
+            proto::TFSYNC => self.fsync(&mut request).await,
+            proto::TXATTRWALK => self.xattrwalk(&mut request).await,
+            proto::TSTATFS => self.statfs(&mut request).await,
+            _ => Err(Errno(libc::ENOSYS)),
+        }
+    }
+    
+    fn version(&self, body: &[u8]) -> Encoder {
+        let mut request = Decoder::new(body);
+        let (Ok(msize), Ok(version)) = (request.u32(), request.string()) else {
+            return Encoder::error(libc::EPROTO);
+        };
+        
+        let msize = msize.min(self.max_msize);
+        self.msize.store(msize, Ordering::Relaxed);
+        
+        // A new version starts a new session
+        self.fids.lock().unwrap().clear();
+        *self.token.lock().unwrap() = None;
+        
+        let mut reply = Encoder::reply(proto::TVERSION);
+        reply.u32(msize).string(if version == proto::VERSION { proto::VERSION } else { "unknown" });
+        reply
+    }
+    
+    fn flush(&self, body: &[u8], tag: u16, replies: &mpsc::UnboundedSender<Bytes>) {
+        let Ok(old_tag) = Decoder::new(body).u16() else {
+            let _ = replies.send(Encoder::error(libc::EPROTO).frame(tag));
+            return;
+        };
+        
+        // Rflush goes out under the lock, so the flushed request either
+        // answered before it or never will
+        let mut inflight = self.inflight.lock().unwrap();
+        if let Some((_, task)) = inflight.remove(&old_tag) {
+            task.abort();
+        }
+        let _ = replies.send(Encoder::reply(proto::TFLUSH).frame(tag));
+    }
+    
+    /// Open an auth fid for the client to write its token to
+    fn auth(&self, request: &mut Decoder<'_>) -> Reply {
+        let afid = request.u32()?;
+        let _uname = request.string()?;
+        let _aname = request.string()?;
+        
+        self.insert_fid(afid, Fid::Auth(Vec::new()))?;
+        let mut reply = Encoder::reply(proto::TAUTH);
+        reply.qid(Qid { kind: proto::QID_AUTH, version: 0, path: afid as u64 });
+        Ok(reply)
+    }
+    
+    async fn attach(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let afid = request.u32()?;
+        let uname = request.string()?;
+        let aname = request.string()?;
+        // 9P2000.L appends the numeric uid; older clients leave it out
+        if let Some(uid) = request.u32().ok().filter(|uid| *uid != proto::NONUNAME) {
+            self.owner.store(uid, Ordering::Relaxed);
+        }
+        
+        let token = if afid != proto::NOFID {
+            let auth = self.fids.lock().unwrap().remove(&afid).ok_or(Errno(libc::EBADF))?;
+            let Fid::Auth(written) = &*auth.lock().await else {
+                return Err(Errno(libc::EINVAL));
+            };
+            String::from_utf8_lossy(written).trim().to_string()
+        } else if uname.starts_with("gnos.") {
+            uname
+        } else {
+            warn!("🧵 9P attach as {} refused: no capability token", uname);
+            return Err(Errno(libc::EACCES));
+        };
+        
+        // aname selects the subtree to export, e.g. `-o aname=/cloud`
+        let ino = if aname.is_empty() || aname == "/" {
+            ROOT_INODE
+        } else {
+            self.core.resolve(Path::new(&aname)).ok_or(Errno(libc::ENOENT))?
+        };
+        let attr = self.core.stat(ino).await?;
+        self.core.capability_manager.check_token(Some(&token), &attr.inode.path, Operation::List).await?;
+        
+        self.insert_fid(fid, Fid::Node { ino, root: ino, open: None })?;
+        // The uname may be the token, so the log names its owner instead
+        info!("🧵 9P attach by {} at {}", self.core.capability_manager.principal(Some(&token)), attr.inode.path.display());
+        *self.token.lock().unwrap() = Some(token);
+        
+        let mut reply = Encoder::reply(proto::TATTACH);
+        reply.qid(qid(&attr.inode));
+        Ok(reply)
+    }
+    
+    async fn walk(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let new_fid = request.u32()?;
+        let count = request.u16()?;
+        let names = (0..count).map(|_| request.string()).collect::<io::Result<Vec<_>>>()?;
+        
+        let (mut current, root) = match &*self.fid(fid)?.lock().await {
+            Fid::Node { ino, root, .. } => (*ino, *root),
+            _ => return Err(Errno(libc::EINVAL)),
+        };
+        let mut qids = Vec::with_capacity(names.len());
+        for name in &names {
+            let next = if name == ".." {
+                // The attach point is the client's `/`
+                Some(if current == root { root } else { self.core.parent(current) })
+            } else {
+                self.core.lookup(current, OsStr::new(name)).await
+            };
+            let Some(next) = next else {
+                break;
+            };
+            
+            qids.push(qid(&self.core.stat(next).await?.inode));
+            current = next;
+        }
+        
+        // A walk that fails at the first name is an error; a later failure
+        // returns the qids so far and leaves new_fid unset
+        if qids.is_empty() && !names.is_empty() {
+            return Err(Errno(libc::ENOENT));
+        }
+        if qids.len() == names.len() {
+            if new_fid == fid {
+                self.fids.lock().unwrap().remove(&fid);
+            }
+            self.insert_fid(new_fid, Fid::Node { ino: current, root, open: None })?;
+        }
+        
+        let mut reply = Encoder::reply(proto::TWALK);
+        reply.u16(qids.len() as u16);
+        for qid in qids {
+            reply.qid(qid);
+        }
+        Ok(reply)
+    }
+    
+    async fn getattr(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let _request_mask = request.u64()?;
+        
+        let attr = self.core.stat(self.node(fid).await?).await?;
+        let NodeAttr { inode, size, mtime, perm, .. } = &attr;
+        // Attached users are assumed to have a group of their own
+        let owner = self.owner.load(Ordering::Relaxed);
+        let file_type = if inode.symlink.is_some() {
+            libc::S_IFLNK
+        } else if inode.is_dir {
+            libc::S_IFDIR
+        } else {
+            libc::S_IFREG
+        };
+        
+        let mut reply = Encoder::reply(proto::TGETATTR);
+        reply
+            .u64(proto::GETATTR_BASIC)
+            .qid(qid(&attr.inode))
+            .u32(file_type | *perm as u32)
+            .u32(owner)
+            .u32(owner)
+            .u64(if inode.is_dir { 2 } else { 1 })
+            .u64(0)
+            .u64(*size)
+            .u64(4096)
+            .u64(size.div_ceil(512));
+        timestamp(&mut reply, SystemTime::now());
+        timestamp(&mut reply, *mtime);
+        timestamp(&mut reply, inode.ctime);
+        timestamp(&mut reply, inode.crtime);
+        // gen and data_version are unused by 9P2000.L clients
+        reply.u64(0).u64(0);
+        Ok(reply)
+    }
+    
+    async fn setattr(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let valid = request.u32()?;
+        let _mode = request.u32()?;
+        let _uid = request.u32()?;
+        let _gid = request.u32()?;
+        let size = request.u64()?;
+        
+        // Modes and owners are what capabilities make them, so they can't be
+        // changed; time changes are accepted and dropped, as backends keep
+        // their own
+        if valid & (proto::SETATTR_MODE | proto::SETATTR_UID | proto::SETATTR_GID) != 0 {
+            return Err(Errno(libc::EPERM));
+        }
+        if valid & proto::SETATTR_SIZE == 0 {
+            return Ok(Encoder::reply(proto::TSETATTR));
+        }
+        
+        let fid = self.fid(fid)?;
+        let mut state = fid.lock().await;
+        let Fid::Node { ino, open, .. } = &mut *state else {
+            return Err(Errno(libc::EINVAL));
+        };
+        match open {
+            // The kernel sends O_TRUNC this way
+            Some(file) => {
+                self.authorize(&file.path, Operation::Write).await?;
+                file.truncate(size)?;
+            }
+            // truncate(2) by path; only emptying a file needs no contents
+            None if size == 0 => {
+                let path = self.core.inode(*ino).ok_or(Errno(libc::ENOENT))?.path;
+                self.authorize(&path, Operation::Write).await?;
+                let mut file = self.core.open(*ino, true).await?;
+                file.fence_with(&self.attached()?);
+                file.truncate(0)?;
+                self.core.commit(&mut file).await?;
+            }
+            None => return Err(Errno(libc::ENOSYS)),
+        }
+        
+        Ok(Encoder::reply(proto::TSETATTR))
+    }
+    
+    async fn lopen(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let flags = request.u32()? as i32;
+        
+        let fid = self.fid(fid)?;
+        let mut state = fid.lock().await;
+        let Fid::Node { ino, open, .. } = &mut *state else {
+            return Err(Errno(libc::EINVAL));
+        };
+        
+        // Directories need no handle; Treaddir checks list permission itself
+        let attr = self.core.stat(*ino).await?;
+        if !attr.inode.is_dir {
+            let write = flags & libc::O_ACCMODE != libc::O_RDONLY;
+            self.authorize(&attr.inode.path, if write { Operation::Write } else { Operation::Read }).await?;
+            let mut file = self.core.open(*ino, write).await?;
+            if write {
+                file.fence_with(&self.attached()?);
+                if flags & libc::O_TRUNC != 0 {
+                    file.truncate(0)?;
+                }
+            }
+            *open = Some(Box::new(file));
+        }
+        
+        let mut reply = Encoder::reply(proto::TLOPEN);
+        reply.qid(qid(&attr.inode)).u32(self.iounit());
+        Ok(reply)
+    }
+    
+    /// Create a file in a directory fid and open it for writing; the fid
+    /// then refers to the new file
+    async fn lcreate(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let name = request.string()?;
+        let _flags = request.u32()?;
+        let _mode = request.u32()?;
+        let _gid = request.u32()?;
+        
+        let fid = self.fid(fid)?;
+        let mut state = fid.lock().await;
+        let Fid::Node { ino, open: open @ None, .. } = &mut *state else {
+            return Err(Errno(libc::EINVAL));
+        };
+        self.authorize_child(*ino, OsStr::new(&name)).await?;
+        let (created, mut file) = self.core.create(*ino, OsStr::new(&name)).await?;
+        file.fence_with(&self.attached()?);
+        *ino = created;
+        *open = Some(Box::new(file));
+        
+        let mut reply = Encoder::reply(proto::TLCREATE);
+        reply.qid(qid(&self.core.inode(created).ok_or(Errno(libc::ENOENT))?)).u32(self.iounit());
+        Ok(reply)
+    }
+    
+    async fn mkdir(&self, request: &mut Decoder<'_>) -> Reply {
+        let dfid = request.u32()?;
+        let name = request.string()?;
+        let _mode = request.u32()?;
+        let _gid = request.u32()?;
+        
+        let dir = self.node(dfid).await?;
+        self.authorize_child(dir, OsStr::new(&name)).await?;
+        let ino = self.core.mkdir(dir, OsStr::new(&name)).await?;
+        
+        let mut reply = Encoder::reply(proto::TMKDIR);
+        reply.qid(qid(&self.core.inode(ino).ok_or(Errno(libc::ENOENT))?));
+        Ok(reply)
+    }
+    
+    async fn unlinkat(&self, request: &mut Decoder<'_>) -> Reply {
+        let dfid = request.u32()?;
+        let name = request.string()?;
+        let flags = request.u32()? as i32;
+        
+        let dir = self.node(dfid).await?;
+        self.authorize_child(dir, OsStr::new(&name)).await?;
+        let ino = self.core.lookup(dir, OsStr::new(&name)).await.ok_or(Errno(libc::ENOENT))?;
+        match (flags & libc::AT_REMOVEDIR != 0, self.core.stat(ino).await?.inode.is_dir) {
+            (true, false) => return Err(Errno(libc::ENOTDIR)),
+            (false, true) => return Err(Errno(libc::EISDIR)),
+            _ => {}
+        }
+        self.core.remove(dir, OsStr::new(&name), None).await?;
+        
+        Ok(Encoder::reply(proto::TUNLINKAT))
+    }
+    
+    /// Remove what a fid refers to; the fid is released even if that fails
+    async fn remove(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        
+        let fid = self.fids.lock().unwrap().remove(&fid).ok_or(Errno(libc::EBADF))?;
+        let Fid::Node { ino, .. } = &*fid.lock().await else {
+            return Err(Errno(libc::EINVAL));
+        };
+        let path = self.core.inode(*ino).ok_or(Errno(libc::ENOENT))?.path;
+        let name = path.file_name().ok_or(Errno(libc::EBUSY))?;
+        self.authorize(&path, Operation::Write).await?;
+        self.core.remove(self.core.parent(*ino), name, None).await?;
+        
+        Ok(Encoder::reply(proto::TREMOVE))
+    }
+    
+    /// Move the file a fid refers to into another directory fid; the fid
+    /// follows it
+    async fn rename(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let dfid = request.u32()?;
+        let name = request.string()?;
+        
+        let new_dir = self.node(dfid).await?;
+        let fid = self.fid(fid)?;
+        let mut state = fid.lock().await;
+        let Fid::Node { ino, .. } = &mut *state else {
+            return Err(Errno(libc::EINVAL));
+        };
+        let path = self.core.inode(*ino).ok_or(Errno(libc::ENOENT))?.path;
+        let old_name = path.file_name().ok_or(Errno(libc::EBUSY))?;
+        *ino = self.move_entry(self.core.parent(*ino), old_name, new_dir, OsStr::new(&name)).await?;
+        
+        Ok(Encoder::reply(proto::TRENAME))
+    }
+    
+    async fn renameat(&self, request: &mut Decoder<'_>) -> Reply {
+        let old_dir = self.node(request.u32()?).await?;
+        let old_name = request.string()?;
+        let new_dir = self.node(request.u32()?).await?;
+        let new_name = request.string()?;
+        
+        self.move_entry(old_dir, OsStr::new(&old_name), new_dir, OsStr::new(&new_name)).await?;
+        Ok(Encoder::reply(proto::TRENAMEAT))
+    }
+    
+    async fn readdir(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let offset = request.u64()?;
+        let count = request.u32()?.min(self.iounit()) as usize;
+        
+        let ino = self.node(fid).await?;
+        self.authorize(&self.core.inode(ino).ok_or(Errno(libc::ENOENT))?.path, Operation::List).await?;
+        let entries = self.core.list(ino, offset == 0).await?;
+        
+        let mut dirents = Encoder::buffer();
+        for (i, entry) in entries.iter().enumerate().skip(offset as usize) {
+            let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+            // qid + offset + type + name
+            if dirents.len() + 13 + 8 + 1 + 2 + name.len() > count {
+                break;
+            }
+            
+            dirents
+                .qid(qid(entry))
+                .u64((i + 1) as u64)
+                .u8(dirent_type(entry))
+                .string(&name);
+        }
+        
+        let mut reply = Encoder::reply(proto::TREADDIR);
+        reply.data(dirents.as_bytes());
+        Ok(reply)
+    }
+    
+    async fn read(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let offset = request.u64()?;
+        let count = request.u32()?.min(self.iounit());
+        
+        let data = match &mut *self.fid(fid)?.lock().await {
+            Fid::Node { open: Some(file), .. } => self.core.read(file, offset, count).await?,
+            Fid::Node { open: None, .. } | Fid::Auth(_) => return Err(Errno(libc::EBADF)),
+            Fid::Xattr(value) => {
+                let start = std::cmp::min(offset as usize, value.len());
+                let end = std::cmp::min(start + count as usize, value.len());
+                value.slice(start..end)
+            }
+        };
+        
+        let mut reply = Encoder::reply(proto::TREAD);
+        reply.data(&data);
+        Ok(reply)
+    }
+    
+    async fn write(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let offset = request.u64()?;
+        let data = request.data()?;
+        
+        let fid = self.fid(fid)?;
+        let mut state = fid.lock().await;
+        if let Fid::Auth(token) = &mut *state {
+            token.extend_from_slice(data);
+            let mut reply = Encoder::reply(proto::TWRITE);
+            reply.u32(data.len() as u32);
+            return Ok(reply);
+        }
+        let Fid::Node { open: Some(file), .. } = &mut *state else {
+            return Err(Errno(libc::EBADF));
+        };
+        // The token may have lost the grant since the fid was opened
+        self.authorize(&file.path, Operation::Write).await?;
+        self.core.check_writable(file)?;
+        self.core.check_quota(&file.path, std::cmp::max(file.buffered_len(), offset + data.len() as u64))?;
+        self.core.write(file, offset, data).await?;
+        
+        let mut reply = Encoder::reply(proto::TWRITE);
+        reply.u32(data.len() as u32);
+        Ok(reply)
+    }
+    
+    /// Forget a fid, committing anything written through it
+    async fn clunk(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        
+        // The fid is released even if the commit fails
+        let fid = self.fids.lock().unwrap().remove(&fid).ok_or(Errno(libc::EBADF))?;
+        if let Fid::Node { open: Some(file), .. } = &mut *fid.lock().await {
+            self.core.commit(file).await?;
+        }
+        
+        Ok(Encoder::reply(proto::TCLUNK))
+    }
+    
+    async fn readlink(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        
+        let inode = self.core.inode(self.node(fid).await?).ok_or(Errno(libc::ENOENT))?;
+        let target = inode.symlink.ok_or(Errno(libc::EINVAL))?;
+        
+        let mut reply = Encoder::reply(proto::TREADLINK);
+        reply.string(&target.to_string_lossy());
+        Ok(reply)
+    }
+    
+    async fn fsync(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let _datasync = request.u32()?;
+        
+        // In write-back mode fsync means "uploaded", not just "journaled"
+        if let Fid::Node { open: Some(file), .. } = &mut *self.fid(fid)?.lock().await {
+            self.core.commit(file).await?;
+            self.core.sync(&file.path).await?;
+        }
+        
+        Ok(Encoder::reply(proto::TFSYNC))
+    }
+    
+    async fn xattrwalk(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let new_fid = request.u32()?;
+        let name = request.string()?;
+        
+        let inode = self.core.inode(self.node(fid).await?).ok_or(Errno(libc::ENOENT))?;
+        
+        // An empty name lists the attribute names, NUL-terminated
+        let value = if name.is_empty() {
+            let mut names = Vec::new();
+            for name in self.core.xattr_names() {
+                names.extend_from_slice(name.as_bytes());
+                names.push(0);
+            }
+            Bytes::from(names)
+        } else {
+            let value = self.core.xattr(&inode.path, OsStr::new(&name)).ok_or(Errno(libc::ENODATA))?;
+            Bytes::from(value)
+        };
+        
+        let size = value.len() as u64;
+        self.insert_fid(new_fid, Fid::Xattr(value))?;
+        
+        let mut reply = Encoder::reply(proto::TXATTRWALK);
+        reply.u64(size);
+        Ok(reply)
+    }
+    
+    /// Capacity of the innermost quota covering the fid's node
+    async fn statfs(&self, request: &mut Decoder<'_>) -> Reply {
+        let fid = request.u32()?;
+        let stats = self.core.statfs(self.node(fid).await?);
+        
+        let mut reply = Encoder::reply(proto::TSTATFS);
+        reply
+            .u32(proto::V9FS_MAGIC)
+            .u32(stats.block_size)
+            .u64(stats.blocks)
+            .u64(stats.blocks_free)
+            .u64(stats.blocks_free)
+            .u64(stats.files)
+            .u64(stats.files_free)
+            .u64(crate::GNOS_MAGIC)
+            .u32(255);
+        Ok(reply)
+    }
+    
+    fn fid(&self, fid: u32) -> std::result::Result<FidRef, Errno> {
+        self.fids.lock().unwrap().get(&fid).cloned().ok_or(Errno(libc::EBADF))
+    }
+    
+    /// Inode a fid was walked to
+    async fn node(&self, fid: u32) -> std::result::Result<u64, Errno> {
+        match &*self.fid(fid)?.lock().await {
+            Fid::Node { ino, .. } => Ok(*ino),
+            Fid::Xattr(_) | Fid::Auth(_) => Err(Errno(libc::EINVAL)),
+        }
+    }
+    
+    /// Check `operation` on `path` against the token the client attached with
+    async fn authorize(&self, path: &Path, operation: Operation) -> std::result::Result<(), Errno> {
+        self.core.capability_manager.check_token(Some(&self.attached()?), path, operation).await?;
+        Ok(())
+    }
+    
+    /// Path of `name` in the directory `dir`, once the attach token may
+    /// write it
+    async fn authorize_child(&self, dir: u64, name: &OsStr) -> std::result::Result<PathBuf, Errno> {
+        let parent = self.core.inode(dir).ok_or(Errno(libc::ENOENT))?;
+        let path = join_name(&parent.path, name)?;
+        self.authorize(&path, Operation::Write).await?;
+        Ok(path)
+    }
+    
+    /// Rename `name` in `dir` to `new_name` in `new_dir`, a write to both
+    /// under the attach token, returning the inode it ends up at
+    async fn move_entry(&self, dir: u64, name: &OsStr, new_dir: u64, new_name: &OsStr) -> std::result::Result<u64, Errno> {
+        self.authorize_child(dir, name).await?;
+        let target = self.authorize_child(new_dir, new_name).await?;
+        self.core.rename(dir, name, new_dir, new_name).await?;
+        self.core.resolve(&target).ok_or(Errno(libc::ENOENT))
+    }
+    
+    /// The token the client attached with
+    fn attached(&self) -> std::result::Result<String, Errno> {
+        self.token.lock().unwrap().clone().ok_or(Errno(libc::EACCES))
+    }
+    
+    fn insert_fid(&self, fid: u32, state: Fid) -> std::result::Result<(), Errno> {
+        let mut fids = self.fids.lock().unwrap();
+        if fids.contains_key(&fid) {
+            return Err(Errno(libc::EBADF));
+        }
+        fids.insert(fid, Arc::new(tokio::sync::Mutex::new(state)));
+        Ok(())
+    }
+    
+    fn iounit(&self) -> u32 {
+        self.msize.load(Ordering::Relaxed).saturating_sub(proto::IO_HEADER_LEN)
+    }
+    
+    /// Commit writes left on fids the client never clunked
+    async fn clunk_all(&self) {
+        let fids: Vec<FidRef> = self.fids.lock().unwrap().drain().map(|(_, fid)| fid).collect();
+        for fid in fids {
+            if let Fid::Node { open: Some(file), .. } = &mut *fid.lock().await {
+                let _ = self.core.commit(file).await;
+            }
+        }
+    }
+}
+
+fn qid(inode: &GnosInode) -> Qid {
+    let mut qid = Qid::new(inode.ino, inode.is_dir);
+    if inode.symlink.is_some() {
+        qid.kind = proto::QID_SYMLINK;
+    }
+    qid
+}
+
+fn dirent_type(inode: &GnosInode) -> u8 {
+    if inode.symlink.is_some() {
+        libc::DT_LNK
+    } else if inode.is_dir {
+        libc::DT_DIR
+    } else {
+        libc::DT_REG
+    }
+}
+
+fn timestamp(reply: &mut Encoder, time: SystemTime) {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    reply.u64(since.as_secs()).u64(since.subsec_nanos() as u64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::time::Duration;
+    
+    use async_trait::async_trait;
+    
+    use crate::config::DriverConfig;
+    use crate::drivers::{DriverRegistry, GnosDriver, ResourceMetadata};
+    use crate::security::{Capability, CapabilityManager, SecurityConfig};
+    
+    const ROOT: &str = "/net/mem";
+    
+    /// Files and directories kept in memory, as the served backend
+    struct MemoryDriver {
+        files: Mutex<HashMap<PathBuf, Bytes>>,
+        dirs: Mutex<HashSet<PathBuf>>,
+    }
+    
+    impl MemoryDriver {
+        fn new() -> Self {
+            Self {
+                files: Mutex::new(HashMap::new()),
+                dirs: Mutex::new(HashSet::from([PathBuf::from(ROOT)])),
+            }
+        }
+        
+        fn file(&self, path: &str) -> Option<Bytes> {
+            self.files.lock().unwrap().get(Path::new(path)).cloned()
+        }
+    }
+    
+    #[async_trait]
+    impl GnosDriver for MemoryDriver {
+        async fn read(&self, path: &Path) -> Result<Bytes> {
+            self.files.lock().unwrap().get(path).cloned()
+                .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))
+        }
+        
+        async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+            self.files.lock().unwrap().insert(path.to_path_buf(), Bytes::copy_from_slice(data));
+            Ok(())
+        }
+        
+        async fn create_dir(&self, path: &Path) -> Result<()> {
+            self.dirs.lock().unwrap().insert(path.to_path_buf());
+            Ok(())
+        }
+        
+        async fn delete(&self, path: &Path) -> Result<()> {
+            let removed = self.files.lock().unwrap().remove(path).is_some() || self.dirs.lock().unwrap().remove(path);
+            removed.then_some(()).ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))
+        }
+        
+        async fn list(&self, path: &Path) -> Result<Vec<String>> {
+            let files = self.files.lock().unwrap().keys().cloned().collect::<Vec<_>>();
+            let dirs = self.dirs.lock().unwrap().iter().cloned().collect::<Vec<_>>();
+            Ok(files.into_iter().chain(dirs)
+                .filter(|child| child.parent() == Some(path))
+                .filter_map(|child| Some(child.file_name()?.to_string_lossy().into_owned()))
+                .collect())
+        }
+        
+        async fn exists(&self, path: &Path) -> Result<bool> {
+            Ok(self.files.lock().unwrap().contains_key(path) || self.dirs.lock().unwrap().contains(path))
+        }
+        
+        async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+            if self.dirs.lock().unwrap().contains(path) {
+                return Ok(ResourceMetadata { is_directory: true, ..ResourceMetadata::default() });
+            }
+            let size = self.read(path).await?.len() as u64;
+            Ok(ResourceMetadata { size, ..ResourceMetadata::default() })
+        }
+        
+        fn name(&self) -> &'static str {
+            "Memory Driver"
+        }
+        
+        fn supports(&self, path: &Path) -> bool {
+            path.starts_with(ROOT)
+        }
+        
+        fn prefixes(&self) -> Vec<PathBuf> {
+            vec![PathBuf::from(ROOT)]
+        }
+    }
+    
+    struct Served {
+        addr: std::net::SocketAddr,
+        capabilities: Arc<CapabilityManager>,
+        driver: Arc<MemoryDriver>,
+        _key_dir: tempfile::TempDir,
+    }
+    
+    impl Served {
+        /// A token for `path` with rwx `permissions`
+        fn token(&self, path: &str, permissions: u8) -> String {
+            self.capabilities.issue(&Capability {
+                path: PathBuf::from(path),
+                permissions,
+                expiration: SystemTime::now() + Duration::from_secs(3600),
+                owner: "ninep-test".to_string(),
+                break_glass: None,
+                issued: Some(SystemTime::now()),
+                not_before: None,
+                max_idle_seconds: None,
+                deny: Vec::new(),
+            }).unwrap()
+        }
+        
+        /// A client that has negotiated the version and attached fid 0 to
+        /// `/net/mem` with `token`
+        async fn attach(&self, token: &str) -> Client {
+            let mut client = Client { stream: TcpStream::connect(self.addr).await.unwrap() };
+            client.call(proto::TVERSION, |body| { body.u32(8192).string(proto::VERSION); }).await.unwrap();
+            client.call(proto::TATTACH, |body| {
+                body.u32(0).u32(proto::NOFID).string(token).string(ROOT).u32(proto::NONUNAME);
+            }).await.unwrap();
+            client
+        }
+    }
+    
+    async fn serve() -> Served {
+        let mut drivers = DriverConfig::default();
+        drivers.ai.enabled = false;
+        drivers.cloud.enabled = false;
+        drivers.http.enabled = false;
+        drivers.sensors.enabled = false;
+        let driver = Arc::new(MemoryDriver::new());
+        let registry = DriverRegistry::new(drivers).await.unwrap().with_driver("memory", driver.clone());
+        let key_dir = tempfile::tempdir().unwrap();
+        let capabilities = Arc::new(CapabilityManager::new(SecurityConfig {
+            signing_key_file: key_dir.path().join("signing.key"),
+            ..SecurityConfig::default()
+        }));
+        let core = VfsCore::new(Arc::new(registry), capabilities.clone());
+        core.inode_manager.get_or_create(Path::new(ROOT), true);
+        
+        let config = NinePConfig { enabled: true, listen: "127.0.0.1:0".to_string(), msize: 8192 };
+        let server = NinePServer::bind(core, &config).await.unwrap();
+        let addr = server.listener.local_addr().unwrap();
+        server.spawn();
+        Served { addr, capabilities, driver, _key_dir: key_dir }
+    }
+    
+    /// One request at a time over a real connection
+    struct Client {
+        stream: TcpStream,
+    }
+    
+    impl Client {
+        /// Send a request of type `kind` and return the reply's body, or
+        /// the errno of an Rlerror
+        async fn call(&mut self, kind: u8, build: impl FnOnce(&mut Encoder)) -> std::result::Result<Vec<u8>, i32> {
+            let mut body = Encoder::buffer();
+            build(&mut body);
+            let mut frame = ((proto::HEADER_LEN + body.len()) as u32).to_le_bytes().to_vec();
+            frame.push(kind);
+            frame.extend_from_slice(&1u16.to_le_bytes());
+            frame.extend_from_slice(body.as_bytes());
+            self.stream.write_all(&frame).await.unwrap();
+            
+            let size = self.stream.read_u32_le().await.unwrap() as usize;
+            let mut reply = vec![0; size - 4];
+            self.stream.read_exact(&mut reply).await.unwrap();
+            match reply[0] {
+                proto::RLERROR => Err(u32::from_le_bytes(reply[3..7].try_into().unwrap()) as i32),
+                answer => {
+                    assert_eq!(answer, kind + 1);
+                    Ok(reply[3..].to_vec())
+                }
+            }
+        }
+        
+        async fn walk(&mut self, fid: u32, new_fid: u32, names: &[&str]) -> std::result::Result<(), i32> {
+            let reply = self.call(proto::TWALK, |body| {
+                body.u32(fid).u32(new_fid).u16(names.len() as u16);
+                for name in names {
+                    body.string(name);
+                }
+            }).await?;
+            assert_eq!(Decoder::new(&reply).u16().unwrap() as usize, names.len());
+            Ok(())
+        }
+        
+        async fn lopen(&mut self, fid: u32, flags: i32) -> std::result::Result<(), i32> {
+            self.call(proto::TLOPEN, |body| { body.u32(fid).u32(flags as u32); }).await.map(drop)
+        }
+        
+        async fn read(&mut self, fid: u32) -> Vec<u8> {
+            let reply = self.call(proto::TREAD, |body| { body.u32(fid).u64(0).u32(4096); }).await.unwrap();
+            Decoder::new(&reply).data().unwrap().to_vec()
+        }
+        
+        async fn write(&mut self, fid: u32, data: &[u8]) -> std::result::Result<(), i32> {
+            let reply = self.call(proto::TWRITE, |body| { body.u32(fid).u64(0).data(data); }).await?;
+            assert_eq!(Decoder::new(&reply).u32().unwrap() as usize, data.len());
+            Ok(())
+        }
+        
+        async fn clunk(&mut self, fid: u32) -> std::result::Result<(), i32> {
+            self.call(proto::TCLUNK, |body| { body.u32(fid); }).await.map(drop)
+        }
+        
+        /// Names in a directory fid, from Treaddir
+        async fn names(&mut self, fid: u32) -> Vec<String> {
+            let reply = self.call(proto::TREADDIR, |body| { body.u32(fid).u64(0).u32(4096); }).await.unwrap();
+            let mut reply = Decoder::new(&reply);
+            let mut dirents = Decoder::new(reply.data().unwrap());
+            let mut names = Vec::new();
+            // qid, offset and type come before each name
+            while dirents.u64().is_ok() {
+                dirents.u32().unwrap();
+                dirents.u64().unwrap();
+                dirents.u16().unwrap();
+                names.push(dirents.string().unwrap());
+            }
+            names.sort();
+            names
+        }
+    }
+    
+    #[tokio::test]
+    async fn attach_walk_read_and_write_round_trip() {
+        let served = serve().await;
+        served.driver.write(Path::new("/net/mem/a"), b"hello").await.unwrap();
+        
+        let mut anonymous = Client { stream: TcpStream::connect(served.addr).await.unwrap() };
+        anonymous.call(proto::TVERSION, |body| { body.u32(8192).string(proto::VERSION); }).await.unwrap();
+        let refused = anonymous.call(proto::TATTACH, |body| {
+            body.u32(0).u32(proto::NOFID).string("nobody").string(ROOT).u32(proto::NONUNAME);
+        }).await;
+        assert_eq!(refused, Err(libc::EACCES));
+        
+        let mut client = served.attach(&served.token(ROOT, 0b110)).await;
+        assert_eq!(client.names(0).await, ["a"]);
+        assert_eq!(client.walk(0, 1, &["missing"]).await, Err(libc::ENOENT));
+        
+        client.walk(0, 1, &["a"]).await.unwrap();
+        client.lopen(1, libc::O_RDONLY).await.unwrap();
+        assert_eq!(client.read(1).await, b"hello");
+        client.clunk(1).await.unwrap();
+        
+        client.walk(0, 2, &["a"]).await.unwrap();
+        client.lopen(2, libc::O_WRONLY | libc::O_TRUNC).await.unwrap();
+        client.write(2, b"bye").await.unwrap();
+        client.clunk(2).await.unwrap();
+        assert_eq!(served.driver.file("/net/mem/a").unwrap(), "bye");
+    }
+    
+    #[tokio::test]
+    async fn files_and_directories_are_created_renamed_and_removed() {
+        let served = serve().await;
+        let mut client = served.attach(&served.token(ROOT, 0b110)).await;
+        
+        client.walk(0, 1, &[]).await.unwrap();
+        client.call(proto::TLCREATE, |body| {
+            body.u32(1).string("b").u32((libc::O_WRONLY | libc::O_CREAT) as u32).u32(0o644).u32(0);
+        }).await.unwrap();
+        client.write(1, b"created").await.unwrap();
+        client.clunk(1).await.unwrap();
+        assert_eq!(served.driver.file("/net/mem/b").unwrap(), "created");
+        
+        client.call(proto::TMKDIR, |body| { body.u32(0).string("sub").u32(0o755).u32(0); }).await.unwrap();
+        assert!(served.driver.dirs.lock().unwrap().contains(Path::new("/net/mem/sub")));
+        
+        client.walk(0, 2, &["sub"]).await.unwrap();
+        client.call(proto::TRENAMEAT, |body| { body.u32(0).string("b").u32(2).string("c"); }).await.unwrap();
+        assert_eq!(served.driver.file("/net/mem/sub/c").unwrap(), "created");
+        assert!(served.driver.file("/net/mem/b").is_none());
+        
+        // Trename moves the fid along with the file
+        client.walk(2, 3, &["c"]).await.unwrap();
+        client.call(proto::TRENAME, |body| { body.u32(3).u32(0).string("d"); }).await.unwrap();
+        assert_eq!(served.driver.file("/net/mem/d").unwrap(), "created");
+        client.lopen(3, libc::O_RDONLY).await.unwrap();
+        assert_eq!(client.read(3).await, b"created");
+        
+        let not_a_dir = client.call(proto::TUNLINKAT, |body| {
+            body.u32(0).string("d").u32(libc::AT_REMOVEDIR as u32);
+        }).await;
+        assert_eq!(not_a_dir, Err(libc::ENOTDIR));
+        client.call(proto::TUNLINKAT, |body| { body.u32(0).string("sub").u32(libc::AT_REMOVEDIR as u32); }).await.unwrap();
+        assert!(!served.driver.dirs.lock().unwrap().contains(Path::new("/net/mem/sub")));
+        
+        client.walk(0, 4, &["d"]).await.unwrap();
+        client.call(proto::TREMOVE, |body| { body.u32(4); }).await.unwrap();
+        assert!(served.driver.files.lock().unwrap().is_empty());
+        // Tremove released the fid
+        assert_eq!(client.clunk(4).await, Err(libc::EBADF));
+    }
+    
+    #[tokio::test]
+    async fn writes_are_held_to_the_attach_token() {
+        let served = serve().await;
+        served.driver.write(Path::new("/net/mem/a"), b"hello").await.unwrap();
+        
+        let mut reader = served.attach(&served.token(ROOT, 0b100)).await;
+        assert_eq!(reader.names(0).await, ["a"]);
+        reader.walk(0, 1, &["a"]).await.unwrap();
+        assert_eq!(reader.lopen(1, libc::O_WRONLY).await, Err(libc::EACCES));
+        let mkdir = reader.call(proto::TMKDIR, |body| { body.u32(0).string("sub").u32(0o755).u32(0); }).await;
+        assert_eq!(mkdir, Err(libc::EACCES));
+        
+        // Modes and owners aren't the client's to change
+        let chmod = reader.call(proto::TSETATTR, |body| {
+            body.u32(1).u32(proto::SETATTR_MODE).u32(0o777).u32(0).u32(0).u64(0);
+            body.u64(0).u64(0).u64(0).u64(0);
+        }).await;
+        assert_eq!(chmod, Err(libc::EPERM));
+        
+        // A revoked token stops writes through fids it already opened
+        let token = served.token(ROOT, 0b110);
+        let mut writer = served.attach(&token).await;
+        writer.walk(0, 1, &["a"]).await.unwrap();
+        writer.lopen(1, libc::O_WRONLY | libc::O_TRUNC).await.unwrap();
+        writer.write(1, b"first").await.unwrap();
+        served.capabilities.revoke(&token).unwrap();
+        assert_eq!(writer.write(1, b"second").await, Err(libc::EACCES));
+        assert_eq!(writer.clunk(1).await, Err(libc::EACCES));
+        assert_eq!(served.driver.file("/net/mem/a").unwrap(), "hello");
+    }
+}
@@ -0,0 +1,232 @@
+//! 9P2000.L wire format
+//!
+//! Every message is `size[4] type[1] tag[2] body`, little-endian, with
+//! strings as `len[2] bytes`.
+
+use std::io;
+
+use bytes::Bytes;
+
+pub const VERSION: &str = "9P2000.L";
+
+/// size + type + tag
+pub const HEADER_LEN: usize = 7;
+/// Header plus the count field of Rread/Twrite
+pub const IO_HEADER_LEN: u32 = 24;
+
+pub const QID_DIR: u8 = 0x80;
+pub const QID_FILE: u8 = 0x00;
+pub const QID_SYMLINK: u8 = 0x02;
+pub const QID_AUTH: u8 = 0x08;
+
+/// `valid` mask for the fields Rgetattr fills in (P9_GETATTR_BASIC)
+pub const GETATTR_BASIC: u64 = 0x0000_07ff;
+/// Tsetattr `valid` bits for a mode, owner or group change
+pub const SETATTR_MODE: u32 = 0x0000_0001;
+pub const SETATTR_UID: u32 = 0x0000_0002;
+pub const SETATTR_GID: u32 = 0x0000_0004;
+/// Tsetattr `valid` bit for a size change
+pub const SETATTR_SIZE: u32 = 0x0000_0008;
+
+pub const V9FS_MAGIC: u32 = 0x0102_1997;
+
+/// Tattach `n_uname` of a client that didn't send a numeric uid
+pub const NONUNAME: u32 = u32::MAX;
+/// Tattach `afid` of a client that didn't authenticate first
+pub const NOFID: u32 = u32::MAX;
+
+pub const RLERROR: u8 = 7;
+pub const TSTATFS: u8 = 8;
+pub const TLOPEN: u8 = 12;
+pub const TLCREATE: u8 = 14;
+pub const TRENAME: u8 = 20;
+pub const TREADLINK: u8 = 22;
+pub const TGETATTR: u8 = 24;
+pub const TSETATTR: u8 = 26;
+pub const TXATTRWALK: u8 = 30;
+pub const TREADDIR: u8 = 40;
+pub const TFSYNC: u8 = 50;
+pub const TMKDIR: u8 = 72;
+pub const TRENAMEAT: u8 = 74;
+pub const TUNLINKAT: u8 = 76;
+pub const TVERSION: u8 = 100;
+pub const TAUTH: u8 = 102;
+pub const TATTACH: u8 = 104;
+pub const TFLUSH: u8 = 108;
+pub const TWALK: u8 = 110;
+pub const TREAD: u8 = 116;
+pub const TWRITE: u8 = 118;
+pub const TCLUNK: u8 = 120;
+pub const TREMOVE: u8 = 122;
+
+/// Name of a request type, for spans and logs
+pub fn message_name(kind: u8) -> &'static str {
+    match kind {
+        TSTATFS => "statfs",
+        TLOPEN => "lopen",
+        TLCREATE => "lcreate",
+        TRENAME => "rename",
+        TREADLINK => "readlink",
+        TGETATTR => "getattr",
+        TSETATTR => "setattr",
+        TXATTRWALK => "xattrwalk",
+        TREADDIR => "readdir",
+        TFSYNC => "fsync",
+        TMKDIR => "mkdir",
+        TRENAMEAT => "renameat",
+        TUNLINKAT => "unlinkat",
+        TVERSION => "version",
+        TAUTH => "auth",
+        TATTACH => "attach",
+        TFLUSH => "flush",
+        TWALK => "walk",
+        TREAD => "read",
+        TWRITE => "write",
+        TCLUNK => "clunk",
+        TREMOVE => "remove",
+        _ => "unsupported",
+    }
+}
+
+/// Server-side identity of a file
+#[derive(Debug, Clone, Copy)]
+pub struct Qid {
+    pub kind: u8,
+    pub version: u32,
+    pub path: u64,
+}
+
+impl Qid {
+    pub fn new(ino: u64, is_dir: bool) -> Self {
+        Self {
+            kind: if is_dir { QID_DIR } else { QID_FILE },
+            version: 0,
+            path: ino,
+        }
+    }
+}
+
+/// Cursor over a request body
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+    
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated 9P message"));
+        }
+        let (head, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(head)
+    }
+    
+    pub fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    
+    pub fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    
+    pub fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    
+    pub fn string(&mut self) -> io::Result<String> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "9P string is not UTF-8"))
+    }
+    
+    /// `count[4]` followed by that many bytes
+    pub fn data(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
+
+/// Builder for a reply; `frame` prepends the header once the body is known
+pub struct Encoder {
+    kind: u8,
+    body: Vec<u8>,
+}
+
+impl Encoder {
+    /// A reply to a request of type `request_kind`
+    pub fn reply(request_kind: u8) -> Self {
+        Self {
+            kind: request_kind + 1,
+            body: Vec::new(),
+        }
+    }
+    
+    /// Scratch space for a nested structure, e.g. Rreaddir entries
+    pub fn buffer() -> Self {
+        Self { kind: 0, body: Vec::new() }
+    }
+    
+    pub fn error(errno: i32) -> Self {
+        let mut reply = Self { kind: RLERROR, body: Vec::new() };
+        reply.u32(errno as u32);
+        reply
+    }
+    
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.body
+    }
+    
+    pub fn len(&self) -> usize {
+        self.body.len()
+    }
+    
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.body.push(value);
+        self
+    }
+    
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.body.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+    
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.body.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+    
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.body.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+    
+    pub fn string(&mut self, value: &str) -> &mut Self {
+        self.u16(value.len() as u16);
+        self.body.extend_from_slice(value.as_bytes());
+        self
+    }
+    
+    pub fn qid(&mut self, qid: Qid) -> &mut Self {
+        self.u8(qid.kind).u32(qid.version).u64(qid.path)
+    }
+    
+    /// `count[4]` followed by the bytes
+    pub fn data(&mut self, data: &[u8]) -> &mut Self {
+        self.u32(data.len() as u32);
+        self.body.extend_from_slice(data);
+        self
+    }
+    
+    pub fn frame(self, tag: u16) -> Bytes {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.body.len());
+        out.extend_from_slice(&((HEADER_LEN + self.body.len()) as u32).to_le_bytes());
+        out.push(self.kind);
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&self.body);
+        Bytes::from(out)
+    }
+}
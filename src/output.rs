@@ -0,0 +1,254 @@
+//! Machine-readable output of the `gnos-mount` subcommands
+//!
+//! Subcommands that report something take `--output table|json|yaml`.
+//! `table` is laid out for people and may change between releases. `json`
+//! and `yaml` print one document whose `schema` field names its layout and
+//! version, e.g. `gnos.drivers/v1`, next to the subcommand's own fields.
+//! A version only ever gains fields; anything else means a new version, so
+//! scripts can check `schema` and rely on the rest.
+
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned text with emoji, for people
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+#[derive(Serialize)]
+struct Document<'a, T> {
+    schema: &'a str,
+    #[serde(flatten)]
+    body: &'a T,
+}
+
+impl OutputFormat {
+    /// Print `body` as a `schema` document, or hand it to `table` to lay out
+    pub fn emit<T: Serialize>(self, schema: &str, body: &T, table: impl FnOnce(&T)) -> Result<()> {
+        let document = Document { schema, body };
+        match self {
+            OutputFormat::Table => table(body),
+            OutputFormat::Json => {
+                let json = serde_json::to_string_pretty(&document).map_err(std::io::Error::other)?;
+                println!("{}", json);
+            }
+            OutputFormat::Yaml => {
+                let yaml = serde_yaml::to_string(&document).map_err(std::io::Error::other)?;
+                print!("{}", yaml);
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn is_table(self) -> bool {
+        self == OutputFormat::Table
+    }
+}
+
+/// `gnos.drivers/v1`: drivers the build ships
+#[derive(Debug, Clone, Serialize)]
+pub struct DriversOutput {
+    pub drivers: Vec<DriverRow>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DriverRow {
+    pub name: &'static str,
+    /// Where in the namespace it serves
+    pub path: &'static str,
+    pub status: &'static str,
+}
+
+/// `gnos.token/v1`: a capability token
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenOutput {
+    pub token: String,
+    pub path: String,
+    /// As given, e.g. "rw"
+    pub permissions: String,
+    pub expires_hours: u64,
+    /// RFC 3339
+    pub expires_at: String,
+    /// Who the audit log will name
+    pub owner: String,
+    /// RFC 3339; unset when it is valid at once
+    pub not_before: Option<String>,
+    /// Unset when it may idle until it expires
+    pub max_idle_seconds: Option<u64>,
+    /// Subtrees refused whatever the grant, as PATH:PERMS
+    pub deny: Vec<String>,
+}
+
+/// `gnos.breakglass/v1`: an emergency grant
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakGlassOutput {
+    pub token: String,
+    /// Names the grant in alerts and the audit log
+    pub id: String,
+    pub path: String,
+    pub permissions: String,
+    pub owner: String,
+    pub reason: String,
+    /// RFC 3339
+    pub expires_at: String,
+    /// How long the grant keeps working after its first use
+    pub session_minutes: u64,
+}
+
+/// `gnos.presign/v1`: a presigned gateway URL
+#[derive(Debug, Clone, Serialize)]
+pub struct PresignOutput {
+    pub url: String,
+    pub path: String,
+    pub permissions: String,
+    /// RFC 3339
+    pub expires_at: String,
+}
+
+/// `gnos.find/v1`: search matches and how the search went
+#[derive(Debug, Clone, Serialize)]
+pub struct FindOutput {
+    pub matches: Vec<String>,
+    pub count: usize,
+    pub directories: usize,
+    pub errors: usize,
+    /// Stopped at `search.max_results`
+    pub truncated: bool,
+    pub seconds: f64,
+}
+
+/// `gnos.info/v1`: what this build is
+#[derive(Debug, Clone, Serialize)]
+pub struct InfoOutput {
+    pub version: &'static str,
+    /// In hex, e.g. "0x474E4F53"
+    pub magic: String,
+    pub documentation: &'static str,
+    pub issues: &'static str,
+}
+
+/// `gnos.metrics/v1`: a running mount's `/proc/gnos/metrics`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MetricsOutput {
+    pub samples: Vec<MetricSample>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricSample {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+impl MetricsOutput {
+    /// Samples of the Prometheus text exposition, comments left out
+    pub fn parse(text: &str) -> Self {
+        let samples = text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (series, value) = line.rsplit_once(' ')?;
+                let value = value.parse::<f64>().ok()?;
+                let (name, labels) = match series.split_once('{') {
+                    Some((name, labels)) => (name, parse_labels(labels.strip_suffix('}')?)),
+                    None => (series, BTreeMap::new()),
+                };
+                Some(MetricSample { name: name.to_string(), labels, value })
+            })
+            .collect();
+        Self { samples }
+    }
+}
+
+/// `a="1",b="x\"y"` as a map, quotes and escapes undone
+fn parse_labels(text: &str) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    let mut rest = text;
+    while let Some((name, after)) = rest.split_once("=\"") {
+        let mut value = String::new();
+        let mut chars = after.char_indices();
+        let mut end = after.len();
+        while let Some((at, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, escaped)) => value.push(escaped),
+                    None => {}
+                },
+                '"' => {
+                    end = at + 1;
+                    break;
+                }
+                _ => value.push(c),
+            }
+        }
+        labels.insert(name.trim_start_matches(',').trim().to_string(), value);
+        rest = &after[end..];
+    }
+    labels
+}
+
+/// `gnos.costs/v1`: a running mount's `/proc/gnos/costs`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CostsOutput {
+    pub currency: String,
+    pub estimated: f64,
+    /// Requests by kind: reads, writes, lists, heads
+    pub requests: BTreeMap<String, u64>,
+    /// Bytes by direction: read, written
+    pub transferred: BTreeMap<String, u64>,
+    /// The costliest drivers, prefixes and owners
+    pub rows: Vec<CostRow>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CostRow {
+    /// "driver", "prefix" or "owner"
+    pub by: String,
+    pub name: String,
+    pub cost: f64,
+    /// Requests and bytes, named as in `requests` and `transferred`
+    pub counts: BTreeMap<String, u64>,
+}
+
+impl CostsOutput {
+    pub fn parse(text: &str) -> Self {
+        let counts = |fields: &mut dyn Iterator<Item = &str>| fields
+            .filter_map(|field| field.split_once('='))
+            .filter_map(|(name, value)| Some((name.to_string(), value.parse().ok()?)))
+            .collect::<BTreeMap<String, u64>>();
+        
+        let mut costs = Self::default();
+        for line in text.lines() {
+            // Rows are tab-separated, and a path in one may well hold ": "
+            if let Some((key, value)) = line.split_once(": ").filter(|_| !line.contains('\t')) {
+                match key {
+                    "currency" => costs.currency = value.to_string(),
+                    "estimated" => costs.estimated = value.parse().unwrap_or_default(),
+                    "requests" => costs.requests = counts(&mut value.split_whitespace()),
+                    "transferred" => costs.transferred = counts(&mut value.split_whitespace()),
+                    _ => {}
+                }
+                continue;
+            }
+            let mut fields = line.split('\t');
+            if let (Some(by), Some(name), Some(cost)) = (fields.next(), fields.next(), fields.next()) {
+                costs.rows.push(CostRow {
+                    by: by.to_string(),
+                    name: name.to_string(),
+                    cost: cost.parse().unwrap_or_default(),
+                    counts: counts(&mut fields),
+                });
+            }
+        }
+        costs
+    }
+}
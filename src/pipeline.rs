@@ -0,0 +1,213 @@
+//! Pipelines of chained paths
+//!
+//! A pipeline feeds the output of one stage into the next. Each stage takes
+//! its input from the previous stage or from a path, optionally writes it —
+//! raw or wrapped in a prompt — to a target such as an AI model and reads
+//! the answer back, and optionally writes its output to a path:
+//!
+//! ```toml
+//! name = "standup-notes"
+//!
+//! [[stages]]
+//! name = "transcribe"
+//! input = "/cloud/aws/s3/meetings/standup.wav"
+//! target = "/proc/whisper"
+//!
+//! [[stages]]
+//! name = "summarize"
+//! target = "/proc/llama3"
+//! prompt = "Summarize this meeting as bullet points:\n{input}"
+//!
+//! [[stages]]
+//! name = "upload"
+//! output = "/cloud/aws/s3/notes/standup.md"
+//! ```
+//!
+//! Specs may also be written in YAML with the same fields.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::client::GnosClient;
+use crate::{GnosError, Result};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipelineSpec {
+    /// Defaults to the spec's file name
+    #[serde(default)]
+    pub name: Option<String>,
+    pub stages: Vec<StageSpec>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StageSpec {
+    pub name: String,
+    /// Read as this stage's input instead of the previous stage's output
+    pub input: Option<PathBuf>,
+    /// Written with the input and read back for the output, e.g. an AI model
+    pub target: Option<PathBuf>,
+    /// What is written to `target`, with `{input}` replaced by the input
+    pub prompt: Option<String>,
+    /// Where the stage's output is also written
+    pub output: Option<PathBuf>,
+}
+
+impl PipelineSpec {
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let spec: Self = toml::from_str(text)
+            .map_err(|e| GnosError::InvalidPath(format!("invalid pipeline: {}", e)))?;
+        spec.validate()?;
+        Ok(spec)
+    }
+    
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        let spec: Self = serde_yaml::from_str(text)
+            .map_err(|e| GnosError::InvalidPath(format!("invalid pipeline: {}", e)))?;
+        spec.validate()?;
+        Ok(spec)
+    }
+    
+    fn validate(&self) -> Result<()> {
+        let Some(first) = self.stages.first() else {
+            return Err(GnosError::InvalidPath("pipeline has no stages".to_string()));
+        };
+        if first.input.is_none() {
+            return Err(GnosError::InvalidPath(format!("first stage {} needs an input", first.name)));
+        }
+        
+        for (index, stage) in self.stages.iter().enumerate() {
+            if stage.name.is_empty() || stage.name.contains('/') || stage.name == "status" {
+                return Err(GnosError::InvalidPath(format!("bad stage name: {:?}", stage.name)));
+            }
+            if self.stages[..index].iter().any(|earlier| earlier.name == stage.name) {
+                return Err(GnosError::InvalidPath(format!("duplicate stage name: {}", stage.name)));
+            }
+            if stage.prompt.is_some() && stage.target.is_none() {
+                return Err(GnosError::InvalidPath(format!("stage {} has a prompt but no target", stage.name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StageState {
+    Pending,
+    Running,
+    Done { bytes: usize, elapsed: Duration },
+    Failed(String),
+    /// An earlier stage failed
+    Skipped,
+}
+
+impl fmt::Display for StageState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageState::Pending => write!(f, "pending"),
+            StageState::Running => write!(f, "running"),
+            StageState::Done { bytes, elapsed } => write!(f, "done: {} bytes in {:.1}s", bytes, elapsed.as_secs_f64()),
+            StageState::Failed(error) => write!(f, "failed: {}", error),
+            StageState::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+/// Per-stage state of a running pipeline, for status files
+pub struct PipelineProgress {
+    stages: Mutex<Vec<(String, StageState)>>,
+}
+
+impl PipelineProgress {
+    pub fn new(spec: &PipelineSpec) -> Self {
+        Self {
+            stages: Mutex::new(spec.stages.iter().map(|stage| (stage.name.clone(), StageState::Pending)).collect()),
+        }
+    }
+    
+    pub fn stages(&self) -> Vec<(String, StageState)> {
+        self.stages.lock().unwrap().clone()
+    }
+    
+    pub fn stage(&self, name: &str) -> Option<StageState> {
+        self.stages.lock().unwrap().iter().find(|(stage, _)| stage == name).map(|(_, state)| state.clone())
+    }
+    
+    /// `running`, `done`, `failed` or `pending`
+    pub fn overall(&self) -> &'static str {
+        let stages = self.stages.lock().unwrap();
+        if stages.iter().any(|(_, state)| matches!(state, StageState::Failed(_))) {
+            "failed"
+        } else if stages.iter().all(|(_, state)| matches!(state, StageState::Done { .. })) {
+            "done"
+        } else if stages.iter().any(|(_, state)| !matches!(state, StageState::Pending)) {
+            "running"
+        } else {
+            "pending"
+        }
+    }
+    
+    fn set(&self, index: usize, state: StageState) {
+        if let Some(stage) = self.stages.lock().unwrap().get_mut(index) {
+            stage.1 = state;
+        }
+    }
+}
+
+/// Run every stage in order, stopping at the first failure
+pub async fn run(spec: &PipelineSpec, client: &GnosClient, progress: &PipelineProgress) -> Result<()> {
+    let name = spec.name.as_deref().unwrap_or("pipeline");
+    let mut carried: Option<Bytes> = None;
+    
+    for (index, stage) in spec.stages.iter().enumerate() {
+        progress.set(index, StageState::Running);
+        let started = Instant::now();
+        
+        match run_stage(stage, carried.take(), client).await {
+            Ok(output) => {
+                progress.set(index, StageState::Done { bytes: output.len(), elapsed: started.elapsed() });
+                carried = Some(output);
+            }
+            Err(e) => {
+                warn!("❌ Pipeline {} failed at {}: {}", name, stage.name, e);
+                progress.set(index, StageState::Failed(e.to_string()));
+                for rest in index + 1..spec.stages.len() {
+                    progress.set(rest, StageState::Skipped);
+                }
+                return Err(e);
+            }
+        }
+    }
+    
+    info!("🔗 Pipeline {} finished {} stages", name, spec.stages.len());
+    Ok(())
+}
+
+async fn run_stage(stage: &StageSpec, carried: Option<Bytes>, client: &GnosClient) -> Result<Bytes> {
+    let input = match &stage.input {
+        Some(path) => client.read(path).await?,
+        None => carried.ok_or_else(|| GnosError::InvalidPath(format!("stage {} has no input", stage.name)))?,
+    };
+    
+    let output = match &stage.target {
+        Some(target) => {
+            let request = match &stage.prompt {
+                Some(prompt) => Bytes::from(prompt.replace("{input}", &String::from_utf8_lossy(&input))),
+                None => input,
+            };
+            client.write(target, &request).await?;
+            client.read(target).await?
+        }
+        None => input,
+    };
+    
+    if let Some(path) = &stage.output {
+        client.write(path, &output).await?;
+    }
+    Ok(output)
+}
@@ -0,0 +1,325 @@
+//! Trusted-plugin policy for driver plugins
+//!
+//! `[plugins] paths` names shared libraries built with
+//! `gnos_driver_sdk::export_plugin!`, each holding one driver. Before a
+//! plugin is loaded, its file must carry a detached Ed25519 signature
+//! (`<plugin>.sig`, raw or base64) from one of the configured publisher
+//! keys. Every decision is recorded in the capability audit log with the
+//! plugin's SHA-256 fingerprint, so operators can see exactly which
+//! binaries were admitted and who signed them.
+//!
+//! The bytes that were verified are the ones loaded: they are copied to an
+//! anonymous file and opened from there, so replacing the plugin on disk
+//! after the check changes nothing. Drivers cross the library boundary as
+//! Rust trait objects, so a plugin must be built with the same compiler
+//! against the same GNOS version; a plugin for another version is refused.
+//! Plugins stay loaded for the life of the process.
+
+use std::ffi::{c_char, c_void, CStr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::digest::{digest, SHA256};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use tracing::{info, warn};
+
+use crate::config::PluginConfig;
+use crate::drivers::{DriverRegistry, GnosDriver};
+use crate::security::CapabilityManager;
+use crate::{GnosError, Result};
+
+/// What a plugin reports from `gnos_plugin_abi`: only a plugin built
+/// against this GNOS version can be loaded by it
+pub const PLUGIN_ABI: &str = concat!("gnos-", env!("CARGO_PKG_VERSION"), "\0");
+
+/// What a plugin's `gnos_plugin_create` hands over
+pub struct PluginDriver {
+    /// Label the driver is registered under
+    pub name: String,
+    pub driver: Arc<dyn GnosDriver>,
+}
+
+/// A plugin file that passed verification
+#[derive(Debug, Clone)]
+pub struct VerifiedPlugin {
+    pub path: PathBuf,
+    /// Hex SHA-256 of the plugin file
+    pub fingerprint: String,
+    /// Publisher whose key signed it; `None` if admitted unsigned by policy
+    pub publisher: Option<String>,
+    /// The bytes that were verified, so the loader uses exactly these
+    pub contents: Vec<u8>,
+}
+
+pub struct PluginVerifier {
+    config: PluginConfig,
+    capability_manager: Arc<CapabilityManager>,
+}
+
+impl PluginVerifier {
+    pub fn new(config: PluginConfig, capability_manager: Arc<CapabilityManager>) -> Self {
+        Self { config, capability_manager }
+    }
+    
+    /// Check `path` against the publisher keys and audit the outcome
+    pub async fn verify(&self, path: &Path) -> Result<VerifiedPlugin> {
+        let contents = tokio::fs::read(path).await?;
+        let fingerprint = fingerprint(&contents);
+        
+        let outcome = match read_signature(path).await {
+            Ok(Some(signature)) => self.publisher_of(&contents, &signature)
+                .map(Some)
+                .ok_or_else(|| "signature matches no trusted publisher".to_string()),
+            Ok(None) if !self.config.require_signatures => Ok(None),
+            Ok(None) => Err("no signature".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        
+        match outcome {
+            Ok(publisher) => {
+                let signer = publisher.as_deref().unwrap_or("unsigned");
+                self.capability_manager.audit_plugin(path, signer, true, format!("sha256:{}", fingerprint));
+                info!("🧩 Plugin {} verified (sha256:{}, {})", path.display(), fingerprint, signer);
+                Ok(VerifiedPlugin {
+                    path: path.to_path_buf(),
+                    fingerprint,
+                    publisher,
+                    contents,
+                })
+            }
+            Err(reason) => {
+                self.capability_manager.audit_plugin(path, "unknown", false, format!("sha256:{}: {}", fingerprint, reason));
+                warn!("🚫 Refusing plugin {}: {}", path.display(), reason);
+                Err(GnosError::PermissionDenied(format!("plugin {} refused: {}", path.display(), reason)))
+            }
+        }
+    }
+    
+    /// Verify and load every configured plugin, registering its driver;
+    /// a plugin that is refused or fails to load stops startup
+    pub async fn load_all(&self, mut registry: DriverRegistry) -> Result<DriverRegistry> {
+        for path in &self.config.paths {
+            let plugin = self.verify(path).await?;
+            let loaded = load(&plugin)?;
+            info!("🧩 Loaded driver {} from plugin {}", loaded.name, path.display());
+            registry = registry.with_driver(&loaded.name, loaded.driver);
+        }
+        Ok(registry)
+    }
+    
+    /// Name of the publisher whose key made `signature` over `contents`
+    fn publisher_of(&self, contents: &[u8], signature: &[u8]) -> Option<String> {
+        self.config.publishers.iter()
+            .find(|publisher| match STANDARD.decode(publisher.public_key.trim()) {
+                Ok(key) => UnparsedPublicKey::new(&ED25519, key).verify(contents, signature).is_ok(),
+                Err(_) => {
+                    warn!("❌ Publisher {} has an unreadable public key", publisher.name);
+                    false
+                }
+            })
+            .map(|publisher| publisher.name.clone())
+    }
+}
+
+/// The detached signature next to a plugin, if there is one
+async fn read_signature(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(".sig");
+    let raw = match tokio::fs::read(&signature_path).await {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    
+    // Ed25519 signatures are 64 bytes; anything else is taken as base64
+    if raw.len() == 64 {
+        return Ok(Some(raw));
+    }
+    let text = String::from_utf8_lossy(&raw);
+    STANDARD.decode(text.trim())
+        .map(Some)
+        .map_err(|_| GnosError::InvalidPath(format!("unreadable signature for {}", path.display())))
+}
+
+/// Open a verified plugin from its verified bytes and take its driver
+#[cfg(target_os = "linux")]
+fn load(plugin: &VerifiedPlugin) -> Result<PluginDriver> {
+    use std::io::Write;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    
+    let refused = |reason: &str| GnosError::PermissionDenied(format!("plugin {}: {}", plugin.path.display(), reason));
+    
+    let fd = unsafe { libc::memfd_create(c"gnos-plugin".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut image = unsafe { std::fs::File::from_raw_fd(fd) };
+    image.write_all(&plugin.contents)?;
+    
+    let image_path = std::ffi::CString::new(format!("/proc/self/fd/{}", image.as_raw_fd()))
+        .map_err(|_| refused("unloadable image path"))?;
+    // Mapped once opened, so the anonymous file can go
+    let handle = unsafe { libc::dlopen(image_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    drop(image);
+    if handle.is_null() {
+        let error = unsafe { libc::dlerror() };
+        let reason = if error.is_null() {
+            "not a loadable library".to_string()
+        } else {
+            unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned()
+        };
+        return Err(refused(&reason));
+    }
+    
+    let abi = unsafe { libc::dlsym(handle, c"gnos_plugin_abi".as_ptr()) };
+    let create = unsafe { libc::dlsym(handle, c"gnos_plugin_create".as_ptr()) };
+    if abi.is_null() || create.is_null() {
+        unsafe { libc::dlclose(handle) };
+        return Err(refused("no gnos_plugin_abi/gnos_plugin_create; build it with gnos_driver_sdk::export_plugin!"));
+    }
+    let abi = unsafe { CStr::from_ptr(std::mem::transmute::<*mut c_void, extern "C" fn() -> *const c_char>(abi)()) };
+    if abi.to_bytes_with_nul() != PLUGIN_ABI.as_bytes() {
+        let reason = format!("built for {}, not {}", abi.to_string_lossy(), PLUGIN_ABI.trim_end_matches('\0'));
+        unsafe { libc::dlclose(handle) };
+        return Err(refused(&reason));
+    }
+    
+    // The library is never closed: the driver's code and vtables live in it
+    let create = unsafe { std::mem::transmute::<*mut c_void, extern "C" fn() -> *mut PluginDriver>(create) };
+    let driver = create();
+    if driver.is_null() {
+        return Err(refused("gnos_plugin_create returned no driver"));
+    }
+    Ok(*unsafe { Box::from_raw(driver) })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn load(plugin: &VerifiedPlugin) -> Result<PluginDriver> {
+    Err(GnosError::Config(format!("plugin {}: driver plugins are only loaded on Linux", plugin.path.display())))
+}
+
+fn fingerprint(contents: &[u8]) -> String {
+    digest(&SHA256, contents).as_ref().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    
+    use crate::config::{DriverConfig, PublisherKey};
+    use crate::security::SecurityConfig;
+    
+    struct Fixture {
+        dir: tempfile::TempDir,
+        key: Ed25519KeyPair,
+        capabilities: Arc<CapabilityManager>,
+    }
+    
+    impl Fixture {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            let capabilities = Arc::new(CapabilityManager::new(SecurityConfig {
+                signing_key_file: dir.path().join("signing.key"),
+                ..SecurityConfig::default()
+            }));
+            Self { key: Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap(), dir, capabilities }
+        }
+        
+        fn verifier(&self, require_signatures: bool, paths: Vec<PathBuf>) -> PluginVerifier {
+            let publisher = PublisherKey {
+                name: "gnos-project".to_string(),
+                public_key: STANDARD.encode(self.key.public_key().as_ref()),
+            };
+            let config = PluginConfig { paths, require_signatures, publishers: vec![publisher] };
+            PluginVerifier::new(config, self.capabilities.clone())
+        }
+        
+        /// A plugin file holding `contents`, signed by `key` if given
+        fn plugin(&self, name: &str, contents: &[u8], key: Option<&Ed25519KeyPair>) -> PathBuf {
+            let path = self.dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            if let Some(key) = key {
+                std::fs::write(path.with_extension("so.sig"), STANDARD.encode(key.sign(contents))).unwrap();
+            }
+            path
+        }
+    }
+    
+    #[tokio::test]
+    async fn plugins_signed_by_a_publisher_are_admitted_and_audited() {
+        let fixture = Fixture::new();
+        let path = fixture.plugin("kv.so", b"plugin bytes", Some(&fixture.key));
+        
+        let plugin = fixture.verifier(true, Vec::new()).verify(&path).await.unwrap();
+        assert_eq!(plugin.publisher.as_deref(), Some("gnos-project"));
+        assert_eq!(plugin.contents, b"plugin bytes");
+        assert_eq!(plugin.fingerprint, fingerprint(b"plugin bytes"));
+        
+        let audited = fixture.capabilities.audit_log().into_iter().last().unwrap();
+        assert!(audited.success);
+        assert_eq!(audited.owner, "gnos-project");
+        assert_eq!(audited.reason, Some(format!("sha256:{}", plugin.fingerprint)));
+    }
+    
+    #[tokio::test]
+    async fn unsigned_and_foreign_plugins_are_refused() {
+        let fixture = Fixture::new();
+        let unsigned = fixture.plugin("unsigned.so", b"plugin bytes", None);
+        let stranger = Ed25519KeyPair::from_pkcs8(Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap().as_ref()).unwrap();
+        let foreign = fixture.plugin("foreign.so", b"plugin bytes", Some(&stranger));
+        
+        let verifier = fixture.verifier(true, Vec::new());
+        for path in [&unsigned, &foreign] {
+            assert!(matches!(verifier.verify(path).await, Err(GnosError::PermissionDenied(_))));
+        }
+        assert!(fixture.capabilities.audit_log().iter().all(|entry| !entry.success));
+        
+        // Without the requirement an unsigned plugin is admitted, but one
+        // carrying an untrusted signature still isn't
+        let lenient = fixture.verifier(false, Vec::new());
+        assert_eq!(lenient.verify(&unsigned).await.unwrap().publisher, None);
+        assert!(lenient.verify(&foreign).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn refused_plugins_are_never_opened() {
+        let fixture = Fixture::new();
+        let unsigned = fixture.plugin("unsigned.so", b"not even a library", None);
+        let mut drivers = DriverConfig::default();
+        drivers.ai.enabled = false;
+        drivers.cloud.enabled = false;
+        drivers.http.enabled = false;
+        drivers.sensors.enabled = false;
+        let registry = DriverRegistry::new(drivers).await.unwrap();
+        
+        let refused = fixture.verifier(true, vec![unsigned]).load_all(registry).await.err().unwrap();
+        assert!(refused.to_string().contains("no signature"), "{}", refused);
+    }
+    
+    #[test]
+    fn verified_libraries_without_the_plugin_entry_points_are_refused() {
+        // A shared library this process already has mapped, e.g. libc
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+        let library = maps.lines()
+            .filter_map(|line| line.split_whitespace().nth(5))
+            .find(|path| path.contains(".so"))
+            .expect("no shared library mapped");
+        let contents = std::fs::read(library).unwrap();
+        let plugin = VerifiedPlugin {
+            path: PathBuf::from(library),
+            fingerprint: fingerprint(&contents),
+            publisher: Some("gnos-project".to_string()),
+            contents,
+        };
+        
+        let refused = load(&plugin).err().unwrap();
+        assert!(refused.to_string().contains("gnos_plugin_create"), "{}", refused);
+        
+        let garbage = VerifiedPlugin { contents: b"not a library".to_vec(), ..plugin };
+        assert!(matches!(load(&garbage), Err(GnosError::PermissionDenied(_))));
+    }
+}
@@ -0,0 +1,377 @@
+//! Per-driver call pools
+//!
+//! Every driver, and each tenant's copy of it, gets its own pool of
+//! `concurrency` slots under `[pools]`; a call waits for a free slot in its
+//! driver's pool before it starts. A burst of slow inferences fills the AI
+//! driver's pool and queues behind it while object storage calls still find
+//! their own slots free. With `max_queue` set, calls beyond that many
+//! waiting fail straight away with `EAGAIN` instead of piling up.
+//!
+//! Calls waiting in a full pool are let in by priority class (see
+//! [`crate::qos`]) rather than in arrival order.
+//!
+//! Queue depths are listed at `/proc/gnos/pools` and exported as the
+//! `gnos_driver_pool_*` gauges.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tracing::debug;
+
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::{CacheMode, PoolConfig};
+use crate::drivers::{BatchOp, ChangeStream, GnosDriver, Growth, PartPolicy, PathParams, ResourceMetadata, WriteOptions};
+use crate::qos::{self, QosClass, Scheduler, Weights};
+use crate::telemetry::LabelledGauge;
+use crate::{GnosError, Result};
+
+/// Counts a call as waiting or running for as long as it is held, even if
+/// the call is dropped halfway
+struct Occupied<'a>(&'a AtomicUsize);
+
+impl<'a> Occupied<'a> {
+    fn enter(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for Occupied<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// One driver's slots
+pub struct Pool {
+    driver: String,
+    /// `None` when the pool is unbounded
+    slots: Option<Arc<Scheduler>>,
+    size: usize,
+    max_queue: usize,
+    /// Largest transfer run as `QosClass::Small`
+    small_bytes: u64,
+    waiting: AtomicUsize,
+    running: AtomicUsize,
+    calls: AtomicU64,
+    rejected: AtomicU64,
+    waited_micros: AtomicU64,
+    /// Calls and time spent queued per class, indexed by `QosClass::index`
+    class_calls: [AtomicU64; 4],
+    class_waited_micros: [AtomicU64; 4],
+}
+
+impl Pool {
+    fn new(driver: &str, size: usize, max_queue: usize, config: &PoolConfig) -> Self {
+        Self {
+            driver: driver.to_string(),
+            slots: (size > 0).then(|| Scheduler::new(size, Weights::new(&config.qos))),
+            size,
+            max_queue,
+            small_bytes: config.qos.small_kb * 1024,
+            waiting: AtomicUsize::new(0),
+            running: AtomicUsize::new(0),
+            calls: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            waited_micros: AtomicU64::new(0),
+            class_calls: Default::default(),
+            class_waited_micros: Default::default(),
+        }
+    }
+    
+    /// Class of a transfer of `bytes`
+    fn transfer(&self, bytes: u64) -> QosClass {
+        if bytes <= self.small_bytes {
+            QosClass::Small
+        } else {
+            QosClass::Bulk
+        }
+    }
+    
+    /// Run `call` once a slot is free, queued as `class` unless the task
+    /// has a class of its own
+    async fn run<T>(&self, class: QosClass, call: impl Future<Output = Result<T>>) -> Result<T> {
+        let class = qos::class_or(class);
+        self.class_calls[class.index()].fetch_add(1, Ordering::Relaxed);
+        let _slot = match &self.slots {
+            Some(slots) => match slots.try_acquire() {
+                Some(slot) => Some(slot),
+                None => {
+                    let waiting = self.waiting.load(Ordering::Relaxed);
+                    if self.max_queue > 0 && waiting >= self.max_queue {
+                        self.rejected.fetch_add(1, Ordering::Relaxed);
+                        return Err(GnosError::RateLimited {
+                            message: format!("{} has {} calls queued", self.driver, waiting),
+                            retry_after: None,
+                        });
+                    }
+                    
+                    let _waiting = Occupied::enter(&self.waiting);
+                    let started = Instant::now();
+                    let slot = slots.acquire(class).await;
+                    let waited = started.elapsed();
+                    debug!("{} call to {} waited {:?} for a slot", class.name(), self.driver, waited);
+                    self.waited_micros.fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+                    self.class_waited_micros[class.index()].fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+                    Some(slot)
+                }
+            },
+            None => None,
+        };
+        
+        let _running = Occupied::enter(&self.running);
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        call.await
+    }
+}
+
+/// The pools of every driver; cheap to share between drivers
+pub struct DriverPools {
+    config: PoolConfig,
+    pools: Mutex<BTreeMap<String, Arc<Pool>>>,
+}
+
+impl DriverPools {
+    pub fn new(config: &PoolConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config: config.clone(),
+            pools: Mutex::new(BTreeMap::new()),
+        })
+    }
+    
+    /// The pool of the driver labelled `driver`, created on first use
+    pub fn pool(&self, driver: &str) -> Arc<Pool> {
+        let mut pools = self.pools.lock().unwrap();
+        pools.entry(driver.to_string())
+            .or_insert_with(|| {
+                let own = self.config.drivers.iter().find(|pool| pool.driver == driver);
+                let size = own.map_or(self.config.concurrency, |pool| pool.concurrency);
+                let max_queue = own.and_then(|pool| pool.max_queue).unwrap_or(self.config.max_queue);
+                Arc::new(Pool::new(driver, size, max_queue, &self.config))
+            })
+            .clone()
+    }
+    
+    /// Running and queued calls per driver, for `/proc/gnos/metrics`
+    pub fn gauges(&self) -> Vec<LabelledGauge> {
+        let pools = self.pools.lock().unwrap();
+        let per_driver = |count: fn(&Pool) -> f64| {
+            pools.values().map(|pool| (pool.driver.clone(), count(pool))).collect()
+        };
+        vec![
+            LabelledGauge {
+                name: "gnos_driver_pool_running",
+                help: "Driver calls holding a pool slot",
+                label: "driver",
+                values: per_driver(|pool| pool.running.load(Ordering::Relaxed) as f64),
+            },
+            LabelledGauge {
+                name: "gnos_driver_pool_waiting",
+                help: "Driver calls queued for a pool slot",
+                label: "driver",
+                values: per_driver(|pool| pool.waiting.load(Ordering::Relaxed) as f64),
+            },
+        ]
+    }
+    
+    /// Calls running or queued across every pool
+    pub fn in_flight(&self) -> usize {
+        self.pools.lock().unwrap().values()
+            .map(|pool| pool.running.load(Ordering::Relaxed) + pool.waiting.load(Ordering::Relaxed))
+            .sum()
+    }
+    
+    /// Plain-text view for `/proc/gnos/pools`
+    pub fn status_report(&self) -> String {
+        let mut report = String::from("driver\tsize\trunning\twaiting\tcalls\trejected\twaited\n");
+        let pools = self.pools.lock().unwrap();
+        for pool in pools.values() {
+            report.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{:.3}s\n",
+                pool.driver,
+                if pool.size == 0 { "unbounded".to_string() } else { pool.size.to_string() },
+                pool.running.load(Ordering::Relaxed),
+                pool.waiting.load(Ordering::Relaxed),
+                pool.calls.load(Ordering::Relaxed),
+                pool.rejected.load(Ordering::Relaxed),
+                pool.waited_micros.load(Ordering::Relaxed) as f64 / 1e6,
+            ));
+        }
+        
+        report.push_str("\nclass\tweight\tcalls\twaited\n");
+        let weights = Weights::new(&self.config.qos);
+        for class in QosClass::ALL {
+            let (calls, waited) = pools.values().fold((0, 0), |(calls, waited), pool| {
+                (
+                    calls + pool.class_calls[class.index()].load(Ordering::Relaxed),
+                    waited + pool.class_waited_micros[class.index()].load(Ordering::Relaxed),
+                )
+            });
+            report.push_str(&format!(
+                "{}\t{}\t{}\t{:.3}s\n",
+                class.name(),
+                weights.of(class),
+                calls,
+                waited as f64 / 1e6,
+            ));
+        }
+        report
+    }
+}
+
+/// A driver whose calls take a slot in its pool first
+pub struct PooledDriver {
+    inner: Arc<dyn GnosDriver>,
+    pool: Arc<Pool>,
+}
+
+impl PooledDriver {
+    pub fn new(driver: &str, inner: Arc<dyn GnosDriver>, pools: &DriverPools) -> Self {
+        Self { inner, pool: pools.pool(driver) }
+    }
+}
+
+#[async_trait]
+impl GnosDriver for PooledDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        self.pool.run(QosClass::Small, self.inner.read(path)).await
+    }
+    
+    async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+        self.pool.run(QosClass::Small, self.inner.read_checked(path)).await
+    }
+    
+    async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        self.pool.run(self.pool.transfer(len), self.inner.read_range(path, offset, len)).await
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.inner.streams(path)
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        self.pool.run(QosClass::Small, self.inner.read_growing(path, have)).await
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.pool.run(self.pool.transfer(data.len() as u64), self.inner.write(path, data)).await
+    }
+    
+    fn upload_checksums(&self, path: &Path) -> &[ChecksumAlgorithm] {
+        self.inner.upload_checksums(path)
+    }
+    
+    async fn write_with(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+        self.pool.run(self.pool.transfer(data.len() as u64), self.inner.write_with(path, data, options)).await
+    }
+    
+    fn conditional_writes(&self, path: &Path) -> bool {
+        self.inner.conditional_writes(path)
+    }
+    
+    fn accepted_encodings(&self) -> &[&'static str] {
+        self.inner.accepted_encodings()
+    }
+    
+    async fn write_encoded(&self, path: &Path, data: &[u8], encoding: &str) -> Result<()> {
+        self.pool.run(self.pool.transfer(data.len() as u64), self.inner.write_encoded(path, data, encoding)).await
+    }
+    
+    fn invokes(&self, path: &Path) -> bool {
+        self.inner.invokes(path)
+    }
+    
+    async fn invoke(&self, path: &Path, payload: &[u8]) -> Result<Bytes> {
+        self.pool.run(self.pool.transfer(payload.len() as u64), self.inner.invoke(path, payload)).await
+    }
+    
+    fn supports_parts(&self, path: &Path) -> bool {
+        self.inner.supports_parts(path)
+    }
+    
+    fn part_policy(&self, path: &Path) -> PartPolicy {
+        self.inner.part_policy(path)
+    }
+    
+    async fn begin_parts(&self, path: &Path) -> Result<String> {
+        self.pool.run(QosClass::Bulk, self.inner.begin_parts(path)).await
+    }
+    
+    async fn write_part(&self, path: &Path, upload_id: &str, part: u64, data: &[u8]) -> Result<()> {
+        self.pool.run(QosClass::Bulk, self.inner.write_part(path, upload_id, part, data)).await
+    }
+    
+    async fn complete_parts(&self, path: &Path, upload_id: &str, parts: u64) -> Result<()> {
+        self.pool.run(QosClass::Bulk, self.inner.complete_parts(path, upload_id, parts)).await
+    }
+    
+    async fn abort_parts(&self, path: &Path, upload_id: &str) -> Result<()> {
+        self.pool.run(QosClass::Bulk, self.inner.abort_parts(path, upload_id)).await
+    }
+    
+    fn supports_batches(&self) -> bool {
+        self.inner.supports_batches()
+    }
+    
+    async fn commit_batch(&self, ops: &[BatchOp]) -> Result<()> {
+        self.pool.run(QosClass::Bulk, self.inner.commit_batch(ops)).await
+    }
+    
+    async fn materialize(&self, path: &Path, params: &PathParams) -> Result<()> {
+        self.pool.run(QosClass::Small, self.inner.materialize(path, params)).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        self.pool.run(QosClass::Interactive, self.inner.create_dir(path)).await
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        self.pool.run(QosClass::Interactive, self.inner.delete(path)).await
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        self.pool.run(QosClass::Interactive, self.inner.list(path)).await
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        self.pool.run(QosClass::Interactive, self.inner.list_with_metadata(path)).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        self.pool.run(QosClass::Interactive, self.inner.exists(path)).await
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        self.pool.run(QosClass::Interactive, self.inner.metadata(path)).await
+    }
+    
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        self.inner.supports(path)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        self.inner.prefixes()
+    }
+    
+    fn cache_mode(&self, path: &Path) -> CacheMode {
+        self.inner.cache_mode(path)
+    }
+    
+    fn private(&self, path: &Path) -> bool {
+        self.inner.private(path)
+    }
+    
+    fn watch(&self, path: &Path) -> Option<ChangeStream> {
+        self.inner.watch(path)
+    }
+}
@@ -0,0 +1,199 @@
+//! Priority classes for driver calls
+//!
+//! When a driver's pool is full, calls waiting for a slot are let in by
+//! class rather than in arrival order:
+//!
+//! - `interactive`: metadata, listings, deletes and directory creation, what
+//!   `ls` and `stat` wait on;
+//! - `small`: reads and writes up to `small_kb`;
+//! - `bulk`: larger transfers and multipart uploads;
+//! - `background`: everything the daemon does on its own, such as write-back
+//!   uploads, index crawls, lifecycle sweeps and change polling.
+//!
+//! Each freed slot goes to a waiting class in proportion to the weights
+//! under `[pools.qos]`, so a 50 GB upload through the mount still leaves
+//! `ls` most of the slots while neither class starves. Calls only queue once
+//! the pool is full; until then every class runs at once.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::oneshot;
+
+use crate::config::QosConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QosClass {
+    Interactive,
+    Small,
+    Bulk,
+    Background,
+}
+
+impl QosClass {
+    pub const ALL: [QosClass; 4] = [QosClass::Interactive, QosClass::Small, QosClass::Bulk, QosClass::Background];
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            QosClass::Interactive => "interactive",
+            QosClass::Small => "small",
+            QosClass::Bulk => "bulk",
+            QosClass::Background => "background",
+        }
+    }
+    
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+tokio::task_local! {
+    static CLASS: QosClass;
+}
+
+/// Run `future` with every driver call it makes in `class`, whatever the call
+pub async fn scope<F: Future>(class: QosClass, future: F) -> F::Output {
+    CLASS.scope(class, future).await
+}
+
+/// The class a call runs in: its task's scope if there is one, else `default`
+pub fn class_or(default: QosClass) -> QosClass {
+    CLASS.try_with(|class| *class).unwrap_or(default)
+}
+
+/// Weights of the classes, indexed by `QosClass::index`
+#[derive(Debug, Clone, Copy)]
+pub struct Weights([i64; 4]);
+
+impl Weights {
+    pub fn new(config: &QosConfig) -> Self {
+        let weights = [config.interactive, config.small, config.bulk, config.background];
+        // A class weighted 0 would never get a slot while others wait
+        Self(weights.map(|weight| weight.max(1) as i64))
+    }
+    
+    pub fn of(&self, class: QosClass) -> i64 {
+        self.0[class.index()]
+    }
+}
+
+#[derive(Default)]
+struct State {
+    free: usize,
+    queues: [VecDeque<oneshot::Sender<()>>; 4],
+    /// Smooth weighted round robin credit of each class
+    credits: [i64; 4],
+}
+
+impl State {
+    fn idle(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+    
+    /// The waiting class to hand the next slot to
+    fn next(&mut self, weights: &Weights) -> Option<usize> {
+        let waiting: Vec<usize> = (0..4).filter(|&class| !self.queues[class].is_empty()).collect();
+        let total: i64 = waiting.iter().map(|&class| weights.0[class]).sum();
+        for &class in &waiting {
+            self.credits[class] += weights.0[class];
+        }
+        // Ties go to the more urgent class
+        let next = waiting.into_iter().max_by_key(|&class| (self.credits[class], std::cmp::Reverse(class)))?;
+        self.credits[next] -= total;
+        Some(next)
+    }
+}
+
+/// Slots of one pool, handed to waiting calls by class
+pub struct Scheduler {
+    weights: Weights,
+    state: Mutex<State>,
+}
+
+impl Scheduler {
+    pub fn new(size: usize, weights: Weights) -> Arc<Self> {
+        Arc::new(Self {
+            weights,
+            state: Mutex::new(State { free: size, ..State::default() }),
+        })
+    }
+    
+    /// A slot, if one is free and nobody is waiting for it
+    pub fn try_acquire(self: &Arc<Self>) -> Option<Slot> {
+        let mut state = self.state.lock().unwrap();
+        if state.free > 0 && state.idle() {
+            state.free -= 1;
+            return Some(Slot(self.clone()));
+        }
+        None
+    }
+    
+    /// Wait for a slot behind the calls of `class` already waiting
+    pub async fn acquire(self: &Arc<Self>, class: QosClass) -> Slot {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            if state.free > 0 && state.idle() {
+                state.free -= 1;
+                return Slot(self.clone());
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.queues[class.index()].push_back(sender);
+            receiver
+        };
+        let mut waiter = Waiter { scheduler: self.clone(), receiver: Some(receiver) };
+        waiter.granted().await;
+        Slot(self.clone())
+    }
+    
+    /// Pass a freed slot on to the next waiting call, or keep it free
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(class) = state.next(&self.weights) {
+            if let Some(sender) = state.queues[class].pop_front() {
+                // A waiter that gave up has dropped its receiver
+                if sender.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        state.free += 1;
+    }
+}
+
+/// A slot held for as long as a call runs
+pub struct Slot(Arc<Scheduler>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// A call waiting for a slot; if it is dropped just after being handed one,
+/// the slot goes on to the next call
+struct Waiter {
+    scheduler: Arc<Scheduler>,
+    receiver: Option<oneshot::Receiver<()>>,
+}
+
+impl Waiter {
+    async fn granted(&mut self) {
+        if let Some(receiver) = self.receiver.as_mut() {
+            // The scheduler outlives its waiters, so the sender is never dropped unsent
+            let _ = receiver.await;
+        }
+        self.receiver = None;
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.receiver.take() {
+            receiver.close();
+            if receiver.try_recv().is_ok() {
+                self.scheduler.release();
+            }
+        }
+    }
+}
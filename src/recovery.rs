@@ -0,0 +1,424 @@
+//! Panic isolation for driver calls
+//!
+//! Every driver call runs under `catch_unwind`, so a driver that panics
+//! fails that one call with `EIO` instead of unwinding through the FUSE
+//! loop or a 9P or gateway task. The panic is logged and recorded and the
+//! driver marked degraded: from then on its calls fail straight away with
+//! `EHOSTUNREACH`, while every other driver carries on.
+//!
+//! With `[recovery] restart` on, a degraded driver is built again from its
+//! config after `restart_delay_seconds`; plugins, having no config, are put
+//! back in service as they are. Instances a restart replaces are kept until
+//! unmount, since callers may still hold answers borrowed from them, and
+//! `max_restarts` bounds how many that can be.
+//!
+//! `/proc/gnos/recovery` lists each driver's state, panics and restarts,
+//! and the `gnos_driver_degraded` and `gnos_driver_panics` gauges export them.
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::runtime::Handle;
+use tracing::{error, info, warn};
+
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::config::{CacheMode, RecoveryConfig};
+use crate::drivers::{BatchOp, ChangeStream, GnosDriver, Growth, PartPolicy, PathParams, ResourceMetadata, WriteOptions};
+use crate::telemetry::LabelledGauge;
+use crate::{GnosError, Result};
+
+/// Builds a fresh instance of a driver
+pub type Rebuild = Box<dyn Fn() -> BoxFuture<'static, Result<Arc<dyn GnosDriver>>> + Send + Sync>;
+
+/// A driver's latest panic
+struct Panic {
+    at: SystemTime,
+    op: &'static str,
+    path: PathBuf,
+    message: String,
+}
+
+/// One driver's instances and health
+struct Supervised {
+    /// Config name, e.g. `cloud` or `cloud@acme`
+    label: String,
+    /// The instance first built, then one per restart; each is set once,
+    /// so references into any of them live as long as this does
+    generations: Box<[OnceLock<Arc<dyn GnosDriver>>]>,
+    current: AtomicUsize,
+    rebuild: Option<Rebuild>,
+    /// Where restarts run; `None` outside a runtime, or with restarts off
+    restarts_on: Option<Handle>,
+    restart_delay: Duration,
+    max_restarts: u64,
+    degraded: AtomicBool,
+    panics: AtomicU64,
+    restarts: AtomicU64,
+    last_panic: Mutex<Option<Panic>>,
+}
+
+impl Supervised {
+    fn driver(&self) -> &Arc<dyn GnosDriver> {
+        self.generations[self.current.load(Ordering::Acquire)].get()
+            .expect("the current generation is always built")
+    }
+    
+    /// Record a panic caught in `op` and take the driver out of service,
+    /// returning the error the call fails with
+    fn panicked(self: &Arc<Self>, op: &'static str, path: &Path, payload: Box<dyn Any + Send>) -> GnosError {
+        let message = panic_message(payload.as_ref());
+        error!("💥 {} driver panicked in {} of {}: {}", self.label, op, path.display(), message);
+        self.panics.fetch_add(1, Ordering::Relaxed);
+        *self.last_panic.lock().unwrap() = Some(Panic {
+            at: SystemTime::now(),
+            op,
+            path: path.to_path_buf(),
+            message: message.clone(),
+        });
+        
+        if !self.degraded.swap(true, Ordering::AcqRel) {
+            match &self.restarts_on {
+                Some(runtime) if self.restarts.load(Ordering::Relaxed) < self.max_restarts => {
+                    warn!("🩹 {} driver degraded; restarting it in {:?}", self.label, self.restart_delay);
+                    runtime.spawn(self.clone().restart());
+                }
+                _ => warn!("🩹 {} driver degraded until the next mount", self.label),
+            }
+        }
+        GnosError::Driver(format!("{} driver panicked: {}", self.label, message))
+    }
+    
+    /// Put the driver back in service after the delay, built afresh if it
+    /// can be, retrying while restarts remain
+    async fn restart(self: Arc<Self>) {
+        loop {
+            tokio::time::sleep(self.restart_delay).await;
+            let attempt = self.restarts.fetch_add(1, Ordering::Relaxed) + 1;
+            let rebuilt = match &self.rebuild {
+                Some(rebuild) => match AssertUnwindSafe(rebuild()).catch_unwind().await {
+                    Ok(rebuilt) => rebuilt,
+                    Err(payload) => Err(GnosError::Driver(format!("panicked: {}", panic_message(payload.as_ref())))),
+                },
+                None => Ok(self.driver().clone()),
+            };
+            
+            match rebuilt {
+                Ok(driver) => {
+                    if self.rebuild.is_some() {
+                        // A generation per restart allowed, and failed
+                        // attempts use none, so there is always room
+                        let next = self.current.load(Ordering::Acquire) + 1;
+                        let _ = self.generations[next].set(driver);
+                        self.current.store(next, Ordering::Release);
+                    }
+                    self.degraded.store(false, Ordering::Release);
+                    info!("♻️ {} driver restarted ({} of {})", self.label, attempt, self.max_restarts);
+                    return;
+                }
+                Err(e) if attempt < self.max_restarts => {
+                    warn!("❌ Failed to restart {} driver, trying again: {}", self.label, e);
+                }
+                Err(e) => {
+                    warn!("❌ Failed to restart {} driver, leaving it degraded: {}", self.label, e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// What a panic was raised with, when it is text
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload.downcast_ref::<&str>().map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "non-text panic payload".to_string())
+}
+
+/// Health of every driver; cheap to share between drivers
+pub struct Supervisor {
+    config: RecoveryConfig,
+    drivers: Mutex<Vec<Arc<Supervised>>>,
+}
+
+impl Supervisor {
+    pub fn new(config: &RecoveryConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config: config.clone(),
+            drivers: Mutex::new(Vec::new()),
+        })
+    }
+    
+    /// Labels of the degraded drivers, sorted
+    pub fn degraded(&self) -> Vec<String> {
+        let mut labels: Vec<String> = self.drivers.lock().unwrap().iter()
+            .filter(|driver| driver.degraded.load(Ordering::Acquire))
+            .map(|driver| driver.label.clone())
+            .collect();
+        labels.sort();
+        labels
+    }
+    
+    /// Degraded state and panics per driver, for `/proc/gnos/metrics`
+    pub fn gauges(&self) -> Vec<LabelledGauge> {
+        let drivers = self.drivers.lock().unwrap();
+        let per_driver = |value: fn(&Supervised) -> f64| {
+            drivers.iter().map(|driver| (driver.label.clone(), value(driver))).collect()
+        };
+        vec![
+            LabelledGauge {
+                name: "gnos_driver_degraded",
+                help: "Whether a panic has taken the driver out of service",
+                label: "driver",
+                values: per_driver(|driver| u8::from(driver.degraded.load(Ordering::Acquire)) as f64),
+            },
+            LabelledGauge {
+                name: "gnos_driver_panics",
+                help: "Panics caught in the driver's calls",
+                label: "driver",
+                values: per_driver(|driver| driver.panics.load(Ordering::Relaxed) as f64),
+            },
+        ]
+    }
+    
+    /// Plain-text view for `/proc/gnos/recovery`
+    pub fn status_report(&self) -> String {
+        let mut report = format!(
+            "restart: {}\n\ndriver\tstate\tpanics\trestarts\tlast panic\n",
+            if self.config.restart {
+                format!("after {}s, at most {} times", self.config.restart_delay_seconds, self.config.max_restarts)
+            } else {
+                "off".to_string()
+            },
+        );
+        let mut drivers = self.drivers.lock().unwrap().clone();
+        drivers.sort_by(|a, b| a.label.cmp(&b.label));
+        for driver in drivers {
+            report.push_str(&format!(
+                "{}\t{}\t{}\t{}",
+                driver.label,
+                if driver.degraded.load(Ordering::Acquire) { "degraded" } else { "ok" },
+                driver.panics.load(Ordering::Relaxed),
+                driver.restarts.load(Ordering::Relaxed),
+            ));
+            if let Some(panic) = driver.last_panic.lock().unwrap().as_ref() {
+                let timestamp: chrono::DateTime<chrono::Utc> = panic.at.into();
+                report.push_str(&format!("\t{} {} {}: {}",
+                                         timestamp.to_rfc3339(), panic.op, panic.path.display(), panic.message));
+            }
+            report.push('\n');
+        }
+        report
+    }
+}
+
+/// A driver whose panics are caught and degrade only itself
+pub struct IsolatedDriver {
+    name: &'static str,
+    state: Arc<Supervised>,
+}
+
+impl IsolatedDriver {
+    /// `rebuild` builds `inner` again for restarts; without it a restart
+    /// puts `inner` itself back in service
+    pub fn new(label: &str, inner: Arc<dyn GnosDriver>, rebuild: Option<Rebuild>, supervisor: &Supervisor) -> Self {
+        let config = &supervisor.config;
+        let restarts_on = config.restart.then(Handle::try_current).and_then(|runtime| runtime.ok());
+        let generations = 1 + if restarts_on.is_some() && rebuild.is_some() { config.max_restarts as usize } else { 0 };
+        let generations: Box<[OnceLock<Arc<dyn GnosDriver>>]> = (0..generations).map(|_| OnceLock::new()).collect();
+        let _ = generations[0].set(inner.clone());
+        
+        let state = Arc::new(Supervised {
+            label: label.to_string(),
+            generations,
+            current: AtomicUsize::new(0),
+            rebuild,
+            restarts_on,
+            restart_delay: Duration::from_secs(config.restart_delay_seconds),
+            max_restarts: u64::from(config.max_restarts),
+            degraded: AtomicBool::new(false),
+            panics: AtomicU64::new(0),
+            restarts: AtomicU64::new(0),
+            last_panic: Mutex::new(None),
+        });
+        supervisor.drivers.lock().unwrap().push(state.clone());
+        Self { name: inner.name(), state }
+    }
+    
+    /// Run `call` on the current instance, unless the driver is degraded
+    async fn guard<'a, T, F>(&'a self, op: &'static str, path: &Path, call: impl FnOnce(&'a dyn GnosDriver) -> F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        if self.state.degraded.load(Ordering::Acquire) {
+            return Err(GnosError::Unreachable(format!("{} driver is degraded after a panic", self.state.label)));
+        }
+        let driver = self.state.driver().as_ref();
+        // Inside the async block so a panic building the future is caught too
+        match AssertUnwindSafe(async move { call(driver).await }).catch_unwind().await {
+            Ok(result) => result,
+            Err(payload) => Err(self.state.panicked(op, path, payload)),
+        }
+    }
+    
+    /// A hint from the current instance, or `fallback` if giving it panics
+    fn hint<'a, T>(&'a self, op: &'static str, path: &Path, ask: impl FnOnce(&'a dyn GnosDriver) -> T, fallback: T) -> T {
+        let driver = self.state.driver().as_ref();
+        match panic::catch_unwind(AssertUnwindSafe(|| ask(driver))) {
+            Ok(answer) => answer,
+            Err(payload) => {
+                self.state.panicked(op, path, payload);
+                fallback
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl GnosDriver for IsolatedDriver {
+    async fn read(&self, path: &Path) -> Result<Bytes> {
+        self.guard("read", path, |driver| driver.read(path)).await
+    }
+    
+    async fn read_checked(&self, path: &Path) -> Result<(Bytes, Option<Checksum>)> {
+        self.guard("read", path, |driver| driver.read_checked(path)).await
+    }
+    
+    async fn read_range(&self, path: &Path, offset: u64, len: u64) -> Result<Bytes> {
+        self.guard("read", path, |driver| driver.read_range(path, offset, len)).await
+    }
+    
+    fn streams(&self, path: &Path) -> bool {
+        self.hint("streams", path, |driver| driver.streams(path), false)
+    }
+    
+    async fn read_growing(&self, path: &Path, have: u64) -> Result<Growth> {
+        self.guard("read", path, |driver| driver.read_growing(path, have)).await
+    }
+    
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.guard("write", path, |driver| driver.write(path, data)).await
+    }
+    
+    fn upload_checksums(&self, path: &Path) -> &[ChecksumAlgorithm] {
+        self.hint("upload_checksums", path, |driver| driver.upload_checksums(path), &[])
+    }
+    
+    async fn write_with(&self, path: &Path, data: &[u8], options: &WriteOptions) -> Result<Option<Checksum>> {
+        self.guard("write", path, |driver| driver.write_with(path, data, options)).await
+    }
+    
+    fn conditional_writes(&self, path: &Path) -> bool {
+        self.hint("conditional_writes", path, |driver| driver.conditional_writes(path), false)
+    }
+    
+    fn accepted_encodings(&self) -> &[&'static str] {
+        self.hint("accepted_encodings", Path::new("/"), |driver| driver.accepted_encodings(), &[])
+    }
+    
+    async fn write_encoded(&self, path: &Path, data: &[u8], encoding: &str) -> Result<()> {
+        self.guard("write", path, |driver| driver.write_encoded(path, data, encoding)).await
+    }
+    
+    fn invokes(&self, path: &Path) -> bool {
+        self.hint("invokes", path, |driver| driver.invokes(path), false)
+    }
+    
+    async fn invoke(&self, path: &Path, payload: &[u8]) -> Result<Bytes> {
+        self.guard("invoke", path, |driver| driver.invoke(path, payload)).await
+    }
+    
+    fn supports_parts(&self, path: &Path) -> bool {
+        self.hint("supports_parts", path, |driver| driver.supports_parts(path), false)
+    }
+    
+    fn part_policy(&self, path: &Path) -> PartPolicy {
+        self.hint("part_policy", path, |driver| driver.part_policy(path), PartPolicy::default())
+    }
+    
+    async fn begin_parts(&self, path: &Path) -> Result<String> {
+        self.guard("begin_parts", path, |driver| driver.begin_parts(path)).await
+    }
+    
+    async fn write_part(&self, path: &Path, upload_id: &str, part: u64, data: &[u8]) -> Result<()> {
+        self.guard("write_part", path, |driver| driver.write_part(path, upload_id, part, data)).await
+    }
+    
+    async fn complete_parts(&self, path: &Path, upload_id: &str, parts: u64) -> Result<()> {
+        self.guard("complete_parts", path, |driver| driver.complete_parts(path, upload_id, parts)).await
+    }
+    
+    async fn abort_parts(&self, path: &Path, upload_id: &str) -> Result<()> {
+        self.guard("abort_parts", path, |driver| driver.abort_parts(path, upload_id)).await
+    }
+    
+    fn supports_batches(&self) -> bool {
+        self.hint("supports_batches", Path::new("/"), |driver| driver.supports_batches(), false)
+    }
+    
+    async fn commit_batch(&self, ops: &[BatchOp]) -> Result<()> {
+        let path = ops.first().map_or(Path::new("/"), BatchOp::path);
+        self.guard("commit_batch", path, |driver| driver.commit_batch(ops)).await
+    }
+    
+    async fn materialize(&self, path: &Path, params: &PathParams) -> Result<()> {
+        self.guard("materialize", path, |driver| driver.materialize(path, params)).await
+    }
+    
+    async fn create_dir(&self, path: &Path) -> Result<()> {
+        self.guard("create_dir", path, |driver| driver.create_dir(path)).await
+    }
+    
+    async fn delete(&self, path: &Path) -> Result<()> {
+        self.guard("delete", path, |driver| driver.delete(path)).await
+    }
+    
+    async fn list(&self, path: &Path) -> Result<Vec<String>> {
+        self.guard("list", path, |driver| driver.list(path)).await
+    }
+    
+    async fn list_with_metadata(&self, path: &Path) -> Result<Vec<(String, Option<ResourceMetadata>)>> {
+        self.guard("list", path, |driver| driver.list_with_metadata(path)).await
+    }
+    
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        self.guard("exists", path, |driver| driver.exists(path)).await
+    }
+    
+    async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+        self.guard("metadata", path, |driver| driver.metadata(path)).await
+    }
+    
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    
+    fn supports(&self, path: &Path) -> bool {
+        self.hint("supports", path, |driver| driver.supports(path), false)
+    }
+    
+    fn prefixes(&self) -> Vec<PathBuf> {
+        self.state.driver().prefixes()
+    }
+    
+    fn cache_mode(&self, path: &Path) -> CacheMode {
+        self.hint("cache_mode", path, |driver| driver.cache_mode(path), CacheMode::DirectIo)
+    }
+    
+    fn private(&self, path: &Path) -> bool {
+        // Fail closed: a path of unknown privacy is kept out of shared caches
+        self.hint("private", path, |driver| driver.private(path), true)
+    }
+    
+    fn watch(&self, path: &Path) -> Option<ChangeStream> {
+        self.hint("watch", path, |driver| driver.watch(path), None)
+    }
+}
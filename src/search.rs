@@ -0,0 +1,408 @@
+//! Namespace search
+//!
+//! A query names a scope and any mix of a file-name glob, content words, a
+//! content regex and metadata filters:
+//!
+//! ```text
+//! scope=/cloud/aws name=*.log text="disk full" content="sd[a-z]+" min_size=1K max_size=1G newer=7d
+//! ```
+//!
+//! Scopes inside a prefix the content index has crawled are answered from
+//! the index, reading only files a `content` regex still has to check.
+//! Otherwise the engine walks the scope breadth-first, fanning out across
+//! every driver the scope spans and listing each level's directories in
+//! parallel. Name and metadata filters are applied to listings; content is
+//! only read for files that pass them.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use futures::stream::{self, StreamExt};
+use regex::bytes::Regex;
+use tracing::{debug, warn};
+
+use crate::config::SearchConfig;
+use crate::drivers::{DriverRegistry, ResourceMetadata};
+use crate::index::{self, ContentIndex};
+use crate::security::{CapabilityManager, Operation};
+use crate::{GnosError, Result};
+
+/// Where a scope without a driver of its own is searched from
+const DRIVER_ROOTS: &[&str] = &["/cloud", "/net", "/dev", "/proc"];
+
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub scope: PathBuf,
+    /// Glob over the entry name; `*` and `?` are supported
+    pub name: Option<String>,
+    /// Words the content must contain, in any order; matched case-insensitively
+    pub words: Vec<String>,
+    pub content: Option<Regex>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Only entries modified within this long
+    pub newer: Option<Duration>,
+}
+
+impl SearchQuery {
+    fn needs_metadata(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some() || self.newer.is_some()
+    }
+    
+    pub(crate) fn matches_name(&self, path: &Path) -> bool {
+        match (&self.name, path.file_name()) {
+            (None, _) => true,
+            (Some(pattern), Some(name)) => glob_match(pattern.as_bytes(), name.to_string_lossy().as_bytes()),
+            (Some(_), None) => false,
+        }
+    }
+    
+    pub(crate) fn matches_metadata(&self, metadata: &ResourceMetadata) -> bool {
+        if self.min_size.is_some_and(|min| metadata.size < min) || self.max_size.is_some_and(|max| metadata.size > max) {
+            return false;
+        }
+        match self.newer {
+            Some(window) => SystemTime::now().duration_since(metadata.last_modified).map_or(true, |age| age <= window),
+            None => true,
+        }
+    }
+}
+
+impl FromStr for SearchQuery {
+    type Err = GnosError;
+    
+    fn from_str(text: &str) -> Result<Self> {
+        let mut query = SearchQuery {
+            scope: PathBuf::from("/"),
+            name: None,
+            words: Vec::new(),
+            content: None,
+            min_size: None,
+            max_size: None,
+            newer: None,
+        };
+        
+        for term in split_terms(text)? {
+            let (key, value) = term.split_once('=')
+                .ok_or_else(|| GnosError::InvalidPath(format!("search term without a value: {}", term)))?;
+            match key {
+                "scope" => query.scope = PathBuf::from(value),
+                "name" => query.name = Some(value.to_string()),
+                "text" => query.words.extend(index::words(value.as_bytes())),
+                "content" => {
+                    let regex = Regex::new(value)
+                        .map_err(|e| GnosError::InvalidPath(format!("bad content pattern: {}", e)))?;
+                    query.content = Some(regex);
+                }
+                "min_size" => query.min_size = Some(parse_size(value)?),
+                "max_size" => query.max_size = Some(parse_size(value)?),
+                "newer" => query.newer = Some(parse_age(value)?),
+                _ => return Err(GnosError::InvalidPath(format!("unknown search term: {}", key))),
+            }
+        }
+        
+        if !query.scope.is_absolute() {
+            return Err(GnosError::InvalidPath(format!("search scope must be absolute: {}", query.scope.display())));
+        }
+        Ok(query)
+    }
+}
+
+/// Outcome of a finished search
+#[derive(Debug, Default, Clone)]
+pub struct SearchStats {
+    pub directories: usize,
+    pub matches: usize,
+    pub errors: usize,
+    /// Stopped at `max_results` rather than running out of namespace
+    pub truncated: bool,
+}
+
+pub struct SearchEngine {
+    driver_registry: Arc<DriverRegistry>,
+    capability_manager: Arc<CapabilityManager>,
+    concurrency: usize,
+    max_depth: usize,
+    max_results: usize,
+    max_content_bytes: u64,
+    index: Option<Arc<ContentIndex>>,
+}
+
+impl SearchEngine {
+    pub fn new(
+        config: &SearchConfig,
+        driver_registry: Arc<DriverRegistry>,
+        capability_manager: Arc<CapabilityManager>,
+    ) -> Self {
+        Self {
+            driver_registry,
+            capability_manager,
+            concurrency: config.concurrency.max(1),
+            max_depth: config.max_depth,
+            max_results: config.max_results,
+            max_content_bytes: config.max_content_mb * 1024 * 1024,
+            index: None,
+        }
+    }
+    
+    /// Answer queries inside indexed prefixes from `index`
+    pub fn with_index(mut self, index: Arc<ContentIndex>) -> Self {
+        self.index = Some(index);
+        self
+    }
+    
+    /// Run `query`, calling `on_match` with each matching path as it is found
+    pub async fn run<F>(&self, query: &SearchQuery, on_match: F) -> Result<SearchStats>
+    where
+        F: Fn(&Path) + Send + Sync,
+    {
+        self.capability_manager.check_permission(&query.scope, Operation::List).await?;
+        
+        if let Some(index) = self.index.as_ref().filter(|index| index.covers(&query.scope)) {
+            return Ok(self.run_indexed(index, query, on_match).await);
+        }
+        
+        let mut stats = SearchStats::default();
+        let matches = AtomicUsize::new(0);
+        let mut level = self.starting_points(&query.scope);
+        if level.is_empty() {
+            return Err(GnosError::PathNotFound(query.scope.display().to_string()));
+        }
+        
+        for depth in 0..=self.max_depth {
+            if level.is_empty() || stats.truncated {
+                break;
+            }
+            
+            let listings: Vec<_> = stream::iter(std::mem::take(&mut level))
+                .map(|dir| async move {
+                    let result = self.list_dir(&dir).await;
+                    (dir, result)
+                })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+            
+            let mut candidates = Vec::new();
+            for (dir, result) in listings {
+                match result {
+                    Ok(entries) => {
+                        stats.directories += 1;
+                        for (path, metadata) in entries {
+                            let is_dir = metadata.as_ref().is_some_and(|m| m.is_directory);
+                            if is_dir && depth < self.max_depth {
+                                level.push(path.clone());
+                            }
+                            if !is_dir && query.matches_name(&path)
+                                && metadata.as_ref().map_or(!query.needs_metadata(), |m| query.matches_metadata(m)) {
+                                candidates.push((path, metadata));
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        stats.errors += 1;
+                        debug!("Search skipped {}: {}", dir.display(), e);
+                    }
+                }
+            }
+            
+            // Content is only read for entries the cheaper filters let through
+            stream::iter(candidates)
+                .map(|(path, metadata)| async move {
+                    let matched = self.matches_content(query, &path, metadata.as_ref(), true).await;
+                    (path, matched)
+                })
+                .buffer_unordered(self.concurrency)
+                .for_each(|(path, matched)| {
+                    if matched && matches.fetch_add(1, Ordering::Relaxed) < self.max_results {
+                        on_match(&path);
+                    }
+                    futures::future::ready(())
+                })
+                .await;
+            
+            if matches.load(Ordering::Relaxed) >= self.max_results {
+                stats.truncated = true;
+            }
+        }
+        
+        stats.matches = std::cmp::min(matches.load(Ordering::Relaxed), self.max_results);
+        Ok(stats)
+    }
+    
+    /// Filter the index's candidates, reading only what a content regex must check
+    async fn run_indexed<F>(&self, index: &ContentIndex, query: &SearchQuery, on_match: F) -> SearchStats
+    where
+        F: Fn(&Path) + Send + Sync,
+    {
+        let candidates = index.search(query);
+        debug!("Index answered {} with {} candidates", query.scope.display(), candidates.len());
+        
+        let mut stats = SearchStats::default();
+        let matched: Vec<PathBuf> = stream::iter(candidates)
+            .map(|(path, metadata)| async move {
+                // The index already checked the words
+                let matched = self.matches_content(query, &path, Some(&metadata), false).await;
+                (path, matched)
+            })
+            .buffered(self.concurrency)
+            .filter_map(|(path, matched)| futures::future::ready(matched.then_some(path)))
+            .take(self.max_results + 1)
+            .collect()
+            .await;
+        
+        for path in matched.iter().take(self.max_results) {
+            on_match(path);
+        }
+        stats.truncated = matched.len() > self.max_results;
+        stats.matches = std::cmp::min(matched.len(), self.max_results);
+        stats
+    }
+    
+    /// Roots to walk: the scope itself, or each driver root beneath it
+    fn starting_points(&self, scope: &Path) -> Vec<PathBuf> {
+        if self.driver_registry.get_driver(scope).is_some() {
+            return vec![scope.to_path_buf()];
+        }
+        DRIVER_ROOTS.iter()
+            .map(PathBuf::from)
+            .filter(|root| root.starts_with(scope) && self.driver_registry.get_driver(root).is_some())
+            .collect()
+    }
+    
+    async fn list_dir(&self, dir: &Path) -> Result<Vec<(PathBuf, Option<ResourceMetadata>)>> {
+        let driver = self.driver_registry.get_driver(dir)
+            .ok_or_else(|| GnosError::PathNotFound(dir.display().to_string()))?;
+        
+        let mut entries = Vec::new();
+        for (name, metadata) in driver.list_with_metadata(dir).await? {
+            let path = dir.join(&name);
+            // Without metadata there is no telling directories from files, so ask
+            let metadata = match metadata {
+                Some(metadata) => Some(metadata),
+                None => driver.metadata(&path).await.ok(),
+            };
+            entries.push((path, metadata));
+        }
+        Ok(entries)
+    }
+    
+    async fn matches_content(&self, query: &SearchQuery, path: &Path, metadata: Option<&ResourceMetadata>, check_words: bool) -> bool {
+        let check_words = check_words && !query.words.is_empty();
+        if query.content.is_none() && !check_words {
+            return true;
+        }
+        if metadata.is_some_and(|m| m.size > self.max_content_bytes) {
+            return false;
+        }
+        if self.capability_manager.check_permission(path, Operation::Read).await.is_err() {
+            return false;
+        }
+        
+        let Some(driver) = self.driver_registry.get_driver(path) else {
+            return false;
+        };
+        match driver.read(path).await {
+            Ok(data) => {
+                query.content.as_ref().is_none_or(|pattern| pattern.is_match(&data))
+                    && (!check_words || contains_words(&data, &query.words))
+            }
+            Err(e) => {
+                warn!("❌ Search could not read {}: {}", path.display(), e);
+                false
+            }
+        }
+    }
+}
+
+fn contains_words(data: &[u8], words: &[String]) -> bool {
+    let found = index::words(data);
+    words.iter().all(|word| found.binary_search(word).is_ok())
+}
+
+/// Whitespace-separated terms; double quotes keep a value's spaces
+fn split_terms(text: &str) -> Result<Vec<String>> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        return Err(GnosError::InvalidPath("unterminated quote in search".to_string()));
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    Ok(terms)
+}
+
+/// Byte count with an optional K, M or G suffix
+fn parse_size(value: &str) -> Result<u64> {
+    let (digits, multiplier) = match value.chars().last() {
+        Some('K' | 'k') => (&value[..value.len() - 1], 1024),
+        Some('M' | 'm') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('G' | 'g') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    digits.parse::<u64>()
+        .map(|n| n.saturating_mul(multiplier))
+        .map_err(|_| GnosError::InvalidPath(format!("bad size: {}", value)))
+}
+
+/// Age such as 30s, 15m, 2h or 7d
+fn parse_age(value: &str) -> Result<Duration> {
+    let unit_at = value.char_indices().last().map_or(0, |(at, _)| at);
+    let (digits, unit) = value.split_at(unit_at);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(GnosError::InvalidPath(format!("bad age: {}", value))),
+    };
+    digits.parse::<u64>()
+        .map(|n| Duration::from_secs(n.saturating_mul(seconds)))
+        .map_err(|_| GnosError::InvalidPath(format!("bad age: {}", value)))
+}
+
+pub(crate) fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and how much of the name it has swallowed
+    let mut star: Option<(usize, usize)> = None;
+    
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    
+    pattern[p..].iter().all(|&c| c == b'*')
+}
@@ -1,9 +1,34 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::{Duration, SystemTime};
 use serde::{Deserialize, Serialize};
 use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+use crate::telemetry::RequestId;
+use crate::tenants::{tenant_of, tenant_root};
+use crate::vfs::path::{is_within, normalize};
 use crate::{GnosError, Result};
 
+mod capabilities;
+
+pub use capabilities::SigningKey;
+
+/// Audit entries kept in memory; older ones are dropped first
+const AUDIT_LOG_CAPACITY: usize = 10_000;
+
+/// Audit entries kept per tenant
+const TENANT_AUDIT_LOG_CAPACITY: usize = 1_000;
+
+/// Break-glass activations waiting for the alert monitor
+const BREAK_GLASS_EVENTS: usize = 64;
+
+/// How often idle tokens are revoked and expired ones forgotten
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Operation {
     Read,
@@ -13,7 +38,7 @@ pub enum Operation {
 }
 
 impl Operation {
-    fn to_bit(&self) -> u8 {
+    fn to_bit(self) -> u8 {
         match self {
             Operation::Read => 0b100,
             Operation::Write => 0b010,
@@ -29,6 +54,64 @@ pub struct Capability {
     pub permissions: u8, // rwx bits
     pub expiration: SystemTime,
     pub owner: String,
+    /// Set on emergency grants from `gnos-mount breakglass`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub break_glass: Option<BreakGlass>,
+    /// When it was minted; `security.max_token_lifetime` counts from here
+    /// whatever `expiration` says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued: Option<SystemTime>,
+    /// Refused before this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<SystemTime>,
+    /// Revoked once unused for this long
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_idle_seconds: Option<u64>,
+    /// Subtrees within `path` it refuses operations in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<Deny>,
+}
+
+/// Operations refused in a subtree however broad, or specific, the grant
+/// that would otherwise allow them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deny {
+    pub path: PathBuf,
+    /// rwx letters refused; all three unless narrowed
+    #[serde(default = "default_deny_perms")]
+    pub perms: String,
+}
+
+fn default_deny_perms() -> String {
+    "rwx".to_string()
+}
+
+impl Deny {
+    /// `PATH` or `PATH:PERMS`, e.g. `/cloud/prod/secrets` or `/cloud/prod:w`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (path, perms) = match spec.rsplit_once(':') {
+            Some((path, perms)) if !perms.is_empty() && perms.chars().all(|c| "rwx".contains(c)) => (path, perms),
+            _ => (spec, "rwx"),
+        };
+        Ok(Self { path: normalize(Path::new(path))?, perms: perms.to_string() })
+    }
+    
+    /// rwx bits it refuses on the canonical `path`; letters it can't parse
+    /// refuse everything rather than nothing
+    pub fn refused(&self, path: &Path) -> u8 {
+        if !is_within(path, &self.path) {
+            return 0;
+        }
+        parse_permissions(&self.perms).unwrap_or(0b111)
+    }
+}
+
+/// Why an emergency grant was issued; every use is audited with it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakGlass {
+    /// Tells grants apart in alerts and the audit log
+    pub id: String,
+    pub reason: String,
 }
 
 impl Capability {
@@ -40,24 +123,38 @@ impl Capability {
         SystemTime::now() > self.expiration
     }
     
+    /// Whether its `not_before` time is still to come
+    pub fn is_premature(&self) -> bool {
+        self.not_before.is_some_and(|not_before| SystemTime::now() < not_before)
+    }
+    
+    /// Whether the canonical `path` lies within the capability's path
     pub fn is_valid_for_path(&self, path: &Path) -> bool {
-        path.starts_with(&self.path)
+        is_within(path, &self.path)
+    }
+    
+    /// The entry of its own that refuses `operation` on the canonical `path`
+    pub fn denial(&self, path: &Path, operation: Operation) -> Option<&Deny> {
+        self.deny.iter().find(|deny| deny.refused(path) & operation.to_bit() != 0)
     }
     
-    pub fn to_token(&self) -> Result<String> {
+    /// `gnos.<payload>.<signature>`, signed with `key`
+    pub fn to_token(&self, key: &SigningKey) -> Result<String> {
         let json = serde_json::to_string(self)
             .map_err(|e| GnosError::Driver(format!("Failed to serialize capability: {}", e)))?;
         
         let encoded = URL_SAFE_NO_PAD.encode(json.as_bytes());
-        Ok(format!("gnos.{}", encoded))
+        let signature = key.sign(&encoded);
+        Ok(format!("gnos.{}.{}", encoded, signature))
     }
     
+    /// What a token claims, without checking its signature; only
+    /// `CapabilityManager` decides whether to believe it
     pub fn from_token(token: &str) -> Result<Self> {
-        if !token.starts_with("gnos.") {
-            return Err(GnosError::PermissionDenied("Invalid token format".to_string()));
-        }
-        
-        let encoded = &token[5..];
+        Self::from_payload(split_token(token)?.0)
+    }
+    
+    fn from_payload(encoded: &str) -> Result<Self> {
         let json_bytes = URL_SAFE_NO_PAD.decode(encoded)
             .map_err(|_| GnosError::PermissionDenied("Invalid token encoding".to_string()))?;
         
@@ -71,10 +168,67 @@ impl Capability {
     }
 }
 
+/// A token's payload and, when it has one, its signature
+fn split_token(token: &str) -> Result<(&str, Option<&str>)> {
+    let rest = token.strip_prefix("gnos.")
+        .ok_or_else(|| GnosError::PermissionDenied("Invalid token format".to_string()))?;
+    Ok(match rest.split_once('.') {
+        Some((payload, signature)) => (payload, Some(signature)),
+        None => (rest, None),
+    })
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityConfig {
     pub default_permissions: u8,
     pub max_token_lifetime: Duration,
+    /// File the audit log is appended to when the daemon shuts down
+    #[serde(default)]
+    pub audit_file: Option<PathBuf>,
+    #[serde(default)]
+    pub break_glass: BreakGlassConfig,
+    /// What becomes of a write still buffered or half uploaded when the
+    /// capability that opened it is revoked; new chunks are refused either way
+    #[serde(default)]
+    pub revoked_writes: RevokedWrites,
+    /// Subtrees refused to every capability and to development mode,
+    /// e.g. `[[security.deny]]`
+    #[serde(default)]
+    pub deny: Vec<Deny>,
+    /// Tokens the mount itself holds, one per line, used for local access
+    /// in place of `GNOS_TOKEN`; also `gnos-mount mount --token-file`
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
+    /// Tokens revoked ahead of their expiry, one per line, re-read as it
+    /// changes
+    #[serde(default)]
+    pub revocation_file: Option<PathBuf>,
+    /// HMAC key tokens are signed and checked with, created on first use;
+    /// `gnos-mount token` and the daemon must share it
+    #[serde(default = "default_signing_key_file")]
+    pub signing_key_file: PathBuf,
+    /// Refuse tokens without a valid signature; turned off only to keep
+    /// accepting tokens minted before they were signed
+    #[serde(default = "default_require_signatures")]
+    pub require_signatures: bool,
+}
+
+fn default_signing_key_file() -> PathBuf {
+    PathBuf::from("/var/lib/gnos/signing.key")
+}
+
+fn default_require_signatures() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevokedWrites {
+    /// Drop what the handle holds and discard parts already sent
+    #[default]
+    Abort,
+    /// Finish sending what was written before the revocation, and audit it
+    Complete,
 }
 
 impl Default for SecurityConfig {
@@ -82,32 +236,852 @@ impl Default for SecurityConfig {
         Self {
             default_permissions: 0b100, // Read-only by default
             max_token_lifetime: Duration::from_secs(24 * 3600), // 24 hours
+            audit_file: None,
+            break_glass: BreakGlassConfig::default(),
+            revoked_writes: RevokedWrites::default(),
+            deny: Vec::new(),
+            token_file: None,
+            revocation_file: None,
+            signing_key_file: default_signing_key_file(),
+            require_signatures: default_require_signatures(),
+        }
+    }
+}
+
+/// Limits on emergency grants from `gnos-mount breakglass`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BreakGlassConfig {
+    /// Longest a grant may be issued for
+    pub max_minutes: u64,
+    /// How long a grant keeps working once first used; it is revoked after
+    pub session_minutes: u64,
+}
+
+impl Default for BreakGlassConfig {
+    fn default() -> Self {
+        Self {
+            max_minutes: 60,
+            session_minutes: 15,
         }
     }
 }
 
+/// Named grants that can be minted without spelling out path, permissions
+/// and lifetime each time: `[capability.templates.<name>]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CapabilityConfig {
+    pub templates: BTreeMap<String, CapabilityTemplate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapabilityTemplate {
+    /// Subtree instances are limited to
+    pub path: PathBuf,
+    /// rwx letters, e.g. "r"
+    #[serde(default = "default_template_perms")]
+    pub perms: String,
+    /// How long instances last: seconds, or a number followed by s, m, h or d
+    pub ttl: String,
+    /// Owner instances are audited as; the template's name if unset
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Revoke instances unused for this long, in the form of `ttl`
+    #[serde(default)]
+    pub max_idle: Option<String>,
+    /// Subtrees within `path` instances are refused in
+    #[serde(default)]
+    pub deny: Vec<Deny>,
+}
+
+fn default_template_perms() -> String {
+    "r".to_string()
+}
+
+impl CapabilityConfig {
+    /// A capability from template `name`, covering `path` when given, which
+    /// may narrow the template's path but not leave it
+    pub fn instantiate(&self, name: &str, path: Option<&Path>) -> Result<Capability> {
+        let template = self.templates.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.templates.keys().map(String::as_str).collect();
+            GnosError::Config(format!("no capability template {} (configured: {})",
+                                           name, if known.is_empty() { "none".to_string() } else { known.join(", ") }))
+        })?;
+        
+        let granted = normalize(&template.path)?;
+        let path = match path {
+            Some(path) => {
+                let path = normalize(path)?;
+                if !is_within(&path, &granted) {
+                    return Err(GnosError::PermissionDenied(format!(
+                        "template {} is limited to {}", name, granted.display())));
+                }
+                path
+            }
+            None => granted,
+        };
+        
+        let now = SystemTime::now();
+        Ok(Capability {
+            path,
+            permissions: parse_permissions(&template.perms)?,
+            expiration: now + parse_ttl(&template.ttl)?,
+            owner: template.owner.clone().unwrap_or_else(|| name.to_string()),
+            break_glass: None,
+            issued: Some(now),
+            not_before: None,
+            max_idle_seconds: template.max_idle.as_deref().map(parse_ttl).transpose()?.map(|idle| idle.as_secs()),
+            deny: template.deny.iter()
+                .map(|deny| {
+                    parse_permissions(&deny.perms)?;
+                    Ok(Deny { path: normalize(&deny.path)?, perms: deny.perms.clone() })
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// rwx bits from letters such as "rw"
+pub fn parse_permissions(perms: &str) -> Result<u8> {
+    perms.chars().try_fold(0u8, |bits, letter| match letter {
+        'r' => Ok(bits | 0b100),
+        'w' => Ok(bits | 0b010),
+        'x' => Ok(bits | 0b001),
+        _ => Err(GnosError::Config(format!("Invalid permission: {}", letter))),
+    })
+}
+
+/// "90", "90s", "30m", "8h" or "7d"
+pub fn parse_ttl(ttl: &str) -> Result<Duration> {
+    let ttl = ttl.trim();
+    let (number, unit) = ttl.find(|c: char| !c.is_ascii_digit())
+        .map_or((ttl, ""), |at| ttl.split_at(at));
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => 0,
+    };
+    match number.parse::<u64>() {
+        Ok(number) if seconds > 0 => Ok(Duration::from_secs(number.saturating_mul(seconds))),
+        _ => Err(GnosError::Config(format!("Invalid ttl {:?}: expected e.g. 90s, 30m, 8h or 7d", ttl))),
+    }
+}
+
+/// Tokens are remembered by a hash rather than kept in memory as they are
+fn token_key(token: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    token.hash(&mut hasher);
+    hasher.finish()
+}
+
 pub struct CapabilityManager {
     config: SecurityConfig,
+    /// `None` when the key file can't be read or created, which leaves
+    /// signed tokens unverifiable and so refused
+    signing_key: Option<SigningKey>,
+    audit_log: Mutex<VecDeque<AuditEntry>>,
+    /// Decisions about each tracked tenant's subtree, also kept in `audit_log`
+    tenant_audit_logs: Mutex<HashMap<String, VecDeque<AuditEntry>>>,
+    /// Newest entry already appended to `audit_file`
+    audit_flushed: Mutex<SystemTime>,
+    /// When each break-glass grant was first used, by grant ID
+    break_glass_used: Mutex<HashMap<String, SystemTime>>,
+    /// First uses of break-glass grants, for immediate alerts
+    break_glass_events: broadcast::Sender<AuditEntry>,
+    /// Tokens with an idle limit, by `token_key`
+    token_uses: Mutex<HashMap<u64, TokenUse>>,
+    /// Tokens revoked outright, by `token_key`, with the expiry after which
+    /// they needn't be remembered
+    revoked: Mutex<HashMap<u64, SystemTime>>,
+    /// Modification time of `revocation_file` when it was last read
+    revocations_read: Mutex<Option<SystemTime>>,
+    /// Tokens not yet seen count as idle from here at the earliest
+    started: SystemTime,
+    /// The mount's ambient credentials, from `token_file`
+    keyring: Mutex<Keyring>,
+}
+
+/// Capabilities held by the mount rather than presented by a process
+#[derive(Default)]
+struct Keyring {
+    tokens: Vec<String>,
+    /// Modification time of `token_file` when it was read, so a rotated
+    /// file is picked up
+    modified: Option<SystemTime>,
+}
+
+/// When a token with an idle limit was last used
+struct TokenUse {
+    last_used: SystemTime,
+    max_idle: Duration,
+    /// Its record is kept until then
+    expiration: SystemTime,
+    path: PathBuf,
+    owner: String,
+    /// Set by the cleanup task once it went unused too long
+    revoked: bool,
+}
+
+/// One permission decision
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub timestamp: SystemTime,
+    /// The FUSE operation that asked, to line up with spans and backend logs
+    pub request_id: Option<RequestId>,
+    pub operation: Operation,
+    pub path: PathBuf,
+    pub owner: String,
+    pub success: bool,
+    pub reason: Option<String>,
+}
+
+impl AuditEntry {
+    /// Tab-separated: time, operation, path, owner, decision, reason
+    fn line(&self) -> String {
+        let timestamp: chrono::DateTime<chrono::Utc> = self.timestamp.into();
+        format!("{}\t{:?}\t{}\t{}\t{}\t{}\n",
+                timestamp.to_rfc3339(), self.operation, self.path.display(), self.owner,
+                if self.success { "allowed" } else { "denied" },
+                self.reason.as_deref().unwrap_or("-"))
+    }
 }
 
 impl CapabilityManager {
     pub fn new(config: SecurityConfig) -> Self {
-        Self { config }
+        let signing_key = match SigningKey::load_or_create(&config.signing_key_file) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                warn!("🔏 No capability signing key, so no token can be issued or verified: {}", e);
+                None
+            }
+        };
+        let manager = Self {
+            config,
+            signing_key,
+            audit_log: Mutex::new(VecDeque::new()),
+            tenant_audit_logs: Mutex::new(HashMap::new()),
+            audit_flushed: Mutex::new(SystemTime::UNIX_EPOCH),
+            break_glass_used: Mutex::new(HashMap::new()),
+            break_glass_events: broadcast::channel(BREAK_GLASS_EVENTS).0,
+            token_uses: Mutex::new(HashMap::new()),
+            revoked: Mutex::new(HashMap::new()),
+            revocations_read: Mutex::new(None),
+            started: SystemTime::now(),
+            keyring: Mutex::new(Keyring::default()),
+        };
+        manager.reload_revocation_file();
+        manager
     }
     
+    /// Sign `capability` into a token this manager, and any sharing its
+    /// key file, accepts
+    pub fn issue(&self, capability: &Capability) -> Result<String> {
+        let key = self.signing_key.as_ref().ok_or_else(|| GnosError::Config(format!(
+            "no signing key at {} to sign the token with", self.config.signing_key_file.display())))?;
+        capability.to_token(key)
+    }
+    
+    /// The capability `token` carries, once its signature checks out
+    pub fn capability(&self, token: &str) -> Result<Capability> {
+        let (payload, signature) = split_token(token)?;
+        let signed = match (&self.signing_key, signature) {
+            (Some(key), Some(signature)) => key.verify(payload, signature),
+            _ => false,
+        };
+        if !signed && self.config.require_signatures {
+            return Err(GnosError::PermissionDenied("token is unsigned or its signature is invalid".to_string()));
+        }
+        Capability::from_payload(payload)
+    }
+    
+    /// Read the mount's ambient credentials from `token_file`: one token per
+    /// line, blank lines and `#` comments skipped. From then on local access
+    /// is decided with them, and `GNOS_TOKEN` is no longer consulted, so a
+    /// daemonized mount doesn't depend on whoever started it. Returns how
+    /// many were loaded, none without a `token_file`
+    pub fn load_token_file(&self) -> Result<usize> {
+        let Some(file) = &self.config.token_file else {
+            return Ok(0);
+        };
+        let content = std::fs::read_to_string(file)
+            .map_err(|e| GnosError::Config(format!("token file {}: {}", file.display(), e)))?;
+        let metadata = std::fs::metadata(file)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if metadata.permissions().mode() & 0o077 != 0 {
+                warn!("🔑 Token file {} is readable by others; chmod 600 it", file.display());
+            }
+        }
+        
+        let mut tokens = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // The token itself stays out of the error, which may be logged
+            let capability = self.capability(line).map_err(|e| {
+                GnosError::Config(format!("token file {} line {}: {}", file.display(), number + 1, e))
+            })?;
+            if capability.is_expired() {
+                warn!("🔑 Ambient capability for {} ({}) in {} has expired", capability.path.display(), capability.owner, file.display());
+            }
+            tokens.push(line.to_string());
+        }
+        if tokens.is_empty() {
+            return Err(GnosError::Config(format!("token file {} holds no tokens", file.display())));
+        }
+        
+        let count = tokens.len();
+        *self.keyring.lock().unwrap() = Keyring { tokens, modified: metadata.modified().ok() };
+        info!("🔑 Mount holds {} ambient capabilities from {}", count, file.display());
+        Ok(count)
+    }
+    
+    /// Read `token_file` again if it changed since, keeping the current
+    /// credentials when the new file can't be used
+    fn reload_token_file(&self) {
+        let Some(file) = &self.config.token_file else {
+            return;
+        };
+        let modified = std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_none() || modified == self.keyring.lock().unwrap().modified {
+            return;
+        }
+        if let Err(e) = self.load_token_file() {
+            warn!("🔑 Keeping the current ambient capabilities: {}", e);
+        }
+    }
+    
+    /// Revoke `token` ahead of its expiry: checks with it fail from now on,
+    /// as does the next chunk of any write it is fencing
+    pub fn revoke(&self, token: &str) -> Result<()> {
+        let capability = self.capability(token)?;
+        if self.revoked.lock().unwrap().insert(token_key(token), capability.expiration).is_none() {
+            info!("🔒 Revoked {}'s capability for {}", capability.owner, capability.path.display());
+            self.log_access(&capability.path, Operation::Execute, &capability.owner, false, Some("revoked".to_string()));
+        }
+        Ok(())
+    }
+    
+    /// Revoke the tokens listed in `revocation_file` if it changed since it
+    /// was last read; lines that aren't tokens are skipped
+    fn reload_revocation_file(&self) {
+        let Some(file) = &self.config.revocation_file else {
+            return;
+        };
+        let modified = std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_none() || modified == *self.revocations_read.lock().unwrap() {
+            return;
+        }
+        let content = match std::fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("🔒 Can't read revocation file {}: {}", file.display(), e);
+                return;
+            }
+        };
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(e) = self.revoke(line) {
+                warn!("🔒 Revocation file {} line {}: {}", file.display(), number + 1, e);
+            }
+        }
+        *self.revocations_read.lock().unwrap() = modified;
+    }
+    
+    /// Plain-text view for `/proc/gnos/keyring`: what each ambient
+    /// capability grants, never the token itself
+    pub fn keyring_report(&self) -> String {
+        let mut report = String::from("owner\tpath\tpermissions\texpires\tstate\n");
+        for token in &self.keyring.lock().unwrap().tokens {
+            let Ok(capability) = self.capability(token) else { continue };
+            let state = if capability.is_expired() {
+                "expired".to_string()
+            } else if capability.is_premature() {
+                "not yet valid".to_string()
+            } else {
+                self.lapsed(token, &capability).unwrap_or_else(|| "in force".to_string())
+            };
+            let letters: String = [(0b100, 'r'), (0b010, 'w'), (0b001, 'x')].iter()
+                .filter(|(bit, _)| capability.permissions & bit != 0)
+                .map(|(_, letter)| *letter)
+                .collect();
+            report.push_str(&format!("{}\t{}\t{}\t{}\t{}\n",
+                                     capability.owner, capability.path.display(), letters,
+                                     chrono::DateTime::<chrono::Utc>::from(capability.expiration).to_rfc3339(), state));
+        }
+        report
+    }
+    
+    /// The token the local process acts with for `operation` on `path`: the
+    /// first ambient credential that grants it, or failing that the first
+    /// one, so its refusal is what gets audited; `GNOS_TOKEN` when the mount
+    /// holds none
+    fn local_token(&self, path: &Path, operation: Operation) -> Option<String> {
+        let keyring = self.keyring.lock().unwrap();
+        if keyring.tokens.is_empty() {
+            return std::env::var("GNOS_TOKEN").ok();
+        }
+        let granting = normalize(path).ok()
+            .and_then(|path| keyring.tokens.iter().find(|token| self.grants(token, &path, operation)));
+        granting.or(keyring.tokens.first()).cloned()
+    }
+    
+    /// Whether `token` is in force and grants `operation` on the canonical
+    /// `path`, deny entries included
+    fn grants(&self, token: &str, path: &Path, operation: Operation) -> bool {
+        self.capability(token).is_ok_and(|capability| {
+            capability.is_valid_for_path(path)
+                && capability.allows(operation)
+                && !capability.is_expired()
+                && !capability.is_premature()
+                && self.lapsed(token, &capability).is_none()
+                && self.denial(Some(&capability), path, operation).is_none()
+        })
+    }
+    
+    /// Revoke idle tokens and forget expired ones in the background, until
+    /// the manager is dropped
+    pub fn spawn_cleanup(self: &Arc<Self>) {
+        let manager: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(CLEANUP_INTERVAL).await;
+                let Some(this) = manager.upgrade() else { break };
+                this.cleanup();
+                this.reload_token_file();
+                this.reload_revocation_file();
+            }
+        });
+    }
+    
+    fn cleanup(&self) {
+        let now = SystemTime::now();
+        let mut revoked = Vec::new();
+        let mut uses = self.token_uses.lock().unwrap();
+        uses.retain(|_, used| {
+            if now > used.expiration {
+                return false;
+            }
+            if !used.revoked && now.duration_since(used.last_used).unwrap_or_default() > used.max_idle {
+                used.revoked = true;
+                revoked.push((used.path.clone(), used.owner.clone(), used.max_idle));
+            }
+            true
+        });
+        drop(uses);
+        self.revoked.lock().unwrap().retain(|_, expiration| now <= *expiration);
+        for (path, owner, idle) in revoked {
+            info!("🔒 Revoked {}'s capability for {} after {}s unused", owner, path.display(), idle.as_secs());
+            self.log_access(&path, Operation::Execute, &owner, false,
+                            Some(format!("revoked: unused for {}s", idle.as_secs())));
+        }
+        
+        // A grant can't outlive max_minutes, so nor need its session
+        let limits = &self.config.break_glass;
+        let kept = Duration::from_secs((limits.max_minutes + limits.session_minutes) * 60);
+        self.break_glass_used.lock().unwrap()
+            .retain(|_, first| first.elapsed().unwrap_or_default() < kept);
+        debug!("🧹 Cleaned up capability state");
+    }
+    
+    /// Check the local process's access: with the mount's ambient
+    /// credentials when it has any, with `GNOS_TOKEN` otherwise
     pub async fn check_permission(&self, path: &Path, operation: Operation) -> Result<()> {
-        // Check environment variable for token
-        if let Ok(token) = std::env::var("GNOS_TOKEN") {
-            if let Ok(capability) = Capability::from_token(&token) {
-                if capability.is_valid_for_path(path) && 
-                   capability.allows(operation) && 
-                   !capability.is_expired() {
-                    return Ok(());
+        let token = self.local_token(path, operation);
+        self.check_token(token.as_deref(), path, operation).await
+    }
+    
+    /// Check a token presented by a remote caller. Development mode only
+    /// stands in for a missing token: one that is presented and fails a
+    /// check is refused
+    #[tracing::instrument(name = "capability.check", skip(self, token), fields(path = %path.display()))]
+    pub async fn check_token(&self, token: Option<&str>, path: &Path, operation: Operation) -> Result<()> {
+        // `..` and the like must not carry a request out of the granted subtree
+        let path = &normalize(path)?;
+        match (token, tenant_of(path)) {
+            (Some(token), tenant) => self.check_presented(tenant, token, path, operation),
+            (None, Some(tenant)) => {
+                self.log_access(path, operation, "anonymous", false, Some(format!("no capability for tenant {}", tenant)));
+                Err(GnosError::PermissionDenied(format!("{} requires a capability for tenant {}", path.display(), tenant)))
+            }
+            (None, None) => {
+                if let Some(reason) = self.denial(None, path, operation) {
+                    self.log_access(path, operation, "anonymous", false, Some(reason.clone()));
+                    return Err(GnosError::PermissionDenied(format!("{}: {}", path.display(), reason)));
                 }
+                // For now, allow all operations (development mode)
+                self.log_access(path, operation, "anonymous", true, Some("development mode".to_string()));
+                Ok(())
+            }
+        }
+    }
+    
+    /// Check a presented token against every limit it carries. Tenant
+    /// subtrees are further only open to capabilities issued inside them:
+    /// a token for `/` or for another tenant is refused there
+    fn check_presented(&self, tenant: Option<&str>, token: &str, path: &Path, operation: Operation) -> Result<()> {
+        let capability = match self.capability(token) {
+            Ok(capability) => capability,
+            Err(e) => {
+                self.log_access(path, operation, "anonymous", false, Some(e.to_string()));
+                return Err(e);
             }
+        };
+        
+        let outside_tenant = tenant
+            .filter(|tenant| !normalize(&capability.path).is_ok_and(|granted| granted.starts_with(tenant_root(tenant))));
+        let refusal = if let Some(tenant) = outside_tenant {
+            Some(format!("capability for {} does not cover tenant {}", capability.path.display(), tenant))
+        } else if !capability.is_valid_for_path(path) {
+            Some(format!("capability is limited to {}", capability.path.display()))
+        } else if !capability.allows(operation) {
+            Some(format!("capability does not allow {:?}", operation))
+        } else {
+            self.denial(Some(&capability), path, operation)
+        };
+        if let Some(reason) = refusal {
+            self.log_access(path, operation, &capability.owner, false, Some(reason.clone()));
+            return Err(GnosError::PermissionDenied(format!("{}: {}", path.display(), reason)));
+        }
+        if let Some(not_before) = capability.not_before.filter(|_| capability.is_premature()) {
+            let reason = format!("not valid before {}", chrono::DateTime::<chrono::Utc>::from(not_before).to_rfc3339());
+            self.log_access(path, operation, &capability.owner, false, Some(reason.clone()));
+            return Err(GnosError::PermissionDenied(format!("{}: {}", path.display(), reason)));
+        }
+        if capability.is_expired() {
+            self.log_access(path, operation, &capability.owner, false, Some("expired".to_string()));
+            return Err(GnosError::CapabilityExpired);
+        }
+        if let Some(reason) = self.lapsed(token, &capability) {
+            self.log_access(path, operation, &capability.owner, false, Some(reason));
+            return Err(GnosError::CapabilityExpired);
         }
         
-        // For now, allow all operations (development mode)
+        self.touch(token, &capability);
+        let reason = self.use_break_glass(&capability, path, operation);
+        self.log_access(path, operation, &capability.owner, true, reason);
         Ok(())
     }
+    
+    /// Why `operation` on the canonical `path` is refused whatever grants it.
+    /// Deny entries always win over grants, however specific the grant: the
+    /// `security.deny` policy's first, then the capability's own; only where
+    /// neither refuses does the capability's grant, or development mode,
+    /// decide
+    fn denial(&self, capability: Option<&Capability>, path: &Path, operation: Operation) -> Option<String> {
+        if let Some(deny) = self.config.deny.iter().find(|deny| deny.refused(path) & operation.to_bit() != 0) {
+            return Some(format!("{:?} denied by policy under {}", operation, deny.path.display()));
+        }
+        capability.and_then(|capability| capability.denial(path, operation))
+            .map(|deny| format!("{:?} denied by capability under {}", operation, deny.path.display()))
+    }
+    
+    /// rwx bits the deny entries that apply take away on `path`
+    fn denied_bits(&self, capability: Option<&Capability>, path: &Path) -> u8 {
+        self.config.deny.iter()
+            .chain(capability.into_iter().flat_map(|capability| &capability.deny))
+            .fold(0, |bits, deny| bits | deny.refused(path))
+    }
+    
+    /// Why a capability that is otherwise in force no longer is: revoked,
+    /// past the lifetime cap, unused for longer than it may idle, or a
+    /// break-glass session that is over
+    fn lapsed(&self, token: &str, capability: &Capability) -> Option<String> {
+        if self.revoked.lock().unwrap().contains_key(&token_key(token)) {
+            return Some("revoked".to_string());
+        }
+        if !self.break_glass_live(capability) {
+            return Some("break-glass session is over".to_string());
+        }
+        let now = SystemTime::now();
+        let cap = self.config.max_token_lifetime;
+        if capability.issued.is_some_and(|issued| now.duration_since(issued).unwrap_or_default() > cap) {
+            return Some(format!("older than the {}s lifetime cap", cap.as_secs()));
+        }
+        
+        let idle = Duration::from_secs(capability.max_idle_seconds?);
+        let last_used = match self.token_uses.lock().unwrap().get(&token_key(token)) {
+            Some(used) if used.revoked => return Some(format!("revoked: unused for {}s", idle.as_secs())),
+            Some(used) => used.last_used,
+            // Uses before a restart aren't known, so a token idles from
+            // the later of its issue and the daemon's start
+            None => capability.issued.map_or(self.started, |issued| issued.max(self.started)),
+        };
+        (now.duration_since(last_used).unwrap_or_default() > idle)
+            .then(|| format!("unused for over {}s", idle.as_secs()))
+    }
+    
+    /// Note a use of a token that may only idle so long
+    fn touch(&self, token: &str, capability: &Capability) {
+        let Some(idle) = capability.max_idle_seconds else {
+            return;
+        };
+        self.token_uses.lock().unwrap().insert(token_key(token), TokenUse {
+            last_used: SystemTime::now(),
+            max_idle: Duration::from_secs(idle),
+            expiration: capability.expiration,
+            path: capability.path.clone(),
+            owner: capability.owner.clone(),
+            revoked: false,
+        });
+    }
+    
+    /// Whether a break-glass grant is unused or still inside the session its
+    /// first use opened; ordinary capabilities always are
+    fn break_glass_live(&self, capability: &Capability) -> bool {
+        let Some(grant) = &capability.break_glass else {
+            return true;
+        };
+        let session = Duration::from_secs(self.config.break_glass.session_minutes * 60);
+        self.break_glass_used.lock().unwrap()
+            .get(&grant.id)
+            .is_none_or(|first| first.elapsed().unwrap_or_default() < session)
+    }
+    
+    /// Note a use of a break-glass grant, alerting on the first one, and
+    /// return the audit reason every use carries
+    fn use_break_glass(&self, capability: &Capability, path: &Path, operation: Operation) -> Option<String> {
+        let grant = capability.break_glass.as_ref()?;
+        let reason = format!("break-glass {}: {}", grant.id, grant.reason);
+        
+        let mut used = self.break_glass_used.lock().unwrap();
+        if used.contains_key(&grant.id) {
+            return Some(reason);
+        }
+        // The first use starts the session and is announced right away
+        used.insert(grant.id.clone(), SystemTime::now());
+        drop(used);
+        warn!("🚨 Break-glass grant {} for {} first used by {} on {}: {}",
+              grant.id, capability.path.display(), capability.owner, path.display(), grant.reason);
+        let _ = self.break_glass_events.send(AuditEntry {
+            timestamp: SystemTime::now(),
+            request_id: RequestId::current(),
+            operation,
+            path: path.to_path_buf(),
+            owner: capability.owner.clone(),
+            success: true,
+            reason: Some(reason.clone()),
+        });
+        Some(reason)
+    }
+    
+    /// First uses of break-glass grants as they happen
+    pub fn break_glass_events(&self) -> broadcast::Receiver<AuditEntry> {
+        self.break_glass_events.subscribe()
+    }
+    
+    /// Record that a break-glass grant was issued; the grant's owner is the
+    /// principal
+    pub fn audit_break_glass(&self, capability: &Capability) {
+        if let Some(grant) = &capability.break_glass {
+            self.log_access(&capability.path, Operation::Execute, &capability.owner, true,
+                            Some(format!("break-glass {} issued: {}", grant.id, grant.reason)));
+        }
+    }
+    
+    /// The local process's token, when it rather than development mode is
+    /// what grants `operation` on `path`; writes through a handle opened
+    /// with it are fenced by it
+    pub fn local_grant(&self, path: &Path, operation: Operation) -> Option<String> {
+        let token = self.local_token(path, operation)?;
+        let path = normalize(path).ok()?;
+        let capability = self.capability(&token).ok()?;
+        (capability.is_valid_for_path(&path) && capability.allows(operation) && self.denial(Some(&capability), &path, operation).is_none())
+            .then_some(token)
+    }
+    
+    /// Whether `token` still grants `operation` on `path`, checked before
+    /// each chunk of a write it authorized; a revoked capability's refusal
+    /// is audited
+    pub fn fence(&self, token: &str, path: &Path, operation: Operation) -> Result<()> {
+        let capability = self.capability(token)?;
+        let refusal = if capability.is_expired() {
+            Some("expired".to_string())
+        } else {
+            self.lapsed(token, &capability)
+        };
+        match refusal {
+            Some(reason) => {
+                self.log_access(path, operation, &capability.owner, false, Some(format!("fenced: {}", reason)));
+                Err(GnosError::CapabilityExpired)
+            }
+            None => {
+                self.touch(token, &capability);
+                Ok(())
+            }
+        }
+    }
+    
+    /// Record how a write caught by a revocation ended, per `revoked_writes`
+    pub fn audit_revoked_write(&self, token: &str, path: &Path, bytes: u64) {
+        let owner = self.capability(token).map_or_else(|_| "anonymous".to_string(), |capability| capability.owner);
+        let (success, outcome) = match self.config.revoked_writes {
+            RevokedWrites::Abort => (false, "aborted"),
+            RevokedWrites::Complete => (true, "completed"),
+        };
+        warn!("🔒 Write of {} bytes to {} {} after {}'s capability was revoked", bytes, path.display(), outcome, owner);
+        self.log_access(path, Operation::Write, &owner, success,
+                        Some(format!("{} after revocation: {} bytes in flight", outcome, bytes)));
+    }
+    
+    pub fn revoked_writes(&self) -> RevokedWrites {
+        self.config.revoked_writes
+    }
+    
+    /// rwx bits the local process holds on `path`: whatever any ambient
+    /// credential grants, or what `GNOS_TOKEN` does
+    pub fn effective_permissions(&self, path: &Path) -> u8 {
+        let keyring = self.keyring.lock().unwrap();
+        if keyring.tokens.is_empty() {
+            drop(keyring);
+            return self.token_permissions(std::env::var("GNOS_TOKEN").ok().as_deref(), path);
+        }
+        keyring.tokens.iter().fold(0, |bits, token| bits | self.token_permissions(Some(token), path))
+    }
+    
+    /// rwx bits `token` grants on `path`, decided as `check_token` would but
+    /// without auditing; for reporting mode bits rather than enforcing them
+    pub fn token_permissions(&self, token: Option<&str>, path: &Path) -> u8 {
+        let Ok(path) = normalize(path) else { return 0 };
+        let capability = token
+            .and_then(|token| Some((token, self.capability(token).ok()?)))
+            .filter(|(_, capability)| capability.is_valid_for_path(&path) && !capability.is_expired() && !capability.is_premature())
+            .filter(|(token, capability)| self.lapsed(token, capability).is_none())
+            .map(|(_, capability)| capability);
+        
+        let granted = match (tenant_of(&path), token) {
+            // Only a missing token falls back to development mode
+            (None, None) => 0b111,
+            (tenant, _) => capability.as_ref()
+                .filter(|capability| tenant.is_none_or(|tenant| {
+                    normalize(&capability.path).is_ok_and(|granted| granted.starts_with(tenant_root(tenant)))
+                }))
+                .map_or(0, |capability| capability.permissions & 0b111),
+        };
+        // A token's deny entries hold even once its grant no longer does
+        let presented = token.and_then(|token| self.capability(token).ok());
+        granted & !self.denied_bits(presented.as_ref(), &path)
+    }
+    
+    /// Owner a change made with `token` is attributed to, as in the audit log
+    pub fn principal(&self, token: Option<&str>) -> String {
+        token.and_then(|token| self.capability(token).ok())
+            .filter(|capability| !capability.is_expired())
+            .map_or_else(|| "anonymous".to_string(), |capability| capability.owner)
+    }
+    
+    /// Owner for changes made by the local process: the first ambient
+    /// credential's, or `GNOS_TOKEN`'s
+    pub fn local_principal(&self) -> String {
+        let ambient = self.keyring.lock().unwrap().tokens.first().cloned();
+        self.principal(ambient.or_else(|| std::env::var("GNOS_TOKEN").ok()).as_deref())
+    }
+    
+    /// Record whether a driver plugin was admitted; `reason` carries its fingerprint
+    pub fn audit_plugin(&self, path: &Path, publisher: &str, success: bool, reason: String) {
+        self.log_access(path, Operation::Execute, publisher, success, Some(reason));
+    }
+    
+    /// Record a transfer whose data didn't match its digest; `driver` stands
+    /// in as the owner
+    pub fn audit_integrity(&self, path: &Path, operation: Operation, driver: &str, reason: String) {
+        self.log_access(path, operation, driver, false, Some(reason));
+    }
+    
+    /// Record a mutation a dry run kept from reaching its backend; `driver`
+    /// stands in as the owner
+    pub fn audit_dry_run(&self, path: &Path, operation: Operation, driver: &str, reason: String) {
+        self.log_access(path, operation, driver, true, Some(reason));
+    }
+    
+    /// Recent permission decisions, oldest first
+    pub fn audit_log(&self) -> Vec<AuditEntry> {
+        self.audit_log.lock().unwrap().iter().cloned().collect()
+    }
+    
+    /// Keep a separate audit stream for `tenant`
+    pub fn track_tenant(&self, tenant: &str) {
+        self.tenant_audit_logs.lock().unwrap().entry(tenant.to_string()).or_default();
+    }
+    
+    /// Recent decisions about one tenant's subtree, oldest first
+    pub fn tenant_audit_log(&self, tenant: &str) -> Vec<AuditEntry> {
+        self.tenant_audit_logs.lock().unwrap()
+            .get(tenant)
+            .map_or_else(Vec::new, |log| log.iter().cloned().collect())
+    }
+    
+    /// Plain-text view of a tenant's audit stream, one decision per line
+    pub fn tenant_audit_report(&self, tenant: &str) -> String {
+        self.tenant_audit_log(tenant).iter().map(AuditEntry::line).collect()
+    }
+    
+    /// Append entries not yet written to the configured `audit_file`, in
+    /// the format of the tenant audit streams; returns how many were written
+    pub fn flush_audit(&self) -> std::io::Result<usize> {
+        use std::io::Write;
+        
+        let Some(file) = &self.config.audit_file else {
+            return Ok(0);
+        };
+        let mut flushed = self.audit_flushed.lock().unwrap();
+        let entries: Vec<AuditEntry> = self.audit_log.lock().unwrap().iter()
+            .filter(|entry| entry.timestamp > *flushed)
+            .cloned()
+            .collect();
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        
+        let mut out = std::fs::OpenOptions::new().create(true).append(true).open(file)?;
+        out.write_all(entries.iter().map(AuditEntry::line).collect::<String>().as_bytes())?;
+        out.sync_data()?;
+        if let Some(last) = entries.last() {
+            *flushed = last.timestamp;
+        }
+        Ok(entries.len())
+    }
+    
+    fn log_access(
+        &self,
+        path: &Path,
+        operation: Operation,
+        owner: &str,
+        success: bool,
+        reason: Option<String>,
+    ) {
+        let entry = AuditEntry {
+            timestamp: SystemTime::now(),
+            request_id: RequestId::current(),
+            operation,
+            path: path.to_path_buf(),
+            owner: owner.to_string(),
+            success,
+            reason,
+        };
+        debug!("Audit: {:?}", entry);
+        
+        let mut tenant_logs = self.tenant_audit_logs.lock().unwrap();
+        if let Some(log) = tenant_of(path).and_then(|tenant| tenant_logs.get_mut(tenant)) {
+            if log.len() >= TENANT_AUDIT_LOG_CAPACITY {
+                log.pop_front();
+            }
+            log.push_back(entry.clone());
+        }
+        
+        let mut log = self.audit_log.lock().unwrap();
+        if log.len() >= AUDIT_LOG_CAPACITY {
+            log.pop_front();
+        }
+        log.push_back(entry);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,94 @@
+//! Coordinated shutdown of the daemon
+//!
+//! On SIGTERM or SIGINT a mount winds down in order instead of dropping
+//! whatever it held:
+//!
+//! 1. new opens, creates, removals and renames fail with `ESHUTDOWN`, while
+//!    handles already open can still be read, written and closed;
+//! 2. driver calls already running or queued get `drain_seconds` to finish;
+//! 3. the filesystem is unmounted, and on the way out commits the writes
+//!    still buffered in open handles and flushes the write-back queue,
+//!    within `flush_seconds`;
+//! 4. the content index and the audit log are saved.
+//!
+//! A second signal while draining unmounts straight away.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::config::ShutdownConfig;
+use crate::pools::DriverPools;
+
+/// How often the drain checks for calls still running
+const DRAIN_POLL: Duration = Duration::from_millis(100);
+
+pub struct Shutdown {
+    config: ShutdownConfig,
+    draining: AtomicBool,
+}
+
+impl Shutdown {
+    pub fn new(config: &ShutdownConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config: config.clone(),
+            draining: AtomicBool::new(false),
+        })
+    }
+    
+    /// Whether new work is being refused
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+    
+    /// How long the unmount may spend committing what is buffered
+    pub fn flush_deadline(&self) -> Duration {
+        Duration::from_secs(self.config.flush_seconds)
+    }
+    
+    /// Wait for SIGTERM or SIGINT, then refuse new work and wait for the
+    /// calls in `pools` to finish; returns when it is time to unmount
+    pub async fn wait(&self, pools: &DriverPools) {
+        signal().await;
+        self.draining.store(true, Ordering::Relaxed);
+        warn!("🛑 Shutting down: refusing new operations, draining {} driver calls", pools.in_flight());
+        
+        let deadline = Instant::now() + Duration::from_secs(self.config.drain_seconds);
+        loop {
+            let in_flight = pools.in_flight();
+            if in_flight == 0 {
+                info!("🛑 Driver calls drained");
+                return;
+            }
+            if Instant::now() >= deadline {
+                warn!("⏱️ Unmounting with {} driver calls still running", in_flight);
+                return;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(DRAIN_POLL) => {}
+                _ = signal() => {
+                    warn!("🛑 Second signal, unmounting now");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// The next SIGTERM or SIGINT
+async fn signal() {
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        }
+        Err(e) => {
+            warn!("❌ Can't listen for SIGTERM, only Ctrl-C: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
@@ -0,0 +1,461 @@
+//! Backup and restore of daemon state
+//!
+//! A backup is a tar.zst holding a `manifest.json` and one directory per
+//! state component, copied from wherever the config puts it:
+//!
+//! - `journal`: the write-back journal, i.e. writes not yet uploaded
+//! - `index`: the content index
+//! - `copy`: checkpoints of interrupted copies
+//! - `cache`: the disk chunk cache, only when asked for since it can be large
+//!
+//! Restoring writes each component into the directory the restoring node's
+//! config names, so state can move between machines with different layouts.
+//! The archive is first unpacked beside those directories and checked
+//! against its manifest; only plain files and directories are accepted, and
+//! nothing is moved into place through a symlink.
+//! Restored journal entries are replayed, and uploaded, at the next mount.
+//! Capability tokens are self-contained and the inode table is rebuilt
+//! from the drivers, so neither needs carrying over. Both sides should run
+//! with the daemon stopped so the journal isn't changing underneath them.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::GnosConfig;
+use crate::{GnosError, Result};
+
+const MANIFEST: &str = "manifest.json";
+
+/// What a backup holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateManifest {
+    /// GNOS version that wrote the backup
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+    pub components: Vec<ComponentSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentSummary {
+    pub name: String,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackupOptions {
+    /// Also copy the disk cache
+    pub include_cache: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    /// Restore into state directories that already hold files, replacing
+    /// files of the same name
+    pub force: bool,
+}
+
+/// State directories named by `config`, by component
+pub fn components(config: &GnosConfig, include_cache: bool) -> Vec<(&'static str, PathBuf)> {
+    let mut components = vec![
+        ("journal", config.writeback.journal_dir.clone()),
+        ("index", config.index.index_dir.clone()),
+        ("copy", config.copy.checkpoint_dir.clone()),
+    ];
+    if include_cache {
+        components.push(("cache", config.cache.dir.clone()));
+    }
+    components
+}
+
+/// Write the state directories named by `config` to a tar.zst at `dest`
+pub async fn backup(config: &GnosConfig, dest: &Path, options: &BackupOptions) -> Result<StateManifest> {
+    let components = components(config, options.include_cache);
+    let dest = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || write_backup(&components, &dest))
+        .await
+        .map_err(|e| GnosError::Driver(format!("backup task failed: {}", e)))?
+}
+
+/// Unpack a backup into the state directories named by `config`
+pub async fn restore(config: &GnosConfig, archive: &Path, options: &RestoreOptions) -> Result<StateManifest> {
+    let components = components(config, true);
+    let archive = archive.to_path_buf();
+    let force = options.force;
+    tokio::task::spawn_blocking(move || read_backup(&components, &archive, force))
+        .await
+        .map_err(|e| GnosError::Driver(format!("restore task failed: {}", e)))?
+}
+
+fn write_backup(components: &[(&'static str, PathBuf)], dest: &Path) -> Result<StateManifest> {
+    // Files are listed up front so the manifest, written first, describes them
+    let mut listed = Vec::new();
+    let mut summaries = Vec::new();
+    for (name, dir) in components {
+        if !dir.is_dir() {
+            debug!("No {} state at {}", name, dir.display());
+            continue;
+        }
+        let files = walk(dir)?;
+        summaries.push(ComponentSummary {
+            name: name.to_string(),
+            files: files.len() as u64,
+            bytes: files.iter().map(|(_, size)| size).sum(),
+        });
+        listed.push((*name, dir, files));
+    }
+    let manifest = StateManifest {
+        version: crate::VERSION.to_string(),
+        created_at: Utc::now(),
+        components: summaries,
+    };
+    
+    let encoder = zstd::stream::write::Encoder::new(BufWriter::new(File::create(dest)?), 3)?;
+    let mut builder = tar::Builder::new(encoder);
+    
+    let json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| GnosError::Driver(format!("Failed to serialize backup manifest: {}", e)))?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at.timestamp().max(0) as u64);
+    builder.append_data(&mut header, MANIFEST, json.as_slice())?;
+    
+    for (name, dir, files) in listed {
+        for (relative, _) in files {
+            builder.append_path_with_name(dir.join(&relative), Path::new(name).join(&relative))?;
+        }
+        info!("💾 Backed up {} state from {}", name, dir.display());
+    }
+    
+    builder.into_inner()?.finish()?.flush()?;
+    Ok(manifest)
+}
+
+fn read_backup(components: &[(&'static str, PathBuf)], archive: &Path, force: bool) -> Result<StateManifest> {
+    let decoder = zstd::stream::read::Decoder::new(BufReader::new(File::open(archive)?))?;
+    let mut archive_reader = tar::Archive::new(decoder);
+    let mut entries = archive_reader.entries()?;
+    
+    let manifest: StateManifest = match entries.next() {
+        Some(entry) => {
+            let entry = entry?;
+            if entry.path()? != Path::new(MANIFEST) {
+                return Err(GnosError::InvalidPath(format!("{} is not a GNOS state backup", archive.display())));
+            }
+            serde_json::from_reader(entry)
+                .map_err(|e| GnosError::InvalidPath(format!("unreadable backup manifest: {}", e)))?
+        }
+        None => return Err(GnosError::InvalidPath(format!("{} is empty", archive.display()))),
+    };
+    
+    let mut staged = Vec::new();
+    for summary in &manifest.components {
+        let Some((_, dir)) = components.iter().find(|(name, _)| *name == summary.name) else {
+            return Err(GnosError::InvalidPath(format!("unknown state component in backup: {}", summary.name)));
+        };
+        if !force && has_files(dir)? {
+            return Err(GnosError::ResourceBusy(format!(
+                "{} already holds {} state; restore with --force to merge into it", dir.display(), summary.name
+            )));
+        }
+        staged.push(Staged::new(summary.clone(), dir)?);
+    }
+    
+    for entry in entries {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let mut parts = path.components();
+        let (Some(Component::Normal(name)), relative) = (parts.next(), parts.as_path()) else {
+            return Err(GnosError::InvalidPath(format!("unexpected backup entry: {}", path.display())));
+        };
+        if relative.components().any(|part| !matches!(part, Component::Normal(_))) {
+            return Err(GnosError::InvalidPath(format!("unsafe backup entry: {}", path.display())));
+        }
+        let Some(stage) = staged.iter_mut().find(|stage| name == OsStr::new(&stage.summary.name)) else {
+            return Err(GnosError::InvalidPath(format!("backup entry outside its manifest: {}", path.display())));
+        };
+        
+        let target = stage.staging.join(relative);
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => std::fs::create_dir_all(&target)?,
+            tar::EntryType::Regular if relative != Path::new("") => {
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                stage.bytes += entry.size();
+                stage.files.push(relative.to_path_buf());
+                entry.unpack(&target)?;
+            }
+            kind => {
+                return Err(GnosError::InvalidPath(format!(
+                    "backup entry {} is a {:?}, not a file or directory", path.display(), kind
+                )));
+            }
+        }
+    }
+    
+    // Nothing is moved into place until the whole archive has unpacked,
+    // matched its manifest, and every target has been checked
+    for stage in &staged {
+        let summary = &stage.summary;
+        if (stage.files.len() as u64, stage.bytes) != (summary.files, summary.bytes) {
+            return Err(GnosError::InvalidPath(format!(
+                "backup holds {} files ({} bytes) of {} state where its manifest lists {} ({} bytes)",
+                stage.files.len(), stage.bytes, summary.name, summary.files, summary.bytes
+            )));
+        }
+        stage.check_targets()?;
+    }
+    for stage in &staged {
+        stage.move_into_place()?;
+        info!("💾 Restored {} files of {} state", stage.files.len(), stage.summary.name);
+    }
+    Ok(manifest)
+}
+
+/// A component unpacked beside the directory it is restored into, removed
+/// once dropped
+struct Staged {
+    summary: ComponentSummary,
+    /// Where the component goes, with symlinks resolved so the staging
+    /// directory is on the same filesystem
+    dir: PathBuf,
+    staging: PathBuf,
+    /// Unpacked files, relative to both directories
+    files: Vec<PathBuf>,
+    bytes: u64,
+}
+
+impl Staged {
+    fn new(summary: ComponentSummary, dir: &Path) -> Result<Self> {
+        let dir = match dir.canonicalize() {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => dir.to_path_buf(),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(dir_name) = dir.file_name() else {
+            return Err(GnosError::InvalidPath(format!("{} can't hold {} state", dir.display(), summary.name)));
+        };
+        let mut name = OsString::from(".");
+        name.push(dir_name);
+        name.push(format!(".restore-{}", std::process::id()));
+        let staging = dir.with_file_name(name);
+        
+        if let Some(parent) = staging.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::create_dir(&staging)?;
+        Ok(Self { summary, dir, staging, files: Vec::new(), bytes: 0 })
+    }
+    
+    /// Refuse to put a file where it would land through a symlink, or where
+    /// a file stands in for one of its directories or a directory for it
+    fn check_targets(&self) -> Result<()> {
+        for relative in &self.files {
+            let mut target = self.dir.clone();
+            let last = relative.components().count();
+            for (depth, part) in relative.components().enumerate() {
+                target.push(part);
+                let metadata = match std::fs::symlink_metadata(&target) {
+                    Ok(metadata) => metadata,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                    Err(e) => return Err(e.into()),
+                };
+                if metadata.file_type().is_symlink() {
+                    return Err(GnosError::InvalidPath(format!(
+                        "{} is a symlink; not restoring {} state through it", target.display(), self.summary.name
+                    )));
+                }
+                if metadata.is_dir() == (depth + 1 == last) {
+                    return Err(GnosError::ResourceBusy(format!(
+                        "{} is in the way of restoring {}", target.display(), relative.display()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    fn move_into_place(&self) -> Result<()> {
+        for relative in &self.files {
+            let target = self.dir.join(relative);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::rename(self.staging.join(relative), &target)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.staging);
+    }
+}
+
+/// Files below `dir` with their sizes, relative to it
+fn walk(dir: &Path) -> io::Result<Vec<(PathBuf, u64)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        for entry in std::fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let path = relative.join(entry.file_name());
+            if metadata.is_dir() {
+                pending.push(path);
+            } else if metadata.is_file() {
+                files.push((path, metadata.len()));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn has_files(dir: &Path) -> io::Result<bool> {
+    match walk(dir) {
+        Ok(files) => Ok(!files.is_empty()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    type Builder = tar::Builder<zstd::stream::write::Encoder<'static, BufWriter<File>>>;
+    
+    /// A backup of `components` whose manifest lists `files` of `bytes` for
+    /// each, with entries added by `add`
+    fn archive(dest: &Path, components: &[(&str, u64, u64)], add: impl FnOnce(&mut Builder)) {
+        let manifest = StateManifest {
+            version: crate::VERSION.to_string(),
+            created_at: Utc::now(),
+            components: components.iter()
+                .map(|(name, files, bytes)| ComponentSummary { name: name.to_string(), files: *files, bytes: *bytes })
+                .collect(),
+        };
+        let json = serde_json::to_vec(&manifest).unwrap();
+        let mut builder = tar::Builder::new(zstd::stream::write::Encoder::new(BufWriter::new(File::create(dest).unwrap()), 3).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, MANIFEST, json.as_slice()).unwrap();
+        add(&mut builder);
+        builder.into_inner().unwrap().finish().unwrap().flush().unwrap();
+    }
+    
+    fn file(builder: &mut Builder, path: &str, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    
+    /// What is left below `dir` besides `dir` itself
+    fn contents(dir: &Path) -> Vec<PathBuf> {
+        let mut found: Vec<PathBuf> = walk(dir).map(|files| files.into_iter().map(|(path, _)| path).collect()).unwrap_or_default();
+        found.sort();
+        found
+    }
+    
+    #[test]
+    fn backups_round_trip() {
+        let root = tempfile::tempdir().unwrap();
+        let journal = root.path().join("journal");
+        std::fs::create_dir_all(journal.join("sub")).unwrap();
+        std::fs::write(journal.join("1.wb"), b"one").unwrap();
+        std::fs::write(journal.join("sub/2.wb"), b"two!").unwrap();
+        let backup = root.path().join("state.tar.zst");
+        
+        let written = write_backup(&[("journal", journal.clone())], &backup).unwrap();
+        assert_eq!((written.components[0].files, written.components[0].bytes), (2, 7));
+        
+        let restored = root.path().join("restored");
+        read_backup(&[("journal", restored.clone())], &backup, false).unwrap();
+        assert_eq!(std::fs::read(restored.join("1.wb")).unwrap(), b"one");
+        assert_eq!(std::fs::read(restored.join("sub/2.wb")).unwrap(), b"two!");
+        // The staging directory is gone
+        assert_eq!(std::fs::read_dir(root.path()).unwrap().count(), 3);
+        
+        let refused = read_backup(&[("journal", restored)], &backup, false).unwrap_err();
+        assert!(matches!(refused, GnosError::ResourceBusy(_)));
+    }
+    
+    #[test]
+    fn links_in_the_archive_are_refused_before_anything_is_written() {
+        let root = tempfile::tempdir().unwrap();
+        let backup = root.path().join("state.tar.zst");
+        archive(&backup, &[("journal", 2, 3)], |builder| {
+            file(builder, "journal/1.wb", b"one");
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, "journal/2.wb", "/etc/passwd").unwrap();
+        });
+        
+        let journal = root.path().join("journal");
+        let refused = read_backup(&[("journal", journal.clone())], &backup, false).unwrap_err();
+        assert!(refused.to_string().contains("not a file or directory"), "{}", refused);
+        assert!(contents(&journal).is_empty());
+        assert_eq!(std::fs::read_dir(root.path()).unwrap().count(), 1);
+    }
+    
+    #[test]
+    fn archives_that_disagree_with_their_manifest_are_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let backup = root.path().join("state.tar.zst");
+        let journal = root.path().join("journal");
+        
+        // An entry for a component the manifest doesn't list
+        archive(&backup, &[("journal", 1, 3)], |builder| {
+            file(builder, "journal/1.wb", b"one");
+            file(builder, "index/segment", b"extra");
+        });
+        let components = [("journal", journal.clone()), ("index", root.path().join("index"))];
+        assert!(read_backup(&components, &backup, false).is_err());
+        assert!(contents(&journal).is_empty());
+        
+        // A file the manifest doesn't count
+        archive(&backup, &[("journal", 1, 3)], |builder| {
+            file(builder, "journal/1.wb", b"one");
+            file(builder, "journal/2.wb", b"two");
+        });
+        let refused = read_backup(&components, &backup, false).unwrap_err();
+        assert!(refused.to_string().contains("manifest lists 1"), "{}", refused);
+        assert!(contents(&journal).is_empty());
+    }
+    
+    #[test]
+    fn forced_restores_never_write_through_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = root.path().join("outside");
+        let journal = root.path().join("journal");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::create_dir_all(&journal).unwrap();
+        std::os::unix::fs::symlink(&outside, journal.join("sub")).unwrap();
+        std::os::unix::fs::symlink(outside.join("target"), journal.join("2.wb")).unwrap();
+        
+        let backup = root.path().join("state.tar.zst");
+        for entry in ["sub/1.wb", "2.wb"] {
+            archive(&backup, &[("journal", 2, 6)], |builder| {
+                file(builder, "journal/0.wb", b"zero");
+                file(builder, &format!("journal/{}", entry), b"in");
+            });
+            let refused = read_backup(&[("journal", journal.clone())], &backup, true).unwrap_err();
+            assert!(refused.to_string().contains("is a symlink"), "{}", refused);
+            assert!(!journal.join("0.wb").exists());
+            assert!(contents(&outside).is_empty());
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+use crate::config::AlertConfig;
+use crate::drivers::network::SharedHttpClient;
+use crate::security::{AuditEntry, CapabilityManager};
+use crate::telemetry::Metrics;
+
+/// A threshold crossing, as logged and as posted to the webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    /// `driver_errors`, `audit_denials` or `break_glass`
+    pub kind: &'static str,
+    /// Driver name or principal the alert is about
+    pub subject: String,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+impl Alert {
+    pub fn now(kind: &'static str, subject: String, message: String) -> Self {
+        Self {
+            kind,
+            subject,
+            message,
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        }
+    }
+    
+    /// POST the alert as JSON, logging rather than returning failures
+    pub async fn post(&self, http: &SharedHttpClient, url: &str) {
+        match http.fetch(http.client().post(url).json(self)).await {
+            Ok((status, _)) if !status.is_success() => {
+                warn!("❌ Alert webhook returned {}", status);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("❌ Alert webhook failed: {}", e),
+        }
+    }
+}
+
+/// Periodically compares driver error rates and per-principal permission
+/// denials against the configured budgets, and alerts on break-glass grants
+/// the moment they are first used
+pub struct AlertMonitor {
+    config: AlertConfig,
+    metrics: Arc<Metrics>,
+    capability_manager: Arc<CapabilityManager>,
+    http: Arc<SharedHttpClient>,
+    /// Driver call and error totals at the previous check
+    driver_totals: HashMap<String, (u64, u64)>,
+    audit_checked_at: SystemTime,
+    last_fired: HashMap<(&'static str, String), Instant>,
+    break_glass: broadcast::Receiver<AuditEntry>,
+}
+
+impl AlertMonitor {
+    pub fn new(
+        config: AlertConfig,
+        metrics: Arc<Metrics>,
+        capability_manager: Arc<CapabilityManager>,
+        http: Arc<SharedHttpClient>,
+    ) -> Self {
+        let break_glass = capability_manager.break_glass_events();
+        Self {
+            config,
+            metrics,
+            capability_manager,
+            http,
+            driver_totals: HashMap::new(),
+            audit_checked_at: SystemTime::now(),
+            last_fired: HashMap::new(),
+            break_glass,
+        }
+    }
+    
+    /// Check every `interval_seconds` until the runtime shuts down
+    pub fn spawn(mut self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval_seconds.max(1)));
+            interval.tick().await;
+            
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => self.check().await,
+                    entry = self.break_glass.recv() => match entry {
+                        Ok(entry) => self.break_glass_used(entry).await,
+                        Err(RecvError::Lagged(missed)) => {
+                            warn!("🚨 {} break-glass uses went unannounced; see the audit log", missed);
+                        }
+                        // The manager outlives the monitor
+                        Err(RecvError::Closed) => return,
+                    },
+                }
+            }
+        })
+    }
+    
+    async fn check(&mut self) {
+        let mut alerts = Vec::new();
+        
+        // Error rate per driver over the last window
+        for (driver, calls, errors) in self.metrics.driver_totals() {
+            let (prev_calls, prev_errors) = self.driver_totals.insert(driver.clone(), (calls, errors)).unwrap_or_default();
+            let window_calls = calls.saturating_sub(prev_calls);
+            let window_errors = errors.saturating_sub(prev_errors);
+            if window_calls < self.config.min_driver_calls {
+                continue;
+            }
+            
+            let rate = window_errors as f64 / window_calls as f64;
+            if rate >= self.config.driver_error_rate {
+                alerts.push(("driver_errors", driver, format!(
+                    "{} of {} calls failed ({:.0}%) in the last {}s",
+                    window_errors, window_calls, rate * 100.0, self.config.interval_seconds
+                )));
+            }
+        }
+        
+        // Denials per principal since the last check; a burst often means a leaked or stale token
+        let since = std::mem::replace(&mut self.audit_checked_at, SystemTime::now());
+        let mut denials: HashMap<String, usize> = HashMap::new();
+        for entry in self.capability_manager.audit_log() {
+            if !entry.success && entry.timestamp > since {
+                *denials.entry(entry.owner).or_default() += 1;
+            }
+        }
+        for (owner, count) in denials {
+            if count >= self.config.max_denials_per_principal {
+                alerts.push(("audit_denials", owner, format!(
+                    "{} permission denials in the last {}s", count, self.config.interval_seconds
+                )));
+            }
+        }
+        
+        for (kind, subject, message) in alerts {
+            self.fire(kind, subject, message).await;
+        }
+    }
+    
+    async fn break_glass_used(&mut self, entry: AuditEntry) {
+        let message = format!("{:?} of {} under {}", entry.operation, entry.path.display(),
+                              entry.reason.as_deref().unwrap_or("break-glass"));
+        // Each grant announces its first use once, so there is nothing to cool down
+        self.last_fired.remove(&("break_glass", entry.owner.clone()));
+        self.fire("break_glass", entry.owner, message).await;
+    }
+    
+    async fn fire(&mut self, kind: &'static str, subject: String, message: String) {
+        let cooldown = Duration::from_secs(self.config.cooldown_seconds);
+        let key = (kind, subject.clone());
+        if self.last_fired.get(&key).is_some_and(|fired| fired.elapsed() < cooldown) {
+            debug!("Suppressed repeat {} alert for {}", kind, subject);
+            return;
+        }
+        self.last_fired.insert(key, Instant::now());
+        
+        warn!("🚨 {} alert for {}: {}", kind, subject, message);
+        
+        if let Some(url) = &self.config.webhook_url {
+            Alert::now(kind, subject, message).post(&self.http, url).await;
+        }
+    }
+}
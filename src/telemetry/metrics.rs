@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+const OP_PREFIX: &str = "fuse.";
+const DRIVER_PREFIX: &str = "driver.";
+
+/// Latency histogram bucket bounds, in seconds
+const BUCKETS: [f64; 9] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 30.0];
+
+/// Counters behind `/proc/gnos/metrics`, fed from the FUSE and driver spans
+pub struct Metrics {
+    started: Instant,
+    ops: DashMap<String, Stats>,
+    drivers: DashMap<String, Stats>,
+}
+
+/// A gauge with one value per label, e.g. queue depth per driver
+pub struct LabelledGauge {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub label: &'static str,
+    pub values: Vec<(String, f64)>,
+}
+
+#[derive(Default)]
+struct Stats {
+    count: AtomicU64,
+    errors: AtomicU64,
+    total_micros: AtomicU64,
+    buckets: [AtomicU64; BUCKETS.len()],
+}
+
+impl Stats {
+    fn observe(&self, elapsed: Duration) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            ops: DashMap::new(),
+            drivers: DashMap::new(),
+        }
+    }
+    
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+    
+    /// FUSE operations that failed since start
+    pub fn error_count(&self) -> u64 {
+        self.ops.iter().map(|stats| stats.errors.load(Ordering::Relaxed)).sum()
+    }
+    
+    /// Calls and errors per driver since start
+    pub fn driver_totals(&self) -> Vec<(String, u64, u64)> {
+        self.drivers.iter()
+            .map(|entry| (
+                entry.key().clone(),
+                entry.count.load(Ordering::Relaxed),
+                entry.errors.load(Ordering::Relaxed),
+            ))
+            .collect()
+    }
+    
+    fn stats<'a>(map: &'a DashMap<String, Stats>, key: &str) -> dashmap::mapref::one::Ref<'a, String, Stats> {
+        if let Some(stats) = map.get(key) {
+            return stats;
+        }
+        map.entry(key.to_string()).or_default().downgrade()
+    }
+    
+    /// Prometheus text exposition, with caller-supplied gauges appended
+    pub fn render(&self, gauges: &[(&str, &str, f64)], labelled: &[LabelledGauge]) -> String {
+        let mut out = String::new();
+        
+        gauge(&mut out, "gnos_uptime_seconds", "Seconds since the daemon started", self.uptime().as_secs_f64());
+        
+        write_stats(&mut out, &self.ops, "op", "gnos_fuse_ops", "FUSE operations served",
+                    "gnos_fuse_op_duration_seconds", "FUSE operation latency");
+        write_stats(&mut out, &self.drivers, "driver", "gnos_driver_calls", "Driver calls made",
+                    "gnos_driver_call_duration_seconds", "Driver call latency");
+        
+        for (name, help, value) in gauges {
+            gauge(&mut out, name, help, *value);
+        }
+        for labelled in labelled {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", labelled.name, labelled.help, labelled.name);
+            for (key, value) in &labelled.values {
+                let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", labelled.name, labelled.label, escape(key), value);
+            }
+        }
+        
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}", name, help, name, name, value);
+}
+
+fn write_stats(
+    out: &mut String,
+    map: &DashMap<String, Stats>,
+    label: &str,
+    counter: &str,
+    counter_help: &str,
+    histogram: &str,
+    histogram_help: &str,
+) {
+    // Sorted so scrapes diff cleanly
+    let sorted: BTreeMap<String, _> = map.iter()
+        .map(|entry| {
+            let stats = entry.value();
+            let buckets: Vec<u64> = stats.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+            (entry.key().clone(), (
+                stats.count.load(Ordering::Relaxed),
+                stats.errors.load(Ordering::Relaxed),
+                stats.total_micros.load(Ordering::Relaxed),
+                buckets,
+            ))
+        })
+        .collect();
+    
+    let _ = writeln!(out, "# HELP {}_total {}\n# TYPE {}_total counter", counter, counter_help, counter);
+    for (key, (count, _, _, _)) in &sorted {
+        let _ = writeln!(out, "{}_total{{{}=\"{}\"}} {}", counter, label, escape(key), count);
+    }
+    
+    let _ = writeln!(out, "# HELP {}_errors_total Failed {}\n# TYPE {}_errors_total counter",
+                     counter, counter_help.to_lowercase(), counter);
+    for (key, (_, errors, _, _)) in &sorted {
+        let _ = writeln!(out, "{}_errors_total{{{}=\"{}\"}} {}", counter, label, escape(key), errors);
+    }
+    
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", histogram, histogram_help, histogram);
+    for (key, (count, _, micros, buckets)) in &sorted {
+        let key = escape(key);
+        let mut cumulative = 0;
+        for (bound, hits) in BUCKETS.iter().zip(buckets) {
+            cumulative += hits;
+            let _ = writeln!(out, "{}_bucket{{{}=\"{}\",le=\"{}\"}} {}", histogram, label, key, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{}=\"{}\",le=\"+Inf\"}} {}", histogram, label, key, count);
+        let _ = writeln!(out, "{}_sum{{{}=\"{}\"}} {}", histogram, label, key, *micros as f64 / 1_000_000.0);
+        let _ = writeln!(out, "{}_count{{{}=\"{}\"}} {}", histogram, label, key, count);
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Feeds `Metrics` from span lifetimes; an operation counts as failed when
+/// it logged a warning
+pub struct MetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+struct SpanState {
+    started: Instant,
+    /// For driver spans the driver called; for operations the last one they called
+    driver: Mutex<Option<String>>,
+    failed: AtomicBool,
+}
+
+struct DriverField(Option<String>);
+
+impl Visit for DriverField {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "driver" {
+            self.0 = Some(value.to_string());
+        }
+    }
+    
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let name = attrs.metadata().name();
+        if !name.starts_with(OP_PREFIX) && !name.starts_with(DRIVER_PREFIX) {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        
+        let mut driver = DriverField(None);
+        attrs.record(&mut driver);
+        span.extensions_mut().insert(SpanState {
+            started: Instant::now(),
+            driver: Mutex::new(driver.0),
+            failed: AtomicBool::new(false),
+        });
+    }
+    
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() > Level::WARN {
+            return;
+        }
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+        
+        for span in scope.filter(|span| span.name().starts_with(OP_PREFIX)) {
+            if let Some(state) = span.extensions().get::<SpanState>() {
+                state.failed.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+    
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(state) = span.extensions_mut().remove::<SpanState>() else {
+            return;
+        };
+        let elapsed = state.started.elapsed();
+        let driver = state.driver.into_inner().unwrap();
+        
+        if let Some(op) = span.name().strip_prefix(OP_PREFIX) {
+            let stats = Metrics::stats(&self.metrics.ops, op);
+            stats.observe(elapsed);
+            
+            // Driver failures surface as warnings from the operation after
+            // the call has returned, so they're charged to the driver here
+            if state.failed.load(Ordering::Relaxed) {
+                stats.errors.fetch_add(1, Ordering::Relaxed);
+                if let Some(driver) = driver {
+                    Metrics::stats(&self.metrics.drivers, &driver).errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            return;
+        }
+        
+        let Some(driver) = driver else {
+            return;
+        };
+        Metrics::stats(&self.metrics.drivers, &driver).observe(elapsed);
+        
+        if let Some(op) = span.scope().skip(1).find(|parent| parent.name().starts_with(OP_PREFIX)) {
+            if let Some(op_state) = op.extensions().get::<SpanState>() {
+                *op_state.driver.lock().unwrap() = Some(driver);
+            }
+        }
+    }
+}
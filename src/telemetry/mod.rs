@@ -0,0 +1,94 @@
+mod alerts;
+mod metrics;
+mod profile;
+mod request_id;
+mod slow_ops;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::TelemetryConfig;
+use crate::{GnosError, Result};
+
+pub use alerts::{Alert, AlertMonitor};
+pub use metrics::{LabelledGauge, Metrics, MetricsLayer};
+pub use profile::{folded_since, ProfileLayer, Profiler};
+pub use request_id::RequestId;
+pub use slow_ops::SlowOpLayer;
+
+/// Installed log subscriber, plus the span exporter when OTLP is configured
+///
+/// FUSE operations are spans named `fuse.<op>`; capability checks, cache
+/// lookups and driver calls nest under them.
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+    metrics: Arc<Metrics>,
+    profiler: Option<Arc<Profiler>>,
+}
+
+impl Telemetry {
+    pub fn init(debug: bool, config: &TelemetryConfig) -> Result<Self> {
+        let level = if debug { "debug" } else { "info" };
+        let metrics = Arc::new(Metrics::new());
+        let profiler = config.profiling.then(|| Arc::new(Profiler::new()));
+        let registry = tracing_subscriber::registry()
+            .with(EnvFilter::new(format!("gnos={},warn", level)))
+            .with(tracing_subscriber::fmt::layer().with_target(false))
+            .with(MetricsLayer::new(metrics.clone()))
+            .with((config.slow_op_threshold_ms > 0).then(|| {
+                SlowOpLayer::new(Duration::from_millis(config.slow_op_threshold_ms))
+            }))
+            .with(profiler.clone().map(ProfileLayer::new));
+        
+        let Some(endpoint) = &config.otlp_endpoint else {
+            registry.init();
+            return Ok(Self { provider: None, metrics, profiler });
+        };
+        
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint.clone())
+            .build()
+            .map_err(|e| GnosError::Driver(format!("OTLP exporter: {}", e)))?;
+        
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new([KeyValue::new("service.name", config.service_name.clone())]))
+            .build();
+        
+        registry
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("gnos")))
+            .init();
+        
+        tracing::info!("📡 Exporting spans to {}", endpoint);
+        Ok(Self { provider: Some(provider), metrics, profiler })
+    }
+    
+    /// Counters collected from spans, for `/proc/gnos/metrics`
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+    
+    /// Busy time per span stack, for `/proc/gnos/profile`, when profiling is on
+    pub fn profiler(&self) -> Option<Arc<Profiler>> {
+        self.profiler.clone()
+    }
+    
+    /// Flush spans still buffered in the batch exporter
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!("❌ Flushing spans failed: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Busy time per stack of spans, in folded form for flamegraphs
+///
+/// Each line of `folded()` is a stack from the outermost span in, e.g.
+/// `fuse.read;driver.call(S3 Driver read) 5130`, with the microseconds spent
+/// in its innermost span and not in a span below it. Counts add up from the
+/// daemon's start, so `gnos profile` reads them twice and keeps the
+/// difference.
+pub struct Profiler {
+    stacks: Mutex<HashMap<String, u64>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self { stacks: Mutex::new(HashMap::new()) }
+    }
+    
+    fn record(&self, stack: String, busy: Duration) {
+        let micros = busy.as_micros() as u64;
+        if micros > 0 {
+            *self.stacks.lock().unwrap().entry(stack).or_default() += micros;
+        }
+    }
+    
+    /// Plain-text view for `/proc/gnos/profile`
+    pub fn folded(&self) -> String {
+        let stacks = self.stacks.lock().unwrap();
+        let sorted: BTreeMap<&String, &u64> = stacks.iter().collect();
+        let mut out = String::new();
+        for (stack, micros) in sorted {
+            let _ = writeln!(out, "{} {}", stack, micros);
+        }
+        out
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What accrued between two reads of `folded()`, in the same form
+pub fn folded_since(before: &str, after: &str) -> String {
+    let before = parse_folded(before);
+    let mut out = String::new();
+    for (stack, micros) in parse_folded(after) {
+        let grown = micros.saturating_sub(before.get(stack).copied().unwrap_or(0));
+        if grown > 0 {
+            let _ = writeln!(out, "{} {}", stack, grown);
+        }
+    }
+    out
+}
+
+fn parse_folded(folded: &str) -> BTreeMap<&str, u64> {
+    folded.lines()
+        .filter_map(|line| line.rsplit_once(' '))
+        .filter_map(|(stack, micros)| Some((stack, micros.parse().ok()?)))
+        .collect()
+}
+
+/// Feeds a `Profiler` from the FUSE, 9P and driver spans while they are entered
+pub struct ProfileLayer {
+    profiler: Arc<Profiler>,
+}
+
+impl ProfileLayer {
+    pub fn new(profiler: Arc<Profiler>) -> Self {
+        Self { profiler }
+    }
+}
+
+/// Per-span state kept in the registry's span extensions
+#[derive(Default)]
+struct Busy {
+    frame: String,
+    entered: Option<Instant>,
+    /// Time spent in child spans since the span was last entered
+    children: Duration,
+}
+
+/// Fields that tell calls to the same span apart in a frame
+#[derive(Default)]
+struct Frame {
+    driver: Option<String>,
+    op: Option<String>,
+}
+
+impl Frame {
+    /// The span's name, with the driver and operation of a call; `;`
+    /// separates frames in a folded stack, so it can't appear in one
+    fn label(&self, name: &str) -> String {
+        let label = match (&self.driver, &self.op) {
+            (Some(driver), Some(op)) => format!("{}({} {})", name, driver, op),
+            (Some(detail), None) | (None, Some(detail)) => format!("{}({})", name, detail),
+            (None, None) => name.to_string(),
+        };
+        label.replace(';', ":")
+    }
+}
+
+impl Visit for Frame {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "driver" => self.driver = Some(value.to_string()),
+            "op" => self.op = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+impl<S> Layer<S> for ProfileLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut frame = Frame::default();
+        attrs.record(&mut frame);
+        span.extensions_mut().insert(Busy { frame: frame.label(span.name()), ..Default::default() });
+    }
+    
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(busy) = span.extensions_mut().get_mut::<Busy>() {
+                busy.entered = Some(Instant::now());
+            }
+        }
+    }
+    
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let (elapsed, children) = {
+            let mut extensions = span.extensions_mut();
+            let Some(busy) = extensions.get_mut::<Busy>() else {
+                return;
+            };
+            let Some(entered) = busy.entered.take() else {
+                return;
+            };
+            (entered.elapsed(), std::mem::take(&mut busy.children))
+        };
+        
+        // Time spent here, inside the parent's entry, isn't the parent's own
+        if let Some(parent) = span.parent() {
+            if let Some(busy) = parent.extensions_mut().get_mut::<Busy>().filter(|busy| busy.entered.is_some()) {
+                busy.children += elapsed;
+            }
+        }
+        
+        let stack = span.scope().from_root()
+            .map(|span| span.extensions().get::<Busy>().map_or_else(|| span.name().to_string(), |busy| busy.frame.clone()))
+            .collect::<Vec<_>>()
+            .join(";");
+        self.profiler.record(stack, elapsed.saturating_sub(children));
+    }
+}
@@ -0,0 +1,67 @@
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+tokio::task_local! {
+    static TASK_REQUEST: RequestId;
+}
+
+thread_local! {
+    /// The FUSE operation running on this session thread
+    static THREAD_REQUEST: Cell<Option<RequestId>> = const { Cell::new(None) };
+}
+
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// Correlates everything done on behalf of one kernel operation: its span,
+/// audit entries and the `x-gnos-request-id` header sent to backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId {
+    epoch: u32,
+    seq: u64,
+}
+
+impl RequestId {
+    /// Start a new FUSE operation on the current thread
+    pub fn begin() -> Self {
+        let id = Self::next();
+        THREAD_REQUEST.with(|current| current.set(Some(id)));
+        id
+    }
+    
+    /// A fresh ID for work that runs on a task, e.g. one 9P message
+    pub fn next() -> Self {
+        Self {
+            epoch: process_epoch(),
+            seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+    
+    /// The request being served, from the enclosing task or FUSE thread
+    pub fn current() -> Option<Self> {
+        TASK_REQUEST.try_with(|id| *id).ok()
+            .or_else(|| THREAD_REQUEST.with(|current| current.get()))
+    }
+    
+    /// Run `future` with this ID as the current request, e.g. on a worker task
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        TASK_REQUEST.scope(self, future).await
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}-{:08x}", self.epoch, self.seq)
+    }
+}
+
+/// Start time of this process, so IDs from different runs don't collide
+fn process_epoch() -> u32 {
+    static EPOCH: OnceLock<u32> = OnceLock::new();
+    *EPOCH.get_or_init(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs() as u32)
+    })
+}
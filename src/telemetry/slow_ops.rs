@@ -0,0 +1,133 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{warn, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Prefix of the spans wrapping each FUSE operation
+const OP_PREFIX: &str = "fuse.";
+
+/// Logs FUSE operations slower than a threshold at WARN, with the time spent
+/// in each cache lookup and driver call underneath them
+///
+/// Works from the same spans as OTLP export, so it needs no collector.
+pub struct SlowOpLayer {
+    threshold: Duration,
+}
+
+impl SlowOpLayer {
+    pub fn new(threshold: Duration) -> Self {
+        Self { threshold }
+    }
+}
+
+/// Per-span state kept in the registry's span extensions
+#[derive(Default)]
+struct Timing {
+    started: Option<Instant>,
+    request_id: Option<String>,
+    path: Option<String>,
+    driver: Option<String>,
+    op: Option<String>,
+    /// Finished child spans of an operation, in completion order
+    breakdown: Vec<(String, Duration)>,
+}
+
+impl Timing {
+    fn label(&self, name: &str) -> String {
+        match (&self.op, &self.driver) {
+            (Some(op), Some(driver)) => format!("{}({} {})", name, driver, op),
+            (None, Some(driver)) => format!("{}({})", name, driver),
+            _ => name.to_string(),
+        }
+    }
+}
+
+impl Visit for Timing {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "path" => self.path = Some(value.to_string()),
+            "driver" => self.driver = Some(value.to_string()),
+            "op" => self.op = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // Display-recorded fields (`%path`) arrive here
+        match field.name() {
+            "path" => self.path = Some(format!("{:?}", value)),
+            "request_id" => self.request_id = Some(format!("{:?}", value)),
+            _ => {}
+        }
+    }
+}
+
+impl<S> Layer<S> for SlowOpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        
+        let mut timing = Timing { started: Some(Instant::now()), ..Default::default() };
+        attrs.record(&mut timing);
+        span.extensions_mut().insert(timing);
+    }
+    
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<Timing>() {
+                values.record(timing);
+            }
+        }
+    }
+    
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(timing) = span.extensions_mut().remove::<Timing>() else {
+            return;
+        };
+        let elapsed = timing.started.map_or(Duration::ZERO, |started| started.elapsed());
+        
+        // Children report into the operation that encloses them
+        if let Some(op) = span.scope().skip(1).find(|parent| parent.name().starts_with(OP_PREFIX)) {
+            if let Some(op_timing) = op.extensions_mut().get_mut::<Timing>() {
+                op_timing.breakdown.push((timing.label(span.name()), elapsed));
+                if op_timing.path.is_none() {
+                    op_timing.path = timing.path;
+                }
+                if op_timing.driver.is_none() {
+                    op_timing.driver = timing.driver;
+                }
+            }
+            return;
+        }
+        
+        if !span.name().starts_with(OP_PREFIX) || elapsed < self.threshold {
+            return;
+        }
+        
+        let breakdown = timing.breakdown.iter()
+            .map(|(label, took)| format!("{} {:?}", label, took))
+            .collect::<Vec<_>>()
+            .join(", ");
+        
+        warn!(
+            "🐢 Slow {} took {:?} (request={}, path={}, driver={}) [{}]",
+            &span.name()[OP_PREFIX.len()..],
+            elapsed,
+            timing.request_id.as_deref().unwrap_or("-"),
+            timing.path.as_deref().unwrap_or("-"),
+            timing.driver.as_deref().unwrap_or("-"),
+            if breakdown.is_empty() { "no driver or cache calls" } else { breakdown.as_str() },
+        );
+    }
+}
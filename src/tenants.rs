@@ -0,0 +1,39 @@
+//! Tenant-scoped namespaces
+//!
+//! Each configured tenant gets an isolated subtree, `/tenants/<id>`, served
+//! by its own driver instances built from its own driver config, with its
+//! own quota and audit stream. Paths inside a tenant never reach another
+//! tenant's drivers, and only capabilities issued for that tenant's subtree
+//! can open them.
+
+use std::path::{Component, Path, PathBuf};
+
+pub const TENANTS_ROOT: &str = "/tenants";
+
+/// Subtree a tenant's namespace lives in
+pub fn tenant_root(id: &str) -> PathBuf {
+    Path::new(TENANTS_ROOT).join(id)
+}
+
+/// Tenant whose subtree holds `path`; `/tenants` itself belongs to none
+pub fn tenant_of(path: &Path) -> Option<&str> {
+    let mut components = path.strip_prefix(TENANTS_ROOT).ok()?.components();
+    match components.next() {
+        Some(Component::Normal(id)) => id.to_str(),
+        _ => None,
+    }
+}
+
+/// Where `path`, inside a tenant's subtree, sits in that tenant's own namespace
+pub fn tenant_path(id: &str, path: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(tenant_root(id)).ok()?;
+    Some(Path::new("/").join(relative))
+}
+
+/// Whether `id` can name a tenant subtree
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id != "."
+        && id != ".."
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
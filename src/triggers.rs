@@ -0,0 +1,231 @@
+//! Per-path write triggers
+//!
+//! Rules attach an action to a path pattern. Matching change events are
+//! queued for a pool of workers, which run the action with retries and
+//! keep an audit trail of every run, readable at `/proc/gnos/triggers`:
+//!
+//! ```toml
+//! [[triggers.rules]]
+//! pattern = "/cloud/aws/s3/incoming/*"
+//! action = "prompt"
+//! model = "/proc/llama3"
+//! template = "Summarize this document:\n{content}"
+//! output = "{path}.summary"
+//! ```
+//!
+//! Actions act through a client that publishes no events, so a trigger's
+//! own writes never set off further triggers.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tracing::{debug, info, warn};
+
+use crate::client::GnosClient;
+use crate::config::{TriggerAction, TriggerConfig, TriggerRule};
+use crate::drivers::network::SharedHttpClient;
+use crate::events::{EventBus, EventKind, NamespaceEvent};
+use crate::qos::{self, QosClass};
+use crate::search;
+use crate::{GnosError, Result};
+
+/// Runs kept for `/proc/gnos/triggers`
+const AUDIT_CAPACITY: usize = 256;
+
+/// One execution of a trigger, successful or not
+#[derive(Debug, Clone)]
+pub struct TriggerRun {
+    pub timestamp: SystemTime,
+    pub pattern: String,
+    pub path: PathBuf,
+    pub event: EventKind,
+    pub attempts: u32,
+    /// `None` on success
+    pub error: Option<String>,
+}
+
+/// Body POSTed by webhook actions
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    pattern: &'a str,
+    event: &'a NamespaceEvent,
+}
+
+struct Job {
+    rule: Arc<TriggerRule>,
+    event: NamespaceEvent,
+}
+
+pub struct TriggerEngine {
+    client: GnosClient,
+    http: Arc<SharedHttpClient>,
+    rules: Vec<Arc<TriggerRule>>,
+    workers: usize,
+    queue_size: usize,
+    max_retries: u32,
+    retry_backoff: Duration,
+    audit: Mutex<VecDeque<TriggerRun>>,
+}
+
+impl TriggerEngine {
+    /// `client` must not publish events of its own; webhooks are posted
+    /// through `http`
+    pub fn new(config: &TriggerConfig, client: GnosClient, http: Arc<SharedHttpClient>) -> Arc<Self> {
+        Arc::new(Self {
+            client,
+            http,
+            rules: config.rules.iter().cloned().map(Arc::new).collect(),
+            workers: config.workers.max(1),
+            queue_size: config.queue_size.max(1),
+            max_retries: config.max_retries,
+            retry_backoff: Duration::from_millis(config.retry_backoff_ms),
+            audit: Mutex::new(VecDeque::new()),
+        })
+    }
+    
+    /// Subscribe to `events` and start the workers
+    pub fn spawn(self: &Arc<Self>, events: &EventBus) {
+        let (jobs, job_rx) = mpsc::channel::<Job>(self.queue_size);
+        let job_rx = Arc::new(tokio::sync::Mutex::new(job_rx));
+        
+        for _ in 0..self.workers {
+            let engine = self.clone();
+            let job_rx = job_rx.clone();
+            tokio::spawn(qos::scope(QosClass::Background, async move {
+                loop {
+                    let Some(job) = job_rx.lock().await.recv().await else {
+                        return;
+                    };
+                    engine.run(job).await;
+                }
+            }));
+        }
+        
+        let engine = self.clone();
+        let mut subscription = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let event = match subscription.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("⚡ Triggers missed {} events", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                
+                for rule in engine.matching(&event) {
+                    if jobs.try_send(Job { rule: rule.clone(), event: event.clone() }).is_err() {
+                        warn!("⚡ Trigger queue full, dropped {} for {}", rule.pattern, event.path.display());
+                        engine.record(rule, &event, 0, Some("dropped: queue full".to_string()));
+                    }
+                }
+            }
+        });
+        
+        info!("⚡ {} triggers armed with {} workers", self.rules.len(), self.workers);
+    }
+    
+    pub fn status_report(&self) -> String {
+        let mut report = String::new();
+        for run in self.audit.lock().unwrap().iter() {
+            let at = chrono::DateTime::<chrono::Utc>::from(run.timestamp).format("%Y-%m-%dT%H:%M:%SZ");
+            let outcome = run.error.as_deref().unwrap_or("ok");
+            report.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{} attempts\t{}\n",
+                at, run.pattern, run.event.as_str(), run.path.display(), run.attempts, outcome,
+            ));
+        }
+        report
+    }
+    
+    fn matching<'a>(&'a self, event: &'a NamespaceEvent) -> impl Iterator<Item = &'a Arc<TriggerRule>> {
+        self.rules.iter().filter(|rule| rule.on.contains(&event.kind) && matches(&rule.pattern, &event.path))
+    }
+    
+    async fn run(&self, job: Job) {
+        let Job { rule, event } = job;
+        let mut attempts = 0;
+        
+        let result = loop {
+            attempts += 1;
+            match self.execute(&rule, &event).await {
+                Ok(()) => break Ok(()),
+                Err(e) if attempts <= self.max_retries => {
+                    let backoff = e.retry_after().unwrap_or_else(|| self.retry_backoff * 2u32.saturating_pow(attempts - 1));
+                    debug!("Trigger {} on {} failed ({}), retrying in {:?}", rule.pattern, event.path.display(), e, backoff);
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        
+        match &result {
+            Ok(()) => info!("⚡ Trigger {} ran for {}", rule.pattern, event.path.display()),
+            Err(e) => warn!("❌ Trigger {} failed for {} after {} attempts: {}", rule.pattern, event.path.display(), attempts, e),
+        }
+        self.record(&rule, &event, attempts, result.err().map(|e| e.to_string()));
+    }
+    
+    async fn execute(&self, rule: &TriggerRule, event: &NamespaceEvent) -> Result<()> {
+        match &rule.action {
+            TriggerAction::Prompt { model, template, output } => {
+                let prompt = self.render(template, event).await?;
+                self.client.write(model, prompt.as_bytes()).await?;
+                let answer = self.client.read(model).await?;
+                
+                if let Some(output) = output {
+                    let output = PathBuf::from(self.render(output, event).await?);
+                    self.client.write(&output, &answer).await?;
+                }
+                Ok(())
+            }
+            TriggerAction::Webhook { url } => {
+                let payload = WebhookPayload { pattern: &rule.pattern, event };
+                let (status, _) = self.http.fetch(self.http.client().post(url).json(&payload)).await
+                    .map_err(|e| e.context(format!("webhook {}", url)))?;
+                if !status.is_success() {
+                    return Err(GnosError::Driver(format!("webhook {} returned {}", url, status)));
+                }
+                Ok(())
+            }
+        }
+    }
+    
+    /// Fill in a template; the changed file is only read when it uses `{content}`
+    async fn render(&self, template: &str, event: &NamespaceEvent) -> Result<String> {
+        let name = event.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut rendered = template
+            .replace("{path}", &event.path.to_string_lossy())
+            .replace("{name}", &name)
+            .replace("{event}", event.kind.as_str());
+        if rendered.contains("{content}") {
+            let content = self.client.read(&event.path).await?;
+            rendered = rendered.replace("{content}", &String::from_utf8_lossy(&content));
+        }
+        Ok(rendered)
+    }
+    
+    fn record(&self, rule: &TriggerRule, event: &NamespaceEvent, attempts: u32, error: Option<String>) {
+        let mut audit = self.audit.lock().unwrap();
+        if audit.len() >= AUDIT_CAPACITY {
+            audit.pop_front();
+        }
+        audit.push_back(TriggerRun {
+            timestamp: SystemTime::now(),
+            pattern: rule.pattern.clone(),
+            path: event.path.clone(),
+            event: event.kind,
+            attempts,
+            error,
+        });
+    }
+}
+
+fn matches(pattern: &str, path: &Path) -> bool {
+    search::glob_match(pattern.as_bytes(), path.to_string_lossy().as_bytes())
+}
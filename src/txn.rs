@@ -0,0 +1,168 @@
+//! Multi-file transactions
+//!
+//! A transaction stages writes and deletes and applies them together, for
+//! changes such as a config push that must never be half applied. When
+//! every path belongs to one driver with atomic batches, the driver commits
+//! them in one step. Otherwise each touched path is snapshotted first, the
+//! operations are applied in order, and on a failure the paths already
+//! changed are restored from their snapshots.
+//!
+//! ```no_run
+//! # async fn demo(client: gnos::GnosClient) -> gnos::Result<()> {
+//! use std::path::Path;
+//!
+//! let mut txn = client.transaction();
+//! txn.write(Path::new("/cloud/aws/s3/cfg/app.toml"), b"replicas = 3\n".to_vec())
+//!    .delete(Path::new("/cloud/aws/s3/cfg/app.toml.bak"));
+//! txn.commit().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tracing::{info, warn};
+
+use crate::cache::CompressionPolicy;
+use crate::client::GnosClient;
+use crate::drivers::{BatchOp, DriverRegistry, GnosDriver};
+use crate::tenants::tenant_of;
+use crate::{GnosError, Result};
+
+/// Changes staged against a `GnosClient`; dropping it without committing discards them
+pub struct Transaction {
+    client: GnosClient,
+    ops: Vec<BatchOp>,
+}
+
+impl Transaction {
+    pub(crate) fn new(client: GnosClient) -> Self {
+        Self {
+            client,
+            ops: Vec::new(),
+        }
+    }
+    
+    pub fn write(&mut self, path: &Path, data: impl Into<Bytes>) -> &mut Self {
+        self.ops.push(BatchOp::Write {
+            path: path.to_path_buf(),
+            data: data.into(),
+        });
+        self
+    }
+    
+    pub fn delete(&mut self, path: &Path) -> &mut Self {
+        self.ops.push(BatchOp::Delete { path: path.to_path_buf() });
+        self
+    }
+    
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+    
+    /// Apply every staged change, or none; returns how many were applied
+    pub async fn commit(self) -> Result<usize> {
+        self.client.commit_batch(self.ops).await
+    }
+}
+
+/// Apply `ops` atomically where the backend allows, with snapshot rollback otherwise
+///
+/// Permission checks are the caller's job. Snapshots hold the previous
+/// contents of every touched path in memory until the commit finishes.
+pub async fn apply(registry: &DriverRegistry, compression: &CompressionPolicy, ops: Vec<BatchOp>) -> Result<usize> {
+    if ops.is_empty() {
+        return Ok(0);
+    }
+    
+    let tenant = tenant_of(ops[0].path());
+    if ops.iter().any(|op| tenant_of(op.path()) != tenant) {
+        return Err(GnosError::PermissionDenied("a transaction can't span tenant namespaces".to_string()));
+    }
+    
+    let drivers = ops.iter()
+        .map(|op| registry.get_driver(op.path())
+            .ok_or_else(|| GnosError::PathNotFound(op.path().display().to_string())))
+        .collect::<Result<Vec<_>>>()?;
+    
+    let first = &drivers[0];
+    if first.supports_batches() && drivers.iter().all(|driver| driver.name() == first.name()) {
+        first.commit_batch(&ops).await?;
+        info!("🧾 Committed {} operations atomically on {}", ops.len(), first.name());
+        return Ok(ops.len());
+    }
+    
+    // Nothing is changed until every snapshot has been taken
+    let mut snapshots: Vec<Snapshot> = Vec::new();
+    for (op, driver) in ops.iter().zip(&drivers) {
+        if snapshots.iter().any(|snapshot| snapshot.path == op.path()) {
+            continue;
+        }
+        let before = match driver.read(op.path()).await {
+            Ok(data) => Some(data),
+            Err(GnosError::PathNotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        snapshots.push(Snapshot {
+            path: op.path().to_path_buf(),
+            driver: driver.clone(),
+            before,
+        });
+    }
+    
+    for (index, (op, driver)) in ops.iter().zip(&drivers).enumerate() {
+        let result = match op {
+            BatchOp::Write { path, data } => compression.write_through(driver.as_ref(), path, data).await,
+            BatchOp::Delete { path } => driver.delete(path).await,
+        };
+        let Err(e) = result else {
+            continue;
+        };
+        
+        warn!("↩️  Transaction failed at operation {} of {}: {}; rolling back", index + 1, ops.len(), e);
+        // The failed operation may have half-applied, so its path is restored too
+        let touched: Vec<&Path> = ops[..=index].iter().map(BatchOp::path).collect();
+        let mut stuck = Vec::new();
+        for snapshot in snapshots.iter().filter(|snapshot| touched.contains(&snapshot.path.as_path())) {
+            if let Err(restore_error) = snapshot.restore(compression).await {
+                warn!("🧨 Rollback of {} failed: {}", snapshot.path.display(), restore_error);
+                stuck.push(snapshot.path.display().to_string());
+            }
+        }
+        
+        if stuck.is_empty() {
+            return Err(e);
+        }
+        return Err(GnosError::Driver(format!(
+            "transaction failed ({}) and rollback could not restore {}", e, stuck.join(", ")
+        )));
+    }
+    
+    info!("🧾 Committed {} operations", ops.len());
+    Ok(ops.len())
+}
+
+/// A path's contents before the transaction touched it; `None` if it didn't exist
+struct Snapshot {
+    path: PathBuf,
+    driver: Arc<dyn GnosDriver>,
+    before: Option<Bytes>,
+}
+
+impl Snapshot {
+    async fn restore(&self, compression: &CompressionPolicy) -> Result<()> {
+        match &self.before {
+            Some(data) => compression.write_through(self.driver.as_ref(), &self.path, data).await,
+            None => match self.driver.delete(&self.path).await {
+                Err(GnosError::PathNotFound(_)) => Ok(()),
+                result => result,
+            },
+        }
+    }
+}
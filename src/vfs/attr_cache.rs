@@ -0,0 +1,52 @@
+//! Short-lived cache of driver metadata, keyed by inode
+//!
+//! Filled by `getattr` misses and, more importantly, by `readdir` when a
+//! driver returns metadata alongside its listing, so an `ls -l` costs one
+//! backend call instead of one per entry.
+
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+use crate::drivers::ResourceMetadata;
+
+pub struct AttrCache {
+    ttl: Duration,
+    entries: DashMap<u64, (ResourceMetadata, Instant)>,
+}
+
+impl AttrCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: DashMap::new(),
+        }
+    }
+    
+    pub fn get(&self, ino: u64) -> Option<ResourceMetadata> {
+        let entry = self.entries.get(&ino)?;
+        let (metadata, cached_at) = entry.value();
+        (cached_at.elapsed() < self.ttl).then(|| metadata.clone())
+    }
+    
+    /// Last known metadata however old, for when the driver can't be asked
+    pub fn get_stale(&self, ino: u64) -> Option<ResourceMetadata> {
+        self.entries.get(&ino).map(|entry| entry.value().0.clone())
+    }
+    
+    pub fn insert(&self, ino: u64, metadata: ResourceMetadata) {
+        self.entries.insert(ino, (metadata, Instant::now()));
+    }
+    
+    pub fn invalidate(&self, ino: u64) {
+        self.entries.remove(&ino);
+    }
+    
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
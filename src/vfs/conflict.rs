@@ -0,0 +1,122 @@
+//! Optimistic concurrency for shared objects
+//!
+//! Under a `vfs.conditional_writes` prefix, a handle opened for writing
+//! remembers the version of the object it was opened against: its entity
+//! tag where the backend reports one, else its size and modification time.
+//! When the handle is flushed the write only lands if the object is still at
+//! that version, so two writers sharing a bucket can't silently overwrite
+//! each other. Backends with `If-Match` check this atomically; for the rest
+//! the VFS compares the object's metadata just before writing.
+//!
+//! A write that lost the race fails with `EBUSY`, and the
+//! `user.gnos.conflict` xattr says what changed until the file is next
+//! written successfully.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+
+use crate::drivers::{Precondition, ResourceMetadata};
+use crate::vfs::path::is_within;
+
+/// Extended attribute describing the last conflicting write to a file
+pub const CONFLICT_XATTR: &str = "user.gnos.conflict";
+
+/// The state of an object a write was based on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Version {
+    /// Nothing was there yet
+    Absent,
+    Present {
+        etag: Option<String>,
+        size: u64,
+        mtime: SystemTime,
+    },
+}
+
+impl Version {
+    pub fn of(metadata: &ResourceMetadata) -> Self {
+        Version::Present {
+            etag: metadata.etag.clone(),
+            size: metadata.size,
+            mtime: metadata.last_modified,
+        }
+    }
+    
+    /// What to ask a backend that checks preconditions itself, if this
+    /// version can be expressed as one
+    pub fn precondition(&self) -> Option<Precondition> {
+        match self {
+            Version::Absent => Some(Precondition::Absent),
+            Version::Present { etag: Some(etag), .. } => Some(Precondition::Matches(etag.clone())),
+            Version::Present { etag: None, .. } => None,
+        }
+    }
+    
+    /// Whether `current` is still this version; entity tags are compared
+    /// when both sides have one
+    pub fn matches(&self, current: &Version) -> bool {
+        match (self, current) {
+            (Version::Absent, Version::Absent) => true,
+            (Version::Present { etag: Some(base), .. }, Version::Present { etag: Some(current), .. }) => base == current,
+            (
+                Version::Present { size: base_size, mtime: base_mtime, .. },
+                Version::Present { size, mtime, .. },
+            ) => base_size == size && base_mtime == mtime,
+            _ => false,
+        }
+    }
+    
+    /// e.g. `etag "abc", 1024 bytes, modified 2026-10-16T09:00:00+00:00`
+    pub fn describe(&self) -> String {
+        match self {
+            Version::Absent => "absent".to_string(),
+            Version::Present { etag, size, mtime } => format!(
+                "{}{} bytes, modified {}",
+                etag.as_ref().map_or_else(String::new, |etag| format!("etag {}, ", etag)),
+                size,
+                chrono::DateTime::<chrono::Utc>::from(*mtime).to_rfc3339(),
+            ),
+        }
+    }
+}
+
+/// Prefixes under conditional writes, and the conflicts last seen on them
+#[derive(Default)]
+pub struct ConflictTable {
+    prefixes: Vec<PathBuf>,
+    recent: DashMap<PathBuf, String>,
+}
+
+impl ConflictTable {
+    pub fn new(prefixes: &[String]) -> Self {
+        Self {
+            prefixes: prefixes.iter().map(PathBuf::from).collect(),
+            recent: DashMap::new(),
+        }
+    }
+    
+    pub fn enabled(&self) -> bool {
+        !self.prefixes.is_empty()
+    }
+    
+    /// Whether writes to `path` are conditional
+    pub fn covers(&self, path: &Path) -> bool {
+        self.prefixes.iter().any(|prefix| is_within(path, prefix))
+    }
+    
+    pub fn record(&self, path: &Path, description: String) {
+        self.recent.insert(path.to_path_buf(), description);
+    }
+    
+    /// Forget a conflict once the file has been written
+    pub fn clear(&self, path: &Path) {
+        self.recent.remove(path);
+    }
+    
+    /// The last conflict on `path`, for `user.gnos.conflict`
+    pub fn describe(&self, path: &Path) -> Option<String> {
+        self.recent.get(path).map(|description| description.clone())
+    }
+}
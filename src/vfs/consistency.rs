@@ -0,0 +1,180 @@
+//! Read-your-writes for eventually consistent backends
+//!
+//! An object store may keep serving the previous version of an object for a
+//! while after it was overwritten, and a write-back queue holds writes back
+//! on purpose. Under a `vfs.read_your_writes` prefix the mount remembers
+//! what it last wrote to each path and answers reads and `stat` from that
+//! copy until the backend reports the new version, so `echo x > file && cat
+//! file` prints `x` whatever the backend's consistency.
+//!
+//! A write is taken as visible once the backend reports its size with a
+//! modification time no older than the write, and its copy is dropped. A
+//! copy the backend never catches up with is dropped
+//! `read_your_writes_seconds` after the write, unless a queued write for the
+//! path hasn't been uploaded yet, so changes made by other clients are
+//! hidden no longer than that.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::Bytes;
+use tracing::debug;
+
+use crate::drivers::ResourceMetadata;
+use crate::vfs::memory::{MemoryBudget, MemoryUse, Reservation};
+use crate::vfs::path::is_within;
+
+/// Backends that keep whole-second modification times round the write's down
+const MTIME_GRANULARITY: Duration = Duration::from_secs(1);
+
+struct Recent {
+    data: Bytes,
+    written_at: SystemTime,
+    recorded: Instant,
+    /// The copy's share of the memory budget
+    _memory: Reservation,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<PathBuf, Recent>,
+    bytes: usize,
+}
+
+/// Writes this mount made that the backend may not show yet
+pub struct RecentWrites {
+    prefixes: Vec<PathBuf>,
+    window: Duration,
+    max_bytes: usize,
+    memory: Arc<MemoryBudget>,
+    state: Mutex<State>,
+}
+
+impl RecentWrites {
+    pub fn new(prefixes: &[String], window: Duration, max_bytes: usize, memory: Arc<MemoryBudget>) -> Self {
+        Self {
+            prefixes: prefixes.iter().map(PathBuf::from).collect(),
+            window,
+            max_bytes,
+            memory,
+            state: Mutex::new(State::default()),
+        }
+    }
+    
+    pub fn disabled() -> Self {
+        Self::new(&[], Duration::ZERO, 0, MemoryBudget::unbounded())
+    }
+    
+    pub fn enabled(&self) -> bool {
+        !self.prefixes.is_empty() && !self.window.is_zero()
+    }
+    
+    fn covers(&self, path: &Path) -> bool {
+        self.enabled() && self.prefixes.iter().any(|prefix| is_within(path, prefix))
+    }
+    
+    /// Remember what was just written to `path`; a payload that doesn't fit
+    /// still replaces the older copy, so a stale one is never served.
+    /// `pending` tells which paths still have a queued write, whose copies
+    /// outlive the window
+    pub fn record(&self, path: &Path, data: Bytes, pending: impl Fn(&Path) -> bool) {
+        if !self.covers(path) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        Self::drop_entry(&mut state, path);
+        self.expire(&mut state, pending);
+        if state.bytes + data.len() > self.max_bytes {
+            debug!("Not keeping {} bytes written to {} for read-your-writes", data.len(), path.display());
+            return;
+        }
+        let Some(memory) = self.memory.try_reserve(MemoryUse::Caches, data.len() as u64) else {
+            debug!("No memory to keep {} bytes written to {} for read-your-writes", data.len(), path.display());
+            return;
+        };
+        state.bytes += data.len();
+        state.entries.insert(path.to_path_buf(), Recent {
+            data,
+            written_at: SystemTime::now(),
+            recorded: Instant::now(),
+            _memory: memory,
+        });
+    }
+    
+    /// Stop answering for `path`, e.g. once it is deleted
+    pub fn forget(&self, path: &Path) {
+        if self.enabled() {
+            Self::drop_entry(&mut self.state.lock().unwrap(), path);
+        }
+    }
+    
+    /// What was last written to `path`, if the backend may not show it yet
+    pub fn get(&self, path: &Path, pending: impl Fn(&Path) -> bool) -> Option<Bytes> {
+        if !self.enabled() {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, pending);
+        state.entries.get(path).map(|recent| recent.data.clone())
+    }
+    
+    /// Size and modification time to report for `path` given what the
+    /// backend says, dropping the copy once the backend has caught up
+    pub fn reconcile(
+        &self,
+        path: &Path,
+        metadata: Option<&ResourceMetadata>,
+        pending: impl Fn(&Path) -> bool,
+    ) -> Option<(u64, SystemTime)> {
+        if !self.enabled() {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, &pending);
+        let recent = state.entries.get(path)?;
+        
+        let visible = !pending(path) && metadata.is_some_and(|metadata| {
+            metadata.size == recent.data.len() as u64
+                && metadata.last_modified + MTIME_GRANULARITY >= recent.written_at
+        });
+        if visible {
+            debug!("Write to {} is visible at the backend", path.display());
+            Self::drop_entry(&mut state, path);
+            return None;
+        }
+        Some((recent.data.len() as u64, recent.written_at))
+    }
+    
+    /// Drop copies older than the window, except those of pending writes
+    fn expire(&self, state: &mut State, pending: impl Fn(&Path) -> bool) {
+        let expired: Vec<PathBuf> = state.entries.iter()
+            .filter(|(path, recent)| recent.recorded.elapsed() >= self.window && !pending(path))
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in expired {
+            Self::drop_entry(state, &path);
+        }
+    }
+    
+    fn drop_entry(state: &mut State, path: &Path) {
+        if let Some(recent) = state.entries.remove(path) {
+            state.bytes -= recent.data.len();
+        }
+    }
+    
+    /// Plain-text view for `/proc/gnos/consistency`
+    pub fn status_report(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut report = format!(
+            "window: {}s\nheld: {} ({} bytes of {})\n",
+            self.window.as_secs(), state.entries.len(), state.bytes, self.max_bytes,
+        );
+        for (path, recent) in &state.entries {
+            report.push_str(&format!("{}\t{}\t{}s ago\n",
+                                     path.display(), recent.data.len(), recent.recorded.elapsed().as_secs()));
+        }
+        report
+    }
+}
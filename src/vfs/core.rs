@@ -0,0 +1,1751 @@
+//! Frontend-independent filesystem logic
+//!
+//! The FUSE mount and the 9P server both translate their protocol into calls
+//! on a `VfsCore`, so permission checks, attribute and chunk caching, and
+//! write-back behave the same whichever way the namespace is reached.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::Bytes;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use tracing::{debug, info, info_span, warn, Instrument, Span};
+
+use crate::cache::{object_version, CompressionPolicy, DiskCache};
+use crate::client::GnosClient;
+use crate::config::{CacheMode, CacheModeRule, RetentionMode, SearchConfig, VfsConfig};
+use crate::drivers::chat::SESSIONS_ROOT;
+use crate::drivers::models::MODELS_ROOT;
+use crate::drivers::{BatchOp, DriverRegistry, GnosDriver, PartPolicy, Precondition, ResourceMetadata};
+use crate::events::{EventBus, EventKind};
+use crate::index::ContentIndex;
+use crate::mime::{self, MIME_XATTR};
+use crate::pipeline;
+use crate::search::{SearchEngine, SearchQuery};
+use crate::security::{CapabilityManager, Operation, RevokedWrites};
+use crate::tenants::{tenant_root, TENANTS_ROOT};
+use crate::vfs::attr_cache::AttrCache;
+use crate::vfs::conflict::{ConflictTable, Version, CONFLICT_XATTR};
+use crate::vfs::consistency::RecentWrites;
+use crate::vfs::handles::{HandleTrace, HandleTraces, TRACE_XATTR};
+use crate::vfs::inode::{GnosInode, InodeManager};
+use crate::vfs::keys::{DataKey, KeyRing};
+use crate::vfs::memory::{MemoryBudget, MemoryUse, Reservation};
+use crate::vfs::namespace::NamespaceFilter;
+use crate::vfs::offline::{Connectivity, RemoteVersion};
+use crate::vfs::path::{is_within, join_name};
+use crate::vfs::pipelines::{self as pipeline_dir, PipelineTable, PIPELINES_ROOT};
+use crate::vfs::procfs::{ProcFs, PROC_ROOT};
+use crate::vfs::quota::{QuotaStats, QuotaTable};
+use crate::vfs::retention::{Protection, RetentionTable, RETENTION_XATTR};
+use crate::vfs::search::{self as search_dir, SearchTable, SEARCH_QUERY, SEARCH_ROOT};
+use crate::vfs::shards::ShardView;
+use crate::vfs::template::TemplateSet;
+use crate::vfs::txn::{TxnTable, TXN_CONTROL};
+use crate::vfs::warm;
+use crate::vfs::writeback::{SyncState, WriteBackQueue};
+use crate::{txn, GnosError, Result};
+
+pub const ROOT_INODE: u64 = 1;
+
+/// Extended attribute reporting write-back state (clean, dirty, error: ...)
+pub const SYNC_XATTR: &str = "user.gnos.sync";
+
+/// Bytes a rename copies at a time
+const RENAME_CHUNK: u32 = 1024 * 1024;
+
+/// Driver roots recreated inside each tenant's subtree, relative to it
+const TENANT_DRIVER_ROOTS: &[(&str, bool)] = &[
+    ("proc", true),
+    ("proc/llama3", false),
+    ("proc/models", true),
+    ("proc/sessions", true),
+    ("cloud", true),
+    ("cloud/aws", true),
+    ("cloud/gcp", true),
+    ("cloud/azure", true),
+    ("net", true),
+    ("net/http", true),
+    ("dev", true),
+    ("dev/sensors", true),
+];
+
+/// Shared state behind every frontend; cheap to clone
+#[derive(Clone)]
+pub struct VfsCore {
+    pub(crate) driver_registry: Arc<DriverRegistry>,
+    pub(crate) capability_manager: Arc<CapabilityManager>,
+    pub(crate) inode_manager: Arc<InodeManager>,
+    pub(crate) attr_cache: Arc<AttrCache>,
+    pub(crate) cache_modes: Arc<Vec<CacheModeRule>>,
+    pub(crate) disk_cache: Option<Arc<DiskCache>>,
+    pub(crate) compression: Arc<CompressionPolicy>,
+    pub(crate) write_back: Option<Arc<WriteBackQueue>>,
+    pub(crate) connectivity: Arc<Connectivity>,
+    pub(crate) procfs: Arc<ProcFs>,
+    pub(crate) transactions: Arc<TxnTable>,
+    pub(crate) search_engine: Arc<SearchEngine>,
+    pub(crate) searches: Arc<SearchTable>,
+    pub(crate) pipelines: Arc<PipelineTable>,
+    pub(crate) quotas: Arc<QuotaTable>,
+    pub(crate) retention: Arc<RetentionTable>,
+    pub(crate) conflicts: Arc<ConflictTable>,
+    pub(crate) recent_writes: Arc<RecentWrites>,
+    pub(crate) shards: Arc<ShardView>,
+    pub(crate) index: Option<Arc<ContentIndex>>,
+    pub(crate) events: Option<Arc<EventBus>>,
+    pub(crate) templates: Arc<TemplateSet>,
+    pub(crate) namespace: Arc<NamespaceFilter>,
+    pub(crate) handle_traces: Arc<HandleTraces>,
+    pub(crate) keys: Arc<KeyRing>,
+    pub(crate) memory: Arc<MemoryBudget>,
+}
+
+/// An inode with its driver-reported size and modification time
+#[derive(Debug, Clone)]
+pub struct NodeAttr {
+    pub inode: GnosInode,
+    pub size: u64,
+    pub mtime: SystemTime,
+    /// Mode bits as the caller's capability allows them, without the file type
+    pub perm: u16,
+    /// BSD file flags, set for objects under a retention rule
+    pub flags: u32,
+}
+
+/// Per-handle state: a FUSE file handle or a 9P fid opened for I/O
+#[derive(Debug)]
+pub struct OpenFile {
+    pub path: PathBuf,
+    /// Driver payload shared with the driver's own cache; reads slice it
+    data: Option<Bytes>,
+    /// Writes accumulated since the last commit, from the end of what
+    /// `upload` has sent
+    write_buffer: Option<Vec<u8>>,
+    /// The write buffer's share of the memory budget
+    memory: Reservation,
+    /// Multipart upload a large sequential write is being sent through
+    upload: Option<PartUpload>,
+    /// Lowest offset written or truncated to since the last commit, which
+    /// tells an append from a rewrite under an append-only rule
+    written_from: Option<u64>,
+    /// Version of the object the handle's writes are based on, when writes
+    /// to it are conditional
+    base: Option<Version>,
+    /// `data` is a streaming resource that hasn't finished yet
+    growing: bool,
+    /// Offset of `data` in a stream that drops its oldest bytes
+    data_offset: u64,
+    /// How far a stream reader was moved ahead past bytes dropped before it
+    /// got to them; its offsets are this much behind the stream's
+    skew: u64,
+    pub cache_mode: CacheMode,
+    /// Login session of the opener, which scopes `/.gnos/txn` transactions
+    pub session: Option<u32>,
+    /// What was done through the handle, when handle tracing is on
+    pub trace: Option<Arc<Mutex<HandleTrace>>>,
+    /// The opener's data key, when it set one over the path
+    pub key: Option<Arc<DataKey>>,
+    /// Token the handle was opened for writing with; each chunk written
+    /// through it is fenced by that token's capability
+    grant: Option<String>,
+}
+
+/// A write being sent in parts before its handle is committed
+#[derive(Debug)]
+struct PartUpload {
+    id: String,
+    policy: PartPolicy,
+    /// Bytes already sent, which the handle no longer holds
+    sent: u64,
+    parts: u64,
+}
+
+impl OpenFile {
+    /// Fence writes through the handle by `token`, the capability of the
+    /// remote client that opened it, instead of the mount's own
+    pub fn fence_with(&mut self, token: &str) {
+        self.grant = Some(token.to_string());
+    }
+    
+    /// Buffer a write at `offset`; the driver sees the whole object on
+    /// commit. Bytes already sent in parts can't be written again
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<()> {
+        let start = self.buffer_offset(offset)?;
+        self.touch(offset);
+        let buffer = self.write_buffer.get_or_insert_with(Vec::new);
+        let end = start + data.len();
+        if buffer.len() < end {
+            buffer.resize(end, 0);
+        }
+        buffer[start..end].copy_from_slice(data);
+        Ok(())
+    }
+    
+    /// Size of the pending write
+    pub fn buffered_len(&self) -> u64 {
+        self.sent() + self.write_buffer.as_ref().map_or(0, |buffer| buffer.len() as u64)
+    }
+    
+    /// Whether a commit would push anything
+    pub fn has_pending_write(&self) -> bool {
+        self.write_buffer.is_some() || self.upload.is_some()
+    }
+    
+    fn sent(&self) -> u64 {
+        self.upload.as_ref().map_or(0, |upload| upload.sent)
+    }
+    
+    /// Where `offset` falls in the write buffer
+    fn buffer_offset(&self, offset: u64) -> Result<usize> {
+        offset.checked_sub(self.sent())
+            .map(|start| start as usize)
+            .ok_or_else(|| GnosError::InvalidPath(format!(
+                "{} is being uploaded in parts; its first {} bytes are already sent",
+                self.path.display(), self.sent(),
+            )))
+    }
+    
+    /// Record a frontend operation in the handle's trace, if it has one
+    pub fn trace_op<T>(&self, op: &'static str, offset: Option<u64>, bytes: u64, started: Instant, result: &Result<T>) {
+        if let Some(trace) = &self.trace {
+            trace.lock().unwrap().op(op, offset, bytes, started, result);
+        }
+    }
+    
+    /// Record a driver call made for this handle in its trace, if it has one
+    fn trace_driver<T>(&self, op: &'static str, driver: &'static str, offset: Option<u64>, bytes: u64, started: Instant, result: &Result<T>) {
+        if let Some(trace) = &self.trace {
+            trace.lock().unwrap().driver_call(op, driver, offset, bytes, started, result);
+        }
+    }
+    
+    /// Truncating an open handle resizes its pending write
+    pub fn truncate(&mut self, size: u64) -> Result<()> {
+        let len = self.buffer_offset(size)?;
+        self.memory.try_grow_to(len as u64)?;
+        self.touch(size);
+        self.write_buffer.get_or_insert_with(Vec::new).resize(len, 0);
+        self.memory.shrink_to(len as u64);
+        Ok(())
+    }
+    
+    /// Driver data as the opener reads it, opened with its key if it has one
+    fn plaintext(&self, data: Bytes) -> Result<Bytes> {
+        match &self.key {
+            Some(key) => key.open(&self.path, data),
+            None => Ok(data),
+        }
+    }
+    
+    fn touch(&mut self, offset: u64) {
+        self.written_from = Some(self.written_from.map_or(offset, |from| from.min(offset)));
+    }
+}
+
+impl VfsCore {
+    pub fn new(
+        driver_registry: Arc<DriverRegistry>,
+        capability_manager: Arc<CapabilityManager>,
+    ) -> Self {
+        let inode_manager = InodeManager::new();
+        
+        // Create root directory
+        inode_manager.create_directory(ROOT_INODE, PathBuf::from("/"));
+        
+        // Pre-create known structure
+        inode_manager.create_directory(2, PathBuf::from("/proc"));
+        inode_manager.create_directory(3, PathBuf::from("/cloud"));
+        inode_manager.create_directory(4, PathBuf::from("/net"));
+        inode_manager.create_directory(5, PathBuf::from("/dev"));
+        inode_manager.create_directory(6, PathBuf::from(PROC_ROOT));
+        inode_manager.create_directory(7, PathBuf::from("/.gnos"));
+        inode_manager.create_file(8, PathBuf::from(TXN_CONTROL));
+        inode_manager.create_directory(9, PathBuf::from(SEARCH_ROOT));
+        inode_manager.create_file(11, PathBuf::from(SEARCH_QUERY));
+        inode_manager.create_directory(12, PathBuf::from(PIPELINES_ROOT));
+        inode_manager.create_directory(13, PathBuf::from(MODELS_ROOT));
+        inode_manager.create_directory(14, PathBuf::from(SESSIONS_ROOT));
+        
+        // AI models
+        inode_manager.create_file(10, PathBuf::from("/proc/llama3"));
+        
+        // Driver roots
+        inode_manager.create_directory(20, PathBuf::from("/cloud/aws"));
+        inode_manager.create_directory(21, PathBuf::from("/cloud/gcp"));
+        inode_manager.create_directory(22, PathBuf::from("/cloud/azure"));
+        inode_manager.create_directory(30, PathBuf::from("/net/http"));
+        inode_manager.create_directory(40, PathBuf::from("/dev/sensors"));
+        
+        let search_engine = SearchEngine::new(&SearchConfig::default(), driver_registry.clone(), capability_manager.clone());
+        
+        Self {
+            driver_registry,
+            capability_manager,
+            inode_manager: Arc::new(inode_manager),
+            attr_cache: Arc::new(AttrCache::new(Duration::from_secs(VfsConfig::default().attr_cache_ttl_seconds))),
+            cache_modes: Arc::new(Vec::new()),
+            disk_cache: None,
+            compression: Arc::new(CompressionPolicy::default()),
+            write_back: None,
+            connectivity: Arc::new(Connectivity::disabled()),
+            procfs: Arc::new(ProcFs::new()),
+            transactions: Arc::new(TxnTable::new()),
+            search_engine: Arc::new(search_engine),
+            searches: Arc::new(SearchTable::new()),
+            pipelines: Arc::new(PipelineTable::new()),
+            quotas: Arc::new(QuotaTable::new(Vec::new())),
+            retention: Arc::new(RetentionTable::new(Vec::new())),
+            conflicts: Arc::new(ConflictTable::default()),
+            recent_writes: Arc::new(RecentWrites::disabled()),
+            shards: Arc::new(ShardView::disabled()),
+            index: None,
+            events: None,
+            templates: Arc::new(TemplateSet::default()),
+            namespace: Arc::new(NamespaceFilter::default()),
+            handle_traces: Arc::new(HandleTraces::disabled()),
+            keys: Arc::new(KeyRing::default()),
+            memory: MemoryBudget::unbounded(),
+        }
+    }
+    
+    /// Give `tenant` its `/tenants/<id>` subtree, laid out like `/`
+    pub fn add_tenant(&self, tenant: &str) {
+        let root = tenant_root(tenant);
+        self.inode_manager.get_or_create(Path::new(TENANTS_ROOT), true);
+        self.inode_manager.get_or_create(&root, true);
+        for (path, is_dir) in TENANT_DRIVER_ROOTS {
+            self.inode_manager.get_or_create(&root.join(path), *is_dir);
+        }
+    }
+    
+    /// Expose a generated file under `/proc/gnos`; `name` may hold
+    /// directories, which are created as needed
+    pub fn register_proc_file<F>(&self, name: &str, generator: F)
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        let path = self.procfs.register(name, generator);
+        for dir in path.ancestors().skip(1).take_while(|dir| *dir != Path::new(PROC_ROOT)) {
+            self.inode_manager.get_or_create(dir, true);
+        }
+        if self.inode_manager.find_by_path(&path).is_none() {
+            let ino = self.inode_manager.allocate();
+            self.inode_manager.create_file(ino, path);
+        }
+    }
+    
+    /// Stop serving a file registered with `register_proc_file`
+    pub fn unregister_proc_file(&self, name: &str) {
+        if let Some(path) = self.procfs.unregister(name) {
+            self.inode_manager.remove(&path);
+        }
+    }
+    
+    pub fn inode(&self, ino: u64) -> Option<GnosInode> {
+        self.inode_manager.get(ino)
+    }
+    
+    /// Inode already known for a path
+    pub fn resolve(&self, path: &Path) -> Option<u64> {
+        if !self.namespace.reaches(path) {
+            return None;
+        }
+        self.inode_manager.find_by_path(path)
+    }
+    
+    /// Parent directory of an inode; the root is its own parent
+    pub fn parent(&self, ino: u64) -> u64 {
+        self.inode_manager.get(ino)
+            .and_then(|inode| inode.path.parent().and_then(|parent| self.inode_manager.find_by_path(parent)))
+            .unwrap_or(ROOT_INODE)
+    }
+    
+    /// Child of a directory by name, materializing it from a path template if unseen
+    pub async fn lookup(&self, parent: u64, name: &OsStr) -> Option<u64> {
+        let parent = self.inode_manager.get(parent)?;
+        let path = join_name(&parent.path, name).ok()?;
+        if !self.namespace.reaches(&path) {
+            return None;
+        }
+        match self.inode_manager.find_by_path(&path) {
+            Some(ino) => Some(ino),
+            None if self.shards.contains(&path) => {
+                self.shards.populate(&self.inode_manager, &path);
+                self.inode_manager.find_by_path(&path)
+            }
+            None => self.materialize(&path).await,
+        }
+    }
+    
+    /// Attributes for an inode, consulting the attr cache and then its driver
+    pub async fn stat(&self, ino: u64) -> Result<NodeAttr> {
+        let mut inode = self.inode_manager.get(ino)
+            .ok_or_else(|| GnosError::PathNotFound(format!("inode {}", ino)))?;
+        
+        // Inodes discovered without metadata start as files; the driver has the final say
+        let metadata = self.metadata_for(&inode).await;
+        if let Some(metadata) = &metadata {
+            if metadata.is_directory && !inode.is_dir && ino >= 100 {
+                self.inode_manager.update(ino, |inode| {
+                    inode.is_dir = true;
+                    inode.permissions = 0o755;
+                });
+                inode.is_dir = true;
+                inode.permissions = 0o755;
+            }
+        }
+        
+        let mut size = match &metadata {
+            Some(metadata) if !inode.is_dir => metadata.size,
+            _ => inode.size,
+        };
+        let mut mtime = metadata.as_ref().map_or(inode.mtime, |m| m.last_modified);
+        // Until the backend shows this mount's last write, the write is what it reports
+        if !inode.is_dir {
+            if let Some(written) = self.recent_writes.reconcile(&inode.path, metadata.as_ref(), self.write_pending()) {
+                (size, mtime) = written;
+            }
+        }
+        let mut perm = self.effective_mode(&inode);
+        
+        // Only objects the driver knows about are protected, not ones still being created
+        let protection = metadata.as_ref()
+            .filter(|metadata| !metadata.is_directory)
+            .and_then(|metadata| self.retention.protection(&inode.path, metadata.last_modified));
+        let flags = protection.map_or(0, |protection| protection.flags());
+        if let Some(protection) = protection {
+            perm = protection.mask(perm);
+        }
+        
+        Ok(NodeAttr { inode, size, mtime, perm, flags })
+    }
+    
+    /// The inode's mode narrowed to what the caller's capability grants, so
+    /// `ls -l` and `access(2)`-style checks agree with what would be allowed.
+    /// The owner gets the capability's bits; group and other never get write,
+    /// since a capability is held by one principal, and private paths such
+    /// as secrets give them nothing at all
+    fn effective_mode(&self, inode: &GnosInode) -> u16 {
+        if inode.symlink.is_some() {
+            return inode.permissions;
+        }
+        let mut bits = self.capability_manager.effective_permissions(&inode.path) as u16;
+        // Listing a directory is a read; traversing it needs x as well
+        if inode.is_dir && bits & 0o4 != 0 {
+            bits |= 0o1;
+        }
+        let mut mask = (bits << 6) | ((bits & !0o2) << 3) | (bits & !0o2);
+        if self.driver_registry.get_driver(&inode.path).is_some_and(|driver| driver.private(&inode.path)) {
+            mask &= 0o700;
+        }
+        inode.permissions & mask
+    }
+    
+    /// Entries of a directory; `refresh` re-lists it from the driver first,
+    /// which frontends do once per listing pass rather than per continuation
+    pub async fn list(&self, ino: u64, refresh: bool) -> Result<Vec<GnosInode>> {
+        let dir = self.inode_manager.get(ino)
+            .ok_or_else(|| GnosError::PathNotFound(format!("inode {}", ino)))?;
+        
+        if refresh {
+            self.capability_manager.check_permission(&dir.path, Operation::List).await?;
+            self.refresh_directory(&dir).await;
+        }
+        
+        let mut children = self.inode_manager.children(&dir.path);
+        children.retain(|child| self.namespace.reaches(&child.path));
+        Ok(children)
+    }
+    
+    /// Check access and prepare a handle; generated files are rendered now
+    pub async fn open(&self, ino: u64, write: bool) -> Result<OpenFile> {
+        let inode = match self.inode_manager.get(ino) {
+            Some(inode) if !inode.is_dir => inode,
+            Some(inode) => return Err(GnosError::InvalidPath(format!("{} is a directory", inode.path.display()))),
+            None => return Err(GnosError::PathNotFound(format!("inode {}", ino))),
+        };
+        Span::current().record("path", tracing::field::display(inode.path.display()));
+        self.check_exposed(&inode.path)?;
+        
+        let operation = if write { Operation::Write } else { Operation::Read };
+        self.capability_manager.check_permission(&inode.path, operation).await?;
+        if write {
+            if let Some(protection) = self.protection_of(&inode.path).await? {
+                if protection.mode == RetentionMode::WriteOnce {
+                    return Err(protection.refusal(&inode.path, "written"));
+                }
+            }
+        }
+        
+        let proc_data = self.procfs.render(&inode.path).or_else(|| {
+            pipeline_dir::is_pipeline_path(&inode.path).then(|| self.pipelines.render(&inode.path))
+        });
+        let generated = proc_data.is_some()
+            || inode.path == Path::new(TXN_CONTROL)
+            || inode.path == Path::new(SEARCH_QUERY);
+        let cache_mode = self.cache_mode_for(&inode.path, generated);
+        let base = if write && !generated && self.conflicts.covers(&inode.path) {
+            self.metadata_for(&inode).await.map(|metadata| Version::of(&metadata))
+        } else {
+            None
+        };
+        let grant = write.then(|| self.capability_manager.local_grant(&inode.path, Operation::Write)).flatten();
+        
+        Ok(OpenFile {
+            path: inode.path,
+            data: proc_data,
+            write_buffer: None,
+            memory: self.memory.reservation(MemoryUse::WriteBuffers),
+            upload: None,
+            written_from: None,
+            base,
+            growing: false,
+            data_offset: 0,
+            skew: 0,
+            cache_mode,
+            session: None,
+            trace: None,
+            key: None,
+            grant,
+        })
+    }
+    
+    /// Create a file in a directory and open it for writing; it reaches its
+    /// driver, empty if nothing is written, when the handle is committed
+    pub async fn create(&self, parent: u64, name: &OsStr) -> Result<(u64, OpenFile)> {
+        let parent = match self.inode_manager.get(parent) {
+            Some(inode) if inode.is_dir => inode,
+            Some(inode) => return Err(GnosError::InvalidPath(format!("{} is not a directory", inode.path.display()))),
+            None => return Err(GnosError::PathNotFound(format!("inode {}", parent))),
+        };
+        let path = join_name(&parent.path, name)?;
+        Span::current().record("path", tracing::field::display(path.display()));
+        self.check_exposed(&path)?;
+        
+        if self.inode_manager.find_by_path(&path).is_some() {
+            return Err(GnosError::ResourceBusy(format!("{} exists", path.display())));
+        }
+        let backed = if pipeline_dir::is_pipeline_path(&path) {
+            pipeline_dir::is_spec_path(&path)
+        } else {
+            !self.procfs.contains(&path)
+                && !search_dir::is_search_path(&path)
+                && !self.shards.contains(&path)
+                && self.driver_registry.get_driver(&path).is_some()
+        };
+        if !backed {
+            return Err(GnosError::PermissionDenied(format!("can't create {}", path.display())));
+        }
+        self.capability_manager.check_permission(&path, Operation::Write).await?;
+        
+        let ino = self.inode_manager.get_or_create(&path, false);
+        let cache_mode = self.cache_mode_for(&path, pipeline_dir::is_pipeline_path(&path));
+        let base = self.conflicts.covers(&path).then_some(Version::Absent);
+        let grant = self.capability_manager.local_grant(&path, Operation::Write);
+        Ok((ino, OpenFile {
+            path,
+            data: None,
+            write_buffer: Some(Vec::new()),
+            memory: self.memory.reservation(MemoryUse::WriteBuffers),
+            upload: None,
+            written_from: None,
+            base,
+            growing: false,
+            data_offset: 0,
+            skew: 0,
+            cache_mode,
+            session: None,
+            trace: None,
+            key: None,
+            grant,
+        }))
+    }
+    
+    /// Create a directory through its driver, e.g. a chat session, and list
+    /// what the driver put in it
+    pub async fn mkdir(&self, parent: u64, name: &OsStr) -> Result<u64> {
+        let parent = match self.inode_manager.get(parent) {
+            Some(inode) if inode.is_dir => inode,
+            Some(inode) => return Err(GnosError::InvalidPath(format!("{} is not a directory", inode.path.display()))),
+            None => return Err(GnosError::PathNotFound(format!("inode {}", parent))),
+        };
+        let path = join_name(&parent.path, name)?;
+        Span::current().record("path", tracing::field::display(path.display()));
+        self.check_exposed(&path)?;
+        
+        if self.inode_manager.find_by_path(&path).is_some() {
+            return Err(GnosError::ResourceBusy(format!("{} exists", path.display())));
+        }
+        if self.shards.contains(&path) {
+            return Err(GnosError::PermissionDenied(format!("can't create {}", path.display())));
+        }
+        self.capability_manager.check_permission(&path, Operation::Write).await?;
+        
+        let driver = self.driver_registry.get_driver(&path)
+            .ok_or_else(|| GnosError::PermissionDenied(format!("can't create {}", path.display())))?;
+        let result = driver.create_dir(&path)
+            .instrument(driver_span("create_dir", driver.as_ref(), &path))
+            .await;
+        self.connectivity.record(driver.name(), &result);
+        result?;
+        
+        let ino = self.inode_manager.get_or_create(&path, true);
+        if let Some(dir) = self.inode_manager.get(ino) {
+            self.refresh_directory(&dir).await;
+        }
+        self.publish(EventKind::Created, &path);
+        info!("📁 Created {}", path.display());
+        Ok(ino)
+    }
+    
+    /// Delete a child of a directory through its driver; inside an open
+    /// transaction the delete waits for `commit`
+    pub async fn remove(&self, parent: u64, name: &OsStr, session: Option<u32>) -> Result<()> {
+        let parent = self.inode_manager.get(parent)
+            .ok_or_else(|| GnosError::PathNotFound(format!("inode {}", parent)))?;
+        let path = join_name(&parent.path, name)?;
+        Span::current().record("path", tracing::field::display(path.display()));
+        self.check_exposed(&path)?;
+        
+        if self.procfs.contains(&path)
+            || search_dir::is_search_path(&path)
+            || pipeline_dir::is_pipeline_path(&path)
+            || self.shards.contains(&path)
+            || path == Path::new(TXN_CONTROL)
+        {
+            return Err(GnosError::PermissionDenied(format!("{} is generated", path.display())));
+        }
+        self.capability_manager.check_permission(&path, Operation::Write).await?;
+        self.check_deletable(&path).await?;
+        
+        if let Some(session) = session.filter(|&session| self.transactions.is_open(session)) {
+            self.transactions.stage(session, BatchOp::Delete { path: path.clone() });
+            debug!("Staged delete of {} in transaction", path.display());
+            return Ok(());
+        }
+        
+        // Writes still journaled for the path would land after a direct
+        // delete and bring the file back, so the delete queues behind them
+        let journaled = self.write_back.as_ref()
+            .filter(|queue| queue.sync_state(&path) != SyncState::Clean);
+        if let Some(queue) = journaled {
+            queue.enqueue_delete(&path).await?;
+        } else {
+            let driver = self.driver_registry.get_driver(&path)
+                .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+            let result = driver.delete(&path)
+                .instrument(driver_span("delete", driver.as_ref(), &path))
+                .await;
+            self.connectivity.record(driver.name(), &result);
+            result?;
+        }
+        
+        if let Some(cache) = &self.disk_cache {
+            cache.invalidate(&path).await;
+        }
+        if let Some(ino) = self.inode_manager.find_by_path(&path) {
+            self.attr_cache.invalidate(ino);
+        }
+        self.inode_manager.remove(&path);
+        self.quotas.forget(&path);
+        self.recent_writes.forget(&path);
+        if let Some(index) = &self.index {
+            index.notify(&path);
+        }
+        self.publish(EventKind::Deleted, &path);
+        info!("🗑️  Removed {}", path.display());
+        Ok(())
+    }
+    
+    /// Move a file to a new name as a copy followed by a delete, each
+    /// checked as its own write; directories fail with EXDEV, which `mv`
+    /// answers by copying their contents itself
+    pub async fn rename(&self, parent: u64, name: &OsStr, new_parent: u64, new_name: &OsStr) -> Result<()> {
+        let ino = self.lookup(parent, name).await
+            .ok_or_else(|| GnosError::PathNotFound(name.to_string_lossy().into_owned()))?;
+        let inode = self.inode_manager.get(ino)
+            .ok_or_else(|| GnosError::PathNotFound(format!("inode {}", ino)))?;
+        if inode.is_dir {
+            return Err(GnosError::Io(std::io::Error::from_raw_os_error(libc::EXDEV)));
+        }
+        
+        let mut source = self.open(ino, false).await?;
+        let mut target = match self.lookup(new_parent, new_name).await {
+            Some(existing) if existing == ino => return Ok(()),
+            Some(existing) => {
+                let mut target = self.open(existing, true).await?;
+                target.truncate(0)?;
+                target
+            }
+            None => self.create(new_parent, new_name).await?.1,
+        };
+        self.check_writable(&target)?;
+        
+        let mut offset = 0;
+        loop {
+            let chunk = self.read(&mut source, offset, RENAME_CHUNK).await?;
+            if chunk.is_empty() {
+                break;
+            }
+            self.check_quota(&target.path, offset + chunk.len() as u64)?;
+            self.write(&mut target, offset, &chunk).await?;
+            offset += chunk.len() as u64;
+        }
+        self.commit(&mut target).await?;
+        self.remove(parent, name, None).await?;
+        info!("🚚 Renamed {} to {}", inode.path.display(), target.path.display());
+        Ok(())
+    }
+    
+    /// Read a window of an open file
+    pub async fn read(&self, file: &mut OpenFile, offset: u64, size: u32) -> Result<Bytes> {
+        // A call written through the handle goes out before its reply is read
+        if file.write_buffer.is_some() && self.driver_registry.get_driver(&file.path).is_some_and(|driver| driver.invokes(&file.path)) {
+            self.commit(file).await?;
+        }
+        
+        // The control file answers with the reader's transaction status
+        if file.data.is_none() && file.path == Path::new(TXN_CONTROL) {
+            file.data = Some(Bytes::from(self.transactions.status(file.session)));
+        }
+        if file.data.is_none() && file.path == Path::new(SEARCH_QUERY) {
+            file.data = Some(Bytes::from(self.searches.status_report()));
+        }
+        
+        if file.data.is_none() {
+            file.data = self.recent_writes.get(&file.path, self.write_pending())
+                .map(|data| file.plaintext(data))
+                .transpose()?;
+        }
+        
+        // Fetch once per handle; later reads are slices of the same buffer
+        if file.data.is_none() {
+            let driver = self.driver_registry.get_driver(&file.path)
+                .ok_or_else(|| GnosError::PathNotFound(file.path.display().to_string()))?;
+            
+            if driver.streams(&file.path) {
+                self.read_growing(driver.as_ref(), file, offset).await?;
+            } else {
+                // Cacheable objects are read chunk by chunk so only touched ranges are fetched
+                // Chunks already on disk are served even while the backend is offline
+                // Sealed objects only open whole, so keyed handles skip the chunks
+                // Private contents stay off the disk
+                if let Some(cache) = self.disk_cache.as_ref()
+                    .filter(|c| c.handles(&file.path) && file.key.is_none() && !driver.private(&file.path)) {
+                    // Chunks are only served for the version of the object last seen
+                    let inode = self.inode_manager.find_by_path(&file.path).and_then(|ino| self.inode_manager.get(ino));
+                    let metadata = match &inode {
+                        Some(inode) => self.metadata_for(inode).await,
+                        None => None,
+                    };
+                    // An object its backend reports no version of would look changed
+                    // on every stat, so it is read past the cache
+                    let version = match &metadata {
+                        Some(metadata) => object_version(metadata).map(Some),
+                        None => Some(None),
+                    };
+                    if let Some(version) = version {
+                        let started = Instant::now();
+                        let data = cache.read_through(driver.as_ref(), &file.path, version.as_deref(), offset, size).await;
+                        self.connectivity.record(driver.name(), &data);
+                        let bytes = data.as_ref().map_or(0, |data| data.len() as u64);
+                        file.trace_driver("cache.read", driver.name(), Some(offset), bytes, started, &data);
+                        return data;
+                    }
+                }
+                
+                let started = Instant::now();
+                let data = driver.read(&file.path)
+                    .instrument(driver_span("read", driver.as_ref(), &file.path))
+                    .await;
+                self.connectivity.record(driver.name(), &data);
+                let bytes = data.as_ref().map_or(0, |data| data.len() as u64);
+                file.trace_driver("driver.read", driver.name(), None, bytes, started, &data);
+                file.data = Some(file.plaintext(data?)?);
+            }
+        }
+        
+        // A reader that caught up with a stream waits for its next chunk rather
+        // than seeing end of file, so `cat` and `tail -f` print tokens as they come
+        while file.growing && offset + file.skew >= file.data_offset + file.data.as_ref().map_or(0, |data| data.len() as u64) {
+            let driver = self.driver_registry.get_driver(&file.path)
+                .ok_or_else(|| GnosError::PathNotFound(file.path.display().to_string()))?;
+            self.read_growing(driver.as_ref(), file, offset).await?;
+        }
+        
+        // Bytes the stream dropped before this reader got to them are skipped
+        let mut at = offset + file.skew;
+        if at < file.data_offset {
+            file.skew += file.data_offset - at;
+            at = file.data_offset;
+        }
+        
+        Ok(reply_window(file.data.as_ref().unwrap_or(&Bytes::new()), at - file.data_offset, size))
+    }
+    
+    /// Refresh a streaming handle once its resource extends past the reader's `offset`
+    async fn read_growing(&self, driver: &dyn GnosDriver, file: &mut OpenFile, offset: u64) -> Result<()> {
+        let started = Instant::now();
+        let result = driver.read_growing(&file.path, offset + file.skew)
+            .instrument(driver_span("read", driver, &file.path))
+            .await;
+        self.connectivity.record(driver.name(), &result);
+        let bytes = result.as_ref().map_or(0, |growth| growth.data.len() as u64);
+        file.trace_driver("driver.read_growing", driver.name(), Some(offset + file.skew), bytes, started, &result);
+        let growth = result?;
+        file.data = Some(growth.data);
+        file.data_offset = growth.offset;
+        file.growing = growth.growing;
+        Ok(())
+    }
+    
+    /// Buffer a write through a handle; once a sequential write to a driver
+    /// with multipart uploads outgrows the driver's threshold, its full parts
+    /// are sent as they fill up instead of waiting for the commit. A write
+    /// the memory budget can't hold fails with ENOSPC at once, as a FUSE
+    /// thread mustn't sit waiting for uploads to drain
+    pub async fn write(&self, file: &mut OpenFile, offset: u64, data: &[u8]) -> Result<()> {
+        if let Some(token) = &file.grant {
+            self.capability_manager.fence(token, &file.path, Operation::Write)?;
+        }
+        let end = file.buffer_offset(offset)? as u64 + data.len() as u64;
+        file.memory.try_grow_to(end)?;
+        file.write_at(offset, data)?;
+        
+        let buffered = file.write_buffer.as_ref().map_or(0, |buffer| buffer.len() as u64);
+        let policy = match &file.upload {
+            Some(upload) => upload.policy,
+            None => {
+                let Some(driver) = self.part_driver(file) else {
+                    return Ok(());
+                };
+                let policy = driver.part_policy(&file.path);
+                if buffered < policy.threshold.max(policy.part_size) {
+                    return Ok(());
+                }
+                let result = driver.begin_parts(&file.path)
+                    .instrument(driver_span("begin_parts", driver.as_ref(), &file.path))
+                    .await;
+                self.connectivity.record(driver.name(), &result);
+                info!("📤 Uploading {} in parts of {} bytes", file.path.display(), policy.part_size);
+                file.upload = Some(PartUpload { id: result?, policy, sent: 0, parts: 0 });
+                policy
+            }
+        };
+        
+        // Parts go out a batch at a time, so a writer holds at most about a batch
+        if buffered >= policy.part_size * policy.concurrency as u64 {
+            self.send_parts(file, false).await?;
+        }
+        Ok(())
+    }
+    
+    /// The driver to send `file` to in parts, if its writes can be: parts
+    /// land straight at the backend, so writes that must first pass through
+    /// the write-back journal, a transaction, a retention rule, compression
+    /// or a conditional check are sent whole on commit
+    fn part_driver(&self, file: &OpenFile) -> Option<Arc<dyn GnosDriver>> {
+        let path = &file.path;
+        let plain = self.write_back.is_none()
+            && file.base.is_none()
+            && file.key.is_none()
+            && !file.session.is_some_and(|session| self.transactions.is_open(session))
+            && !self.retention.covers(path)
+            && !self.compression.compress_transfer(path)
+            && !self.procfs.contains(path)
+            && !search_dir::is_search_path(path)
+            && !pipeline_dir::is_pipeline_path(path)
+            && path != Path::new(TXN_CONTROL);
+        if !plain {
+            return None;
+        }
+        self.driver_registry.get_driver(path).filter(|driver| driver.supports_parts(path))
+    }
+    
+    /// Send the full parts a handle holds, and with `last` whatever remains
+    /// as the final part; parts that would take the object past a quota
+    /// are refused before any of them is sent
+    async fn send_parts(&self, file: &mut OpenFile, last: bool) -> Result<()> {
+        let Some(upload) = file.upload.as_mut() else {
+            return Ok(());
+        };
+        let driver = self.driver_registry.get_driver(&file.path)
+            .ok_or_else(|| GnosError::PathNotFound(file.path.display().to_string()))?;
+        let part_size = upload.policy.part_size as usize;
+        
+        let buffer = file.write_buffer.get_or_insert_with(Vec::new);
+        let len = if last { buffer.len() } else { buffer.len() - buffer.len() % part_size };
+        if len > 0 || last {
+            self.quotas.check(&[(file.path.clone(), Some(upload.sent + len as u64))])?;
+        }
+        let tail = buffer.split_off(len);
+        let data = Bytes::from(std::mem::replace(buffer, tail));
+        file.memory.shrink_to(buffer.len() as u64);
+        let mut parts: Vec<(u64, Bytes)> = (0..len).step_by(part_size)
+            .enumerate()
+            .map(|(n, start)| (upload.parts + n as u64, data.slice(start..(start + part_size).min(len))))
+            .collect();
+        // An object needs at least one part, even an empty one
+        if last && parts.is_empty() && upload.parts == 0 {
+            parts.push((0, Bytes::new()));
+        }
+        if parts.is_empty() {
+            return Ok(());
+        }
+        
+        let count = parts.len() as u64;
+        let sent = upload.sent;
+        let started = Instant::now();
+        let (path, id) = (&file.path, &upload.id);
+        let result = stream::iter(parts)
+            .map(|(part, data)| {
+                let driver = driver.clone();
+                async move {
+                    driver.write_part(path, id, part, &data)
+                        .instrument(driver_span("write_part", driver.as_ref(), path))
+                        .await
+                }
+            })
+            .buffer_unordered(upload.policy.concurrency.max(1))
+            .try_collect::<()>()
+            .await;
+        self.connectivity.record(driver.name(), &result);
+        file.trace_driver("driver.write_part", driver.name(), Some(sent), len as u64, started, &result);
+        result?;
+        
+        let Some(upload) = file.upload.as_mut() else {
+            return Ok(());
+        };
+        upload.parts += count;
+        upload.sent += len as u64;
+        debug!("Sent {} parts of {} ({} bytes so far)", upload.parts, file.path.display(), upload.sent);
+        Ok(())
+    }
+    
+    /// Drop a handle's pending write, discarding any parts already sent
+    async fn abort_write(&self, file: &mut OpenFile) {
+        file.write_buffer = None;
+        file.memory.shrink_to(0);
+        file.written_from = None;
+        let Some(upload) = file.upload.take() else {
+            return;
+        };
+        let Some(driver) = self.driver_registry.get_driver(&file.path) else {
+            return;
+        };
+        let started = Instant::now();
+        let result = driver.abort_parts(&file.path, &upload.id)
+            .instrument(driver_span("abort_parts", driver.as_ref(), &file.path))
+            .await;
+        self.connectivity.record(driver.name(), &result);
+        file.trace_driver("driver.abort_parts", driver.name(), None, upload.sent, started, &result);
+        match result {
+            Ok(()) => info!("🗑️  Discarded {} parts of {}", upload.parts, file.path.display()),
+            Err(e) => warn!("❌ Discarding the parts of {} failed: {}", file.path.display(), e),
+        }
+    }
+    
+    /// Send the rest of a write that went out in parts and assemble them
+    async fn commit_parts(&self, file: &mut OpenFile) -> Result<()> {
+        let path = file.path.clone();
+        let size = file.buffered_len();
+        let change = [(path.clone(), Some(size))];
+        
+        let kind = self.write_kind(&path).await;
+        self.send_parts(file, true).await?;
+        file.write_buffer = None;
+        file.memory.shrink_to(0);
+        file.written_from = None;
+        let Some(upload) = file.upload.take() else {
+            return Ok(());
+        };
+        
+        let driver = self.driver_registry.get_driver(&path)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        let started = Instant::now();
+        let result = driver.complete_parts(&path, &upload.id, upload.parts)
+            .instrument(driver_span("complete_parts", driver.as_ref(), &path))
+            .await;
+        self.connectivity.record(driver.name(), &result);
+        file.trace_driver("driver.complete_parts", driver.name(), None, size, started, &result);
+        
+        if let Some(cache) = &self.disk_cache {
+            cache.invalidate(&path).await;
+        }
+        if let Some(ino) = self.inode_manager.find_by_path(&path) {
+            self.attr_cache.invalidate(ino);
+        }
+        match result {
+            Ok(()) => {
+                info!("📤 Uploaded {} in {} parts ({} bytes)", path.display(), upload.parts, size);
+                self.quotas.record(&change);
+                self.conflicts.clear(&path);
+                self.recent_writes.forget(&path);
+                if let Some(index) = &self.index {
+                    index.notify(&path);
+                }
+                self.publish(kind, &path);
+                Ok(())
+            }
+            Err(e) => {
+                warn!("❌ Assembling {} from {} parts failed: {}", path.display(), upload.parts, e);
+                Err(e)
+            }
+        }
+    }
+    
+    /// Reject writes the handle can never commit
+    pub fn check_writable(&self, file: &OpenFile) -> Result<()> {
+        if self.procfs.contains(&file.path) {
+            return Err(GnosError::PermissionDenied(format!("{} is generated", file.path.display())));
+        }
+        if pipeline_dir::is_pipeline_path(&file.path) && !pipeline_dir::is_spec_path(&file.path) {
+            return Err(GnosError::PermissionDenied(format!("{} is a pipeline status file", file.path.display())));
+        }
+        Ok(())
+    }
+    
+    /// Refuse growing `path` to `size` bytes past a quota, before the write is committed
+    pub fn check_quota(&self, path: &Path, size: u64) -> Result<()> {
+        self.quotas.check(&[(path.to_path_buf(), Some(size))])
+    }
+    
+    /// Capacity reported for the filesystem holding `ino`: its innermost quota
+    pub fn statfs(&self, ino: u64) -> QuotaStats {
+        self.inode_manager.get(ino)
+            .and_then(|inode| self.quotas.stats(&inode.path))
+            .unwrap_or(QuotaStats { block_size: 4096, ..QuotaStats::default() })
+    }
+    
+    /// Push a handle's buffered writes to its driver, or to the write-back queue
+    pub async fn commit(&self, file: &mut OpenFile) -> Result<()> {
+        // What was written before a revocation is finished or dropped, as
+        // security.revoked_writes says
+        if let Some(token) = file.grant.clone().filter(|_| file.has_pending_write()) {
+            if let Err(e) = self.capability_manager.fence(&token, &file.path, Operation::Write) {
+                self.capability_manager.audit_revoked_write(&token, &file.path, file.buffered_len());
+                if self.capability_manager.revoked_writes() == RevokedWrites::Abort {
+                    self.abort_write(file).await;
+                    return Err(e);
+                }
+            }
+        }
+        if file.upload.is_some() {
+            file.data = None;
+            file.growing = false;
+            file.data_offset = 0;
+            file.skew = 0;
+            return self.commit_parts(file).await;
+        }
+        let Some(buffer) = file.write_buffer.take() else {
+            return Ok(());
+        };
+        // The buffer stays charged to the budget until the write leaves it
+        let held = file.memory.take();
+        let written_from = file.written_from.take();
+        
+        // Reads after a write go back to the driver, e.g. to pick up an AI response
+        file.data = None;
+        file.growing = false;
+        file.data_offset = 0;
+        file.skew = 0;
+        let path = file.path.clone();
+        let data = Bytes::from(buffer);
+        
+        if path == Path::new(TXN_CONTROL) {
+            return self.txn_control(file.session, &data).await;
+        }
+        if path == Path::new(SEARCH_QUERY) {
+            return self.start_searches(&data);
+        }
+        if pipeline_dir::is_spec_path(&path) {
+            return self.start_pipeline(&path, &data);
+        }
+        
+        // The reply is what the handle reads next, so a call can't wait in
+        // the journal or a transaction
+        if let Some(driver) = self.driver_registry.get_driver(&path).filter(|driver| driver.invokes(&path)) {
+            let started = Instant::now();
+            let bytes = data.len() as u64;
+            let reply = driver.invoke(&path, &data)
+                .instrument(driver_span("invoke", driver.as_ref(), &path))
+                .await;
+            self.connectivity.record(driver.name(), &reply);
+            file.trace_driver("driver.invoke", driver.name(), None, bytes, started, &reply);
+            file.data = Some(reply.map_err(|e| {
+                warn!("❌ Invoking {} failed: {}", path.display(), e);
+                e
+            })?);
+            // The reply reads from where the call ended, as a shell reading
+            // back through a `<>` descriptor does, or from the start
+            file.data_offset = bytes;
+            return Ok(());
+        }
+        
+        let data = self.retained_write(&path, written_from, data).await?;
+        // Nothing past here sees a keyed file unsealed, the journal included
+        let data = match &file.key {
+            Some(key) => key.seal(&path, &data)?,
+            None => data,
+        };
+        
+        // Inside an open transaction the write waits for `commit`
+        if let Some(session) = file.session.filter(|&session| self.transactions.is_open(session)) {
+            self.transactions.stage(session, BatchOp::Write { path: path.clone(), data });
+            debug!("Staged write to {} in transaction", path.display());
+            return Ok(());
+        }
+        
+        let change = [(path.clone(), Some(data.len() as u64))];
+        self.quotas.check(&change)?;
+        
+        let precondition = match file.base.take() {
+            Some(base) => self.check_unchanged(&path, &base).await?,
+            None => None,
+        };
+        
+        let kind = self.write_kind(&path).await;
+        let started = Instant::now();
+        let bytes = data.len() as u64;
+        let written = data.clone();
+        // Private contents go straight to the backend, never into the on-disk journal
+        let journal = self.write_back.as_ref()
+            .filter(|_| !self.driver_registry.get_driver(&path).is_some_and(|driver| driver.private(&path)));
+        let result = match journal {
+            Some(queue) => {
+                // The queue charges the payload itself
+                drop(held);
+                let base = self.remote_version(&path);
+                let result = queue.enqueue(&path, data, base).await;
+                file.trace_driver("writeback.enqueue", "journal", None, bytes, started, &result);
+                result
+            }
+            None => match self.driver_registry.get_driver(&path) {
+                Some(driver) => {
+                    let conditional = precondition.is_some();
+                    let result = self.compression.write_through_if(driver.as_ref(), &path, &data, precondition).await;
+                    file.trace_driver("driver.write", driver.name(), None, bytes, started, &result);
+                    if let (true, Err(GnosError::ResourceBusy(reason))) = (conditional, &result) {
+                        self.conflicts.record(&path, format!("backend refused the write: {}", reason));
+                    }
+                    // Later flushes through the handle build on this write
+                    if result.is_ok() && self.conflicts.covers(&path) {
+                        file.base = driver.metadata(&path).await.ok().map(|metadata| Version::of(&metadata));
+                    }
+                    result
+                }
+                None => Err(GnosError::PathNotFound(path.display().to_string())),
+            },
+        };
+        
+        if let Some(cache) = &self.disk_cache {
+            cache.invalidate(&path).await;
+        }
+        if let Some(ino) = self.inode_manager.find_by_path(&path) {
+            self.attr_cache.invalidate(ino);
+        }
+        if let (Ok(()), Some(index)) = (&result, &self.index) {
+            index.notify(&path);
+        }
+        if result.is_ok() {
+            self.quotas.record(&change);
+            self.conflicts.clear(&path);
+            self.recent_writes.record(&path, written, self.write_pending());
+            self.publish(kind, &path);
+        }
+        
+        result.map_err(|e| {
+            warn!("❌ Write to {} failed: {}", path.display(), e);
+            e
+        })
+    }
+    
+    /// Wait until a path's writes have reached the backend, not just the journal
+    pub async fn sync(&self, path: &Path) -> Result<()> {
+        match &self.write_back {
+            Some(queue) => queue.flush(path).instrument(info_span!("writeback.flush")).await,
+            None => Ok(()),
+        }
+    }
+    
+    /// Upload everything still queued, e.g. before unmounting
+    pub async fn sync_all(&self) -> Result<()> {
+        match &self.write_back {
+            Some(queue) => queue.flush_all().await,
+            None => Ok(()),
+        }
+    }
+    
+    /// Take `uid`'s data key for files at or below `path`; only someone who
+    /// may read the path can key it
+    pub async fn set_key(&self, uid: u32, path: &Path, value: &[u8]) -> Result<()> {
+        self.check_exposed(path)?;
+        self.capability_manager.check_permission(path, Operation::Read).await?;
+        let fingerprint = self.keys.set(uid, path, value)?;
+        info!("🔐 Data key {} set on {} for uid {}", fingerprint, path.display(), uid);
+        Ok(())
+    }
+    
+    /// Forget the data key `uid` set on `path`
+    pub fn forget_key(&self, uid: u32, path: &Path) -> Result<()> {
+        if !self.keys.forget(uid, path) {
+            return Err(GnosError::PathNotFound(format!("no data key on {}", path.display())));
+        }
+        info!("🔐 Data key on {} forgotten for uid {}", path.display(), uid);
+        Ok(())
+    }
+    
+    pub fn xattr(&self, path: &Path, name: &OsStr) -> Option<String> {
+        match (name.to_str(), &self.write_back) {
+            (Some(SYNC_XATTR), Some(queue)) => Some(queue.sync_state(path).to_string()),
+            (Some(TRACE_XATTR), _) => self.handle_traces.report_for(path),
+            (Some(MIME_XATTR), _) => self.mime_type(path),
+            (Some(RETENTION_XATTR), _) => self.cached_protection(path).map(|protection| protection.describe()),
+            (Some(CONFLICT_XATTR), _) => self.conflicts.describe(path),
+            _ => None,
+        }
+    }
+    
+    /// Protection on a file as of its last known metadata, for reporting
+    fn cached_protection(&self, path: &Path) -> Option<Protection> {
+        let ino = self.inode_manager.find_by_path(path)?;
+        let metadata = self.attr_cache.get_stale(ino).filter(|metadata| !metadata.is_directory)?;
+        self.retention.protection(path, metadata.last_modified)
+    }
+    
+    /// Type the backend last reported for a file, else its extension's
+    fn mime_type(&self, path: &Path) -> Option<String> {
+        let inode = self.inode_manager.get(self.inode_manager.find_by_path(path)?)?;
+        if inode.is_dir {
+            return None;
+        }
+        self.attr_cache.get_stale(inode.ino)
+            .and_then(|metadata| metadata.mime_type)
+            .or_else(|| mime::from_extension(path).map(str::to_string))
+    }
+    
+    pub fn xattr_names(&self) -> Vec<&'static str> {
+        let mut names = vec![MIME_XATTR];
+        if self.write_back.is_some() {
+            names.push(SYNC_XATTR);
+        }
+        if self.handle_traces.enabled() {
+            names.push(TRACE_XATTR);
+        }
+        if !self.retention.is_empty() {
+            names.push(RETENTION_XATTR);
+        }
+        if self.conflicts.enabled() {
+            names.push(CONFLICT_XATTR);
+        }
+        names
+    }
+    
+    /// Run commands written to `/.gnos/txn` on behalf of `session`
+    async fn txn_control(&self, session: Option<u32>, commands: &[u8]) -> Result<()> {
+        let session = session
+            .ok_or_else(|| GnosError::InvalidPath(format!("{} needs a login session", TXN_CONTROL)))?;
+        let commands = std::str::from_utf8(commands)
+            .map_err(|_| GnosError::InvalidPath(format!("{} commands must be UTF-8", TXN_CONTROL)))?;
+        
+        for line in commands.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            match command {
+                "begin" => {
+                    if !self.transactions.begin(session) {
+                        return Err(GnosError::ResourceBusy("transaction already open".to_string()));
+                    }
+                    info!("🧾 Transaction opened for session {}", session);
+                }
+                "delete" => {
+                    let path = PathBuf::from(argument.trim());
+                    self.check_exposed(&path)?;
+                    self.capability_manager.check_permission(&path, Operation::Write).await?;
+                    self.check_deletable(&path).await?;
+                    if !self.transactions.stage(session, BatchOp::Delete { path }) {
+                        return Err(GnosError::InvalidPath("no open transaction".to_string()));
+                    }
+                }
+                "abort" => {
+                    if self.transactions.take(session).is_some() {
+                        self.transactions.finish(session, "aborted".to_string());
+                        info!("🧾 Transaction aborted for session {}", session);
+                    }
+                }
+                "commit" => {
+                    let ops = self.transactions.take(session)
+                        .ok_or_else(|| GnosError::InvalidPath("no open transaction".to_string()))?;
+                    let sizes: Vec<(PathBuf, Option<u64>)> = ops.iter()
+                        .map(|op| match op {
+                            BatchOp::Write { path, data } => (path.clone(), Some(data.len() as u64)),
+                            BatchOp::Delete { path } => (path.clone(), None),
+                        })
+                        .collect();
+                    if let Err(e) = self.quotas.check(&sizes) {
+                        self.transactions.finish(session, format!("failed: {}", e));
+                        return Err(e);
+                    }
+                    
+                    let written: Vec<(PathBuf, Option<Bytes>)> = ops.iter()
+                        .map(|op| match op {
+                            BatchOp::Write { path, data } => (path.clone(), Some(data.clone())),
+                            BatchOp::Delete { path } => (path.clone(), None),
+                        })
+                        .collect();
+                    let mut changes = Vec::with_capacity(ops.len());
+                    for op in &ops {
+                        let kind = match op {
+                            BatchOp::Write { path, .. } => self.write_kind(path).await,
+                            BatchOp::Delete { .. } => EventKind::Deleted,
+                        };
+                        changes.push((kind, op.path().to_path_buf()));
+                    }
+                    
+                    let result = txn::apply(&self.driver_registry, &self.compression, ops).await;
+                    if result.is_ok() {
+                        self.quotas.record(&sizes);
+                        for (path, data) in written {
+                            match data {
+                                Some(data) => self.recent_writes.record(&path, data, self.write_pending()),
+                                None => self.recent_writes.forget(&path),
+                            }
+                        }
+                    }
+                    for (kind, path) in &changes {
+                        if result.is_ok() {
+                            self.publish(*kind, path);
+                        }
+                        if let Some(cache) = &self.disk_cache {
+                            cache.invalidate(path).await;
+                        }
+                        if let Some(ino) = self.inode_manager.find_by_path(path) {
+                            self.attr_cache.invalidate(ino);
+                        }
+                        if let Some(index) = &self.index {
+                            index.notify(path);
+                        }
+                    }
+                    
+                    let outcome = match &result {
+                        Ok(applied) => format!("committed: {} operations", applied),
+                        Err(e) => format!("failed: {}", e),
+                    };
+                    self.transactions.finish(session, outcome);
+                    result?;
+                }
+                other => {
+                    return Err(GnosError::InvalidPath(format!("unknown transaction command: {}", other)));
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Start a search per query line written to `/proc/search/query`; each
+    /// fills its own result directory in the background
+    fn start_searches(&self, queries: &[u8]) -> Result<()> {
+        let queries = std::str::from_utf8(queries)
+            .map_err(|_| GnosError::InvalidPath(format!("{} queries must be UTF-8", SEARCH_QUERY)))?;
+        
+        // Every line is parsed before any search starts, so a typo starts nothing
+        let parsed = queries.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| line.parse::<SearchQuery>().map(|query| (line, query)))
+            .collect::<Result<Vec<_>>>()?;
+        
+        for (text, query) in parsed {
+            let (id, dir) = self.searches.start(text);
+            self.inode_manager.get_or_create(&dir, true);
+            info!("🔎 Search {} started: {}", id, text);
+            
+            let engine = self.search_engine.clone();
+            let searches = self.searches.clone();
+            let inode_manager = self.inode_manager.clone();
+            let namespace = self.namespace.clone();
+            tokio::spawn(async move {
+                let on_match = |path: &Path| {
+                    if !namespace.exposes(path) {
+                        return;
+                    }
+                    let (name, target) = search_dir::result_entry(path);
+                    inode_manager.create_symlink(&dir.join(name), target);
+                    searches.matched(id);
+                };
+                let state = match engine.run(&query, on_match).await {
+                    Ok(stats) if stats.truncated => "done (truncated)".to_string(),
+                    Ok(_) => "done".to_string(),
+                    Err(e) => format!("failed: {}", e),
+                };
+                info!("🔎 Search {} {}", id, state);
+                searches.finish(id, state);
+            });
+        }
+        
+        Ok(())
+    }
+    
+    /// Run the pipeline in a spec written to `/proc/pipelines`, reporting
+    /// progress through a status directory named after it
+    fn start_pipeline(&self, path: &Path, text: &[u8]) -> Result<()> {
+        let text = std::str::from_utf8(text)
+            .map_err(|_| GnosError::InvalidPath(format!("{} must be UTF-8", path.display())))?;
+        let mut spec = pipeline_dir::parse_spec(path, text)?;
+        for stage in &spec.stages {
+            for stage_path in [&stage.input, &stage.target, &stage.output].into_iter().flatten() {
+                self.check_exposed(stage_path)?;
+            }
+        }
+        let (name, progress, dir) = self.pipelines.start(path, text, &spec)?;
+        spec.name.get_or_insert_with(|| name.clone());
+        
+        self.inode_manager.get_or_create(&dir, true);
+        self.inode_manager.get_or_create(&dir.join("status"), false);
+        for stage in &spec.stages {
+            self.inode_manager.get_or_create(&dir.join(&stage.name), false);
+        }
+        info!("🔗 Pipeline {} started with {} stages", name, spec.stages.len());
+        
+        // Stages act with the daemon's capability, like writes through the mount
+        let mut client = GnosClient::with_components(self.driver_registry.clone(), self.capability_manager.clone())
+            .with_compression(self.compression.clone());
+        if let Some(events) = &self.events {
+            client = client.with_events(events.clone());
+        }
+        tokio::spawn(async move {
+            let _ = pipeline::run(&spec, &client, &progress).await;
+        });
+        Ok(())
+    }
+    
+    /// Refuse touching a path this mount doesn't expose; hidden paths don't
+    /// exist and the directories leading to exposed ones can't be changed
+    fn check_exposed(&self, path: &Path) -> Result<()> {
+        if self.namespace.exposes(path) {
+            Ok(())
+        } else if self.namespace.reaches(path) {
+            Err(GnosError::PermissionDenied(format!("{} is outside this mount's namespace", path.display())))
+        } else {
+            Err(GnosError::PathNotFound(path.display().to_string()))
+        }
+    }
+    
+    /// Inode for a path that matches a template, if its driver accepts the bound values
+    async fn materialize(&self, path: &Path) -> Option<u64> {
+        if self.templates.is_empty() {
+            return None;
+        }
+        let binding = self.templates.bind(path)?;
+        let driver = self.driver_registry.get_driver(path)?;
+        
+        if let Err(e) = driver.materialize(path, &binding.params).await {
+            debug!("Not materializing {}: {}", path.display(), e);
+            return None;
+        }
+        debug!("Materialized {} with {:?}", path.display(), binding.params);
+        Some(self.inode_manager.get_or_create(path, binding.is_dir))
+    }
+    
+    /// Protection on the object at `path` right now, asked of its driver
+    /// rather than the attr cache; `None` if no rule covers it, it doesn't
+    /// exist yet or its window has passed. A driver that can't say whether
+    /// the object exists fails the check, so nothing slips through an outage
+    async fn protection_of(&self, path: &Path) -> Result<Option<Protection>> {
+        if !self.retention.covers(path) {
+            return Ok(None);
+        }
+        let Some(driver) = self.driver_registry.get_driver(path) else {
+            return Ok(None);
+        };
+        let result = driver.metadata(path)
+            .instrument(driver_span("metadata", driver.as_ref(), path))
+            .await;
+        self.connectivity.record(driver.name(), &result);
+        match result {
+            Ok(metadata) if metadata.is_directory => Ok(None),
+            Ok(metadata) => Ok(self.retention.protection(path, metadata.last_modified)),
+            Err(GnosError::PathNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    
+    /// Hold a commit to what the retention rules allow: nothing over a
+    /// write-once object, and only bytes past the end of an append-only one,
+    /// which are added to what it already holds. A handle opened to append
+    /// buffers only the new bytes, so the rest is filled in from the driver
+    async fn retained_write(&self, path: &Path, written_from: Option<u64>, data: Bytes) -> Result<Bytes> {
+        let Some(protection) = self.protection_of(path).await? else {
+            return Ok(data);
+        };
+        if protection.mode == RetentionMode::WriteOnce {
+            return Err(protection.refusal(path, "rewritten"));
+        }
+        
+        let driver = self.driver_registry.get_driver(path)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        let result = driver.read(path)
+            .instrument(driver_span("read", driver.as_ref(), path))
+            .await;
+        self.connectivity.record(driver.name(), &result);
+        let existing = result?;
+        let held = existing.len();
+        
+        if written_from.is_none_or(|from| from >= held as u64) && data.len() >= held {
+            let mut appended = existing.to_vec();
+            appended.extend_from_slice(&data[held..]);
+            debug!("Appending {} bytes to {}", appended.len() - held, path.display());
+            return Ok(Bytes::from(appended));
+        }
+        if data.starts_with(&existing) {
+            return Ok(data);
+        }
+        Err(protection.refusal(path, "changed except by appending"))
+    }
+    
+    /// Fail a write based on `base` if the object has moved on since, or
+    /// hand back the precondition for a backend that checks it itself
+    async fn check_unchanged(&self, path: &Path, base: &Version) -> Result<Option<Precondition>> {
+        let driver = self.driver_registry.get_driver(path)
+            .ok_or_else(|| GnosError::PathNotFound(path.display().to_string()))?;
+        // Queued writes land later, so only a direct write can carry the check
+        if self.write_back.is_none() && driver.conditional_writes(path) {
+            if let Some(precondition) = base.precondition() {
+                return Ok(Some(precondition));
+            }
+        }
+        
+        let result = driver.metadata(path)
+            .instrument(driver_span("metadata", driver.as_ref(), path))
+            .await;
+        self.connectivity.record(driver.name(), &result);
+        let current = match result {
+            Ok(metadata) => Version::of(&metadata),
+            Err(GnosError::PathNotFound(_)) => Version::Absent,
+            Err(e) => return Err(e),
+        };
+        if base.matches(&current) {
+            return Ok(None);
+        }
+        
+        let conflict = format!("opened at {}, now {}", base.describe(), current.describe());
+        warn!("⚔️ Write to {} refused: changed since it was opened ({})", path.display(), conflict);
+        self.conflicts.record(path, conflict.clone());
+        Err(GnosError::ResourceBusy(format!("{} changed since it was opened: {}", path.display(), conflict)))
+    }
+    
+    /// Refuse deleting a protected object, or a directory a rule covers or
+    /// sits under, since deleting it could take protected objects with it
+    async fn check_deletable(&self, path: &Path) -> Result<()> {
+        if let Some(protection) = self.protection_of(path).await? {
+            return Err(protection.refusal(path, "deleted"));
+        }
+        let is_dir = self.inode_manager.find_by_path(path)
+            .and_then(|ino| self.inode_manager.get(ino))
+            .is_some_and(|inode| inode.is_dir);
+        if self.retention.contains_protected(path) || (is_dir && self.retention.covers(path)) {
+            return Err(GnosError::Immutable(format!("{} holds retained objects", path.display())));
+        }
+        Ok(())
+    }
+    
+    async fn write_kind(&self, path: &Path) -> EventKind {
+        match (&self.events, self.driver_registry.get_driver(path)) {
+            (Some(events), Some(driver)) => events.write_kind(driver.as_ref(), path).await,
+            _ => EventKind::Modified,
+        }
+    }
+    
+    fn publish(&self, kind: EventKind, path: &Path) {
+        let Some(events) = self.events.as_ref().filter(|events| events.is_active()) else {
+            return;
+        };
+        if let Some(driver) = self.driver_registry.get_driver(path) {
+            events.publish(kind, path, driver.name(), self.capability_manager.local_principal());
+        }
+    }
+    
+    /// Driver metadata for an inode, from the attr cache or the driver itself;
+    /// an unreachable driver is answered with the last metadata seen
+    async fn metadata_for(&self, inode: &GnosInode) -> Option<ResourceMetadata> {
+        let cached = info_span!("cache.attr", ino = inode.ino)
+            .in_scope(|| self.attr_cache.get(inode.ino));
+        if cached.is_some() {
+            return cached;
+        }
+        if self.procfs.contains(&inode.path)
+            || search_dir::is_search_path(&inode.path)
+            || pipeline_dir::is_pipeline_path(&inode.path)
+            || self.shards.contains(&inode.path)
+        {
+            return None;
+        }
+        
+        let driver = self.driver_registry.get_driver(&inode.path)?;
+        if !self.connectivity.should_try(driver.name()) {
+            return self.attr_cache.get_stale(inode.ino);
+        }
+        
+        let result = driver.metadata(&inode.path)
+            .instrument(driver_span("metadata", driver.as_ref(), &inode.path))
+            .await;
+        self.connectivity.record(driver.name(), &result);
+        
+        match result {
+            Ok(metadata) => {
+                if !metadata.is_directory {
+                    self.quotas.observe(&inode.path, metadata.size);
+                }
+                self.attr_cache.insert(inode.ino, metadata.clone());
+                Some(metadata)
+            }
+            Err(GnosError::Unreachable(_)) if self.connectivity.enabled() => self.attr_cache.get_stale(inode.ino),
+            Err(_) => None,
+        }
+    }
+    
+    /// Whether a path still has a queued write that hasn't reached its backend
+    fn write_pending(&self) -> impl Fn(&Path) -> bool + '_ {
+        move |path| self.write_back.as_ref().is_some_and(|queue| queue.sync_state(path) != SyncState::Clean)
+    }
+    
+    /// Remote version a write to `path` is based on, when its driver is offline;
+    /// the write-back queue checks it before uploading on reconnect
+    fn remote_version(&self, path: &Path) -> Option<RemoteVersion> {
+        let driver = self.driver_registry.get_driver(path)?;
+        if !self.connectivity.is_offline(driver.name()) {
+            return None;
+        }
+        
+        let ino = self.inode_manager.find_by_path(path)?;
+        self.attr_cache.get_stale(ino).as_ref().map(RemoteVersion::of)
+    }
+    
+    /// Page cache behaviour for a file: generated files are always direct,
+    /// then config rules, then the owning driver's preference
+    fn cache_mode_for(&self, path: &Path, generated: bool) -> CacheMode {
+        if generated {
+            return CacheMode::DirectIo;
+        }
+        
+        if let Some(rule) = self.cache_modes.iter().find(|rule| is_within(path, &rule.prefix)) {
+            return rule.mode;
+        }
+        
+        self.driver_registry
+            .get_driver(path)
+            .map_or(CacheMode::Auto, |driver| driver.cache_mode(path))
+    }
+    
+    /// Merge the driver's current listing of a directory into the inode table,
+    /// priming the attr cache with any metadata that came with it; shard
+    /// views are rebuilt from what the sharded directory last listed
+    async fn refresh_directory(&self, dir: &GnosInode) {
+        if self.shards.contains(&dir.path) {
+            self.shards.populate(&self.inode_manager, &dir.path);
+            return;
+        }
+        if self.procfs.contains(&dir.path)
+            || search_dir::is_search_path(&dir.path)
+            || pipeline_dir::is_pipeline_path(&dir.path)
+        {
+            return;
+        }
+        let Some(driver) = self.driver_registry.get_driver(&dir.path) else {
+            return;
+        };
+        
+        if !self.connectivity.should_try(driver.name()) {
+            return;
+        }
+        
+        let listing = driver.list_with_metadata(&dir.path)
+            .instrument(driver_span("list", driver.as_ref(), &dir.path))
+            .await;
+        self.connectivity.record(driver.name(), &listing);
+        let listing = match listing {
+            Ok(listing) => listing,
+            Err(e) => {
+                warn!("❌ Listing {} failed: {}", dir.path.display(), e);
+                return;
+            }
+        };
+        self.shards.refresh(&self.inode_manager, &dir.path, listing.len());
+        
+        for (name, metadata) in listing {
+            let path = match join_name(&dir.path, OsStr::new(&name)) {
+                Ok(path) => path,
+                Err(e) => {
+                    debug!("Skipping entry of {}: {}", dir.path.display(), e);
+                    continue;
+                }
+            };
+            if !self.namespace.reaches(&path) {
+                continue;
+            }
+            if let Some(metadata) = metadata.as_ref().filter(|metadata| !metadata.is_directory) {
+                self.quotas.observe(&path, metadata.size);
+            }
+            warm::merge_entry(&self.inode_manager, &self.attr_cache, &path, metadata);
+        }
+    }
+}
+
+/// errno a frontend should report for an error
+pub fn errno(error: &GnosError) -> i32 {
+    error.errno()
+}
+
+/// `size` bytes of `data` from `start`, or what is left of them; a view of
+/// the same buffer, so replies never copy the payload
+pub fn reply_window(data: &Bytes, start: u64, size: u32) -> Bytes {
+    let start = std::cmp::min(start, data.len() as u64) as usize;
+    let end = std::cmp::min(start + size as usize, data.len());
+    data.slice(start..end)
+}
+
+/// Child span for a driver call made on behalf of the current operation
+fn driver_span(op: &'static str, driver: &dyn GnosDriver, path: &Path) -> Span {
+    info_span!("driver.call", op = op, driver = driver.name(), path = %path.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn reply_windows_share_the_payload() {
+        let data = Bytes::from((0..=255u8).cycle().take(1024 * 1024).collect::<Vec<u8>>());
+        let window = reply_window(&data, 4096, 128 * 1024);
+        assert_eq!(window.len(), 128 * 1024);
+        assert_eq!(window.as_ptr(), data[4096..].as_ptr());
+        assert_eq!(&window[..], &data[4096..4096 + 128 * 1024]);
+    }
+    
+    #[test]
+    fn reply_windows_stop_at_the_end() {
+        let data = Bytes::from_static(b"0123456789");
+        assert_eq!(&reply_window(&data, 6, 128)[..], b"6789");
+        assert!(reply_window(&data, 10, 128).is_empty());
+        assert!(reply_window(&data, u64::MAX, u32::MAX).is_empty());
+        assert!(reply_window(&Bytes::new(), 0, 4096).is_empty());
+    }
+    
+    #[test]
+    fn consecutive_windows_cover_the_payload_once() {
+        let data = Bytes::from(vec![7u8; 1_000_003]);
+        let mut offset = 0;
+        loop {
+            let window = reply_window(&data, offset, 128 * 1024);
+            if window.is_empty() {
+                break;
+            }
+            assert_eq!(window.as_ptr(), data[offset as usize..].as_ptr());
+            offset += window.len() as u64;
+        }
+        assert_eq!(offset, data.len() as u64);
+    }
+}
@@ -1,123 +1,461 @@
 use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::path::PathBuf;
-use std::time::{Duration, SystemTime};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 use fuser::{
-    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, 
-    ReplyEntry, ReplyWrite, ReplyOpen, Request,
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory,
+    ReplyCreate, ReplyEmpty, ReplyEntry, ReplyWrite, ReplyOpen, ReplyStatfs, ReplyXattr, Request,
 };
-use tracing::{debug, info};
+use tokio::runtime::Handle;
+use tracing::{debug, info, instrument, warn};
 
+use crate::cache::{CompressionPolicy, DiskCache};
+use crate::config::{CacheMode, QuotaConfig, RetentionConfig, SearchConfig, TenantConfig, VfsConfig};
 use crate::drivers::DriverRegistry;
-use crate::security::{CapabilityManager, Operation};
-use crate::vfs::inode::{InodeManager, GnosInode};
-use crate::{GnosError, Result};
+use crate::events::EventBus;
+use crate::index::ContentIndex;
+use crate::search::SearchEngine;
+use crate::security::CapabilityManager;
+use crate::shutdown::Shutdown;
+use crate::telemetry::{Metrics, RequestId};
+use crate::triggers::TriggerEngine;
+use crate::vfs::attr_cache::AttrCache;
+use crate::vfs::conflict::ConflictTable;
+use crate::vfs::consistency::RecentWrites;
+use crate::vfs::core::{self, NodeAttr, OpenFile, VfsCore};
+use crate::vfs::handles::{HandleTrace, HandleTraces, HANDLES_DIR};
+use crate::vfs::inode::GnosInode;
+use crate::vfs::keys::KEY_XATTR;
+use crate::vfs::memory::MemoryBudget;
+use crate::vfs::namespace::NamespaceFilter;
+use crate::vfs::offline::Connectivity;
+use crate::vfs::quota::QuotaTable;
+use crate::vfs::retention::RetentionTable;
+use crate::vfs::shards::ShardView;
+use crate::vfs::template::{PathTemplate, TemplateSet};
+use crate::vfs::warm::Warmer;
+use crate::vfs::writeback::WriteBackQueue;
 
 const TTL: Duration = Duration::from_secs(1);
-const ROOT_INODE: u64 = 1;
 
+/// Most bytes moved by one copy_file_range call; the kernel calls again for the rest
+const COPY_RANGE_WINDOW: u64 = 8 * 1024 * 1024;
+
+/// FUSE frontend over a `VfsCore`
 pub struct GnosFileSystem {
-    driver_registry: DriverRegistry,
-    capability_manager: CapabilityManager,
-    inode_manager: InodeManager,
+    core: VfsCore,
     open_files: HashMap<u64, OpenFile>,
     next_fh: u64,
-}
-
-#[derive(Debug)]
-struct OpenFile {
-    path: PathBuf,
-    data: Option<Vec<u8>>,
+    runtime: Handle,
+    shutdown: Option<Arc<Shutdown>>,
 }
 
 impl GnosFileSystem {
     pub fn new(
-        driver_registry: DriverRegistry,
-        capability_manager: CapabilityManager,
+        driver_registry: Arc<DriverRegistry>,
+        capability_manager: Arc<CapabilityManager>,
     ) -> Self {
-        let mut inode_manager = InodeManager::new();
-        
-        // Create root directory
-        inode_manager.create_directory(ROOT_INODE, PathBuf::from("/"));
-        
-        // Pre-create known structure
-        inode_manager.create_directory(2, PathBuf::from("/proc"));
-        inode_manager.create_directory(3, PathBuf::from("/cloud"));
-        inode_manager.create_directory(4, PathBuf::from("/net"));
-        inode_manager.create_directory(5, PathBuf::from("/dev"));
-        
-        // AI models
-        inode_manager.create_file(10, PathBuf::from("/proc/llama3"));
-        
         Self {
-            driver_registry,
-            capability_manager,
-            inode_manager,
+            core: VfsCore::new(driver_registry, capability_manager),
             open_files: HashMap::new(),
             next_fh: 1,
+            runtime: Handle::current(),
+            shutdown: None,
+        }
+    }
+    
+    /// Hold write buffers, queued writes and the read-your-writes cache to
+    /// `memory`. Call before `with_vfs_config`, which sizes its caches from it
+    pub fn with_memory(mut self, memory: Arc<MemoryBudget>) -> Self {
+        let status = memory.clone();
+        self.register_proc_file("memory", move || status.status_report());
+        self.core.memory = memory;
+        self
+    }
+    
+    /// Apply attr cache and page cache settings
+    pub fn with_vfs_config(mut self, config: VfsConfig) -> Self {
+        self.core.attr_cache = Arc::new(AttrCache::new(Duration::from_secs(config.attr_cache_ttl_seconds)));
+        
+        // Longest prefix wins
+        let mut cache_modes = config.cache_modes;
+        cache_modes.sort_by_key(|rule| std::cmp::Reverse(rule.prefix.len()));
+        self.core.cache_modes = Arc::new(cache_modes);
+        
+        let templates = config.path_templates.iter()
+            .filter_map(|pattern| match PathTemplate::parse(pattern) {
+                Ok(template) => Some(template),
+                Err(e) => {
+                    warn!("❌ Ignoring path template: {}", e);
+                    None
+                }
+            })
+            .collect();
+        self.core.templates = Arc::new(TemplateSet::new(templates));
+        self.core.namespace = Arc::new(NamespaceFilter::new(&config.only));
+        self.core.handle_traces = Arc::new(HandleTraces::new(config.trace_handles, config.trace_max_events));
+        self.core.conflicts = Arc::new(ConflictTable::new(&config.conditional_writes));
+        
+        let recent_writes = Arc::new(RecentWrites::new(
+            &config.read_your_writes,
+            Duration::from_secs(config.read_your_writes_seconds),
+            config.read_your_writes_max_mb * 1024 * 1024,
+            self.core.memory.clone(),
+        ));
+        if recent_writes.enabled() {
+            let status = recent_writes.clone();
+            self.register_proc_file("consistency", move || status.status_report());
+        }
+        self.core.recent_writes = recent_writes;
+        self.core.shards = Arc::new(ShardView::new(config.shard_threshold, config.shard_prefix_len));
+        self
+    }
+    
+    /// A warmer that fills this filesystem's inode table and attr cache
+    pub fn warmer(&self, config: &VfsConfig) -> Warmer {
+        Warmer::new(
+            self.core.driver_registry.clone(),
+            self.core.inode_manager.clone(),
+            self.core.attr_cache.clone(),
+            config.warm_concurrency,
+            config.warm_max_depth,
+        )
+    }
+    
+    /// The quotas this mount enforces, for counting what their prefixes hold
+    pub fn quotas(&self) -> Arc<QuotaTable> {
+        self.core.quotas.clone()
+    }
+    
+    /// The retention rules this mount enforces, so expiry leaves protected objects alone
+    pub fn retention(&self) -> Arc<RetentionTable> {
+        self.core.retention.clone()
+    }
+    
+    /// The shared state behind this mount, for serving it over other protocols
+    pub fn core(&self) -> VfsCore {
+        self.core.clone()
+    }
+    
+    /// Serve reads of cacheable prefixes through a persistent chunk cache
+    pub fn with_disk_cache(mut self, disk_cache: Arc<DiskCache>) -> Self {
+        self.core.disk_cache = Some(disk_cache);
+        self
+    }
+    
+    /// Compress writes for path classes and backends that allow it
+    pub fn with_compression(mut self, compression: Arc<CompressionPolicy>) -> Self {
+        self.core.compression = compression;
+        self
+    }
+    
+    /// Publish every change made through the mount on `events`
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.core.events = Some(events);
+        self
+    }
+    
+    /// Expose the trigger audit trail at `/proc/gnos/triggers`
+    pub fn with_triggers(mut self, triggers: Arc<TriggerEngine>) -> Self {
+        self.register_proc_file("triggers", move || triggers.status_report());
+        self
+    }
+    
+    /// Keep `index` fresh with writes through the mount and expose `/proc/gnos/index`
+    ///
+    /// Call before `with_search` so searches consult the index.
+    pub fn with_index(mut self, index: Arc<ContentIndex>) -> Self {
+        let status = index.clone();
+        self.register_proc_file("index", move || status.status_report());
+        self.core.index = Some(index);
+        self
+    }
+    
+    /// Limits for searches started through `/proc/search/query`
+    pub fn with_search(mut self, config: &SearchConfig) -> Self {
+        let mut engine = SearchEngine::new(
+            config,
+            self.core.driver_registry.clone(),
+            self.core.capability_manager.clone(),
+        );
+        if let Some(index) = &self.core.index {
+            engine = engine.with_index(index.clone());
+        }
+        self.core.search_engine = Arc::new(engine);
+        self
+    }
+    
+    /// Acknowledge writes once journaled and upload them in the background
+    pub fn with_write_back(mut self, queue: Arc<WriteBackQueue>) -> Self {
+        let status_queue = queue.clone();
+        self.register_proc_file("sync", move || status_queue.status_report());
+        let conflict_queue = queue.clone();
+        self.register_proc_file("conflicts", move || conflict_queue.conflict_report());
+        self.core.write_back = Some(queue);
+        self
+    }
+    
+    /// Protect append-only and write-once prefixes and list them at `/proc/gnos/retention`
+    pub fn with_retention(mut self, config: &RetentionConfig) -> Self {
+        let retention = Arc::new(RetentionTable::new(config.rules.clone()));
+        let status = retention.clone();
+        self.register_proc_file("retention", move || status.status_report());
+        self.core.retention = retention;
+        self
+    }
+    
+    /// Enforce per-prefix quotas and expose their usage at `/proc/gnos/quota`
+    pub fn with_quotas(mut self, config: &QuotaConfig) -> Self {
+        let quotas = Arc::new(QuotaTable::new(config.rules.clone()));
+        let status = quotas.clone();
+        self.register_proc_file("quota", move || status.status_report());
+        self.core.quotas = quotas;
+        self
+    }
+    
+    /// Lay out each tenant's `/tenants/<id>` subtree and expose its audit
+    /// stream at `/proc/gnos/tenants/<id>/audit`
+    ///
+    /// Tenant quotas are enforced by `with_quotas` from `TenantConfig::quota_rule`.
+    pub fn with_tenants(mut self, tenants: &[TenantConfig]) -> Self {
+        for tenant in tenants {
+            self.core.add_tenant(&tenant.id);
+            self.core.capability_manager.track_tenant(&tenant.id);
+            
+            let capability_manager = self.core.capability_manager.clone();
+            let id = tenant.id.clone();
+            self.register_proc_file(&format!("tenants/{}/audit", tenant.id), move || {
+                capability_manager.tenant_audit_report(&id)
+            });
         }
+        self
+    }
+    
+    /// Keep serving from local state while backends are unreachable
+    pub fn with_offline(mut self, connectivity: Arc<Connectivity>) -> Self {
+        let status = connectivity.clone();
+        self.register_proc_file("offline", move || status.status_report());
+        self.core.connectivity = connectivity;
+        self
+    }
+    
+    /// Refuse new work once `shutdown` starts draining, and commit what
+    /// open handles buffered within its deadline on unmount
+    pub fn with_shutdown(mut self, shutdown: Arc<Shutdown>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+    
+    /// Expose `/proc/gnos/metrics` (Prometheus text) and `/proc/gnos/health` (JSON)
+    ///
+    /// Call after the other `with_*` builders so their caches and queues are reported.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        let inodes = self.core.inode_manager.clone();
+        let attrs = self.core.attr_cache.clone();
+        let disk_cache = self.core.disk_cache.clone();
+        let write_back = self.core.write_back.clone();
+        let registry = self.core.driver_registry.clone();
+        let memory = self.core.memory.clone();
+        let scrape_metrics = metrics.clone();
+        self.register_proc_file("metrics", move || {
+            let mut gauges = vec![
+                ("gnos_inodes", "Inodes known to the filesystem", inodes.len() as f64),
+                ("gnos_attr_cache_entries", "Cached driver metadata entries", attrs.len() as f64),
+            ];
+            if let Some(cache) = &disk_cache {
+                let usage = cache.usage();
+                gauges.push(("gnos_cache_chunks", "Chunks in the disk cache", usage.chunks as f64));
+                gauges.push(("gnos_cache_logical_bytes", "Object bytes held by the disk cache", usage.logical_bytes as f64));
+                gauges.push(("gnos_cache_physical_bytes", "Disk bytes used by the cache", usage.physical_bytes as f64));
+            }
+            if let Some(queue) = &write_back {
+                let stats = queue.stats();
+                gauges.push(("gnos_writeback_pending", "Writes waiting for upload", stats.pending as f64));
+                gauges.push(("gnos_writeback_failed", "Paths whose last upload failed", stats.failed as f64));
+            }
+            gauges.extend(memory.gauges());
+            let mut labelled = registry.pool_gauges();
+            labelled.extend(registry.recovery_gauges());
+            scrape_metrics.render(&gauges, &labelled)
+        });
+        
+        let registry = self.core.driver_registry.clone();
+        let drivers = registry.count();
+        let write_back = self.core.write_back.clone();
+        self.register_proc_file("health", move || {
+            let write_back = write_back.as_ref().map(|queue| queue.stats());
+            let degraded_drivers = registry.degraded();
+            let degraded = write_back.is_some_and(|stats| stats.failed > 0) || !degraded_drivers.is_empty();
+            let health = serde_json::json!({
+                "status": if degraded { "degraded" } else { "ok" },
+                "version": crate::VERSION,
+                "uptime_seconds": metrics.uptime().as_secs(),
+                "drivers": drivers,
+                "degraded_drivers": degraded_drivers,
+                "errors": metrics.error_count(),
+                "writeback": write_back.map(|stats| serde_json::json!({
+                    "pending": stats.pending,
+                    "failed": stats.failed,
+                })),
+            });
+            format!("{:#}\n", health)
+        });
+        
+        self
+    }
+    
+    /// Expose a generated file under `/proc/gnos`
+    pub fn register_proc_file<F>(&mut self, name: &str, generator: F)
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.core.register_proc_file(name, generator);
     }
     
-    fn get_file_attr(&self, ino: u64) -> Result<FileAttr> {
-        let inode = self.inode_manager.get(ino)
-            .ok_or_else(|| GnosError::PathNotFound(format!("inode {}", ino)))?;
+    /// Push a handle's buffered writes to its driver, or to the write-back queue
+    fn commit(&mut self, fh: u64) -> std::result::Result<(), libc::c_int> {
+        let Some(open_file) = self.open_files.get_mut(&fh) else {
+            return Err(libc::EBADF);
+        };
         
-        let now = SystemTime::now();
+        let (pending, bytes) = (open_file.has_pending_write(), open_file.buffered_len());
+        let started = Instant::now();
+        let result = self.runtime.block_on(self.core.commit(open_file));
+        if pending {
+            open_file.trace_op("commit", None, bytes, started, &result);
+        }
+        result.map_err(|e| core::errno(&e))
+    }
+    
+    /// Give a new handle the data key its opener set over the path, if any;
+    /// keyed handles bypass the page cache, which would hold the plaintext
+    /// past the handle and report the sealed size
+    fn take_key(&self, req: &Request, open_file: &mut OpenFile) {
+        open_file.key = self.core.keys.key_for(req.uid(), &open_file.path);
+        if open_file.key.is_some() {
+            open_file.cache_mode = CacheMode::DirectIo;
+        }
+    }
+    
+    /// Start tracing a new handle and expose its trace at `/proc/gnos/handles/<fh>`
+    fn start_trace(&self, fh: u64, path: &Path) -> Option<Arc<Mutex<HandleTrace>>> {
+        let trace = self.core.handle_traces.start(fh, path)?;
+        let report = trace.clone();
+        self.core.register_proc_file(&format!("{}/{}", HANDLES_DIR, fh), move || report.lock().unwrap().report());
+        Some(trace)
+    }
+    
+    /// Whether the daemon is shutting down, so operations that start new
+    /// work are refused; handles already open still finish theirs
+    fn draining(&self) -> bool {
+        self.shutdown.as_ref().is_some_and(|shutdown| shutdown.is_draining())
+    }
+    
+    fn remove(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        if self.draining() {
+            reply.error(libc::ESHUTDOWN);
+            return;
+        }
+        match self.runtime.block_on(self.core.remove(parent, name, session_of(req.pid()))) {
+            Ok(()) => reply.ok(),
+            Err(e) => {
+                warn!("🚫 Removing {:?} failed: {}", name, e);
+                reply.error(core::errno(&e));
+            }
+        }
+    }
+    
+    /// Attributes as `req`'s caller sees them: owned by the caller, with the
+    /// mode bits its capability allows
+    fn get_file_attr(&self, req: &Request, ino: u64) -> std::result::Result<FileAttr, libc::c_int> {
+        let NodeAttr { inode, size, mtime, perm, flags } = self.runtime.block_on(self.core.stat(ino))
+            .map_err(|e| core::errno(&e))?;
         
         Ok(FileAttr {
             ino,
-            size: inode.size,
-            blocks: (inode.size + 511) / 512,
-            atime: now,
-            mtime: inode.mtime,
+            size,
+            blocks: size.div_ceil(512),
+            atime: SystemTime::now(),
+            mtime,
             ctime: inode.ctime,
             crtime: inode.crtime,
-            kind: if inode.is_dir { FileType::Directory } else { FileType::RegularFile },
-            perm: inode.permissions,
+            kind: file_type(&inode),
+            perm,
             nlink: if inode.is_dir { 2 } else { 1 },
-            uid: 1000,
-            gid: 1000,
+            uid: req.uid(),
+            gid: req.gid(),
             rdev: 0,
-            flags: 0,
+            flags,
             blksize: 4096,
         })
     }
 }
 
 impl Filesystem for GnosFileSystem {
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+    #[instrument(name = "fuse.lookup", skip_all, fields(request_id = %RequestId::begin(), parent_ino = parent, name = ?name))]
+    fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
         debug!("lookup: parent={}, name={:?}", parent, name);
         
-        let parent_inode = match self.inode_manager.get(parent) {
-            Some(inode) => inode,
-            None => {
-                reply.error(libc::ENOENT);
-                return;
-            }
-        };
-        
-        let child_path = parent_inode.path.join(name);
-        
-        if let Some(child_ino) = self.inode_manager.find_by_path(&child_path) {
-            match self.get_file_attr(child_ino) {
+        match self.runtime.block_on(self.core.lookup(parent, name)) {
+            Some(child_ino) => match self.get_file_attr(req, child_ino) {
                 Ok(attr) => reply.entry(&TTL, &attr, 0),
                 Err(_) => reply.error(libc::EIO),
-            }
-        } else {
-            reply.error(libc::ENOENT);
+            },
+            None => reply.error(libc::ENOENT),
         }
     }
     
-    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+    #[instrument(name = "fuse.getattr", skip_all, fields(request_id = %RequestId::begin(), ino = ino))]
+    fn getattr(&mut self, req: &Request, ino: u64, reply: ReplyAttr) {
         debug!("getattr: ino={}", ino);
         
-        match self.get_file_attr(ino) {
+        match self.get_file_attr(req, ino) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(errno) => reply.error(errno),
+        }
+    }
+    
+    #[instrument(name = "fuse.setattr", skip_all, fields(request_id = %RequestId::begin(), ino = ino, size = ?size))]
+    fn setattr(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<fuser::TimeOrNow>,
+        _mtime: Option<fuser::TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        debug!("setattr: ino={}, size={:?}", ino, size);
+        
+        if let (Some(size), Some(fh)) = (size, fh) {
+            if let Some(open_file) = self.open_files.get_mut(&fh) {
+                if let Err(e) = open_file.truncate(size) {
+                    warn!("❌ Truncating {} failed: {}", open_file.path.display(), e);
+                    reply.error(core::errno(&e));
+                    return;
+                }
+            }
+        }
+        
+        match self.get_file_attr(req, ino) {
             Ok(attr) => reply.attr(&TTL, &attr),
-            Err(_) => reply.error(libc::ENOENT),
+            Err(errno) => reply.error(errno),
         }
     }
     
+    #[instrument(name = "fuse.readdir", skip_all, fields(request_id = %RequestId::begin(), ino = ino, offset = offset))]
     fn readdir(
         &mut self,
         _req: &Request,
@@ -128,17 +466,19 @@ impl Filesystem for GnosFileSystem {
     ) {
         debug!("readdir: ino={}, offset={}", ino, offset);
         
-        let entries = match ino {
-            1 => vec![("proc", 2), ("cloud", 3), ("net", 4), ("dev", 5)], // root
-            2 => vec![("llama3", 10)], // /proc
-            3 => vec![("aws", 20), ("gcp", 21), ("azure", 22)], // /cloud
-            4 => vec![("http", 30)], // /net
-            5 => vec![("sensors", 40)], // /dev
-            _ => vec![],
+        let entries = match self.runtime.block_on(self.core.list(ino, offset == 0)) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("🚫 Listing inode {} failed: {}", ino, e);
+                reply.error(core::errno(&e));
+                return;
+            }
         };
         
-        for (i, (name, ino)) in entries.iter().enumerate().skip(offset as usize) {
-            if reply.add(*ino, (i + 1) as i64, FileType::RegularFile, name) {
+        for (i, entry) in entries.iter().enumerate().skip(offset as usize) {
+            let kind = file_type(entry);
+            let name = entry.path.file_name().unwrap_or_default();
+            if reply.add(entry.ino, (i + 1) as i64, kind, name) {
                 break;
             }
         }
@@ -146,28 +486,134 @@ impl Filesystem for GnosFileSystem {
         reply.ok();
     }
     
-    fn open(&mut self, _req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
+    #[instrument(name = "fuse.readlink", skip_all, fields(request_id = %RequestId::begin(), ino = ino))]
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        debug!("readlink: ino={}", ino);
+        
+        match self.core.inode(ino).and_then(|inode| inode.symlink) {
+            Some(target) => reply.data(target.as_os_str().as_bytes()),
+            None => reply.error(libc::EINVAL),
+        }
+    }
+    
+    #[instrument(name = "fuse.open", skip_all, fields(request_id = %RequestId::begin(), ino = ino, path = tracing::field::Empty))]
+    fn open(&mut self, req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
         debug!("open: ino={}", ino);
+        if self.draining() {
+            reply.error(libc::ESHUTDOWN);
+            return;
+        }
         
-        let inode = match self.inode_manager.get(ino) {
-            Some(inode) if !inode.is_dir => inode,
-            _ => {
-                reply.error(libc::ENOENT);
+        let write = flags & libc::O_ACCMODE != libc::O_RDONLY;
+        let mut open_file = match self.runtime.block_on(self.core.open(ino, write)) {
+            Ok(open_file) => open_file,
+            Err(e) => {
+                warn!("🚫 Opening inode {} failed: {}", ino, e);
+                reply.error(core::errno(&e));
                 return;
             }
         };
         
+        open_file.session = session_of(req.pid());
+        self.take_key(req, &mut open_file);
+        
+        // Generated files are snapshotted at open and report no size, so they
+        // always come back direct and reads run until the data ends
+        let open_flags = match open_file.cache_mode {
+            CacheMode::Auto => 0,
+            CacheMode::DirectIo => fuser::consts::FOPEN_DIRECT_IO,
+            CacheMode::KeepCache => fuser::consts::FOPEN_KEEP_CACHE,
+        };
+        
         let fh = self.next_fh;
         self.next_fh += 1;
+        open_file.trace = self.start_trace(fh, &open_file.path);
+        self.open_files.insert(fh, open_file);
         
-        self.open_files.insert(fh, OpenFile {
-            path: inode.path.clone(),
-            data: None,
-        });
+        reply.opened(fh, open_flags);
+    }
+    
+    #[instrument(name = "fuse.create", skip_all, fields(request_id = %RequestId::begin(), parent_ino = parent, name = ?name, path = tracing::field::Empty))]
+    fn create(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        debug!("create: parent={}, name={:?}", parent, name);
+        if self.draining() {
+            reply.error(libc::ESHUTDOWN);
+            return;
+        }
+        
+        let (ino, mut open_file) = match self.runtime.block_on(self.core.create(parent, name)) {
+            Ok(created) => created,
+            Err(e) => {
+                warn!("🚫 Creating {:?} failed: {}", name, e);
+                reply.error(core::errno(&e));
+                return;
+            }
+        };
+        let attr = match self.get_file_attr(req, ino) {
+            Ok(attr) => attr,
+            Err(errno) => {
+                reply.error(errno);
+                return;
+            }
+        };
+        
+        open_file.session = session_of(req.pid());
+        self.take_key(req, &mut open_file);
+        let open_flags = match open_file.cache_mode {
+            CacheMode::DirectIo => fuser::consts::FOPEN_DIRECT_IO,
+            _ => 0,
+        };
+        
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        open_file.trace = self.start_trace(fh, &open_file.path);
+        self.open_files.insert(fh, open_file);
+        
+        reply.created(&TTL, &attr, 0, fh, open_flags);
+    }
+    
+    #[instrument(name = "fuse.mkdir", skip_all, fields(request_id = %RequestId::begin(), parent_ino = parent, name = ?name, path = tracing::field::Empty))]
+    fn mkdir(&mut self, req: &Request, parent: u64, name: &OsStr, _mode: u32, _umask: u32, reply: ReplyEntry) {
+        debug!("mkdir: parent={}, name={:?}", parent, name);
+        if self.draining() {
+            reply.error(libc::ESHUTDOWN);
+            return;
+        }
         
-        reply.opened(fh, 0);
+        match self.runtime.block_on(self.core.mkdir(parent, name)) {
+            Ok(ino) => match self.get_file_attr(req, ino) {
+                Ok(attr) => reply.entry(&TTL, &attr, 0),
+                Err(errno) => reply.error(errno),
+            },
+            Err(e) => {
+                warn!("🚫 Creating directory {:?} failed: {}", name, e);
+                reply.error(core::errno(&e));
+            }
+        }
+    }
+    
+    #[instrument(name = "fuse.unlink", skip_all, fields(request_id = %RequestId::begin(), parent_ino = parent, name = ?name, path = tracing::field::Empty))]
+    fn unlink(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("unlink: parent={}, name={:?}", parent, name);
+        self.remove(req, parent, name, reply);
+    }
+    
+    #[instrument(name = "fuse.rmdir", skip_all, fields(request_id = %RequestId::begin(), parent_ino = parent, name = ?name, path = tracing::field::Empty))]
+    fn rmdir(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("rmdir: parent={}, name={:?}", parent, name);
+        self.remove(req, parent, name, reply);
     }
     
+    #[instrument(name = "fuse.read", skip_all, fields(request_id = %RequestId::begin(), fh = fh, offset = offset, size = size, path = tracing::field::Empty))]
     fn read(
         &mut self,
         _req: &Request,
@@ -181,39 +627,62 @@ impl Filesystem for GnosFileSystem {
     ) {
         debug!("read: fh={}, offset={}, size={}", fh, offset, size);
         
-        if let Some(open_file) = self.open_files.get(&fh) {
-            // Simple simulation for now
-            let data = format!("GNOS Virtual File: {}\n", open_file.path.display()).into_bytes();
-            
-            let start = offset as usize;
-            let end = std::cmp::min(start + size as usize, data.len());
-            
-            if start < data.len() {
-                reply.data(&data[start..end]);
-            } else {
-                reply.data(&[]);
-            }
-        } else {
+        let Some(open_file) = self.open_files.get_mut(&fh) else {
             reply.error(libc::EBADF);
+            return;
+        };
+        tracing::Span::current().record("path", tracing::field::display(open_file.path.display()));
+        
+        let started = Instant::now();
+        let result = self.runtime.block_on(self.core.read(open_file, offset as u64, size));
+        let bytes = result.as_ref().map_or(0, |data| data.len() as u64);
+        open_file.trace_op("read", Some(offset as u64), bytes, started, &result);
+        
+        match result {
+            Ok(data) => reply.data(&data),
+            Err(e) => {
+                warn!("❌ Read failed for {}: {}", open_file.path.display(), e);
+                reply.error(libc::EIO);
+            }
         }
     }
     
+    #[instrument(name = "fuse.write", skip_all, fields(request_id = %RequestId::begin(), fh = fh, offset = offset, bytes = data.len()))]
     fn write(
         &mut self,
         _req: &Request,
         _ino: u64,
         fh: u64,
-        _offset: i64,
+        offset: i64,
         data: &[u8],
         _write_flags: u32,
         _flags: i32,
         _lock: Option<u64>,
         reply: ReplyWrite,
     ) {
-        debug!("write: fh={}, size={}", fh, data.len());
+        debug!("write: fh={}, offset={}, size={}", fh, offset, data.len());
         
         if let Some(open_file) = self.open_files.get_mut(&fh) {
-            open_file.data = Some(data.to_vec());
+            if self.core.check_writable(open_file).is_err() {
+                reply.error(libc::EACCES);
+                return;
+            }
+            let started = Instant::now();
+            let end = std::cmp::max(open_file.buffered_len(), offset as u64 + data.len() as u64);
+            let quota = self.core.check_quota(&open_file.path, end);
+            open_file.trace_op("write", Some(offset as u64), data.len() as u64, started, &quota);
+            if let Err(e) = quota {
+                warn!("🚫 Write to {} refused: {}", open_file.path.display(), e);
+                reply.error(core::errno(&e));
+                return;
+            }
+            
+            if let Err(e) = self.runtime.block_on(self.core.write(open_file, offset as u64, data)) {
+                warn!("❌ Write to {} failed: {}", open_file.path.display(), e);
+                reply.error(core::errno(&e));
+                return;
+            }
+            
             info!("✍️  Wrote {} bytes to {}", data.len(), open_file.path.display());
             reply.written(data.len() as u32);
         } else {
@@ -221,6 +690,70 @@ impl Filesystem for GnosFileSystem {
         }
     }
     
+    /// Copy between handles without bouncing the data through the caller,
+    /// e.g. `cp` from one driver's tree to another's
+    #[instrument(name = "fuse.copy_file_range", skip_all, fields(request_id = %RequestId::begin(), fh_in = fh_in, fh_out = fh_out, len = len))]
+    fn copy_file_range(
+        &mut self,
+        _req: &Request,
+        _ino_in: u64,
+        fh_in: u64,
+        offset_in: i64,
+        _ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        _flags: u32,
+        reply: ReplyWrite,
+    ) {
+        debug!("copy_file_range: fh_in={}, fh_out={}, len={}", fh_in, fh_out, len);
+        
+        let Some(source) = self.open_files.get_mut(&fh_in) else {
+            reply.error(libc::EBADF);
+            return;
+        };
+        let window = std::cmp::min(len, COPY_RANGE_WINDOW) as u32;
+        let started = Instant::now();
+        let result = self.runtime.block_on(self.core.read(source, offset_in as u64, window));
+        let bytes = result.as_ref().map_or(0, |data| data.len() as u64);
+        source.trace_op("read", Some(offset_in as u64), bytes, started, &result);
+        let data = match result {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("❌ Read failed for {}: {}", source.path.display(), e);
+                reply.error(core::errno(&e));
+                return;
+            }
+        };
+        
+        let Some(dest) = self.open_files.get_mut(&fh_out) else {
+            reply.error(libc::EBADF);
+            return;
+        };
+        if self.core.check_writable(dest).is_err() {
+            reply.error(libc::EACCES);
+            return;
+        }
+        let started = Instant::now();
+        let end = std::cmp::max(dest.buffered_len(), offset_out as u64 + data.len() as u64);
+        let quota = self.core.check_quota(&dest.path, end);
+        dest.trace_op("write", Some(offset_out as u64), data.len() as u64, started, &quota);
+        if let Err(e) = quota {
+            warn!("🚫 Copy to {} refused: {}", dest.path.display(), e);
+            reply.error(core::errno(&e));
+            return;
+        }
+        
+        if let Err(e) = self.runtime.block_on(self.core.write(dest, offset_out as u64, &data)) {
+            warn!("❌ Copy to {} failed: {}", dest.path.display(), e);
+            reply.error(core::errno(&e));
+            return;
+        }
+        info!("📦 Copied {} bytes to {}", data.len(), dest.path.display());
+        reply.written(data.len() as u32);
+    }
+    
+    #[instrument(name = "fuse.release", skip_all, fields(request_id = %RequestId::begin(), fh = fh))]
     fn release(
         &mut self,
         _req: &Request,
@@ -229,10 +762,212 @@ impl Filesystem for GnosFileSystem {
         _flags: i32,
         _lock_owner: Option<u64>,
         _flush: bool,
-        reply: fuser::ReplyEmpty,
+        reply: ReplyEmpty,
     ) {
         debug!("release: fh={}", fh);
+        let result = self.commit(fh);
         self.open_files.remove(&fh);
+        if let Some(trace) = self.core.handle_traces.finish(fh) {
+            self.core.unregister_proc_file(&format!("{}/{}", HANDLES_DIR, fh));
+            info!("🔍 {}", trace.lock().unwrap().summary());
+        }
+        
+        match result {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+    
+    #[instrument(name = "fuse.flush", skip_all, fields(request_id = %RequestId::begin(), fh = fh))]
+    fn flush(&mut self, _req: &Request, _ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        debug!("flush: fh={}", fh);
+        
+        match self.commit(fh) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+    
+    #[instrument(name = "fuse.fsync", skip_all, fields(request_id = %RequestId::begin(), fh = fh))]
+    fn fsync(&mut self, _req: &Request, _ino: u64, fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        debug!("fsync: fh={}", fh);
+        
+        if let Err(errno) = self.commit(fh) {
+            reply.error(errno);
+            return;
+        }
+        
+        // In write-back mode fsync means "uploaded", not just "journaled"
+        if let Some(open_file) = self.open_files.get(&fh) {
+            if let Err(e) = self.runtime.block_on(self.core.sync(&open_file.path)) {
+                warn!("❌ fsync of {} failed: {}", open_file.path.display(), e);
+                reply.error(libc::EIO);
+                return;
+            }
+        }
+        
         reply.ok();
     }
+    
+    /// Capacity of the innermost quota covering `ino`, so `df` shows a
+    /// team's share rather than the backends' unknown size
+    #[instrument(name = "fuse.statfs", skip_all, fields(request_id = %RequestId::begin(), ino = ino))]
+    fn statfs(&mut self, _req: &Request, ino: u64, reply: ReplyStatfs) {
+        debug!("statfs: ino={}", ino);
+        
+        let stats = self.core.statfs(ino);
+        reply.statfs(
+            stats.blocks,
+            stats.blocks_free,
+            stats.blocks_free,
+            stats.files,
+            stats.files_free,
+            stats.block_size,
+            255,
+            stats.block_size,
+        );
+    }
+    
+    #[instrument(name = "fuse.getxattr", skip_all, fields(request_id = %RequestId::begin(), ino = ino, name = ?name))]
+    fn getxattr(&mut self, req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        debug!("getxattr: ino={}, name={:?}", ino, name);
+        
+        let Some(inode) = self.core.inode(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        
+        // Each user sees only their own key, and only its fingerprint
+        let value = match name.to_str() {
+            Some(KEY_XATTR) => self.core.keys.describe(req.uid(), &inode.path),
+            _ => self.core.xattr(&inode.path, name),
+        };
+        match value {
+            Some(value) => reply_xattr(reply, value.as_bytes(), size),
+            None => reply.error(libc::ENODATA),
+        }
+    }
+    
+    #[instrument(name = "fuse.listxattr", skip_all, fields(request_id = %RequestId::begin(), ino = ino))]
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        debug!("listxattr: ino={}", ino);
+        
+        let mut names = Vec::new();
+        for name in self.core.xattr_names() {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        
+        reply_xattr(reply, &names, size);
+    }
+    
+    #[instrument(name = "fuse.setxattr", skip_all, fields(request_id = %RequestId::begin(), ino = ino, name = ?name))]
+    fn setxattr(&mut self, req: &Request, ino: u64, name: &OsStr, value: &[u8], _flags: i32, _position: u32, reply: ReplyEmpty) {
+        debug!("setxattr: ino={}, name={:?}", ino, name);
+        
+        let Some(inode) = self.core.inode(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        if name.to_str() != Some(KEY_XATTR) {
+            reply.error(libc::ENOTSUP);
+            return;
+        }
+        
+        match self.runtime.block_on(self.core.set_key(req.uid(), &inode.path, value)) {
+            Ok(()) => reply.ok(),
+            Err(e) => {
+                warn!("🚫 Setting a data key on {} failed: {}", inode.path.display(), e);
+                reply.error(core::errno(&e));
+            }
+        }
+    }
+    
+    #[instrument(name = "fuse.removexattr", skip_all, fields(request_id = %RequestId::begin(), ino = ino, name = ?name))]
+    fn removexattr(&mut self, req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("removexattr: ino={}, name={:?}", ino, name);
+        
+        let Some(inode) = self.core.inode(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        if name.to_str() != Some(KEY_XATTR) {
+            reply.error(libc::ENOTSUP);
+            return;
+        }
+        
+        match self.core.forget_key(req.uid(), &inode.path) {
+            Ok(()) => reply.ok(),
+            Err(_) => reply.error(libc::ENODATA),
+        }
+    }
+    
+    /// Commit what handles still buffer and flush the write-back queue,
+    /// within the shutdown deadline if there is one, then save the index
+    /// and the audit log
+    fn destroy(&mut self) {
+        let core = self.core.clone();
+        let mut open_files = std::mem::take(&mut self.open_files);
+        let flush = async move {
+            // Handles the kernel never released, e.g. of processes killed with the mount
+            for open_file in open_files.values_mut().filter(|open_file| open_file.has_pending_write()) {
+                info!("💾 Committing {} before unmount", open_file.path.display());
+                if let Err(e) = core.commit(open_file).await {
+                    warn!("❌ Unmounting without the write to {}: {}", open_file.path.display(), e);
+                }
+            }
+            if core.write_back.is_some() {
+                info!("💾 Flushing write-back queue before unmount");
+                if let Err(e) = core.sync_all().await {
+                    warn!("❌ Unmounting with unsynced writes: {}", e);
+                }
+            }
+        };
+        match self.shutdown.as_ref().map(|shutdown| shutdown.flush_deadline()) {
+            Some(deadline) => {
+                if self.runtime.block_on(tokio::time::timeout(deadline, flush)).is_err() {
+                    warn!("⏱️ Gave up flushing after {:?}; the write-back journal keeps what was queued", deadline);
+                }
+            }
+            None => self.runtime.block_on(flush),
+        }
+        
+        if let Some(index) = &self.core.index {
+            self.runtime.block_on(index.persist());
+        }
+        match self.core.capability_manager.flush_audit() {
+            Ok(0) => {}
+            Ok(entries) => info!("📜 Appended {} audit entries", entries),
+            Err(e) => warn!("❌ Saving the audit log failed: {}", e),
+        }
+    }
+}
+
+/// Answer an xattr request, honouring the size-probe convention
+fn reply_xattr(reply: ReplyXattr, value: &[u8], size: u32) {
+    if size == 0 {
+        reply.size(value.len() as u32);
+    } else if value.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(value);
+    }
+}
+
+/// Login session of a process, from `/proc/<pid>/stat`
+fn session_of(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // Fields after the parenthesised command: state, ppid, pgrp, session
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(3)?.parse().ok()
+}
+
+fn file_type(inode: &GnosInode) -> FileType {
+    if inode.symlink.is_some() {
+        FileType::Symlink
+    } else if inode.is_dir {
+        FileType::Directory
+    } else {
+        FileType::RegularFile
+    }
 }
@@ -0,0 +1,210 @@
+//! Per-handle operation traces
+//!
+//! With `trace_handles` on, every open file handle records what was done
+//! through it: each read, write and commit with its offset, size and
+//! duration, and every driver call those made. A slow `cp` can then be
+//! explained from `/proc/gnos/handles/<fh>`, or from the `user.gnos.trace`
+//! xattr of the file being copied, without turning on tracing for the
+//! whole mount.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use dashmap::DashMap;
+
+use crate::Result;
+
+/// Directory under `/proc/gnos` holding one file per traced handle
+pub const HANDLES_DIR: &str = "handles";
+
+/// Extended attribute with the traces of a file's open handles
+pub const TRACE_XATTR: &str = "user.gnos.trace";
+
+/// One thing done through a handle
+#[derive(Debug, Clone)]
+pub struct TraceEvent {
+    /// Since the handle was opened
+    pub at: Duration,
+    /// Frontend operation (`read`, `write`, `commit`) or driver call (`driver.read`)
+    pub op: &'static str,
+    /// Driver that served a driver call
+    pub driver: Option<&'static str>,
+    pub offset: Option<u64>,
+    pub bytes: u64,
+    pub duration: Duration,
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct HandleTrace {
+    fh: u64,
+    path: PathBuf,
+    opened: Instant,
+    opened_at: SystemTime,
+    max_events: usize,
+    events: VecDeque<TraceEvent>,
+    /// Events dropped to stay within `max_events`; totals still count them
+    dropped: u64,
+    ops: u64,
+    bytes_read: u64,
+    bytes_written: u64,
+    driver_calls: u64,
+    driver_time: Duration,
+}
+
+impl HandleTrace {
+    fn new(fh: u64, path: PathBuf, max_events: usize) -> Self {
+        Self {
+            fh,
+            path,
+            opened: Instant::now(),
+            opened_at: SystemTime::now(),
+            max_events,
+            events: VecDeque::new(),
+            dropped: 0,
+            ops: 0,
+            bytes_read: 0,
+            bytes_written: 0,
+            driver_calls: 0,
+            driver_time: Duration::ZERO,
+        }
+    }
+    
+    /// Record a frontend operation that started at `started`
+    pub fn op<T>(&mut self, op: &'static str, offset: Option<u64>, bytes: u64, started: Instant, result: &Result<T>) {
+        self.ops += 1;
+        match op {
+            "read" => self.bytes_read += bytes,
+            "write" => self.bytes_written += bytes,
+            _ => {}
+        }
+        self.push(op, None, offset, bytes, started, result);
+    }
+    
+    /// Record a driver call made for this handle
+    pub fn driver_call<T>(&mut self, op: &'static str, driver: &'static str, offset: Option<u64>, bytes: u64, started: Instant, result: &Result<T>) {
+        self.driver_calls += 1;
+        self.driver_time += started.elapsed();
+        self.push(op, Some(driver), offset, bytes, started, result);
+    }
+    
+    fn push<T>(&mut self, op: &'static str, driver: Option<&'static str>, offset: Option<u64>, bytes: u64, started: Instant, result: &Result<T>) {
+        if self.events.len() >= self.max_events {
+            self.events.pop_front();
+            self.dropped += 1;
+        }
+        self.events.push_back(TraceEvent {
+            at: started.duration_since(self.opened),
+            op,
+            driver,
+            offset,
+            bytes,
+            duration: started.elapsed(),
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+    }
+    
+    /// One-line totals, as logged when the handle is released
+    pub fn summary(&self) -> String {
+        format!(
+            "fh {} {}: {} ops over {:.3}s, {} bytes read, {} written, {} driver calls taking {:.3}s",
+            self.fh, self.path.display(), self.ops, self.opened.elapsed().as_secs_f64(),
+            self.bytes_read, self.bytes_written, self.driver_calls, self.driver_time.as_secs_f64()
+        )
+    }
+    
+    /// Plain-text view for `/proc/gnos/handles/<fh>`
+    pub fn report(&self) -> String {
+        let opened_at = chrono::DateTime::<chrono::Utc>::from(self.opened_at);
+        let mut report = format!("{}\nopened: {}\n", self.summary(), opened_at.to_rfc3339());
+        if self.dropped > 0 {
+            report.push_str(&format!("dropped: {} oldest events\n", self.dropped));
+        }
+        for event in &self.events {
+            report.push_str(&format!(
+                "+{:.6}s\t{}\t{}\t{}\t{} bytes\t{:.3}ms\t{}\n",
+                event.at.as_secs_f64(),
+                event.op,
+                event.driver.unwrap_or("-"),
+                event.offset.map_or_else(|| "-".to_string(), |offset| format!("@{}", offset)),
+                event.bytes,
+                event.duration.as_secs_f64() * 1000.0,
+                event.error.as_deref().unwrap_or("ok"),
+            ));
+        }
+        report
+    }
+}
+
+/// Traces of the open handles, by handle number
+pub struct HandleTraces {
+    enabled: bool,
+    max_events: usize,
+    traces: DashMap<u64, Arc<Mutex<HandleTrace>>>,
+}
+
+impl HandleTraces {
+    pub fn new(enabled: bool, max_events: usize) -> Self {
+        Self {
+            enabled,
+            max_events: max_events.max(1),
+            traces: DashMap::new(),
+        }
+    }
+    
+    pub fn disabled() -> Self {
+        Self::new(false, 1)
+    }
+    
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    
+    /// Start tracing a newly opened handle, if tracing is on
+    pub fn start(&self, fh: u64, path: &Path) -> Option<Arc<Mutex<HandleTrace>>> {
+        if !self.enabled {
+            return None;
+        }
+        let trace = Arc::new(Mutex::new(HandleTrace::new(fh, path.to_path_buf(), self.max_events)));
+        self.traces.insert(fh, trace.clone());
+        Some(trace)
+    }
+    
+    /// Stop tracing a released handle and return its trace
+    pub fn finish(&self, fh: u64) -> Option<Arc<Mutex<HandleTrace>>> {
+        self.traces.remove(&fh).map(|(_, trace)| trace)
+    }
+    
+    /// Check that every trace is filed under its own handle number; only
+    /// meaningful while no handle is being opened or released
+    pub fn check_invariants(&self) -> std::result::Result<(), String> {
+        for entry in self.traces.iter() {
+            let fh = entry.value().lock().unwrap().fh;
+            if fh != *entry.key() {
+                return Err(format!("trace of fh {} is filed under fh {}", fh, entry.key()));
+            }
+        }
+        if !self.enabled && !self.traces.is_empty() {
+            return Err(format!("{} traces with tracing off", self.traces.len()));
+        }
+        Ok(())
+    }
+    
+    /// Reports of every open handle on `path`, for `user.gnos.trace`
+    pub fn report_for(&self, path: &Path) -> Option<String> {
+        let mut traces: Vec<_> = self.traces.iter()
+            .filter(|entry| entry.value().lock().unwrap().path == path)
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        if traces.is_empty() {
+            return None;
+        }
+        traces.sort_by_key(|(fh, _)| *fh);
+        Some(traces.iter()
+            .map(|(_, trace)| trace.lock().unwrap().report())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
@@ -1,8 +1,10 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::SystemTime;
 
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+
 #[derive(Debug, Clone)]
 pub struct GnosInode {
     pub ino: u64,
@@ -13,6 +15,8 @@ pub struct GnosInode {
     pub mtime: SystemTime,
     pub ctime: SystemTime,
     pub crtime: SystemTime,
+    /// Target of a symbolic link, relative to the link's directory
+    pub symlink: Option<PathBuf>,
 }
 
 impl GnosInode {
@@ -27,6 +31,7 @@ impl GnosInode {
             mtime: now,
             ctime: now,
             crtime: now,
+            symlink: None,
         }
     }
     
@@ -41,48 +46,175 @@ impl GnosInode {
             mtime: now,
             ctime: now,
             crtime: now,
+            symlink: None,
         }
     }
 }
 
+/// Inode table shared by all FUSE operations
+///
+/// Both maps are sharded (`DashMap`), so concurrent lookups on different
+/// paths rarely contend on the same lock.
 pub struct InodeManager {
-    inodes: Arc<RwLock<HashMap<u64, GnosInode>>>,
-    path_to_ino: Arc<RwLock<HashMap<PathBuf, u64>>>,
-    next_ino: Arc<RwLock<u64>>,
+    inodes: DashMap<u64, GnosInode>,
+    path_to_ino: DashMap<PathBuf, u64>,
+    next_ino: AtomicU64,
 }
 
 impl InodeManager {
     pub fn new() -> Self {
         Self {
-            inodes: Arc::new(RwLock::new(HashMap::new())),
-            path_to_ino: Arc::new(RwLock::new(HashMap::new())),
-            next_ino: Arc::new(RwLock::new(2)), // Start from 2 (1 is root)
+            inodes: DashMap::new(),
+            path_to_ino: DashMap::new(),
+            next_ino: AtomicU64::new(100), // Below 100 is reserved for the static layout
         }
     }
     
-    pub fn create_directory(&mut self, ino: u64, path: PathBuf) -> u64 {
+    pub fn create_directory(&self, ino: u64, path: PathBuf) -> u64 {
         let inode = GnosInode::new_directory(ino, path.clone());
         
-        self.inodes.write().unwrap().insert(ino, inode);
-        self.path_to_ino.write().unwrap().insert(path, ino);
+        self.inodes.insert(ino, inode);
+        self.path_to_ino.insert(path, ino);
         
         ino
     }
     
-    pub fn create_file(&mut self, ino: u64, path: PathBuf) -> u64 {
+    pub fn create_file(&self, ino: u64, path: PathBuf) -> u64 {
         let inode = GnosInode::new_file(ino, path.clone());
         
-        self.inodes.write().unwrap().insert(ino, inode);
-        self.path_to_ino.write().unwrap().insert(path, ino);
+        self.inodes.insert(ino, inode);
+        self.path_to_ino.insert(path, ino);
         
         ino
     }
     
+    /// Symbolic link at `path` pointing to `target`; an existing inode is left alone
+    pub fn create_symlink(&self, path: &Path, target: PathBuf) -> u64 {
+        match self.path_to_ino.entry(path.to_path_buf()) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                let ino = self.allocate();
+                let mut inode = GnosInode::new_file(ino, path.to_path_buf());
+                inode.permissions = 0o777;
+                inode.size = target.as_os_str().len() as u64;
+                inode.symlink = Some(target);
+                
+                self.inodes.insert(ino, inode);
+                entry.insert(ino);
+                ino
+            }
+        }
+    }
+    
+    /// Hand out a fresh inode number
+    pub fn allocate(&self) -> u64 {
+        self.next_ino.fetch_add(1, Ordering::Relaxed)
+    }
+    
+    /// Direct children of a directory, in path order
+    pub fn children(&self, parent: &Path) -> Vec<GnosInode> {
+        let mut children: Vec<GnosInode> = self.inodes.iter()
+            .filter(|entry| entry.path.parent() == Some(parent))
+            .map(|entry| entry.value().clone())
+            .collect();
+        children.sort_by(|a, b| a.path.cmp(&b.path));
+        children
+    }
+    
     pub fn get(&self, ino: u64) -> Option<GnosInode> {
-        self.inodes.read().unwrap().get(&ino).cloned()
+        self.inodes.get(&ino).map(|entry| entry.value().clone())
+    }
+    
+    pub fn find_by_path(&self, path: &Path) -> Option<u64> {
+        self.path_to_ino.get(path).map(|entry| *entry.value())
+    }
+    
+    /// Inode for `path`, creating it with a fresh number if it is new
+    ///
+    /// Safe to race: the path's shard stays locked until the inode exists.
+    pub fn get_or_create(&self, path: &Path, is_dir: bool) -> u64 {
+        match self.path_to_ino.entry(path.to_path_buf()) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                let ino = self.allocate();
+                let inode = if is_dir {
+                    GnosInode::new_directory(ino, path.to_path_buf())
+                } else {
+                    GnosInode::new_file(ino, path.to_path_buf())
+                };
+                
+                self.inodes.insert(ino, inode);
+                entry.insert(ino);
+                ino
+            }
+        }
+    }
+    
+    /// Forget a path and everything below it
+    pub fn remove(&self, path: &Path) {
+        let doomed: Vec<(PathBuf, u64)> = self.path_to_ino.iter()
+            .filter(|entry| entry.key().starts_with(path))
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect();
+        for (path, ino) in doomed {
+            self.path_to_ino.remove(&path);
+            self.inodes.remove(&ino);
+        }
+    }
+    
+    /// Apply a change to an existing inode in place
+    pub fn update<F>(&self, ino: u64, f: F)
+    where
+        F: FnOnce(&mut GnosInode),
+    {
+        if let Some(mut entry) = self.inodes.get_mut(&ino) {
+            f(entry.value_mut());
+        }
+    }
+    
+    /// Check that the two maps agree: every path maps to an inode with that
+    /// path and back, and no inode number is ahead of the allocator
+    ///
+    /// Only meaningful while nothing else is mutating the table, e.g. once
+    /// the threads of a concurrency test have joined.
+    pub fn check_invariants(&self) -> std::result::Result<(), String> {
+        let next_ino = self.next_ino.load(Ordering::Relaxed);
+        for entry in self.path_to_ino.iter() {
+            let (path, ino) = (entry.key(), *entry.value());
+            match self.inodes.get(&ino) {
+                Some(inode) if inode.path == *path => {}
+                Some(inode) => return Err(format!(
+                    "{} maps to inode {} of {}", path.display(), ino, inode.path.display()
+                )),
+                None => return Err(format!("{} maps to missing inode {}", path.display(), ino)),
+            }
+        }
+        for entry in self.inodes.iter() {
+            let inode = entry.value();
+            if inode.ino != *entry.key() {
+                return Err(format!("inode {} is stored as {}", inode.ino, entry.key()));
+            }
+            if inode.ino >= next_ino {
+                return Err(format!("inode {} was never allocated (next is {})", inode.ino, next_ino));
+            }
+            if self.find_by_path(&inode.path) != Some(inode.ino) {
+                return Err(format!("inode {} of {} is unreachable by path", inode.ino, inode.path.display()));
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn len(&self) -> usize {
+        self.inodes.len()
     }
     
-    pub fn find_by_path(&self, path: &PathBuf) -> Option<u64> {
-        self.path_to_ino.read().unwrap().get(path).copied()
+    pub fn is_empty(&self) -> bool {
+        self.inodes.is_empty()
+    }
+}
+
+impl Default for InodeManager {
+    fn default() -> Self {
+        Self::new()
     }
 }
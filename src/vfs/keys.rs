@@ -0,0 +1,154 @@
+//! Per-user data keys for sensitive files
+//!
+//! A user hands the daemon a 256-bit key for a file or directory by setting
+//! the `user.gnos.key` xattr on it, either as the raw 32 bytes or in base64:
+//!
+//! ```text
+//! setfattr -n user.gnos.key -v "0s$(head -c32 /dev/urandom | base64)" /mnt/gnos/cloud/hr
+//! ```
+//!
+//! Files that user opens at or below that path are taken with the key at
+//! open: writes are sealed with AES-256-GCM before they reach the driver,
+//! the write-back journal or any cache, and reads are opened again. Other
+//! users, and handles opened without the key, see the sealed bytes. Keys
+//! live in memory only; removing the xattr or unmounting forgets them, and
+//! reading the xattr shows only a fingerprint.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use bytes::Bytes;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::vfs::path::is_within;
+use crate::{GnosError, Result};
+
+/// Extended attribute a data key is set through
+pub const KEY_XATTR: &str = "user.gnos.key";
+
+/// Leads every sealed object, ahead of its nonce
+const MAGIC: &[u8] = b"GNOSKEY1";
+
+const KEY_LEN: usize = 32;
+
+/// A user's key; wiped from memory when the last handle using it is gone
+pub struct DataKey([u8; KEY_LEN]);
+
+impl DataKey {
+    /// A key from the xattr value: the raw bytes or their base64
+    fn parse(value: &[u8]) -> Result<Self> {
+        let raw = match value.len() {
+            KEY_LEN => value.to_vec(),
+            _ => std::str::from_utf8(value).ok()
+                .and_then(|text| STANDARD.decode(text.trim()).ok())
+                .unwrap_or_default(),
+        };
+        let key: [u8; KEY_LEN] = raw.as_slice().try_into()
+            .map_err(|_| GnosError::InvalidPath(format!("{} takes a 32-byte key, raw or in base64", KEY_XATTR)))?;
+        Ok(Self(key))
+    }
+    
+    /// Names the key without giving it away
+    pub fn fingerprint(&self) -> String {
+        let hash = digest::digest(&digest::SHA256, &self.0);
+        hash.as_ref()[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+    
+    fn aead(&self) -> LessSafeKey {
+        // Always 32 bytes, the length AES-256-GCM takes
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &self.0).expect("AES-256 key length"))
+    }
+    
+    /// `data` encrypted for storage: magic, nonce, then ciphertext and tag
+    pub fn seal(&self, path: &Path, data: &[u8]) -> Result<Bytes> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce)
+            .map_err(|_| GnosError::Driver(format!("no randomness to seal {}", path.display())))?;
+        let mut sealed = data.to_vec();
+        self.aead().seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut sealed)
+            .map_err(|_| GnosError::Driver(format!("sealing {} failed", path.display())))?;
+        
+        let mut out = Vec::with_capacity(MAGIC.len() + NONCE_LEN + sealed.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        Ok(Bytes::from(out))
+    }
+    
+    /// What was sealed in `data`; objects written before the key was set
+    /// aren't sealed and come back as they are
+    pub fn open(&self, path: &Path, data: Bytes) -> Result<Bytes> {
+        if !data.starts_with(MAGIC) {
+            return Ok(data);
+        }
+        let rest = &data[MAGIC.len()..];
+        if rest.len() < NONCE_LEN {
+            return Err(GnosError::Driver(format!("{} is sealed but truncated", path.display())));
+        }
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| GnosError::Driver(format!("{} has a malformed nonce", path.display())))?;
+        let mut buffer = sealed.to_vec();
+        let plain = self.aead().open_in_place(nonce, Aad::empty(), &mut buffer)
+            .map_err(|_| GnosError::PermissionDenied(format!("{} is sealed with a different key", path.display())))?;
+        Ok(Bytes::copy_from_slice(plain))
+    }
+}
+
+impl fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DataKey({})", self.fingerprint())
+    }
+}
+
+impl Drop for DataKey {
+    fn drop(&mut self) {
+        for byte in self.0.iter_mut() {
+            // Volatile so the wipe isn't optimised away as a dead store
+            unsafe { std::ptr::write_volatile(byte, 0) };
+        }
+    }
+}
+
+/// Keys set through `user.gnos.key`, by user and path
+#[derive(Default)]
+pub struct KeyRing {
+    keys: Mutex<HashMap<(u32, PathBuf), Arc<DataKey>>>,
+}
+
+impl KeyRing {
+    /// Key files at or below `path` for `uid`, replacing any key set there
+    pub fn set(&self, uid: u32, path: &Path, value: &[u8]) -> Result<String> {
+        let key = DataKey::parse(value)?;
+        let fingerprint = key.fingerprint();
+        self.keys.lock().unwrap().insert((uid, path.to_path_buf()), Arc::new(key));
+        Ok(fingerprint)
+    }
+    
+    /// Forget the key `uid` set on `path`; handles already open keep theirs
+    pub fn forget(&self, uid: u32, path: &Path) -> bool {
+        self.keys.lock().unwrap().remove(&(uid, path.to_path_buf())).is_some()
+    }
+    
+    /// The key `uid` set on `path` or the nearest directory above it
+    pub fn key_for(&self, uid: u32, path: &Path) -> Option<Arc<DataKey>> {
+        self.keys.lock().unwrap().iter()
+            .filter(|((owner, keyed), _)| *owner == uid && is_within(path, keyed))
+            .max_by_key(|((_, keyed), _)| keyed.components().count())
+            .map(|(_, key)| key.clone())
+    }
+    
+    /// Where `uid`'s key for `path` was set and its fingerprint, for the xattr
+    pub fn describe(&self, uid: u32, path: &Path) -> Option<String> {
+        let keys = self.keys.lock().unwrap();
+        let ((_, keyed), key) = keys.iter()
+            .filter(|((owner, keyed), _)| *owner == uid && is_within(path, keyed))
+            .max_by_key(|((_, keyed), _)| keyed.components().count())?;
+        Some(format!("{} from {}", key.fingerprint(), keyed.display()))
+    }
+}
@@ -0,0 +1,250 @@
+//! Memory budget
+//!
+//! Open-file write buffers, write-back payloads waiting for upload and the
+//! read-your-writes cache all draw on one budget, `memory.budget_mb`. Each
+//! holds a `Reservation` for what it keeps and gives it back as it lets go,
+//! so the budget always knows what is held and by whom.
+//!
+//! A write-back payload that would take the daemon past the budget waits for
+//! uploads to drain, up to `memory.wait_ms`, then fails with ENOSPC; with
+//! `on_exhausted = "fail"` it fails at once. One that is larger than the
+//! whole budget fails straight away, since nothing could ever make room for
+//! it. Open-file write buffers never wait, as they fill on FUSE threads: a
+//! write that doesn't fit fails with ENOSPC. Caches never wait either: what
+//! doesn't fit is not cached. Writes replayed from the journal at start are
+//! counted but never refused.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+use crate::config::{MemoryConfig, MemoryPressure};
+use crate::{GnosError, Result};
+
+/// What memory is held for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryUse {
+    WriteBuffers,
+    QueuedWrites,
+    Caches,
+}
+
+impl MemoryUse {
+    const ALL: [MemoryUse; 3] = [MemoryUse::WriteBuffers, MemoryUse::QueuedWrites, MemoryUse::Caches];
+    
+    fn name(self) -> &'static str {
+        match self {
+            MemoryUse::WriteBuffers => "write_buffers",
+            MemoryUse::QueuedWrites => "queued_writes",
+            MemoryUse::Caches => "caches",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Usage {
+    held: [u64; 3],
+    peak: u64,
+    /// Writes refused for want of memory
+    refused: u64,
+    /// Writes that had to wait for memory
+    waited: u64,
+}
+
+impl Usage {
+    fn total(&self) -> u64 {
+        self.held.iter().sum()
+    }
+}
+
+/// The daemon-wide ceiling on buffered and cached bytes
+pub struct MemoryBudget {
+    /// Zero when memory is only counted, not bounded
+    limit: u64,
+    on_exhausted: MemoryPressure,
+    wait: Duration,
+    usage: Mutex<Usage>,
+    released: Notify,
+}
+
+impl MemoryBudget {
+    pub fn new(config: &MemoryConfig) -> Arc<Self> {
+        Arc::new(Self {
+            limit: config.budget_mb.saturating_mul(1024 * 1024),
+            on_exhausted: config.on_exhausted,
+            wait: Duration::from_millis(config.wait_ms),
+            usage: Mutex::new(Usage::default()),
+            released: Notify::new(),
+        })
+    }
+    
+    /// A budget that counts but never refuses
+    pub fn unbounded() -> Arc<Self> {
+        Self::new(&MemoryConfig { budget_mb: 0, ..MemoryConfig::default() })
+    }
+    
+    pub fn bounded(&self) -> bool {
+        self.limit > 0
+    }
+    
+    /// An empty reservation to grow as `kind` takes memory
+    pub fn reservation(self: &Arc<Self>, kind: MemoryUse) -> Reservation {
+        Reservation { budget: self.clone(), kind, bytes: 0 }
+    }
+    
+    /// Reserve `bytes` if they fit now, for a cache that would rather not
+    /// keep something than wait
+    pub fn try_reserve(self: &Arc<Self>, kind: MemoryUse, bytes: u64) -> Option<Reservation> {
+        self.try_take(kind, bytes).then(|| Reservation { budget: self.clone(), kind, bytes })
+    }
+    
+    /// Count `bytes` whether or not they fit, for memory already in use
+    pub fn charge(self: &Arc<Self>, kind: MemoryUse, bytes: u64) -> Reservation {
+        let mut usage = self.usage.lock().unwrap();
+        usage.held[kind as usize] += bytes;
+        usage.peak = usage.peak.max(usage.total());
+        Reservation { budget: self.clone(), kind, bytes }
+    }
+    
+    fn try_take(&self, kind: MemoryUse, bytes: u64) -> bool {
+        let mut usage = self.usage.lock().unwrap();
+        if self.limit > 0 && usage.total().saturating_add(bytes) > self.limit {
+            return false;
+        }
+        usage.held[kind as usize] += bytes;
+        usage.peak = usage.peak.max(usage.total());
+        true
+    }
+    
+    /// Take `bytes`, waiting for memory to be released if the budget says to
+    async fn take(&self, kind: MemoryUse, bytes: u64) -> Result<()> {
+        if self.try_take(kind, bytes) {
+            return Ok(());
+        }
+        if bytes > self.limit || self.on_exhausted == MemoryPressure::Fail {
+            return Err(self.refuse(kind, bytes));
+        }
+        
+        self.usage.lock().unwrap().waited += 1;
+        debug!("Waiting for {} bytes of memory for {}", bytes, kind.name());
+        let deadline = tokio::time::Instant::now() + self.wait;
+        loop {
+            let released = self.released.notified();
+            if self.try_take(kind, bytes) {
+                return Ok(());
+            }
+            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                return Err(self.refuse(kind, bytes));
+            }
+        }
+    }
+    
+    fn release(&self, kind: MemoryUse, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        {
+            let mut usage = self.usage.lock().unwrap();
+            usage.held[kind as usize] = usage.held[kind as usize].saturating_sub(bytes);
+        }
+        self.released.notify_waiters();
+    }
+    
+    fn refuse(&self, kind: MemoryUse, bytes: u64) -> GnosError {
+        let held = {
+            let mut usage = self.usage.lock().unwrap();
+            usage.refused += 1;
+            usage.total()
+        };
+        warn!("🧠 Memory budget exhausted: {} more bytes for {} with {} of {} held",
+              bytes, kind.name(), held, self.limit);
+        GnosError::Io(std::io::Error::from_raw_os_error(libc::ENOSPC))
+            .context(format!("memory budget of {} bytes exhausted ({} held)", self.limit, held))
+    }
+    
+    /// Gauges for `/proc/gnos/metrics`
+    pub fn gauges(&self) -> Vec<(&'static str, &'static str, f64)> {
+        let usage = self.usage.lock().unwrap();
+        vec![
+            ("gnos_memory_held_bytes", "Bytes held by write buffers, queued writes and caches", usage.total() as f64),
+            ("gnos_memory_budget_bytes", "Memory budget; 0 when unbounded", self.limit as f64),
+            ("gnos_memory_refused", "Writes refused for want of memory", usage.refused as f64),
+        ]
+    }
+    
+    /// Plain-text view for `/proc/gnos/memory`
+    pub fn status_report(&self) -> String {
+        let usage = self.usage.lock().unwrap();
+        let budget = if self.limit == 0 { "unbounded".to_string() } else { format!("{} bytes", self.limit) };
+        let mut report = format!(
+            "budget: {}\nheld: {} bytes\npeak: {} bytes\nwaited: {}\nrefused: {}\n",
+            budget, usage.total(), usage.peak, usage.waited, usage.refused,
+        );
+        for kind in MemoryUse::ALL {
+            report.push_str(&format!("{}\t{}\n", kind.name(), usage.held[kind as usize]));
+        }
+        report
+    }
+}
+
+/// Memory held against the budget, given back when dropped
+pub struct Reservation {
+    budget: Arc<MemoryBudget>,
+    kind: MemoryUse,
+    bytes: u64,
+}
+
+impl Reservation {
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+    
+    /// Hold at least `bytes`, waiting for the difference if the budget says to
+    pub async fn grow_to(&mut self, bytes: u64) -> Result<()> {
+        if bytes > self.bytes {
+            self.budget.take(self.kind, bytes - self.bytes).await?;
+            self.bytes = bytes;
+        }
+        Ok(())
+    }
+    
+    /// `grow_to` without waiting, for callers that can't
+    pub fn try_grow_to(&mut self, bytes: u64) -> Result<()> {
+        if bytes > self.bytes {
+            if !self.budget.try_take(self.kind, bytes - self.bytes) {
+                return Err(self.budget.refuse(self.kind, bytes - self.bytes));
+            }
+            self.bytes = bytes;
+        }
+        Ok(())
+    }
+    
+    /// Give back all but `bytes`
+    pub fn shrink_to(&mut self, bytes: u64) {
+        if bytes < self.bytes {
+            self.budget.release(self.kind, self.bytes - bytes);
+            self.bytes = bytes;
+        }
+    }
+    
+    /// Move what is held into a new reservation, leaving this one empty
+    pub fn take(&mut self) -> Reservation {
+        let bytes = std::mem::take(&mut self.bytes);
+        Reservation { budget: self.budget.clone(), kind: self.kind, bytes }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.release(self.kind, self.bytes);
+    }
+}
+
+impl fmt::Debug for Reservation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Reservation({} bytes for {})", self.bytes, self.kind.name())
+    }
+}
@@ -1,5 +1,45 @@
+pub mod attr_cache;
+pub mod conflict;
+pub mod consistency;
+pub mod core;
 pub mod filesystem;
+pub mod handles;
 pub mod inode;
+pub mod keys;
+pub mod memory;
+pub mod namespace;
+pub mod notify;
+pub mod offline;
+pub mod path;
+pub mod pipelines;
+pub mod procfs;
+pub mod quota;
+pub mod retention;
+pub mod search;
+pub mod shards;
+pub mod template;
+pub mod txn;
+pub mod warm;
+pub mod writeback;
 
+pub use attr_cache::AttrCache;
+pub use conflict::{ConflictTable, Version};
+pub use consistency::RecentWrites;
+pub use core::VfsCore;
 pub use filesystem::GnosFileSystem;
+pub use handles::{HandleTrace, HandleTraces};
 pub use inode::{InodeManager, GnosInode};
+pub use memory::{MemoryBudget, MemoryUse, Reservation};
+pub use namespace::NamespaceFilter;
+pub use notify::ChangeBridge;
+pub use offline::{Connectivity, RemoteVersion};
+pub use pipelines::PipelineTable;
+pub use procfs::ProcFs;
+pub use quota::{QuotaStats, QuotaTable};
+pub use retention::{Protection, RetentionTable};
+pub use search::SearchTable;
+pub use shards::ShardView;
+pub use template::{PathTemplate, TemplateSet};
+pub use txn::TxnTable;
+pub use warm::{WarmStats, Warmer};
+pub use writeback::{SyncState, WriteBackQueue, WriteBackStats};
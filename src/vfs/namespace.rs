@@ -0,0 +1,42 @@
+//! Mount-time namespace filtering
+//!
+//! A mount started with `--only /cloud/aws/s3/ci-artifacts` exposes that
+//! subtree and nothing else: the directories leading to it can be walked
+//! and list only the way down, and every other path, whichever driver owns
+//! it, looks like it doesn't exist.
+
+use std::path::{Path, PathBuf};
+
+use crate::vfs::path::normalize;
+
+/// Prefixes a mount exposes; empty exposes everything
+#[derive(Debug, Clone, Default)]
+pub struct NamespaceFilter {
+    prefixes: Vec<PathBuf>,
+}
+
+impl NamespaceFilter {
+    pub fn new(prefixes: &[String]) -> Self {
+        Self {
+            prefixes: prefixes.iter()
+                .map(|prefix| prefix.trim())
+                .filter(|prefix| !prefix.is_empty())
+                .filter_map(|prefix| normalize(Path::new(prefix)).ok())
+                .collect(),
+        }
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+    
+    /// Whether `path` lies inside an exposed prefix, so it may be read and changed
+    pub fn exposes(&self, path: &Path) -> bool {
+        self.is_empty() || self.prefixes.iter().any(|prefix| path.starts_with(prefix))
+    }
+    
+    /// Whether `path` is exposed or a directory on the way to an exposed prefix
+    pub fn reaches(&self, path: &Path) -> bool {
+        self.exposes(path) || self.prefixes.iter().any(|prefix| prefix.starts_with(path))
+    }
+}
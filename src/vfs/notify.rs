@@ -0,0 +1,295 @@
+//! Remote change notification
+//!
+//! The kernel caches names, attributes and pages of the mount, and file
+//! watchers only hear about changes made through it. Under `[notify]` the
+//! bridge follows the change stream each `watch` directory's driver offers,
+//! and passes on every change it reports:
+//!
+//! - a deleted entry is dropped with `notify_delete`, which inotify and
+//!   fanotify watchers see as `IN_DELETE`;
+//! - a new entry has its parent's cached name invalidated, so the next
+//!   lookup finds it;
+//! - a modified file has its cached pages invalidated, or with `store_max_kb`
+//!   replaced with its new contents through `notify_store`.
+//!
+//! Directories whose driver has no change stream are reported at startup
+//! and not watched. A stream that ends, say because the backend dropped the
+//! connection, is followed again after `resubscribe_seconds`; what changed
+//! in between is missed.
+//!
+//! The kernel raises no watcher events for the last two, so with `touch` the
+//! bridge also opens each created or modified file for writing through the
+//! mount and closes it unchanged, which watchers see as `IN_OPEN` and
+//! `IN_CLOSE_WRITE`. Files this process may not write, and files whose
+//! writes are calls, are left alone. Changes this mount made itself are
+//! passed on too when the backend reports them. `/proc/gnos/notify` counts
+//! what has been passed on.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use fuser::Notifier;
+use tracing::{debug, info, warn};
+
+use crate::config::NotifyConfig;
+use crate::drivers::{ChangeKind, RemoteChange, ResourceMetadata};
+use crate::qos::{self, QosClass};
+use crate::vfs::core::VfsCore;
+use crate::vfs::warm;
+
+/// What the kernel is told about a change, given the inodes it may have cached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Notice {
+    /// `notify_delete`: the entry is gone, and watchers see `IN_DELETE`
+    Delete { parent: u64, ino: u64 },
+    /// `notify_inval_entry`: the parent's cached name is stale
+    Entry { parent: u64 },
+    /// `notify_inval_inode` or `notify_store`: the file's cached pages are stale
+    Pages { ino: u64 },
+}
+
+#[derive(Default)]
+struct Counters {
+    subscribed: AtomicU64,
+    created: AtomicU64,
+    modified: AtomicU64,
+    deleted: AtomicU64,
+    stored: AtomicU64,
+    touched: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Passes remote changes on to the kernel and the watchers on the mount
+pub struct ChangeBridge {
+    core: VfsCore,
+    config: NotifyConfig,
+    mount_point: PathBuf,
+    counters: Counters,
+}
+
+impl ChangeBridge {
+    pub fn new(core: VfsCore, config: &NotifyConfig, mount_point: &Path) -> Arc<Self> {
+        Arc::new(Self {
+            core,
+            config: config.clone(),
+            mount_point: mount_point.to_path_buf(),
+            counters: Counters::default(),
+        })
+    }
+    
+    /// Follow the change stream of every watched directory until the runtime
+    /// shuts down, telling the kernel through `notifier`
+    pub fn spawn(self: Arc<Self>, notifier: Notifier) {
+        info!("👀 Watching {} directories for remote changes", self.config.watch.len());
+        let notifier = Arc::new(notifier);
+        for dir in &self.config.watch {
+            let dir = PathBuf::from(dir);
+            tokio::spawn(qos::scope(QosClass::Background, self.clone().follow(dir, notifier.clone())));
+        }
+    }
+    
+    async fn follow(self: Arc<Self>, dir: PathBuf, notifier: Arc<Notifier>) {
+        let retry = Duration::from_secs(self.config.resubscribe_seconds.max(1));
+        loop {
+            let driver = self.core.driver_registry.get_driver(&dir);
+            let Some(mut changes) = driver.as_ref().and_then(|driver| driver.watch(&dir)) else {
+                warn!("❌ Not watching {}: its driver reports no changes", dir.display());
+                return;
+            };
+            self.counters.subscribed.fetch_add(1, Ordering::Relaxed);
+            while let Some(change) = changes.next().await {
+                self.apply(&notifier, change).await;
+            }
+            warn!("❌ Change stream of {} ended; following it again in {}s", dir.display(), retry.as_secs());
+            tokio::time::sleep(retry).await;
+        }
+    }
+    
+    async fn apply(&self, notifier: &Notifier, change: RemoteChange) {
+        let RemoteChange { kind, path, metadata } = change;
+        debug!("Remote change: {:?} {}", kind, path.display());
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        let parent_ino = self.core.inode_manager.find_by_path(parent);
+        let ino = self.core.inode_manager.find_by_path(&path);
+        if let Some(cache) = &self.core.disk_cache {
+            cache.invalidate(&path).await;
+        }
+        
+        match kind {
+            ChangeKind::Deleted => {
+                self.counters.deleted.fetch_add(1, Ordering::Relaxed);
+                if let Some(ino) = ino {
+                    self.core.attr_cache.invalidate(ino);
+                }
+                self.core.inode_manager.remove(&path);
+            }
+            ChangeKind::Created => {
+                self.counters.created.fetch_add(1, Ordering::Relaxed);
+                warm::merge_entry(&self.core.inode_manager, &self.core.attr_cache, &path, metadata.clone());
+            }
+            ChangeKind::Modified => {
+                self.counters.modified.fetch_add(1, Ordering::Relaxed);
+                if let Some(ino) = ino {
+                    match metadata.clone() {
+                        Some(metadata) => self.core.attr_cache.insert(ino, metadata),
+                        None => self.core.attr_cache.invalidate(ino),
+                    }
+                }
+            }
+        }
+        
+        let result = match notice(kind, parent_ino, ino) {
+            Some(Notice::Delete { parent, ino }) => notifier.delete(parent, ino, name),
+            Some(Notice::Entry { parent }) => notifier.inval_entry(parent, name),
+            Some(Notice::Pages { ino }) => self.refresh_pages(notifier, ino, &path, metadata.as_ref()).await,
+            None => Ok(()),
+        };
+        match result {
+            // The kernel not knowing the inode just means nothing was cached
+            Err(e) if e.raw_os_error() == Some(libc::ENOENT) => {}
+            Err(e) => {
+                self.counters.failed.fetch_add(1, Ordering::Relaxed);
+                warn!("❌ Notifying the kernel of {} failed: {}", path.display(), e);
+            }
+            Ok(()) => {}
+        }
+        
+        if self.config.touch && kind != ChangeKind::Deleted && self.writable(&path).await {
+            self.touch(&path).await;
+        }
+    }
+    
+    /// Replace a small file's cached pages with its new contents, or drop them
+    async fn refresh_pages(
+        &self,
+        notifier: &Notifier,
+        ino: u64,
+        path: &Path,
+        metadata: Option<&ResourceMetadata>,
+    ) -> std::io::Result<()> {
+        let small = metadata.is_some_and(|m| !m.is_directory && m.size <= self.config.store_max_kb * 1024);
+        if small {
+            if let Some(driver) = self.core.driver_registry.get_driver(path) {
+                match driver.read(path).await {
+                    Ok(data) => {
+                        notifier.inval_inode(ino, 0, 0)?;
+                        self.counters.stored.fetch_add(1, Ordering::Relaxed);
+                        return notifier.store(ino, 0, &data);
+                    }
+                    Err(e) => debug!("Not storing {}: {}", path.display(), e),
+                }
+            }
+        }
+        notifier.inval_inode(ino, 0, 0)
+    }
+    
+    /// Whether `path` is a file this process could open for writing without
+    /// that being refused or sending anything
+    async fn writable(&self, path: &Path) -> bool {
+        let Some(ino) = self.core.inode_manager.find_by_path(path) else {
+            return false;
+        };
+        let invokes = self.core.driver_registry.get_driver(path).is_some_and(|driver| driver.invokes(path));
+        match self.core.stat(ino).await {
+            Ok(attr) => touchable(attr.inode.is_dir, attr.perm, invokes),
+            Err(e) => {
+                debug!("Not touching {}: {}", path.display(), e);
+                false
+            }
+        }
+    }
+    
+    /// Open and close `path` through the mount, so watchers see a write
+    async fn touch(&self, path: &Path) {
+        let local = self.mount_point.join(path.strip_prefix("/").unwrap_or(path));
+        let result = tokio::task::spawn_blocking(move || {
+            std::fs::OpenOptions::new().write(true).open(&local).map(drop)
+        }).await;
+        match result {
+            Ok(Ok(())) => {
+                self.counters.touched.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(Err(e)) => debug!("Not touching {}: {}", path.display(), e),
+            Err(e) => debug!("Not touching {}: {}", path.display(), e),
+        }
+    }
+    
+    /// Plain-text view for `/proc/gnos/notify`
+    pub fn status_report(&self) -> String {
+        let counter = |count: &AtomicU64| count.load(Ordering::Relaxed);
+        let mut report = format!(
+            "subscribed: {}\ncreated: {}\nmodified: {}\ndeleted: {}\nstored: {}\ntouched: {}\nfailed: {}\n",
+            counter(&self.counters.subscribed),
+            counter(&self.counters.created),
+            counter(&self.counters.modified),
+            counter(&self.counters.deleted),
+            counter(&self.counters.stored),
+            counter(&self.counters.touched),
+            counter(&self.counters.failed),
+        );
+        for dir in &self.config.watch {
+            report.push_str(&format!("watch\t{}\n", dir));
+        }
+        report
+    }
+}
+
+/// What to tell the kernel about a change, given the inodes of the entry and
+/// its parent if the mount has them; nothing when it can't have cached them
+fn notice(kind: ChangeKind, parent: Option<u64>, ino: Option<u64>) -> Option<Notice> {
+    match (kind, parent, ino) {
+        (ChangeKind::Deleted, Some(parent), Some(ino)) => Some(Notice::Delete { parent, ino }),
+        (ChangeKind::Deleted | ChangeKind::Created, Some(parent), _) => Some(Notice::Entry { parent }),
+        (ChangeKind::Modified, _, Some(ino)) => Some(Notice::Pages { ino }),
+        _ => None,
+    }
+}
+
+/// Whether a changed entry can be touched: a file the owner may write whose
+/// writes aren't calls, which opening it could send
+fn touchable(is_dir: bool, perm: u16, invokes: bool) -> bool {
+    !is_dir && perm & 0o200 != 0 && !invokes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn deletes_drop_entries_the_kernel_may_hold() {
+        assert_eq!(notice(ChangeKind::Deleted, Some(1), Some(7)), Some(Notice::Delete { parent: 1, ino: 7 }));
+        // Without an inode the name may still be cached as a negative entry
+        assert_eq!(notice(ChangeKind::Deleted, Some(1), None), Some(Notice::Entry { parent: 1 }));
+        assert_eq!(notice(ChangeKind::Deleted, None, None), None);
+    }
+    
+    #[test]
+    fn creations_invalidate_the_parents_name() {
+        assert_eq!(notice(ChangeKind::Created, Some(1), None), Some(Notice::Entry { parent: 1 }));
+        assert_eq!(notice(ChangeKind::Created, Some(1), Some(7)), Some(Notice::Entry { parent: 1 }));
+        assert_eq!(notice(ChangeKind::Created, None, None), None);
+    }
+    
+    #[test]
+    fn modifications_refresh_only_known_files() {
+        assert_eq!(notice(ChangeKind::Modified, Some(1), Some(7)), Some(Notice::Pages { ino: 7 }));
+        assert_eq!(notice(ChangeKind::Modified, None, Some(7)), Some(Notice::Pages { ino: 7 }));
+        assert_eq!(notice(ChangeKind::Modified, Some(1), None), None);
+    }
+    
+    #[test]
+    fn only_writable_files_are_touched() {
+        assert!(touchable(false, 0o644, false));
+        assert!(touchable(false, 0o600, false));
+        assert!(!touchable(false, 0o444, false));
+        // Group and other write bits don't make it writable for the owner
+        assert!(!touchable(false, 0o466, false));
+        assert!(!touchable(true, 0o755, false));
+        assert!(!touchable(false, 0o644, true));
+    }
+}
@@ -0,0 +1,120 @@
+//! Offline mode
+//!
+//! When a backend stops answering, the mount keeps working from what it
+//! already has: reads come from the disk cache, attributes from the last
+//! metadata seen, and writes stay in the write-back journal. Each journaled
+//! write remembers the remote version it was based on, so on reconnect an
+//! upload that would overwrite someone else's change is held back as a
+//! conflict instead.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use dashmap::DashMap;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::config::OfflineConfig;
+use crate::drivers::ResourceMetadata;
+use crate::{GnosError, Result};
+
+/// Reachability of each driver, as observed by the calls made to it
+pub struct Connectivity {
+    enabled: bool,
+    probe_interval: Duration,
+    /// Drivers currently unreachable: since when, and when they were last tried
+    offline: DashMap<&'static str, (SystemTime, Instant)>,
+    reconnected: Notify,
+}
+
+impl Connectivity {
+    pub fn new(config: &OfflineConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            probe_interval: Duration::from_secs(config.probe_interval_seconds),
+            offline: DashMap::new(),
+            reconnected: Notify::new(),
+        }
+    }
+    
+    /// Offline mode switched off; every call goes to its driver
+    pub fn disabled() -> Self {
+        Self::new(&OfflineConfig::default())
+    }
+    
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    
+    pub fn is_offline(&self, driver: &str) -> bool {
+        self.enabled && self.offline.contains_key(driver)
+    }
+    
+    /// Whether a call should go to the driver, or be answered locally until
+    /// the next probe is due
+    pub fn should_try(&self, driver: &str) -> bool {
+        match self.offline.get(driver) {
+            Some(entry) if self.enabled => entry.value().1.elapsed() >= self.probe_interval,
+            _ => true,
+        }
+    }
+    
+    /// Note the outcome of a driver call
+    pub fn record<T>(&self, driver: &'static str, result: &Result<T>) {
+        if !self.enabled {
+            return;
+        }
+        
+        match result {
+            Err(GnosError::Unreachable(reason)) => {
+                let mut first = false;
+                self.offline.entry(driver)
+                    .and_modify(|entry| entry.1 = Instant::now())
+                    .or_insert_with(|| {
+                        first = true;
+                        (SystemTime::now(), Instant::now())
+                    });
+                if first {
+                    warn!("📴 {} is unreachable, working offline: {}", driver, reason);
+                }
+            }
+            // Any other answer, even an error, means the backend is there
+            _ => {
+                if self.offline.remove(driver).is_some() {
+                    info!("📶 {} is reachable again", driver);
+                    self.reconnected.notify_waiters();
+                }
+            }
+        }
+    }
+    
+    /// Sleep until an unreachable backend is worth trying again
+    pub async fn wait_for_probe(&self) {
+        let _ = tokio::time::timeout(self.probe_interval, self.reconnected.notified()).await;
+    }
+    
+    /// Plain-text view for `/proc/gnos/offline`
+    pub fn status_report(&self) -> String {
+        let mut report = format!("offline: {}\n", self.offline.len());
+        for entry in self.offline.iter() {
+            let since = chrono::DateTime::<chrono::Utc>::from(entry.value().0);
+            report.push_str(&format!("{}\tsince {}\n", entry.key(), since.to_rfc3339()));
+        }
+        report
+    }
+}
+
+/// The remote state a write was made against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RemoteVersion {
+    pub size: u64,
+    pub mtime: SystemTime,
+}
+
+impl RemoteVersion {
+    pub fn of(metadata: &ResourceMetadata) -> Self {
+        Self {
+            size: metadata.size,
+            mtime: metadata.last_modified,
+        }
+    }
+}
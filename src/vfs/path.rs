@@ -0,0 +1,172 @@
+//! Canonical GNOS paths
+//!
+//! Every path that reaches the inode table, a capability check or a driver
+//! goes through here first, so the layers agree on what a path means:
+//! always absolute, no `.` or `..` segments, no empty, duplicate or trailing
+//! separators, UTF-8 names without NUL bytes, and within POSIX length
+//! limits. `..` stops at the root, as it does in a kernel path walk.
+//!
+//! Prefix checks are made per component with `is_within`, so `/cloud` never
+//! covers `/cloudy`. The functions do no I/O and hold no state, so they can
+//! be fuzzed directly; `parse_bytes` takes raw input as a fuzzer produces it.
+
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{GnosError, Result};
+
+/// Longest path accepted, in bytes, as PATH_MAX
+pub const MAX_PATH_LEN: usize = 4096;
+
+/// Longest single name accepted, in bytes, as NAME_MAX
+pub const MAX_NAME_LEN: usize = 255;
+
+/// Canonical form of `path`; relative paths are taken from the root
+pub fn normalize(path: &Path) -> Result<PathBuf> {
+    let mut names: Vec<&str> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                names.pop();
+            }
+            Component::Normal(name) => names.push(check_name(name)?),
+        }
+    }
+    
+    let mut normalized = String::with_capacity(path.as_os_str().len() + 1);
+    for name in &names {
+        normalized.push('/');
+        normalized.push_str(name);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    if normalized.len() > MAX_PATH_LEN {
+        return Err(GnosError::InvalidPath(format!(
+            "path of {} bytes is longer than {}", normalized.len(), MAX_PATH_LEN
+        )));
+    }
+    Ok(PathBuf::from(normalized))
+}
+
+/// Canonical form of a path given as text, e.g. by a remote caller
+pub fn parse(raw: &str) -> Result<PathBuf> {
+    normalize(Path::new(raw))
+}
+
+/// Canonical form of a path given as raw bytes
+pub fn parse_bytes(raw: &[u8]) -> Result<PathBuf> {
+    let raw = std::str::from_utf8(raw)
+        .map_err(|_| GnosError::InvalidPath("path is not UTF-8".to_string()))?;
+    parse(raw)
+}
+
+/// `name` as a single path component: non-empty, not `.` or `..`, UTF-8,
+/// without separators or NUL bytes, and at most `MAX_NAME_LEN` bytes
+pub fn check_name(name: &OsStr) -> Result<&str> {
+    let text = name.to_str()
+        .ok_or_else(|| GnosError::InvalidPath(format!("{:?} is not UTF-8", name)))?;
+    if text.is_empty() || text == "." || text == ".." {
+        return Err(GnosError::InvalidPath(format!("{:?} is not a name", text)));
+    }
+    if text.contains(['/', '\0']) {
+        return Err(GnosError::InvalidPath(format!("{:?} holds a separator or NUL byte", text)));
+    }
+    if text.len() > MAX_NAME_LEN {
+        return Err(GnosError::InvalidPath(format!(
+            "name of {} bytes is longer than {}", text.len(), MAX_NAME_LEN
+        )));
+    }
+    Ok(text)
+}
+
+/// Child `name` of the canonical path `parent`, e.g. for a lookup or a
+/// name from a driver listing
+pub fn join_name(parent: &Path, name: &OsStr) -> Result<PathBuf> {
+    let name = check_name(name)?;
+    let path = parent.join(name);
+    if path.as_os_str().len() > MAX_PATH_LEN {
+        return Err(GnosError::InvalidPath(format!(
+            "path of {} bytes is longer than {}", path.as_os_str().len(), MAX_PATH_LEN
+        )));
+    }
+    Ok(path)
+}
+
+/// Whether the canonical path `path` is `prefix` or lies below it; a
+/// prefix that can't be normalized covers nothing
+pub fn is_within(path: &Path, prefix: impl AsRef<Path>) -> bool {
+    normalize(prefix.as_ref()).is_ok_and(|prefix| path.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::ffi::OsStrExt;
+    
+    use super::*;
+    
+    #[test]
+    fn dot_segments_and_separators_collapse() {
+        for (raw, canonical) in [
+            ("/cloud/aws/s3", "/cloud/aws/s3"),
+            ("cloud/aws", "/cloud/aws"),
+            ("//cloud///aws//", "/cloud/aws"),
+            ("/cloud/./aws/.", "/cloud/aws"),
+            ("/cloud/aws/../gcp", "/cloud/gcp"),
+            ("", "/"),
+            ("/.", "/"),
+        ] {
+            assert_eq!(parse(raw).unwrap(), Path::new(canonical), "{:?}", raw);
+        }
+    }
+    
+    #[test]
+    fn dot_dot_stops_at_the_root() {
+        assert_eq!(parse("/..").unwrap(), Path::new("/"));
+        assert_eq!(parse("/../../etc/passwd").unwrap(), Path::new("/etc/passwd"));
+        assert_eq!(parse("/cloud/../../secrets").unwrap(), Path::new("/secrets"));
+    }
+    
+    #[test]
+    fn bad_names_are_refused() {
+        assert!(parse_bytes(b"/cloud/\xff").is_err());
+        assert!(normalize(Path::new(OsStr::from_bytes(b"/cloud/\xfe\xff"))).is_err());
+        assert!(parse("/cloud/a\0b").is_err());
+        for name in ["", ".", "..", "a/b", "a\0b"] {
+            assert!(check_name(OsStr::new(name)).is_err(), "{:?}", name);
+        }
+    }
+    
+    #[test]
+    fn lengths_are_limited_at_posix_limits() {
+        let longest = "n".repeat(MAX_NAME_LEN);
+        assert!(check_name(OsStr::new(&longest)).is_ok());
+        assert!(check_name(OsStr::new(&format!("{}n", longest))).is_err());
+        
+        let deep = format!("/{}", vec!["n"; MAX_PATH_LEN / 2].join("/"));
+        assert_eq!(deep.len(), MAX_PATH_LEN);
+        assert!(parse(&deep).is_ok());
+        assert!(parse(&format!("{}/n", deep)).is_err());
+        assert!(join_name(Path::new(&deep), OsStr::new("n")).is_err());
+    }
+    
+    #[test]
+    fn joined_names_stay_below_their_parent() {
+        assert_eq!(join_name(Path::new("/cloud"), OsStr::new("aws")).unwrap(), Path::new("/cloud/aws"));
+        assert!(join_name(Path::new("/cloud"), OsStr::new("..")).is_err());
+        assert!(join_name(Path::new("/cloud"), OsStr::new("aws/../..")).is_err());
+    }
+    
+    #[test]
+    fn prefixes_cover_whole_components_only() {
+        let path = Path::new("/cloud/aws/s3");
+        assert!(is_within(path, "/cloud"));
+        assert!(is_within(path, "/cloud/aws/s3"));
+        assert!(is_within(path, "/cloud//aws/"));
+        assert!(is_within(path, "/"));
+        assert!(!is_within(path, "/clo"));
+        assert!(!is_within(Path::new("/cloudy"), "/cloud"));
+        assert!(!is_within(path, "/cloud/\0"));
+    }
+}
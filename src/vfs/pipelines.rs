@@ -0,0 +1,123 @@
+//! `/proc/pipelines`
+//!
+//! Dropping a pipeline spec into `/proc/pipelines` starts it. A directory
+//! named after the pipeline appears next to the spec, with a `status` file
+//! and one file per stage reporting its progress:
+//!
+//! ```text
+//! cp standup-notes.toml /mnt/gnos/proc/pipelines/
+//! cat /mnt/gnos/proc/pipelines/standup-notes/status
+//! cat /mnt/gnos/proc/pipelines/standup-notes/summarize
+//! ```
+//!
+//! Writing the spec again reruns the pipeline once the current run ends.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use dashmap::DashMap;
+
+use crate::pipeline::{PipelineProgress, PipelineSpec};
+use crate::{GnosError, Result};
+
+pub const PIPELINES_ROOT: &str = "/proc/pipelines";
+
+struct PipelineRun {
+    spec_path: PathBuf,
+    spec_text: String,
+    progress: Arc<PipelineProgress>,
+}
+
+/// Pipelines started through the mount, by name
+#[derive(Default)]
+pub struct PipelineTable {
+    runs: DashMap<String, PipelineRun>,
+}
+
+impl PipelineTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Register a run of `spec` and return its progress and status directory;
+    /// a pipeline can't be resubmitted while it is still running
+    pub fn start(&self, spec_path: &Path, spec_text: &str, spec: &PipelineSpec) -> Result<(String, Arc<PipelineProgress>, PathBuf)> {
+        let name = match &spec.name {
+            Some(name) => name.clone(),
+            None => spec_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+        };
+        if name.is_empty() || name.contains('/') || Path::new(&name).extension().is_some() {
+            return Err(GnosError::InvalidPath(format!("bad pipeline name: {:?}", name)));
+        }
+        
+        if let Some(run) = self.runs.get(&name) {
+            if matches!(run.progress.overall(), "running" | "pending") {
+                return Err(GnosError::ResourceBusy(format!("pipeline {} is running", name)));
+            }
+            if run.spec_path != spec_path {
+                return Err(GnosError::InvalidPath(format!(
+                    "pipeline {} already comes from {}", name, run.spec_path.display()
+                )));
+            }
+        }
+        
+        let progress = Arc::new(PipelineProgress::new(spec));
+        self.runs.insert(name.clone(), PipelineRun {
+            spec_path: spec_path.to_path_buf(),
+            spec_text: spec_text.to_string(),
+            progress: progress.clone(),
+        });
+        let dir = Path::new(PIPELINES_ROOT).join(&name);
+        Ok((name, progress, dir))
+    }
+    
+    /// Contents of a spec or status file; paths nothing has been written to
+    /// yet read as empty
+    pub fn render(&self, path: &Path) -> Bytes {
+        let Ok(relative) = path.strip_prefix(PIPELINES_ROOT) else {
+            return Bytes::new();
+        };
+        let mut components = relative.iter().map(|name| name.to_string_lossy());
+        
+        match (components.next(), components.next()) {
+            (Some(_), None) => self.runs.iter()
+                .find(|run| run.spec_path == path)
+                .map_or_else(Bytes::new, |run| Bytes::from(run.spec_text.clone())),
+            (Some(name), Some(file)) => match self.runs.get(name.as_ref()) {
+                Some(run) if file == "status" => Bytes::from(status_report(&run.progress)),
+                Some(run) => run.progress.stage(&file)
+                    .map_or_else(Bytes::new, |state| Bytes::from(format!("{}\n", state))),
+                None => Bytes::new(),
+            },
+            _ => Bytes::new(),
+        }
+    }
+}
+
+fn status_report(progress: &PipelineProgress) -> String {
+    let mut report = format!("{}\n", progress.overall());
+    for (stage, state) in progress.stages() {
+        report.push_str(&format!("{}\t{}\n", stage, state));
+    }
+    report
+}
+
+/// Whether `path` lives in the pipelines tree, which no driver backs
+pub fn is_pipeline_path(path: &Path) -> bool {
+    path.starts_with(PIPELINES_ROOT)
+}
+
+/// Whether `path` is a spec dropped directly into `/proc/pipelines`
+pub fn is_spec_path(path: &Path) -> bool {
+    path.parent() == Some(Path::new(PIPELINES_ROOT))
+        && matches!(path.extension().and_then(|ext| ext.to_str()), Some("toml" | "yaml" | "yml"))
+}
+
+/// Parse a spec by its file extension
+pub fn parse_spec(path: &Path, text: &str) -> Result<PipelineSpec> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => PipelineSpec::from_yaml(text),
+        _ => PipelineSpec::from_toml(text),
+    }
+}
@@ -0,0 +1,51 @@
+//! Virtual files under `/proc/gnos`, rendered on demand from live daemon state.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use bytes::Bytes;
+
+pub const PROC_ROOT: &str = "/proc/gnos";
+
+type Generator = Box<dyn Fn() -> String + Send + Sync>;
+
+#[derive(Default)]
+pub struct ProcFs {
+    files: RwLock<BTreeMap<String, Generator>>,
+}
+
+impl ProcFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Register a file generator and return the path it is served at
+    pub fn register<F>(&self, name: &str, generator: F) -> PathBuf
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.files.write().unwrap().insert(name.to_string(), Box::new(generator));
+        Path::new(PROC_ROOT).join(name)
+    }
+    
+    /// Drop a file generator and return the path it was served at
+    pub fn unregister(&self, name: &str) -> Option<PathBuf> {
+        self.files.write().unwrap().remove(name).map(drop)?;
+        Some(Path::new(PROC_ROOT).join(name))
+    }
+    
+    pub fn contains(&self, path: &Path) -> bool {
+        self.name_of(path).is_some_and(|name| self.files.read().unwrap().contains_key(name))
+    }
+    
+    pub fn render(&self, path: &Path) -> Option<Bytes> {
+        let files = self.files.read().unwrap();
+        let generator = files.get(self.name_of(path)?)?;
+        Some(Bytes::from(generator()))
+    }
+    
+    fn name_of<'a>(&self, path: &'a Path) -> Option<&'a str> {
+        path.strip_prefix(PROC_ROOT).ok()?.to_str()
+    }
+}
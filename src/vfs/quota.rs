@@ -0,0 +1,419 @@
+//! Per-prefix quotas on bytes and object count
+//!
+//! The VFS keeps the size of every object it has seen under a quota prefix,
+//! from listings, metadata and its own writes and deletes, whichever driver
+//! holds it, and refuses commits that would take a quota past its limit.
+//! Usage is seeded by walking each prefix at mount; objects changed outside
+//! the mount are picked up the next time they are listed or stat'ed.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tracing::{debug, warn};
+
+use crate::config::QuotaRule;
+use crate::drivers::DriverRegistry;
+use crate::vfs::path::join_name;
+use crate::{GnosError, Result};
+
+/// What a quota prefix holds
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub bytes: u64,
+    pub objects: u64,
+}
+
+/// Block and file counts for `statfs`, zero where unlimited
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuotaStats {
+    pub block_size: u32,
+    pub blocks: u64,
+    pub blocks_free: u64,
+    pub files: u64,
+    pub files_free: u64,
+}
+
+#[derive(Default)]
+struct State {
+    /// Known object sizes under any quota prefix
+    objects: HashMap<PathBuf, u64>,
+    /// Running totals, one per rule
+    usage: Vec<Usage>,
+}
+
+pub struct QuotaTable {
+    /// Longest prefix first
+    rules: Vec<QuotaRule>,
+    state: Mutex<State>,
+}
+
+impl QuotaTable {
+    pub fn new(mut rules: Vec<QuotaRule>) -> Self {
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.prefix.len()));
+        let usage = vec![Usage::default(); rules.len()];
+        Self {
+            rules,
+            state: Mutex::new(State { objects: HashMap::new(), usage }),
+        }
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    
+    /// Record an object's size as seen in a listing or its metadata
+    pub fn observe(&self, path: &Path, size: u64) {
+        if self.matching(path).next().is_some() {
+            self.set(&mut self.state.lock().unwrap(), path, Some(size));
+        }
+    }
+    
+    /// Drop a deleted path and everything below it
+    pub fn forget(&self, path: &Path) {
+        self.record(&[(path.to_path_buf(), None)]);
+    }
+    
+    /// Refuse `changes` (new sizes, `None` for deletes) if they would take
+    /// any quota past its limit; changes that shrink usage always pass
+    pub fn check(&self, changes: &[(PathBuf, Option<u64>)]) -> Result<()> {
+        let state = self.state.lock().unwrap();
+        let mut sizes: HashMap<&Path, Option<u64>> = HashMap::new();
+        let mut deltas = vec![(0i128, 0i128); self.rules.len()];
+        
+        for (path, size) in changes {
+            let (path, size) = (path.as_path(), *size);
+            let old = sizes.get(path).copied().unwrap_or_else(|| state.objects.get(path).copied());
+            sizes.insert(path, size);
+            for rule in self.matching(path) {
+                deltas[rule].0 += size.unwrap_or(0) as i128 - old.unwrap_or(0) as i128;
+                deltas[rule].1 += size.is_some() as i128 - old.is_some() as i128;
+            }
+        }
+        
+        for (rule, (bytes, objects)) in deltas.into_iter().enumerate() {
+            let QuotaRule { prefix, max_bytes, max_objects } = &self.rules[rule];
+            let usage = state.usage[rule];
+            if let Some(limit) = *max_bytes {
+                let after = usage.bytes as i128 + bytes;
+                if bytes > 0 && after > limit as i128 {
+                    return Err(GnosError::QuotaExceeded(format!(
+                        "{} would hold {} bytes, over its {} byte quota", prefix, after, limit
+                    )));
+                }
+            }
+            if let Some(limit) = *max_objects {
+                let after = usage.objects as i128 + objects;
+                if objects > 0 && after > limit as i128 {
+                    return Err(GnosError::QuotaExceeded(format!(
+                        "{} would hold {} objects, over its {} object quota", prefix, after, limit
+                    )));
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Account for changes that were applied; a delete covers everything below its path
+    pub fn record(&self, changes: &[(PathBuf, Option<u64>)]) {
+        let mut state = self.state.lock().unwrap();
+        for (path, size) in changes {
+            match *size {
+                Some(_) => self.set(&mut state, path, *size),
+                None => {
+                    let gone: Vec<PathBuf> = state.objects.keys()
+                        .filter(|known| known.starts_with(path))
+                        .cloned()
+                        .collect();
+                    for known in gone {
+                        self.set(&mut state, &known, None);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Limits and usage of the innermost quota covering `path`
+    pub fn stats(&self, path: &Path) -> Option<QuotaStats> {
+        let rule = self.matching(path).next()?;
+        let QuotaRule { max_bytes, max_objects, .. } = &self.rules[rule];
+        let usage = self.state.lock().unwrap().usage[rule];
+        
+        const BLOCK: u64 = 4096;
+        let blocks = max_bytes.map_or(0, |limit| limit / BLOCK);
+        Some(QuotaStats {
+            block_size: BLOCK as u32,
+            blocks,
+            blocks_free: blocks.saturating_sub(usage.bytes.div_ceil(BLOCK)),
+            files: max_objects.unwrap_or(0),
+            files_free: max_objects.map_or(0, |limit| limit.saturating_sub(usage.objects)),
+        })
+    }
+    
+    /// Plain-text view for `/proc/gnos/quota`
+    pub fn status_report(&self) -> String {
+        let state = self.state.lock().unwrap();
+        
+        let mut report = format!("quotas: {}\n", self.rules.len());
+        for (rule, usage) in self.rules.iter().zip(&state.usage) {
+            let limit = |limit: Option<u64>| limit.map_or_else(|| "unlimited".to_string(), |limit| limit.to_string());
+            report.push_str(&format!("{}\t{}/{} bytes\t{}/{} objects\n",
+                                     rule.prefix, usage.bytes, limit(rule.max_bytes),
+                                     usage.objects, limit(rule.max_objects)));
+        }
+        report
+    }
+    
+    /// Count what every quota prefix already holds by walking it
+    pub async fn scan(&self, driver_registry: &DriverRegistry, max_depth: usize) {
+        for prefix in self.rules.iter().map(|rule| PathBuf::from(&rule.prefix)) {
+            let mut level = vec![prefix.clone()];
+            for _ in 0..=max_depth {
+                let mut next = Vec::new();
+                for dir in level {
+                    match self.scan_dir(driver_registry, &dir).await {
+                        Ok(dirs) => next.extend(dirs),
+                        Err(e) => warn!("❌ Counting quota usage of {} failed: {}", dir.display(), e),
+                    }
+                }
+                if next.is_empty() {
+                    break;
+                }
+                level = next;
+            }
+            debug!("Scanned quota prefix {}", prefix.display());
+        }
+    }
+    
+    /// Record the objects in `dir` and return its subdirectories
+    async fn scan_dir(&self, driver_registry: &DriverRegistry, dir: &Path) -> Result<Vec<PathBuf>> {
+        let driver = driver_registry.get_driver(dir)
+            .ok_or_else(|| GnosError::PathNotFound(dir.display().to_string()))?;
+        
+        let mut dirs = Vec::new();
+        for (name, metadata) in driver.list_with_metadata(dir).await? {
+            let Ok(path) = join_name(dir, OsStr::new(&name)) else {
+                continue;
+            };
+            let metadata = match metadata {
+                Some(metadata) => metadata,
+                None => driver.metadata(&path).await?,
+            };
+            if metadata.is_directory {
+                dirs.push(path);
+            } else {
+                self.observe(&path, metadata.size);
+            }
+        }
+        Ok(dirs)
+    }
+    
+    /// Rules covering `path`, innermost first
+    fn matching<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = usize> + 'a {
+        self.rules.iter()
+            .enumerate()
+            .filter(move |(_, rule)| path.starts_with(&rule.prefix))
+            .map(|(index, _)| index)
+    }
+    
+    fn set(&self, state: &mut State, path: &Path, size: Option<u64>) {
+        let old = match size {
+            Some(size) => state.objects.insert(path.to_path_buf(), size),
+            None => state.objects.remove(path),
+        };
+        if old.is_none() && size.is_none() {
+            return;
+        }
+        for rule in self.matching(path) {
+            let usage = &mut state.usage[rule];
+            usage.bytes = (usage.bytes + size.unwrap_or(0)).saturating_sub(old.unwrap_or(0));
+            usage.objects = (usage.objects + size.is_some() as u64).saturating_sub(old.is_some() as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    
+    use crate::config::DriverConfig;
+    use crate::drivers::{GnosDriver, PartPolicy, ResourceMetadata};
+    use crate::security::{CapabilityManager, SecurityConfig};
+    use crate::vfs::core::VfsCore;
+    
+    fn rule(prefix: &str, max_bytes: Option<u64>, max_objects: Option<u64>) -> QuotaRule {
+        QuotaRule { prefix: prefix.to_string(), max_bytes, max_objects }
+    }
+    
+    fn change(path: &str, size: Option<u64>) -> (PathBuf, Option<u64>) {
+        (PathBuf::from(path), size)
+    }
+    
+    fn usage(quotas: &QuotaTable, prefix: &str) -> (u64, u64) {
+        let rule = quotas.rules.iter().position(|rule| rule.prefix == prefix).unwrap();
+        let usage = quotas.state.lock().unwrap().usage[rule];
+        (usage.bytes, usage.objects)
+    }
+    
+    #[test]
+    fn nested_prefixes_are_all_charged_innermost_first() {
+        let quotas = QuotaTable::new(vec![rule("/data", None, None), rule("/data/team", None, None), rule("/other", None, None)]);
+        let matching = |path: &str| -> Vec<&str> {
+            quotas.matching(Path::new(path)).map(|rule| quotas.rules[rule].prefix.as_str()).collect()
+        };
+        assert_eq!(matching("/data/team/a"), ["/data/team", "/data"]);
+        // Prefixes match whole components
+        assert_eq!(matching("/data/teammate/a"), ["/data"]);
+        assert!(matching("/elsewhere/a").is_empty());
+        
+        quotas.observe(Path::new("/data/team/a"), 10);
+        quotas.observe(Path::new("/data/b"), 5);
+        quotas.observe(Path::new("/elsewhere/c"), 7);
+        assert_eq!(usage(&quotas, "/data/team"), (10, 1));
+        assert_eq!(usage(&quotas, "/data"), (15, 2));
+        assert_eq!(usage(&quotas, "/other"), (0, 0));
+        assert!(!quotas.state.lock().unwrap().objects.contains_key(Path::new("/elsewhere/c")));
+    }
+    
+    #[test]
+    fn growth_past_a_limit_is_edquot() {
+        let quotas = QuotaTable::new(vec![rule("/q", Some(100), Some(2))]);
+        quotas.observe(Path::new("/q/a"), 60);
+        
+        assert!(quotas.check(&[change("/q/b", Some(40))]).is_ok());
+        let refused = quotas.check(&[change("/q/b", Some(41))]).unwrap_err();
+        assert!(matches!(refused, GnosError::QuotaExceeded(_)));
+        assert_eq!(refused.errno(), libc::EDQUOT);
+        
+        // Rewriting an object only counts what it grows by
+        assert!(quotas.check(&[change("/q/a", Some(100))]).is_ok());
+        assert!(quotas.check(&[change("/q/a", Some(101))]).is_err());
+        
+        quotas.observe(Path::new("/q/b"), 0);
+        assert_eq!(quotas.check(&[change("/q/c", Some(0))]).unwrap_err().errno(), libc::EDQUOT);
+        // Deletes in the same batch make room
+        assert!(quotas.check(&[change("/q/b", None), change("/q/c", Some(40))]).is_ok());
+    }
+    
+    #[test]
+    fn shrinking_passes_even_over_the_limit() {
+        let quotas = QuotaTable::new(vec![rule("/q", Some(100), Some(1))]);
+        quotas.observe(Path::new("/q/a"), 500);
+        quotas.observe(Path::new("/q/b"), 1);
+        assert!(quotas.check(&[change("/q/a", Some(400))]).is_ok());
+        assert!(quotas.check(&[change("/q/b", None)]).is_ok());
+        assert!(quotas.check(&[change("/q/b", Some(2))]).is_err());
+    }
+    
+    #[test]
+    fn deletes_release_everything_below_them() {
+        let quotas = QuotaTable::new(vec![rule("/q", None, None)]);
+        quotas.observe(Path::new("/q/dir/a"), 1);
+        quotas.observe(Path::new("/q/dir/sub/b"), 2);
+        quotas.observe(Path::new("/q/dirt"), 4);
+        quotas.forget(Path::new("/q/dir"));
+        assert_eq!(usage(&quotas, "/q"), (4, 1));
+    }
+    
+    const PARTS_ROOT: &str = "/parts";
+    
+    /// Takes uploads in 4-byte parts and remembers which parts arrived
+    #[derive(Default)]
+    struct PartsDriver {
+        parts: Mutex<Vec<(PathBuf, u64)>>,
+    }
+    
+    #[async_trait]
+    impl GnosDriver for PartsDriver {
+        async fn read(&self, path: &Path) -> Result<Bytes> {
+            Err(GnosError::PathNotFound(path.display().to_string()))
+        }
+        
+        async fn write(&self, _path: &Path, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+        
+        fn supports_parts(&self, _path: &Path) -> bool {
+            true
+        }
+        
+        fn part_policy(&self, _path: &Path) -> PartPolicy {
+            PartPolicy { threshold: 4, part_size: 4, concurrency: 1 }
+        }
+        
+        async fn begin_parts(&self, _path: &Path) -> Result<String> {
+            Ok("upload".to_string())
+        }
+        
+        async fn write_part(&self, path: &Path, _upload_id: &str, part: u64, _data: &[u8]) -> Result<()> {
+            self.parts.lock().unwrap().push((path.to_path_buf(), part));
+            Ok(())
+        }
+        
+        async fn complete_parts(&self, _path: &Path, _upload_id: &str, _parts: u64) -> Result<()> {
+            Ok(())
+        }
+        
+        async fn list(&self, _path: &Path) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+        
+        async fn exists(&self, path: &Path) -> Result<bool> {
+            Ok(path == Path::new(PARTS_ROOT))
+        }
+        
+        async fn metadata(&self, path: &Path) -> Result<ResourceMetadata> {
+            Err(GnosError::PathNotFound(path.display().to_string()))
+        }
+        
+        fn name(&self) -> &'static str {
+            "Parts Driver"
+        }
+        
+        fn supports(&self, path: &Path) -> bool {
+            path.starts_with(PARTS_ROOT)
+        }
+        
+        fn prefixes(&self) -> Vec<PathBuf> {
+            vec![PathBuf::from(PARTS_ROOT)]
+        }
+    }
+    
+    #[tokio::test]
+    async fn uploads_in_parts_stop_at_the_part_that_crosses_a_quota() {
+        let mut drivers = DriverConfig::default();
+        drivers.ai.enabled = false;
+        drivers.cloud.enabled = false;
+        drivers.http.enabled = false;
+        drivers.sensors.enabled = false;
+        let driver = Arc::new(PartsDriver::default());
+        let registry = DriverRegistry::new(drivers).await.unwrap().with_driver("parts", driver.clone());
+        let mut core = VfsCore::new(Arc::new(registry), Arc::new(CapabilityManager::new(SecurityConfig::default())));
+        core.quotas = Arc::new(QuotaTable::new(vec![rule(PARTS_ROOT, Some(10), None)]));
+        let root = core.inode_manager.get_or_create(Path::new(PARTS_ROOT), true);
+        
+        let (_, mut file) = core.create(root, OsStr::new("big")).await.unwrap();
+        core.write(&mut file, 0, b"0123").await.unwrap();
+        core.write(&mut file, 4, b"4567").await.unwrap();
+        let refused = core.write(&mut file, 8, b"89ab").await.unwrap_err();
+        assert_eq!(refused.errno(), libc::EDQUOT);
+        let big = PathBuf::from("/parts/big");
+        assert_eq!(*driver.parts.lock().unwrap(), [(big.clone(), 0), (big, 1)]);
+        
+        // Parts that fit are sent as they fill and counted once assembled
+        driver.parts.lock().unwrap().clear();
+        let (_, mut file) = core.create(root, OsStr::new("small")).await.unwrap();
+        core.write(&mut file, 0, b"0123456789").await.unwrap();
+        assert_eq!(driver.parts.lock().unwrap().len(), 2);
+        core.commit(&mut file).await.unwrap();
+        assert_eq!(driver.parts.lock().unwrap().len(), 3);
+        assert_eq!(core.quotas.stats(Path::new("/parts/small")).unwrap().blocks_free, 0);
+        assert_eq!(usage(&core.quotas, PARTS_ROOT), (10, 1));
+    }
+}
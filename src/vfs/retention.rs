@@ -0,0 +1,182 @@
+//! Append-only and write-once path classes
+//!
+//! `[[retention.rules]]` marks namespace prefixes whose objects can't be
+//! changed through the mount, for audit logs and compliance storage. Under
+//! `append_only` a file can be created and extended, but bytes already
+//! written can't be rewritten, truncated away or deleted; under `write_once`
+//! a file is written once and then left alone. Either protection can be
+//! limited to `retain_seconds` after the object's last change, after which
+//! the object behaves like any other.
+//!
+//! The VFS checks these rules before a change is staged, journaled or sent
+//! to a driver, so transactions, write-back and every frontend are covered;
+//! changes made against the backend directly are out of its reach. A
+//! protected file reports no write bits where it can't be written and the
+//! BSD `UF_APPEND`/`UF_IMMUTABLE` flags, and names its protection in the
+//! `user.gnos.retention` xattr.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::config::{RetentionMode, RetentionRule};
+use crate::vfs::path::is_within;
+use crate::GnosError;
+
+/// Extended attribute naming the protection on a file
+pub const RETENTION_XATTR: &str = "user.gnos.retention";
+
+/// BSD file flags, as `chflags` and `ls -lO` show them
+const UF_IMMUTABLE: u32 = 0x0000_0002;
+const UF_APPEND: u32 = 0x0000_0004;
+
+/// The protection in force on one object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Protection {
+    pub mode: RetentionMode,
+    /// When the protection lapses; `None` holds for good
+    pub until: Option<SystemTime>,
+}
+
+impl Protection {
+    /// Flags for `getattr`
+    pub fn flags(&self) -> u32 {
+        match self.mode {
+            RetentionMode::AppendOnly => UF_APPEND,
+            RetentionMode::WriteOnce => UF_IMMUTABLE,
+        }
+    }
+    
+    /// Mode bits left once the protection is applied: write-once objects
+    /// can't be written at all, append-only ones can still be opened to extend
+    pub fn mask(&self, perm: u16) -> u16 {
+        match self.mode {
+            RetentionMode::AppendOnly => perm,
+            RetentionMode::WriteOnce => perm & !0o222,
+        }
+    }
+    
+    /// The error a forbidden change to `path` fails with; `change` is e.g. "deleted"
+    pub fn refusal(&self, path: &Path, change: &str) -> GnosError {
+        GnosError::Immutable(format!("{} is {} and can't be {}", path.display(), self.describe(), change))
+    }
+    
+    /// e.g. `write-once until 2027-01-01T00:00:00+00:00`
+    pub fn describe(&self) -> String {
+        let mode = match self.mode {
+            RetentionMode::AppendOnly => "append-only",
+            RetentionMode::WriteOnce => "write-once",
+        };
+        match self.until {
+            Some(until) => format!("{} until {}", mode, chrono::DateTime::<chrono::Utc>::from(until).to_rfc3339()),
+            None => mode.to_string(),
+        }
+    }
+}
+
+pub struct RetentionTable {
+    /// Longest prefix first, so the innermost rule wins
+    rules: Vec<RetentionRule>,
+}
+
+impl RetentionTable {
+    pub fn new(mut rules: Vec<RetentionRule>) -> Self {
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.prefix.len()));
+        Self { rules }
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    
+    /// Whether any rule covers `path`
+    pub fn covers(&self, path: &Path) -> bool {
+        self.rule_for(path).is_some()
+    }
+    
+    /// Whether removing `path` would take a protected prefix with it
+    pub fn contains_protected(&self, path: &Path) -> bool {
+        self.rules.iter().any(|rule| is_within(Path::new(&rule.prefix), path))
+    }
+    
+    fn rule_for(&self, path: &Path) -> Option<&RetentionRule> {
+        self.rules.iter().find(|rule| is_within(path, Path::new(&rule.prefix)))
+    }
+    
+    /// Protection on an object at `path` last changed at `modified`, or
+    /// `None` if no rule covers it or its window has passed
+    pub fn protection(&self, path: &Path, modified: SystemTime) -> Option<Protection> {
+        self.protection_at(path, modified, SystemTime::now())
+    }
+    
+    /// Protection as of `now`; a window lapses at the instant it ends
+    fn protection_at(&self, path: &Path, modified: SystemTime, now: SystemTime) -> Option<Protection> {
+        let rule = self.rule_for(path)?;
+        let until = rule.retain_seconds.map(|seconds| modified + Duration::from_secs(seconds));
+        if until.is_some_and(|until| now >= until) {
+            return None;
+        }
+        Some(Protection { mode: rule.mode, until })
+    }
+    
+    /// Plain-text view for `/proc/gnos/retention`
+    pub fn status_report(&self) -> String {
+        let mut report = format!("rules: {}\n", self.rules.len());
+        for rule in &self.rules {
+            let window = rule.retain_seconds.map_or_else(|| "forever".to_string(), |seconds| format!("{}s", seconds));
+            report.push_str(&format!("{}\t{:?}\t{}\n", rule.prefix, rule.mode, window));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+    
+    fn rule(prefix: &str, mode: RetentionMode, retain_seconds: Option<u64>) -> RetentionRule {
+        RetentionRule { prefix: prefix.to_string(), mode, retain_seconds }
+    }
+    
+    #[test]
+    fn the_longest_matching_prefix_wins_whatever_the_config_order() {
+        let table = RetentionTable::new(vec![
+            rule("/audit", RetentionMode::AppendOnly, None),
+            rule("/audit/sealed", RetentionMode::WriteOnce, None),
+            rule("/audit/sealed/drafts", RetentionMode::AppendOnly, Some(60)),
+        ]);
+        let mode = |path: &str| table.protection(Path::new(path), SystemTime::now()).map(|protection| protection.mode);
+        
+        assert_eq!(mode("/audit/log"), Some(RetentionMode::AppendOnly));
+        assert_eq!(mode("/audit/sealed/2026.tar"), Some(RetentionMode::WriteOnce));
+        assert_eq!(mode("/audit/sealed/drafts/next"), Some(RetentionMode::AppendOnly));
+        // Prefixes match whole components
+        assert_eq!(mode("/audit/sealed-copy"), Some(RetentionMode::AppendOnly));
+        assert_eq!(mode("/auditing/log"), None);
+        
+        assert!(table.covers(Path::new("/audit")));
+        assert!(!table.covers(Path::new("/")));
+        assert!(table.contains_protected(Path::new("/")));
+        assert!(!table.contains_protected(Path::new("/auditing")));
+    }
+    
+    #[test]
+    fn protection_lapses_exactly_when_its_window_ends() {
+        let table = RetentionTable::new(vec![rule("/logs", RetentionMode::WriteOnce, Some(3600))]);
+        let path = Path::new("/logs/app.log");
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let until = modified + Duration::from_secs(3600);
+        
+        let held = table.protection_at(path, modified, until - Duration::from_nanos(1)).unwrap();
+        assert_eq!(held, Protection { mode: RetentionMode::WriteOnce, until: Some(until) });
+        assert_eq!(table.protection_at(path, modified, until), None);
+        assert_eq!(table.protection_at(path, modified, until + Duration::from_secs(1)), None);
+    }
+    
+    #[test]
+    fn protection_without_a_window_never_lapses() {
+        let table = RetentionTable::new(vec![rule("/logs", RetentionMode::AppendOnly, None)]);
+        let protection = table.protection_at(Path::new("/logs/a"), UNIX_EPOCH, SystemTime::now() + Duration::from_secs(1 << 40));
+        assert_eq!(protection, Some(Protection { mode: RetentionMode::AppendOnly, until: None }));
+    }
+}
@@ -0,0 +1,90 @@
+//! `/proc/search`
+//!
+//! Writing a query to `/proc/search/query` starts a search and creates a
+//! numbered result directory next to it, which fills with symbolic links
+//! to the matches as they are found:
+//!
+//! ```text
+//! echo 'scope=/cloud/aws name=*.log content=ERROR' > /mnt/gnos/proc/search/query
+//! ls -l /mnt/gnos/proc/search/1/
+//! ```
+//!
+//! Reading `query` lists every search with its state and match count.
+//! Links are relative, so they resolve wherever the namespace is mounted.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+
+pub const SEARCH_ROOT: &str = "/proc/search";
+pub const SEARCH_QUERY: &str = "/proc/search/query";
+
+#[derive(Debug, Clone)]
+struct SearchStatus {
+    query: String,
+    state: String,
+    matches: usize,
+}
+
+/// Searches started through the mount
+#[derive(Default)]
+pub struct SearchTable {
+    next_id: AtomicU64,
+    searches: DashMap<u64, SearchStatus>,
+}
+
+impl SearchTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Register a running search and return its result directory
+    pub fn start(&self, query: &str) -> (u64, PathBuf) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.searches.insert(id, SearchStatus {
+            query: query.trim().to_string(),
+            state: "running".to_string(),
+            matches: 0,
+        });
+        (id, Path::new(SEARCH_ROOT).join(id.to_string()))
+    }
+    
+    pub fn matched(&self, id: u64) {
+        if let Some(mut status) = self.searches.get_mut(&id) {
+            status.matches += 1;
+        }
+    }
+    
+    pub fn finish(&self, id: u64, state: String) {
+        if let Some(mut status) = self.searches.get_mut(&id) {
+            status.state = state;
+        }
+    }
+    
+    pub fn status_report(&self) -> String {
+        let mut ids: Vec<u64> = self.searches.iter().map(|entry| *entry.key()).collect();
+        ids.sort_unstable();
+        
+        let mut report = String::new();
+        for id in ids {
+            if let Some(status) = self.searches.get(&id) {
+                report.push_str(&format!("{}\t{}\t{} matches\t{}\n", id, status.state, status.matches, status.query));
+            }
+        }
+        report
+    }
+}
+
+/// Whether `path` lives in the search tree, which no driver backs
+pub fn is_search_path(path: &Path) -> bool {
+    path.starts_with(SEARCH_ROOT)
+}
+
+/// Result entry for a match: its path flattened into one name, and a link
+/// target climbing out of `/proc/search/<id>` back to the namespace root
+pub fn result_entry(match_path: &Path) -> (String, PathBuf) {
+    let relative = match_path.strip_prefix("/").unwrap_or(match_path);
+    let name = relative.to_string_lossy().replace('%', "%25").replace('/', "%2F");
+    (name, Path::new("../../..").join(relative))
+}
@@ -0,0 +1,170 @@
+//! Virtual shards of large directories
+//!
+//! A directory listing at least `vfs.shard_threshold` entries gets a
+//! generated `.shards` child holding one directory per name prefix, each
+//! filled with symbolic links to the entries it covers:
+//!
+//! ```text
+//! ls /mnt/gnos/cloud/aws/s3/logs/.shards/
+//! ls -l /mnt/gnos/cloud/aws/s3/logs/.shards/ab/
+//! ```
+//!
+//! Interactive tools can browse a few thousand names at a time this way,
+//! while the directory itself still lists every entry. Prefixes are the
+//! first `shard_prefix_len` characters of a name, lowercased. Links are
+//! relative, so they resolve wherever the namespace is mounted, and no
+//! driver ever sees a `.shards` path.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+
+use crate::vfs::inode::InodeManager;
+
+pub const SHARDS_DIR: &str = ".shards";
+
+/// Where a path falls in a shard view
+enum Place {
+    /// `<dir>/.shards`
+    Root(PathBuf),
+    /// `<dir>/.shards/<prefix>`
+    Shard(PathBuf, String),
+    /// `<dir>/.shards/<prefix>/<name>`
+    Link(PathBuf, String),
+}
+
+/// Sharding settings for every large directory of the mount
+pub struct ShardView {
+    threshold: usize,
+    prefix_len: usize,
+}
+
+impl ShardView {
+    pub fn new(threshold: usize, prefix_len: usize) -> Self {
+        Self { threshold, prefix_len: prefix_len.max(1) }
+    }
+    
+    pub fn disabled() -> Self {
+        Self::new(0, 1)
+    }
+    
+    pub fn enabled(&self) -> bool {
+        self.threshold > 0
+    }
+    
+    /// Whether `path` is generated by the view, so no driver backs it
+    pub fn contains(&self, path: &Path) -> bool {
+        self.enabled() && path.components().any(|component| component.as_os_str() == SHARDS_DIR)
+    }
+    
+    /// Shard a name belongs to; names starting `.` or `..` go to `_` or `__`,
+    /// which can't be mistaken for the directory itself or its parent
+    pub fn shard_of(&self, name: &str) -> String {
+        let shard: String = name.chars().take(self.prefix_len).flat_map(char::to_lowercase).collect();
+        if shard == "." || shard == ".." {
+            shard.replace('.', "_")
+        } else {
+            shard
+        }
+    }
+    
+    /// Add or drop the `.shards` child of `dir` after it listed `entries` names
+    pub fn refresh(&self, inodes: &InodeManager, dir: &Path, entries: usize) {
+        if !self.enabled() || self.contains(dir) {
+            return;
+        }
+        let root = dir.join(SHARDS_DIR);
+        if entries >= self.threshold {
+            if inodes.find_by_path(&root).is_none() {
+                debug!("Sharding {} ({} entries)", dir.display(), entries);
+                inodes.get_or_create(&root, true);
+            }
+        } else if inodes.find_by_path(&root).is_some() {
+            debug!("No longer sharding {} ({} entries)", dir.display(), entries);
+            inodes.remove(&root);
+        }
+    }
+    
+    /// Bring a generated directory in line with the entries of the
+    /// directory it shards; a link is filled in through its shard
+    pub fn populate(&self, inodes: &InodeManager, path: &Path) {
+        if !self.enabled() {
+            return;
+        }
+        let (dir, prefix) = match place(path) {
+            Some(Place::Root(dir)) => (dir, None),
+            Some(Place::Shard(dir, prefix) | Place::Link(dir, prefix)) => (dir, Some(prefix)),
+            None => return,
+        };
+        let root = dir.join(SHARDS_DIR);
+        if inodes.find_by_path(&root).is_none() {
+            return;
+        }
+        
+        let names = inodes.children(&dir).into_iter()
+            .filter_map(|entry| entry.path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .filter(|name| name != SHARDS_DIR);
+        match prefix {
+            None => {
+                let shards: BTreeSet<String> = names.map(|name| self.shard_of(&name)).collect();
+                sync_children(inodes, &root, &shards, |path| {
+                    inodes.get_or_create(path, true);
+                });
+            }
+            Some(prefix) => {
+                let shard = root.join(&prefix);
+                if inodes.find_by_path(&shard).is_none() {
+                    return;
+                }
+                let members: BTreeSet<String> = names.filter(|name| self.shard_of(name) == prefix).collect();
+                sync_children(inodes, &shard, &members, |path| {
+                    if let Some(name) = path.file_name() {
+                        inodes.create_symlink(path, Path::new("../..").join(name));
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Make the children of `dir` exactly `names`, creating missing ones with `create`
+fn sync_children(inodes: &InodeManager, dir: &Path, names: &BTreeSet<String>, create: impl Fn(&Path)) {
+    for stale in inodes.children(dir) {
+        let kept = stale.path.file_name().is_some_and(|name| names.contains(name.to_string_lossy().as_ref()));
+        if !kept {
+            inodes.remove(&stale.path);
+        }
+    }
+    for name in names {
+        let path = dir.join(name);
+        if inodes.find_by_path(&path).is_none() {
+            create(&path);
+        }
+    }
+}
+
+/// Split a path at its first `.shards` component
+fn place(path: &Path) -> Option<Place> {
+    let mut dir = PathBuf::new();
+    let mut components = path.components();
+    for component in components.by_ref() {
+        if component.as_os_str() == SHARDS_DIR {
+            break;
+        }
+        dir.push(component);
+    }
+    if dir == path {
+        return None;
+    }
+    
+    let rest: Vec<String> = components
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    match rest.as_slice() {
+        [] => Some(Place::Root(dir)),
+        [prefix] => Some(Place::Shard(dir, prefix.clone())),
+        [prefix, _] => Some(Place::Link(dir, prefix.clone())),
+        _ => None,
+    }
+}
@@ -0,0 +1,122 @@
+//! Path templates
+//!
+//! A template such as `/net/http/{host}/{version}/{endpoint}` lets lookups
+//! of names nobody listed succeed: each `{name}` component matches any one
+//! name, which is materialized on lookup — as a directory while template
+//! components remain, as a file at the end — once the owning driver accepts
+//! the values bound so far. `cat /net/http/api.example.com/v1/status` then
+//! works without declaring the endpoint anywhere.
+//!
+//! The kernel looks a path up one name at a time without saying whether it
+//! expects a directory, so each template has a fixed depth; endpoints of
+//! different depths need one template each. The first matching template
+//! decides.
+
+use std::ffi::OsString;
+use std::path::{Component, Path};
+
+use crate::drivers::PathParams;
+use crate::{GnosError, Result};
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(OsString),
+    Param(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PathTemplate {
+    segments: Vec<Segment>,
+}
+
+/// How a path matched a template
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub params: PathParams,
+    /// More template components follow, so the path is a directory
+    pub is_dir: bool,
+}
+
+impl PathTemplate {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let path = Path::new(pattern);
+        if !path.is_absolute() {
+            return Err(GnosError::InvalidPath(format!("path template must be absolute: {}", pattern)));
+        }
+        
+        let mut segments = Vec::new();
+        for component in path.components().skip(1) {
+            let Component::Normal(name) = component else {
+                return Err(GnosError::InvalidPath(format!("path template can't use . or ..: {}", pattern)));
+            };
+            let text = name.to_string_lossy();
+            let segment = match text.strip_prefix('{').and_then(|rest| rest.strip_suffix('}')) {
+                Some(param) if !param.is_empty() => Segment::Param(param.to_string()),
+                _ if text.contains(['{', '}']) => {
+                    return Err(GnosError::InvalidPath(format!(
+                        "path template parameters must be whole components: {}", pattern
+                    )));
+                }
+                _ => Segment::Literal(name.to_os_string()),
+            };
+            segments.push(segment);
+        }
+        
+        if !segments.iter().any(|segment| matches!(segment, Segment::Param(_))) {
+            return Err(GnosError::InvalidPath(format!("path template has no parameters: {}", pattern)));
+        }
+        Ok(Self { segments })
+    }
+    
+    /// Values `path` binds, if it is the template or a leading part of it
+    /// that ends in a parameter or below one
+    pub fn bind(&self, path: &Path) -> Option<Binding> {
+        let components: Vec<_> = path.components().skip(1).collect();
+        if components.is_empty() || components.len() > self.segments.len() {
+            return None;
+        }
+        
+        let mut params = PathParams::new();
+        for (component, segment) in components.iter().zip(&self.segments) {
+            let Component::Normal(name) = component else {
+                return None;
+            };
+            match segment {
+                Segment::Literal(literal) if literal.as_os_str() == *name => {}
+                Segment::Literal(_) => return None,
+                Segment::Param(param) => {
+                    params.insert(param.clone(), name.to_string_lossy().into_owned());
+                }
+            }
+        }
+        
+        // Paths above the first parameter are plain namespace, not template
+        if params.is_empty() {
+            return None;
+        }
+        Some(Binding {
+            params,
+            is_dir: components.len() < self.segments.len(),
+        })
+    }
+}
+
+/// Templates in the order they were configured
+#[derive(Debug, Clone, Default)]
+pub struct TemplateSet {
+    templates: Vec<PathTemplate>,
+}
+
+impl TemplateSet {
+    pub fn new(templates: Vec<PathTemplate>) -> Self {
+        Self { templates }
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+    
+    pub fn bind(&self, path: &Path) -> Option<Binding> {
+        self.templates.iter().find_map(|template| template.bind(path))
+    }
+}
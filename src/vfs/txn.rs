@@ -0,0 +1,93 @@
+//! `/.gnos/txn` control file
+//!
+//! Transactions through the mount are scoped to a login session, so a
+//! shell script and the tools it runs share one:
+//!
+//! ```text
+//! echo begin > /mnt/gnos/.gnos/txn
+//! cp app.toml /mnt/gnos/cloud/aws/s3/cfg/app.toml
+//! echo "delete /cloud/aws/s3/cfg/app.toml.bak" > /mnt/gnos/.gnos/txn
+//! echo commit > /mnt/gnos/.gnos/txn
+//! cat /mnt/gnos/.gnos/txn
+//! ```
+//!
+//! While a session's transaction is open its writes are staged rather than
+//! sent to drivers, and reads still see the committed contents. `abort`
+//! discards the staged changes. Reading the file reports the session's open
+//! transaction or how the last one ended.
+
+use dashmap::DashMap;
+
+use crate::drivers::BatchOp;
+
+pub const TXN_CONTROL: &str = "/.gnos/txn";
+
+/// Staged changes and outcomes, per session
+#[derive(Default)]
+pub struct TxnTable {
+    open: DashMap<u32, Vec<BatchOp>>,
+    last: DashMap<u32, String>,
+}
+
+impl TxnTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn begin(&self, session: u32) -> bool {
+        if self.open.contains_key(&session) {
+            return false;
+        }
+        self.open.insert(session, Vec::new());
+        true
+    }
+    
+    pub fn is_open(&self, session: u32) -> bool {
+        self.open.contains_key(&session)
+    }
+    
+    /// Add a change to the session's transaction, if it has one open
+    pub fn stage(&self, session: u32, op: BatchOp) -> bool {
+        match self.open.get_mut(&session) {
+            Some(mut ops) => {
+                ops.push(op);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Close the session's transaction, returning what it staged
+    pub fn take(&self, session: u32) -> Option<Vec<BatchOp>> {
+        self.open.remove(&session).map(|(_, ops)| ops)
+    }
+    
+    pub fn finish(&self, session: u32, outcome: String) {
+        self.last.insert(session, outcome);
+    }
+    
+    /// What a read of the control file shows this session
+    pub fn status(&self, session: Option<u32>) -> String {
+        let Some(session) = session else {
+            return "unavailable: no session\n".to_string();
+        };
+        
+        if let Some(ops) = self.open.get(&session) {
+            let writes = ops.iter().filter(|op| matches!(op, BatchOp::Write { .. })).count();
+            let mut report = format!("open: {} writes, {} deletes\n", writes, ops.len() - writes);
+            for op in ops.iter() {
+                let kind = match op {
+                    BatchOp::Write { .. } => "write",
+                    BatchOp::Delete { .. } => "delete",
+                };
+                report.push_str(&format!("{}\t{}\n", kind, op.path().display()));
+            }
+            return report;
+        }
+        
+        match self.last.get(&session) {
+            Some(outcome) => format!("{}\n", outcome.value()),
+            None => "none\n".to_string(),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Namespace warming at mount time
+//!
+//! The first `ls -R` of a fresh mount would otherwise list every directory
+//! from its driver, one lookup at a time. The `warm_prefixes` in `[vfs]` are
+//! walked breadth-first in the background instead, `warm_concurrency`
+//! directories of a level at a time and down to `warm_max_depth` levels,
+//! and what each listing returns goes straight into the inode table and
+//! attr cache. Entries listed without metadata are stat'ed so the walk knows
+//! whether to descend. A failed listing is logged and skipped; a
+//! rate-limited one waits as the backend asks and is tried once more.
+//!
+//! `gnos warm <prefix>` does the same for a running mount from outside,
+//! walking it through the mount point.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, StreamExt};
+use tracing::{debug, warn};
+
+use crate::drivers::{DriverRegistry, ResourceMetadata};
+use crate::vfs::attr_cache::AttrCache;
+use crate::vfs::inode::InodeManager;
+use crate::vfs::path::join_name;
+use crate::{GnosError, Result};
+
+/// What a warm-up pass touched
+#[derive(Debug, Default, Clone)]
+pub struct WarmStats {
+    pub directories: usize,
+    pub entries: usize,
+    pub errors: usize,
+    pub elapsed: Duration,
+}
+
+/// Pre-lists driver namespaces into the inode table and attr cache so the
+/// first walk after mounting is served from memory
+pub struct Warmer {
+    driver_registry: Arc<DriverRegistry>,
+    inode_manager: Arc<InodeManager>,
+    attr_cache: Arc<AttrCache>,
+    concurrency: usize,
+    max_depth: usize,
+}
+
+impl Warmer {
+    pub fn new(
+        driver_registry: Arc<DriverRegistry>,
+        inode_manager: Arc<InodeManager>,
+        attr_cache: Arc<AttrCache>,
+        concurrency: usize,
+        max_depth: usize,
+    ) -> Self {
+        Self {
+            driver_registry,
+            inode_manager,
+            attr_cache,
+            concurrency: concurrency.max(1),
+            max_depth,
+        }
+    }
+    
+    /// Walk `prefix` breadth-first, listing each level's directories in parallel
+    pub async fn warm(&self, prefix: &Path) -> WarmStats {
+        let started = Instant::now();
+        let mut stats = WarmStats::default();
+        let mut level = vec![prefix.to_path_buf()];
+        
+        for depth in 0..=self.max_depth {
+            if level.is_empty() {
+                break;
+            }
+            
+            let listings: Vec<_> = stream::iter(std::mem::take(&mut level))
+                .map(|dir| async move {
+                    let mut result = self.list_dir(&dir).await;
+                    // Warming is in no hurry, so a rate-limited listing waits as asked and goes again
+                    if let Some(wait) = result.as_ref().err().and_then(GnosError::retry_after) {
+                        tokio::time::sleep(wait).await;
+                        result = self.list_dir(&dir).await;
+                    }
+                    (dir, result)
+                })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+            
+            for (dir, result) in listings {
+                match result {
+                    Ok(children) => {
+                        stats.directories += 1;
+                        stats.entries += children.len();
+                        if depth < self.max_depth {
+                            level.extend(children.into_iter().filter(|(_, is_dir)| *is_dir).map(|(path, _)| path));
+                        }
+                    }
+                    Err(e) => {
+                        stats.errors += 1;
+                        warn!("❌ Warming {} failed: {}", dir.display(), e);
+                    }
+                }
+            }
+        }
+        
+        stats.elapsed = started.elapsed();
+        debug!("Warmed {}: {:?}", prefix.display(), stats);
+        stats
+    }
+    
+    async fn list_dir(&self, dir: &Path) -> Result<Vec<(PathBuf, bool)>> {
+        let driver = self.driver_registry.get_driver(dir)
+            .ok_or_else(|| GnosError::PathNotFound(dir.display().to_string()))?;
+        let listing = driver.list_with_metadata(dir).await?;
+        
+        let mut children = Vec::with_capacity(listing.len());
+        for (name, metadata) in listing {
+            let Ok(path) = join_name(dir, OsStr::new(&name)) else {
+                continue;
+            };
+            
+            // A bare name doesn't say whether to descend, so ask the driver
+            let metadata = match metadata {
+                Some(metadata) => Some(metadata),
+                None => driver.metadata(&path).await.ok(),
+            };
+            
+            let is_dir = merge_entry(&self.inode_manager, &self.attr_cache, &path, metadata);
+            children.push((path, is_dir));
+        }
+        
+        Ok(children)
+    }
+}
+
+/// Record a listed entry in the inode table and attr cache, returning
+/// whether it is a directory
+pub(crate) fn merge_entry(
+    inode_manager: &InodeManager,
+    attr_cache: &AttrCache,
+    path: &Path,
+    metadata: Option<ResourceMetadata>,
+) -> bool {
+    let is_dir = metadata.as_ref().is_some_and(|m| m.is_directory);
+    let ino = inode_manager.get_or_create(path, is_dir);
+    
+    if let Some(metadata) = metadata {
+        attr_cache.insert(ino, metadata);
+    }
+    
+    is_dir
+}
@@ -0,0 +1,994 @@
+//! Write-back queue
+//!
+//! In write-back mode a write is acknowledged as soon as it is journaled
+//! locally; a background worker then uploads it to the owning driver with
+//! retries. Journal entries are only removed once the driver accepted the
+//! data, so anything still on disk after a crash is replayed on start.
+//!
+//! An entry is written under a temporary name, synced, renamed into place
+//! and the rename synced before the write is acknowledged, so a crash
+//! leaves either the whole entry or none of it. Its header and each chunk
+//! of its content carry a CRC32C, and an end record closes it; an entry that
+//! fails those checks anyway (a bad disk, say) is moved to `quarantine/`
+//! rather than replayed, and its path reports the lost write. A delete of a
+//! path with writes still queued is journaled behind them, so they can't
+//! land after it. On replay, entries superseded by a later one for the same
+//! path are dropped rather than sent.
+//!
+//! Queued payloads count against the memory budget until they are uploaded,
+//! so a writer outpacing the backend waits for the queue to drain.
+//!
+//! With offline mode on, uploads to an unreachable backend wait for it to
+//! come back instead of using up their retries. A write journaled while
+//! offline carries the remote version it was based on; if the remote has
+//! changed by the time it reconnects, the write is a conflict and is
+//! resolved by the strategy configured for its prefix; every conflict is
+//! listed under `/proc/gnos/conflicts`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, Notify};
+use tracing::{debug, info, instrument, warn};
+
+use crate::cache::CompressionPolicy;
+use crate::config::{ConflictRule, ConflictStrategy, WriteBackConfig};
+use crate::drivers::{DriverRegistry, GnosDriver};
+use crate::qos::{self, QosClass};
+use crate::telemetry::RequestId;
+use crate::vfs::memory::{MemoryBudget, MemoryUse, Reservation};
+use crate::vfs::offline::{Connectivity, RemoteVersion};
+use crate::vfs::path::is_within;
+use crate::{GnosError, Result};
+
+const JOURNAL_MAGIC: &[u8; 8] = b"GNOSWB03";
+/// Entries written before deletes were journaled, without checksums
+const JOURNAL_MAGIC_V2: &[u8; 8] = b"GNOSWB02";
+/// Entries written before offline mode, without a base version
+const JOURNAL_MAGIC_V1: &[u8; 8] = b"GNOSWB01";
+
+/// Content is journaled in chunks of at most this, each with its own CRC
+const JOURNAL_CHUNK: usize = 1024 * 1024;
+/// Chunk length that marks the end record
+const END_OF_CHUNKS: u32 = u32::MAX;
+const OP_WRITE: u8 = 0;
+const OP_DELETE: u8 = 1;
+
+/// Conflicts kept for `/proc/gnos/conflicts`; the oldest are dropped first
+const MAX_CONFLICTS: usize = 256;
+
+/// Upload state of a path as seen through the mount
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncState {
+    Clean,
+    Dirty,
+    /// Held back because the remote changed; rewrite the file to resolve
+    Conflict(String),
+    Failed(String),
+}
+
+/// Queue depth, for health and metrics reporting
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteBackStats {
+    pub pending: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for SyncState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncState::Clean => write!(f, "clean"),
+            SyncState::Dirty => write!(f, "dirty"),
+            SyncState::Conflict(detail) => write!(f, "conflict: {}", detail),
+            SyncState::Failed(reason) => write!(f, "error: {}", reason),
+        }
+    }
+}
+
+/// What a journal entry does to its path
+#[derive(Debug, Clone)]
+enum Mutation {
+    Write(Bytes),
+    Delete,
+}
+
+impl Mutation {
+    /// Bytes the entry holds in memory while queued
+    fn held(&self) -> u64 {
+        match self {
+            Mutation::Write(data) => data.len() as u64,
+            Mutation::Delete => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PendingWrite {
+    path: PathBuf,
+    mutation: Mutation,
+    /// The operation that produced the write; unknown for replayed entries
+    request_id: Option<RequestId>,
+    /// Remote version the write was made against, when made offline
+    base: Option<RemoteVersion>,
+    journaled_at: SystemTime,
+    /// The payload's share of the memory budget, given back once uploaded
+    _memory: Reservation,
+}
+
+/// A journal entry as read back
+struct Entry {
+    path: PathBuf,
+    mutation: Mutation,
+    base: Option<RemoteVersion>,
+    /// Missing from entries older than the checksummed format
+    journaled_at: Option<SystemTime>,
+}
+
+/// Why a journal entry can't be replayed, and whose write it held if the
+/// header was still readable
+struct Damage {
+    path: Option<PathBuf>,
+    reason: &'static str,
+}
+
+/// A write that found the remote changed underneath it
+#[derive(Debug, Clone)]
+struct Conflict {
+    path: PathBuf,
+    detected_at: SystemTime,
+    strategy: ConflictStrategy,
+    /// What happened to the local write
+    outcome: String,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    next_seq: u64,
+    pending: BTreeMap<u64, PendingWrite>,
+    /// Why each path's last write failed, and the sequence of its entry,
+    /// which stays on disk until a later write to the path replaces it
+    failed: HashMap<PathBuf, (u64, String)>,
+    /// Damaged entries moved aside on replay
+    quarantined: usize,
+    /// Keyed by the journal sequence of the conflicting write
+    conflicts: BTreeMap<u64, Conflict>,
+}
+
+impl QueueState {
+    /// Sequence numbers only grow, and the conflict list stays bounded
+    fn check_invariants(&self) -> std::result::Result<(), String> {
+        if let Some((&seq, write)) = self.pending.last_key_value() {
+            if seq > self.next_seq {
+                return Err(format!("write #{} for {} is ahead of #{}", seq, write.path.display(), self.next_seq));
+            }
+        }
+        if let Some(&seq) = self.conflicts.keys().next_back() {
+            if seq > self.next_seq {
+                return Err(format!("conflict #{} is ahead of #{}", seq, self.next_seq));
+            }
+        }
+        if self.conflicts.len() > MAX_CONFLICTS {
+            return Err(format!("{} conflicts kept, at most {}", self.conflicts.len(), MAX_CONFLICTS));
+        }
+        Ok(())
+    }
+}
+
+pub struct WriteBackQueue {
+    journal_dir: PathBuf,
+    max_retries: u32,
+    retry_backoff: Duration,
+    driver_registry: Arc<DriverRegistry>,
+    compression: Arc<CompressionPolicy>,
+    connectivity: Arc<Connectivity>,
+    memory: Arc<MemoryBudget>,
+    conflict_strategy: ConflictStrategy,
+    conflict_rules: Vec<ConflictRule>,
+    state: Mutex<QueueState>,
+    sender: mpsc::UnboundedSender<u64>,
+    progress: Notify,
+}
+
+impl WriteBackQueue {
+    /// Open the journal, requeue anything left from a previous run and start the upload worker
+    pub async fn start(
+        config: WriteBackConfig,
+        driver_registry: Arc<DriverRegistry>,
+        compression: Arc<CompressionPolicy>,
+        connectivity: Arc<Connectivity>,
+        memory: Arc<MemoryBudget>,
+    ) -> Result<Arc<Self>> {
+        tokio::fs::create_dir_all(&config.journal_dir).await?;
+        
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let queue = Arc::new(Self {
+            journal_dir: config.journal_dir,
+            max_retries: config.max_retries,
+            retry_backoff: Duration::from_millis(config.retry_backoff_ms),
+            driver_registry,
+            compression,
+            connectivity,
+            memory,
+            conflict_strategy: config.conflict_strategy,
+            conflict_rules: config.conflicts,
+            state: Mutex::new(QueueState::default()),
+            sender,
+            progress: Notify::new(),
+        });
+        
+        let replayed = queue.replay_journal().await?;
+        if replayed > 0 {
+            info!("📼 Requeued {} journaled writes from previous run", replayed);
+        }
+        
+        // Uploads queued behind the mount yield to calls made through it
+        tokio::spawn(qos::scope(QosClass::Background, run_worker(queue.clone(), receiver)));
+        
+        Ok(queue)
+    }
+    
+    /// Journal a write and queue it for upload; returns once the data is durable locally
+    #[instrument(name = "writeback.enqueue", skip_all, fields(path = %path.display(), bytes = data.len()))]
+    pub async fn enqueue(&self, path: &Path, data: Bytes, base: Option<RemoteVersion>) -> Result<()> {
+        self.journal(path, Mutation::Write(data), base).await
+    }
+    
+    /// Journal a delete behind the writes already queued for `path`, so none
+    /// of them can land after it and bring the file back
+    #[instrument(name = "writeback.enqueue_delete", skip_all, fields(path = %path.display()))]
+    pub async fn enqueue_delete(&self, path: &Path) -> Result<()> {
+        self.journal(path, Mutation::Delete, None).await
+    }
+    
+    async fn journal(&self, path: &Path, mutation: Mutation, base: Option<RemoteVersion>) -> Result<()> {
+        if self.driver_registry.get_driver(path).is_none() {
+            return Err(GnosError::PathNotFound(path.display().to_string()));
+        }
+        // A full queue holds the writer back until uploads make room
+        let mut memory = self.memory.reservation(MemoryUse::QueuedWrites);
+        memory.grow_to(mutation.held()).await?;
+        
+        let seq = {
+            let mut state = self.state.lock().unwrap();
+            state.next_seq += 1;
+            state.next_seq
+        };
+        
+        let journaled_at = SystemTime::now();
+        self.write_journal(seq, &encode_entry(path, &mutation, base, journaled_at)).await?;
+        
+        let superseded = {
+            let mut state = self.state.lock().unwrap();
+            // A fresh write supersedes a failed one and one held back by a
+            // conflict; their entries would otherwise replay over it
+            let mut superseded: Vec<u64> = state.failed.remove(path).map(|(seq, _)| seq).into_iter().collect();
+            state.conflicts.retain(|&seq, conflict| {
+                let held = conflict.path == path && conflict.strategy == ConflictStrategy::Fail;
+                if held {
+                    superseded.push(seq);
+                }
+                !held
+            });
+            state.pending.insert(seq, PendingWrite {
+                path: path.to_path_buf(),
+                mutation,
+                request_id: RequestId::current(),
+                base,
+                journaled_at,
+                _memory: memory,
+            });
+            debug_assert_eq!(state.check_invariants(), Ok(()));
+            superseded
+        };
+        for old in superseded {
+            let _ = tokio::fs::remove_file(self.journal_file(old)).await;
+        }
+        
+        debug!("Journaled #{} for {}", seq, path.display());
+        self.sender.send(seq)
+            .map_err(|_| GnosError::Driver("Write-back worker stopped".to_string()))
+    }
+    
+    /// Make an entry durable: written and synced under a temporary name,
+    /// then renamed into place and the rename synced
+    async fn write_journal(&self, seq: u64, entry: &[u8]) -> Result<()> {
+        let final_path = self.journal_file(seq);
+        let partial = final_path.with_extension("wb.partial");
+        
+        let written = async {
+            let mut journal = tokio::fs::File::create(&partial).await?;
+            journal.write_all(entry).await?;
+            journal.sync_all().await?;
+            tokio::fs::rename(&partial, &final_path).await?;
+            tokio::fs::File::open(&self.journal_dir).await?.sync_all().await
+        }.await;
+        
+        if written.is_err() {
+            let _ = tokio::fs::remove_file(&partial).await;
+        }
+        Ok(written?)
+    }
+    
+    pub fn sync_state(&self, path: &Path) -> SyncState {
+        let state = self.state.lock().unwrap();
+        
+        let held = state.conflicts.values()
+            .rfind(|conflict| conflict.path == path && conflict.strategy == ConflictStrategy::Fail);
+        
+        if state.pending.values().any(|write| write.path == path) {
+            SyncState::Dirty
+        } else if let Some(conflict) = held {
+            SyncState::Conflict(conflict.outcome.clone())
+        } else if let Some((_, reason)) = state.failed.get(path) {
+            SyncState::Failed(reason.clone())
+        } else {
+            SyncState::Clean
+        }
+    }
+    
+    /// Wait until every queued write for `path` has been uploaded
+    pub async fn flush(&self, path: &Path) -> Result<()> {
+        loop {
+            let progressed = self.progress.notified();
+            match self.sync_state(path) {
+                SyncState::Clean => return Ok(()),
+                SyncState::Conflict(detail) => return Err(GnosError::ResourceBusy(detail)),
+                SyncState::Failed(reason) => return Err(GnosError::Driver(reason)),
+                SyncState::Dirty => progressed.await,
+            }
+        }
+    }
+    
+    /// Wait until the whole queue has drained, e.g. before unmounting
+    pub async fn flush_all(&self) -> Result<()> {
+        loop {
+            let progressed = self.progress.notified();
+            let (pending, failed) = {
+                let state = self.state.lock().unwrap();
+                (state.pending.len(), state.failed.len())
+            };
+            
+            if pending == 0 {
+                if failed > 0 {
+                    return Err(GnosError::Driver(format!("{} writes failed to upload", failed)));
+                }
+                return Ok(());
+            }
+            
+            progressed.await;
+        }
+    }
+    
+    /// Check the queue's bookkeeping, e.g. after a concurrency test has
+    /// enqueued from several threads
+    pub fn check_invariants(&self) -> std::result::Result<(), String> {
+        self.state.lock().unwrap().check_invariants()
+    }
+    
+    /// Plain-text view of the queue for `/proc/gnos/sync`
+    pub fn stats(&self) -> WriteBackStats {
+        let state = self.state.lock().unwrap();
+        WriteBackStats {
+            pending: state.pending.len(),
+            failed: state.failed.len(),
+        }
+    }
+    
+    pub fn status_report(&self) -> String {
+        let state = self.state.lock().unwrap();
+        
+        let mut report = format!("pending: {}\nfailed: {}\n", state.pending.len(), state.failed.len());
+        if state.quarantined > 0 {
+            report.push_str(&format!("quarantined: {}\n", state.quarantined));
+        }
+        for (seq, write) in &state.pending {
+            let change = match &write.mutation {
+                Mutation::Write(data) => format!("{} bytes", data.len()),
+                Mutation::Delete => "delete".to_string(),
+            };
+            let journaled_at = chrono::DateTime::<chrono::Utc>::from(write.journaled_at);
+            report.push_str(&format!("dirty\t#{}\t{}\t{}\t{}\n",
+                                     seq, write.path.display(), change, journaled_at.to_rfc3339()));
+        }
+        for (path, (_, reason)) in &state.failed {
+            report.push_str(&format!("error\t{}\t{}\n", path.display(), reason));
+        }
+        
+        report
+    }
+    
+    /// Plain-text view of detected conflicts for `/proc/gnos/conflicts`
+    pub fn conflict_report(&self) -> String {
+        let state = self.state.lock().unwrap();
+        
+        let held = state.conflicts.values()
+            .filter(|conflict| conflict.strategy == ConflictStrategy::Fail)
+            .count();
+        let mut report = format!("conflicts: {}\nheld: {}\n", state.conflicts.len(), held);
+        for (seq, conflict) in &state.conflicts {
+            let detected_at = chrono::DateTime::<chrono::Utc>::from(conflict.detected_at);
+            report.push_str(&format!("{}\t#{}\t{}\t{}\t{}\n",
+                                     strategy_name(conflict.strategy), seq, conflict.path.display(),
+                                     detected_at.to_rfc3339(), conflict.outcome));
+        }
+        
+        report
+    }
+    
+    async fn upload(&self, seq: u64) {
+        let entry = self.state.lock().unwrap().pending.get(&seq)
+            .map(|write| (write.path.clone(), write.mutation.clone(), write.request_id, write.base));
+        let Some((path, mutation, request_id, base)) = entry else {
+            return;
+        };
+        
+        let result = match self.driver_registry.get_driver(&path) {
+            // Uploads carry the ID of the write that queued them
+            Some(driver) => match request_id {
+                Some(id) => id.scope(self.upload_with_retries(seq, driver.as_ref(), &path, &mutation, base)).await,
+                None => self.upload_with_retries(seq, driver.as_ref(), &path, &mutation, base).await,
+            },
+            None => Err(GnosError::PathNotFound(path.display().to_string())),
+        };
+        
+        let (overtaken, superseded) = {
+            let mut state = self.state.lock().unwrap();
+            state.pending.remove(&seq);
+            let superseded = state.pending.range(seq..).any(|(_, write)| write.path == path);
+            let mut overtaken = None;
+            match &result {
+                // An older write to the path that failed is settled by this one
+                Ok(()) => {
+                    if state.failed.get(&path).is_some_and(|(failed, _)| *failed < seq) {
+                        overtaken = state.failed.remove(&path).map(|(failed, _)| failed);
+                    }
+                }
+                // A newer write to the path is still queued and will replace this one
+                Err(_) if superseded => {}
+                Err(e) => {
+                    state.failed.insert(path.clone(), (seq, e.to_string()));
+                }
+            }
+            debug_assert_eq!(state.check_invariants(), Ok(()));
+            (overtaken, superseded)
+        };
+        
+        match result {
+            Ok(()) => {
+                debug!("Uploaded write #{} for {}", seq, path.display());
+                let _ = tokio::fs::remove_file(self.journal_file(seq)).await;
+                if let Some(old) = overtaken {
+                    let _ = tokio::fs::remove_file(self.journal_file(old)).await;
+                }
+            }
+            Err(e) if superseded => {
+                debug!("Write-back #{} of {} failed ({}), but a later write replaces it", seq, path.display(), e);
+                let _ = tokio::fs::remove_file(self.journal_file(seq)).await;
+            }
+            Err(e) => {
+                // The journal entry stays on disk so the write is retried on the next start
+                warn!("❌ Write-back of {} failed: {}", path.display(), e);
+            }
+        }
+    }
+    
+    async fn upload_with_retries(
+        &self,
+        seq: u64,
+        driver: &dyn GnosDriver,
+        path: &Path,
+        mutation: &Mutation,
+        base: Option<RemoteVersion>,
+    ) -> Result<()> {
+        let mut attempt = 0;
+        
+        loop {
+            let result = match (mutation, base) {
+                (Mutation::Write(data), Some(base)) => self.upload_if_unchanged(seq, driver, path, data, base).await,
+                (Mutation::Write(data), None) => self.compression.write_through(driver, path, data).await,
+                // Already gone, e.g. the delete landed just before a crash
+                (Mutation::Delete, _) => match driver.delete(path).await {
+                    Err(GnosError::PathNotFound(_)) => Ok(()),
+                    result => result,
+                },
+            };
+            self.connectivity.record(driver.name(), &result);
+            
+            match result {
+                Ok(()) => return Ok(()),
+                // Waiting out an outage doesn't count against the retries
+                Err(GnosError::Unreachable(_)) if self.connectivity.enabled() => {
+                    debug!("Holding write-back of {} until {} is reachable", path.display(), driver.name());
+                    self.connectivity.wait_for_probe().await;
+                }
+                // A conflict won't resolve itself by retrying
+                Err(e @ GnosError::ResourceBusy(_)) if base.is_some() => return Err(e),
+                Err(e) if attempt < self.max_retries => {
+                    attempt += 1;
+                    // A rate-limited backend said how long to hold off
+                    let delay = e.retry_after()
+                        .unwrap_or_else(|| self.retry_backoff.saturating_mul(2u32.saturating_pow(attempt - 1)));
+                    warn!("⏳ Write-back of {} failed (attempt {}): {}; retrying in {:?}",
+                          path.display(), attempt, e, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    
+    /// Upload a write made offline, resolving a conflict if the remote moved on
+    async fn upload_if_unchanged(
+        &self,
+        seq: u64,
+        driver: &dyn GnosDriver,
+        path: &Path,
+        data: &[u8],
+        base: RemoteVersion,
+    ) -> Result<()> {
+        let current = match driver.metadata(path).await {
+            Ok(metadata) => Some(RemoteVersion::of(&metadata)),
+            Err(GnosError::PathNotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        if current == Some(base) {
+            return self.compression.write_through(driver, path, data).await;
+        }
+        
+        // Retries of the same write keep the time it first conflicted
+        let detected_at = self.state.lock().unwrap().conflicts.get(&seq)
+            .map_or_else(SystemTime::now, |conflict| conflict.detected_at);
+        let strategy = self.conflict_strategy_for(path);
+        let (result, outcome) = match strategy {
+            ConflictStrategy::LastWriterWins => (
+                self.compression.write_through(driver, path, data).await,
+                "local write replaced the remote change".to_string(),
+            ),
+            ConflictStrategy::KeepBoth => {
+                let copy = conflict_copy_path(path, detected_at);
+                (
+                    self.compression.write_through(driver, &copy, data).await,
+                    format!("local write kept as {}", copy.display()),
+                )
+            }
+            ConflictStrategy::Fail => (
+                Err(GnosError::ResourceBusy(format!("conflict: remote changed underneath write #{}", seq))),
+                "remote changed while offline; local write held in journal".to_string(),
+            ),
+        };
+        
+        // A retried upload re-detects the same conflict, so only the first is announced
+        let first = {
+            let mut state = self.state.lock().unwrap();
+            let first = !state.conflicts.contains_key(&seq);
+            state.conflicts.insert(seq, Conflict {
+                path: path.to_path_buf(),
+                detected_at,
+                strategy,
+                outcome: outcome.clone(),
+            });
+            while state.conflicts.len() > MAX_CONFLICTS {
+                state.conflicts.pop_first();
+            }
+            debug_assert_eq!(state.check_invariants(), Ok(()));
+            first
+        };
+        if first {
+            warn!("⚔️  {} changed remotely while offline: {}", path.display(), outcome);
+        }
+        
+        result
+    }
+    
+    fn conflict_strategy_for(&self, path: &Path) -> ConflictStrategy {
+        self.conflict_rules.iter()
+            .find(|rule| is_within(path, &rule.prefix))
+            .map_or(self.conflict_strategy, |rule| rule.strategy)
+    }
+    
+    /// Requeue what a previous run left in the journal: entries that were
+    /// never renamed into place are discarded, damaged ones quarantined, and
+    /// only the latest entry for each path is kept
+    async fn replay_journal(&self) -> Result<usize> {
+        let mut replayed = BTreeMap::new();
+        for (seq, file_path) in scan_journal(&self.journal_dir).await? {
+            // Damaged entries count too, so their numbers aren't reused
+            {
+                let mut state = self.state.lock().unwrap();
+                state.next_seq = std::cmp::max(state.next_seq, seq);
+            }
+            
+            match decode_entry(&tokio::fs::read(&file_path).await?) {
+                Ok(mut entry) => {
+                    // Older entries didn't record when, so the file has to do
+                    if entry.journaled_at.is_none() {
+                        let metadata = tokio::fs::metadata(&file_path).await?;
+                        entry.journaled_at = metadata.modified().ok();
+                    }
+                    replayed.insert(seq, entry);
+                }
+                Err(damage) => self.quarantine(seq, &file_path, damage).await,
+            }
+        }
+        
+        let superseded = superseded(&replayed);
+        for seq in &superseded {
+            replayed.remove(seq);
+            let _ = tokio::fs::remove_file(self.journal_file(*seq)).await;
+        }
+        if !superseded.is_empty() {
+            info!("🧹 Dropped {} journaled writes superseded by later ones", superseded.len());
+        }
+        
+        {
+            let mut state = self.state.lock().unwrap();
+            for (&seq, entry) in &replayed {
+                state.pending.insert(seq, PendingWrite {
+                    path: entry.path.clone(),
+                    mutation: entry.mutation.clone(),
+                    request_id: None,
+                    base: entry.base,
+                    journaled_at: entry.journaled_at.unwrap_or_else(SystemTime::now),
+                    // Already in memory, so counted even past the budget
+                    _memory: self.memory.charge(MemoryUse::QueuedWrites, entry.mutation.held()),
+                });
+            }
+            debug_assert_eq!(state.check_invariants(), Ok(()));
+        }
+        for &seq in replayed.keys() {
+            let _ = self.sender.send(seq);
+        }
+        
+        Ok(replayed.len())
+    }
+    
+    /// Move a damaged entry aside for inspection, and report the lost write
+    /// on its path if the header said whose it was
+    async fn quarantine(&self, seq: u64, file_path: &Path, damage: Damage) {
+        let quarantine_dir = self.journal_dir.join("quarantine");
+        let moved = match tokio::fs::create_dir_all(&quarantine_dir).await {
+            Ok(()) => tokio::fs::rename(file_path, quarantine_dir.join(format!("{:020}.wb", seq))).await,
+            Err(e) => Err(e),
+        };
+        
+        match &damage.path {
+            Some(path) => warn!("🧨 Journaled write #{} for {} is damaged: {}", seq, path.display(), damage.reason),
+            None => warn!("🧨 Journal entry #{} is damaged: {}", seq, damage.reason),
+        }
+        if let Err(e) = moved {
+            warn!("Couldn't move {} to quarantine: {}", file_path.display(), e);
+        }
+        
+        let mut state = self.state.lock().unwrap();
+        state.quarantined += 1;
+        if let Some(path) = damage.path {
+            state.failed.insert(path, (seq, format!("journaled write was damaged: {}", damage.reason)));
+        }
+    }
+    
+    fn journal_file(&self, seq: u64) -> PathBuf {
+        self.journal_dir.join(format!("{:020}.wb", seq))
+    }
+}
+
+/// Entries in the journal directory, oldest first; those never renamed
+/// into place, so never acknowledged, are deleted on the way
+async fn scan_journal(dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let mut found = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    
+    while let Some(entry) = entries.next_entry().await? {
+        let file_path = entry.path();
+        let name = entry.file_name();
+        
+        if name.to_string_lossy().ends_with(".wb.partial") {
+            debug!("Discarding unfinished journal entry {}", file_path.display());
+            let _ = tokio::fs::remove_file(&file_path).await;
+            continue;
+        }
+        if file_path.extension().and_then(|ext| ext.to_str()) != Some("wb") {
+            continue;
+        }
+        
+        let Some(seq) = file_path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok()) else {
+            continue;
+        };
+        found.push((seq, file_path));
+    }
+    found.sort_unstable();
+    Ok(found)
+}
+
+/// Entries overridden by a later one for the same path: the last entry for
+/// a path decides what it ends up as
+fn superseded(entries: &BTreeMap<u64, Entry>) -> Vec<u64> {
+    let latest: HashMap<&Path, u64> = entries.iter().map(|(&seq, entry)| (entry.path.as_path(), seq)).collect();
+    entries.iter()
+        .filter(|(seq, entry)| latest.get(entry.path.as_path()) != Some(*seq))
+        .map(|(seq, _)| *seq)
+        .collect()
+}
+
+async fn run_worker(queue: Arc<WriteBackQueue>, mut receiver: mpsc::UnboundedReceiver<u64>) {
+    while let Some(seq) = receiver.recv().await {
+        queue.upload(seq).await;
+        queue.progress.notify_waiters();
+    }
+}
+
+fn strategy_name(strategy: ConflictStrategy) -> &'static str {
+    match strategy {
+        ConflictStrategy::LastWriterWins => "last_writer_wins",
+        ConflictStrategy::KeepBoth => "keep_both",
+        ConflictStrategy::Fail => "fail",
+    }
+}
+
+/// Sibling name for the local side of a keep-both conflict,
+/// e.g. `report.txt` becomes `report.conflict-20240101T120000Z.txt`
+fn conflict_copy_path(path: &Path, now: SystemTime) -> PathBuf {
+    let stamp = chrono::DateTime::<chrono::Utc>::from(now).format("%Y%m%dT%H%M%SZ");
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.conflict-{}.{}", stem, stamp, ext.to_string_lossy()),
+        None => format!("{}.conflict-{}", stem, stamp),
+    };
+    path.with_file_name(name)
+}
+
+// Entry layout: magic | op (u8) | has base (u8) | base size (u64 LE) | base mtime ns (u64 LE)
+//               | journaled at ns (u64 LE) | path len (u32 LE) | path | header CRC32C (u32 LE)
+//               | chunks: len (u32 LE) | CRC32C (u32 LE) | content
+//               | end: u32::MAX | content len (u64 LE) | chunk count (u32 LE)
+// GNOSWB02 entries are magic | base fields | path len | path | payload, with
+// no checks; GNOSWB01 entries also lack the base fields.
+fn encode_entry(path: &Path, mutation: &Mutation, base: Option<RemoteVersion>, journaled_at: SystemTime) -> Vec<u8> {
+    let path_bytes = path.as_os_str().as_bytes();
+    let (size, mtime) = base.map_or((0, 0), |base| (base.size, unix_nanos(base.mtime)));
+    let (op, data): (u8, &[u8]) = match mutation {
+        Mutation::Write(data) => (OP_WRITE, &data[..]),
+        Mutation::Delete => (OP_DELETE, &[]),
+    };
+    let chunks = data.chunks(JOURNAL_CHUNK);
+    let chunk_count = chunks.len();
+    
+    let mut out = Vec::with_capacity(JOURNAL_MAGIC.len() + 34 + path_bytes.len() + 4
+        + chunk_count * 8 + data.len() + 16);
+    out.extend_from_slice(JOURNAL_MAGIC);
+    out.push(op);
+    out.push(base.is_some() as u8);
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&mtime.to_le_bytes());
+    out.extend_from_slice(&unix_nanos(journaled_at).to_le_bytes());
+    out.extend_from_slice(&(path_bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(path_bytes);
+    let header_crc = crc32c::crc32c(&out);
+    out.extend_from_slice(&header_crc.to_le_bytes());
+    
+    for chunk in chunks {
+        out.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        out.extend_from_slice(&crc32c::crc32c(chunk).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&END_OF_CHUNKS.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    out.extend_from_slice(&(chunk_count as u32).to_le_bytes());
+    out
+}
+
+fn decode_entry(raw: &[u8]) -> std::result::Result<Entry, Damage> {
+    let unreadable = |reason| Damage { path: None, reason };
+    match raw.get(..8) {
+        Some(magic) if magic == JOURNAL_MAGIC => decode_chunked(raw),
+        Some(magic) if magic == JOURNAL_MAGIC_V2 || magic == JOURNAL_MAGIC_V1 => {
+            decode_legacy(raw).ok_or_else(|| unreadable("truncated"))
+        }
+        _ => Err(unreadable("not a journal entry")),
+    }
+}
+
+fn decode_chunked(raw: &[u8]) -> std::result::Result<Entry, Damage> {
+    let mut cursor = Cursor { raw, at: JOURNAL_MAGIC.len() };
+    let header = (|| {
+        let op = cursor.u8()?;
+        let has_base = cursor.u8()? != 0;
+        let size = cursor.u64()?;
+        let mtime = cursor.u64()?;
+        let journaled_at = cursor.u64()?;
+        let path_len = cursor.u32()? as usize;
+        let path = PathBuf::from(std::ffi::OsStr::from_bytes(cursor.take(path_len)?));
+        let base = has_base.then(|| RemoteVersion { size, mtime: UNIX_EPOCH + Duration::from_nanos(mtime) });
+        Some((op, base, UNIX_EPOCH + Duration::from_nanos(journaled_at), path))
+    })();
+    let Some((op, base, journaled_at, path)) = header else {
+        return Err(Damage { path: None, reason: "header is torn" });
+    };
+    let header_end = cursor.at;
+    if cursor.u32() != Some(crc32c::crc32c(&raw[..header_end])) {
+        return Err(Damage { path: None, reason: "header checksum mismatch" });
+    }
+    
+    let damaged = |reason| Damage { path: Some(path.clone()), reason };
+    let mut data = Vec::new();
+    let mut chunk_count = 0u32;
+    loop {
+        let len = cursor.u32().ok_or_else(|| damaged("content is torn"))?;
+        if len == END_OF_CHUNKS {
+            break;
+        }
+        let crc = cursor.u32().ok_or_else(|| damaged("content is torn"))?;
+        let chunk = cursor.take(len as usize).ok_or_else(|| damaged("content is torn"))?;
+        if crc32c::crc32c(chunk) != crc {
+            return Err(damaged("content checksum mismatch"));
+        }
+        data.extend_from_slice(chunk);
+        chunk_count += 1;
+    }
+    if cursor.u64() != Some(data.len() as u64) || cursor.u32() != Some(chunk_count) || cursor.at != raw.len() {
+        return Err(damaged("end record doesn't match the content"));
+    }
+    
+    let mutation = match op {
+        OP_WRITE => Mutation::Write(Bytes::from(data)),
+        OP_DELETE => Mutation::Delete,
+        _ => return Err(damaged("unknown operation")),
+    };
+    Ok(Entry { path, mutation, base, journaled_at: Some(journaled_at) })
+}
+
+fn decode_legacy(raw: &[u8]) -> Option<Entry> {
+    let (base, rest) = match raw.get(..8)? {
+        magic if magic == JOURNAL_MAGIC_V1 => (None, &raw[8..]),
+        _ => {
+            let size = u64::from_le_bytes(raw.get(9..17)?.try_into().ok()?);
+            let mtime = u64::from_le_bytes(raw.get(17..25)?.try_into().ok()?);
+            let base = (raw[8] != 0).then(|| RemoteVersion {
+                size,
+                mtime: UNIX_EPOCH + Duration::from_nanos(mtime),
+            });
+            (base, &raw[25..])
+        }
+    };
+    
+    let path_len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+    let path_end = 4usize.checked_add(path_len)?;
+    let path = PathBuf::from(std::ffi::OsStr::from_bytes(rest.get(4..path_end)?));
+    
+    Some(Entry {
+        path,
+        mutation: Mutation::Write(Bytes::copy_from_slice(&rest[path_end..])),
+        base,
+        journaled_at: None,
+    })
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+/// Reads a journal entry front to back
+struct Cursor<'a> {
+    raw: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.raw.get(self.at..self.at.checked_add(len)?)?;
+        self.at += len;
+        Some(bytes)
+    }
+    
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+    
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+    
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn decoded(raw: &[u8]) -> Entry {
+        match decode_entry(raw) {
+            Ok(entry) => entry,
+            Err(damage) => panic!("entry didn't decode: {}", damage.reason),
+        }
+    }
+    
+    fn damage(raw: &[u8]) -> Damage {
+        match decode_entry(raw) {
+            Ok(entry) => panic!("damaged entry for {} decoded", entry.path.display()),
+            Err(damage) => damage,
+        }
+    }
+    
+    fn written(path: &str) -> Entry {
+        Entry { path: PathBuf::from(path), mutation: Mutation::Write(Bytes::new()), base: None, journaled_at: None }
+    }
+    
+    #[test]
+    fn entries_spanning_several_chunks_round_trip() {
+        let data: Vec<u8> = (0..JOURNAL_CHUNK * 2 + 17).map(|i| (i % 251) as u8).collect();
+        let base = RemoteVersion { size: 42, mtime: UNIX_EPOCH + Duration::from_secs(1_700_000_000) };
+        let journaled_at = UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789);
+        let raw = encode_entry(Path::new("/s3/bucket/big.bin"), &Mutation::Write(Bytes::from(data.clone())), Some(base), journaled_at);
+        
+        let entry = decoded(&raw);
+        assert_eq!(entry.path, Path::new("/s3/bucket/big.bin"));
+        assert_eq!(entry.base, Some(base));
+        assert_eq!(entry.journaled_at, Some(journaled_at));
+        assert!(matches!(entry.mutation, Mutation::Write(ref got) if got[..] == data[..]));
+    }
+    
+    #[test]
+    fn deletes_round_trip() {
+        let raw = encode_entry(Path::new("/s3/bucket/gone"), &Mutation::Delete, None, UNIX_EPOCH);
+        let entry = decoded(&raw);
+        assert_eq!(entry.path, Path::new("/s3/bucket/gone"));
+        assert!(matches!(entry.mutation, Mutation::Delete));
+        assert_eq!(entry.base, None);
+    }
+    
+    #[test]
+    fn damaged_content_names_the_path_it_was_for() {
+        let mut raw = encode_entry(Path::new("/s3/bucket/a"), &Mutation::Write(Bytes::from_static(b"hello")), None, UNIX_EPOCH);
+        let last_content_byte = raw.len() - 17;
+        raw[last_content_byte] ^= 0xff;
+        
+        let damage = damage(&raw);
+        assert_eq!(damage.reason, "content checksum mismatch");
+        assert_eq!(damage.path.as_deref(), Some(Path::new("/s3/bucket/a")));
+    }
+    
+    #[test]
+    fn torn_entries_are_refused_at_every_length() {
+        let raw = encode_entry(Path::new("/s3/bucket/a"), &Mutation::Write(Bytes::from_static(b"hello")), None, UNIX_EPOCH);
+        for len in 0..raw.len() {
+            let damage = damage(&raw[..len]);
+            // Past the header, the path the write was for is still known
+            let header = JOURNAL_MAGIC.len() + 30 + "/s3/bucket/a".len() + 4;
+            assert_eq!(damage.path.is_some(), len >= header, "cut at {}: {}", len, damage.reason);
+        }
+    }
+    
+    #[tokio::test]
+    async fn scanning_discards_partial_entries_and_orders_by_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["10.wb", "9.wb", "11.wb.partial", "notes.txt", "x.wb"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        
+        let found = scan_journal(dir.path()).await.unwrap();
+        let seqs: Vec<u64> = found.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, [9, 10]);
+        assert!(!dir.path().join("11.wb.partial").exists());
+        assert!(dir.path().join("notes.txt").exists());
+    }
+    
+    #[test]
+    fn only_the_last_entry_for_a_path_survives_replay() {
+        let entries = BTreeMap::from([
+            (3, written("/s3/b/a")),
+            (5, written("/s3/b/other")),
+            (8, written("/s3/b/a")),
+            (12, written("/s3/b/a")),
+        ]);
+        assert_eq!(superseded(&entries), [3, 8]);
+    }
+}
@@ -0,0 +1,163 @@
+//! Deterministic-scheduling tests of the tables FUSE and 9P threads share
+//!
+//! Run with `cargo test --features shuttle --test concurrency`. Shuttle only
+//! switches threads at its own synchronization points, so each thread yields
+//! between the table calls that race in the frontends (a lookup resolving a
+//! path and then reading the inode, say). Every call is then explored as if
+//! another thread could run right before or after it. A failing schedule is
+//! printed by shuttle and can be replayed with `shuttle::replay`.
+
+#![cfg(feature = "shuttle")]
+
+use std::path::{Path, PathBuf};
+
+use gnos::vfs::HandleTraces;
+use gnos::InodeManager;
+use shuttle::sync::Arc;
+use shuttle::thread;
+
+const ITERATIONS: usize = 1000;
+const PCT_DEPTH: usize = 3;
+
+/// Both the random and the PCT scheduler, so shallow and deep bugs are found
+fn explore<F>(test: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let test = std::sync::Arc::new(test);
+    let random = test.clone();
+    shuttle::check_random(move || random(), ITERATIONS);
+    shuttle::check_pct(move || test(), ITERATIONS, PCT_DEPTH);
+}
+
+fn join_all(handles: Vec<thread::JoinHandle<()>>) {
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn lookup_races_forget() {
+    explore(|| {
+        let inodes = Arc::new(InodeManager::new());
+        let path = PathBuf::from("/cloud/aws/s3/bucket/key");
+
+        let mut handles: Vec<_> = (0..2)
+            .map(|_| {
+                let inodes = inodes.clone();
+                let path = path.clone();
+                thread::spawn(move || {
+                    let ino = inodes.get_or_create(&path, false);
+                    thread::yield_now();
+                    // The inode may be forgotten in between, but never swapped for another path's
+                    if let Some(inode) = inodes.get(ino) {
+                        assert_eq!(inode.path, path);
+                    }
+                })
+            })
+            .collect();
+        handles.push({
+            let inodes = inodes.clone();
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::yield_now();
+                inodes.remove(&path);
+            })
+        });
+
+        join_all(handles);
+        inodes.check_invariants().unwrap();
+    });
+}
+
+#[test]
+fn concurrent_lookups_share_one_inode() {
+    explore(|| {
+        let inodes = Arc::new(InodeManager::new());
+
+        let handles: Vec<_> = (0..3)
+            .map(|i| {
+                let inodes = inodes.clone();
+                thread::spawn(move || {
+                    let shared = inodes.get_or_create(Path::new("/net/shared"), false);
+                    thread::yield_now();
+                    let own =
+                        inodes.get_or_create(&PathBuf::from(format!("/net/own-{}", i)), false);
+                    assert_ne!(shared, own);
+                    assert_eq!(inodes.find_by_path(Path::new("/net/shared")), Some(shared));
+                })
+            })
+            .collect();
+
+        join_all(handles);
+        assert_eq!(inodes.len(), 4);
+        inodes.check_invariants().unwrap();
+    });
+}
+
+#[test]
+fn subtree_forget_races_child_create() {
+    explore(|| {
+        let inodes = Arc::new(InodeManager::new());
+        inodes.get_or_create(Path::new("/cloud/a"), true);
+
+        let create = {
+            let inodes = inodes.clone();
+            thread::spawn(move || {
+                inodes.get_or_create(Path::new("/cloud/a/b"), true);
+                thread::yield_now();
+                inodes.get_or_create(Path::new("/cloud/a/b/c"), false);
+            })
+        };
+        let forget = {
+            let inodes = inodes.clone();
+            thread::spawn(move || {
+                thread::yield_now();
+                inodes.remove(Path::new("/cloud/a"));
+            })
+        };
+
+        join_all(vec![create, forget]);
+        inodes.check_invariants().unwrap();
+        // Whatever survived is reachable through `children`
+        for inode in inodes.children(Path::new("/cloud/a/b")) {
+            assert_eq!(inodes.find_by_path(&inode.path), Some(inode.ino));
+        }
+    });
+}
+
+#[test]
+fn handle_open_and_release_race_reports() {
+    explore(|| {
+        let traces = Arc::new(HandleTraces::new(true, 16));
+        let path = PathBuf::from("/cloud/aws/s3/bucket/key");
+
+        let mut handles: Vec<_> = (1..=2u64)
+            .map(|fh| {
+                let traces = traces.clone();
+                let path = path.clone();
+                thread::spawn(move || {
+                    traces.start(fh, &path).unwrap();
+                    thread::yield_now();
+                    assert!(traces
+                        .report_for(&path)
+                        .is_some_and(|report| report.contains(&format!("fh {} ", fh))));
+                    thread::yield_now();
+                    assert!(traces.finish(fh).is_some());
+                })
+            })
+            .collect();
+        handles.push({
+            let traces = traces.clone();
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::yield_now();
+                let _ = traces.report_for(&path);
+            })
+        });
+
+        join_all(handles);
+        traces.check_invariants().unwrap();
+        assert!(traces.report_for(&path).is_none());
+    });
+}
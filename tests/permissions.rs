@@ -0,0 +1,197 @@
+//! Precedence of grants and deny entries in the permission checker
+//!
+//! Deny entries, the `security.deny` policy's and a capability's own, win
+//! over any grant however specific, and only refuse the operations their
+//! perms name, and only tokens signed with the daemon's key are believed.
+//! Everything here goes through `CapabilityManager::check_token` and
+//! `token_permissions`, as FUSE and the gateway do.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use gnos::security::{BreakGlass, BreakGlassConfig, Deny, SecurityConfig, SigningKey};
+use gnos::{Capability, CapabilityManager, GnosError, Operation};
+
+fn deny(path: &str, perms: &str) -> Deny {
+    Deny { path: PathBuf::from(path), perms: perms.to_string() }
+}
+
+fn token(manager: &CapabilityManager, path: &str, permissions: u8, deny: Vec<Deny>) -> String {
+    let capability = Capability {
+        path: PathBuf::from(path),
+        permissions,
+        expiration: SystemTime::now() + Duration::from_secs(3600),
+        owner: "tester".to_string(),
+        break_glass: None,
+        issued: Some(SystemTime::now()),
+        not_before: None,
+        max_idle_seconds: None,
+        deny,
+    };
+    manager.issue(&capability).unwrap()
+}
+
+/// One signing key for the whole run, created as the daemon creates its own
+fn key_file() -> PathBuf {
+    static DIR: OnceLock<tempfile::TempDir> = OnceLock::new();
+    DIR.get_or_init(|| tempfile::tempdir().unwrap()).path().join("signing.key")
+}
+
+fn manager(policy: Vec<Deny>) -> CapabilityManager {
+    CapabilityManager::new(SecurityConfig { deny: policy, signing_key_file: key_file(), ..SecurityConfig::default() })
+}
+
+async fn allowed(manager: &CapabilityManager, token: &str, path: &str, operation: Operation) -> bool {
+    match manager.check_token(Some(token), Path::new(path), operation).await {
+        Ok(()) => true,
+        Err(GnosError::PermissionDenied(_)) => false,
+        Err(e) => panic!("unexpected error for {}: {}", path, e),
+    }
+}
+
+#[tokio::test]
+async fn deny_overrides_parent_grant() {
+    let manager = manager(Vec::new());
+    let token = token(&manager, "/cloud", 0b110, vec![deny("/cloud/prod/secrets", "rwx")]);
+    
+    assert!(!allowed(&manager, &token, "/cloud/prod/secrets", Operation::Read).await);
+    assert!(!allowed(&manager, &token, "/cloud/prod/secrets/db/password", Operation::Read).await);
+    assert!(!allowed(&manager, &token, "/cloud/prod/secrets/db/password", Operation::Write).await);
+    assert!(allowed(&manager, &token, "/cloud/prod/config.yaml", Operation::Write).await);
+    assert!(allowed(&manager, &token, "/cloud/staging/secrets", Operation::Read).await);
+}
+
+#[tokio::test]
+async fn deny_covers_whole_components_only() {
+    let manager = manager(Vec::new());
+    let token = token(&manager, "/cloud", 0b110, vec![deny("/cloud/prod", "rwx")]);
+    
+    assert!(!allowed(&manager, &token, "/cloud/prod/app", Operation::Read).await);
+    assert!(allowed(&manager, &token, "/cloud/production/app", Operation::Read).await);
+}
+
+#[tokio::test]
+async fn narrowed_deny_refuses_only_its_operations() {
+    let manager = manager(Vec::new());
+    let token = token(&manager, "/cloud", 0b110, vec![deny("/cloud/prod", "w")]);
+    
+    assert!(allowed(&manager, &token, "/cloud/prod/app.log", Operation::Read).await);
+    assert!(allowed(&manager, &token, "/cloud/prod", Operation::List).await);
+    assert!(!allowed(&manager, &token, "/cloud/prod/app.log", Operation::Write).await);
+    assert_eq!(manager.token_permissions(Some(&token), Path::new("/cloud/prod/app.log")), 0b100);
+    assert_eq!(manager.token_permissions(Some(&token), Path::new("/cloud/dev/app.log")), 0b110);
+}
+
+#[tokio::test]
+async fn policy_deny_wins_over_more_specific_grant() {
+    let manager = manager(vec![deny("/cloud/prod/secrets", "rwx")]);
+    let token = token(&manager, "/cloud/prod/secrets/db", 0b110, Vec::new());
+    
+    assert!(!allowed(&manager, &token, "/cloud/prod/secrets/db/password", Operation::Read).await);
+    assert_eq!(manager.token_permissions(Some(&token), Path::new("/cloud/prod/secrets/db/password")), 0);
+}
+
+#[tokio::test]
+async fn policy_deny_applies_without_a_token() {
+    let manager = manager(vec![deny("/cloud/prod/secrets", "w")]);
+    
+    assert!(manager.check_token(None, Path::new("/cloud/prod/secrets/key"), Operation::Read).await.is_ok());
+    assert!(manager.check_token(None, Path::new("/cloud/prod/secrets/key"), Operation::Write).await.is_err());
+}
+
+#[tokio::test]
+async fn rejected_tokens_do_not_fall_back_to_development_mode() {
+    let manager = manager(Vec::new());
+    let mut capability = Capability::from_token(&token(&manager, "/cloud", 0b110, Vec::new())).unwrap();
+    capability.expiration = SystemTime::now() - Duration::from_secs(60);
+    let expired = manager.issue(&capability).unwrap();
+    
+    let result = manager.check_token(Some(&expired), Path::new("/cloud/prod/app.log"), Operation::Read).await;
+    assert!(matches!(result, Err(GnosError::CapabilityExpired)));
+    assert_eq!(manager.token_permissions(Some(&expired), Path::new("/cloud/prod/app.log")), 0);
+    // A token for elsewhere doesn't reach the rest of the tree either
+    let narrow = token(&manager, "/cloud/dev", 0b110, Vec::new());
+    assert!(!allowed(&manager, &narrow, "/cloud/prod/app.log", Operation::Read).await);
+    assert!(manager.check_token(None, Path::new("/cloud/prod/app.log"), Operation::Read).await.is_ok());
+}
+
+#[tokio::test]
+async fn break_glass_grants_stop_when_their_session_ends() {
+    let manager = CapabilityManager::new(SecurityConfig {
+        signing_key_file: key_file(),
+        break_glass: BreakGlassConfig { session_minutes: 0, ..BreakGlassConfig::default() },
+        ..SecurityConfig::default()
+    });
+    let mut capability = Capability::from_token(&token(&manager, "/cloud/prod", 0b110, Vec::new())).unwrap();
+    capability.break_glass = Some(BreakGlass { id: "incident-42".to_string(), reason: "database down".to_string() });
+    let grant = manager.issue(&capability).unwrap();
+    
+    // The first use opens a session that, at zero minutes, is over at once
+    assert!(allowed(&manager, &grant, "/cloud/prod/db.conf", Operation::Write).await);
+    let result = manager.check_token(Some(&grant), Path::new("/cloud/prod/db.conf"), Operation::Write).await;
+    assert!(matches!(result, Err(GnosError::CapabilityExpired)));
+}
+
+#[tokio::test]
+async fn dot_dot_does_not_step_around_a_deny() {
+    let manager = manager(Vec::new());
+    let token = token(&manager, "/cloud", 0b110, vec![deny("/cloud/prod/secrets", "rwx")]);
+    
+    assert!(!allowed(&manager, &token, "/cloud/prod/app/../secrets/key", Operation::Read).await);
+}
+
+#[tokio::test]
+async fn deny_holds_inside_tenants() {
+    let manager = manager(Vec::new());
+    let token = token(&manager, "/tenants/acme", 0b110, vec![deny("/tenants/acme/keys", "rwx")]);
+    
+    assert!(allowed(&manager, &token, "/tenants/acme/reports/q3.csv", Operation::Read).await);
+    assert!(!allowed(&manager, &token, "/tenants/acme/keys/signing.pem", Operation::Read).await);
+}
+
+#[tokio::test]
+async fn forged_tenant_tokens_are_refused() {
+    let manager = manager(Vec::new());
+    let token = token(&manager, "/tenants/acme", 0b100, Vec::new());
+    assert!(allowed(&manager, &token, "/tenants/acme/reports/q3.csv", Operation::Read).await);
+    
+    // Re-aimed at another tenant and signed with a key of the forger's own
+    let mut capability = Capability::from_token(&token).unwrap();
+    capability.path = PathBuf::from("/tenants/globex");
+    let forged = capability.to_token(&SigningKey::new(b"a key the daemon has never seen")).unwrap();
+    assert!(!allowed(&manager, &forged, "/tenants/globex/ledger.csv", Operation::Read).await);
+    
+    let (unsigned, _) = forged.rsplit_once('.').unwrap();
+    assert!(!allowed(&manager, unsigned, "/tenants/globex/ledger.csv", Operation::Read).await);
+}
+
+#[tokio::test]
+async fn ambient_tokens_that_grant_nothing_are_refused_and_audited() {
+    let dir = tempfile::tempdir().unwrap();
+    let token_file = dir.path().join("tokens");
+    let manager = CapabilityManager::new(SecurityConfig {
+        signing_key_file: key_file(),
+        token_file: Some(token_file.clone()),
+        ..SecurityConfig::default()
+    });
+    std::fs::write(&token_file, token(&manager, "/cloud/dev", 0b110, Vec::new())).unwrap();
+    assert_eq!(manager.load_token_file().unwrap(), 1);
+    
+    assert!(manager.check_permission(Path::new("/cloud/dev/app.log"), Operation::Write).await.is_ok());
+    assert!(manager.check_permission(Path::new("/cloud/prod/app.log"), Operation::Read).await.is_err());
+    assert_eq!(manager.effective_permissions(Path::new("/cloud/prod/app.log")), 0);
+    
+    let refusal = manager.audit_log().pop().unwrap();
+    assert_eq!(refusal.path, Path::new("/cloud/prod/app.log"));
+    assert_eq!(refusal.owner, "tester");
+    assert!(!refusal.success);
+}
+
+#[test]
+fn deny_specs_parse_perms_and_paths() {
+    assert_eq!(Deny::parse("/cloud/prod/secrets").unwrap(), deny("/cloud/prod/secrets", "rwx"));
+    assert_eq!(Deny::parse("/cloud/prod:w").unwrap(), deny("/cloud/prod", "w"));
+    // A colon not followed by rwx letters is part of the path
+    assert_eq!(Deny::parse("/net/http/host:8080").unwrap(), deny("/net/http/host:8080", "rwx"));
+}